@@ -1,3 +1,4 @@
+use crate::congestion_control::{BandwidthEstimator, PacketFeedback};
 use crate::ClientError;
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
@@ -21,6 +22,9 @@ pub struct AdaptiveBitrateController {
     // Thresholds
     rtt_threshold_ms: u32,
     packet_loss_threshold: f32,
+    
+    // Delay-based estimator replacing the threshold rules, if any
+    estimator: Option<Box<dyn BandwidthEstimator>>,
 }
 
 impl AdaptiveBitrateController {
@@ -41,15 +45,44 @@ impl AdaptiveBitrateController {
             
             rtt_threshold_ms: 100,
             packet_loss_threshold: 0.05, // 5%
+            
+            estimator: None,
+        }
+    }
+
+    /// Follow `estimator` instead of the RTT and loss thresholds
+    pub fn with_estimator(mut self, estimator: Box<dyn BandwidthEstimator>) -> Self {
+        self.estimator = Some(estimator);
+        self
+    }
+
+    /// Replace the bandwidth estimator
+    pub fn set_estimator(&mut self, estimator: Box<dyn BandwidthEstimator>) {
+        self.estimator = Some(estimator);
+    }
+
+    /// Pass transport-wide feedback to the bandwidth estimator, if any
+    pub fn on_packet_feedback(&mut self, feedback: &[PacketFeedback]) {
+        if let Some(estimator) = self.estimator.as_mut() {
+            estimator.on_packet_feedback(feedback);
         }
     }
 
     /// Update network metrics
+    ///
+    /// `bandwidth` is the receiver's estimate of the available bandwidth in
+    /// kbps, or 0 if there is no estimate.
     pub fn update_metrics(&mut self, rtt: Duration, packet_loss: f32, bandwidth: u32) {
+        if let Some(estimator) = self.estimator.as_mut() {
+            estimator.on_round_trip_time(rtt);
+        }
+        
         // Add samples
         self.rtt_samples.push_back(rtt);
         self.packet_loss_samples.push_back(packet_loss);
-        self.bandwidth_samples.push_back(bandwidth);
+        if bandwidth > 0 {
+            self.bandwidth_samples.push_back(bandwidth);
+        }
         
         // Keep only recent samples
         if self.rtt_samples.len() > 10 {
@@ -78,7 +111,9 @@ impl AdaptiveBitrateController {
         let avg_bandwidth = self.average_bandwidth();
         
         // Determine adjustment
-        let new_bitrate = if avg_packet_loss > self.packet_loss_threshold {
+        let new_bitrate = if let Some(estimator) = self.estimator.as_ref() {
+            estimator.estimate_kbps()
+        } else if avg_packet_loss > self.packet_loss_threshold {
             // High packet loss - reduce bitrate aggressively
             (self.current_bitrate as f32 * 0.8) as u32
         } else if avg_rtt.as_millis() > self.rtt_threshold_ms as u128 {
@@ -87,13 +122,19 @@ impl AdaptiveBitrateController {
         } else if avg_packet_loss < 0.01 && avg_rtt.as_millis() < 50 {
             // Good conditions - increase bitrate gradually
             let increase = (self.current_bitrate as f32 * 0.1) as u32;
-            let max_increase = (avg_bandwidth as f32 * 0.8) as u32;
-            self.current_bitrate + increase.min(max_increase)
+            self.current_bitrate + increase
         } else {
             // Stable conditions - maintain current bitrate
             self.current_bitrate
         };
         
+        // Stay below the receiver's bandwidth estimate
+        let new_bitrate = if self.bandwidth_samples.is_empty() {
+            new_bitrate
+        } else {
+            new_bitrate.min((avg_bandwidth as f32 * 0.9) as u32)
+        };
+        
         // Clamp to min/max
         self.current_bitrate = new_bitrate.clamp(self.min_bitrate, self.max_bitrate);
         
@@ -112,6 +153,11 @@ impl AdaptiveBitrateController {
         self.current_bitrate = self.current_bitrate.clamp(min, max);
     }
 
+    /// Set the minimum time between adjustments
+    pub fn set_adjustment_interval(&mut self, interval: Duration) {
+        self.adjustment_interval = interval;
+    }
+
     /// Get network quality score (0-100)
     pub fn network_quality_score(&self) -> u8 {
         let rtt_score = self.rtt_score();
@@ -222,6 +268,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bitrate_capped_by_bandwidth_estimate() {
+        let mut controller = AdaptiveBitrateController::new(5000);
+        controller.set_adjustment_interval(Duration::ZERO);
+        
+        // Good path but the receiver estimates only 3 Mbps
+        controller.update_metrics(Duration::from_millis(20), 0.0, 3000);
+        assert_eq!(controller.adjust_bitrate(), Some(2700));
+        
+        // Without an estimate the bitrate grows
+        let mut controller = AdaptiveBitrateController::new(5000);
+        controller.set_adjustment_interval(Duration::ZERO);
+        controller.update_metrics(Duration::from_millis(20), 0.0, 0);
+        assert_eq!(controller.adjust_bitrate(), Some(5500));
+    }
+
+    struct FixedEstimate(u32);
+
+    impl BandwidthEstimator for FixedEstimate {
+        fn on_packet_feedback(&mut self, _feedback: &[PacketFeedback]) {}
+
+        fn estimate_kbps(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_bitrate_follows_estimator() {
+        let mut controller = AdaptiveBitrateController::new(5000).with_estimator(Box::new(FixedEstimate(1500)));
+        controller.set_adjustment_interval(Duration::ZERO);
+        
+        // High RTT and loss would cut the bitrate; the estimator decides instead
+        controller.update_metrics(Duration::from_millis(300), 0.2, 0);
+        assert_eq!(controller.adjust_bitrate(), Some(1500));
+        
+        controller.set_estimator(Box::new(FixedEstimate(100)));
+        assert_eq!(controller.adjust_bitrate(), Some(500));
+    }
+
     #[test]
     fn test_network_quality_score() {
         let mut controller = AdaptiveBitrateController::new(5000);
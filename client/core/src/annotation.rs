@@ -0,0 +1,705 @@
+//! On-screen annotation
+//!
+//! Supporters can draw strokes, arrows, rectangles and text on the host's
+//! screen and show a laser pointer. Annotations use normalized coordinates
+//! tied to a monitor, so both peers render them onto their own frames
+//! regardless of capture scaling. The host only accepts remote annotations
+//! when `DrawOnScreen` is granted.
+
+use crate::capture::Frame;
+use crate::multi_monitor::MonitorInfo;
+use crate::permission_profiles::{Permission, PermissionProfile};
+use crate::ClientError;
+use genxlink_protocol::{
+    Annotation, AnnotationAction, AnnotationColor, AnnotationEvent, AnnotationShape,
+    MessagePayload, NormalizedPoint,
+};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Maximum number of persistent annotations kept at once
+pub const MAX_ANNOTATIONS: usize = 1000;
+/// Maximum number of points in one stroke
+pub const MAX_STROKE_POINTS: usize = 10_000;
+/// Maximum text annotation length
+pub const MAX_TEXT_LENGTH: usize = 256;
+/// Laser pointers disappear when not moved for this long
+pub const LASER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Frame height line widths are specified for
+const REFERENCE_HEIGHT: f32 = 1080.0;
+
+/// Laser pointer position
+#[derive(Debug, Clone)]
+struct LaserPointer {
+    monitor_id: usize,
+    position: NormalizedPoint,
+    updated: Instant,
+}
+
+/// Annotation state shared by both peers
+#[derive(Debug, Default)]
+pub struct AnnotationLayer {
+    /// Annotations in drawing order, with their author
+    annotations: Vec<(String, Annotation)>,
+    lasers: HashMap<String, LaserPointer>,
+}
+
+impl AnnotationLayer {
+    /// Create an empty layer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an annotation event
+    pub fn apply(&mut self, event: &AnnotationEvent) -> Result<(), ClientError> {
+        self.apply_at(event, Instant::now())
+    }
+
+    /// Apply an annotation event at `now`
+    pub fn apply_at(&mut self, event: &AnnotationEvent, now: Instant) -> Result<(), ClientError> {
+        match &event.action {
+            AnnotationAction::Add(annotation) => {
+                validate(annotation)?;
+                if self.annotations.len() >= MAX_ANNOTATIONS {
+                    return Err(ClientError::InvalidInput(format!(
+                        "Annotation limit of {} reached",
+                        MAX_ANNOTATIONS
+                    )));
+                }
+                self.annotations.push((event.author.clone(), annotation.clone()));
+            }
+            AnnotationAction::Laser { monitor_id, position } => match position {
+                Some(position) => {
+                    self.lasers.insert(
+                        event.author.clone(),
+                        LaserPointer {
+                            monitor_id: *monitor_id,
+                            position: *position,
+                            updated: now,
+                        },
+                    );
+                }
+                None => {
+                    self.lasers.remove(&event.author);
+                }
+            },
+            AnnotationAction::Undo => {
+                self.undo(&event.author);
+            }
+            AnnotationAction::Clear { monitor_id } => {
+                self.clear(*monitor_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the most recent annotation by `author`
+    pub fn undo(&mut self, author: &str) -> Option<Annotation> {
+        let index = self.annotations.iter().rposition(|(a, _)| a == author)?;
+        Some(self.annotations.remove(index).1)
+    }
+
+    /// Remove all annotations on one monitor, or everywhere
+    pub fn clear(&mut self, monitor_id: Option<usize>) {
+        match monitor_id {
+            Some(id) => {
+                self.annotations.retain(|(_, a)| a.monitor_id != id);
+                self.lasers.retain(|_, l| l.monitor_id != id);
+            }
+            None => {
+                self.annotations.clear();
+                self.lasers.clear();
+            }
+        }
+    }
+
+    /// Persistent annotations in drawing order
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        self.annotations.iter().map(|(_, a)| a)
+    }
+
+    /// Number of persistent annotations
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    /// Whether there is nothing to draw
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty() && self.lasers.is_empty()
+    }
+
+    /// Render annotations for `monitor` onto a captured frame
+    pub fn render(&self, frame: &mut Frame, monitor: &MonitorInfo) {
+        self.render_at(frame, monitor, Instant::now());
+    }
+
+    /// Render annotations for `monitor` onto a captured frame at `now`
+    pub fn render_at(&self, frame: &mut Frame, monitor: &MonitorInfo, now: Instant) {
+        let mut canvas = Canvas::new(frame);
+
+        for (_, annotation) in &self.annotations {
+            if annotation.monitor_id == monitor.id {
+                canvas.draw_annotation(annotation);
+            }
+        }
+
+        for laser in self.lasers.values() {
+            if laser.monitor_id == monitor.id && now.duration_since(laser.updated) < LASER_TIMEOUT {
+                canvas.draw_laser(laser.position);
+            }
+        }
+    }
+}
+
+fn validate(annotation: &Annotation) -> Result<(), ClientError> {
+    if !annotation.width.is_finite() || annotation.width <= 0.0 || annotation.width > 100.0 {
+        return Err(ClientError::InvalidInput("Invalid annotation width".to_string()));
+    }
+
+    let points: Vec<NormalizedPoint> = match &annotation.shape {
+        AnnotationShape::Stroke { points } => {
+            if points.is_empty() || points.len() > MAX_STROKE_POINTS {
+                return Err(ClientError::InvalidInput("Invalid stroke length".to_string()));
+            }
+            points.clone()
+        }
+        AnnotationShape::Arrow { from, to } | AnnotationShape::Rectangle { from, to, .. } => {
+            vec![*from, *to]
+        }
+        AnnotationShape::Text { position, text, size } => {
+            if text.chars().count() > MAX_TEXT_LENGTH || !(*size > 0.0 && *size <= 1.0) {
+                return Err(ClientError::InvalidInput("Invalid text annotation".to_string()));
+            }
+            vec![*position]
+        }
+    };
+
+    if points
+        .iter()
+        .any(|p| !(0.0..=1.0).contains(&p.x) || !(0.0..=1.0).contains(&p.y))
+    {
+        return Err(ClientError::InvalidInput(
+            "Annotation coordinates must be normalized".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Annotation manager for one side of a session
+///
+/// On the host, remote events are gated by the `DrawOnScreen` permission;
+/// on the viewer every event is accepted.
+pub struct AnnotationManager {
+    author: String,
+    profile: Option<PermissionProfile>,
+    layer: Mutex<AnnotationLayer>,
+}
+
+impl AnnotationManager {
+    /// Create a manager for the viewing side
+    pub fn new(author: impl Into<String>) -> Self {
+        Self {
+            author: author.into(),
+            profile: None,
+            layer: Mutex::new(AnnotationLayer::new()),
+        }
+    }
+
+    /// Create a manager for the host, enforcing `profile`
+    pub fn for_host(author: impl Into<String>, profile: PermissionProfile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::new(author)
+        }
+    }
+
+    /// Author name used for local events
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    /// Apply a locally produced action and return the event to send to the peer
+    pub fn apply_local(&self, action: AnnotationAction) -> Result<AnnotationEvent, ClientError> {
+        let event = AnnotationEvent {
+            author: self.author.clone(),
+            action,
+        };
+        self.layer.lock().apply(&event)?;
+        Ok(event)
+    }
+
+    /// Apply an event received from the peer
+    pub fn apply_remote(&self, event: &AnnotationEvent) -> Result<(), ClientError> {
+        if let Some(profile) = &self.profile {
+            if !profile.has_permission(&Permission::DrawOnScreen) {
+                return Err(ClientError::PermissionDenied(
+                    Permission::DrawOnScreen.name().to_string(),
+                ));
+            }
+        }
+        self.layer.lock().apply(event)
+    }
+
+    /// Handle an annotation message, returning whether it was one
+    pub fn handle_message(&self, payload: &MessagePayload) -> bool {
+        match payload {
+            MessagePayload::Annotation(event) => {
+                if let Err(e) = self.apply_remote(event) {
+                    tracing::warn!("Rejected annotation from {}: {}", event.author, e);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the local author's most recent annotation
+    pub fn undo(&self) -> Result<AnnotationEvent, ClientError> {
+        self.apply_local(AnnotationAction::Undo)
+    }
+
+    /// Remove all annotations on one monitor, or everywhere
+    pub fn clear(&self, monitor_id: Option<usize>) -> Result<AnnotationEvent, ClientError> {
+        self.apply_local(AnnotationAction::Clear { monitor_id })
+    }
+
+    /// Render annotations onto a captured frame
+    pub fn render(&self, frame: &mut Frame, monitor: &MonitorInfo) {
+        self.layer.lock().render(frame, monitor);
+    }
+
+    /// Save the annotated frame as a PNG snapshot
+    pub fn export_snapshot(&self, frame: &Frame, monitor: &MonitorInfo, path: &Path) -> Result<(), ClientError> {
+        let mut snapshot = frame.clone();
+        self.render(&mut snapshot, monitor);
+        export_png(&snapshot, path)
+    }
+
+    /// Number of persistent annotations
+    pub fn annotation_count(&self) -> usize {
+        self.layer.lock().len()
+    }
+}
+
+/// Save a BGRA frame as PNG
+pub fn export_png(frame: &Frame, path: &Path) -> Result<(), ClientError> {
+    let mut rgba = Vec::with_capacity((frame.width * frame.height * 4) as usize);
+    for y in 0..frame.height as usize {
+        let row = y * frame.stride as usize;
+        let row_data = frame
+            .data
+            .get(row..row + frame.width as usize * 4)
+            .ok_or_else(|| ClientError::InvalidInput("Frame data too short".to_string()))?;
+        for pixel in row_data.chunks_exact(4) {
+            rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], 255]);
+        }
+    }
+
+    image::save_buffer_with_format(
+        path,
+        &rgba,
+        frame.width,
+        frame.height,
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| ClientError::IoError(format!("Failed to save snapshot: {}", e)))
+}
+
+/// Software rasterizer for BGRA frames
+struct Canvas<'a> {
+    frame: &'a mut Frame,
+    scale: f32,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(frame: &'a mut Frame) -> Self {
+        let scale = frame.height as f32 / REFERENCE_HEIGHT;
+        Self { frame, scale }
+    }
+
+    fn to_pixels(&self, point: NormalizedPoint) -> (f32, f32) {
+        (
+            point.x * (self.frame.width.saturating_sub(1)) as f32,
+            point.y * (self.frame.height.saturating_sub(1)) as f32,
+        )
+    }
+
+    fn blend(&mut self, x: i32, y: i32, color: AnnotationColor) {
+        if x < 0 || y < 0 || x >= self.frame.width as i32 || y >= self.frame.height as i32 {
+            return;
+        }
+        let offset = y as usize * self.frame.stride as usize + x as usize * 4;
+        let Some(pixel) = self.frame.data.get_mut(offset..offset + 4) else { return };
+
+        let alpha = color.a as u32;
+        let mix = |dst: u8, src: u8| ((src as u32 * alpha + dst as u32 * (255 - alpha)) / 255) as u8;
+        pixel[0] = mix(pixel[0], color.b);
+        pixel[1] = mix(pixel[1], color.g);
+        pixel[2] = mix(pixel[2], color.r);
+    }
+
+    fn fill_rect(&mut self, x0: f32, y0: f32, x1: f32, y1: f32, color: AnnotationColor) {
+        let (left, right) = (x0.min(x1).round() as i32, x0.max(x1).round() as i32);
+        let (top, bottom) = (y0.min(y1).round() as i32, y0.max(y1).round() as i32);
+        for y in top.max(0)..=bottom.min(self.frame.height as i32 - 1) {
+            for x in left.max(0)..=right.min(self.frame.width as i32 - 1) {
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    fn fill_circle(&mut self, cx: f32, cy: f32, radius: f32, color: AnnotationColor) {
+        let r = radius.max(0.5);
+        let r2 = r * r;
+        for y in (cy - r).floor() as i32..=(cy + r).ceil() as i32 {
+            for x in (cx - r).floor() as i32..=(cx + r).ceil() as i32 {
+                let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                if dx * dx + dy * dy <= r2 {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: AnnotationColor) {
+        let radius = width / 2.0;
+        if radius <= 0.5 {
+            // Thin lines: plain DDA so overlapping stamps do not double-blend
+            let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil().max(1.0) as i32;
+            for i in 0..=steps {
+                let t = i as f32 / steps as f32;
+                let x = from.0 + (to.0 - from.0) * t;
+                let y = from.1 + (to.1 - from.1) * t;
+                self.blend(x.round() as i32, y.round() as i32, color);
+            }
+            return;
+        }
+
+        // Thick lines: every pixel within `radius` of the segment
+        let (min_x, max_x) = (from.0.min(to.0) - radius, from.0.max(to.0) + radius);
+        let (min_y, max_y) = (from.1.min(to.1) - radius, from.1.max(to.1) + radius);
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length2 = dx * dx + dy * dy;
+
+        for y in min_y.floor().max(0.0) as i32..=max_y.ceil().min(self.frame.height as f32 - 1.0) as i32 {
+            for x in min_x.floor().max(0.0) as i32..=max_x.ceil().min(self.frame.width as f32 - 1.0) as i32 {
+                let (px, py) = (x as f32 - from.0, y as f32 - from.1);
+                let t = if length2 > 0.0 {
+                    ((px * dx + py * dy) / length2).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let (ex, ey) = (px - t * dx, py - t * dy);
+                if ex * ex + ey * ey <= radius * radius {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    fn draw_annotation(&mut self, annotation: &Annotation) {
+        let color = annotation.color;
+        let width = (annotation.width * self.scale).max(1.0);
+
+        match &annotation.shape {
+            AnnotationShape::Stroke { points } => {
+                let pixels: Vec<(f32, f32)> = points.iter().map(|p| self.to_pixels(*p)).collect();
+                if let [only] = pixels.as_slice() {
+                    self.fill_circle(only.0, only.1, width / 2.0, color);
+                }
+                for segment in pixels.windows(2) {
+                    self.draw_line(segment[0], segment[1], width, color);
+                }
+            }
+            AnnotationShape::Arrow { from, to } => {
+                let (from, to) = (self.to_pixels(*from), self.to_pixels(*to));
+                self.draw_line(from, to, width, color);
+
+                let angle = (to.1 - from.1).atan2(to.0 - from.0);
+                let head = (width * 4.0).max(12.0 * self.scale);
+                for side in [-1.0f32, 1.0] {
+                    let wing = angle + std::f32::consts::PI - side * std::f32::consts::FRAC_PI_6;
+                    let end = (to.0 + head * wing.cos(), to.1 + head * wing.sin());
+                    self.draw_line(to, end, width, color);
+                }
+            }
+            AnnotationShape::Rectangle { from, to, filled } => {
+                let (a, b) = (self.to_pixels(*from), self.to_pixels(*to));
+                if *filled {
+                    self.fill_rect(a.0, a.1, b.0, b.1, color);
+                } else {
+                    self.draw_line((a.0, a.1), (b.0, a.1), width, color);
+                    self.draw_line((b.0, a.1), (b.0, b.1), width, color);
+                    self.draw_line((b.0, b.1), (a.0, b.1), width, color);
+                    self.draw_line((a.0, b.1), (a.0, a.1), width, color);
+                }
+            }
+            AnnotationShape::Text { position, text, size } => {
+                let origin = self.to_pixels(*position);
+                let cell = (size * self.frame.height as f32 / GLYPH_HEIGHT as f32).max(1.0);
+                self.draw_text(origin, text, cell, color);
+            }
+        }
+    }
+
+    fn draw_text(&mut self, origin: (f32, f32), text: &str, cell: f32, color: AnnotationColor) {
+        let advance = (GLYPH_WIDTH + 1) as f32 * cell;
+        for (index, c) in text.chars().enumerate() {
+            let left = origin.0 + index as f32 * advance;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        let x = left + column as f32 * cell;
+                        let y = origin.1 + row as f32 * cell;
+                        self.fill_rect(x, y, x + cell - 1.0, y + cell - 1.0, color);
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_laser(&mut self, position: NormalizedPoint) {
+        let (x, y) = self.to_pixels(position);
+        let radius = (10.0 * self.scale).max(3.0);
+        self.fill_circle(x, y, radius, AnnotationColor { a: 96, ..AnnotationColor::RED });
+        self.fill_circle(x, y, radius / 2.0, AnnotationColor::RED);
+    }
+}
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// 5x7 bitmap glyph; lowercase letters are drawn as capitals
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; GLYPH_HEIGHT],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '?' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        _ => [0x1F, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1F],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission_profiles::PermissionProfileType;
+
+    fn monitor(id: usize) -> MonitorInfo {
+        MonitorInfo {
+            id,
+            name: format!("Monitor {}", id),
+            width: 100,
+            height: 100,
+            x: 0,
+            y: 0,
+            is_primary: id == 0,
+        }
+    }
+
+    fn blank_frame(width: u32, height: u32) -> Frame {
+        Frame {
+            width,
+            height,
+            stride: width * 4,
+            data: vec![0; (width * height * 4) as usize],
+            timestamp: 0,
+        }
+    }
+
+    fn pixel(frame: &Frame, x: u32, y: u32) -> [u8; 4] {
+        let offset = (y * frame.stride + x * 4) as usize;
+        frame.data[offset..offset + 4].try_into().unwrap()
+    }
+
+    fn rectangle(monitor_id: usize) -> AnnotationAction {
+        AnnotationAction::Add(Annotation {
+            id: uuid::Uuid::new_v4(),
+            monitor_id,
+            color: AnnotationColor::RED,
+            width: 4.0,
+            shape: AnnotationShape::Rectangle {
+                from: NormalizedPoint::new(0.25, 0.25),
+                to: NormalizedPoint::new(0.75, 0.75),
+                filled: true,
+            },
+        })
+    }
+
+    #[test]
+    fn test_render_scales_to_frame_and_filters_monitor() {
+        let manager = AnnotationManager::new("viewer");
+        manager.apply_local(rectangle(0)).unwrap();
+        manager.apply_local(rectangle(1)).unwrap();
+
+        // Same annotation lands at the same relative position on a scaled frame
+        for size in [100, 40] {
+            let mut frame = blank_frame(size, size);
+            manager.render(&mut frame, &monitor(0));
+            assert_eq!(pixel(&frame, size / 2, size / 2), [0, 0, 255, 0]);
+            assert_eq!(pixel(&frame, 1, 1), [0, 0, 0, 0]);
+        }
+
+        let mut other = blank_frame(10, 10);
+        manager.render(&mut other, &monitor(2));
+        assert!(other.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_undo_is_per_author_and_clear() {
+        let mut layer = AnnotationLayer::new();
+        let event = |author: &str, action| AnnotationEvent { author: author.to_string(), action };
+
+        layer.apply(&event("a", rectangle(0))).unwrap();
+        layer.apply(&event("b", rectangle(0))).unwrap();
+        layer.apply(&event("a", rectangle(1))).unwrap();
+
+        layer.apply(&event("b", AnnotationAction::Undo)).unwrap();
+        assert_eq!(layer.len(), 2);
+        let monitors: Vec<usize> = layer.annotations().map(|a| a.monitor_id).collect();
+        assert_eq!(monitors, vec![0, 1]);
+
+        layer.apply(&event("a", AnnotationAction::Clear { monitor_id: Some(1) })).unwrap();
+        assert_eq!(layer.len(), 1);
+        layer.clear(None);
+        assert!(layer.is_empty());
+    }
+
+    #[test]
+    fn test_laser_pointer_expires() {
+        let mut layer = AnnotationLayer::new();
+        let now = Instant::now();
+        layer
+            .apply_at(
+                &AnnotationEvent {
+                    author: "viewer".to_string(),
+                    action: AnnotationAction::Laser {
+                        monitor_id: 0,
+                        position: Some(NormalizedPoint::new(0.5, 0.5)),
+                    },
+                },
+                now,
+            )
+            .unwrap();
+
+        let mut frame = blank_frame(50, 50);
+        layer.render_at(&mut frame, &monitor(0), now);
+        assert_ne!(pixel(&frame, 24, 24), [0, 0, 0, 0]);
+
+        let mut later = blank_frame(50, 50);
+        layer.render_at(&mut later, &monitor(0), now + LASER_TIMEOUT);
+        assert!(later.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_host_requires_draw_permission() {
+        let mut profile = PermissionProfile::new(PermissionProfileType::Default);
+        profile.set_permission(Permission::DrawOnScreen, false);
+        let host = AnnotationManager::for_host("host", profile);
+        let viewer = AnnotationManager::new("viewer");
+
+        let event = viewer.apply_local(rectangle(0)).unwrap();
+        assert!(matches!(host.apply_remote(&event), Err(ClientError::PermissionDenied(_))));
+        assert!(host.handle_message(&MessagePayload::Annotation(event)));
+        assert_eq!(host.annotation_count(), 0);
+    }
+
+    #[test]
+    fn test_rejects_invalid_annotations() {
+        let mut layer = AnnotationLayer::new();
+        let add = |shape| AnnotationEvent {
+            author: "viewer".to_string(),
+            action: AnnotationAction::Add(Annotation {
+                id: uuid::Uuid::new_v4(),
+                monitor_id: 0,
+                color: AnnotationColor::BLUE,
+                width: 2.0,
+                shape,
+            }),
+        };
+
+        let outside = AnnotationShape::Arrow {
+            from: NormalizedPoint { x: 0.0, y: 0.0 },
+            to: NormalizedPoint { x: 2.0, y: 0.5 },
+        };
+        assert!(layer.apply(&add(outside)).is_err());
+        assert!(layer.apply(&add(AnnotationShape::Stroke { points: Vec::new() })).is_err());
+        assert!(layer.is_empty());
+    }
+
+    #[test]
+    fn test_text_and_snapshot_export() {
+        let manager = AnnotationManager::new("viewer");
+        manager
+            .apply_local(AnnotationAction::Add(Annotation {
+                id: uuid::Uuid::new_v4(),
+                monitor_id: 0,
+                color: AnnotationColor::GREEN,
+                width: 1.0,
+                shape: AnnotationShape::Text {
+                    position: NormalizedPoint::new(0.0, 0.0),
+                    text: "Hi".to_string(),
+                    size: 0.5,
+                },
+            }))
+            .unwrap();
+
+        let frame = blank_frame(64, 32);
+        let path = std::env::temp_dir().join(format!("genxlink-annotation-{}.png", uuid::Uuid::new_v4()));
+        manager.export_snapshot(&frame, &monitor(0), &path).unwrap();
+
+        let image = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(image.dimensions(), (64, 32));
+        // Top-left pixel of "H" is set, the source frame is untouched
+        assert_eq!(image.get_pixel(0, 0).0, [0, 200, 0, 255]);
+        assert!(frame.data.iter().all(|&b| b == 0));
+    }
+}
@@ -1,8 +1,17 @@
 use crate::database::{DatabaseClient, UserAccount, UserPreferences, SubscriptionType};
 use crate::ClientError;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// How long single sign-on waits for the browser to come back
+pub const SSO_TIMEOUT: Duration = Duration::from_secs(5 * 60);
 
 /// Authentication service for Supabase
 pub struct AuthService {
@@ -12,6 +21,8 @@ pub struct AuthService {
     database_client: DatabaseClient,
     current_user: Option<UserAccount>,
     session: Option<AuthSession>,
+    /// MFA factors enrolled for the current user
+    factors: Vec<MfaFactor>,
 }
 
 /// Authentication session
@@ -23,6 +34,101 @@ pub struct AuthSession {
     pub user_id: String,
 }
 
+impl AuthSession {
+    /// Assurance level recorded in the access token (`aal` claim)
+    ///
+    /// Read without verifying the signature; the server enforces it, this is
+    /// only used for local policy decisions.
+    pub fn assurance_level(&self) -> AssuranceLevel {
+        let aal = self
+            .access_token
+            .split('.')
+            .nth(1)
+            .and_then(|payload| URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok())
+            .and_then(|payload| serde_json::from_slice::<serde_json::Value>(&payload).ok())
+            .and_then(|claims| claims.get("aal").and_then(|v| v.as_str()).map(str::to_string));
+
+        match aal.as_deref() {
+            Some("aal2") => AssuranceLevel::Aal2,
+            _ => AssuranceLevel::Aal1,
+        }
+    }
+}
+
+/// Authenticator assurance level of a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssuranceLevel {
+    /// Password only
+    Aal1,
+    /// Password and a second factor
+    Aal2,
+}
+
+/// Whether outbound remote control needs a login that passed MFA
+///
+/// One policy is shared by every path that starts a session (the session
+/// manager and the connection manager), so none of them can skip the check.
+#[derive(Debug, Clone, Default)]
+pub struct MfaPolicy {
+    required: bool,
+    /// Assurance level of the current login; `None` when signed out
+    assurance: Arc<std::sync::RwLock<Option<AssuranceLevel>>>,
+}
+
+impl MfaPolicy {
+    pub fn new(required: bool) -> Self {
+        Self { required, assurance: Arc::default() }
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Track the login whose assurance level remote control depends on
+    pub fn set_session(&self, session: Option<&AuthSession>) {
+        *self.assurance.write().unwrap() = session.map(AuthSession::assurance_level);
+    }
+
+    /// Fails when MFA is required and the current login hasn't passed it
+    pub fn check_remote_control(&self) -> Result<(), ClientError> {
+        if self.required && *self.assurance.read().unwrap() != Some(AssuranceLevel::Aal2) {
+            return Err(ClientError::PermissionDenied(
+                "Two-factor authentication required for remote control".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Enrolled MFA factor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaFactor {
+    pub id: String,
+    #[serde(default)]
+    pub friendly_name: Option<String>,
+    pub factor_type: String,
+    /// `verified` once enrollment was confirmed with a code
+    pub status: String,
+}
+
+impl MfaFactor {
+    pub fn is_verified(&self) -> bool {
+        self.status == "verified"
+    }
+}
+
+/// TOTP enrollment details to show the user
+#[derive(Debug, Clone, Deserialize)]
+pub struct TotpEnrollment {
+    pub factor_id: String,
+    /// Base32 secret for manual entry
+    pub secret: String,
+    /// `otpauth://` provisioning URI
+    pub uri: String,
+    /// QR code of the provisioning URI (SVG data URI)
+    pub qr_code: String,
+}
+
 /// Login request
 #[derive(Debug, Clone, Serialize)]
 pub struct LoginRequest {
@@ -46,6 +152,16 @@ pub struct AuthResponse {
     pub session: AuthSession,
 }
 
+/// Outcome of single sign-on
+#[derive(Debug, Clone)]
+pub enum SsoLogin {
+    SignedIn(Box<AuthResponse>),
+    /// The account has two-factor authentication: pass a code from the
+    /// authenticator (or a recovery code) to `AuthService::sso_verify_mfa`
+    /// along with this token
+    MfaRequired { mfa_token: String },
+}
+
 /// Password reset request
 #[derive(Debug, Clone, Serialize)]
 pub struct PasswordResetRequest {
@@ -60,6 +176,128 @@ pub struct UpdateProfileRequest {
     pub preferences: Option<UserPreferences>,
 }
 
+/// PKCE verifier and its S256 challenge (RFC 7636)
+#[derive(Debug, Clone)]
+pub struct PkcePair {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl PkcePair {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self::from_verifier(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+/// One-shot HTTP listener on 127.0.0.1 that receives the browser after
+/// signing in at the identity provider (RFC 8252 loopback redirect)
+pub struct LoopbackRedirect {
+    listener: TcpListener,
+    redirect_uri: String,
+}
+
+impl LoopbackRedirect {
+    /// Listen on a free loopback port
+    pub async fn bind() -> Result<Self, ClientError> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| ClientError::IoError(format!("Failed to open redirect listener: {}", e)))?;
+        let port = listener
+            .local_addr()
+            .map_err(|e| ClientError::IoError(format!("Failed to open redirect listener: {}", e)))?
+            .port();
+
+        Ok(Self { listener, redirect_uri: format!("http://127.0.0.1:{}/callback", port) })
+    }
+
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Wait for the browser and return the authorization code it brings
+    ///
+    /// Requests for other paths (e.g. favicons) and redirects with the wrong
+    /// `state` are answered and ignored, so a stray or forged request can't
+    /// end the sign-in. A redirect with the right `state` and an `error`
+    /// fails.
+    pub async fn wait_for_code(self, expected_state: &str, timeout: Duration) -> Result<String, ClientError> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let (mut stream, _) = self
+                    .listener
+                    .accept()
+                    .await
+                    .map_err(|e| ClientError::IoError(format!("Redirect listener failed: {}", e)))?;
+
+                let Some(params) = read_callback(&mut stream).await else {
+                    let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+                    continue;
+                };
+
+                if params.get("state").map(String::as_str) != Some(expected_state) {
+                    let page = "This sign-in response doesn't belong to the current sign-in.";
+                    let response = format!(
+                        "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        page.len(),
+                        page
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                    continue;
+                }
+
+                let result = if let Some(error) = params.get("error") {
+                    let description = params.get("error_description").unwrap_or(error);
+                    Err(ClientError::AuthenticationError(format!("Sign-in failed: {}", description)))
+                } else {
+                    params
+                        .get("code")
+                        .cloned()
+                        .ok_or_else(|| ClientError::AuthenticationError("Sign-in response has no code".to_string()))
+                };
+
+                let page = match &result {
+                    Ok(_) => "Signed in. You can close this window and return to GenXLink.",
+                    Err(_) => "Sign-in failed. You can close this window and return to GenXLink.",
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    page.len(),
+                    page
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                return result;
+            }
+        })
+        .await
+        .map_err(|_| ClientError::AuthenticationError("Timed out waiting for the browser".to_string()))?
+    }
+}
+
+/// Query parameters of a `GET /callback` request, `None` for anything else
+async fn read_callback(stream: &mut tokio::net::TcpStream) -> Option<HashMap<String, String>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 16 * 1024 {
+        let n = stream.read(&mut buf).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let target = request.lines().next()?.strip_prefix("GET ")?.split(' ').next()?;
+    let url = reqwest::Url::parse(&format!("http://127.0.0.1{}", target)).ok()?;
+    (url.path() == "/callback").then(|| url.query_pairs().into_owned().collect())
+}
+
 impl AuthService {
     /// Create a new authentication service
     pub fn new(base_url: String, anon_key: String) -> Self {
@@ -72,6 +310,7 @@ impl AuthService {
             database_client,
             current_user: None,
             session: None,
+            factors: Vec::new(),
         }
     }
 
@@ -143,6 +382,7 @@ impl AuthService {
             let auth_response: SupabaseAuthResponse = response.json().await
                 .map_err(|e| ClientError::IoError(format!("Failed to parse auth response: {}", e)))?;
             
+            self.factors = auth_response.user.factors.clone().unwrap_or_default();
             let user = self.convert_supabase_user(auth_response.user)?;
             let session = AuthSession {
                 access_token: auth_response.access_token,
@@ -158,6 +398,10 @@ impl AuthService {
             self.current_user = Some(user.clone());
             self.session = Some(session.clone());
 
+            if self.mfa_required() {
+                tracing::info!("Second factor required to complete login");
+            }
+
             Ok(AuthResponse { user, session })
         } else {
             let error_text = response.text().await.unwrap_or_default();
@@ -181,6 +425,7 @@ impl AuthService {
             // Clear local session regardless of response
             self.current_user = None;
             self.session = None;
+            self.factors.clear();
             
             // Reset database client to use anon key
             self.database_client.set_auth_token(self.anon_key.clone());
@@ -260,6 +505,81 @@ impl AuthService {
         }
     }
 
+    /// Sign in through the GenXLink API's single sign-on (OpenID Connect)
+    ///
+    /// `open_browser` is handed the identity provider's login page; the
+    /// browser comes back to a loopback listener. The returned session is
+    /// issued by the GenXLink API at `api_url`, not by Supabase, so it isn't
+    /// stored as the current session. Accounts with two-factor
+    /// authentication get `SsoLogin::MfaRequired` instead of a session.
+    pub async fn sso_login<F>(&self, api_url: &str, open_browser: F) -> Result<SsoLogin, ClientError>
+    where
+        F: FnOnce(&str) -> Result<(), ClientError>,
+    {
+        let redirect = LoopbackRedirect::bind().await?;
+        let pkce = PkcePair::generate();
+
+        let started: ApiSsoStartResponse = self
+            .client
+            .post(format!("{}/auth/oidc/authorize", api_url))
+            .json(&serde_json::json!({
+                "redirect_uri": redirect.redirect_uri(),
+                "code_challenge": pkce.challenge,
+            }))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("SSO request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse SSO response: {}", e)))?;
+        let (Some(authorization_url), Some(state)) = (started.authorization_url, started.state) else {
+            return Err(ClientError::AuthenticationError(started.message));
+        };
+
+        open_browser(&authorization_url)?;
+        let code = redirect.wait_for_code(&state, SSO_TIMEOUT).await?;
+
+        let login: ApiLoginResponse = self
+            .client
+            .post(format!("{}/auth/oidc/callback", api_url))
+            .json(&serde_json::json!({
+                "code": code,
+                "state": state,
+                "code_verifier": pkce.verifier,
+            }))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("SSO request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse SSO response: {}", e)))?;
+
+        if let Some(mfa_token) = login.mfa_token {
+            return Ok(SsoLogin::MfaRequired { mfa_token });
+        }
+        login.into_auth_response().map(|response| SsoLogin::SignedIn(Box::new(response)))
+    }
+
+    /// Second single sign-on step for accounts with two-factor
+    /// authentication; a wrong code can be retried with the same token
+    pub async fn sso_verify_mfa(&self, api_url: &str, mfa_token: &str, code: &str) -> Result<AuthResponse, ClientError> {
+        let login: ApiLoginResponse = self
+            .client
+            .post(format!("{}/auth/mfa/verify", api_url))
+            .json(&serde_json::json!({
+                "mfa_token": mfa_token,
+                "code": code,
+            }))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("MFA verification request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse MFA verification response: {}", e)))?;
+
+        login.into_auth_response()
+    }
+
     /// Get current user
     pub fn get_current_user(&self) -> Option<&UserAccount> {
         self.current_user.as_ref()
@@ -271,8 +591,170 @@ impl AuthService {
     }
 
     /// Check if user is authenticated
+    ///
+    /// A session still waiting for its second factor doesn't count.
     pub fn is_authenticated(&self) -> bool {
-        self.session.is_some() && self.current_user.is_some()
+        self.session.is_some() && self.current_user.is_some() && !self.mfa_required()
+    }
+
+    /// Password step done, but a verified factor still has to be presented
+    pub fn mfa_required(&self) -> bool {
+        match &self.session {
+            Some(session) => {
+                self.factors.iter().any(MfaFactor::is_verified)
+                    && session.assurance_level() < AssuranceLevel::Aal2
+            }
+            None => false,
+        }
+    }
+
+    /// Factors enrolled for the current user, as of the last login or refresh
+    pub fn get_factors(&self) -> &[MfaFactor] {
+        &self.factors
+    }
+
+    /// Reload the enrolled factors from the server
+    pub async fn list_factors(&mut self) -> Result<Vec<MfaFactor>, ClientError> {
+        let access_token = self.access_token()?;
+        let url = format!("{}/auth/v1/user", self.base_url);
+
+        let response = self.client
+            .get(&url)
+            .header("apikey", &self.anon_key)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Factor request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(ClientError::AuthenticationError("Failed to list MFA factors".to_string()));
+        }
+
+        let user: SupabaseUser = response.json().await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse user response: {}", e)))?;
+        self.factors = user.factors.unwrap_or_default();
+        Ok(self.factors.clone())
+    }
+
+    /// Start TOTP enrollment; confirm it with `verify_totp`
+    pub async fn enroll_totp(&self, friendly_name: &str) -> Result<TotpEnrollment, ClientError> {
+        let access_token = self.access_token()?;
+        let url = format!("{}/auth/v1/factors", self.base_url);
+
+        let response = self.client
+            .post(&url)
+            .header("apikey", &self.anon_key)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(&serde_json::json!({
+                "factor_type": "totp",
+                "friendly_name": friendly_name,
+                "issuer": "GenXLink",
+            }))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Enrollment request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ClientError::AuthenticationError(format!("TOTP enrollment failed: {}", error_text)));
+        }
+
+        let enrollment: SupabaseEnrollResponse = response.json().await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse enrollment response: {}", e)))?;
+        Ok(TotpEnrollment {
+            factor_id: enrollment.id,
+            secret: enrollment.totp.secret,
+            uri: enrollment.totp.uri,
+            qr_code: enrollment.totp.qr_code,
+        })
+    }
+
+    /// Verify a TOTP code, upgrading the session to `Aal2`
+    ///
+    /// Used both to confirm a new enrollment and as the second login step.
+    pub async fn verify_totp(&mut self, factor_id: &str, code: &str) -> Result<AuthSession, ClientError> {
+        let access_token = self.access_token()?;
+
+        let url = format!("{}/auth/v1/factors/{}/challenge", self.base_url, factor_id);
+        let response = self.client
+            .post(&url)
+            .header("apikey", &self.anon_key)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Challenge request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(ClientError::AuthenticationError("Failed to create MFA challenge".to_string()));
+        }
+        let challenge: SupabaseChallengeResponse = response.json().await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse challenge response: {}", e)))?;
+
+        let url = format!("{}/auth/v1/factors/{}/verify", self.base_url, factor_id);
+        let response = self.client
+            .post(&url)
+            .header("apikey", &self.anon_key)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(&serde_json::json!({
+                "challenge_id": challenge.id,
+                "code": code.trim(),
+            }))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Verify request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(ClientError::AuthenticationError("Invalid authentication code".to_string()));
+        }
+
+        let auth_response: SupabaseAuthResponse = response.json().await
+            .map_err(|e| ClientError::IoError(format!("Failed to parse verify response: {}", e)))?;
+
+        self.factors = auth_response.user.factors.clone().unwrap_or_default();
+        let user = self.convert_supabase_user(auth_response.user)?;
+        let session = AuthSession {
+            access_token: auth_response.access_token,
+            refresh_token: auth_response.refresh_token,
+            expires_at: auth_response.expires_at.unwrap_or(0),
+            user_id: user.id.clone(),
+        };
+
+        self.database_client.set_auth_token(session.access_token.clone());
+        self.current_user = Some(user);
+        self.session = Some(session.clone());
+
+        Ok(session)
+    }
+
+    /// Remove an enrolled factor (requires an `Aal2` session once one is verified)
+    pub async fn unenroll_factor(&mut self, factor_id: &str) -> Result<(), ClientError> {
+        let access_token = self.access_token()?;
+        let url = format!("{}/auth/v1/factors/{}", self.base_url, factor_id);
+
+        let response = self.client
+            .delete(&url)
+            .header("apikey", &self.anon_key)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| ClientError::IoError(format!("Unenroll request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(ClientError::AuthenticationError(format!("Failed to remove factor: {}", error_text)));
+        }
+
+        self.factors.retain(|factor| factor.id != factor_id);
+        Ok(())
+    }
+
+    fn access_token(&self) -> Result<String, ClientError> {
+        self.session
+            .as_ref()
+            .map(|session| session.access_token.clone())
+            .ok_or_else(|| ClientError::AuthenticationError("No active session".to_string()))
     }
 
     /// Update user profile
@@ -409,6 +891,107 @@ struct SupabaseUser {
     pub phone_confirmed_at: Option<SystemTime>,
     pub user_metadata: Option<HashMap<String, serde_json::Value>>,
     pub app_metadata: Option<HashMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub factors: Option<Vec<MfaFactor>>,
+}
+
+/// `exp` claim of a JWT, read without verifying the signature
+fn token_expiry(token: &str) -> Option<u64> {
+    let payload = URL_SAFE_NO_PAD.decode(token.split('.').nth(1)?.trim_end_matches('=')).ok()?;
+    serde_json::from_slice::<serde_json::Value>(&payload).ok()?.get("exp")?.as_u64()
+}
+
+/// GenXLink API response to starting single sign-on
+#[derive(Debug, Clone, Deserialize)]
+struct ApiSsoStartResponse {
+    pub message: String,
+    pub authorization_url: Option<String>,
+    pub state: Option<String>,
+}
+
+/// GenXLink API login response
+#[derive(Debug, Clone, Deserialize)]
+struct ApiLoginResponse {
+    pub message: String,
+    pub user: Option<ApiUser>,
+    pub token: Option<String>,
+    pub refresh_token: Option<String>,
+    /// Set instead of `token` when a second factor is required
+    pub mfa_token: Option<String>,
+}
+
+impl ApiLoginResponse {
+    /// Session issued by the GenXLink API; the message becomes the error
+    /// when there is none
+    fn into_auth_response(self) -> Result<AuthResponse, ClientError> {
+        let (Some(user), Some(access_token)) = (self.user, self.token) else {
+            return Err(ClientError::AuthenticationError(self.message));
+        };
+        let session = AuthSession {
+            expires_at: token_expiry(&access_token).unwrap_or(0),
+            access_token,
+            refresh_token: self.refresh_token.unwrap_or_default(),
+            user_id: user.id.clone(),
+        };
+        Ok(AuthResponse { user: user.into(), session })
+    }
+}
+
+/// GenXLink API user
+#[derive(Debug, Clone, Deserialize)]
+struct ApiUser {
+    pub id: String,
+    pub email: String,
+    pub username: String,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub subscription_type: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_login: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<ApiUser> for UserAccount {
+    fn from(user: ApiUser) -> Self {
+        UserAccount {
+            id: user.id,
+            email: user.email,
+            username: user.username,
+            display_name: user.display_name,
+            avatar_url: user.avatar_url,
+            is_active: user.is_active,
+            is_verified: user.is_verified,
+            subscription_type: match user.subscription_type.as_str() {
+                "premium" => SubscriptionType::Premium,
+                "enterprise" => SubscriptionType::Enterprise,
+                _ => SubscriptionType::Free,
+            },
+            created_at: user.created_at.into(),
+            last_login: user.last_login.map(Into::into),
+            preferences: UserPreferences::default(),
+        }
+    }
+}
+
+/// Supabase TOTP enrollment response
+#[derive(Debug, Clone, Deserialize)]
+struct SupabaseEnrollResponse {
+    pub id: String,
+    pub totp: SupabaseTotpDetails,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SupabaseTotpDetails {
+    pub qr_code: String,
+    pub secret: String,
+    pub uri: String,
+}
+
+/// Supabase MFA challenge response
+#[derive(Debug, Clone, Deserialize)]
+struct SupabaseChallengeResponse {
+    pub id: String,
 }
 
 #[cfg(test)]
@@ -454,6 +1037,165 @@ mod tests {
         assert!(json.contains("Test User"));
     }
 
+    fn session_with_claims(claims: serde_json::Value) -> AuthSession {
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        AuthSession {
+            access_token: format!("eyJhbGciOiJIUzI1NiJ9.{}.signature", payload),
+            refresh_token: "refresh_token".to_string(),
+            expires_at: 0,
+            user_id: "user_123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_assurance_level_from_token() {
+        assert_eq!(session_with_claims(serde_json::json!({ "aal": "aal2" })).assurance_level(), AssuranceLevel::Aal2);
+        assert_eq!(session_with_claims(serde_json::json!({ "aal": "aal1" })).assurance_level(), AssuranceLevel::Aal1);
+        assert_eq!(session_with_claims(serde_json::json!({})).assurance_level(), AssuranceLevel::Aal1);
+
+        let opaque = AuthSession {
+            access_token: "not-a-jwt".to_string(),
+            ..session_with_claims(serde_json::json!({}))
+        };
+        assert_eq!(opaque.assurance_level(), AssuranceLevel::Aal1);
+    }
+
+    #[test]
+    fn test_mfa_required_until_second_factor() {
+        let mut auth_service = AuthService::new(
+            "https://test.supabase.co".to_string(),
+            "test_key".to_string(),
+        );
+        auth_service.current_user = Some(UserAccount {
+            id: "user_123".to_string(),
+            email: "test@example.com".to_string(),
+            username: "testuser".to_string(),
+            display_name: "Test User".to_string(),
+            avatar_url: None,
+            is_active: true,
+            is_verified: true,
+            subscription_type: SubscriptionType::Free,
+            created_at: SystemTime::UNIX_EPOCH,
+            last_login: None,
+            preferences: UserPreferences::default(),
+        });
+        auth_service.session = Some(session_with_claims(serde_json::json!({ "aal": "aal1" })));
+        assert!(auth_service.is_authenticated());
+
+        // Unverified factors don't count
+        auth_service.factors = vec![MfaFactor {
+            id: "factor".to_string(),
+            friendly_name: None,
+            factor_type: "totp".to_string(),
+            status: "unverified".to_string(),
+        }];
+        assert!(!auth_service.mfa_required());
+
+        auth_service.factors[0].status = "verified".to_string();
+        assert!(auth_service.mfa_required());
+        assert!(!auth_service.is_authenticated());
+
+        auth_service.session = Some(session_with_claims(serde_json::json!({ "aal": "aal2" })));
+        assert!(!auth_service.mfa_required());
+        assert!(auth_service.is_authenticated());
+    }
+
+    #[test]
+    fn test_supabase_factor_parsing() {
+        let user: SupabaseUser = serde_json::from_value(serde_json::json!({
+            "id": "user_123",
+            "email": "test@example.com",
+            "created_at": null,
+            "updated_at": null,
+            "last_sign_in_at": null,
+            "email_confirmed_at": null,
+            "phone": null,
+            "phone_confirmed_at": null,
+            "user_metadata": null,
+            "app_metadata": null,
+            "factors": [{ "id": "f1", "friendly_name": "Phone", "factor_type": "totp", "status": "verified" }]
+        })).unwrap();
+        let factors = user.factors.unwrap();
+        assert_eq!(factors.len(), 1);
+        assert!(factors[0].is_verified());
+    }
+
+    #[test]
+    fn test_pkce_pair() {
+        // RFC 7636, appendix B
+        let pair = PkcePair::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(pair.challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+        let generated = PkcePair::generate();
+        assert_eq!(generated.verifier.len(), 43);
+        assert_ne!(generated.verifier, PkcePair::generate().verifier);
+    }
+
+    async fn browser_redirect(redirect_uri: &str, query: &str) -> u16 {
+        reqwest::get(format!("{}?{}", redirect_uri, query)).await.unwrap().status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn test_loopback_redirect_returns_code() {
+        let redirect = LoopbackRedirect::bind().await.unwrap();
+        let uri = redirect.redirect_uri().to_string();
+        assert!(uri.starts_with("http://127.0.0.1:"));
+
+        let browser = tokio::spawn(async move {
+            let favicon = reqwest::get(uri.replace("/callback", "/favicon.ico")).await.unwrap().status().as_u16();
+            (favicon, browser_redirect(&uri, "code=abc%2F123&state=expected").await)
+        });
+        let code = redirect.wait_for_code("expected", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(code, "abc/123");
+        assert_eq!(browser.await.unwrap(), (404, 200));
+    }
+
+    #[tokio::test]
+    async fn test_loopback_redirect_ignores_forged_state() {
+        let redirect = LoopbackRedirect::bind().await.unwrap();
+        let uri = redirect.redirect_uri().to_string();
+        let browser = tokio::spawn(async move {
+            let forged = browser_redirect(&uri, "error=access_denied&state=forged").await;
+            (forged, browser_redirect(&uri, "code=abc&state=expected").await)
+        });
+        let code = redirect.wait_for_code("expected", Duration::from_secs(5)).await.unwrap();
+        assert_eq!(code, "abc");
+        assert_eq!(browser.await.unwrap(), (400, 200));
+
+        let redirect = LoopbackRedirect::bind().await.unwrap();
+        let uri = redirect.redirect_uri().to_string();
+        tokio::spawn(async move { browser_redirect(&uri, "error=access_denied&state=expected").await });
+        let error = redirect.wait_for_code("expected", Duration::from_secs(5)).await.unwrap_err();
+        assert!(error.to_string().contains("access_denied"));
+
+        let redirect = LoopbackRedirect::bind().await.unwrap();
+        assert!(redirect.wait_for_code("expected", Duration::from_millis(50)).await.is_err());
+    }
+
+    #[test]
+    fn test_api_user_conversion() {
+        let user: ApiUser = serde_json::from_value(serde_json::json!({
+            "id": "2f1c", "email": "jane@example.com", "username": "jane", "display_name": "Jane",
+            "avatar_url": null, "is_active": true, "is_verified": true, "subscription_type": "enterprise",
+            "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-01T00:00:00Z", "last_login": null,
+            "preferences": {}, "auth_provider": "oidc"
+        })).unwrap();
+        let account = UserAccount::from(user);
+        assert_eq!(account.subscription_type, SubscriptionType::Enterprise);
+        assert_eq!(account.created_at, SystemTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200));
+
+        let token = format!("header.{}.signature", URL_SAFE_NO_PAD.encode(r#"{"exp":1700000000}"#));
+        assert_eq!(token_expiry(&token), Some(1_700_000_000));
+        assert_eq!(token_expiry("opaque"), None);
+
+        let challenge: ApiLoginResponse = serde_json::from_value(serde_json::json!({
+            "success": true, "message": "Enter your authentication code", "user": null, "token": null,
+            "expires_at": null, "mfa_token": "challenge"
+        })).unwrap();
+        assert_eq!(challenge.mfa_token.as_deref(), Some("challenge"));
+        assert!(challenge.into_auth_response().is_err());
+    }
+
     #[test]
     fn test_auth_session_serialization() {
         let session = AuthSession {
@@ -0,0 +1,397 @@
+//! Brute-force protection for short secrets
+//!
+//! Session passwords and zero-setup PINs are short enough to be guessed
+//! exhaustively. `AttemptLimiter` tracks failed attempts per source and
+//! globally, applies exponential backoff between guesses, locks sources out
+//! after repeated failures and notifies the host when a lockout happens.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// Source identifier used when the caller has no better identity (local UI)
+pub const LOCAL_SOURCE: &str = "local";
+
+/// Lockout policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockoutPolicy {
+    /// Failures from one source before that source is locked out
+    pub max_failures_per_source: u32,
+    /// Failures from all sources (within the window) before the secret is locked
+    pub max_failures_global: u32,
+    /// Delay enforced after the first failure, doubled for every further failure
+    pub base_delay: Duration,
+    /// Upper bound for the backoff delay
+    pub max_delay: Duration,
+    /// How long a lockout lasts
+    pub lockout_duration: Duration,
+    /// Failures older than this are forgotten
+    pub failure_window: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            max_failures_per_source: 5,
+            max_failures_global: 20,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(300),
+            lockout_duration: Duration::from_secs(900),
+            failure_window: Duration::from_secs(900),
+        }
+    }
+}
+
+/// Reason an attempt was refused before the secret was checked
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AttemptError {
+    #[error("Too many attempts, retry in {}s", retry_after.as_secs().max(1))]
+    Backoff { retry_after: Duration },
+
+    #[error("Source locked out, retry in {}s", retry_after.as_secs().max(1))]
+    SourceLocked { retry_after: Duration },
+
+    #[error("Access locked after repeated failures, retry in {}s", retry_after.as_secs().max(1))]
+    GloballyLocked { retry_after: Duration },
+}
+
+impl AttemptError {
+    /// Time until the next attempt will be considered
+    pub fn retry_after(&self) -> Duration {
+        match self {
+            Self::Backoff { retry_after }
+            | Self::SourceLocked { retry_after }
+            | Self::GloballyLocked { retry_after } => *retry_after,
+        }
+    }
+}
+
+/// Lockout notification delivered to the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockoutEvent {
+    /// A single source exceeded its failure budget
+    SourceLockedOut {
+        source: String,
+        failures: u32,
+        duration: Duration,
+    },
+    /// Failures from all sources exceeded the global budget
+    GlobalLockout {
+        failures: u32,
+        duration: Duration,
+    },
+}
+
+/// Per-source attempt state
+#[derive(Debug, Clone)]
+struct SourceState {
+    failures: u32,
+    last_failure: Instant,
+    next_allowed: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Failed attempt tracker with exponential backoff and lockout
+pub struct AttemptLimiter {
+    policy: LockoutPolicy,
+    sources: HashMap<String, SourceState>,
+    global_failures: VecDeque<Instant>,
+    global_locked_until: Option<Instant>,
+    notifier: Option<mpsc::UnboundedSender<LockoutEvent>>,
+}
+
+impl Default for AttemptLimiter {
+    fn default() -> Self {
+        Self::new(LockoutPolicy::default())
+    }
+}
+
+impl AttemptLimiter {
+    /// Create a new limiter with the given policy
+    pub fn new(policy: LockoutPolicy) -> Self {
+        Self {
+            policy,
+            sources: HashMap::new(),
+            global_failures: VecDeque::new(),
+            global_locked_until: None,
+            notifier: None,
+        }
+    }
+
+    /// Get the active policy
+    pub fn policy(&self) -> &LockoutPolicy {
+        &self.policy
+    }
+
+    /// Deliver lockout notifications to the given channel
+    pub fn set_notifier(&mut self, notifier: mpsc::UnboundedSender<LockoutEvent>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Check whether `source` may attempt a verification now
+    pub fn check(&mut self, source: &str) -> Result<(), AttemptError> {
+        self.check_at(source, Instant::now())
+    }
+
+    /// Check whether `source` may attempt a verification at `now`
+    pub fn check_at(&mut self, source: &str, now: Instant) -> Result<(), AttemptError> {
+        if let Some(until) = self.global_locked_until {
+            if now < until {
+                return Err(AttemptError::GloballyLocked { retry_after: until - now });
+            }
+            self.global_locked_until = None;
+            self.global_failures.clear();
+        }
+
+        if let Some(state) = self.sources.get_mut(source) {
+            if let Some(until) = state.locked_until {
+                if now < until {
+                    return Err(AttemptError::SourceLocked { retry_after: until - now });
+                }
+                // Lockout served, start over
+                self.sources.remove(source);
+                return Ok(());
+            }
+
+            if now < state.next_allowed {
+                return Err(AttemptError::Backoff { retry_after: state.next_allowed - now });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Record a failed attempt from `source`
+    ///
+    /// Returns the lockout event if this failure triggered one.
+    pub fn record_failure(&mut self, source: &str) -> Option<LockoutEvent> {
+        self.record_failure_at(source, Instant::now())
+    }
+
+    /// Record a failed attempt from `source` at `now`
+    pub fn record_failure_at(&mut self, source: &str, now: Instant) -> Option<LockoutEvent> {
+        self.evict_stale(now);
+
+        let policy = &self.policy;
+        let state = self.sources.entry(source.to_string()).or_insert(SourceState {
+            failures: 0,
+            last_failure: now,
+            next_allowed: now,
+            locked_until: None,
+        });
+
+        state.failures += 1;
+        state.last_failure = now;
+        state.next_allowed = now + Self::backoff_delay(policy, state.failures);
+
+        let mut event = None;
+
+        if state.failures >= policy.max_failures_per_source && state.locked_until.is_none() {
+            state.locked_until = Some(now + policy.lockout_duration);
+            tracing::warn!(
+                "Source {} locked out after {} failed attempts",
+                source,
+                state.failures
+            );
+            event = Some(LockoutEvent::SourceLockedOut {
+                source: source.to_string(),
+                failures: state.failures,
+                duration: policy.lockout_duration,
+            });
+        }
+
+        self.global_failures.push_back(now);
+        let global_count = self.global_failures.len() as u32;
+
+        if global_count >= self.policy.max_failures_global && self.global_locked_until.is_none() {
+            self.global_locked_until = Some(now + self.policy.lockout_duration);
+            tracing::warn!("Access locked after {} failed attempts", global_count);
+            event = Some(LockoutEvent::GlobalLockout {
+                failures: global_count,
+                duration: self.policy.lockout_duration,
+            });
+        }
+
+        if let (Some(event), Some(notifier)) = (&event, &self.notifier) {
+            let _ = notifier.send(event.clone());
+        }
+
+        event
+    }
+
+    /// Record a successful attempt, clearing the source's failure history
+    pub fn record_success(&mut self, source: &str) {
+        self.sources.remove(source);
+    }
+
+    /// Whether all sources are currently locked out
+    pub fn is_globally_locked(&self) -> bool {
+        self.is_globally_locked_at(Instant::now())
+    }
+
+    /// Whether all sources are locked out at `now`
+    pub fn is_globally_locked_at(&self, now: Instant) -> bool {
+        self.global_locked_until.is_some_and(|until| now < until)
+    }
+
+    /// Number of recorded failures for a source
+    pub fn failures(&self, source: &str) -> u32 {
+        self.sources.get(source).map_or(0, |s| s.failures)
+    }
+
+    /// Number of tracked sources
+    pub fn tracked_sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Forget all attempt history (e.g. after the secret was rotated)
+    pub fn reset(&mut self) {
+        self.sources.clear();
+        self.global_failures.clear();
+        self.global_locked_until = None;
+    }
+
+    /// Backoff delay after `failures` consecutive failures
+    fn backoff_delay(policy: &LockoutPolicy, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        policy.base_delay.saturating_mul(factor).min(policy.max_delay)
+    }
+
+    /// Drop failures that fell out of the window so memory stays bounded
+    fn evict_stale(&mut self, now: Instant) {
+        let window = self.policy.failure_window;
+
+        while let Some(&oldest) = self.global_failures.front() {
+            if now.duration_since(oldest) > window {
+                self.global_failures.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.sources.retain(|_, state| match state.locked_until {
+            Some(until) => now < until,
+            None => now.duration_since(state.last_failure) <= window,
+        });
+    }
+}
+
+/// Compare two secrets in constant time (for equal-length inputs)
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> LockoutPolicy {
+        LockoutPolicy {
+            max_failures_per_source: 3,
+            max_failures_global: 10,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(8),
+            lockout_duration: Duration::from_secs(60),
+            failure_window: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"123456", b"123456"));
+        assert!(!constant_time_eq(b"123456", b"123457"));
+        assert!(!constant_time_eq(b"123456", b"12345"));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let mut limiter = AttemptLimiter::new(policy());
+        let start = Instant::now();
+
+        assert!(limiter.check_at("a", start).is_ok());
+        limiter.record_failure_at("a", start);
+        assert_eq!(
+            limiter.check_at("a", start),
+            Err(AttemptError::Backoff { retry_after: Duration::from_secs(1) })
+        );
+
+        let t = start + Duration::from_secs(1);
+        assert!(limiter.check_at("a", t).is_ok());
+        limiter.record_failure_at("a", t);
+        assert_eq!(limiter.check_at("a", t).unwrap_err().retry_after(), Duration::from_secs(2));
+
+        // Other sources are unaffected
+        assert!(limiter.check_at("b", t).is_ok());
+    }
+
+    #[test]
+    fn test_source_lockout_and_notification() {
+        let mut limiter = AttemptLimiter::new(policy());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        limiter.set_notifier(tx);
+
+        let mut now = Instant::now();
+        for _ in 0..3 {
+            now += Duration::from_secs(10);
+            limiter.check_at("a", now).unwrap();
+            limiter.record_failure_at("a", now);
+        }
+
+        assert!(matches!(
+            limiter.check_at("a", now + Duration::from_secs(30)),
+            Err(AttemptError::SourceLocked { .. })
+        ));
+        assert!(matches!(rx.try_recv(), Ok(LockoutEvent::SourceLockedOut { failures: 3, .. })));
+
+        // Lockout expires
+        assert!(limiter.check_at("a", now + Duration::from_secs(61)).is_ok());
+        assert_eq!(limiter.failures("a"), 0);
+    }
+
+    #[test]
+    fn test_global_lockout() {
+        let mut limiter = AttemptLimiter::new(policy());
+        let now = Instant::now();
+
+        for i in 0..10 {
+            let source = format!("source-{}", i);
+            limiter.check_at(&source, now).unwrap();
+            limiter.record_failure_at(&source, now);
+        }
+
+        assert!(limiter.is_globally_locked_at(now));
+        assert!(matches!(
+            limiter.check_at("fresh", now),
+            Err(AttemptError::GloballyLocked { .. })
+        ));
+    }
+
+    #[test]
+    fn test_success_clears_history() {
+        let mut limiter = AttemptLimiter::new(policy());
+        let now = Instant::now();
+        limiter.record_failure_at("a", now);
+        limiter.record_success("a");
+        assert!(limiter.check_at("a", now).is_ok());
+    }
+
+    #[test]
+    fn test_stale_sources_are_evicted() {
+        let mut limiter = AttemptLimiter::new(policy());
+        let now = Instant::now();
+        for i in 0..5 {
+            limiter.record_failure_at(&format!("source-{}", i), now);
+        }
+        limiter.record_failure_at("late", now + Duration::from_secs(120));
+        assert_eq!(limiter.tracked_sources(), 1);
+    }
+}
@@ -0,0 +1,782 @@
+//! Send-side bandwidth estimation in the style of Google Congestion Control
+//!
+//! The sender stamps every RTP packet with a transport-wide sequence number
+//! and remembers when it left; the receiver reports when each one arrived
+//! (RTCP transport-cc feedback). Comparing how far apart groups of packets
+//! were sent with how far apart they arrived gives the change in queuing
+//! delay at the bottleneck. A trendline over recent changes tells whether
+//! that queue is growing (overuse), draining (underuse) or steady, and an
+//! AIMD controller moves the rate accordingly: multiplicative increase while
+//! far from the last known capacity, additive close to it, and a cut to 85%
+//! of the acknowledged rate on overuse.
+//!
+//! Loss alone is a poor congestion signal on Wi-Fi, so the loss-based limit
+//! only engages above 10% loss and is lifted again below 2%; anything in
+//! between holds it where it is.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use webrtc::interceptor::stream_info::StreamInfo;
+use webrtc::interceptor::{
+    Attributes, Interceptor, InterceptorBuilder, RTCPReader, RTCPWriter, RTPReader, RTPWriter,
+};
+use webrtc::rtcp::transport_feedbacks::transport_layer_cc::{PacketStatusChunk, SymbolTypeTcc, TransportLayerCc};
+use webrtc::sdp::extmap::TRANSPORT_CC_URI;
+use webrtc::util::MarshalSize;
+
+/// Packets sent within this span form one group
+const BURST_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Delay samples in the trendline regression
+const TRENDLINE_WINDOW: usize = 20;
+const TRENDLINE_SMOOTHING: f64 = 0.9;
+const TRENDLINE_THRESHOLD_GAIN: f64 = 4.0;
+
+/// Adaptive overuse threshold (ms), its bounds and adaptation rates
+const INITIAL_THRESHOLD: f64 = 12.5;
+const MIN_THRESHOLD: f64 = 6.0;
+const MAX_THRESHOLD: f64 = 600.0;
+const THRESHOLD_K_UP: f64 = 0.0087;
+const THRESHOLD_K_DOWN: f64 = 0.039;
+
+/// Time the trend must stay above the threshold before signalling overuse
+const OVERUSE_TIME_MS: f64 = 10.0;
+
+/// Fraction of the acknowledged rate to drop to on overuse
+const DECREASE_FACTOR: f64 = 0.85;
+
+/// Window the acknowledged bitrate is measured over
+const ACKED_WINDOW: Duration = Duration::from_millis(500);
+
+/// Loss fractions below which the loss limit lifts and above which it cuts
+const LOW_LOSS: f64 = 0.02;
+const HIGH_LOSS: f64 = 0.10;
+
+/// Packets needed before a loss fraction is acted on
+const MIN_LOSS_PACKETS: u32 = 20;
+
+/// Sent packets remembered while waiting for feedback
+const MAX_SEND_HISTORY: usize = 10_000;
+
+/// Typical RTP packet size, for the additive increase step
+const PACKET_SIZE_BITS: f64 = 1200.0 * 8.0;
+
+/// Receiver feedback for one packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketFeedback {
+    /// Transport-wide sequence number, unwrapped
+    pub sequence_number: i64,
+    /// Size on the wire in bytes
+    pub size: usize,
+    /// When the packet was sent, on the sender's clock
+    pub send_time: Duration,
+    /// When it arrived, on the receiver's clock; `None` if it was lost
+    pub arrival_time: Option<Duration>,
+}
+
+/// Source of bandwidth estimates for `AdaptiveBitrateController`
+pub trait BandwidthEstimator: Send {
+    /// Account for a batch of receiver feedback, in sequence order
+    fn on_packet_feedback(&mut self, feedback: &[PacketFeedback]);
+
+    /// Latest round trip time
+    fn on_round_trip_time(&mut self, _rtt: Duration) {}
+
+    /// Current estimate in kbps
+    fn estimate_kbps(&self) -> u32;
+}
+
+/// State of the bottleneck queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandwidthUsage {
+    Normal,
+    Overusing,
+    Underusing,
+}
+
+/// Overuse detector fitting a line through smoothed queuing delay
+pub struct TrendlineEstimator {
+    samples: VecDeque<(f64, f64)>,
+    first_arrival_ms: Option<f64>,
+    accumulated_delay: f64,
+    smoothed_delay: f64,
+    num_deltas: u32,
+    prev_trend: f64,
+    threshold: f64,
+    last_threshold_update_ms: Option<f64>,
+    time_over_using: Option<f64>,
+    overuse_counter: u32,
+    usage: BandwidthUsage,
+}
+
+impl TrendlineEstimator {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(TRENDLINE_WINDOW),
+            first_arrival_ms: None,
+            accumulated_delay: 0.0,
+            smoothed_delay: 0.0,
+            num_deltas: 0,
+            prev_trend: 0.0,
+            threshold: INITIAL_THRESHOLD,
+            last_threshold_update_ms: None,
+            time_over_using: None,
+            overuse_counter: 0,
+            usage: BandwidthUsage::Normal,
+        }
+    }
+
+    /// Account for one packet group that arrived `recv_delta_ms` after the
+    /// previous one but was sent `send_delta_ms` after it
+    pub fn update(&mut self, recv_delta_ms: f64, send_delta_ms: f64, arrival_ms: f64) -> BandwidthUsage {
+        self.num_deltas = (self.num_deltas + 1).min(1000);
+        let first_arrival_ms = *self.first_arrival_ms.get_or_insert(arrival_ms);
+
+        self.accumulated_delay += recv_delta_ms - send_delta_ms;
+        self.smoothed_delay =
+            TRENDLINE_SMOOTHING * self.smoothed_delay + (1.0 - TRENDLINE_SMOOTHING) * self.accumulated_delay;
+        self.samples.push_back((arrival_ms - first_arrival_ms, self.smoothed_delay));
+        if self.samples.len() > TRENDLINE_WINDOW {
+            self.samples.pop_front();
+        }
+
+        let trend = if self.samples.len() == TRENDLINE_WINDOW {
+            linear_fit_slope(&self.samples).unwrap_or(self.prev_trend)
+        } else {
+            self.prev_trend
+        };
+        self.detect(trend, send_delta_ms, arrival_ms);
+        self.usage
+    }
+
+    pub fn usage(&self) -> BandwidthUsage {
+        self.usage
+    }
+
+    fn detect(&mut self, trend: f64, send_delta_ms: f64, now_ms: f64) {
+        if self.num_deltas < 2 {
+            self.usage = BandwidthUsage::Normal;
+            return;
+        }
+
+        let modified_trend = self.num_deltas.min(60) as f64 * trend * TRENDLINE_THRESHOLD_GAIN;
+        if modified_trend > self.threshold {
+            let time_over_using = match self.time_over_using {
+                // Assume the overuse started halfway through this delta
+                None => send_delta_ms / 2.0,
+                Some(time) => time + send_delta_ms,
+            };
+            self.time_over_using = Some(time_over_using);
+            self.overuse_counter += 1;
+            if time_over_using > OVERUSE_TIME_MS && self.overuse_counter > 1 && trend >= self.prev_trend {
+                self.time_over_using = Some(0.0);
+                self.overuse_counter = 0;
+                self.usage = BandwidthUsage::Overusing;
+            }
+        } else if modified_trend < -self.threshold {
+            self.time_over_using = None;
+            self.overuse_counter = 0;
+            self.usage = BandwidthUsage::Underusing;
+        } else {
+            self.time_over_using = None;
+            self.overuse_counter = 0;
+            self.usage = BandwidthUsage::Normal;
+        }
+        self.prev_trend = trend;
+        self.update_threshold(modified_trend, now_ms);
+    }
+
+    /// Adapt the threshold so it tracks the trend's normal range; sudden
+    /// spikes are left to trigger detection instead
+    fn update_threshold(&mut self, modified_trend: f64, now_ms: f64) {
+        let last_update_ms = *self.last_threshold_update_ms.get_or_insert(now_ms);
+        if modified_trend.abs() > self.threshold + 15.0 {
+            self.last_threshold_update_ms = Some(now_ms);
+            return;
+        }
+
+        let k = if modified_trend.abs() < self.threshold { THRESHOLD_K_DOWN } else { THRESHOLD_K_UP };
+        let time_delta_ms = (now_ms - last_update_ms).clamp(0.0, 100.0);
+        self.threshold += k * (modified_trend.abs() - self.threshold) * time_delta_ms;
+        self.threshold = self.threshold.clamp(MIN_THRESHOLD, MAX_THRESHOLD);
+        self.last_threshold_update_ms = Some(now_ms);
+    }
+}
+
+impl Default for TrendlineEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Least-squares slope of `points`
+fn linear_fit_slope(points: &VecDeque<(f64, f64)>) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (numerator, denominator) = points.iter().fold((0.0, 0.0), |(num, den), (x, y)| {
+        (num + (x - mean_x) * (y - mean_y), den + (x - mean_x) * (x - mean_x))
+    });
+    (denominator != 0.0).then(|| numerator / denominator)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RateControlState {
+    Hold,
+    Increase,
+    Decrease,
+}
+
+/// Additive-increase, multiplicative-decrease control of the delay-based rate
+pub struct AimdRateControl {
+    rate_bps: f64,
+    min_bps: f64,
+    max_bps: f64,
+    state: RateControlState,
+    last_update: Option<Duration>,
+    /// Acknowledged rate at recent overuses, in kbps, with its variance
+    link_capacity_kbps: Option<f64>,
+    link_capacity_var: f64,
+    rtt: Duration,
+}
+
+impl AimdRateControl {
+    pub fn new(start_bps: f64, min_bps: f64, max_bps: f64) -> Self {
+        Self {
+            rate_bps: start_bps,
+            min_bps,
+            max_bps,
+            state: RateControlState::Hold,
+            last_update: None,
+            link_capacity_kbps: None,
+            link_capacity_var: 0.4,
+            rtt: Duration::from_millis(200),
+        }
+    }
+
+    pub fn rate_bps(&self) -> f64 {
+        self.rate_bps
+    }
+
+    pub fn set_rtt(&mut self, rtt: Duration) {
+        self.rtt = rtt;
+    }
+
+    /// Move the rate for `usage` at `now`, given the rate the receiver acknowledged
+    pub fn update(&mut self, usage: BandwidthUsage, acked_bps: Option<f64>, now: Duration) -> f64 {
+        let elapsed = self
+            .last_update
+            .map_or(Duration::ZERO, |last| now.saturating_sub(last))
+            .min(Duration::from_secs(1));
+        self.last_update = Some(now);
+
+        self.state = match (usage, self.state) {
+            (BandwidthUsage::Overusing, _) => RateControlState::Decrease,
+            (BandwidthUsage::Underusing, _) => RateControlState::Hold,
+            (BandwidthUsage::Normal, RateControlState::Hold) => RateControlState::Increase,
+            (BandwidthUsage::Normal, state) => state,
+        };
+
+        match self.state {
+            RateControlState::Hold => {}
+            RateControlState::Increase => {
+                // Well above the old capacity: the link changed, search again
+                if let (Some(acked), Some(capacity)) = (acked_bps, self.link_capacity_kbps) {
+                    if acked / 1000.0 > capacity + 3.0 * self.capacity_std_dev(capacity) {
+                        self.link_capacity_kbps = None;
+                    }
+                }
+                let increase = if self.link_capacity_kbps.is_some() {
+                    self.additive_increase(elapsed)
+                } else {
+                    self.multiplicative_increase(elapsed)
+                };
+                // Don't run far ahead of what actually gets through
+                let limit = acked_bps.map_or(f64::INFINITY, |acked| 1.5 * acked + 10_000.0);
+                if self.rate_bps < limit {
+                    self.rate_bps = (self.rate_bps + increase).min(limit);
+                }
+            }
+            RateControlState::Decrease => {
+                let acked = acked_bps.unwrap_or(self.rate_bps);
+                self.rate_bps = (DECREASE_FACTOR * acked).min(self.rate_bps);
+                self.update_link_capacity(acked / 1000.0);
+                // One cut per overuse; wait for the queue to drain
+                self.state = RateControlState::Hold;
+            }
+        }
+
+        self.rate_bps = self.rate_bps.clamp(self.min_bps, self.max_bps);
+        self.rate_bps
+    }
+
+    fn multiplicative_increase(&self, elapsed: Duration) -> f64 {
+        let alpha = 1.08f64.powf(elapsed.as_secs_f64());
+        (self.rate_bps * (alpha - 1.0)).max(1000.0 * elapsed.as_secs_f64())
+    }
+
+    /// About one packet per response time
+    fn additive_increase(&self, elapsed: Duration) -> f64 {
+        let response_time = (self.rtt + Duration::from_millis(100)).as_secs_f64();
+        let bits_per_frame = self.rate_bps / 30.0;
+        let packets_per_frame = (bits_per_frame / PACKET_SIZE_BITS).ceil().max(1.0);
+        let packet_bits = bits_per_frame / packets_per_frame;
+        (packet_bits / response_time).max(4000.0) * elapsed.as_secs_f64()
+    }
+
+    fn update_link_capacity(&mut self, acked_kbps: f64) {
+        let capacity = match self.link_capacity_kbps {
+            None => acked_kbps,
+            Some(capacity) => 0.95 * capacity + 0.05 * acked_kbps,
+        };
+        let error = capacity - acked_kbps;
+        let normalized = capacity.max(1.0);
+        self.link_capacity_var = (0.95 * self.link_capacity_var + 0.05 * error * error / normalized).clamp(0.4, 2.5);
+        self.link_capacity_kbps = Some(capacity);
+    }
+
+    fn capacity_std_dev(&self, capacity_kbps: f64) -> f64 {
+        (self.link_capacity_var * capacity_kbps).sqrt()
+    }
+}
+
+/// Rate of the bytes the receiver got over the last `ACKED_WINDOW`
+struct AcknowledgedBitrate {
+    arrivals: VecDeque<(Duration, usize)>,
+    first_arrival: Option<Duration>,
+}
+
+impl AcknowledgedBitrate {
+    fn new() -> Self {
+        Self {
+            arrivals: VecDeque::new(),
+            first_arrival: None,
+        }
+    }
+
+    fn on_arrival(&mut self, arrival: Duration, size: usize) {
+        self.first_arrival.get_or_insert(arrival);
+        self.arrivals.push_back((arrival, size));
+    }
+
+    /// Bitrate up to `now`, once a full window has been seen
+    fn bitrate_bps(&mut self, now: Duration) -> Option<f64> {
+        while self.arrivals.front().is_some_and(|(arrival, _)| *arrival + ACKED_WINDOW <= now) {
+            self.arrivals.pop_front();
+        }
+        if now.saturating_sub(self.first_arrival?) < ACKED_WINDOW {
+            return None;
+        }
+        let bytes: usize = self.arrivals.iter().map(|(_, size)| size).sum();
+        Some(bytes as f64 * 8.0 / ACKED_WINDOW.as_secs_f64())
+    }
+}
+
+/// Packets sent within `BURST_INTERVAL` of each other
+#[derive(Debug, Clone, Copy)]
+struct PacketGroup {
+    first_send: Duration,
+    last_send: Duration,
+    last_arrival: Duration,
+}
+
+/// Delay- and loss-based send-side bandwidth estimator
+pub struct GoogCcEstimator {
+    trendline: TrendlineEstimator,
+    rate_control: AimdRateControl,
+    acked: AcknowledgedBitrate,
+    current_group: Option<PacketGroup>,
+    previous_group: Option<PacketGroup>,
+    /// Receiver clock, the latest arrival seen
+    now: Duration,
+    loss_limit_bps: Option<f64>,
+    lost: u32,
+    reported: u32,
+    min_bps: f64,
+    max_bps: f64,
+}
+
+impl GoogCcEstimator {
+    /// Start at `start_kbps` and stay within `min_kbps..=max_kbps`
+    pub fn new(start_kbps: u32, min_kbps: u32, max_kbps: u32) -> Self {
+        let (start, min, max) = (start_kbps as f64 * 1000.0, min_kbps as f64 * 1000.0, max_kbps as f64 * 1000.0);
+        Self {
+            trendline: TrendlineEstimator::new(),
+            rate_control: AimdRateControl::new(start, min, max),
+            acked: AcknowledgedBitrate::new(),
+            current_group: None,
+            previous_group: None,
+            now: Duration::ZERO,
+            loss_limit_bps: None,
+            lost: 0,
+            reported: 0,
+            min_bps: min,
+            max_bps: max,
+        }
+    }
+
+    /// Latest verdict on the bottleneck queue
+    pub fn usage(&self) -> BandwidthUsage {
+        self.trendline.usage()
+    }
+
+    /// Rate the receiver acknowledged over the last half second, in kbps
+    pub fn acknowledged_kbps(&mut self) -> Option<u32> {
+        let now = self.now;
+        self.acked.bitrate_bps(now).map(|bps| (bps / 1000.0) as u32)
+    }
+
+    fn on_received(&mut self, send_time: Duration, arrival: Duration) {
+        match self.current_group.as_mut() {
+            None => {}
+            // Reordered behind the current group, too late to use
+            Some(group) if send_time < group.first_send => return,
+            Some(group) if send_time - group.first_send <= BURST_INTERVAL => {
+                group.last_send = group.last_send.max(send_time);
+                group.last_arrival = group.last_arrival.max(arrival);
+                return;
+            }
+            Some(group) => {
+                let group = *group;
+                if let Some(previous) = self.previous_group {
+                    let send_delta_ms = (group.last_send.as_secs_f64() - previous.last_send.as_secs_f64()) * 1000.0;
+                    let recv_delta_ms =
+                        (group.last_arrival.as_secs_f64() - previous.last_arrival.as_secs_f64()) * 1000.0;
+                    self.trendline
+                        .update(recv_delta_ms, send_delta_ms, group.last_arrival.as_secs_f64() * 1000.0);
+                }
+                self.previous_group = Some(group);
+            }
+        }
+        self.current_group = Some(PacketGroup {
+            first_send: send_time,
+            last_send: send_time,
+            last_arrival: arrival,
+        });
+    }
+
+    /// Cut the limit on heavy loss, lift it when loss is low, hold otherwise
+    fn update_loss_limit(&mut self, delay_based_bps: f64) {
+        if self.reported < MIN_LOSS_PACKETS {
+            return;
+        }
+        let loss = self.lost as f64 / self.reported as f64;
+        self.lost = 0;
+        self.reported = 0;
+
+        if loss > HIGH_LOSS {
+            let current = self.loss_limit_bps.unwrap_or(delay_based_bps).min(delay_based_bps);
+            self.loss_limit_bps = Some(current * (1.0 - loss / 2.0));
+        } else if loss < LOW_LOSS {
+            if let Some(limit) = self.loss_limit_bps {
+                let lifted = limit * 1.08 + 1000.0;
+                self.loss_limit_bps = (lifted < delay_based_bps).then_some(lifted);
+            }
+        }
+    }
+}
+
+impl BandwidthEstimator for GoogCcEstimator {
+    fn on_packet_feedback(&mut self, feedback: &[PacketFeedback]) {
+        for packet in feedback {
+            self.reported += 1;
+            match packet.arrival_time {
+                Some(arrival) => {
+                    self.now = self.now.max(arrival);
+                    self.acked.on_arrival(arrival, packet.size);
+                    self.on_received(packet.send_time, arrival);
+                }
+                None => self.lost += 1,
+            }
+        }
+
+        let acked = self.acked.bitrate_bps(self.now);
+        let delay_based = self.rate_control.update(self.trendline.usage(), acked, self.now);
+        self.update_loss_limit(delay_based);
+    }
+
+    fn on_round_trip_time(&mut self, rtt: Duration) {
+        self.rate_control.set_rtt(rtt);
+    }
+
+    fn estimate_kbps(&self) -> u32 {
+        let delay_based = self.rate_control.rate_bps();
+        let estimate = self.loss_limit_bps.map_or(delay_based, |limit| limit.min(delay_based));
+        (estimate.clamp(self.min_bps, self.max_bps) / 1000.0) as u32
+    }
+}
+
+/// Packets sent with a transport-wide sequence number, waiting for feedback
+pub struct SendHistory {
+    started: Instant,
+    packets: BTreeMap<i64, (Duration, usize)>,
+    last_sequence_number: Option<i64>,
+}
+
+impl SendHistory {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            packets: BTreeMap::new(),
+            last_sequence_number: None,
+        }
+    }
+
+    /// Remember that the packet stamped `sequence_number` left at `now`
+    pub fn on_packet_sent(&mut self, sequence_number: u16, size: usize, now: Instant) {
+        let sequence_number = unwrap_sequence_number(self.last_sequence_number, sequence_number);
+        self.last_sequence_number = Some(sequence_number);
+        self.packets
+            .insert(sequence_number, (now.saturating_duration_since(self.started), size));
+        while self.packets.len() > MAX_SEND_HISTORY {
+            self.packets.pop_first();
+        }
+    }
+
+    /// Match transport-cc feedback to the packets it reports on
+    ///
+    /// Each packet is reported once; repeats in later feedback are ignored.
+    pub fn on_transport_feedback(&mut self, feedback: &TransportLayerCc) -> Vec<PacketFeedback> {
+        let base = unwrap_sequence_number(self.last_sequence_number, feedback.base_sequence_number);
+        // Reference time is in multiples of 64 ms, deltas in microseconds
+        let mut arrival_us = feedback.reference_time as i64 * 64_000;
+        let mut deltas = feedback.recv_deltas.iter();
+
+        let symbols = feedback.packet_chunks.iter().flat_map(|chunk| match chunk {
+            PacketStatusChunk::RunLengthChunk(run) => vec![run.packet_status_symbol; run.run_length as usize],
+            PacketStatusChunk::StatusVectorChunk(vector) => vector.symbol_list.clone(),
+        });
+
+        let mut packets = Vec::new();
+        for (offset, symbol) in symbols.take(feedback.packet_status_count as usize).enumerate() {
+            let arrival_time = match symbol {
+                SymbolTypeTcc::PacketReceivedSmallDelta | SymbolTypeTcc::PacketReceivedLargeDelta => {
+                    arrival_us += deltas.next().map_or(0, |delta| delta.delta);
+                    Some(Duration::from_micros(arrival_us.max(0) as u64))
+                }
+                SymbolTypeTcc::PacketReceivedWithoutDelta => Some(Duration::from_micros(arrival_us.max(0) as u64)),
+                SymbolTypeTcc::PacketNotReceived => None,
+            };
+            let sequence_number = base + offset as i64;
+            if let Some((send_time, size)) = self.packets.remove(&sequence_number) {
+                packets.push(PacketFeedback {
+                    sequence_number,
+                    size,
+                    send_time,
+                    arrival_time,
+                });
+            }
+        }
+        packets
+    }
+}
+
+impl Default for SendHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Extend a 16-bit sequence number to 64 bits, next to the last one seen
+fn unwrap_sequence_number(last: Option<i64>, sequence_number: u16) -> i64 {
+    match last {
+        None => sequence_number as i64,
+        Some(last) => last + sequence_number.wrapping_sub(last as u16) as i16 as i64,
+    }
+}
+
+/// Interceptor recording the send time of every packet carrying a
+/// transport-wide sequence number
+///
+/// Register it before the transport-cc sender interceptor so it sees the
+/// sequence numbers that one stamps on.
+pub struct SendTimeRecorderBuilder {
+    history: Arc<Mutex<SendHistory>>,
+}
+
+impl SendTimeRecorderBuilder {
+    pub fn new(history: Arc<Mutex<SendHistory>>) -> Self {
+        Self { history }
+    }
+}
+
+impl InterceptorBuilder for SendTimeRecorderBuilder {
+    fn build(&self, _id: &str) -> Result<Arc<dyn Interceptor + Send + Sync>, webrtc::interceptor::Error> {
+        Ok(Arc::new(SendTimeRecorder {
+            history: Arc::clone(&self.history),
+        }))
+    }
+}
+
+struct SendTimeRecorder {
+    history: Arc<Mutex<SendHistory>>,
+}
+
+#[async_trait]
+impl Interceptor for SendTimeRecorder {
+    async fn bind_rtcp_reader(&self, reader: Arc<dyn RTCPReader + Send + Sync>) -> Arc<dyn RTCPReader + Send + Sync> {
+        reader
+    }
+
+    async fn bind_rtcp_writer(&self, writer: Arc<dyn RTCPWriter + Send + Sync>) -> Arc<dyn RTCPWriter + Send + Sync> {
+        writer
+    }
+
+    async fn bind_local_stream(
+        &self,
+        info: &StreamInfo,
+        writer: Arc<dyn RTPWriter + Send + Sync>,
+    ) -> Arc<dyn RTPWriter + Send + Sync> {
+        let extension_id = info
+            .rtp_header_extensions
+            .iter()
+            .find(|extension| extension.uri == TRANSPORT_CC_URI)
+            .map(|extension| extension.id as u8);
+        match extension_id {
+            Some(extension_id) => Arc::new(SendTimeWriter {
+                next: writer,
+                extension_id,
+                history: Arc::clone(&self.history),
+            }),
+            // Transport-cc wasn't negotiated for this stream
+            None => writer,
+        }
+    }
+
+    async fn unbind_local_stream(&self, _info: &StreamInfo) {}
+
+    async fn bind_remote_stream(
+        &self,
+        _info: &StreamInfo,
+        reader: Arc<dyn RTPReader + Send + Sync>,
+    ) -> Arc<dyn RTPReader + Send + Sync> {
+        reader
+    }
+
+    async fn unbind_remote_stream(&self, _info: &StreamInfo) {}
+
+    async fn close(&self) -> Result<(), webrtc::interceptor::Error> {
+        Ok(())
+    }
+}
+
+struct SendTimeWriter {
+    next: Arc<dyn RTPWriter + Send + Sync>,
+    extension_id: u8,
+    history: Arc<Mutex<SendHistory>>,
+}
+
+#[async_trait]
+impl RTPWriter for SendTimeWriter {
+    async fn write(
+        &self,
+        packet: &webrtc::rtp::packet::Packet,
+        attributes: &Attributes,
+    ) -> Result<usize, webrtc::interceptor::Error> {
+        if let Some(extension) = packet.header.get_extension(self.extension_id) {
+            if extension.len() >= 2 {
+                let sequence_number = u16::from_be_bytes([extension[0], extension[1]]);
+                let size = packet.header.marshal_size() + packet.payload.len();
+                self.history.lock().on_packet_sent(sequence_number, size, Instant::now());
+            }
+        }
+        self.next.write(packet, attributes).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webrtc::rtcp::transport_feedbacks::transport_layer_cc::{RecvDelta, RunLengthChunk, StatusChunkTypeTcc};
+
+    #[test]
+    fn test_unwrap_sequence_number() {
+        assert_eq!(unwrap_sequence_number(None, 65535), 65535);
+        assert_eq!(unwrap_sequence_number(Some(65535), 0), 65536);
+        assert_eq!(unwrap_sequence_number(Some(65536), 65534), 65534);
+    }
+
+    #[test]
+    fn test_trendline_detects_growing_queue() {
+        let mut trendline = TrendlineEstimator::new();
+        // Groups sent 10 ms apart arriving 10 ms apart: steady
+        for i in 1..=40 {
+            trendline.update(10.0, 10.0, i as f64 * 10.0);
+        }
+        assert_eq!(trendline.usage(), BandwidthUsage::Normal);
+
+        // Now arriving 13 ms apart: the queue grows
+        let mut arrival = 400.0;
+        let overuse = (0..40).any(|_| {
+            arrival += 13.0;
+            trendline.update(13.0, 10.0, arrival) == BandwidthUsage::Overusing
+        });
+        assert!(overuse);
+    }
+
+    #[test]
+    fn test_decrease_to_acknowledged_rate() {
+        let mut control = AimdRateControl::new(2_000_000.0, 100_000.0, 10_000_000.0);
+        let rate = control.update(BandwidthUsage::Overusing, Some(1_000_000.0), Duration::from_secs(1));
+        assert_eq!(rate, 850_000.0);
+
+        // Holds while the queue drains, then increases additively
+        assert_eq!(control.update(BandwidthUsage::Underusing, Some(850_000.0), Duration::from_millis(1100)), rate);
+        let increased = control.update(BandwidthUsage::Normal, Some(850_000.0), Duration::from_millis(1200));
+        assert!(increased > rate && increased < rate * 1.01);
+    }
+
+    #[test]
+    fn test_transport_feedback_matches_history() {
+        let mut history = SendHistory::new();
+        let start = history.started;
+        for (i, sequence_number) in [65534u16, 65535, 0, 1].into_iter().enumerate() {
+            history.on_packet_sent(sequence_number, 1000, start + Duration::from_millis(i as u64 * 10));
+        }
+
+        // 65534 and 65535 received, 0 lost, 1 received
+        let feedback = TransportLayerCc {
+            base_sequence_number: 65534,
+            packet_status_count: 4,
+            reference_time: 1,
+            packet_chunks: vec![
+                PacketStatusChunk::RunLengthChunk(RunLengthChunk {
+                    type_tcc: StatusChunkTypeTcc::RunLengthChunk,
+                    packet_status_symbol: SymbolTypeTcc::PacketReceivedSmallDelta,
+                    run_length: 2,
+                }),
+                PacketStatusChunk::RunLengthChunk(RunLengthChunk {
+                    type_tcc: StatusChunkTypeTcc::RunLengthChunk,
+                    packet_status_symbol: SymbolTypeTcc::PacketNotReceived,
+                    run_length: 1,
+                }),
+                PacketStatusChunk::RunLengthChunk(RunLengthChunk {
+                    type_tcc: StatusChunkTypeTcc::RunLengthChunk,
+                    packet_status_symbol: SymbolTypeTcc::PacketReceivedSmallDelta,
+                    run_length: 1,
+                }),
+            ],
+            recv_deltas: [1000, 10_000, 25_000]
+                .into_iter()
+                .map(|delta| RecvDelta {
+                    type_tcc_packet: SymbolTypeTcc::PacketReceivedSmallDelta,
+                    delta,
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let packets = history.on_transport_feedback(&feedback);
+        let arrivals: Vec<_> = packets.iter().map(|packet| packet.arrival_time.map(|t| t.as_micros())).collect();
+        assert_eq!(arrivals, vec![Some(65_000), Some(75_000), None, Some(100_000)]);
+        assert_eq!(packets[2].sequence_number, 65536);
+        assert_eq!(packets[3].send_time, Duration::from_millis(30));
+
+        // Already reported
+        assert!(history.on_transport_feedback(&feedback).is_empty());
+    }
+}
@@ -4,29 +4,36 @@
 //! This is the main entry point for connecting to remote PCs.
 
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::collections::HashMap;
 use tokio::sync::{RwLock, mpsc, Mutex};
 use anyhow::{Result, anyhow, Context};
 use serde::{Serialize, Deserialize};
 use tracing::{info, error, warn, debug};
 
+use crate::auth_service::MfaPolicy;
 use crate::connection_id::{ConnectionId, get_connection_id};
-use genxlink_protocol::DeviceId;
+use crate::permission_profiles::Permission;
+use crate::session_resume::{ResumptionRegistry, SessionSnapshot};
+use crate::signaling_client::SignalingClient;
+use crate::system_actions::ReconnectStore;
+use genxlink_protocol::{DeviceId, ReconnectTicket, SessionId, SignalingEnvelope, SignalingMessage};
+
+pub use genxlink_protocol::IceServer;
 
 /// Signaling server URL (Railway deployment)
 const SIGNALING_SERVER_URL: &str = "wss://genxlink-signaling.up.railway.app";
 
-/// STUN/TURN servers for NAT traversal
+/// Public STUN servers used until the API has issued servers for a session
+///
+/// TURN relays are never built in: their credentials are issued per session
+/// by the API (see `ice_provisioning`).
 const STUN_SERVERS: &[&str] = &[
     "stun:stun.l.google.com:19302",
     "stun:stun1.l.google.com:19302",
     "stun:stun2.l.google.com:19302",
 ];
 
-const TURN_SERVER: &str = "turn:a.]relay.metered.ca:80";
-const TURN_USERNAME: &str = "83eebabf8b4cce9d5dbcb649";
-const TURN_CREDENTIAL: &str = "2D7JvfkOQtBdYW3R";
-
 /// Connection state
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ConnectionState {
@@ -57,6 +64,14 @@ pub enum ConnectionEvent {
     PeerDisconnected(String),
     ScreenFrameReceived(Vec<u8>),
     InputReceived(InputEvent),
+    /// Peer is restarting and will be reconnected when it comes back
+    PeerRestarting(String),
+    /// Controller asks to connect (host side); answer with
+    /// `accept_connection` or `reject_connection`
+    ConnectionRequested(String),
+    /// Controller came back after losing the network; restore the monitor
+    /// selection and pending transfers from the snapshot
+    SessionResumed { peer: String, snapshot: SessionSnapshot },
     Error(String),
 }
 
@@ -76,31 +91,6 @@ pub enum MouseButton {
     Middle,
 }
 
-/// Signaling message for WebRTC negotiation
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum SignalingMessage {
-    /// Register with the signaling server
-    Register { connection_id: String, device_name: String },
-    /// Request to connect to a peer
-    ConnectRequest { from_id: String, to_id: String },
-    /// Accept connection request
-    ConnectAccept { from_id: String, to_id: String },
-    /// Reject connection request
-    ConnectReject { from_id: String, to_id: String, reason: String },
-    /// WebRTC offer
-    Offer { from_id: String, to_id: String, sdp: String },
-    /// WebRTC answer
-    Answer { from_id: String, to_id: String, sdp: String },
-    /// ICE candidate
-    IceCandidate { from_id: String, to_id: String, candidate: String },
-    /// Peer online notification
-    PeerOnline { connection_id: String, device_name: String },
-    /// Peer offline notification
-    PeerOffline { connection_id: String },
-    /// Error message
-    Error { message: String },
-}
-
 /// Connection Manager
 /// 
 /// Handles all remote connections using Connection IDs
@@ -114,11 +104,27 @@ pub struct ConnectionManager {
     /// Event sender
     event_tx: mpsc::UnboundedSender<ConnectionEvent>,
     /// Signaling WebSocket sender
-    signaling_tx: Arc<Mutex<Option<mpsc::UnboundedSender<SignalingMessage>>>>,
+    signaling_tx: Arc<Mutex<Option<mpsc::UnboundedSender<SignalingEnvelope>>>>,
     /// Screen capture enabled
     screen_capture_enabled: Arc<RwLock<bool>>,
     /// Remote control enabled
     remote_control_enabled: Arc<RwLock<bool>>,
+    /// Restarting peers to reconnect to, by connection ID
+    pending_reconnects: Arc<RwLock<HashMap<String, ReconnectTicket>>>,
+    /// Reconnect ticket store (host side)
+    reconnect_store: Option<ReconnectStore>,
+    /// Connection requests waiting for the user's decision, by controller (host side)
+    pending_requests: Arc<RwLock<HashMap<String, SignalingEnvelope>>>,
+    /// Admitted sessions by controller (host side)
+    host_sessions: Arc<RwLock<HashMap<String, SessionId>>>,
+    /// Sessions controllers can resume after losing the network (host side)
+    resumption: Arc<Mutex<ResumptionRegistry>>,
+    /// Signaling server to register with
+    signaling_url: String,
+    /// Id for the next signaling request
+    next_id: AtomicU64,
+    /// Checked before every outbound session, including resumes
+    mfa_policy: MfaPolicy,
 }
 
 impl ConnectionManager {
@@ -137,6 +143,14 @@ impl ConnectionManager {
             signaling_tx: Arc::new(Mutex::new(None)),
             screen_capture_enabled: Arc::new(RwLock::new(true)),
             remote_control_enabled: Arc::new(RwLock::new(true)),
+            pending_reconnects: Arc::new(RwLock::new(HashMap::new())),
+            reconnect_store: ReconnectStore::default_location().ok(),
+            pending_requests: Arc::new(RwLock::new(HashMap::new())),
+            host_sessions: Arc::new(RwLock::new(HashMap::new())),
+            resumption: Arc::new(Mutex::new(ResumptionRegistry::new())),
+            signaling_url: SIGNALING_SERVER_URL.to_string(),
+            next_id: AtomicU64::new(1),
+            mfa_policy: MfaPolicy::default(),
         };
         
         (manager, event_rx)
@@ -147,36 +161,69 @@ impl ConnectionManager {
         &self.my_connection_id.display_id
     }
     
+    /// My connection ID as the device ID peers address me by
+    fn my_device_id(&self) -> DeviceId {
+        DeviceId::from_string(self.my_connection_id.display_id.clone())
+    }
+    
     /// Get current state
     pub async fn state(&self) -> ConnectionState {
         self.state.read().await.clone()
     }
     
+    /// Use a different signaling server
+    pub fn set_signaling_url(&mut self, url: impl Into<String>) {
+        self.signaling_url = url.into();
+    }
+    
+    /// Require MFA for outbound sessions as `policy` says, usually
+    /// `SessionManager::mfa_policy`
+    pub fn set_mfa_policy(&mut self, policy: MfaPolicy) {
+        self.mfa_policy = policy;
+    }
+    
     /// Connect to the signaling server
-    pub async fn connect_to_signaling(&self) -> Result<()> {
-        info!("Connecting to signaling server: {}", SIGNALING_SERVER_URL);
+    ///
+    /// Registers this device's connection ID and handles signaling messages
+    /// until the manager is dropped. After a restart with a pending reconnect
+    /// ticket, the controller that asked for the restart is admitted again.
+    pub async fn connect_to_signaling(self: &Arc<Self>) -> Result<()> {
+        info!("Connecting to signaling server: {}", self.signaling_url);
         
         self.set_state(ConnectionState::Connecting).await;
         
-        // In a real implementation, this would establish a WebSocket connection
-        // For now, we'll simulate the connection
-        
-        // Create signaling channel
-        let (sig_tx, mut sig_rx) = mpsc::unbounded_channel::<SignalingMessage>();
-        
-        {
-            let mut tx_guard = self.signaling_tx.lock().await;
-            *tx_guard = Some(sig_tx.clone());
-        }
-        
-        // Register with the signaling server
-        let register_msg = SignalingMessage::Register {
-            connection_id: self.my_connection_id.display_id.clone(),
-            device_name: self.my_connection_id.device_name.clone(),
+        let mut client = SignalingClient::new(self.my_device_id(), self.signaling_url.clone());
+        let mut incoming = match client.connect().await {
+            Ok(incoming) => incoming,
+            Err(e) => {
+                self.set_state(ConnectionState::Failed(e.to_string())).await;
+                return Err(anyhow!("Failed to connect to signaling server: {}", e));
+            }
         };
         
-        sig_tx.send(register_msg).ok();
+        // Outgoing messages go through the client, which registers and reconnects
+        let (sig_tx, mut sig_rx) = mpsc::unbounded_channel::<SignalingEnvelope>();
+        *self.signaling_tx.lock().await = Some(sig_tx);
+        tokio::spawn(async move {
+            while let Some(envelope) = sig_rx.recv().await {
+                if let Err(e) = client.send_envelope(envelope) {
+                    warn!("Failed to send signaling message: {}", e);
+                }
+            }
+            client.close().await;
+        });
         
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while let Some(envelope) = incoming.recv().await {
+                let Some(manager) = manager.upgrade() else { break };
+                if let Err(e) = manager.handle_signaling_message(envelope).await {
+                    warn!("Failed to handle signaling message: {}", e);
+                }
+            }
+        });
+        
+        self.check_pending_reconnect();
         self.set_state(ConnectionState::WaitingForPeer).await;
         
         info!("Connected to signaling server, waiting for peers...");
@@ -184,6 +231,19 @@ impl ConnectionManager {
         Ok(())
     }
     
+    /// Log the controller expected back after a restart, dropping tickets for other IDs
+    fn check_pending_reconnect(&self) {
+        let Some(store) = &self.reconnect_store else { return };
+        let Some(pending) = store.load() else { return };
+        
+        if pending.ticket.connection_id != self.my_connection_id.display_id {
+            warn!("Discarding reconnect ticket issued for {}", pending.ticket.connection_id);
+            store.clear();
+            return;
+        }
+        info!("Resuming after restart, expecting {}", pending.controller_id);
+    }
+    
     /// Connect to a remote peer using their Connection ID
     pub async fn connect_to_peer(&self, remote_connection_id: &str) -> Result<()> {
         // Validate the connection ID format
@@ -203,17 +263,24 @@ impl ConnectionManager {
             &cleaned_id[6..9]
         );
         
+        if let Err(e) = self.mfa_policy.check_remote_control() {
+            warn!("Refusing connection to {}: {}", formatted_id, e);
+            let _ = self.event_tx.send(ConnectionEvent::Error(e.to_string()));
+            return Err(e.into());
+        }
+        
         info!("Connecting to peer: {}", formatted_id);
         
         self.set_state(ConnectionState::Connecting).await;
         
         // Send connect request through signaling
         if let Some(tx) = self.signaling_tx.lock().await.as_ref() {
-            let connect_msg = SignalingMessage::ConnectRequest {
-                from_id: self.my_connection_id.display_id.clone(),
-                to_id: formatted_id.clone(),
+            let connect_msg = SignalingMessage::ConnectionRequest {
+                target: DeviceId::from_string(formatted_id.clone()),
+                from: self.my_device_id(),
+                resume_token: None,
             };
-            tx.send(connect_msg).ok();
+            tx.send(connect_msg.into()).ok();
         }
         
         // Add peer to tracking
@@ -261,6 +328,9 @@ impl ConnectionManager {
     pub async fn disconnect_from_peer(&self, connection_id: &str) -> Result<()> {
         info!("Disconnecting from peer: {}", connection_id);
         
+        if let Some(session_id) = self.host_sessions.write().await.remove(connection_id) {
+            self.resumption.lock().await.revoke(&session_id);
+        }
         if let Some(peer) = self.peers.write().await.remove(connection_id) {
             let _ = self.event_tx.send(ConnectionEvent::PeerDisconnected(peer.connection_id));
         }
@@ -371,6 +441,269 @@ impl ConnectionManager {
         Ok(())
     }
     
+    /// Accept resume requests using tickets from this store (host side)
+    ///
+    /// Defaults to the store in the GenXLink config directory, which is where
+    /// `SystemActionManager` saves tickets before a restart.
+    pub fn set_reconnect_store(&mut self, store: ReconnectStore) {
+        self.reconnect_store = Some(store);
+    }
+    
+    /// Admit a controller that asked to connect (host side)
+    ///
+    /// `snapshot` is the state the session starts with; the controller gets a
+    /// resumption token so it can come back without a new prompt if its
+    /// network drops.
+    pub async fn accept_connection(&self, controller_id: &str, snapshot: SessionSnapshot) -> Result<SessionId> {
+        let request = self.pending_requests.write().await.remove(controller_id)
+            .ok_or_else(|| anyhow!("No pending connection request from {}", controller_id))?;
+        
+        let session_id = SessionId::new();
+        let controller = DeviceId::from_string(controller_id.to_string());
+        self.apply_permissions(&snapshot).await;
+        let mut resumption = self.resumption.lock().await;
+        let token = resumption.issue(session_id, controller, snapshot);
+        if let Some(previous) = self.host_sessions.write().await.insert(controller_id.to_string(), session_id) {
+            resumption.revoke(&previous);
+        }
+        drop(resumption);
+        
+        info!("Accepted connection from {}", controller_id);
+        self.admit_peer(controller_id).await;
+        self.reply(&request, SignalingMessage::ConnectionAccepted {
+            session_id,
+            from: self.my_device_id(),
+            resume_token: Some(token),
+        }).await;
+        Ok(session_id)
+    }
+    
+    /// Turn down a controller that asked to connect (host side)
+    pub async fn reject_connection(&self, controller_id: &str, reason: &str) -> Result<()> {
+        let request = self.pending_requests.write().await.remove(controller_id)
+            .ok_or_else(|| anyhow!("No pending connection request from {}", controller_id))?;
+        
+        info!("Rejected connection from {}: {}", controller_id, reason);
+        self.reply(&request, SignalingMessage::ConnectionRejected {
+            reason: reason.to_string(),
+            from: self.my_device_id(),
+        }).await;
+        Ok(())
+    }
+    
+    /// Record the current state of a controller's session so a resume
+    /// restores it (host side)
+    pub async fn update_session(&self, controller_id: &str, snapshot: SessionSnapshot) -> Result<()> {
+        let session_id = self.host_sessions.read().await.get(controller_id).copied()
+            .ok_or_else(|| anyhow!("No session with {}", controller_id))?;
+        self.apply_permissions(&snapshot).await;
+        self.resumption.lock().await.update(&session_id, snapshot);
+        Ok(())
+    }
+    
+    /// Reconnect to a restarting peer once it is back online (controller side)
+    pub async fn expect_reconnect(&self, ticket: ReconnectTicket) {
+        let peer_id = ticket.connection_id.clone();
+        info!("Peer {} is restarting, will reconnect until {}", peer_id, ticket.expires_at);
+        
+        self.peers.write().await.remove(&peer_id);
+        self.pending_reconnects.write().await.insert(peer_id.clone(), ticket);
+        let _ = self.event_tx.send(ConnectionEvent::PeerRestarting(peer_id));
+    }
+    
+    /// Handle a message received from the signaling server
+    async fn handle_signaling_message(&self, envelope: SignalingEnvelope) -> Result<()> {
+        match &envelope.message {
+            SignalingMessage::PeerJoined { peer } => {
+                let connection_id = peer.device_id.0.clone();
+                let ticket = self.pending_reconnects.write().await.remove(&connection_id);
+                let Some(ticket) = ticket else { return Ok(()) };
+                
+                if ticket.expires_at <= chrono::Utc::now() {
+                    warn!("Reconnect ticket for {} expired", connection_id);
+                    return Ok(());
+                }
+                if let Err(e) = self.mfa_policy.check_remote_control() {
+                    warn!("Not resuming session with {}: {}", connection_id, e);
+                    let _ = self.event_tx.send(ConnectionEvent::Error(e.to_string()));
+                    return Ok(());
+                }
+                
+                info!("Peer {} is back online, resuming session", connection_id);
+                self.peers.write().await.insert(connection_id.clone(), RemotePeer {
+                    connection_id,
+                    device_name: peer.device_name.clone(),
+                    state: ConnectionState::Connecting,
+                    connected_at: None,
+                    latency_ms: None,
+                });
+                self.set_state(ConnectionState::Connecting).await;
+                
+                // The host admits the resume request without prompting, and
+                // its decision comes back as the reply to this request
+                if let Some(tx) = self.signaling_tx.lock().await.as_ref() {
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    tx.send(SignalingEnvelope::request(id, SignalingMessage::ConnectionRequest {
+                        target: peer.device_id.clone(),
+                        from: self.my_device_id(),
+                        resume_token: Some(ticket.token),
+                    })).ok();
+                }
+                Ok(())
+            }
+            SignalingMessage::ConnectionAccepted { from, .. } => {
+                let mut peers = self.peers.write().await;
+                let Some(peer) = peers.get_mut(&from.0) else { return Ok(()) };
+                if peer.state != ConnectionState::Connecting {
+                    return Ok(());
+                }
+                
+                info!("Session with {} resumed", from);
+                peer.state = ConnectionState::Connected;
+                peer.connected_at = Some(chrono::Utc::now());
+                let _ = self.event_tx.send(ConnectionEvent::PeerConnected(peer.clone()));
+                drop(peers);
+                
+                self.set_state(ConnectionState::Connected).await;
+                Ok(())
+            }
+            SignalingMessage::ConnectionRejected { reason, from } => {
+                let mut peers = self.peers.write().await;
+                if !peers.get(&from.0).is_some_and(|peer| peer.state == ConnectionState::Connecting) {
+                    return Ok(());
+                }
+                
+                warn!("Peer {} rejected the connection: {}", from, reason);
+                peers.remove(&from.0);
+                let no_peers = peers.is_empty();
+                drop(peers);
+                
+                let _ = self.event_tx.send(ConnectionEvent::Error(format!("{} rejected the connection: {}", from, reason)));
+                if no_peers {
+                    self.set_state(ConnectionState::WaitingForPeer).await;
+                }
+                Ok(())
+            }
+            SignalingMessage::SessionTerminated { peer, reason } => {
+                self.pending_reconnects.write().await.remove(&peer.0);
+                if !self.peers.read().await.contains_key(&peer.0) {
+                    return Ok(());
+                }
+                
+                warn!("Session with {} terminated: {}", peer, reason);
+                let _ = self.event_tx.send(ConnectionEvent::Error(format!("Session with {} ended: {}", peer, reason)));
+                self.disconnect_from_peer(&peer.0).await
+            }
+            SignalingMessage::PeerLeft { device_id } => {
+                let session_id = self.host_sessions.read().await.get(&device_id.0).copied();
+                self.pending_requests.write().await.remove(&device_id.0);
+                let Some(session_id) = session_id else { return Ok(()) };
+                
+                // Kept resumable for a while in case the controller's network comes back
+                info!("Lost session with {}, waiting for it to resume", device_id);
+                self.resumption.lock().await.suspend(&session_id);
+                if let Some(peer) = self.peers.write().await.get_mut(&device_id.0) {
+                    peer.state = ConnectionState::Connecting;
+                }
+                self.expire_lost_sessions().await
+            }
+            SignalingMessage::ConnectionRequest { from, resume_token: None, .. } => {
+                info!("Connection request from {}", from);
+                self.pending_requests.write().await.insert(from.0.clone(), envelope.clone());
+                let _ = self.event_tx.send(ConnectionEvent::ConnectionRequested(from.0.clone()));
+                Ok(())
+            }
+            SignalingMessage::ConnectionRequest { from, resume_token: Some(token), .. } => {
+                self.expire_lost_sessions().await?;
+                let resumed = self.resumption.lock().await.redeem(from, token);
+                
+                let reply = match resumed {
+                    Ok(resumed) => {
+                        info!("Resuming session with {} after a network change", from);
+                        self.apply_permissions(&resumed.snapshot).await;
+                        self.admit_peer(&from.0).await;
+                        let _ = self.event_tx.send(ConnectionEvent::SessionResumed {
+                            peer: from.0.clone(),
+                            snapshot: resumed.snapshot,
+                        });
+                        SignalingMessage::ConnectionAccepted {
+                            session_id: resumed.session_id,
+                            from: self.my_device_id(),
+                            resume_token: Some(resumed.token),
+                        }
+                    }
+                    Err(_) if self.reconnect_store.as_ref().is_some_and(|store| store.redeem(&from.0, token)) => {
+                        info!("Resuming session with {} after restart", from);
+                        SignalingMessage::ConnectionAccepted {
+                            session_id: SessionId::new(),
+                            from: self.my_device_id(),
+                            resume_token: None,
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Rejected resume request from {}: {}", from, e);
+                        SignalingMessage::ConnectionRejected {
+                            reason: "Invalid or expired reconnect ticket".to_string(),
+                            from: self.my_device_id(),
+                        }
+                    }
+                };
+                
+                self.reply(&envelope, reply).await;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// Send the host's decision on a request; the server routes it back by the request's id
+    async fn reply(&self, request: &SignalingEnvelope, message: SignalingMessage) {
+        if let Some(tx) = self.signaling_tx.lock().await.as_ref() {
+            tx.send(request.reply(message)).ok();
+        }
+    }
+    
+    /// Track an admitted controller as a connected peer (host side)
+    async fn admit_peer(&self, controller_id: &str) {
+        let mut peers = self.peers.write().await;
+        let peer = peers.entry(controller_id.to_string()).or_insert_with(|| RemotePeer {
+            connection_id: controller_id.to_string(),
+            device_name: controller_id.to_string(),
+            state: ConnectionState::Connecting,
+            connected_at: None,
+            latency_ms: None,
+        });
+        peer.state = ConnectionState::Connected;
+        peer.connected_at = Some(chrono::Utc::now());
+        let _ = self.event_tx.send(ConnectionEvent::PeerConnected(peer.clone()));
+        drop(peers);
+        
+        self.set_state(ConnectionState::Connected).await;
+    }
+    
+    /// Remote control follows the session's granted permissions
+    async fn apply_permissions(&self, snapshot: &SessionSnapshot) {
+        let control = snapshot.permissions.contains(&Permission::ControlDevice);
+        *self.remote_control_enabled.write().await = control;
+    }
+    
+    /// Close sessions whose controller didn't come back within the resume window
+    async fn expire_lost_sessions(&self) -> Result<()> {
+        let mut resumption = self.resumption.lock().await;
+        resumption.cleanup();
+        let expired: Vec<String> = self.host_sessions.read().await.iter()
+            .filter(|(_, session_id)| !resumption.contains(session_id))
+            .map(|(controller_id, _)| controller_id.clone())
+            .collect();
+        drop(resumption);
+        
+        for controller_id in expired {
+            info!("Session with {} was not resumed in time", controller_id);
+            self.disconnect_from_peer(&controller_id).await?;
+        }
+        Ok(())
+    }
+    
     /// Get list of connected peers
     pub async fn connected_peers(&self) -> Vec<RemotePeer> {
         self.peers.read().await.values().cloned().collect()
@@ -385,38 +718,19 @@ impl ConnectionManager {
         }
     }
     
-    /// Get ICE servers configuration
+    /// Fallback ICE servers (STUN only) for connections without an API session
     pub fn get_ice_servers() -> Vec<IceServer> {
-        let mut servers = Vec::new();
-        
-        // Add STUN servers
-        for stun in STUN_SERVERS {
-            servers.push(IceServer {
+        STUN_SERVERS
+            .iter()
+            .map(|stun| IceServer {
                 urls: vec![stun.to_string()],
                 username: None,
                 credential: None,
-            });
-        }
-        
-        // Add TURN server
-        servers.push(IceServer {
-            urls: vec![TURN_SERVER.to_string()],
-            username: Some(TURN_USERNAME.to_string()),
-            credential: Some(TURN_CREDENTIAL.to_string()),
-        });
-        
-        servers
+            })
+            .collect()
     }
 }
 
-/// ICE server configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct IceServer {
-    pub urls: Vec<String>,
-    pub username: Option<String>,
-    pub credential: Option<String>,
-}
-
 impl Default for ConnectionManager {
     fn default() -> Self {
         Self::new().0
@@ -451,4 +765,162 @@ mod tests {
         }
         assert!(connected);
     }
+    
+    #[tokio::test]
+    async fn test_connect_requires_mfa_when_policy_does() {
+        let (mut manager, mut rx) = ConnectionManager::new();
+        let policy = MfaPolicy::new(true);
+        manager.set_mfa_policy(policy.clone());
+        let session = |payload: &str| crate::auth_service::AuthSession {
+            access_token: format!("e30.{}.sig", payload),
+            refresh_token: String::new(),
+            expires_at: 0,
+            user_id: "test-user".to_string(),
+        };
+        
+        assert!(manager.connect_to_peer("123-456-789").await.is_err());
+        policy.set_session(Some(&session("eyJhYWwiOiJhYWwxIn0")));
+        assert!(manager.connect_to_peer("123-456-789").await.is_err());
+        assert!(manager.connected_peers().await.is_empty());
+        assert!(matches!(rx.try_recv(), Ok(ConnectionEvent::Error(_))));
+        
+        policy.set_session(Some(&session("eyJhYWwiOiJhYWwyIn0")));
+        manager.connect_to_peer("123-456-789").await.unwrap();
+        assert_eq!(manager.connected_peers().await.len(), 1);
+    }
+    
+    fn peer_joined(connection_id: &str, device_name: &str) -> SignalingEnvelope {
+        SignalingMessage::PeerJoined {
+            peer: genxlink_protocol::PeerInfo {
+                device_id: DeviceId::from_string(connection_id.to_string()),
+                device_name: device_name.to_string(),
+                device_type: genxlink_protocol::DeviceType::Desktop,
+                online: true,
+                last_seen: None,
+            },
+        }.into()
+    }
+    
+    #[tokio::test]
+    async fn test_reconnect_after_restart() {
+        let (controller, mut rx) = ConnectionManager::new();
+        let (signaling_tx, mut signaling_rx) = mpsc::unbounded_channel();
+        *controller.signaling_tx.lock().await = Some(signaling_tx);
+        let ticket = crate::system_actions::issue_ticket("123-456-789", std::time::Duration::from_secs(60));
+        let token = ticket.token.clone();
+        
+        controller.expect_reconnect(ticket).await;
+        assert!(matches!(rx.try_recv(), Ok(ConnectionEvent::PeerRestarting(ref id)) if id == "123-456-789"));
+        
+        // Unrelated peers coming online are ignored
+        controller.handle_signaling_message(peer_joined("111-111-111", "Other")).await.unwrap();
+        assert!(controller.connected_peers().await.is_empty());
+        
+        controller.handle_signaling_message(peer_joined("123-456-789", "Host")).await.unwrap();
+        
+        // Exactly one request goes out, carrying the ticket, so the host isn't prompted
+        let request = signaling_rx.try_recv().unwrap();
+        assert!(request.id.is_some());
+        match &request.message {
+            SignalingMessage::ConnectionRequest { target, resume_token, .. } => {
+                assert_eq!(target.0, "123-456-789");
+                assert_eq!(resume_token.as_deref(), Some(token.as_str()));
+            }
+            other => panic!("expected a connection request, got {:?}", other),
+        }
+        assert!(signaling_rx.try_recv().is_err());
+        
+        let peers = controller.connected_peers().await;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].device_name, "Host");
+        assert_eq!(peers[0].state, ConnectionState::Connecting);
+        
+        // The host's decision completes the resume
+        controller.handle_signaling_message(request.reply(SignalingMessage::ConnectionAccepted {
+            session_id: SessionId::new(),
+            from: DeviceId::from_string("123-456-789".to_string()),
+            resume_token: None,
+        })).await.unwrap();
+        assert_eq!(controller.connected_peers().await[0].state, ConnectionState::Connected);
+        assert_eq!(controller.state().await, ConnectionState::Connected);
+        assert!(signaling_rx.try_recv().is_err());
+    }
+    
+    #[tokio::test]
+    async fn test_host_resumes_lost_session() {
+        let (host, mut rx) = ConnectionManager::new();
+        let (signaling_tx, mut signaling_rx) = mpsc::unbounded_channel();
+        *host.signaling_tx.lock().await = Some(signaling_tx);
+        let controller = DeviceId::from_string("123-456-789".to_string());
+        let request = |id: u64, resume_token: Option<&str>| SignalingEnvelope::request(id, SignalingMessage::ConnectionRequest {
+            target: host.my_device_id(),
+            from: controller.clone(),
+            resume_token: resume_token.map(str::to_string),
+        });
+        
+        // A new controller is prompted for, and accepting hands out a token
+        host.handle_signaling_message(request(1, None)).await.unwrap();
+        assert!(matches!(rx.try_recv(), Ok(ConnectionEvent::ConnectionRequested(ref id)) if id == "123-456-789"));
+        let snapshot = SessionSnapshot::new(1, std::collections::HashSet::from([Permission::ControlDevice]));
+        let session_id = host.accept_connection("123-456-789", snapshot).await.unwrap();
+        let SignalingMessage::ConnectionAccepted { resume_token: Some(token), .. } = signaling_rx.try_recv().unwrap().message else {
+            panic!("expected the host to accept with a resumption token");
+        };
+        host.update_session("123-456-789", SessionSnapshot::new(2, Default::default())).await.unwrap();
+        assert!(!*host.remote_control_enabled.read().await);
+        
+        // The token can't take over a session that is still running
+        host.handle_signaling_message(request(2, Some(&token))).await.unwrap();
+        assert!(matches!(signaling_rx.try_recv().unwrap().message, SignalingMessage::ConnectionRejected { .. }));
+        
+        // Once the controller drops off, its token re-admits it without a prompt
+        host.handle_signaling_message(SignalingMessage::PeerLeft { device_id: controller.clone() }.into()).await.unwrap();
+        assert_eq!(host.connected_peers().await[0].state, ConnectionState::Connecting);
+        while rx.try_recv().is_ok() {}
+        host.handle_signaling_message(request(3, Some(&token))).await.unwrap();
+        let reply = signaling_rx.try_recv().unwrap();
+        assert_eq!(reply.reply_to, Some(3));
+        match reply.message {
+            SignalingMessage::ConnectionAccepted { session_id: resumed_id, resume_token: Some(next_token), .. } => {
+                assert_eq!(resumed_id, session_id);
+                assert_ne!(next_token, token);
+            }
+            other => panic!("expected the session to be resumed, got {:?}", other),
+        }
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert!(!events.iter().any(|event| matches!(event, ConnectionEvent::ConnectionRequested(_))));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ConnectionEvent::SessionResumed { peer, snapshot } if peer == "123-456-789" && snapshot.monitor_index == 2
+        )));
+        assert_eq!(host.connected_peers().await[0].state, ConnectionState::Connected);
+        
+        // Ending the session on purpose revokes the token
+        host.disconnect_from_peer("123-456-789").await.unwrap();
+        host.handle_signaling_message(SignalingMessage::PeerLeft { device_id: controller.clone() }.into()).await.unwrap();
+        host.handle_signaling_message(request(4, Some(&token))).await.unwrap();
+        assert!(matches!(signaling_rx.try_recv().unwrap().message, SignalingMessage::ConnectionRejected { .. }));
+    }
+    
+    #[tokio::test]
+    async fn test_terminated_session_is_closed() {
+        let (manager, mut rx) = ConnectionManager::new();
+        manager.connect_to_peer("123-456-789").await.unwrap();
+        while rx.try_recv().is_ok() {}
+        
+        let terminated = |peer: &str| -> SignalingEnvelope {
+            SignalingMessage::SessionTerminated {
+                peer: DeviceId::from_string(peer.to_string()),
+                reason: "Terminated by an administrator".to_string(),
+            }.into()
+        };
+        manager.handle_signaling_message(terminated("111-111-111")).await.unwrap();
+        assert_eq!(manager.connected_peers().await.len(), 1);
+        
+        manager.handle_signaling_message(terminated("123-456-789")).await.unwrap();
+        assert!(manager.connected_peers().await.is_empty());
+        assert_eq!(manager.state().await, ConnectionState::WaitingForPeer);
+        let events: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert!(events.iter().any(|event| matches!(event, ConnectionEvent::PeerDisconnected(id) if id == "123-456-789")));
+    }
 }
@@ -1,14 +1,23 @@
-use crate::{ClientError, remote_control::{RemoteControlEvent, RemoteControlHandler}};
-use genxlink_protocol::{MessagePayload, MouseEvent, KeyboardEvent};
+use crate::{ClientError, annotation::AnnotationManager, connection_manager::ConnectionManager, host_control::HostControlManager, remote_control::{RemoteControlEvent, RemoteControlHandler}, system_actions::SystemActionManager, system_info::SystemInfoService};
+use genxlink_protocol::{MessagePayload, MouseEvent, KeyboardEvent, QualityReport, SystemActionStatus};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
 use webrtc::data_channel::RTCDataChannel;
 use bytes::Bytes;
 
 /// Control channel for remote control events
 pub struct ControlChannel {
     data_channel: Arc<RTCDataChannel>,
-    handler: Arc<RemoteControlHandler>,
+    /// Injects the controller's input; `None` where this host can't
+    handler: Option<Arc<RemoteControlHandler>>,
+    host_control: Option<Arc<HostControlManager>>,
+    system_info: Option<Arc<SystemInfoService>>,
+    annotations: Option<Arc<AnnotationManager>>,
+    quality_reports: Option<mpsc::UnboundedSender<QualityReport>>,
+    /// System action handler and the connection ID of the controller on this channel
+    system_actions: Option<(Arc<SystemActionManager>, String)>,
+    connection_manager: Option<Arc<ConnectionManager>>,
     enabled: Arc<Mutex<bool>>,
 }
 
@@ -18,20 +27,79 @@ impl ControlChannel {
         data_channel: Arc<RTCDataChannel>,
         handler: Arc<RemoteControlHandler>,
     ) -> Self {
+        let mut channel = Self::without_input(data_channel);
+        channel.handler = Some(handler);
+        channel
+    }
+
+    /// Control channel of a host that can't inject input
+    ///
+    /// Mouse and keyboard events are dropped; everything else works.
+    pub fn without_input(data_channel: Arc<RTCDataChannel>) -> Self {
         Self {
             data_channel,
-            handler,
+            handler: None,
+            host_control: None,
+            system_info: None,
+            annotations: None,
+            quality_reports: None,
+            system_actions: None,
+            connection_manager: None,
             enabled: Arc::new(Mutex::new(true)),
         }
     }
 
+    /// Handle privacy mode, input blocking and lock requests on this channel
+    pub fn set_host_control(&mut self, host_control: Arc<HostControlManager>) {
+        self.host_control = Some(host_control);
+    }
+
+    /// Answer system information and process requests on this channel
+    pub fn set_system_info(&mut self, system_info: Arc<SystemInfoService>) {
+        self.system_info = Some(system_info);
+    }
+
+    /// Apply screen annotations received on this channel
+    pub fn set_annotations(&mut self, annotations: Arc<AnnotationManager>) {
+        self.annotations = Some(annotations);
+    }
+
+    /// Pass quality reports from the host on to `reports`
+    pub fn set_quality_reports(&mut self, reports: mpsc::UnboundedSender<QualityReport>) {
+        self.quality_reports = Some(reports);
+    }
+
+    /// Handle reboot, sign-out, lock and Ctrl+Alt+Del requests from `controller_id`
+    pub fn set_system_actions(&mut self, system_actions: Arc<SystemActionManager>, controller_id: impl Into<String>) {
+        self.system_actions = Some((system_actions, controller_id.into()));
+    }
+
+    /// Reconnect through `connection_manager` when the host restarts for us
+    pub fn set_connection_manager(&mut self, connection_manager: Arc<ConnectionManager>) {
+        self.connection_manager = Some(connection_manager);
+    }
+
     /// Start listening for control events
     pub async fn start(&self) -> Result<(), ClientError> {
-        let handler = Arc::clone(&self.handler);
+        let handler = self.handler.clone();
+        let host_control = self.host_control.clone();
+        let system_info = self.system_info.clone();
+        let annotations = self.annotations.clone();
+        let quality_reports = self.quality_reports.clone();
+        let system_actions = self.system_actions.clone();
+        let connection_manager = self.connection_manager.clone();
+        let data_channel = Arc::clone(&self.data_channel);
         let enabled = Arc::clone(&self.enabled);
 
         self.data_channel.on_message(Box::new(move |msg| {
-            let handler = Arc::clone(&handler);
+            let handler = handler.clone();
+            let host_control = host_control.clone();
+            let system_info = system_info.clone();
+            let annotations = annotations.clone();
+            let quality_reports = quality_reports.clone();
+            let system_actions = system_actions.clone();
+            let connection_manager = connection_manager.clone();
+            let data_channel = Arc::clone(&data_channel);
             let enabled = Arc::clone(&enabled);
             
             Box::pin(async move {
@@ -54,13 +122,74 @@ impl ControlChannel {
                             MessagePayload::KeyboardEvent(keyboard_event) => {
                                 RemoteControlEvent::Keyboard(keyboard_event)
                             }
-                            _ => {
-                                tracing::warn!("Unsupported control message");
+                            MessagePayload::Annotation(_) => {
+                                match annotations {
+                                    Some(ref annotations) => {
+                                        annotations.handle_message(&payload);
+                                    }
+                                    None => tracing::warn!("Annotations not enabled on this channel"),
+                                }
+                                return;
+                            }
+                            MessagePayload::QualityReport(report) => {
+                                match quality_reports {
+                                    Some(ref reports) => {
+                                        let _ = reports.send(report);
+                                    }
+                                    None => tracing::debug!("Ignoring quality report"),
+                                }
+                                return;
+                            }
+                            MessagePayload::SystemActionResponse(response) => {
+                                match (response.status, response.reconnect, connection_manager) {
+                                    (SystemActionStatus::Executing, Some(ticket), Some(connection_manager)) => {
+                                        connection_manager.expect_reconnect(ticket).await;
+                                    }
+                                    (status, _, _) => tracing::info!("{:?} request {}: {:?}", response.action, response.request_id, status),
+                                }
+                                return;
+                            }
+                            other => {
+                                let reply = match (&other, &host_control, &system_info, &system_actions) {
+                                    (MessagePayload::SystemAction(_), _, _, Some((system_actions, controller_id))) => {
+                                        system_actions.handle_message(controller_id, &other).await
+                                    }
+                                    (
+                                        MessagePayload::SystemInfoRequest(_) | MessagePayload::ProcessKillRequest(_),
+                                        _,
+                                        Some(system_info),
+                                        _,
+                                    ) => {
+                                        // Collection walks /proc, keep it off the runtime threads
+                                        let system_info = Arc::clone(system_info);
+                                        tokio::task::spawn_blocking(move || system_info.handle_message(&other))
+                                            .await
+                                            .ok()
+                                            .flatten()
+                                    }
+                                    (_, Some(host_control), _, _) => host_control.handle_message(&other).await,
+                                    _ => None,
+                                };
+                                
+                                match reply {
+                                    Some(reply) => {
+                                        if let Ok(data) = serde_json::to_vec(&reply) {
+                                            if let Err(e) = data_channel.send(&Bytes::from(data)).await {
+                                                tracing::error!("Failed to send control reply: {}", e);
+                                            }
+                                        }
+                                    }
+                                    None => tracing::warn!("Unsupported control message"),
+                                }
                                 return;
                             }
                         };
 
                         // Handle event
+                        let Some(handler) = handler else {
+                            tracing::warn!("Remote input not available on this host");
+                            return;
+                        };
                         if let Err(e) = handler.handle_event(event).await {
                             tracing::error!("Failed to handle control event: {}", e);
                         }
@@ -94,6 +223,59 @@ impl ControlChannel {
         Ok(())
     }
 
+    /// Record the host user's decision on a system action and answer the controller
+    pub async fn confirm_system_action(&self, request_id: uuid::Uuid, approved: bool) -> Result<(), ClientError> {
+        let (system_actions, _) = self
+            .system_actions
+            .as_ref()
+            .ok_or_else(|| ClientError::InvalidInput("System actions not enabled on this channel".to_string()))?;
+        let response = system_actions.confirm(request_id, approved).await?;
+
+        let data = serde_json::to_vec(&MessagePayload::SystemActionResponse(response))
+            .map_err(|e| ClientError::TransportError(format!("Serialization failed: {}", e)))?;
+
+        self.data_channel
+            .send(&Bytes::from(data))
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to send: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Send the host's measured connection quality to the viewer
+    pub async fn send_quality_report(&self, report: QualityReport) -> Result<(), ClientError> {
+        let data = serde_json::to_vec(&MessagePayload::QualityReport(report))
+            .map_err(|e| ClientError::TransportError(format!("Serialization failed: {}", e)))?;
+
+        self.data_channel
+            .send(&Bytes::from(data))
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to send: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Send every report published on `reports` to the viewer, until the
+    /// sender is dropped
+    ///
+    /// Reports sent while the data channel isn't open are skipped.
+    pub fn forward_quality_reports(
+        self: &Arc<Self>,
+        mut reports: watch::Receiver<Option<QualityReport>>,
+    ) -> JoinHandle<()> {
+        let channel = Arc::clone(self);
+        tokio::spawn(async move {
+            while reports.changed().await.is_ok() {
+                let Some(report) = reports.borrow_and_update().clone() else {
+                    continue;
+                };
+                if let Err(e) = channel.send_quality_report(report).await {
+                    tracing::debug!("Quality report not sent: {}", e);
+                }
+            }
+        })
+    }
+
     /// Enable control channel
     pub async fn enable(&self) {
         let mut enabled = self.enabled.lock().await;
@@ -186,6 +368,10 @@ impl Default for ControlChannelManager {
 pub struct ControlChannelBuilder {
     data_channel: Option<Arc<RTCDataChannel>>,
     handler: Option<Arc<RemoteControlHandler>>,
+    host_control: Option<Arc<HostControlManager>>,
+    system_info: Option<Arc<SystemInfoService>>,
+    annotations: Option<Arc<AnnotationManager>>,
+    quality_reports: Option<mpsc::UnboundedSender<QualityReport>>,
 }
 
 impl ControlChannelBuilder {
@@ -194,6 +380,10 @@ impl ControlChannelBuilder {
         Self {
             data_channel: None,
             handler: None,
+            host_control: None,
+            system_info: None,
+            annotations: None,
+            quality_reports: None,
         }
     }
 
@@ -209,6 +399,30 @@ impl ControlChannelBuilder {
         self
     }
 
+    /// Set host control manager
+    pub fn with_host_control(mut self, host_control: Arc<HostControlManager>) -> Self {
+        self.host_control = Some(host_control);
+        self
+    }
+
+    /// Set system information service
+    pub fn with_system_info(mut self, system_info: Arc<SystemInfoService>) -> Self {
+        self.system_info = Some(system_info);
+        self
+    }
+
+    /// Set annotation manager
+    pub fn with_annotations(mut self, annotations: Arc<AnnotationManager>) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    /// Set where quality reports from the host go
+    pub fn with_quality_reports(mut self, reports: mpsc::UnboundedSender<QualityReport>) -> Self {
+        self.quality_reports = Some(reports);
+        self
+    }
+
     /// Build the control channel
     pub fn build(self) -> Result<ControlChannel, ClientError> {
         let data_channel = self.data_channel
@@ -217,7 +431,20 @@ impl ControlChannelBuilder {
         let handler = self.handler
             .ok_or_else(|| ClientError::TransportError("Handler not set".to_string()))?;
 
-        Ok(ControlChannel::new(data_channel, handler))
+        let mut channel = ControlChannel::new(data_channel, handler);
+        if let Some(host_control) = self.host_control {
+            channel.set_host_control(host_control);
+        }
+        if let Some(system_info) = self.system_info {
+            channel.set_system_info(system_info);
+        }
+        if let Some(annotations) = self.annotations {
+            channel.set_annotations(annotations);
+        }
+        if let Some(reports) = self.quality_reports {
+            channel.set_quality_reports(reports);
+        }
+        Ok(channel)
     }
 }
 
@@ -0,0 +1,167 @@
+//! Device Identity
+//!
+//! Ed25519 key pair that identifies this device to its peers.
+//! The key is generated once on first run and stored next to the installation
+//! ID; LAN announcements are signed with it and peers pin its public key.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+use sha2::{Digest, Sha256};
+
+/// Length of an Ed25519 public key in bytes
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of an Ed25519 signature in bytes
+pub const SIGNATURE_LEN: usize = 64;
+
+const KEY_FILE: &str = "device_identity.key";
+
+/// Long-term signing key of this device
+pub struct DeviceIdentity {
+    key_pair: Ed25519KeyPair,
+    pkcs8: Vec<u8>,
+}
+
+impl DeviceIdentity {
+    /// Load the device key from the config directory, creating it on first run
+    pub fn get_or_create() -> Result<Self, String> {
+        let key_file = crate::installation_id::InstallationId::get_config_dir()?.join(KEY_FILE);
+
+        if key_file.exists() {
+            let pkcs8 = fs::read(&key_file)
+                .map_err(|e| format!("Failed to read device key: {}", e))?;
+            Self::from_pkcs8(&pkcs8)
+        } else {
+            let identity = Self::generate()?;
+            Self::save_key(&key_file, &identity.pkcs8)?;
+            log::info!("Created device identity {}", identity.fingerprint());
+            Ok(identity)
+        }
+    }
+
+    /// Generate a new, unsaved key pair
+    pub fn generate() -> Result<Self, String> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| "Failed to generate device key".to_string())?;
+        Self::from_pkcs8(pkcs8.as_ref())
+    }
+
+    /// Load a key pair from its PKCS#8 encoding
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self, String> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|e| format!("Invalid device key: {}", e))?;
+
+        Ok(Self {
+            key_pair,
+            pkcs8: pkcs8.to_vec(),
+        })
+    }
+
+    /// PKCS#8 encoding of the private key
+    pub fn pkcs8(&self) -> &[u8] {
+        &self.pkcs8
+    }
+
+    /// Raw Ed25519 public key
+    pub fn public_key(&self) -> &[u8] {
+        self.key_pair.public_key().as_ref()
+    }
+
+    /// Fingerprint of the public key, for display and pinning
+    pub fn fingerprint(&self) -> String {
+        fingerprint(self.public_key())
+    }
+
+    /// Sign a message with the device key
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.key_pair.sign(message).as_ref().to_vec()
+    }
+
+    fn save_key(key_file: &Path, pkcs8: &[u8]) -> Result<(), String> {
+        fs::write(key_file, pkcs8)
+            .map_err(|e| format!("Failed to save device key: {}", e))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(key_file, fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Failed to protect device key: {}", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for DeviceIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceIdentity")
+            .field("fingerprint", &self.fingerprint())
+            .finish()
+    }
+}
+
+/// Verify a signature made by a device key
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    public_key.len() == PUBLIC_KEY_LEN
+        && signature.len() == SIGNATURE_LEN
+        && signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+            .verify(message, signature)
+            .is_ok()
+}
+
+/// SHA-256 fingerprint of a public key as colon-separated hex pairs
+pub fn fingerprint(public_key: &[u8]) -> String {
+    Sha256::digest(public_key)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Global device identity accessor
+static DEVICE_IDENTITY: std::sync::OnceLock<Arc<DeviceIdentity>> = std::sync::OnceLock::new();
+
+/// Get the global device identity
+pub fn get_device_identity() -> Arc<DeviceIdentity> {
+    DEVICE_IDENTITY
+        .get_or_init(|| {
+            let identity = DeviceIdentity::get_or_create().unwrap_or_else(|e| {
+                log::error!("Failed to load device identity: {}", e);
+                // Fall back to a key that only lives for this run
+                DeviceIdentity::generate().expect("Ed25519 key generation failed")
+            });
+            Arc::new(identity)
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let identity = DeviceIdentity::generate().unwrap();
+        let signature = identity.sign(b"hello");
+
+        assert!(verify_signature(identity.public_key(), b"hello", &signature));
+        assert!(!verify_signature(identity.public_key(), b"hellO", &signature));
+
+        let other = DeviceIdentity::generate().unwrap();
+        assert!(!verify_signature(other.public_key(), b"hello", &signature));
+    }
+
+    #[test]
+    fn test_pkcs8_round_trip() {
+        let identity = DeviceIdentity::generate().unwrap();
+        let loaded = DeviceIdentity::from_pkcs8(identity.pkcs8()).unwrap();
+
+        assert_eq!(identity.public_key(), loaded.public_key());
+        assert_eq!(identity.fingerprint(), loaded.fingerprint());
+        assert_eq!(identity.fingerprint().len(), 32 * 3 - 1);
+    }
+}
@@ -1,5 +1,8 @@
 use crate::{Frame, ClientError};
-use openh264::encoder::Encoder as OpenH264Encoder;
+use openh264::encoder::{Encoder as OpenH264Encoder, EncoderConfig as OpenH264Config, FrameType};
+use openh264::formats::YUVBuffer;
+use openh264::OpenH264API;
+use openh264_sys2::{SBitrateInfo, ENCODER_OPTION_BITRATE, ENCODER_OPTION_FRAME_RATE, SPATIAL_LAYER_ALL};
 
 /// Encoder configuration
 #[derive(Debug, Clone)]
@@ -51,15 +54,28 @@ pub trait VideoEncoder: Send + Sync {
     /// Flush any pending frames
     fn flush(&mut self) -> Result<Vec<EncodedFrame>, ClientError>;
     
+    /// Make the next encoded frame a keyframe
+    fn request_keyframe(&mut self) {}
+    
+    /// Encode at `bitrate` bits per second and `fps` from the next frame on
+    fn set_rate(&mut self, _bitrate: u32, _fps: u32) -> Result<(), ClientError> {
+        Ok(())
+    }
+    
     /// Get encoder configuration
     fn get_config(&self) -> &EncoderConfig;
 }
 
 /// H.264 encoder implementation
+///
+/// Frames are encoded at their own size; the encoder starts over with a
+/// keyframe when the size changes.
 pub struct H264Encoder {
     config: Option<EncoderConfig>,
     encoder: Option<OpenH264Encoder>,
-    frame_count: u64,
+    /// Size the current encoder was opened for
+    dimensions: Option<(u32, u32)>,
+    keyframe_requested: bool,
 }
 
 impl H264Encoder {
@@ -67,9 +83,19 @@ impl H264Encoder {
         Self {
             config: None,
             encoder: None,
-            frame_count: 0,
+            dimensions: None,
+            keyframe_requested: false,
         }
     }
+
+    /// OpenH264 encoder for `config`'s bitrate and frame rate
+    fn open(config: &EncoderConfig) -> Result<OpenH264Encoder, ClientError> {
+        let options = OpenH264Config::new()
+            .set_bitrate_bps(config.bitrate)
+            .max_frame_rate(config.fps as f32);
+        OpenH264Encoder::with_api_config(OpenH264API::from_source(), options)
+            .map_err(|e| ClientError::EncodingError(format!("Failed to create encoder: {:?}", e)))
+    }
 }
 
 impl Default for H264Encoder {
@@ -80,44 +106,57 @@ impl Default for H264Encoder {
 
 impl VideoEncoder for H264Encoder {
     fn init(&mut self, config: EncoderConfig) -> Result<(), ClientError> {
-        // Create OpenH264 encoder
-        let encoder = OpenH264Encoder::new()
-            .map_err(|e| ClientError::EncodingError(format!("Failed to create encoder: {:?}", e)))?;
+        let encoder = Self::open(&config)?;
         
         tracing::info!("H.264 encoder initialized: {}x{} @ {} fps, {} bps", 
             config.width, config.height, config.fps, config.bitrate);
         
         self.encoder = Some(encoder);
         self.config = Some(config);
-        self.frame_count = 0;
+        self.dimensions = None;
         
         Ok(())
     }
     
     fn encode(&mut self, frame: &Frame) -> Result<EncodedFrame, ClientError> {
-        let config = self.config.as_ref()
+        let config = self.config.as_mut()
             .ok_or_else(|| ClientError::EncodingError("Encoder not initialized".to_string()))?;
         
+        // 4:2:0 needs even dimensions; an odd last row or column is dropped
+        let (width, height) = (frame.width & !1, frame.height & !1);
+        if width == 0 || height == 0 {
+            return Err(ClientError::EncodingError(format!("Frame too small: {}x{}", frame.width, frame.height)));
+        }
+        
+        // OpenH264 would reinitialize with the rate it was opened with, so
+        // open a new encoder at the current rate instead
+        if self.dimensions.is_some_and(|dimensions| dimensions != (width, height)) {
+            self.encoder = Some(Self::open(config)?);
+        }
+        self.dimensions = Some((width, height));
+        config.width = width;
+        config.height = height;
+        
+        let yuv = Self::bgra_to_yuv(&frame.data, frame.stride.max(frame.width * 4), width, height)?;
+        let yuv_buffer = YUVBuffer::from_vec(yuv, width as usize, height as usize);
+        
         let encoder = self.encoder.as_mut()
             .ok_or_else(|| ClientError::EncodingError("Encoder not initialized".to_string()))?;
         
-        // Create YUV buffer for encoding
-        let yuv_buffer = openh264::formats::YUVBuffer::new(
-            config.width as usize, 
-            config.height as usize
-        );
+        // The viewer lost the picture
+        if self.keyframe_requested {
+            self.keyframe_requested = false;
+            encoder.force_intra_frame();
+        }
         
         // Encode frame
         let bitstream = encoder.encode(&yuv_buffer)
             .map_err(|e| ClientError::EncodingError(format!("Encoding failed: {:?}", e)))?;
         
-        self.frame_count += 1;
-        let is_keyframe = self.frame_count % 30 == 1; // Keyframe every 30 frames
-        
         Ok(EncodedFrame {
             data: bitstream.to_vec(),
             timestamp: frame.timestamp,
-            is_keyframe,
+            is_keyframe: matches!(bitstream.frame_type(), FrameType::IDR | FrameType::I),
         })
     }
     
@@ -126,31 +165,75 @@ impl VideoEncoder for H264Encoder {
         Ok(vec![])
     }
     
+    fn request_keyframe(&mut self) {
+        self.keyframe_requested = true;
+    }
+    
+    fn set_rate(&mut self, bitrate: u32, fps: u32) -> Result<(), ClientError> {
+        let config = self.config.as_mut()
+            .ok_or_else(|| ClientError::EncodingError("Encoder not initialized".to_string()))?;
+        if (config.bitrate, config.fps) == (bitrate, fps) {
+            return Ok(());
+        }
+        config.bitrate = bitrate;
+        config.fps = fps;
+        
+        // Until the first frame the encoder isn't set up; open it at the new rate
+        let Some(encoder) = self.encoder.as_mut().filter(|_| self.dimensions.is_some()) else {
+            self.encoder = Some(Self::open(config)?);
+            return Ok(());
+        };
+        
+        // Changed in place, so the stream goes on without a keyframe
+        let mut bitrate_info = SBitrateInfo {
+            iLayer: SPATIAL_LAYER_ALL,
+            iBitrate: bitrate.min(i32::MAX as u32) as i32,
+        };
+        let mut frame_rate = fps.max(1) as f32;
+        // SAFETY: both options take a pointer to the value type set here and
+        // are read during the call only
+        let failed = unsafe {
+            let raw_api = encoder.raw_api();
+            raw_api.set_option(ENCODER_OPTION_BITRATE, std::ptr::addr_of_mut!(bitrate_info).cast()) != 0
+                || raw_api.set_option(ENCODER_OPTION_FRAME_RATE, std::ptr::addr_of_mut!(frame_rate).cast()) != 0
+        };
+        if failed {
+            return Err(ClientError::EncodingError(format!("Failed to set rate to {} bps at {} fps", bitrate, fps)));
+        }
+        
+        tracing::debug!("H.264 encoder now at {} bps, {} fps", bitrate, fps);
+        Ok(())
+    }
+    
     fn get_config(&self) -> &EncoderConfig {
         self.config.as_ref().expect("Encoder not initialized")
     }
 }
 
 impl H264Encoder {
-    /// Convert BGRA to YUV420 format
-    fn bgra_to_yuv(&self, bgra: &[u8], width: u32, height: u32) -> Result<Vec<u8>, ClientError> {
-        let pixel_count = (width * height) as usize;
+    /// Convert the top-left `width` x `height` of a BGRA image with rows
+    /// `stride` bytes apart to YUV420 (I420)
+    fn bgra_to_yuv(bgra: &[u8], stride: u32, width: u32, height: u32) -> Result<Vec<u8>, ClientError> {
+        let (stride, width, height) = (stride as usize, width as usize, height as usize);
+        if bgra.len() < stride * (height - 1) + width * 4 {
+            return Err(ClientError::EncodingError(format!(
+                "Frame data too short for {}x{}: {} bytes", width, height, bgra.len()
+            )));
+        }
+        
+        let pixel_count = width * height;
         let mut yuv = vec![0u8; pixel_count * 3 / 2]; // YUV420 format
         
-        // Simple BGRA to YUV conversion
         // Y plane
-        for y in 0..height as usize {
-            for x in 0..width as usize {
-                let idx = (y * width as usize + x) * 4;
-                if idx + 3 < bgra.len() {
-                    let b = bgra[idx] as f32;
-                    let g = bgra[idx + 1] as f32;
-                    let r = bgra[idx + 2] as f32;
-                    
-                    // BT.601 conversion
-                    let y_val = (0.299 * r + 0.587 * g + 0.114 * b) as u8;
-                    yuv[y * width as usize + x] = y_val;
-                }
+        for y in 0..height {
+            for x in 0..width {
+                let idx = y * stride + x * 4;
+                let b = bgra[idx] as f32;
+                let g = bgra[idx + 1] as f32;
+                let r = bgra[idx + 2] as f32;
+                
+                // BT.601 conversion
+                yuv[y * width + x] = (0.299 * r + 0.587 * g + 0.114 * b) as u8;
             }
         }
         
@@ -158,21 +241,16 @@ impl H264Encoder {
         let u_offset = pixel_count;
         let v_offset = pixel_count + pixel_count / 4;
         
-        for y in (0..height as usize).step_by(2) {
-            for x in (0..width as usize).step_by(2) {
-                let idx = (y * width as usize + x) * 4;
-                if idx + 3 < bgra.len() {
-                    let b = bgra[idx] as f32;
-                    let g = bgra[idx + 1] as f32;
-                    let r = bgra[idx + 2] as f32;
-                    
-                    let u_val = ((-0.169 * r - 0.331 * g + 0.500 * b) + 128.0) as u8;
-                    let v_val = ((0.500 * r - 0.419 * g - 0.081 * b) + 128.0) as u8;
-                    
-                    let uv_idx = (y / 2) * (width as usize / 2) + (x / 2);
-                    yuv[u_offset + uv_idx] = u_val;
-                    yuv[v_offset + uv_idx] = v_val;
-                }
+        for y in (0..height).step_by(2) {
+            for x in (0..width).step_by(2) {
+                let idx = y * stride + x * 4;
+                let b = bgra[idx] as f32;
+                let g = bgra[idx + 1] as f32;
+                let r = bgra[idx + 2] as f32;
+                
+                let uv_idx = (y / 2) * (width / 2) + (x / 2);
+                yuv[u_offset + uv_idx] = ((-0.169 * r - 0.331 * g + 0.500 * b) + 128.0) as u8;
+                yuv[v_offset + uv_idx] = ((0.500 * r - 0.419 * g - 0.081 * b) + 128.0) as u8;
             }
         }
         
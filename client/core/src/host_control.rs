@@ -0,0 +1,593 @@
+//! Host control: privacy mode, input blocking and workstation locking
+//!
+//! These actions run on the controlled host. Privacy mode blanks the
+//! physical display while screen capture keeps streaming, input blocking
+//! disables the local keyboard and mouse (injected remote input still
+//! works), and the workstation can be locked immediately or when the
+//! session ends.
+
+use crate::permission_profiles::{Permission, PermissionProfile};
+use crate::ClientError;
+use genxlink_protocol::{
+    BlockInputRequest, HostControlStatus, LockDeviceRequest, LockTiming, MessagePayload,
+    PrivacyModeRequest,
+};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Platform backend for host control actions
+pub trait HostControl: Send + Sync {
+    /// Blank or restore the physical display
+    fn set_privacy_mode(&mut self, enabled: bool) -> Result<(), ClientError>;
+
+    /// Block or unblock local keyboard and mouse
+    fn set_input_blocked(&mut self, blocked: bool) -> Result<(), ClientError>;
+
+    /// Lock the workstation
+    fn lock_workstation(&mut self) -> Result<(), ClientError>;
+
+    /// Backend name for logging
+    fn name(&self) -> &'static str;
+}
+
+/// No-op backend for platforms without an implementation (and for tests)
+#[derive(Debug, Default)]
+pub struct NoopHostControl {
+    pub privacy_mode: bool,
+    pub input_blocked: bool,
+    pub lock_count: u32,
+}
+
+impl NoopHostControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl HostControl for NoopHostControl {
+    fn set_privacy_mode(&mut self, enabled: bool) -> Result<(), ClientError> {
+        self.privacy_mode = enabled;
+        Ok(())
+    }
+
+    fn set_input_blocked(&mut self, blocked: bool) -> Result<(), ClientError> {
+        self.input_blocked = blocked;
+        Ok(())
+    }
+
+    fn lock_workstation(&mut self) -> Result<(), ClientError> {
+        self.lock_count += 1;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "noop"
+    }
+}
+
+/// X11 implementation using the standard X utilities
+///
+/// Privacy mode forces DPMS off (the framebuffer, and therefore capture, is
+/// unaffected) and re-asserts it while enabled, since any input wakes the
+/// monitor. Input blocking disables every physical slave device through
+/// `xinput`; the XTEST devices used for remote input injection stay enabled.
+#[cfg(target_os = "linux")]
+pub mod x11_impl {
+    use super::*;
+    use std::process::Command;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// Interval at which privacy mode re-blanks the display
+    const PRIVACY_REASSERT_INTERVAL: Duration = Duration::from_millis(500);
+
+    /// An input device reported by `xinput list --short`
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct XInputDevice {
+        pub id: u32,
+        pub name: String,
+        pub is_slave: bool,
+    }
+
+    impl XInputDevice {
+        /// Whether the device is physical local input that should be blocked
+        pub fn is_blockable(&self) -> bool {
+            self.is_slave && !self.name.contains("XTEST")
+        }
+    }
+
+    /// Parse the output of `xinput list --short`
+    pub fn parse_xinput_list(output: &str) -> Vec<XInputDevice> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let id_pos = line.find("id=")?;
+                let name = line[..id_pos]
+                    .trim_start_matches(|c: char| !c.is_alphanumeric())
+                    .trim()
+                    .to_string();
+                let id = line[id_pos + 3..]
+                    .split(|c: char| !c.is_ascii_digit())
+                    .next()?
+                    .parse()
+                    .ok()?;
+                let is_slave = line.contains("[slave");
+                Some(XInputDevice { id, name, is_slave })
+            })
+            .collect()
+    }
+
+    pub struct X11HostControl {
+        display: Option<String>,
+        privacy_active: Arc<AtomicBool>,
+        disabled_devices: Vec<u32>,
+    }
+
+    impl X11HostControl {
+        /// Use the `DISPLAY` of the current process
+        pub fn new() -> Self {
+            Self {
+                display: None,
+                privacy_active: Arc::new(AtomicBool::new(false)),
+                disabled_devices: Vec::new(),
+            }
+        }
+
+        /// Target a specific display (e.g. ":99" under Xvfb)
+        pub fn with_display(display: impl Into<String>) -> Self {
+            let mut control = Self::new();
+            control.display = Some(display.into());
+            control
+        }
+
+        /// Devices currently disabled by input blocking
+        pub fn disabled_devices(&self) -> &[u32] {
+            &self.disabled_devices
+        }
+
+        fn command(&self, program: &str) -> Command {
+            let mut cmd = Command::new(program);
+            if let Some(ref display) = self.display {
+                cmd.env("DISPLAY", display);
+            }
+            cmd
+        }
+
+        fn run(&self, program: &str, args: &[&str]) -> Result<String, ClientError> {
+            let output = self
+                .command(program)
+                .args(args)
+                .output()
+                .map_err(|e| ClientError::IoError(format!("Failed to run {}: {}", program, e)))?;
+
+            if !output.status.success() {
+                return Err(ClientError::IoError(format!(
+                    "{} {} failed: {}",
+                    program,
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+
+        /// List input devices on the display
+        pub fn list_devices(&self) -> Result<Vec<XInputDevice>, ClientError> {
+            let output = self.run("xinput", &["list", "--short"])?;
+            Ok(parse_xinput_list(&output))
+        }
+
+        /// Whether the X server has input device `id` enabled
+        pub fn is_device_enabled(&self, id: u32) -> Result<bool, ClientError> {
+            let output = self.run("xinput", &["list-props", &id.to_string()])?;
+            output
+                .lines()
+                .find(|line| line.trim_start().starts_with("Device Enabled"))
+                .and_then(|line| line.rsplit(':').next())
+                .map(|value| value.trim() == "1")
+                .ok_or_else(|| ClientError::IoError(format!("No enabled state for input device {}", id)))
+        }
+
+        /// Whether DPMS reports the monitor on, or `None` without DPMS
+        pub fn is_monitor_on(&self) -> Result<Option<bool>, ClientError> {
+            let output = self.run("xset", &["q"])?;
+            Ok(output
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("Monitor is "))
+                .map(|state| state.trim() == "On"))
+        }
+    }
+
+    impl Default for X11HostControl {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl HostControl for X11HostControl {
+        fn set_privacy_mode(&mut self, enabled: bool) -> Result<(), ClientError> {
+            if !enabled {
+                self.privacy_active.store(false, Ordering::SeqCst);
+                self.run("xset", &["dpms", "force", "on"])?;
+                return Ok(());
+            }
+
+            self.run("xset", &["+dpms"])?;
+            self.run("xset", &["dpms", "force", "off"])?;
+
+            if !self.privacy_active.swap(true, Ordering::SeqCst) {
+                let active = Arc::clone(&self.privacy_active);
+                let display = self.display.clone();
+                std::thread::spawn(move || {
+                    while active.load(Ordering::SeqCst) {
+                        std::thread::sleep(PRIVACY_REASSERT_INTERVAL);
+                        if !active.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let mut cmd = Command::new("xset");
+                        if let Some(ref display) = display {
+                            cmd.env("DISPLAY", display);
+                        }
+                        let _ = cmd.args(["dpms", "force", "off"]).output();
+                    }
+                });
+            }
+
+            Ok(())
+        }
+
+        fn set_input_blocked(&mut self, blocked: bool) -> Result<(), ClientError> {
+            if blocked {
+                for device in self.list_devices()? {
+                    if device.is_blockable() && !self.disabled_devices.contains(&device.id) {
+                        self.run("xinput", &["disable", &device.id.to_string()])?;
+                        self.disabled_devices.push(device.id);
+                    }
+                }
+                tracing::info!("Blocked {} local input devices", self.disabled_devices.len());
+            } else {
+                for id in std::mem::take(&mut self.disabled_devices) {
+                    if let Err(e) = self.run("xinput", &["enable", &id.to_string()]) {
+                        tracing::warn!("Failed to re-enable input device {}: {}", id, e);
+                    }
+                }
+                tracing::info!("Local input devices unblocked");
+            }
+            Ok(())
+        }
+
+        fn lock_workstation(&mut self) -> Result<(), ClientError> {
+            if self.run("loginctl", &["lock-session"]).is_ok() {
+                return Ok(());
+            }
+            self.run("xdg-screensaver", &["lock"]).map(|_| ())
+        }
+
+        fn name(&self) -> &'static str {
+            "x11"
+        }
+    }
+
+    impl Drop for X11HostControl {
+        fn drop(&mut self) {
+            // Never leave the host blanked or without input
+            if self.privacy_active.swap(false, Ordering::SeqCst) {
+                let _ = self.run("xset", &["dpms", "force", "on"]);
+            }
+            let _ = self.set_input_blocked(false);
+        }
+    }
+}
+
+/// Create the host control backend for the current platform
+pub fn create_host_control() -> Box<dyn HostControl> {
+    #[cfg(target_os = "linux")]
+    {
+        if std::env::var_os("DISPLAY").is_some() {
+            return Box::new(x11_impl::X11HostControl::new());
+        }
+    }
+
+    Box::new(NoopHostControl::new())
+}
+
+/// Applies host control requests from the controller, enforcing permissions
+pub struct HostControlManager {
+    backend: Arc<Mutex<Box<dyn HostControl>>>,
+    profile: PermissionProfile,
+    status: Arc<Mutex<HostControlStatus>>,
+}
+
+impl HostControlManager {
+    /// Create a new host control manager
+    pub fn new(backend: Box<dyn HostControl>, profile: PermissionProfile) -> Self {
+        Self {
+            backend: Arc::new(Mutex::new(backend)),
+            profile,
+            status: Arc::new(Mutex::new(HostControlStatus::default())),
+        }
+    }
+
+    /// Current host control state
+    pub async fn status(&self) -> HostControlStatus {
+        self.status.lock().await.clone()
+    }
+
+    /// Handle a host control message, returning the status to report back
+    ///
+    /// Returns `None` for messages that are not host control requests.
+    pub async fn handle_message(&self, payload: &MessagePayload) -> Option<MessagePayload> {
+        let result = match payload {
+            MessagePayload::PrivacyMode(request) => self.set_privacy_mode(request).await,
+            MessagePayload::BlockInput(request) => self.set_input_blocked(request).await,
+            MessagePayload::LockDevice(request) => self.lock_device(request).await,
+            _ => return None,
+        };
+
+        let mut status = self.status().await;
+        status.error = result.err().map(|e| e.to_string());
+        Some(MessagePayload::HostControlStatus(status))
+    }
+
+    /// Enable or disable privacy mode
+    pub async fn set_privacy_mode(&self, request: &PrivacyModeRequest) -> Result<(), ClientError> {
+        self.require(Permission::EnablePrivacyMode)?;
+        self.backend.lock().await.set_privacy_mode(request.enabled)?;
+        self.status.lock().await.privacy_mode = request.enabled;
+        tracing::info!("Privacy mode {}", if request.enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+
+    /// Block or unblock local input
+    pub async fn set_input_blocked(&self, request: &BlockInputRequest) -> Result<(), ClientError> {
+        self.require(Permission::BlockInputDevices)?;
+        self.backend.lock().await.set_input_blocked(request.enabled)?;
+        self.status.lock().await.input_blocked = request.enabled;
+        tracing::info!("Local input {}", if request.enabled { "blocked" } else { "unblocked" });
+        Ok(())
+    }
+
+    /// Lock the workstation now or schedule a lock for session end
+    pub async fn lock_device(&self, request: &LockDeviceRequest) -> Result<(), ClientError> {
+        self.require(Permission::LockDevice)?;
+        match request.timing {
+            LockTiming::Now => self.backend.lock().await.lock_workstation(),
+            LockTiming::OnSessionEnd => {
+                self.status.lock().await.lock_on_session_end = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Restore the host when the session ends
+    ///
+    /// Always turns privacy mode off and unblocks input, then locks the
+    /// workstation if that was requested. Every step runs even when an
+    /// earlier one fails; the failures are reported together.
+    pub async fn end_session(&self) -> Result<(), ClientError> {
+        let mut status = self.status.lock().await;
+        let mut backend = self.backend.lock().await;
+        let mut errors = Vec::new();
+
+        if status.privacy_mode {
+            match backend.set_privacy_mode(false) {
+                Ok(()) => status.privacy_mode = false,
+                Err(e) => errors.push(format!("privacy mode: {}", e)),
+            }
+        }
+        if status.input_blocked {
+            match backend.set_input_blocked(false) {
+                Ok(()) => status.input_blocked = false,
+                Err(e) => errors.push(format!("input blocking: {}", e)),
+            }
+        }
+        if status.lock_on_session_end {
+            status.lock_on_session_end = false;
+            match backend.lock_workstation() {
+                Ok(()) => tracing::info!("Workstation locked at session end ({})", backend.name()),
+                Err(e) => errors.push(format!("workstation lock: {}", e)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            tracing::error!("Failed to restore the host at session end: {}", errors.join("; "));
+            Err(ClientError::IoError(format!(
+                "Failed to restore the host at session end: {}",
+                errors.join("; ")
+            )))
+        }
+    }
+
+    fn require(&self, permission: Permission) -> Result<(), ClientError> {
+        if self.profile.has_permission(&permission) {
+            Ok(())
+        } else {
+            Err(ClientError::PermissionDenied(permission.name().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission_profiles::PermissionProfileType;
+
+    /// Backend that shares its state with the test
+    struct SharedBackend(Arc<std::sync::Mutex<NoopHostControl>>);
+
+    impl HostControl for SharedBackend {
+        fn set_privacy_mode(&mut self, enabled: bool) -> Result<(), ClientError> {
+            self.0.lock().unwrap().set_privacy_mode(enabled)
+        }
+        fn set_input_blocked(&mut self, blocked: bool) -> Result<(), ClientError> {
+            self.0.lock().unwrap().set_input_blocked(blocked)
+        }
+        fn lock_workstation(&mut self) -> Result<(), ClientError> {
+            self.0.lock().unwrap().lock_workstation()
+        }
+        fn name(&self) -> &'static str {
+            "shared"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_end_restores_host() {
+        let state = Arc::new(std::sync::Mutex::new(NoopHostControl::new()));
+        let manager = HostControlManager::new(
+            Box::new(SharedBackend(Arc::clone(&state))),
+            PermissionProfile::new(PermissionProfileType::FullAccess),
+        );
+
+        manager.set_privacy_mode(&PrivacyModeRequest { enabled: true }).await.unwrap();
+        manager.set_input_blocked(&BlockInputRequest { enabled: true }).await.unwrap();
+        manager.lock_device(&LockDeviceRequest { timing: LockTiming::OnSessionEnd }).await.unwrap();
+        assert!(state.lock().unwrap().privacy_mode);
+        assert!(state.lock().unwrap().input_blocked);
+        assert_eq!(state.lock().unwrap().lock_count, 0);
+
+        manager.end_session().await.unwrap();
+        let state = state.lock().unwrap();
+        assert!(!state.privacy_mode);
+        assert!(!state.input_blocked);
+        assert_eq!(state.lock_count, 1);
+    }
+
+    /// Backend whose display can't be restored
+    struct StuckDisplay(Arc<std::sync::Mutex<NoopHostControl>>);
+
+    impl HostControl for StuckDisplay {
+        fn set_privacy_mode(&mut self, enabled: bool) -> Result<(), ClientError> {
+            if enabled {
+                self.0.lock().unwrap().set_privacy_mode(true)
+            } else {
+                Err(ClientError::IoError("xset failed".to_string()))
+            }
+        }
+        fn set_input_blocked(&mut self, blocked: bool) -> Result<(), ClientError> {
+            self.0.lock().unwrap().set_input_blocked(blocked)
+        }
+        fn lock_workstation(&mut self) -> Result<(), ClientError> {
+            self.0.lock().unwrap().lock_workstation()
+        }
+        fn name(&self) -> &'static str {
+            "stuck"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_end_runs_every_step() {
+        let state = Arc::new(std::sync::Mutex::new(NoopHostControl::new()));
+        let manager = HostControlManager::new(
+            Box::new(StuckDisplay(Arc::clone(&state))),
+            PermissionProfile::new(PermissionProfileType::FullAccess),
+        );
+        manager.set_privacy_mode(&PrivacyModeRequest { enabled: true }).await.unwrap();
+        manager.set_input_blocked(&BlockInputRequest { enabled: true }).await.unwrap();
+        manager.lock_device(&LockDeviceRequest { timing: LockTiming::OnSessionEnd }).await.unwrap();
+
+        // Privacy mode fails to turn off; input is still unblocked and the lock still happens
+        let error = manager.end_session().await.unwrap_err();
+        assert!(error.to_string().contains("privacy mode"));
+        assert!(!state.lock().unwrap().input_blocked);
+        assert_eq!(state.lock().unwrap().lock_count, 1);
+
+        let status = manager.status().await;
+        assert!(status.privacy_mode);
+        assert!(!status.input_blocked);
+    }
+
+    #[tokio::test]
+    async fn test_permissions_enforced() {
+        let profile = PermissionProfile::new(PermissionProfileType::ScreenSharing);
+        let manager = HostControlManager::new(Box::new(NoopHostControl::new()), profile);
+
+        let reply = manager
+            .handle_message(&MessagePayload::BlockInput(BlockInputRequest { enabled: true }))
+            .await;
+        match reply {
+            Some(MessagePayload::HostControlStatus(status)) => {
+                assert!(!status.input_blocked);
+                assert!(status.error.is_some());
+            }
+            _ => panic!("Expected host control status"),
+        }
+
+        assert!(manager.handle_message(&MessagePayload::Ping).await.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_xinput_list() {
+        let output = "\
+⎡ Virtual core pointer                    \tid=2\t[master pointer  (3)]
+⎜   ↳ Virtual core XTEST pointer              \tid=4\t[slave  pointer  (2)]
+⎜   ↳ SynPS/2 Synaptics TouchPad              \tid=12\t[slave  pointer  (2)]
+⎣ Virtual core keyboard                   \tid=3\t[master keyboard (2)]
+    ↳ Virtual core XTEST keyboard             \tid=5\t[slave  keyboard (3)]
+    ↳ AT Translated Set 2 keyboard            \tid=11\t[slave  keyboard (3)]
+";
+        let devices = x11_impl::parse_xinput_list(output);
+        assert_eq!(devices.len(), 6);
+        assert_eq!(devices[2].name, "SynPS/2 Synaptics TouchPad");
+
+        let blockable: Vec<u32> = devices.iter().filter(|d| d.is_blockable()).map(|d| d.id).collect();
+        assert_eq!(blockable, vec![12, 11]);
+    }
+
+    /// Exercises the X11 backend against a real server; run under
+    /// `xvfb-run cargo test` (skipped when no display is available).
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_x11_input_blocking_under_xvfb() {
+        if std::env::var_os("DISPLAY").is_none() {
+            println!("DISPLAY not set, skipping X11 host control test");
+            return;
+        }
+
+        let mut backend = x11_impl::X11HostControl::new();
+        let devices = match backend.list_devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                println!("xinput unavailable, skipping: {}", e);
+                return;
+            }
+        };
+
+        let blockable: Vec<u32> = devices.iter().filter(|d| d.is_blockable()).map(|d| d.id).collect();
+        let injection: Vec<u32> = devices.iter().filter(|d| d.is_slave && !d.is_blockable()).map(|d| d.id).collect();
+
+        backend.set_input_blocked(true).unwrap();
+        assert_eq!(backend.disabled_devices().len(), blockable.len());
+        for id in &blockable {
+            assert!(!backend.is_device_enabled(*id).unwrap(), "device {} still enabled", id);
+        }
+        // Remote input keeps working
+        for id in &injection {
+            assert!(backend.is_device_enabled(*id).unwrap(), "XTEST device {} disabled", id);
+        }
+
+        backend.set_input_blocked(false).unwrap();
+        assert!(backend.disabled_devices().is_empty());
+        for id in &blockable {
+            assert!(backend.is_device_enabled(*id).unwrap(), "device {} not restored", id);
+        }
+
+        // Dropping the backend mid-session restores input and the monitor
+        let probe = x11_impl::X11HostControl::new();
+        backend.set_input_blocked(true).unwrap();
+        let dpms = backend.set_privacy_mode(true).is_ok();
+        if dpms {
+            assert_eq!(probe.is_monitor_on().unwrap(), Some(false));
+        }
+        drop(backend);
+        for id in &blockable {
+            assert!(probe.is_device_enabled(*id).unwrap(), "device {} not restored on drop", id);
+        }
+        if dpms {
+            assert_eq!(probe.is_monitor_on().unwrap(), Some(true));
+        }
+    }
+}
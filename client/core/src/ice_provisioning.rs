@@ -0,0 +1,171 @@
+//! ICE server provisioning
+//!
+//! STUN and TURN servers come from the API for each session
+//! (`GET /api/sessions/{id}/ice-servers`) rather than being built into the
+//! client. The TURN credentials in the answer are bound to the session and
+//! expire, so the provider fetches a new list ahead of `expires_at` and
+//! publishes it to subscribers. A live peer connection can't switch to the
+//! new credentials, so `WebRTCSession::use_ice_provider` moves the session
+//! onto a new connection with each list before the old one runs out.
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use genxlink_protocol::IceConfiguration;
+
+use crate::ClientError;
+
+/// Fetch new servers this long before the credentials expire
+pub const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Never refresh more often than this
+const MIN_REFRESH_DELAY: Duration = Duration::from_secs(1);
+
+/// Wait between attempts after a failed refresh
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+
+/// ICE servers issued by the API for one session
+pub struct IceProvider {
+    client: reqwest::Client,
+    url: String,
+    access_token: String,
+    current: watch::Sender<IceConfiguration>,
+}
+
+impl IceProvider {
+    /// Fetch the servers for `session_id` from the API at `api_url`
+    pub async fn fetch(api_url: &str, access_token: &str, session_id: Uuid) -> Result<Self, ClientError> {
+        let client = reqwest::Client::new();
+        let url = format!("{}/api/sessions/{}/ice-servers", api_url.trim_end_matches('/'), session_id);
+        let config = request(&client, &url, access_token).await?;
+        info!(
+            "Got {} ICE server entries for session {} (policy {:?}, valid until {})",
+            config.ice_servers.len(),
+            session_id,
+            config.ice_transport_policy,
+            config.expires_at
+        );
+
+        let (current, _) = watch::channel(config);
+        Ok(Self {
+            client,
+            url,
+            access_token: access_token.to_string(),
+            current,
+        })
+    }
+
+    /// Latest servers
+    pub fn current(&self) -> IceConfiguration {
+        self.current.borrow().clone()
+    }
+
+    /// Be told whenever new servers are issued
+    pub fn subscribe(&self) -> watch::Receiver<IceConfiguration> {
+        self.current.subscribe()
+    }
+
+    /// Fetch new servers now
+    pub async fn refresh(&self) -> Result<IceConfiguration, ClientError> {
+        let config = request(&self.client, &self.url, &self.access_token).await?;
+        self.current.send_replace(config.clone());
+        Ok(config)
+    }
+
+    /// Refresh in the background before each list expires
+    ///
+    /// The task ends when the provider is dropped, or when refreshing keeps
+    /// failing until the current credentials have expired (e.g. the session
+    /// ended). Abort the handle to stop it sooner.
+    pub fn spawn_refresh(self: &Arc<Self>) -> JoinHandle<()> {
+        let provider = Arc::downgrade(self);
+        tokio::spawn(refresh_loop(provider))
+    }
+}
+
+async fn refresh_loop(provider: Weak<IceProvider>) {
+    let mut delay = match provider.upgrade() {
+        Some(provider) => refresh_delay(provider.current.borrow().expires_at, Utc::now()),
+        None => return,
+    };
+    loop {
+        tokio::time::sleep(delay).await;
+        let Some(provider) = provider.upgrade() else {
+            return;
+        };
+
+        delay = match provider.refresh().await {
+            Ok(config) => {
+                info!("Refreshed ICE servers, valid until {}", config.expires_at);
+                refresh_delay(config.expires_at, Utc::now())
+            }
+            Err(e) if provider.current.borrow().expires_at <= Utc::now() => {
+                warn!("Giving up refreshing expired ICE servers: {}", e);
+                return;
+            }
+            Err(e) => {
+                warn!("ICE server refresh failed, retrying: {}", e);
+                RETRY_DELAY
+            }
+        };
+    }
+}
+
+/// Time until servers expiring at `expires_at` should be replaced
+///
+/// Normally `REFRESH_MARGIN` ahead of expiry; short-lived credentials are
+/// replaced halfway through their remaining lifetime instead.
+pub fn refresh_delay(expires_at: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    let remaining = (expires_at - now).to_std().unwrap_or(Duration::ZERO);
+    let margin = REFRESH_MARGIN.min(remaining / 2);
+    (remaining - margin).max(MIN_REFRESH_DELAY)
+}
+
+async fn request(client: &reqwest::Client, url: &str, access_token: &str) -> Result<IceConfiguration, ClientError> {
+    let response = client
+        .get(url)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .map_err(|e| ClientError::TransportError(format!("ICE server request failed: {}", e)))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                ClientError::AuthenticationError(format!("Not allowed to get ICE servers ({})", status))
+            }
+            reqwest::StatusCode::SERVICE_UNAVAILABLE => {
+                ClientError::TransportError("Relay required but the server can't provide or enforce a TURN relay".to_string())
+            }
+            _ => ClientError::TransportError(format!("ICE server request failed ({})", status)),
+        });
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| ClientError::TransportError(format!("Invalid ICE server response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_delay() {
+        let now = Utc::now();
+        // Ahead of expiry by the margin
+        assert_eq!(refresh_delay(now + chrono::Duration::hours(1), now), Duration::from_secs(55 * 60));
+        // Halfway for short-lived credentials
+        assert_eq!(refresh_delay(now + chrono::Duration::seconds(60), now), Duration::from_secs(30));
+        // Already expired
+        assert_eq!(refresh_delay(now - chrono::Duration::seconds(5), now), MIN_REFRESH_DELAY);
+    }
+}
@@ -52,7 +52,7 @@ impl InstallationId {
     }
     
     /// Get the config directory path
-    fn get_config_dir() -> Result<PathBuf, String> {
+    pub(crate) fn get_config_dir() -> Result<PathBuf, String> {
         let config_dir = dirs::config_dir()
             .ok_or("Could not find config directory")?
             .join("GenXLink");
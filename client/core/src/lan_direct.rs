@@ -0,0 +1,925 @@
+//! Serverless LAN direct connect
+//!
+//! Peers found through mDNS are dialed directly over TCP, with no signaling
+//! server involved. Both ends prove possession of their device key (the
+//! dialer checks it against the key pinned from the peer's signed
+//! announcement) while agreeing on an X25519 session key. After that, every
+//! frame is encrypted with ChaCha20-Poly1305 and the usual
+//! `ConnectionRequest` / `ConnectionResponse` session handshake runs on top,
+//! so the host's session password and lockout policy apply as they do for
+//! internet sessions.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::{Mutex as SyncMutex, RwLock};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{aead, agreement, hkdf};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use genxlink_protocol::{
+    ConnectionRequest, ConnectionResponse, DeviceId, Message, MessagePayload, SessionId,
+    MAX_MESSAGE_SIZE, PROTOCOL_VERSION,
+};
+
+use crate::device_identity::{self, DeviceIdentity, PUBLIC_KEY_LEN};
+use crate::mdns::PeerInfo;
+use crate::p2p_discovery::P2PDiscovery;
+use crate::session_password::SessionPasswordManager;
+use crate::transport::Transport;
+use crate::ClientError;
+
+/// TCP port hosts listen on for direct LAN connections (advertised in the mDNS SRV record)
+pub const LAN_DIRECT_PORT: u16 = 47600;
+
+/// Time allowed for dialing plus the whole handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Domain separation for the handshake transcript
+const HANDSHAKE_CONTEXT: &[u8] = b"genxlink-lan-direct-v1";
+
+const NONCE_LEN: usize = 32;
+
+/// Handshake frames are small; anything larger is rejected before allocating
+const MAX_HANDSHAKE_FRAME: usize = 16 * 1024;
+
+/// Unauthenticated connections a host handles at once, from all addresses
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// Unauthenticated connections a host handles at once from one address
+const MAX_HANDSHAKES_PER_ADDRESS: usize = 4;
+
+/// This device, as presented to LAN peers
+#[derive(Clone)]
+pub struct LocalDevice {
+    pub identity: Arc<DeviceIdentity>,
+    pub device_id: DeviceId,
+    pub device_name: String,
+    pub capabilities: Vec<String>,
+}
+
+impl LocalDevice {
+    /// This device using the persistent device identity
+    pub fn new(device_id: DeviceId, device_name: String) -> Self {
+        Self {
+            identity: device_identity::get_device_identity(),
+            device_id,
+            device_name,
+            capabilities: vec!["screen-share".to_string(), "file-transfer".to_string()],
+        }
+    }
+}
+
+/// The authenticated remote end of a LAN connection
+#[derive(Debug, Clone)]
+pub struct RemoteDevice {
+    pub device_id: DeviceId,
+    pub device_name: String,
+    pub public_key: Vec<u8>,
+    pub address: SocketAddr,
+    pub capabilities: Vec<String>,
+}
+
+/// First handshake message from each side
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello {
+    device_id: DeviceId,
+    device_name: String,
+    public_key: Vec<u8>,
+    ephemeral_key: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+/// Signature over the handshake transcript
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyProof {
+    signature: Vec<u8>,
+}
+
+/// Which end of the handshake we are; each direction gets its own key
+#[derive(Debug, Clone, Copy)]
+enum Role {
+    Dialer,
+    Host,
+}
+
+impl Role {
+    fn label(self) -> &'static [u8] {
+        match self {
+            Role::Dialer => b"dialer",
+            Role::Host => b"host",
+        }
+    }
+
+    fn peer(self) -> Role {
+        match self {
+            Role::Dialer => Role::Host,
+            Role::Host => Role::Dialer,
+        }
+    }
+}
+
+/// An authenticated, encrypted connection to a LAN peer
+pub struct LanConnection {
+    stream: TcpStream,
+    sealing_key: aead::LessSafeKey,
+    opening_key: aead::LessSafeKey,
+    send_counter: u64,
+    receive_counter: u64,
+    session_id: SessionId,
+    sequence: u64,
+    remote: RemoteDevice,
+}
+
+impl LanConnection {
+    /// Session established by the handshake
+    pub fn session_id(&self) -> SessionId {
+        self.session_id
+    }
+
+    /// The authenticated peer
+    pub fn remote(&self) -> &RemoteDevice {
+        &self.remote
+    }
+
+    /// Send a message
+    pub async fn send(&mut self, message: &Message) -> Result<(), ClientError> {
+        let data = serde_json::to_vec(message)
+            .map_err(|e| ClientError::TransportError(format!("Failed to encode message: {}", e)))?;
+        self.send_frame(data).await
+    }
+
+    /// Send a payload in this session's envelope
+    pub async fn send_payload(&mut self, payload: MessagePayload) -> Result<(), ClientError> {
+        self.sequence += 1;
+        let message = Message {
+            session_id: self.session_id,
+            sequence: self.sequence,
+            payload,
+        };
+        self.send(&message).await
+    }
+
+    /// Receive the next message; `None` once the peer has closed the connection
+    pub async fn receive(&mut self) -> Result<Option<Message>, ClientError> {
+        let Some(data) = self.receive_frame().await? else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| ClientError::TransportError(format!("Invalid message from peer: {}", e)))
+    }
+
+    /// Close the connection
+    pub async fn close(&mut self) -> Result<(), ClientError> {
+        self.stream.shutdown().await.map_err(transport_error)
+    }
+
+    async fn send_frame(&mut self, mut data: Vec<u8>) -> Result<(), ClientError> {
+        let nonce = frame_nonce(self.send_counter);
+        self.send_counter += 1;
+        self.sealing_key
+            .seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut data)
+            .map_err(|_| ClientError::TransportError("Encryption failed".to_string()))?;
+        write_frame(&mut self.stream, &data).await
+    }
+
+    async fn receive_frame(&mut self) -> Result<Option<Vec<u8>>, ClientError> {
+        let Some(mut data) =
+            read_frame(&mut self.stream, MAX_MESSAGE_SIZE + aead::MAX_TAG_LEN).await?
+        else {
+            return Ok(None);
+        };
+
+        let nonce = frame_nonce(self.receive_counter);
+        self.receive_counter += 1;
+        let plaintext = self
+            .opening_key
+            .open_in_place(nonce, aead::Aad::empty(), &mut data)
+            .map_err(|_| ClientError::TransportError("Frame failed authentication".to_string()))?;
+        let len = plaintext.len();
+        data.truncate(len);
+        Ok(Some(data))
+    }
+}
+
+/// Dial a discovered peer and open a session with the host's session password
+pub async fn connect(
+    local: &LocalDevice,
+    peer: &PeerInfo,
+    password: &str,
+) -> Result<LanConnection, ClientError> {
+    let mut last_error = ClientError::TransportError(format!("{} has no known address", peer.device_id));
+
+    for ip in &peer.addresses {
+        let address = SocketAddr::new(*ip, peer.port);
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, dial(local, peer, address, password)).await {
+            Ok(Ok(connection)) => return Ok(connection),
+            // The host itself refused the session; other addresses reach the same host
+            Ok(Err(e @ ClientError::PermissionDenied(_))) => return Err(e),
+            Ok(Err(e)) => {
+                debug!("Direct connection to {} failed: {}", address, e);
+                last_error = e;
+            }
+            Err(_) => {
+                debug!("Direct connection to {} timed out", address);
+                last_error = ClientError::TransportError(format!("Timed out connecting to {}", address));
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+async fn dial(
+    local: &LocalDevice,
+    peer: &PeerInfo,
+    address: SocketAddr,
+    password: &str,
+) -> Result<LanConnection, ClientError> {
+    let mut stream = TcpStream::connect(address).await.map_err(transport_error)?;
+    stream.set_nodelay(true).map_err(transport_error)?;
+
+    let ephemeral = EphemeralKey::generate()?;
+    let hello = local_hello(local, &ephemeral)?;
+    let hello_bytes = encode(&hello)?;
+    write_frame(&mut stream, &hello_bytes).await?;
+
+    let host_hello_bytes = read_handshake_frame(&mut stream).await?;
+    let host_hello: Hello = decode(&host_hello_bytes)?;
+    let host_proof: KeyProof = decode(&read_handshake_frame(&mut stream).await?)?;
+
+    if host_hello.device_id != peer.device_id {
+        return Err(ClientError::AuthenticationError(format!(
+            "{} answered as {}",
+            address, host_hello.device_id
+        )));
+    }
+    if host_hello.public_key != peer.public_key {
+        return Err(ClientError::AuthenticationError(format!(
+            "{} presented key {}, expected the pinned key {}",
+            peer.device_id,
+            device_identity::fingerprint(&host_hello.public_key),
+            peer.fingerprint()
+        )));
+    }
+
+    let transcript = transcript(&hello_bytes, &host_hello_bytes);
+    verify_proof(&host_hello, Role::Host, &transcript, &host_proof)?;
+    let proof = KeyProof {
+        signature: local.identity.sign(&proof_message(Role::Dialer, &transcript)),
+    };
+    write_frame(&mut stream, &encode(&proof)?).await?;
+
+    let remote = RemoteDevice {
+        device_id: host_hello.device_id.clone(),
+        device_name: host_hello.device_name.clone(),
+        public_key: host_hello.public_key.clone(),
+        address,
+        capabilities: Vec::new(),
+    };
+    let mut connection = secure(stream, Role::Dialer, ephemeral, &host_hello, &transcript, remote)?;
+
+    // Same session handshake as internet sessions
+    connection
+        .send_payload(MessagePayload::ConnectionRequest(ConnectionRequest {
+            device_id: local.device_id.clone(),
+            password: password.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+            capabilities: local.capabilities.clone(),
+        }))
+        .await?;
+
+    let reply = connection
+        .receive()
+        .await?
+        .ok_or_else(|| ClientError::TransportError("Host closed the connection".to_string()))?;
+    let MessagePayload::ConnectionResponse(response) = reply.payload else {
+        return Err(ClientError::TransportError("Expected a connection response".to_string()));
+    };
+    if !response.accepted {
+        return Err(ClientError::PermissionDenied(
+            response.reason.unwrap_or_else(|| "Connection rejected".to_string()),
+        ));
+    }
+
+    connection.session_id = reply.session_id;
+    connection.remote.capabilities = response.server_capabilities;
+    info!("Connected directly to {} at {}", connection.remote.device_id, address);
+    Ok(connection)
+}
+
+/// Listener accepting direct LAN connections
+pub struct LanDirectHost {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+struct HostState {
+    local: LocalDevice,
+    passwords: Arc<Mutex<SessionPasswordManager>>,
+    /// Keys of devices that have authenticated before, pinned on first use
+    pinned_keys: RwLock<HashMap<DeviceId, Vec<u8>>>,
+}
+
+/// Handshakes in progress per address, so peers can't tie a host up with
+/// connections that never finish authenticating
+#[derive(Default)]
+pub(crate) struct HandshakeLimits {
+    pending: SyncMutex<HashMap<IpAddr, usize>>,
+}
+
+/// A handshake slot, given back when dropped
+pub(crate) struct HandshakeSlot {
+    limits: Arc<HandshakeLimits>,
+    address: IpAddr,
+}
+
+impl HandshakeLimits {
+    /// Take a slot for a connection from `address`; `None` when the host or
+    /// that address is at its limit
+    pub(crate) fn try_acquire(self: &Arc<Self>, address: IpAddr) -> Option<HandshakeSlot> {
+        let mut pending = self.pending.lock();
+        if pending.values().sum::<usize>() >= MAX_PENDING_HANDSHAKES {
+            return None;
+        }
+        let count = pending.entry(address).or_insert(0);
+        if *count >= MAX_HANDSHAKES_PER_ADDRESS {
+            return None;
+        }
+        *count += 1;
+        Some(HandshakeSlot { limits: self.clone(), address })
+    }
+}
+
+impl Drop for HandshakeSlot {
+    fn drop(&mut self) {
+        let mut pending = self.limits.pending.lock();
+        if let Some(count) = pending.get_mut(&self.address) {
+            *count -= 1;
+            if *count == 0 {
+                pending.remove(&self.address);
+            }
+        }
+    }
+}
+
+impl LanDirectHost {
+    /// Listen on `address`; authenticated connections arrive on the returned channel
+    pub async fn start(
+        address: SocketAddr,
+        local: LocalDevice,
+        passwords: Arc<Mutex<SessionPasswordManager>>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<LanConnection>), ClientError> {
+        let listener = TcpListener::bind(address).await.map_err(transport_error)?;
+        let local_addr = listener.local_addr().map_err(transport_error)?;
+        let (connection_tx, connection_rx) = mpsc::unbounded_channel();
+
+        let state = Arc::new(HostState {
+            local,
+            passwords,
+            pinned_keys: RwLock::new(HashMap::new()),
+        });
+
+        info!("Accepting direct LAN connections on {}", local_addr);
+
+        let limits = Arc::new(HandshakeLimits::default());
+        let task = tokio::spawn(async move {
+            loop {
+                let (stream, peer_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept LAN connection: {}", e);
+                        continue;
+                    }
+                };
+                let Some(slot) = limits.try_acquire(peer_addr.ip()) else {
+                    debug!("Dropping LAN connection from {}: too many handshakes in progress", peer_addr);
+                    continue;
+                };
+
+                let state = state.clone();
+                let connection_tx = connection_tx.clone();
+                tokio::spawn(async move {
+                    let _slot = slot;
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, state.handshake(stream, peer_addr)).await {
+                        Ok(Ok(connection)) => {
+                            let _ = connection_tx.send(connection);
+                        }
+                        Ok(Err(e)) => warn!("Rejected LAN connection from {}: {}", peer_addr, e),
+                        Err(_) => warn!("LAN handshake with {} timed out", peer_addr),
+                    }
+                });
+            }
+        });
+
+        Ok((Self { local_addr, task }, connection_rx))
+    }
+
+    /// Address the listener is bound to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stop accepting connections
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for LanDirectHost {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl HostState {
+    async fn handshake(&self, mut stream: TcpStream, address: SocketAddr) -> Result<LanConnection, ClientError> {
+        stream.set_nodelay(true).map_err(transport_error)?;
+
+        let dialer_hello_bytes = read_handshake_frame(&mut stream).await?;
+        let dialer_hello: Hello = decode(&dialer_hello_bytes)?;
+        validate_hello(&dialer_hello)?;
+
+        if let Some(pinned) = self.pinned_keys.read().get(&dialer_hello.device_id) {
+            if *pinned != dialer_hello.public_key {
+                return Err(ClientError::AuthenticationError(format!(
+                    "{} presented key {} which does not match its pinned key",
+                    dialer_hello.device_id,
+                    device_identity::fingerprint(&dialer_hello.public_key)
+                )));
+            }
+        }
+
+        let ephemeral = EphemeralKey::generate()?;
+        let hello_bytes = encode(&local_hello(&self.local, &ephemeral)?)?;
+        let transcript = transcript(&dialer_hello_bytes, &hello_bytes);
+        let proof = KeyProof {
+            signature: self.local.identity.sign(&proof_message(Role::Host, &transcript)),
+        };
+        write_frame(&mut stream, &hello_bytes).await?;
+        write_frame(&mut stream, &encode(&proof)?).await?;
+
+        let dialer_proof: KeyProof = decode(&read_handshake_frame(&mut stream).await?)?;
+        verify_proof(&dialer_hello, Role::Dialer, &transcript, &dialer_proof)?;
+
+        let remote = RemoteDevice {
+            device_id: dialer_hello.device_id.clone(),
+            device_name: dialer_hello.device_name.clone(),
+            public_key: dialer_hello.public_key.clone(),
+            address,
+            capabilities: Vec::new(),
+        };
+        let mut connection = secure(stream, Role::Host, ephemeral, &dialer_hello, &transcript, remote)?;
+
+        let request = connection
+            .receive()
+            .await?
+            .ok_or_else(|| ClientError::TransportError("Dialer closed the connection".to_string()))?;
+        let session_id = request.session_id;
+        let MessagePayload::ConnectionRequest(request) = request.payload else {
+            return Err(ClientError::TransportError("Expected a connection request".to_string()));
+        };
+
+        let verdict = self.check_request(&request, &dialer_hello.device_id, address).await;
+        connection.session_id = session_id;
+        connection
+            .send_payload(MessagePayload::ConnectionResponse(ConnectionResponse {
+                accepted: verdict.is_ok(),
+                reason: verdict.as_ref().err().cloned(),
+                server_capabilities: self.local.capabilities.clone(),
+            }))
+            .await?;
+        verdict.map_err(ClientError::PermissionDenied)?;
+
+        self.pinned_keys
+            .write()
+            .entry(dialer_hello.device_id.clone())
+            .or_insert(dialer_hello.public_key);
+        connection.remote.capabilities = request.capabilities;
+
+        info!(
+            "Accepted direct LAN session {} from {} ({})",
+            connection.session_id, connection.remote.device_id, address
+        );
+        Ok(connection)
+    }
+
+    async fn check_request(&self, request: &ConnectionRequest, authenticated: &DeviceId, address: SocketAddr) -> Result<(), String> {
+        if request.device_id != *authenticated {
+            return Err("Device ID does not match the authenticated key".to_string());
+        }
+        authorize_request(&self.passwords, request, address.ip()).await
+    }
+}
+
+/// Check a session request against the protocol version and the host's
+/// session password; the error is the reason sent back to the dialer
+///
+/// Wrong passwords count against the dialer's `address`, not the device ID
+/// it claims, which it could change with every guess.
+pub(crate) async fn authorize_request(
+    passwords: &Mutex<SessionPasswordManager>,
+    request: &ConnectionRequest,
+    address: IpAddr,
+) -> Result<(), String> {
+    if request.protocol_version != PROTOCOL_VERSION {
+        return Err(format!(
+            "Protocol version {} is not supported (expected {})",
+            request.protocol_version, PROTOCOL_VERSION
+        ));
+    }
+
+    match passwords.lock().await.verify_password_from(&address.to_string(), &request.password) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Invalid password".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Transport over a direct LAN connection to an mDNS-discovered peer
+pub struct LanDirectTransport {
+    local: LocalDevice,
+    discovery: P2PDiscovery,
+    password: String,
+    connection: Option<LanConnection>,
+}
+
+impl LanDirectTransport {
+    pub fn new(local: LocalDevice, discovery: P2PDiscovery, password: String) -> Self {
+        Self {
+            local,
+            discovery,
+            password,
+            connection: None,
+        }
+    }
+
+    /// Use an already established connection (e.g. one accepted by [`LanDirectHost`])
+    pub fn from_connection(local: LocalDevice, discovery: P2PDiscovery, connection: LanConnection) -> Self {
+        Self {
+            local,
+            discovery,
+            password: String::new(),
+            connection: Some(connection),
+        }
+    }
+
+    fn connection(&mut self) -> Result<&mut LanConnection, ClientError> {
+        self.connection
+            .as_mut()
+            .ok_or_else(|| ClientError::TransportError("Not connected".to_string()))
+    }
+}
+
+#[async_trait]
+impl Transport for LanDirectTransport {
+    async fn connect(&mut self, remote_id: &DeviceId) -> Result<SessionId, ClientError> {
+        let peer = self.discovery.get_peer(remote_id).await.ok_or_else(|| {
+            ClientError::TransportError(format!("{} has not been discovered on the LAN", remote_id))
+        })?;
+
+        let connection = connect(&self.local, &peer, &self.password).await?;
+        let session_id = connection.session_id();
+        self.connection = Some(connection);
+        Ok(session_id)
+    }
+
+    async fn send(&mut self, message: &Message) -> Result<(), ClientError> {
+        self.connection()?.send(message).await
+    }
+
+    async fn receive(&mut self) -> Result<Option<Message>, ClientError> {
+        self.connection()?.receive().await
+    }
+
+    async fn disconnect(&mut self) -> Result<(), ClientError> {
+        if let Some(mut connection) = self.connection.take() {
+            connection.close().await?;
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+}
+
+/// Ephemeral X25519 key for one handshake
+struct EphemeralKey {
+    private_key: agreement::EphemeralPrivateKey,
+    public_key: agreement::PublicKey,
+}
+
+impl EphemeralKey {
+    fn generate() -> Result<Self, ClientError> {
+        let private_key = agreement::EphemeralPrivateKey::generate(&agreement::X25519, &SystemRandom::new())
+            .map_err(|_| ClientError::TransportError("Failed to generate session key".to_string()))?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| ClientError::TransportError("Failed to generate session key".to_string()))?;
+        Ok(Self { private_key, public_key })
+    }
+}
+
+fn local_hello(local: &LocalDevice, ephemeral: &EphemeralKey) -> Result<Hello, ClientError> {
+    let mut nonce = vec![0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| ClientError::TransportError("Failed to generate nonce".to_string()))?;
+
+    Ok(Hello {
+        device_id: local.device_id.clone(),
+        device_name: local.device_name.clone(),
+        public_key: local.identity.public_key().to_vec(),
+        ephemeral_key: ephemeral.public_key.as_ref().to_vec(),
+        nonce,
+    })
+}
+
+fn validate_hello(hello: &Hello) -> Result<(), ClientError> {
+    if hello.public_key.len() != PUBLIC_KEY_LEN || hello.ephemeral_key.len() != 32 || hello.nonce.len() != NONCE_LEN {
+        return Err(ClientError::TransportError("Malformed handshake".to_string()));
+    }
+    Ok(())
+}
+
+/// Hash of both hello frames, exactly as sent
+fn transcript(dialer_hello: &[u8], host_hello: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(HANDSHAKE_CONTEXT);
+    for frame in [dialer_hello, host_hello] {
+        hasher.update((frame.len() as u32).to_be_bytes());
+        hasher.update(frame);
+    }
+    hasher.finalize().into()
+}
+
+fn proof_message(role: Role, transcript: &[u8; 32]) -> Vec<u8> {
+    [HANDSHAKE_CONTEXT, role.label(), transcript].concat()
+}
+
+fn verify_proof(hello: &Hello, role: Role, transcript: &[u8; 32], proof: &KeyProof) -> Result<(), ClientError> {
+    validate_hello(hello)?;
+    if device_identity::verify_signature(&hello.public_key, &proof_message(role, transcript), &proof.signature) {
+        Ok(())
+    } else {
+        Err(ClientError::AuthenticationError(format!(
+            "{} failed to prove possession of its device key",
+            hello.device_id
+        )))
+    }
+}
+
+/// Derive the per-direction keys and wrap the stream
+fn secure(
+    stream: TcpStream,
+    role: Role,
+    ephemeral: EphemeralKey,
+    peer_hello: &Hello,
+    transcript: &[u8; 32],
+    remote: RemoteDevice,
+) -> Result<LanConnection, ClientError> {
+    let peer_key = agreement::UnparsedPublicKey::new(&agreement::X25519, &peer_hello.ephemeral_key);
+    let (sealing_key, opening_key) = agreement::agree_ephemeral(ephemeral.private_key, &peer_key, |secret| {
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, transcript).extract(secret);
+        let key = |role: Role| -> Result<aead::LessSafeKey, ring::error::Unspecified> {
+            let info = [role.label()];
+            let okm = prk.expand(&info, &aead::CHACHA20_POLY1305)?;
+            Ok(aead::LessSafeKey::new(aead::UnboundKey::from(okm)))
+        };
+        Ok::<_, ring::error::Unspecified>((key(role)?, key(role.peer())?))
+    })
+    .and_then(|keys| keys)
+    .map_err(|_| ClientError::AuthenticationError("Key agreement failed".to_string()))?;
+
+    Ok(LanConnection {
+        stream,
+        sealing_key,
+        opening_key,
+        send_counter: 0,
+        receive_counter: 0,
+        session_id: SessionId::new(),
+        sequence: 0,
+        remote,
+    })
+}
+
+fn frame_nonce(counter: u64) -> aead::Nonce {
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    aead::Nonce::assume_unique_for_key(nonce)
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, ClientError> {
+    serde_json::to_vec(value).map_err(|e| ClientError::TransportError(format!("Failed to encode handshake: {}", e)))
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, ClientError> {
+    serde_json::from_slice(data).map_err(|e| ClientError::TransportError(format!("Malformed handshake: {}", e)))
+}
+
+async fn read_handshake_frame<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, ClientError> {
+    read_frame(stream, MAX_HANDSHAKE_FRAME)
+        .await?
+        .ok_or_else(|| ClientError::TransportError("Connection closed during handshake".to_string()))
+}
+
+/// Write a length-prefixed frame
+pub(crate) async fn write_frame<S: AsyncWrite + Unpin>(stream: &mut S, data: &[u8]) -> Result<(), ClientError> {
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await.map_err(transport_error)?;
+    stream.write_all(data).await.map_err(transport_error)?;
+    stream.flush().await.map_err(transport_error)
+}
+
+/// Read a length-prefixed frame; `None` on a clean close between frames
+pub(crate) async fn read_frame<S: AsyncRead + Unpin>(stream: &mut S, max_len: usize) -> Result<Option<Vec<u8>>, ClientError> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(transport_error(e)),
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len {
+        return Err(ClientError::TransportError(format!("Frame of {} bytes exceeds the limit", len)));
+    }
+
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await.map_err(transport_error)?;
+    Ok(Some(data))
+}
+
+pub(crate) fn transport_error(e: std::io::Error) -> ClientError {
+    ClientError::TransportError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genxlink_protocol::messages::KeyModifiers;
+    use genxlink_protocol::{ClipboardData, KeyboardEvent};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn device(name: &str) -> LocalDevice {
+        LocalDevice {
+            identity: Arc::new(DeviceIdentity::generate().unwrap()),
+            device_id: DeviceId::new(),
+            device_name: name.to_string(),
+            capabilities: vec!["screen-share".to_string()],
+        }
+    }
+
+    fn discovered(host: &LocalDevice, address: SocketAddr) -> PeerInfo {
+        PeerInfo {
+            device_id: host.device_id.clone(),
+            device_name: host.device_name.clone(),
+            ip_address: address.ip().to_string(),
+            addresses: vec![address.ip()],
+            port: address.port(),
+            last_seen: std::time::SystemTime::now(),
+            capabilities: host.capabilities.clone(),
+            public_key: host.identity.public_key().to_vec(),
+        }
+    }
+
+    async fn start_host(host: &LocalDevice) -> (LanDirectHost, mpsc::UnboundedReceiver<LanConnection>, String) {
+        let mut passwords = SessionPasswordManager::new();
+        let password = passwords.generate_password();
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+        let (listener, incoming) = LanDirectHost::start(loopback, host.clone(), Arc::new(Mutex::new(passwords)))
+            .await
+            .unwrap();
+        (listener, incoming, password)
+    }
+
+    #[tokio::test]
+    async fn test_direct_session_round_trip() {
+        let host = device("Host");
+        let viewer = device("Viewer");
+        let (listener, mut incoming, password) = start_host(&host).await;
+
+        let peer = discovered(&host, listener.local_addr());
+        let mut dialer = connect(&viewer, &peer, &password).await.unwrap();
+        let mut accepted = incoming.recv().await.unwrap();
+
+        assert_eq!(dialer.session_id(), accepted.session_id());
+        assert_eq!(dialer.remote().device_id, host.device_id);
+        assert_eq!(dialer.remote().capabilities, host.capabilities);
+        assert_eq!(accepted.remote().device_id, viewer.device_id);
+        assert_eq!(accepted.remote().public_key, viewer.identity.public_key());
+
+        dialer
+            .send_payload(MessagePayload::KeyboardEvent(KeyboardEvent {
+                key_code: 65,
+                scan_code: 30,
+                pressed: true,
+                modifiers: KeyModifiers { ctrl: false, alt: false, shift: false, meta: false },
+            }))
+            .await
+            .unwrap();
+        let received = accepted.receive().await.unwrap().unwrap();
+        assert!(matches!(received.payload, MessagePayload::KeyboardEvent(ref e) if e.key_code == 65));
+
+        accepted
+            .send_payload(MessagePayload::ClipboardSync(ClipboardData {
+                content_type: "text/plain".to_string(),
+                data: b"copied".to_vec(),
+            }))
+            .await
+            .unwrap();
+        let received = dialer.receive().await.unwrap().unwrap();
+        assert!(matches!(received.payload, MessagePayload::ClipboardSync(ref c) if c.data == b"copied"));
+
+        dialer.close().await.unwrap();
+        assert!(accepted.receive().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_rejected() {
+        let host = device("Host");
+        let viewer = device("Viewer");
+        let (listener, _incoming, _password) = start_host(&host).await;
+
+        let peer = discovered(&host, listener.local_addr());
+        let result = connect(&viewer, &peer, "wrong").await;
+        assert!(matches!(result, Err(ClientError::PermissionDenied(ref reason)) if reason == "Invalid password"));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_passwords_count_against_the_address() {
+        let host = device("Host");
+        let (listener, _incoming, password) = start_host(&host).await;
+        let peer = discovered(&host, listener.local_addr());
+
+        assert!(connect(&device("Guesser"), &peer, "wrong").await.is_err());
+        // A fresh device ID from the same address is still backed off
+        let result = connect(&device("Guesser again"), &peer, &password).await;
+        assert!(matches!(result, Err(ClientError::PermissionDenied(ref reason)) if reason != "Invalid password"));
+    }
+
+    #[test]
+    fn test_pending_handshakes_are_capped() {
+        let limits = Arc::new(HandshakeLimits::default());
+        let address = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+        let mut slots: Vec<_> = (0..MAX_HANDSHAKES_PER_ADDRESS)
+            .map(|_| limits.try_acquire(address).unwrap())
+            .collect();
+        assert!(limits.try_acquire(address).is_none());
+        slots.pop();
+        slots.push(limits.try_acquire(address).unwrap());
+
+        // Other addresses have their own share, up to the host's total
+        for i in 0..(MAX_PENDING_HANDSHAKES - MAX_HANDSHAKES_PER_ADDRESS) {
+            slots.push(limits.try_acquire(IpAddr::V4(Ipv4Addr::new(10, 0, (i / 4) as u8, 1))).unwrap());
+        }
+        assert!(limits.try_acquire(IpAddr::V4(Ipv4Addr::new(10, 1, 0, 1))).is_none());
+
+        drop(slots);
+        assert!(limits.pending.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_host_must_hold_pinned_key() {
+        let host = device("Host");
+        let viewer = device("Viewer");
+        let (listener, _incoming, password) = start_host(&host).await;
+
+        // The dialer expects a different key than the one the host holds
+        let mut peer = discovered(&host, listener.local_addr());
+        peer.public_key = DeviceIdentity::generate().unwrap().public_key().to_vec();
+
+        let result = connect(&viewer, &peer, &password).await;
+        assert!(matches!(result, Err(ClientError::AuthenticationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dialer_key_is_pinned_after_first_session() {
+        let host = device("Host");
+        let viewer = device("Viewer");
+        let (listener, mut incoming, password) = start_host(&host).await;
+        let peer = discovered(&host, listener.local_addr());
+
+        connect(&viewer, &peer, &password).await.unwrap();
+        incoming.recv().await.unwrap();
+
+        // Same device ID, different key
+        let impostor = LocalDevice {
+            identity: Arc::new(DeviceIdentity::generate().unwrap()),
+            ..viewer.clone()
+        };
+        assert!(connect(&impostor, &peer, &password).await.is_err());
+        assert!(connect(&viewer, &peer, &password).await.is_ok());
+    }
+}
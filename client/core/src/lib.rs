@@ -10,12 +10,14 @@ pub mod remote_control;
 pub mod control_channel;
 pub mod file_transfer;
 pub mod session_password;
+pub mod brute_force;
 pub mod multi_monitor;
 pub mod clipboard;
 pub mod session_history;
 pub mod chat;
 pub mod hardware_encoder;
 pub mod adaptive_bitrate;
+pub mod congestion_control;
 pub mod permission_profiles;
 pub mod audio_streaming;
 pub mod localization;
@@ -23,19 +25,31 @@ pub mod theme;
 pub mod zero_setup;
 pub mod gst_tunnel;
 pub mod lan_discovery;
+pub mod mdns;
+pub mod lan_direct;
+pub mod quic_transport;
+pub mod network_diagnostics;
 pub mod transport;
 pub mod streaming;
+pub mod loss_recovery;
 pub mod pipeline;
 pub mod performance;
 pub mod performance_optimizer;
+pub mod quality_monitor;
 pub mod webrtc;
+pub mod ice_provisioning;
 pub mod signaling_client;
+pub mod session_resume;
 pub mod screen_capture;
 pub mod video_encoder;
 pub mod screen_streamer;
 pub mod webrtc_session;
 pub mod input_injection;
 pub mod remote_control_manager;
+pub mod host_control;
+pub mod system_actions;
+pub mod system_info;
+pub mod annotation;
 pub mod audio_capture;
 pub mod audio_playback;
 pub mod audio_stream_manager;
@@ -54,6 +68,7 @@ pub mod p2p_discovery;
 pub mod p2p_signaling;
 pub mod p2p_integration;
 pub mod installation_id;
+pub mod device_identity;
 pub mod connection_id;
 pub mod connection_manager;
 
@@ -80,6 +95,14 @@ pub enum ClientError {
     #[error("Transport error: {0}")]
     TransportError(String),
     
+    #[error("Signaling error ({code}): {message}")]
+    SignalingError {
+        code: genxlink_protocol::SignalingErrorCode,
+        message: String,
+        /// Seconds to wait before retrying, for rate-limited requests
+        retry_after_secs: Option<u64>,
+    },
+    
     #[error("Clipboard error: {0}")]
     ClipboardError(String),
     
@@ -98,6 +121,9 @@ pub enum ClientError {
     #[error("Invalid input: {0}")]
     InvalidInput(String),
     
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    
     #[error("Not supported on this platform")]
     PlatformNotSupported,
 }
@@ -0,0 +1,527 @@
+//! Loss recovery for the video stream
+//!
+//! Three mechanisms, from cheapest to most expensive for the viewer:
+//!
+//! - Forward error correction: after each frame the host sends ULPFEC
+//!   packets (RFC 5109) that XOR groups of the frame's packets, so the
+//!   viewer can rebuild one lost packet per group without a round trip. The
+//!   share of FEC packets follows the loss the viewer reports.
+//! - Retransmission: sent packets are kept for a short while and resent
+//!   when the viewer NACKs them (RFC 4585).
+//! - Keyframes: when neither helped, the viewer sends a PLI or FIR and the
+//!   encoder starts over with a keyframe.
+//!
+//! FEC packets go out on a stream of their own, negotiated as
+//! `video/ulpfec` and without RED encapsulation, so they have their own SSRC
+//! and sequence numbers; the base sequence number and mask in each FEC
+//! header refer to the media stream.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use webrtc::rtp::header::Header;
+use webrtc::rtp::packet::Packet;
+use webrtc::util::{Marshal, Unmarshal};
+
+use crate::ClientError;
+
+/// MIME type of the FEC stream
+pub const MIME_TYPE_ULPFEC: &str = "video/ulpfec";
+
+/// Payload type `MediaEngine::register_default_codecs` gives ULPFEC
+///
+/// Only a default: a track binding writes the negotiated one.
+pub const ULPFEC_PAYLOAD_TYPE: u8 = 116;
+
+/// Packets kept for retransmission
+pub const RETRANSMISSION_BUFFER_SIZE: usize = 1024;
+
+/// Packets older than this are not worth resending
+const MAX_RETRANSMISSION_AGE: Duration = Duration::from_secs(1);
+
+/// Repeated NACKs for a packet within this time are answered once
+const MIN_RETRANSMISSION_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Loss fraction below which no FEC is sent
+const MIN_FEC_LOSS: f32 = 0.01;
+
+/// FEC packets per media packet, at most
+const MAX_FEC_OVERHEAD: f32 = 0.5;
+
+/// Media packets one FEC packet can cover (the long ULP mask)
+const MAX_FEC_GROUP: usize = 48;
+
+/// ULPFEC header, and the level 0 header with a 16 or 48 bit mask
+const FEC_HEADER_SIZE: usize = 10;
+const ULP_HEADER_SIZE_SHORT: usize = 4;
+const ULP_HEADER_SIZE_LONG: usize = 8;
+
+const RTP_HEADER_SIZE: usize = 12;
+
+/// FEC packets kept at the viewer waiting for a recoverable loss
+const MAX_PENDING_FEC: usize = 64;
+
+struct SentPacket {
+    packet: Packet,
+    sent_at: Instant,
+    resent_at: Option<Instant>,
+}
+
+/// Recently sent packets, for answering NACKs
+pub struct RetransmissionBuffer {
+    packets: HashMap<u16, SentPacket>,
+    order: VecDeque<u16>,
+    capacity: usize,
+}
+
+impl RetransmissionBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            packets: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Keep `packet`, sent at `now`
+    pub fn insert(&mut self, packet: Packet, now: Instant) {
+        let sequence_number = packet.header.sequence_number;
+        let sent = SentPacket {
+            packet,
+            sent_at: now,
+            resent_at: None,
+        };
+        if self.packets.insert(sequence_number, sent).is_none() {
+            self.order.push_back(sequence_number);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.packets.remove(&oldest);
+            }
+        }
+    }
+
+    /// Packets to resend for `nack`
+    ///
+    /// Packets no longer kept, too old, or resent moments ago are skipped.
+    pub fn on_nack(&mut self, nack: &TransportLayerNack, now: Instant) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        for sequence_number in nack.nacks.iter().flat_map(|pair| pair.packet_list()) {
+            let Some(sent) = self.packets.get_mut(&sequence_number) else {
+                continue;
+            };
+            if now.saturating_duration_since(sent.sent_at) > MAX_RETRANSMISSION_AGE {
+                continue;
+            }
+            if sent
+                .resent_at
+                .is_some_and(|at| now.saturating_duration_since(at) < MIN_RETRANSMISSION_INTERVAL)
+            {
+                continue;
+            }
+            sent.resent_at = Some(now);
+            packets.push(sent.packet.clone());
+        }
+        packets
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
+
+impl Default for RetransmissionBuffer {
+    fn default() -> Self {
+        Self::new(RETRANSMISSION_BUFFER_SIZE)
+    }
+}
+
+/// FEC packets per media packet for a loss fraction
+///
+/// None below 1% loss; above, about two and a half times the loss, so one
+/// lost packet per group is usually all there is to recover.
+pub fn fec_overhead_for_loss(loss: f32) -> f32 {
+    if loss < MIN_FEC_LOSS {
+        0.0
+    } else {
+        (loss * 2.5).clamp(0.1, MAX_FEC_OVERHEAD)
+    }
+}
+
+/// Generates ULPFEC payloads protecting each frame's packets
+pub struct FecEncoder {
+    overhead: f32,
+}
+
+impl FecEncoder {
+    pub fn new() -> Self {
+        Self { overhead: 0.0 }
+    }
+
+    /// Adapt the overhead to the loss fraction the viewer reports
+    pub fn set_loss_rate(&mut self, loss: f32) {
+        self.overhead = fec_overhead_for_loss(loss);
+    }
+
+    /// FEC packets per media packet
+    pub fn overhead(&self) -> f32 {
+        self.overhead
+    }
+
+    /// FEC payloads protecting `media`, one frame's packets in sequence order
+    ///
+    /// FEC packet `i` of a group covers every media packet whose index is
+    /// `i` modulo the number of FEC packets, so a burst of consecutive
+    /// losses spreads over several FEC packets.
+    pub fn protect(&self, media: &[Packet]) -> Result<Vec<Bytes>, ClientError> {
+        if self.overhead <= 0.0 || media.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut payloads = Vec::new();
+        for group in media.chunks(MAX_FEC_GROUP) {
+            let marshaled = group
+                .iter()
+                .map(|packet| {
+                    packet
+                        .marshal()
+                        .map_err(|e| ClientError::StreamingError(format!("Failed to marshal RTP packet: {}", e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let base = group[0].header.sequence_number;
+            let fec_count = ((group.len() as f32 * self.overhead).ceil() as usize).clamp(1, group.len());
+
+            for i in 0..fec_count {
+                let protected: Vec<_> = (i..group.len())
+                    .step_by(fec_count)
+                    .map(|index| (group[index].header.sequence_number.wrapping_sub(base), &marshaled[index]))
+                    .collect();
+                payloads.push(fec_payload(base, &protected));
+            }
+        }
+        Ok(payloads)
+    }
+}
+
+impl Default for FecEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// XOR of the protected packets in the ULPFEC format
+///
+/// `protected` holds each packet's offset from `base` and its bytes.
+fn fec_payload(base: u16, protected: &[(u16, &Bytes)]) -> Bytes {
+    let long_mask = protected.iter().any(|(offset, _)| *offset >= 16);
+    let protection_length = protected
+        .iter()
+        .map(|(_, packet)| packet.len() - RTP_HEADER_SIZE)
+        .max()
+        .unwrap_or(0);
+
+    let mut header = [0u8; 8];
+    let mut length_recovery = 0u16;
+    let mut payload = vec![0u8; protection_length];
+    let mut mask = 0u64;
+    for (offset, packet) in protected {
+        for (recovery, byte) in header.iter_mut().zip(packet.iter()) {
+            *recovery ^= byte;
+        }
+        length_recovery ^= (packet.len() - RTP_HEADER_SIZE) as u16;
+        for (recovery, byte) in payload.iter_mut().zip(&packet[RTP_HEADER_SIZE..]) {
+            *recovery ^= byte;
+        }
+        mask |= 1 << (47 - offset);
+    }
+
+    let ulp_header_size = if long_mask { ULP_HEADER_SIZE_LONG } else { ULP_HEADER_SIZE_SHORT };
+    let mut fec = BytesMut::with_capacity(FEC_HEADER_SIZE + ulp_header_size + protection_length);
+    // E = 0, L, then the P, X and CC recovery bits
+    fec.extend_from_slice(&[((long_mask as u8) << 6) | (header[0] & 0x3f), header[1]]);
+    fec.extend_from_slice(&base.to_be_bytes());
+    fec.extend_from_slice(&header[4..8]);
+    fec.extend_from_slice(&length_recovery.to_be_bytes());
+    fec.extend_from_slice(&(protection_length as u16).to_be_bytes());
+    let mask_bytes = mask.to_be_bytes();
+    fec.extend_from_slice(if long_mask { &mask_bytes[2..8] } else { &mask_bytes[2..4] });
+    fec.extend_from_slice(&payload);
+    fec.freeze()
+}
+
+/// A received FEC packet, parsed
+struct FecPacket {
+    ssrc: u32,
+    protected: Vec<u16>,
+    header_recovery: [u8; 8],
+    length_recovery: u16,
+    payload: Bytes,
+}
+
+impl FecPacket {
+    fn parse(packet: &Packet) -> Option<Self> {
+        let fec = &packet.payload;
+        if fec.len() < FEC_HEADER_SIZE + ULP_HEADER_SIZE_SHORT {
+            return None;
+        }
+        let long_mask = fec[0] & 0x40 != 0;
+        let ulp_header_size = if long_mask { ULP_HEADER_SIZE_LONG } else { ULP_HEADER_SIZE_SHORT };
+        let payload_start = FEC_HEADER_SIZE + ulp_header_size;
+        if fec.len() < payload_start {
+            return None;
+        }
+
+        let base = u16::from_be_bytes([fec[2], fec[3]]);
+        let mut mask_bytes = [0u8; 8];
+        mask_bytes[2..2 + ulp_header_size - 2].copy_from_slice(&fec[FEC_HEADER_SIZE + 2..payload_start]);
+        let mask = u64::from_be_bytes(mask_bytes);
+        let protected = (0..48u16)
+            .filter(|offset| mask & (1 << (47 - offset)) != 0)
+            .map(|offset| base.wrapping_add(offset))
+            .collect();
+
+        Some(Self {
+            ssrc: packet.header.ssrc,
+            protected,
+            header_recovery: [fec[0], fec[1], 0, 0, fec[4], fec[5], fec[6], fec[7]],
+            length_recovery: u16::from_be_bytes([fec[8], fec[9]]),
+            payload: packet.payload.slice(payload_start..),
+        })
+    }
+}
+
+/// Rebuilds lost media packets from ULPFEC packets, on the viewer side
+pub struct FecDecoder {
+    media: HashMap<u16, Bytes>,
+    order: VecDeque<u16>,
+    pending: VecDeque<FecPacket>,
+    media_ssrc: Option<u32>,
+}
+
+impl FecDecoder {
+    pub fn new() -> Self {
+        Self {
+            media: HashMap::new(),
+            order: VecDeque::new(),
+            pending: VecDeque::new(),
+            media_ssrc: None,
+        }
+    }
+
+    /// Account for a received media packet
+    ///
+    /// Returns the packets it made recoverable.
+    pub fn on_media(&mut self, packet: &Packet) -> Vec<Packet> {
+        self.media_ssrc = Some(packet.header.ssrc);
+        if let Ok(bytes) = packet.marshal() {
+            self.store(packet.header.sequence_number, bytes);
+        }
+        self.recover()
+    }
+
+    /// Whether media packet `sequence_number` was received or recovered
+    pub fn contains(&self, sequence_number: u16) -> bool {
+        self.media.contains_key(&sequence_number)
+    }
+
+    /// Account for a received FEC packet
+    ///
+    /// Returns the media packets recovered with it.
+    pub fn on_fec(&mut self, packet: &Packet) -> Vec<Packet> {
+        if let Some(fec) = FecPacket::parse(packet) {
+            self.pending.push_back(fec);
+            if self.pending.len() > MAX_PENDING_FEC {
+                self.pending.pop_front();
+            }
+        }
+        self.recover()
+    }
+
+    fn store(&mut self, sequence_number: u16, bytes: Bytes) {
+        if self.media.insert(sequence_number, bytes).is_none() {
+            self.order.push_back(sequence_number);
+        }
+        while self.order.len() > RETRANSMISSION_BUFFER_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.media.remove(&oldest);
+            }
+        }
+    }
+
+    /// Recover while some FEC packet is missing exactly one of its packets
+    fn recover(&mut self) -> Vec<Packet> {
+        let mut recovered = Vec::new();
+        loop {
+            // FEC packets with nothing missing are done with
+            self.pending
+                .retain(|fec| fec.protected.iter().any(|seq| !self.media.contains_key(seq)));
+
+            let Some(index) = self.pending.iter().position(|fec| {
+                fec.protected.iter().filter(|seq| !self.media.contains_key(seq)).count() == 1
+            }) else {
+                return recovered;
+            };
+            let fec = self.pending.remove(index).expect("index from position");
+            if let Some((sequence_number, bytes)) = self.recover_one(&fec) {
+                if let Ok(packet) = Packet::unmarshal(&mut bytes.clone()) {
+                    recovered.push(packet);
+                }
+                self.store(sequence_number, bytes);
+            }
+        }
+    }
+
+    fn recover_one(&self, fec: &FecPacket) -> Option<(u16, Bytes)> {
+        let missing = *fec.protected.iter().find(|seq| !self.media.contains_key(seq))?;
+
+        let mut header = fec.header_recovery;
+        let mut length = fec.length_recovery;
+        let mut payload = fec.payload.to_vec();
+        for bytes in fec.protected.iter().filter_map(|seq| self.media.get(seq)) {
+            for (recovery, byte) in header.iter_mut().zip(bytes.iter()) {
+                *recovery ^= byte;
+            }
+            length ^= (bytes.len() - RTP_HEADER_SIZE) as u16;
+            for (recovery, byte) in payload.iter_mut().zip(&bytes[RTP_HEADER_SIZE..]) {
+                *recovery ^= byte;
+            }
+        }
+        let length = length as usize;
+        if length > payload.len() {
+            return None;
+        }
+
+        let mut packet = BytesMut::with_capacity(RTP_HEADER_SIZE + length);
+        // Version 2 with the recovered P, X and CC bits
+        packet.extend_from_slice(&[0x80 | (header[0] & 0x3f), header[1]]);
+        packet.extend_from_slice(&missing.to_be_bytes());
+        packet.extend_from_slice(&header[4..8]);
+        // FEC on its own stream doesn't carry the media SSRC
+        packet.extend_from_slice(&self.media_ssrc.unwrap_or(fec.ssrc).to_be_bytes());
+        packet.extend_from_slice(&payload[..length]);
+        Some((missing, packet.freeze()))
+    }
+}
+
+impl Default for FecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// RTP packet carrying one ULPFEC payload
+pub fn fec_packet(payload: Bytes, sequence_number: u16, timestamp: u32, ssrc: u32) -> Packet {
+    Packet {
+        header: Header {
+            version: 2,
+            payload_type: ULPFEC_PAYLOAD_TYPE,
+            sequence_number,
+            timestamp,
+            ssrc,
+            ..Default::default()
+        },
+        payload,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webrtc::rtcp::transport_feedbacks::transport_layer_nack::nack_pairs_from_sequence_numbers;
+
+    fn media_packet(sequence_number: u16, marker: bool, payload: &[u8]) -> Packet {
+        Packet {
+            header: Header {
+                version: 2,
+                marker,
+                payload_type: 96,
+                sequence_number,
+                timestamp: 3000,
+                ssrc: 0x1234,
+                ..Default::default()
+            },
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    #[test]
+    fn test_fec_overhead_follows_loss() {
+        assert_eq!(fec_overhead_for_loss(0.0), 0.0);
+        assert_eq!(fec_overhead_for_loss(0.02), 0.1);
+        assert_eq!(fec_overhead_for_loss(0.1), 0.25);
+        assert_eq!(fec_overhead_for_loss(0.4), MAX_FEC_OVERHEAD);
+    }
+
+    #[test]
+    fn test_fec_recovers_one_loss_per_group() {
+        // Wraps around the sequence space, payloads of different lengths
+        let media: Vec<_> = (0..6u16)
+            .map(|i| media_packet(65533u16.wrapping_add(i), i == 5, &vec![i as u8 + 1; 100 + i as usize * 7]))
+            .collect();
+        let mut encoder = FecEncoder::new();
+        encoder.set_loss_rate(0.2);
+        let payloads = encoder.protect(&media).unwrap();
+        assert_eq!(payloads.len(), 3);
+
+        // Lose one packet covered by each FEC packet; FEC has its own stream
+        let mut decoder = FecDecoder::new();
+        for packet in media.iter().filter(|p| ![65533, 65534, 2].contains(&p.header.sequence_number)) {
+            assert!(decoder.on_media(packet).is_empty());
+        }
+        let mut recovered: Vec<_> = payloads
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, payload)| decoder.on_fec(&fec_packet(payload, 1 + i as u16, 3000, 0x5678)))
+            .collect();
+        recovered.sort_by_key(|p| p.header.sequence_number.wrapping_sub(65533));
+        assert_eq!(recovered, vec![media[0].clone(), media[1].clone(), media[5].clone()]);
+    }
+
+    #[test]
+    fn test_fec_long_mask() {
+        let media: Vec<_> = (0..40u16).map(|i| media_packet(i, i == 39, &[i as u8; 50])).collect();
+        let mut encoder = FecEncoder::new();
+        encoder.set_loss_rate(0.01);
+        let payloads = encoder.protect(&media).unwrap();
+        assert_eq!(payloads.len(), 4);
+
+        let mut decoder = FecDecoder::new();
+        for packet in media.iter().filter(|p| p.header.sequence_number != 37) {
+            decoder.on_media(packet);
+        }
+        let recovered: Vec<_> = payloads
+            .into_iter()
+            .flat_map(|payload| decoder.on_fec(&fec_packet(payload, 40, 3000, 0x1234)))
+            .collect();
+        assert_eq!(recovered, vec![media[37].clone()]);
+    }
+
+    #[test]
+    fn test_retransmission_buffer() {
+        let start = Instant::now();
+        let mut buffer = RetransmissionBuffer::new(4);
+        for sequence_number in 10..16u16 {
+            buffer.insert(media_packet(sequence_number, false, &[0]), start);
+        }
+        assert_eq!(buffer.len(), 4);
+
+        let nack = TransportLayerNack {
+            sender_ssrc: 1,
+            media_ssrc: 0x1234,
+            nacks: nack_pairs_from_sequence_numbers(&[11, 13, 15]),
+        };
+        let resent: Vec<_> = buffer.on_nack(&nack, start).iter().map(|p| p.header.sequence_number).collect();
+        assert_eq!(resent, vec![13, 15]);
+
+        // The same NACK again right away is a duplicate; later it is answered
+        assert!(buffer.on_nack(&nack, start + Duration::from_millis(5)).is_empty());
+        assert_eq!(buffer.on_nack(&nack, start + Duration::from_millis(50)).len(), 2);
+        assert!(buffer.on_nack(&nack, start + Duration::from_secs(2)).is_empty());
+    }
+}
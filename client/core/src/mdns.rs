@@ -0,0 +1,1120 @@
+//! mDNS / DNS-SD LAN discovery
+//!
+//! Devices advertise a `_genxlink._tcp.local` service (RFC 6762 / RFC 6763)
+//! on the IPv4 and IPv6 mDNS groups and browse for each other. The TXT record
+//! carries the device's Ed25519 public key and a signature over the
+//! announcement and its A/AAAA addresses, so a spoofed packet can't
+//! impersonate, redirect or evict a peer. The first key seen for a device ID
+//! is pinned, and the pins are kept on disk so a restart doesn't hand the ID
+//! to whoever announces it first afterwards.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use simple_dns::rdata::{RData, A, AAAA, PTR, SRV, TXT};
+use simple_dns::{Name, Packet, PacketFlag, Question, ResourceRecord, CLASS, QCLASS, QTYPE, TYPE};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use genxlink_protocol::DeviceId;
+
+use crate::device_identity::{self, DeviceIdentity};
+
+/// DNS-SD service type advertised by GenXLink devices
+pub const SERVICE_TYPE: &str = "_genxlink._tcp.local";
+
+/// Standard mDNS port
+pub const MDNS_PORT: u16 = 5353;
+
+/// mDNS IPv4 multicast group
+pub const MDNS_IPV4_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// mDNS IPv6 multicast group
+pub const MDNS_IPV6_GROUP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+/// TTL of our records; peers not refreshed within it are reported lost
+const RECORD_TTL: u32 = 120;
+
+/// Announcements whose timestamp is further than this from our clock are rejected
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+/// Announcement format version (TXT `v` key)
+const ANNOUNCEMENT_VERSION: &str = "2";
+
+/// Domain separation for announcement signatures
+const SIGNATURE_CONTEXT: &[u8] = b"genxlink-lan-announcement-v2";
+
+/// TXT strings are limited to 255 bytes, including the `name=` prefix
+const MAX_DEVICE_NAME_LEN: usize = 200;
+
+/// File in the GenXLink config directory holding pinned peer keys
+const KNOWN_KEYS_FILE: &str = "lan_peer_keys.json";
+
+const MAX_PACKET_SIZE: usize = 9000;
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Information about a discovered peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub device_id: DeviceId,
+    pub device_name: String,
+    /// Preferred address to reach the peer on
+    pub ip_address: String,
+    /// Every address the peer advertised
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+    pub last_seen: SystemTime,
+    pub capabilities: Vec<String>,
+    /// Ed25519 key that signed the peer's announcements
+    pub public_key: Vec<u8>,
+}
+
+impl PeerInfo {
+    /// Fingerprint of the peer's public key
+    pub fn fingerprint(&self) -> String {
+        device_identity::fingerprint(&self.public_key)
+    }
+}
+
+/// Discovery events
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent {
+    PeerDiscovered(PeerInfo),
+    PeerLost(DeviceId),
+    PeerUpdated(PeerInfo),
+}
+
+/// The service this device advertises
+#[derive(Debug, Clone)]
+pub struct ServiceDescription {
+    pub device_id: DeviceId,
+    pub device_name: String,
+    /// Port the device accepts peer connections on
+    pub port: u16,
+    pub capabilities: Vec<String>,
+}
+
+/// mDNS socket and timing configuration
+#[derive(Debug, Clone)]
+pub struct MdnsConfig {
+    /// UDP port to listen and send on
+    pub port: u16,
+    /// Use the IPv4 group
+    pub ipv4: bool,
+    /// Use the IPv6 group (best effort; skipped if the host has no IPv6 multicast)
+    pub ipv6: bool,
+    /// Interface address to join the IPv4 group on (unspecified = OS default)
+    pub ipv4_interface: Ipv4Addr,
+    /// Interface index to join the IPv6 group on (0 = OS default)
+    pub ipv6_interface: u32,
+    /// Addresses advertised in A/AAAA records (detected when empty)
+    pub addresses: Vec<IpAddr>,
+    /// How often presence is re-announced and peers are queried
+    pub announce_interval: Duration,
+    /// File pinned peer keys are kept in; `None` keeps them in memory only
+    pub known_keys: Option<PathBuf>,
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self {
+            port: MDNS_PORT,
+            ipv4: true,
+            ipv6: true,
+            ipv4_interface: Ipv4Addr::UNSPECIFIED,
+            ipv6_interface: 0,
+            addresses: Vec::new(),
+            announce_interval: Duration::from_secs(RECORD_TTL as u64 / 2),
+            known_keys: dirs::config_dir().map(|dir| dir.join("GenXLink").join(KNOWN_KEYS_FILE)),
+        }
+    }
+}
+
+impl MdnsConfig {
+    /// IPv4 loopback only, for running several responders on one host
+    pub fn loopback(port: u16) -> Self {
+        Self {
+            port,
+            ipv6: false,
+            ipv4_interface: Ipv4Addr::LOCALHOST,
+            addresses: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            known_keys: None,
+            ..Self::default()
+        }
+    }
+}
+
+/// A signed announcement, carried in the service's TXT record
+#[derive(Debug, Clone)]
+struct Announcement {
+    device_id: String,
+    device_name: String,
+    port: u16,
+    capabilities: Vec<String>,
+    public_key: Vec<u8>,
+    /// A/AAAA addresses sent with the announcement, sorted
+    addresses: Vec<IpAddr>,
+    /// Unix time in milliseconds
+    timestamp: u64,
+    goodbye: bool,
+    signature: Vec<u8>,
+}
+
+impl Announcement {
+    fn new(identity: &DeviceIdentity, service: &ServiceDescription, mut addresses: Vec<IpAddr>, goodbye: bool) -> Self {
+        addresses.sort();
+        addresses.dedup();
+        let mut announcement = Self {
+            device_id: service.device_id.0.clone(),
+            device_name: truncate(&service.device_name, MAX_DEVICE_NAME_LEN).to_string(),
+            port: service.port,
+            capabilities: service.capabilities.clone(),
+            public_key: identity.public_key().to_vec(),
+            addresses,
+            timestamp: unix_millis(SystemTime::now()),
+            goodbye,
+            signature: Vec::new(),
+        };
+        announcement.signature = identity.sign(&announcement.signed_bytes());
+        announcement
+    }
+
+    /// Canonical encoding of every field except the signature
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut out = SIGNATURE_CONTEXT.to_vec();
+        let capabilities = self.capabilities.join(",");
+        for field in [
+            self.device_id.as_bytes(),
+            self.device_name.as_bytes(),
+            capabilities.as_bytes(),
+            &self.public_key,
+        ] {
+            out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            out.extend_from_slice(field);
+        }
+        out.extend_from_slice(&(self.addresses.len() as u32).to_be_bytes());
+        for address in &self.addresses {
+            match address {
+                IpAddr::V4(v4) => {
+                    out.push(4);
+                    out.extend_from_slice(&v4.octets());
+                }
+                IpAddr::V6(v6) => {
+                    out.push(6);
+                    out.extend_from_slice(&v6.octets());
+                }
+            }
+        }
+        out.extend_from_slice(&self.port.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.push(self.goodbye as u8);
+        out
+    }
+
+    fn txt_strings(&self) -> Vec<String> {
+        let mut strings = vec![
+            format!("v={}", ANNOUNCEMENT_VERSION),
+            format!("id={}", self.device_id),
+            format!("name={}", self.device_name),
+            format!("caps={}", self.capabilities.join(",")),
+            format!("pk={}", URL_SAFE_NO_PAD.encode(&self.public_key)),
+            format!("ts={}", self.timestamp),
+        ];
+        if self.goodbye {
+            strings.push("bye=1".to_string());
+        }
+        strings.push(format!("sig={}", URL_SAFE_NO_PAD.encode(&self.signature)));
+        strings
+    }
+
+    /// Parse an announcement from TXT attributes, the SRV port and the
+    /// addresses of the SRV target
+    fn parse(attributes: &HashMap<String, Option<String>>, port: u16, mut addresses: Vec<IpAddr>) -> Result<Self> {
+        let get = |key: &str| {
+            attributes
+                .get(key)
+                .and_then(|value| value.as_deref())
+                .ok_or_else(|| anyhow!("missing TXT key '{}'", key))
+        };
+
+        let version = get("v")?;
+        if version != ANNOUNCEMENT_VERSION {
+            return Err(anyhow!("unsupported announcement version {}", version));
+        }
+
+        let capabilities = get("caps").unwrap_or_default();
+        addresses.sort();
+        addresses.dedup();
+
+        Ok(Self {
+            device_id: get("id")?.to_string(),
+            device_name: get("name")?.to_string(),
+            port,
+            capabilities: capabilities
+                .split(',')
+                .filter(|c| !c.is_empty())
+                .map(str::to_string)
+                .collect(),
+            public_key: URL_SAFE_NO_PAD.decode(get("pk")?).context("invalid public key")?,
+            addresses,
+            timestamp: get("ts")?.parse().context("invalid timestamp")?,
+            goodbye: attributes.contains_key("bye"),
+            signature: URL_SAFE_NO_PAD.decode(get("sig")?).context("invalid signature")?,
+        })
+    }
+
+    /// Check the signature and that the announcement is recent
+    fn verify(&self, now: SystemTime) -> Result<()> {
+        if !device_identity::verify_signature(&self.public_key, &self.signed_bytes(), &self.signature) {
+            return Err(anyhow!("bad signature"));
+        }
+
+        let skew = unix_millis(now).abs_diff(self.timestamp);
+        if skew > MAX_CLOCK_SKEW.as_millis() as u64 {
+            return Err(anyhow!("timestamp is {}s away from local clock", skew / 1000));
+        }
+
+        Ok(())
+    }
+}
+
+/// A discovered peer and its replay/expiry bookkeeping
+struct PeerState {
+    info: PeerInfo,
+    timestamp: u64,
+    expires_at: Instant,
+}
+
+/// mDNS responder and browser for the GenXLink service
+#[derive(Clone)]
+pub struct MdnsDiscovery {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    identity: Arc<DeviceIdentity>,
+    service: ServiceDescription,
+    config: MdnsConfig,
+    instance_name: String,
+    host_name: String,
+    peers: RwLock<HashMap<DeviceId, PeerState>>,
+    pinned_keys: RwLock<KnownKeys>,
+    events: broadcast::Sender<DiscoveryEvent>,
+    sockets: Mutex<Vec<Arc<UdpSocket>>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl MdnsDiscovery {
+    /// Create a responder for `service`; nothing is sent until [`start`](Self::start)
+    pub fn new(identity: Arc<DeviceIdentity>, service: ServiceDescription, config: MdnsConfig) -> Self {
+        let label = instance_label(&service.device_id);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let pinned_keys = KnownKeys::load(config.known_keys.clone());
+
+        Self {
+            inner: Arc::new(Inner {
+                identity,
+                instance_name: format!("{}.{}", label, SERVICE_TYPE),
+                host_name: format!("{}.local", label),
+                service,
+                config,
+                peers: RwLock::new(HashMap::new()),
+                pinned_keys: RwLock::new(pinned_keys),
+                events,
+                sockets: Mutex::new(Vec::new()),
+                tasks: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Join the mDNS groups, start answering queries and browsing for peers
+    pub async fn start(&self) -> Result<()> {
+        if self.is_running() {
+            return Ok(());
+        }
+
+        let config = &self.inner.config;
+        let mut sockets = Vec::new();
+
+        if config.ipv4 {
+            let socket = bind_ipv4(config)
+                .with_context(|| format!("failed to join mDNS group {}", MDNS_IPV4_GROUP))?;
+            sockets.push(Arc::new(UdpSocket::from_std(socket)?));
+        }
+
+        if config.ipv6 {
+            match bind_ipv6(config) {
+                Ok(socket) => sockets.push(Arc::new(UdpSocket::from_std(socket)?)),
+                Err(e) => warn!("IPv6 mDNS unavailable: {}", e),
+            }
+        }
+
+        if sockets.is_empty() {
+            return Err(anyhow!("no mDNS socket could be opened"));
+        }
+
+        info!(
+            "Advertising {} on mDNS port {} ({} socket(s))",
+            self.inner.instance_name,
+            config.port,
+            sockets.len()
+        );
+
+        let mut tasks = Vec::new();
+        for socket in &sockets {
+            tasks.push(tokio::spawn(Inner::receive_loop(self.inner.clone(), socket.clone())));
+        }
+        *self.inner.sockets.lock() = sockets;
+
+        tasks.push(tokio::spawn(Inner::announce_loop(self.inner.clone())));
+        tasks.push(tokio::spawn(Inner::expiry_loop(self.inner.clone())));
+        *self.inner.tasks.lock() = tasks;
+
+        Ok(())
+    }
+
+    /// Send a goodbye, leave the groups and forget discovered peers
+    pub async fn stop(&self) {
+        if !self.is_running() {
+            return;
+        }
+
+        if let Some(packet) = self.inner.build_response(true) {
+            self.inner.multicast(&packet).await;
+        }
+
+        for task in self.inner.tasks.lock().drain(..) {
+            task.abort();
+        }
+        self.inner.sockets.lock().clear();
+
+        let lost: Vec<DeviceId> = self.inner.peers.write().drain().map(|(id, _)| id).collect();
+        for device_id in lost {
+            let _ = self.inner.events.send(DiscoveryEvent::PeerLost(device_id));
+        }
+
+        info!("Stopped advertising {}", self.inner.instance_name);
+    }
+
+    /// Whether the responder is running
+    pub fn is_running(&self) -> bool {
+        !self.inner.tasks.lock().is_empty()
+    }
+
+    /// Subscribe to peer discovery events
+    pub fn subscribe(&self) -> broadcast::Receiver<DiscoveryEvent> {
+        self.inner.events.subscribe()
+    }
+
+    /// Ask the network for GenXLink services right away
+    pub async fn query(&self) {
+        if let Some(packet) = build_query() {
+            self.inner.multicast(&packet).await;
+        }
+    }
+
+    /// All currently known peers
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        self.inner.peers.read().values().map(|p| p.info.clone()).collect()
+    }
+
+    /// A known peer by device ID
+    pub fn peer(&self, device_id: &DeviceId) -> Option<PeerInfo> {
+        self.inner.peers.read().get(device_id).map(|p| p.info.clone())
+    }
+
+    /// The service this responder advertises
+    pub fn service(&self) -> &ServiceDescription {
+        &self.inner.service
+    }
+}
+
+impl Inner {
+    async fn receive_loop(inner: Arc<Inner>, socket: Arc<UdpSocket>) {
+        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+
+        loop {
+            let (len, src) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("mDNS receive error: {}", e);
+                    continue;
+                }
+            };
+
+            let packet = match Packet::parse(&buf[..len]) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    debug!("Ignoring malformed mDNS packet from {}: {}", src, e);
+                    continue;
+                }
+            };
+
+            if packet.has_flags(PacketFlag::RESPONSE) {
+                inner.handle_response(&packet, src);
+            } else if let Some(unicast) = inner.matches_query(&packet) {
+                let Some(reply) = inner.build_response(false) else { continue };
+                if unicast {
+                    if let Err(e) = socket.send_to(&reply, src).await {
+                        debug!("Failed to answer {}: {}", src, e);
+                    }
+                } else {
+                    inner.multicast(&reply).await;
+                }
+            }
+        }
+    }
+
+    async fn announce_loop(inner: Arc<Inner>) {
+        let query = build_query();
+
+        // RFC 6762 §8.3: announce at least twice, one second apart
+        for delay in [Duration::ZERO, Duration::from_secs(1)] {
+            tokio::time::sleep(delay).await;
+            if let Some(query) = &query {
+                inner.multicast(query).await;
+            }
+            if let Some(packet) = inner.build_response(false) {
+                inner.multicast(&packet).await;
+            }
+        }
+
+        let mut interval = tokio::time::interval(inner.config.announce_interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Some(packet) = inner.build_response(false) {
+                inner.multicast(&packet).await;
+            }
+            if let Some(query) = &query {
+                inner.multicast(query).await;
+            }
+        }
+    }
+
+    async fn expiry_loop(inner: Arc<Inner>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            interval.tick().await;
+
+            let now = Instant::now();
+            let expired: Vec<DeviceId> = {
+                let mut peers = inner.peers.write();
+                let expired: Vec<DeviceId> = peers
+                    .iter()
+                    .filter(|(_, peer)| peer.expires_at <= now)
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in &expired {
+                    peers.remove(id);
+                }
+                expired
+            };
+
+            for device_id in expired {
+                info!("LAN peer {} expired", device_id);
+                let _ = inner.events.send(DiscoveryEvent::PeerLost(device_id));
+            }
+        }
+    }
+
+    async fn multicast(&self, packet: &[u8]) {
+        let sockets = self.sockets.lock().clone();
+
+        for socket in sockets {
+            let destination = match socket.local_addr() {
+                Ok(SocketAddr::V4(_)) => SocketAddr::from((MDNS_IPV4_GROUP, self.config.port)),
+                Ok(SocketAddr::V6(_)) => SocketAddr::V6(SocketAddrV6::new(
+                    MDNS_IPV6_GROUP,
+                    self.config.port,
+                    0,
+                    self.config.ipv6_interface,
+                )),
+                Err(_) => continue,
+            };
+
+            if let Err(e) = socket.send_to(packet, destination).await {
+                debug!("Failed to send mDNS packet to {}: {}", destination, e);
+            }
+        }
+    }
+
+    /// Whether a query asks for our records, and if so whether all matching
+    /// questions requested a unicast answer
+    fn matches_query(&self, packet: &Packet) -> Option<bool> {
+        let service = Name::new_unchecked(SERVICE_TYPE);
+        let instance = Name::new_unchecked(&self.instance_name);
+        let host = Name::new_unchecked(&self.host_name);
+
+        let mut matched = false;
+        let mut unicast = true;
+        for question in &packet.questions {
+            let hit = (question.qname == service
+                && matches!(question.qtype, QTYPE::ANY | QTYPE::TYPE(TYPE::PTR)))
+                || question.qname == instance
+                || question.qname == host;
+            if hit {
+                matched = true;
+                unicast &= question.unicast_response;
+            }
+        }
+
+        matched.then_some(unicast)
+    }
+
+    /// Our PTR/SRV/TXT/A/AAAA records; TTL 0 with a signed goodbye when leaving
+    fn build_response(&self, goodbye: bool) -> Option<Vec<u8>> {
+        let addresses = self.advertised_addresses();
+        let announcement = Announcement::new(&self.identity, &self.service, addresses.clone(), goodbye);
+        let txt_strings = announcement.txt_strings();
+        let ttl = if goodbye { 0 } else { RECORD_TTL };
+
+        let service = Name::new_unchecked(SERVICE_TYPE);
+        let instance = Name::new_unchecked(&self.instance_name);
+        let host = Name::new_unchecked(&self.host_name);
+
+        let mut txt = TXT::new();
+        for string in &txt_strings {
+            if let Err(e) = txt.add_string(string) {
+                warn!("Cannot encode TXT entry: {}", e);
+                return None;
+            }
+        }
+
+        let mut packet = Packet::new_reply(0);
+        packet.set_flags(PacketFlag::AUTHORITATIVE_ANSWER);
+        packet.answers.push(ResourceRecord::new(
+            service,
+            CLASS::IN,
+            ttl,
+            RData::PTR(PTR(instance.clone())),
+        ));
+        packet.additional_records.push(
+            ResourceRecord::new(
+                instance.clone(),
+                CLASS::IN,
+                ttl,
+                RData::SRV(SRV {
+                    priority: 0,
+                    weight: 0,
+                    port: self.service.port,
+                    target: host.clone(),
+                }),
+            )
+            .with_cache_flush(true),
+        );
+        packet.additional_records.push(
+            ResourceRecord::new(instance, CLASS::IN, ttl, RData::TXT(txt)).with_cache_flush(true),
+        );
+
+        for address in addresses {
+            let rdata = match address {
+                IpAddr::V4(v4) => RData::A(A::from(v4)),
+                IpAddr::V6(v6) => RData::AAAA(AAAA::from(v6)),
+            };
+            packet.additional_records.push(
+                ResourceRecord::new(host.clone(), CLASS::IN, ttl, rdata).with_cache_flush(true),
+            );
+        }
+
+        match packet.build_bytes_vec_compressed() {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                warn!("Failed to encode mDNS response: {}", e);
+                None
+            }
+        }
+    }
+
+    fn advertised_addresses(&self) -> Vec<IpAddr> {
+        if self.config.addresses.is_empty() {
+            local_addresses()
+        } else {
+            self.config.addresses.clone()
+        }
+    }
+
+    fn handle_response(&self, packet: &Packet, src: SocketAddr) {
+        let service = Name::new_unchecked(SERVICE_TYPE);
+        let records: Vec<&ResourceRecord> =
+            packet.answers.iter().chain(&packet.additional_records).collect();
+
+        for record in &records {
+            let RData::PTR(PTR(instance)) = &record.rdata else { continue };
+            if record.name != service {
+                continue;
+            }
+
+            let srv = records.iter().find_map(|r| match &r.rdata {
+                RData::SRV(srv) if r.name == *instance => Some(srv),
+                _ => None,
+            });
+            let txt = records.iter().find_map(|r| match &r.rdata {
+                RData::TXT(txt) if r.name == *instance => Some(txt),
+                _ => None,
+            });
+            let (Some(srv), Some(txt)) = (srv, txt) else {
+                debug!("Incomplete GenXLink service record from {}", src);
+                continue;
+            };
+
+            let addresses: Vec<IpAddr> = records
+                .iter()
+                .filter(|r| r.name == srv.target)
+                .filter_map(|r| match &r.rdata {
+                    RData::A(a) => Some(IpAddr::V4(Ipv4Addr::from(a.address))),
+                    RData::AAAA(aaaa) => Some(IpAddr::V6(Ipv6Addr::from(aaaa.address))),
+                    _ => None,
+                })
+                .collect();
+
+            match Announcement::parse(&txt.attributes(), srv.port, addresses) {
+                Ok(announcement) => self.accept(announcement, record.ttl, src.ip()),
+                Err(e) => debug!("Ignoring announcement from {}: {}", src, e),
+            }
+        }
+    }
+
+    fn accept(&self, announcement: Announcement, ttl: u32, source: IpAddr) {
+        let device_id = DeviceId(announcement.device_id.clone());
+        if device_id == self.service.device_id {
+            return;
+        }
+
+        if let Err(e) = announcement.verify(SystemTime::now()) {
+            warn!("Rejected LAN announcement for {} from {}: {}", device_id, source, e);
+            return;
+        }
+
+        if let Some(pinned) = self.pinned_keys.read().get(&device_id) {
+            if *pinned != announcement.public_key {
+                warn!(
+                    "Rejected LAN announcement for {} from {}: key {} does not match pinned key",
+                    device_id,
+                    source,
+                    device_identity::fingerprint(&announcement.public_key)
+                );
+                return;
+            }
+        }
+
+        let event = {
+            let mut peers = self.peers.write();
+
+            if let Some(existing) = peers.get(&device_id) {
+                if announcement.timestamp <= existing.timestamp {
+                    debug!("Ignoring replayed announcement for {}", device_id);
+                    return;
+                }
+            }
+
+            if announcement.goodbye || ttl == 0 {
+                if !announcement.goodbye {
+                    debug!("Ignoring unsigned goodbye for {}", device_id);
+                    return;
+                }
+                peers.remove(&device_id).map(|_| DiscoveryEvent::PeerLost(device_id.clone()))
+            } else {
+                if let Err(e) = self.pinned_keys.write().pin(&device_id, &announcement.public_key) {
+                    warn!("Not accepting LAN peer {}: failed to pin its key: {}", device_id, e);
+                    return;
+                }
+
+                let addresses = announcement.addresses;
+                let mut addresses = if addresses.is_empty() { vec![source] } else { addresses };
+                // Prefer the address the announcement actually arrived from
+                if let Some(index) = addresses.iter().position(|a| *a == source) {
+                    addresses.swap(0, index);
+                }
+
+                let info = PeerInfo {
+                    device_id: device_id.clone(),
+                    device_name: announcement.device_name,
+                    ip_address: addresses[0].to_string(),
+                    addresses,
+                    port: announcement.port,
+                    last_seen: SystemTime::now(),
+                    capabilities: announcement.capabilities,
+                    public_key: announcement.public_key,
+                };
+
+                let state = PeerState {
+                    info: info.clone(),
+                    timestamp: announcement.timestamp,
+                    expires_at: Instant::now() + Duration::from_secs(ttl as u64),
+                };
+
+                match peers.insert(device_id.clone(), state) {
+                    None => Some(DiscoveryEvent::PeerDiscovered(info)),
+                    Some(previous) if !same_advertisement(&previous.info, &info) => {
+                        Some(DiscoveryEvent::PeerUpdated(info))
+                    }
+                    Some(_) => None,
+                }
+            }
+        };
+
+        match event {
+            Some(DiscoveryEvent::PeerDiscovered(ref info)) => {
+                info!("Discovered LAN peer {} ({}) at {}", info.device_name, info.device_id, info.ip_address)
+            }
+            Some(DiscoveryEvent::PeerLost(ref id)) => info!("LAN peer {} left", id),
+            _ => {}
+        }
+
+        if let Some(event) = event {
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+/// Public key each peer device ID was first announced with
+struct KnownKeys {
+    keys: HashMap<DeviceId, Vec<u8>>,
+    /// Where the keys are saved; `None` keeps them in memory only
+    path: Option<PathBuf>,
+}
+
+impl KnownKeys {
+    /// Keys saved at `path`; an unreadable file starts empty
+    fn load(path: Option<PathBuf>) -> Self {
+        let keys = path
+            .as_deref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|json| match serde_json::from_str::<HashMap<DeviceId, String>>(&json) {
+                Ok(keys) => Some(keys),
+                Err(e) => {
+                    warn!("Ignoring unreadable LAN peer key file: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(device_id, key)| Some((device_id, URL_SAFE_NO_PAD.decode(key).ok()?)))
+            .collect();
+        Self { keys, path }
+    }
+
+    fn get(&self, device_id: &DeviceId) -> Option<&Vec<u8>> {
+        self.keys.get(device_id)
+    }
+
+    /// Pin `device_id` to `public_key` unless it's pinned already, saving
+    /// the pin before it takes effect
+    fn pin(&mut self, device_id: &DeviceId, public_key: &[u8]) -> std::io::Result<()> {
+        if self.keys.contains_key(device_id) {
+            return Ok(());
+        }
+        if let Some(path) = &self.path {
+            let mut saved: HashMap<&DeviceId, String> =
+                self.keys.iter().map(|(id, key)| (id, URL_SAFE_NO_PAD.encode(key))).collect();
+            saved.insert(device_id, URL_SAFE_NO_PAD.encode(public_key));
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // Written aside and renamed, so a crash can't leave a torn file
+            let staging = path.with_extension("json.tmp");
+            std::fs::write(&staging, serde_json::to_vec_pretty(&saved)?)?;
+            std::fs::rename(&staging, path)?;
+        }
+        self.keys.insert(device_id.clone(), public_key.to_vec());
+        Ok(())
+    }
+}
+
+fn same_advertisement(a: &PeerInfo, b: &PeerInfo) -> bool {
+    a.device_name == b.device_name
+        && a.addresses == b.addresses
+        && a.port == b.port
+        && a.capabilities == b.capabilities
+}
+
+fn build_query() -> Option<Vec<u8>> {
+    let mut packet = Packet::new_query(0);
+    packet.questions.push(Question::new(
+        Name::new_unchecked(SERVICE_TYPE),
+        QTYPE::TYPE(TYPE::PTR),
+        QCLASS::CLASS(CLASS::IN),
+        false,
+    ));
+    packet.build_bytes_vec_compressed().ok()
+}
+
+fn bind_ipv4(config: &MdnsConfig) -> std::io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, config.port)).into())?;
+    socket.join_multicast_v4(&MDNS_IPV4_GROUP, &config.ipv4_interface)?;
+    socket.set_multicast_if_v4(&config.ipv4_interface)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+fn bind_ipv6(config: &MdnsConfig) -> std::io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_only_v6(true)?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, config.port)).into())?;
+    socket.join_multicast_v6(&MDNS_IPV6_GROUP, config.ipv6_interface)?;
+    socket.set_multicast_if_v6(config.ipv6_interface)?;
+    socket.set_multicast_loop_v6(true)?;
+    socket.set_multicast_hops_v6(255)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Primary local addresses, found by asking the OS which source address it
+/// would route through (no packets are sent)
+fn local_addresses() -> Vec<IpAddr> {
+    [("0.0.0.0:0", "192.0.2.1:9"), ("[::]:0", "[2001:db8::1]:9")]
+        .iter()
+        .filter_map(|(bind, target)| {
+            let socket = std::net::UdpSocket::bind(bind).ok()?;
+            socket.connect(target).ok()?;
+            let ip = socket.local_addr().ok()?.ip();
+            (!ip.is_unspecified() && !ip.is_loopback()).then_some(ip)
+        })
+        .collect()
+}
+
+/// DNS label for a device: at most 63 bytes and free of dots
+fn instance_label(device_id: &DeviceId) -> String {
+    truncate(&device_id.0.replace('.', "-"), 63).to_string()
+}
+
+fn truncate(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str) -> ServiceDescription {
+        ServiceDescription {
+            device_id: DeviceId::new(),
+            device_name: name.to_string(),
+            port: 7000,
+            capabilities: vec!["screen-share".to_string(), "file-transfer".to_string()],
+        }
+    }
+
+    fn responder(name: &str, port: u16) -> MdnsDiscovery {
+        let identity = Arc::new(DeviceIdentity::generate().unwrap());
+        MdnsDiscovery::new(identity, service(name), MdnsConfig::loopback(port))
+    }
+
+    fn free_port() -> u16 {
+        std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+    }
+
+    fn txt_attributes(announcement: &Announcement) -> HashMap<String, Option<String>> {
+        let strings = announcement.txt_strings();
+        let mut txt = TXT::new();
+        for string in &strings {
+            txt.add_string(string).unwrap();
+        }
+        txt.attributes()
+    }
+
+    fn signed_at(
+        identity: &DeviceIdentity,
+        service: &ServiceDescription,
+        goodbye: bool,
+        timestamp: u64,
+    ) -> Announcement {
+        let mut announcement = Announcement::new(identity, service, Vec::new(), goodbye);
+        announcement.timestamp = timestamp;
+        announcement.signature = identity.sign(&announcement.signed_bytes());
+        announcement
+    }
+
+    async fn next_event(events: &mut broadcast::Receiver<DiscoveryEvent>) -> DiscoveryEvent {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for discovery event")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_announcement_round_trip_and_signature() {
+        let identity = DeviceIdentity::generate().unwrap();
+        let service = service("Office PC");
+        let addresses: Vec<IpAddr> = vec!["192.168.1.20".parse().unwrap(), "fe80::1".parse().unwrap()];
+        let announcement = Announcement::new(&identity, &service, addresses.clone(), false);
+
+        // Addresses arrive in A/AAAA records, in any order
+        let reversed = addresses.iter().rev().copied().collect();
+        let parsed = Announcement::parse(&txt_attributes(&announcement), service.port, reversed).unwrap();
+        assert_eq!(parsed.device_id, service.device_id.0);
+        assert_eq!(parsed.capabilities, service.capabilities);
+        assert!(parsed.verify(SystemTime::now()).is_ok());
+
+        let mut renamed = parsed.clone();
+        renamed.device_name = "Impostor".to_string();
+        assert!(renamed.verify(SystemTime::now()).is_err());
+
+        let mut redirected = parsed.clone();
+        redirected.port = 7001;
+        assert!(redirected.verify(SystemTime::now()).is_err());
+
+        let mut rerouted = parsed.clone();
+        rerouted.addresses = vec!["192.168.1.66".parse().unwrap()];
+        assert!(rerouted.verify(SystemTime::now()).is_err());
+
+        let mut stripped = parsed.clone();
+        stripped.addresses.clear();
+        assert!(stripped.verify(SystemTime::now()).is_err());
+
+        let mut forced_goodbye = parsed.clone();
+        forced_goodbye.goodbye = true;
+        assert!(forced_goodbye.verify(SystemTime::now()).is_err());
+
+        let later = SystemTime::now() + MAX_CLOCK_SKEW + Duration::from_secs(1);
+        assert!(parsed.verify(later).is_err());
+    }
+
+    #[test]
+    fn test_rejects_key_change_and_replay() {
+        let browser = responder("Browser", 0);
+        let mut events = browser.subscribe();
+        let victim = service("Victim");
+        let source = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let genuine_key = DeviceIdentity::generate().unwrap();
+        let first = Announcement::new(&genuine_key, &victim, Vec::new(), false);
+        browser.inner.accept(first.clone(), RECORD_TTL, source);
+        assert!(matches!(events.try_recv(), Ok(DiscoveryEvent::PeerDiscovered(_))));
+
+        // Another key claiming the same device ID is refused, even a goodbye
+        let attacker = DeviceIdentity::generate().unwrap();
+        let mut spoofed = victim.clone();
+        spoofed.device_name = "Victim (spoofed)".to_string();
+        browser.inner.accept(Announcement::new(&attacker, &spoofed, Vec::new(), false), RECORD_TTL, source);
+        browser.inner.accept(Announcement::new(&attacker, &victim, Vec::new(), true), 0, source);
+        assert!(events.try_recv().is_err());
+        assert_eq!(browser.peer(&victim.device_id).unwrap().device_name, "Victim");
+
+        // An older genuine announcement can't roll the peer back
+        let mut renamed = victim.clone();
+        renamed.device_name = "Victim (renamed)".to_string();
+        let newer = signed_at(&genuine_key, &renamed, false, first.timestamp + 1);
+        browser.inner.accept(newer.clone(), RECORD_TTL, source);
+        assert!(matches!(events.try_recv(), Ok(DiscoveryEvent::PeerUpdated(_))));
+
+        // Nor can the latest one be replayed from another address
+        browser.inner.accept(newer, RECORD_TTL, "192.168.1.66".parse().unwrap());
+        assert_eq!(browser.peer(&victim.device_id).unwrap().ip_address, "127.0.0.1");
+        browser.inner.accept(first.clone(), RECORD_TTL, source);
+        assert_eq!(browser.peer(&victim.device_id).unwrap().device_name, "Victim (renamed)");
+
+        // A TTL-0 record without a signed goodbye doesn't evict the peer
+        let refresh = signed_at(&genuine_key, &renamed, false, first.timestamp + 2);
+        browser.inner.accept(refresh, 0, source);
+        assert!(browser.peer(&victim.device_id).is_some());
+
+        let goodbye = signed_at(&genuine_key, &renamed, true, first.timestamp + 3);
+        browser.inner.accept(goodbye, 0, source);
+        assert!(browser.peer(&victim.device_id).is_none());
+        assert!(matches!(events.try_recv(), Ok(DiscoveryEvent::PeerLost(_))));
+    }
+
+    #[test]
+    fn test_pinned_keys_survive_restart() {
+        let path = std::env::temp_dir().join(format!("genxlink-lan-keys-{}.json", uuid::Uuid::new_v4()));
+        let browser = |path: &PathBuf| {
+            let identity = Arc::new(DeviceIdentity::generate().unwrap());
+            let config = MdnsConfig { known_keys: Some(path.clone()), ..MdnsConfig::loopback(0) };
+            MdnsDiscovery::new(identity, service("Browser"), config)
+        };
+        let victim = service("Victim");
+        let source = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let genuine_key = DeviceIdentity::generate().unwrap();
+
+        let before = browser(&path);
+        before.inner.accept(Announcement::new(&genuine_key, &victim, Vec::new(), false), RECORD_TTL, source);
+        assert!(before.peer(&victim.device_id).is_some());
+        drop(before);
+
+        // After a restart the attacker still can't take over the device ID
+        let after = browser(&path);
+        let attacker = DeviceIdentity::generate().unwrap();
+        after.inner.accept(Announcement::new(&attacker, &victim, Vec::new(), false), RECORD_TTL, source);
+        assert!(after.peer(&victim.device_id).is_none());
+        after.inner.accept(Announcement::new(&genuine_key, &victim, Vec::new(), false), RECORD_TTL, source);
+        assert!(after.peer(&victim.device_id).is_some());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_discovers_loopback_responders() {
+        let port = free_port();
+        let alice = responder("Alice", port);
+        let bob = responder("Bob", port);
+        let browser = responder("Browser", port);
+
+        let mut events = browser.subscribe();
+        browser.start().await.unwrap();
+        alice.start().await.unwrap();
+        bob.start().await.unwrap();
+
+        let mut found = HashMap::new();
+        while found.len() < 2 {
+            if let DiscoveryEvent::PeerDiscovered(peer) = next_event(&mut events).await {
+                found.insert(peer.device_id.clone(), peer);
+            }
+        }
+
+        for responder in [&alice, &bob] {
+            let peer = &found[&responder.service().device_id];
+            assert_eq!(peer.device_name, responder.service().device_name);
+            assert_eq!(peer.port, 7000);
+            assert_eq!(peer.ip_address, "127.0.0.1");
+            assert_eq!(peer.public_key, responder.inner.identity.public_key());
+        }
+
+        // Alice and Bob browse too, and never list themselves
+        assert!(alice.peer(&alice.service().device_id).is_none());
+
+        alice.stop().await;
+        loop {
+            if let DiscoveryEvent::PeerLost(id) = next_event(&mut events).await {
+                assert_eq!(id, alice.service().device_id);
+                break;
+            }
+        }
+        assert_eq!(browser.peers().len(), 1);
+
+        bob.stop().await;
+        browser.stop().await;
+    }
+}
@@ -0,0 +1,1069 @@
+//! Connectivity diagnostics
+//!
+//! Finds out why connections fail instead of leaving users with a bare
+//! `ConnectionState::Failed`. STUN binding tests in the style of RFC 5780
+//! classify how the local NAT maps and filters UDP traffic, repeated bindings
+//! measure UDP round trips and loss, and TCP probes check that the signaling
+//! and relay servers are reachable and how far away they are. Everything ends
+//! up in a [`DiagnosticsReport`] that prints as text or serializes to JSON.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::{debug, info};
+
+use genxlink_protocol::NatConfig;
+
+use crate::config::ServerConfig;
+use crate::gst_tunnel::NetworkCondition;
+use crate::ClientError;
+
+/// Port used when a STUN server is given without one
+pub const DEFAULT_STUN_PORT: u16 = 3478;
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+const MAX_DATAGRAM: usize = 1500;
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_RESPONSE: u16 = 0x0101;
+const BINDING_ERROR_RESPONSE: u16 = 0x0111;
+
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_CHANGE_REQUEST: u16 = 0x0003;
+const ATTR_CHANGED_ADDRESS: u16 = 0x0005;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const ATTR_RESPONSE_ORIGIN: u16 = 0x802B;
+const ATTR_OTHER_ADDRESS: u16 = 0x802C;
+
+const CHANGE_IP: u32 = 0x04;
+const CHANGE_PORT: u32 = 0x02;
+
+const FAMILY_IPV4: u8 = 0x01;
+const FAMILY_IPV6: u8 = 0x02;
+
+/// Round trip above which the connection is reported as slow
+const HIGH_LATENCY_MS: f64 = 150.0;
+
+/// Kind of STUN message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StunMessageType {
+    BindingRequest,
+    BindingResponse,
+    BindingErrorResponse,
+}
+
+impl StunMessageType {
+    fn code(self) -> u16 {
+        match self {
+            Self::BindingRequest => BINDING_REQUEST,
+            Self::BindingResponse => BINDING_RESPONSE,
+            Self::BindingErrorResponse => BINDING_ERROR_RESPONSE,
+        }
+    }
+
+    fn from_code(code: u16) -> Option<Self> {
+        match code {
+            BINDING_REQUEST => Some(Self::BindingRequest),
+            BINDING_RESPONSE => Some(Self::BindingResponse),
+            BINDING_ERROR_RESPONSE => Some(Self::BindingErrorResponse),
+            _ => None,
+        }
+    }
+}
+
+/// STUN attributes used by the binding tests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StunAttribute {
+    MappedAddress(SocketAddr),
+    XorMappedAddress(SocketAddr),
+    /// Ask the server to answer from its alternate IP and/or port
+    ChangeRequest { change_ip: bool, change_port: bool },
+    /// RFC 3489 name for the server's alternate address
+    ChangedAddress(SocketAddr),
+    OtherAddress(SocketAddr),
+    ResponseOrigin(SocketAddr),
+    ErrorCode { code: u16, reason: String },
+    Unknown { kind: u16, value: Vec<u8> },
+}
+
+/// A STUN message (RFC 5389 framing, RFC 5780 attributes)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StunMessage {
+    pub message_type: StunMessageType,
+    pub transaction_id: [u8; 12],
+    pub attributes: Vec<StunAttribute>,
+}
+
+impl StunMessage {
+    /// Binding request with a fresh transaction ID
+    pub fn binding_request() -> Self {
+        let mut transaction_id = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut transaction_id);
+        Self {
+            message_type: StunMessageType::BindingRequest,
+            transaction_id,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with_attribute(mut self, attribute: StunAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    /// Address the server saw the request come from
+    pub fn mapped_address(&self) -> Option<SocketAddr> {
+        self.find(|a| match a {
+            StunAttribute::XorMappedAddress(addr) => Some(*addr),
+            _ => None,
+        })
+        .or_else(|| {
+            self.find(|a| match a {
+                StunAttribute::MappedAddress(addr) => Some(*addr),
+                _ => None,
+            })
+        })
+    }
+
+    /// The server's alternate address, if it supports RFC 5780 tests
+    pub fn other_address(&self) -> Option<SocketAddr> {
+        self.find(|a| match a {
+            StunAttribute::OtherAddress(addr) | StunAttribute::ChangedAddress(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    pub fn response_origin(&self) -> Option<SocketAddr> {
+        self.find(|a| match a {
+            StunAttribute::ResponseOrigin(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    /// Flags of a CHANGE-REQUEST attribute as `(change_ip, change_port)`
+    pub fn change_request(&self) -> Option<(bool, bool)> {
+        self.find(|a| match a {
+            StunAttribute::ChangeRequest { change_ip, change_port } => Some((*change_ip, *change_port)),
+            _ => None,
+        })
+    }
+
+    fn find<T>(&self, f: impl FnMut(&StunAttribute) -> Option<T>) -> Option<T> {
+        self.attributes.iter().find_map(f)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for attribute in &self.attributes {
+            let (kind, value) = self.encode_attribute(attribute);
+            body.extend_from_slice(&kind.to_be_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(&value);
+            body.resize(body.len().next_multiple_of(4), 0);
+        }
+
+        let mut buf = Vec::with_capacity(HEADER_LEN + body.len());
+        buf.extend_from_slice(&self.message_type.code().to_be_bytes());
+        buf.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        buf.extend_from_slice(&self.transaction_id);
+        buf.extend_from_slice(&body);
+        buf
+    }
+
+    fn encode_attribute(&self, attribute: &StunAttribute) -> (u16, Vec<u8>) {
+        match attribute {
+            StunAttribute::MappedAddress(addr) => (ATTR_MAPPED_ADDRESS, encode_address(*addr)),
+            StunAttribute::XorMappedAddress(addr) => {
+                (ATTR_XOR_MAPPED_ADDRESS, encode_address(xor_address(*addr, &self.transaction_id)))
+            }
+            StunAttribute::ChangeRequest { change_ip, change_port } => {
+                let mut flags = 0;
+                if *change_ip {
+                    flags |= CHANGE_IP;
+                }
+                if *change_port {
+                    flags |= CHANGE_PORT;
+                }
+                (ATTR_CHANGE_REQUEST, flags.to_be_bytes().to_vec())
+            }
+            StunAttribute::ChangedAddress(addr) => (ATTR_CHANGED_ADDRESS, encode_address(*addr)),
+            StunAttribute::OtherAddress(addr) => (ATTR_OTHER_ADDRESS, encode_address(*addr)),
+            StunAttribute::ResponseOrigin(addr) => (ATTR_RESPONSE_ORIGIN, encode_address(*addr)),
+            StunAttribute::ErrorCode { code, reason } => {
+                let mut value = vec![0, 0, (code / 100) as u8, (code % 100) as u8];
+                value.extend_from_slice(reason.as_bytes());
+                (ATTR_ERROR_CODE, value)
+            }
+            StunAttribute::Unknown { kind, value } => (*kind, value.clone()),
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self, ClientError> {
+        if buf.len() < HEADER_LEN {
+            return Err(malformed("message shorter than the header"));
+        }
+        let code = u16::from_be_bytes([buf[0], buf[1]]);
+        let length = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        let cookie = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        if code & 0xC000 != 0 || cookie != MAGIC_COOKIE {
+            return Err(malformed("not a STUN message"));
+        }
+        if !length.is_multiple_of(4) || HEADER_LEN + length != buf.len() {
+            return Err(malformed("length does not match the datagram"));
+        }
+        let message_type =
+            StunMessageType::from_code(code).ok_or_else(|| malformed(&format!("unsupported message type {:#06x}", code)))?;
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&buf[8..HEADER_LEN]);
+
+        let mut attributes = Vec::new();
+        let mut rest = &buf[HEADER_LEN..];
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err(malformed("truncated attribute header"));
+            }
+            let kind = u16::from_be_bytes([rest[0], rest[1]]);
+            let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            let padded = len.next_multiple_of(4);
+            if rest.len() < 4 + padded {
+                return Err(malformed("truncated attribute"));
+            }
+            let value = &rest[4..4 + len];
+            attributes.push(decode_attribute(kind, value, &transaction_id)?);
+            rest = &rest[4 + padded..];
+        }
+
+        Ok(Self {
+            message_type,
+            transaction_id,
+            attributes,
+        })
+    }
+}
+
+fn decode_attribute(kind: u16, value: &[u8], transaction_id: &[u8; 12]) -> Result<StunAttribute, ClientError> {
+    Ok(match kind {
+        ATTR_MAPPED_ADDRESS => StunAttribute::MappedAddress(decode_address(value)?),
+        ATTR_XOR_MAPPED_ADDRESS => StunAttribute::XorMappedAddress(xor_address(decode_address(value)?, transaction_id)),
+        ATTR_CHANGE_REQUEST => {
+            let bytes: [u8; 4] = value.try_into().map_err(|_| malformed("bad CHANGE-REQUEST"))?;
+            let flags = u32::from_be_bytes(bytes);
+            StunAttribute::ChangeRequest {
+                change_ip: flags & CHANGE_IP != 0,
+                change_port: flags & CHANGE_PORT != 0,
+            }
+        }
+        ATTR_CHANGED_ADDRESS => StunAttribute::ChangedAddress(decode_address(value)?),
+        ATTR_OTHER_ADDRESS => StunAttribute::OtherAddress(decode_address(value)?),
+        ATTR_RESPONSE_ORIGIN => StunAttribute::ResponseOrigin(decode_address(value)?),
+        ATTR_ERROR_CODE if value.len() >= 4 => StunAttribute::ErrorCode {
+            code: (value[2] & 0x07) as u16 * 100 + value[3] as u16,
+            reason: String::from_utf8_lossy(&value[4..]).into_owned(),
+        },
+        _ => StunAttribute::Unknown {
+            kind,
+            value: value.to_vec(),
+        },
+    })
+}
+
+fn encode_address(addr: SocketAddr) -> Vec<u8> {
+    let mut value = vec![0];
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            value.push(FAMILY_IPV4);
+            value.extend_from_slice(&addr.port().to_be_bytes());
+            value.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            value.push(FAMILY_IPV6);
+            value.extend_from_slice(&addr.port().to_be_bytes());
+            value.extend_from_slice(&ip.octets());
+        }
+    }
+    value
+}
+
+fn decode_address(value: &[u8]) -> Result<SocketAddr, ClientError> {
+    if value.len() < 4 {
+        return Err(malformed("truncated address"));
+    }
+    let port = u16::from_be_bytes([value[2], value[3]]);
+    let ip = match (value[1], &value[4..]) {
+        (FAMILY_IPV4, octets) if octets.len() == 4 => IpAddr::from(<[u8; 4]>::try_from(octets).unwrap()),
+        (FAMILY_IPV6, octets) if octets.len() == 16 => IpAddr::from(<[u8; 16]>::try_from(octets).unwrap()),
+        _ => return Err(malformed("bad address family")),
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+/// XOR an address with the magic cookie and transaction ID (its own inverse)
+fn xor_address(addr: SocketAddr, transaction_id: &[u8; 12]) -> SocketAddr {
+    let cookie = MAGIC_COOKIE.to_be_bytes();
+    let port = addr.port() ^ (MAGIC_COOKIE >> 16) as u16;
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => {
+            let mut octets = ip.octets();
+            for (octet, mask) in octets.iter_mut().zip(cookie) {
+                *octet ^= mask;
+            }
+            IpAddr::from(octets)
+        }
+        IpAddr::V6(ip) => {
+            let mut octets = ip.octets();
+            for (octet, mask) in octets.iter_mut().zip(cookie.iter().chain(transaction_id)) {
+                *octet ^= mask;
+            }
+            IpAddr::from(octets)
+        }
+    };
+    SocketAddr::new(ip, port)
+}
+
+fn malformed(reason: &str) -> ClientError {
+    ClientError::InvalidInput(format!("Malformed STUN message: {}", reason))
+}
+
+/// How the NAT picks the public address for outgoing UDP flows (RFC 4787)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MappingBehavior {
+    /// The local address is already public
+    NoNat,
+    /// One public address regardless of destination
+    EndpointIndependent,
+    /// A new public address per destination IP
+    AddressDependent,
+    /// A new public address per destination IP and port
+    AddressAndPortDependent,
+    Unknown,
+}
+
+/// Which inbound UDP packets the NAT lets through to a mapping (RFC 4787)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilteringBehavior {
+    /// Anyone may send to the mapped address
+    EndpointIndependent,
+    /// Only IPs the client has sent to
+    AddressDependent,
+    /// Only IP and port pairs the client has sent to
+    AddressAndPortDependent,
+    Unknown,
+}
+
+/// Classic NAT type, derived from mapping and filtering behavior
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NatType {
+    OpenInternet,
+    FullCone,
+    RestrictedCone,
+    PortRestrictedCone,
+    Symmetric,
+    UdpBlocked,
+    Unknown,
+}
+
+impl NatType {
+    pub fn classify(mapping: MappingBehavior, filtering: FilteringBehavior) -> Self {
+        match (mapping, filtering) {
+            (MappingBehavior::NoNat, _) => Self::OpenInternet,
+            (MappingBehavior::AddressDependent | MappingBehavior::AddressAndPortDependent, _) => Self::Symmetric,
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::EndpointIndependent) => Self::FullCone,
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::AddressDependent) => Self::RestrictedCone,
+            (MappingBehavior::EndpointIndependent, FilteringBehavior::AddressAndPortDependent) => {
+                Self::PortRestrictedCone
+            }
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Whether a direct peer-to-peer path is likely without a relay
+    pub fn direct_connection_likely(&self) -> bool {
+        matches!(
+            self,
+            Self::OpenInternet | Self::FullCone | Self::RestrictedCone | Self::PortRestrictedCone
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::OpenInternet => "Open Internet",
+            Self::FullCone => "Full Cone",
+            Self::RestrictedCone => "Restricted Cone",
+            Self::PortRestrictedCone => "Port Restricted Cone",
+            Self::Symmetric => "Symmetric",
+            Self::UdpBlocked => "UDP Blocked",
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for NatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Round-trip statistics for a series of probes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub sent: u32,
+    pub received: u32,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+impl LatencyStats {
+    fn from_samples(sent: u32, samples: &[Duration]) -> Self {
+        let millis: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        Self {
+            sent,
+            received: samples.len() as u32,
+            min_ms: millis.iter().copied().reduce(f64::min),
+            avg_ms: (!millis.is_empty()).then(|| millis.iter().sum::<f64>() / millis.len() as f64),
+            max_ms: millis.iter().copied().reduce(f64::max),
+        }
+    }
+
+    pub fn loss_percent(&self) -> f32 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        (self.sent - self.received) as f32 * 100.0 / self.sent as f32
+    }
+}
+
+/// Result of the STUN binding tests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NatReport {
+    pub nat_type: NatType,
+    pub mapping: MappingBehavior,
+    pub filtering: FilteringBehavior,
+    /// Server that answered the binding tests
+    pub stun_server: Option<SocketAddr>,
+    pub local_address: Option<SocketAddr>,
+    /// Server-reflexive address other peers would see
+    pub public_address: Option<SocketAddr>,
+    /// Whether the server supports the RFC 5780 change tests
+    pub rfc5780: bool,
+    /// Round trips of repeated binding requests
+    pub udp_latency: LatencyStats,
+}
+
+impl NatReport {
+    fn blocked(local_address: Option<SocketAddr>, udp_latency: LatencyStats) -> Self {
+        Self {
+            nat_type: NatType::UdpBlocked,
+            mapping: MappingBehavior::Unknown,
+            filtering: FilteringBehavior::Unknown,
+            stun_server: None,
+            local_address,
+            public_address: None,
+            rfc5780: false,
+            udp_latency,
+        }
+    }
+}
+
+/// Reachability of a signaling or relay server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceProbe {
+    pub name: String,
+    pub url: String,
+    pub address: Option<SocketAddr>,
+    pub reachable: bool,
+    /// TCP connect times
+    pub latency: LatencyStats,
+    pub error: Option<String>,
+}
+
+/// What to test and how patiently
+#[derive(Debug, Clone)]
+pub struct DiagnosticsConfig {
+    /// STUN servers as `host:port`, optionally prefixed with `stun:`
+    pub stun_servers: Vec<String>,
+    pub signaling_url: Option<String>,
+    pub relay_url: Option<String>,
+    /// Local address for the UDP tests
+    pub bind_address: SocketAddr,
+    /// Wait per STUN transmission
+    pub stun_timeout: Duration,
+    /// Transmissions per binding test before giving up
+    pub stun_attempts: u32,
+    /// Samples per latency measurement
+    pub probe_count: u32,
+    pub probe_timeout: Duration,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            stun_servers: NatConfig::default().stun_servers,
+            signaling_url: None,
+            relay_url: None,
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 0)),
+            stun_timeout: Duration::from_millis(500),
+            stun_attempts: 3,
+            probe_count: 5,
+            probe_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    /// Test the servers a client would use with `config`
+    pub fn for_servers(config: &ServerConfig) -> Self {
+        Self {
+            signaling_url: Some(config.signaling_server_url.clone()),
+            relay_url: Some(config.relay_server_url.clone()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Structured outcome of a diagnostics run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub generated_at: DateTime<Utc>,
+    pub nat: NatReport,
+    pub services: Vec<ServiceProbe>,
+    /// Plain-language explanations of what was found
+    pub findings: Vec<String>,
+}
+
+impl DiagnosticsReport {
+    pub fn udp_blocked(&self) -> bool {
+        self.nat.nat_type == NatType::UdpBlocked
+    }
+
+    /// Network condition from the measured round trips and loss
+    ///
+    /// Bandwidth is not measured here, so the caller supplies its estimate.
+    pub fn network_condition(&self, bandwidth_kbps: u32) -> Option<NetworkCondition> {
+        let latency = self
+            .services
+            .iter()
+            .find(|s| s.name == "signaling")
+            .and_then(|s| s.latency.avg_ms)
+            .or(self.nat.udp_latency.avg_ms)?;
+        Some(NetworkCondition::from_metrics(
+            latency.round() as u32,
+            self.nat.udp_latency.loss_percent(),
+            bandwidth_kbps,
+        ))
+    }
+
+    pub fn to_json(&self) -> Result<String, ClientError> {
+        serde_json::to_string_pretty(self).map_err(|e| ClientError::InvalidInput(e.to_string()))
+    }
+}
+
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "GenXLink connectivity diagnostics ({})", self.generated_at.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        writeln!(f)?;
+        writeln!(f, "NAT type:       {}", self.nat.nat_type)?;
+        writeln!(f, "Mapping:        {:?}", self.nat.mapping)?;
+        writeln!(f, "Filtering:      {:?}", self.nat.filtering)?;
+        if let Some(local) = self.nat.local_address {
+            writeln!(f, "Local address:  {}", local)?;
+        }
+        if let Some(public) = self.nat.public_address {
+            writeln!(f, "Public address: {}", public)?;
+        }
+        if let Some(server) = self.nat.stun_server {
+            writeln!(f, "STUN server:    {}{}", server, if self.nat.rfc5780 { " (RFC 5780)" } else { "" })?;
+        }
+        writeln!(f, "UDP round trip: {}", format_latency(&self.nat.udp_latency))?;
+
+        for service in &self.services {
+            writeln!(f)?;
+            let status = if service.reachable { "reachable" } else { "UNREACHABLE" };
+            writeln!(f, "{} ({}): {}", service.name, service.url, status)?;
+            if service.reachable {
+                writeln!(f, "  TCP connect: {}", format_latency(&service.latency))?;
+            }
+            if let Some(error) = &service.error {
+                writeln!(f, "  Error: {}", error)?;
+            }
+        }
+
+        if !self.findings.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Findings:")?;
+            for finding in &self.findings {
+                writeln!(f, "  - {}", finding)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_latency(stats: &LatencyStats) -> String {
+    match (stats.min_ms, stats.avg_ms, stats.max_ms) {
+        (Some(min), Some(avg), Some(max)) => format!(
+            "min {:.1} ms / avg {:.1} ms / max {:.1} ms, {:.0}% loss",
+            min,
+            avg,
+            max,
+            stats.loss_percent()
+        ),
+        _ => format!("no replies to {} probes", stats.sent),
+    }
+}
+
+/// Runs the connectivity tests
+pub struct NetworkDiagnostics {
+    config: DiagnosticsConfig,
+}
+
+impl NetworkDiagnostics {
+    pub fn new(config: DiagnosticsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Run every test and assemble the report
+    pub async fn run(&self) -> DiagnosticsReport {
+        info!("Running connectivity diagnostics");
+
+        let nat = match self.detect_nat().await {
+            Ok(nat) => nat,
+            Err(e) => {
+                debug!("NAT detection failed: {}", e);
+                NatReport::blocked(None, LatencyStats::default())
+            }
+        };
+
+        let mut services = Vec::new();
+        if let Some(url) = &self.config.signaling_url {
+            services.push(self.probe_service("signaling", url).await);
+        }
+        if let Some(url) = &self.config.relay_url {
+            services.push(self.probe_service("relay", url).await);
+        }
+
+        let findings = findings(&nat, &services);
+        DiagnosticsReport {
+            generated_at: Utc::now(),
+            nat,
+            services,
+            findings,
+        }
+    }
+
+    /// Classify the NAT with STUN binding tests
+    ///
+    /// Follows RFC 5780 when the server advertises an alternate address;
+    /// otherwise mapping is inferred by comparing two servers and filtering
+    /// stays unknown.
+    pub async fn detect_nat(&self) -> Result<NatReport, ClientError> {
+        let servers = self.resolve_stun_servers().await;
+        let socket = self.bind().await?;
+
+        // Test I: plain binding against each server until one answers
+        let mut answered = None;
+        for server in &servers {
+            if let Some(response) = self.binding(&socket, *server, None).await? {
+                answered = Some((*server, response));
+                break;
+            }
+        }
+        let local_address = local_endpoint(&socket, servers.first().copied()).await;
+        let Some((server, first)) = answered else {
+            let udp_latency = LatencyStats::from_samples(servers.len() as u32 * self.config.stun_attempts, &[]);
+            return Ok(NatReport::blocked(local_address, udp_latency));
+        };
+
+        let udp_latency = self.measure_udp(&socket, server).await?;
+        let other = first.message.other_address();
+        let mapped = first.mapped;
+
+        let mapping = if local_address == Some(mapped) {
+            MappingBehavior::NoNat
+        } else if let Some(other) = other {
+            self.test_mapping(&socket, server, other, mapped).await?
+        } else {
+            self.compare_servers(&socket, server, &servers, mapped).await?
+        };
+
+        let filtering = match (mapping, other) {
+            (MappingBehavior::NoNat, _) => FilteringBehavior::EndpointIndependent,
+            (_, Some(_)) => self.test_filtering(server).await?,
+            (_, None) => FilteringBehavior::Unknown,
+        };
+
+        let nat_type = NatType::classify(mapping, filtering);
+        info!("NAT type {} (mapping {:?}, filtering {:?}), public address {}", nat_type, mapping, filtering, mapped);
+
+        Ok(NatReport {
+            nat_type,
+            mapping,
+            filtering,
+            stun_server: Some(server),
+            local_address,
+            public_address: Some(mapped),
+            rfc5780: other.is_some(),
+            udp_latency,
+        })
+    }
+
+    /// RFC 5780 mapping tests II and III
+    async fn test_mapping(
+        &self,
+        socket: &UdpSocket,
+        server: SocketAddr,
+        other: SocketAddr,
+        mapped: SocketAddr,
+    ) -> Result<MappingBehavior, ClientError> {
+        let Some(second) = self.binding(socket, SocketAddr::new(other.ip(), server.port()), None).await? else {
+            return Ok(MappingBehavior::Unknown);
+        };
+        if second.mapped == mapped {
+            return Ok(MappingBehavior::EndpointIndependent);
+        }
+
+        let Some(third) = self.binding(socket, other, None).await? else {
+            return Ok(MappingBehavior::Unknown);
+        };
+        Ok(if third.mapped == second.mapped {
+            MappingBehavior::AddressDependent
+        } else {
+            MappingBehavior::AddressAndPortDependent
+        })
+    }
+
+    /// Fallback for servers without an alternate address
+    async fn compare_servers(
+        &self,
+        socket: &UdpSocket,
+        server: SocketAddr,
+        servers: &[SocketAddr],
+        mapped: SocketAddr,
+    ) -> Result<MappingBehavior, ClientError> {
+        for candidate in servers.iter().filter(|s| s.ip() != server.ip()) {
+            if let Some(response) = self.binding(socket, *candidate, None).await? {
+                return Ok(if response.mapped == mapped {
+                    MappingBehavior::EndpointIndependent
+                } else {
+                    MappingBehavior::AddressDependent
+                });
+            }
+        }
+        Ok(MappingBehavior::Unknown)
+    }
+
+    /// RFC 5780 filtering tests II and III
+    ///
+    /// Runs on a fresh socket so the mapping tests have not already opened
+    /// the NAT towards the alternate address.
+    async fn test_filtering(&self, server: SocketAddr) -> Result<FilteringBehavior, ClientError> {
+        let socket = self.bind().await?;
+        if self.binding(&socket, server, None).await?.is_none() {
+            return Ok(FilteringBehavior::Unknown);
+        }
+
+        let change_both = StunAttribute::ChangeRequest { change_ip: true, change_port: true };
+        if self.binding(&socket, server, Some(change_both)).await?.is_some() {
+            return Ok(FilteringBehavior::EndpointIndependent);
+        }
+
+        let change_port = StunAttribute::ChangeRequest { change_ip: false, change_port: true };
+        Ok(if self.binding(&socket, server, Some(change_port)).await?.is_some() {
+            FilteringBehavior::AddressDependent
+        } else {
+            FilteringBehavior::AddressAndPortDependent
+        })
+    }
+
+    /// Repeated single-shot bindings for round trip and loss
+    async fn measure_udp(&self, socket: &UdpSocket, server: SocketAddr) -> Result<LatencyStats, ClientError> {
+        let mut samples = Vec::new();
+        for _ in 0..self.config.probe_count {
+            if let Some(response) = self.transact(socket, server, StunMessage::binding_request()).await? {
+                samples.push(response.rtt);
+            }
+        }
+        Ok(LatencyStats::from_samples(self.config.probe_count, &samples))
+    }
+
+    /// One binding test, retransmitting until an answer or the attempts run out
+    async fn binding(
+        &self,
+        socket: &UdpSocket,
+        server: SocketAddr,
+        change: Option<StunAttribute>,
+    ) -> Result<Option<BindingResponse>, ClientError> {
+        let mut request = StunMessage::binding_request();
+        request.attributes.extend(change);
+        for attempt in 1..=self.config.stun_attempts {
+            if let Some(response) = self.transact(socket, server, request.clone()).await? {
+                return Ok(Some(response));
+            }
+            debug!("No STUN answer from {} (attempt {})", server, attempt);
+        }
+        Ok(None)
+    }
+
+    /// Send `request` once and wait for the matching response
+    async fn transact(
+        &self,
+        socket: &UdpSocket,
+        server: SocketAddr,
+        request: StunMessage,
+    ) -> Result<Option<BindingResponse>, ClientError> {
+        let started = Instant::now();
+        socket.send_to(&request.encode(), server).await.map_err(io_error)?;
+
+        let deadline = started + self.config.stun_timeout;
+        let mut buf = [0u8; MAX_DATAGRAM];
+        loop {
+            let (len, source) = match tokio::time::timeout_at(deadline.into(), socket.recv_from(&mut buf)).await {
+                Ok(received) => received.map_err(io_error)?,
+                Err(_) => return Ok(None),
+            };
+            // Stray datagrams and answers to earlier transmissions are skipped
+            let Ok(message) = StunMessage::decode(&buf[..len]) else { continue };
+            if message.transaction_id != request.transaction_id {
+                continue;
+            }
+            if message.message_type == StunMessageType::BindingErrorResponse {
+                debug!("STUN error response from {}: {:?}", source, message.attributes);
+                return Ok(None);
+            }
+            let Some(mapped) = message.mapped_address() else {
+                return Err(malformed("binding response without a mapped address"));
+            };
+            return Ok(Some(BindingResponse {
+                mapped,
+                rtt: started.elapsed(),
+                message,
+            }));
+        }
+    }
+
+    /// TCP reachability and connect latency of a server URL
+    pub async fn probe_service(&self, name: &str, url: &str) -> ServiceProbe {
+        let mut probe = ServiceProbe {
+            name: name.to_string(),
+            url: url.to_string(),
+            address: None,
+            reachable: false,
+            latency: LatencyStats::default(),
+            error: None,
+        };
+
+        let address = match resolve_url(url).await {
+            Ok(address) => address,
+            Err(e) => {
+                probe.error = Some(e.to_string());
+                return probe;
+            }
+        };
+        probe.address = Some(address);
+
+        let mut samples = Vec::new();
+        for _ in 0..self.config.probe_count {
+            let started = Instant::now();
+            match tokio::time::timeout(self.config.probe_timeout, TcpStream::connect(address)).await {
+                Ok(Ok(_)) => samples.push(started.elapsed()),
+                Ok(Err(e)) => probe.error = Some(e.to_string()),
+                Err(_) => probe.error = Some("connection timed out".to_string()),
+            }
+        }
+
+        probe.reachable = !samples.is_empty();
+        if probe.reachable {
+            probe.error = None;
+        }
+        probe.latency = LatencyStats::from_samples(self.config.probe_count, &samples);
+        probe
+    }
+
+    async fn bind(&self) -> Result<UdpSocket, ClientError> {
+        UdpSocket::bind(self.config.bind_address).await.map_err(io_error)
+    }
+
+    async fn resolve_stun_servers(&self) -> Vec<SocketAddr> {
+        let mut resolved = Vec::new();
+        for server in &self.config.stun_servers {
+            let host = server.strip_prefix("stun:").unwrap_or(server);
+            let host = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+                host.to_string()
+            } else {
+                format!("{}:{}", host, DEFAULT_STUN_PORT)
+            };
+            let lookup = tokio::net::lookup_host(&host).await;
+            match lookup {
+                Ok(addresses) => resolved.extend(
+                    addresses
+                        .filter(|a| a.is_ipv4() == self.config.bind_address.is_ipv4())
+                        .take(1),
+                ),
+                Err(e) => debug!("Could not resolve STUN server {}: {}", server, e),
+            }
+        }
+        resolved
+    }
+}
+
+struct BindingResponse {
+    mapped: SocketAddr,
+    rtt: Duration,
+    message: StunMessage,
+}
+
+/// The address the socket is bound to, with the interface IP filled in
+async fn local_endpoint(socket: &UdpSocket, towards: Option<SocketAddr>) -> Option<SocketAddr> {
+    let local = socket.local_addr().ok()?;
+    if !local.ip().is_unspecified() {
+        return Some(local);
+    }
+    // Connecting a throwaway socket makes the OS pick the outgoing interface
+    let probe = UdpSocket::bind(SocketAddr::new(local.ip(), 0)).await.ok()?;
+    probe.connect(towards?).await.ok()?;
+    Some(SocketAddr::new(probe.local_addr().ok()?.ip(), local.port()))
+}
+
+async fn resolve_url(url: &str) -> Result<SocketAddr, ClientError> {
+    let parsed = Url::parse(url).map_err(|e| ClientError::InvalidInput(format!("Invalid URL {}: {}", url, e)))?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| ClientError::InvalidInput(format!("URL has no host: {}", url)))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| ClientError::InvalidInput(format!("URL has no port: {}", url)))?;
+    let mut addresses = tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+        .await
+        .map_err(|e| ClientError::TransportError(format!("Could not resolve {}: {}", host, e)))?;
+    addresses
+        .next()
+        .ok_or_else(|| ClientError::TransportError(format!("No address for {}", host)))
+}
+
+fn io_error(e: std::io::Error) -> ClientError {
+    ClientError::IoError(e.to_string())
+}
+
+/// Explain the measurements in terms of what the user will experience
+fn findings(nat: &NatReport, services: &[ServiceProbe]) -> Vec<String> {
+    let mut findings = Vec::new();
+
+    match nat.nat_type {
+        NatType::UdpBlocked => findings.push(
+            "No STUN server answered over UDP; UDP is probably blocked by a firewall. \
+             Direct connections will not work and sessions will go through the relay."
+                .to_string(),
+        ),
+        NatType::Symmetric => findings.push(
+            "Symmetric NAT: the public port changes per destination, so direct connections \
+             usually fail and sessions will go through the relay."
+                .to_string(),
+        ),
+        NatType::Unknown => findings.push(
+            "The NAT type could not be fully determined; the STUN server does not support RFC 5780 tests."
+                .to_string(),
+        ),
+        nat_type if nat_type.direct_connection_likely() => {
+            findings.push(format!("{}: direct peer-to-peer connections should work.", nat_type))
+        }
+        _ => {}
+    }
+
+    if nat.udp_latency.received > 0 && nat.udp_latency.loss_percent() > 0.0 {
+        findings.push(format!(
+            "{:.0}% of UDP probes were lost; expect degraded video quality.",
+            nat.udp_latency.loss_percent()
+        ));
+    }
+
+    for service in services {
+        if !service.reachable {
+            findings.push(format!(
+                "The {} server at {} is unreachable ({}).",
+                service.name,
+                service.url,
+                service.error.as_deref().unwrap_or("unknown error")
+            ));
+        } else if service.latency.avg_ms.is_some_and(|avg| avg > HIGH_LATENCY_MS) {
+            findings.push(format!(
+                "High latency to the {} server ({:.0} ms).",
+                service.name,
+                service.latency.avg_ms.unwrap_or_default()
+            ));
+        }
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_mapped_address_roundtrip() {
+        for addr in ["203.0.113.7:40001", "[2001:db8::1]:3478"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let message = StunMessage::binding_request()
+                .with_attribute(StunAttribute::XorMappedAddress(addr))
+                .with_attribute(StunAttribute::OtherAddress("198.51.100.2:3479".parse().unwrap()));
+            let decoded = StunMessage::decode(&message.encode()).unwrap();
+            assert_eq!(decoded, message);
+            assert_eq!(decoded.mapped_address(), Some(addr));
+            assert_eq!(decoded.other_address(), Some("198.51.100.2:3479".parse().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_change_request_roundtrip() {
+        let message = StunMessage::binding_request()
+            .with_attribute(StunAttribute::ChangeRequest { change_ip: false, change_port: true });
+        let decoded = StunMessage::decode(&message.encode()).unwrap();
+        assert_eq!(decoded.change_request(), Some((false, true)));
+    }
+
+    #[test]
+    fn test_rejects_malformed_messages() {
+        let mut encoded = StunMessage::binding_request().encode();
+        assert!(StunMessage::decode(&encoded[..10]).is_err());
+
+        encoded[4] ^= 0xFF;
+        assert!(StunMessage::decode(&encoded).is_err());
+    }
+
+    #[test]
+    fn test_nat_classification() {
+        use FilteringBehavior as F;
+        use MappingBehavior as M;
+
+        assert_eq!(NatType::classify(M::NoNat, F::Unknown), NatType::OpenInternet);
+        assert_eq!(NatType::classify(M::EndpointIndependent, F::EndpointIndependent), NatType::FullCone);
+        assert_eq!(NatType::classify(M::EndpointIndependent, F::AddressDependent), NatType::RestrictedCone);
+        assert_eq!(
+            NatType::classify(M::EndpointIndependent, F::AddressAndPortDependent),
+            NatType::PortRestrictedCone
+        );
+        assert_eq!(NatType::classify(M::AddressAndPortDependent, F::AddressAndPortDependent), NatType::Symmetric);
+        assert_eq!(NatType::classify(M::EndpointIndependent, F::Unknown), NatType::Unknown);
+        assert!(!NatType::Symmetric.direct_connection_likely());
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let stats = LatencyStats::from_samples(4, &[Duration::from_millis(10), Duration::from_millis(30)]);
+        assert_eq!(stats.received, 2);
+        assert_eq!(stats.min_ms, Some(10.0));
+        assert_eq!(stats.avg_ms, Some(20.0));
+        assert_eq!(stats.max_ms, Some(30.0));
+        assert_eq!(stats.loss_percent(), 50.0);
+    }
+}
@@ -4,7 +4,7 @@ use tokio::sync::{RwLock, mpsc};
 use tracing::{info, error, warn, debug};
 use uuid::Uuid;
 
-use crate::p2p_discovery::{P2PDiscovery, PeerInfo, DiscoveryEvent, P2PConnectionManager};
+use crate::p2p_discovery::{P2PDiscovery, PeerInfo, DiscoveryEvent, P2PConnectionManager, P2P_SIGNALING_PORT};
 use crate::p2p_signaling::{P2PSignaling, P2PSignalingEvent};
 use crate::webrtc_session::WebRTCSession;
 use genxlink_protocol::{DeviceId, SignalingMessage};
@@ -56,7 +56,7 @@ impl P2PIntegration {
         
         let (discovery, _) = P2PDiscovery::new(device_id.clone(), device_name.clone());
         let (signaling, _) = P2PSignaling::new(device_id.clone(), device_name.clone());
-        let connection_manager = P2PConnectionManager::new(device_id.clone(), device_name.clone());
+        let connection_manager = P2PConnectionManager::with_discovery(device_id.clone(), device_name.clone(), discovery.clone());
         
         let integration = Self {
             device_id: device_id.clone(),
@@ -96,22 +96,26 @@ impl P2PIntegration {
     /// Start event handlers for discovery and signaling events
     async fn start_event_handlers(&mut self) {
         let event_tx = self.event_tx.clone();
-        let state = self.state.clone();
-        let device_id = self.device_id.clone();
+        let mut discovery_events = self.discovery.subscribe();
         
         // Handle discovery events
-        // Note: In a real implementation, we'd get discovery events from the discovery service
-        // For now, we'll simulate this with periodic checks
-        
         tokio::spawn(async move {
-            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(10));
-            
             loop {
-                interval.tick().await;
-                
-                // Simulate peer discovery
-                // In a real implementation, this would be triggered by actual discovery events
-                debug!("P2P integration heartbeat - checking for peers");
+                match discovery_events.recv().await {
+                    Ok(DiscoveryEvent::PeerDiscovered(peer)) => {
+                        let _ = event_tx.send(P2PIntegrationEvent::PeerDiscovered(peer));
+                    }
+                    Ok(DiscoveryEvent::PeerUpdated(peer)) => {
+                        debug!("Peer updated: {} ({})", peer.device_name, peer.device_id);
+                    }
+                    Ok(DiscoveryEvent::PeerLost(device_id)) => {
+                        debug!("Peer left the network: {}", device_id);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Dropped {} discovery events", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
             }
         });
     }
@@ -136,7 +140,7 @@ impl P2PIntegration {
         // Create WebRTC session
         let session = WebRTCSession::new(
             self.device_id.clone(),
-            format!("ws://{}:{}/ws", peer_info.ip_address, peer_info.port)
+            format!("ws://{}:{}/ws", peer_info.ip_address, P2P_SIGNALING_PORT)
         );
         
         // Start streaming session
@@ -1,20 +1,25 @@
 use anyhow::{Result, anyhow, Context};
-use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::{RwLock, mpsc, Mutex};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message, WebSocketStream, MaybeTlsStream};
+use tokio::net::TcpListener;
+use tokio_tungstenite::{accept_async, connect_async, tungstenite::Message, WebSocketStream};
 use tracing::{info, error, warn, debug};
-use uuid::Uuid;
 use futures::{SinkExt, StreamExt};
 
-use crate::p2p_discovery::{P2PDiscovery, PeerInfo, DiscoveryEvent};
+use crate::p2p_discovery::{P2PDiscovery, PeerInfo, DiscoveryEvent, P2P_SIGNALING_PORT};
 use crate::webrtc_session::WebRTCSession;
-use genxlink_protocol::{DeviceId, SignalingMessage};
+use genxlink_protocol::{DeviceId, MessageId, SignalingEnvelope, SignalingMessage};
 
 /// Direct P2P signaling without central server
 /// Uses WebSocket connections between peers for SDP exchange
+///
+/// Peers speak the same protocol as the signaling server: each side sends
+/// `Register` with its device ID first, then offers, answers and candidates
+/// as `SignalingEnvelope`s. There is no server challenge on a direct link, so
+/// `Register` carries no proof. An answer carries the offer's id in `reply_to`.
 pub struct P2PSignaling {
     device_id: DeviceId,
     discovery: P2PDiscovery,
@@ -22,6 +27,7 @@ pub struct P2PSignaling {
     active_peer_connections: Arc<RwLock<HashMap<DeviceId, PeerWebSocketConnection>>>,
     pending_offers: Arc<RwLock<HashMap<String, PendingOffer>>>,
     event_tx: mpsc::UnboundedSender<P2PSignalingEvent>,
+    next_id: AtomicU64,
 }
 
 /// WebSocket connection to a peer
@@ -29,7 +35,6 @@ pub struct P2PSignaling {
 pub struct PeerWebSocketConnection {
     device_id: DeviceId,
     websocket_tx: mpsc::UnboundedSender<Message>,
-    message_rx: mpsc::UnboundedReceiver<P2PSignalingMessage>,
     last_seen: chrono::DateTime<chrono::Utc>,
 }
 
@@ -46,7 +51,8 @@ pub enum ConnectionState {
 pub enum P2PSignalingEvent {
     PeerConnected(DeviceId),
     PeerDisconnected(DeviceId),
-    OfferReceived { from_device: DeviceId, offer: String },
+    /// Pass `offer_id` to [`P2PSignaling::send_answer`] to correlate the answer
+    OfferReceived { from_device: DeviceId, offer: String, offer_id: Option<MessageId> },
     AnswerReceived { from_device: DeviceId, answer: String },
     IceCandidateReceived { from_device: DeviceId, candidate: String },
     ConnectionFailed(DeviceId, String),
@@ -61,26 +67,6 @@ struct PendingOffer {
     created_at: std::time::SystemTime,
 }
 
-/// Direct signaling message for P2P communication
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct P2PSignalingMessage {
-    message_type: P2PMessageType,
-    from_device: DeviceId,
-    to_device: DeviceId,
-    offer_id: Option<String>,
-    payload: String,
-    timestamp: std::time::SystemTime,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-enum P2PMessageType {
-    Offer,
-    Answer,
-    IceCandidate,
-    Ping,
-    Pong,
-}
-
 impl P2PSignaling {
     /// Create a new P2P signaling service
     pub fn new(device_id: DeviceId, device_name: String) -> (Self, mpsc::UnboundedReceiver<P2PSignalingEvent>) {
@@ -94,6 +80,7 @@ impl P2PSignaling {
             active_peer_connections: Arc::new(RwLock::new(HashMap::new())),
             pending_offers: Arc::new(RwLock::new(HashMap::new())),
             event_tx,
+            next_id: AtomicU64::new(1),
         };
         
         (signaling, event_rx)
@@ -117,7 +104,7 @@ impl P2PSignaling {
 
     /// Start WebSocket server for peer connections
     async fn start_websocket_server(&mut self) -> Result<()> {
-        let addr: std::net::SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        let addr = std::net::SocketAddr::from(([0, 0, 0, 0], P2P_SIGNALING_PORT));
         let listener = TcpListener::bind(addr).await
             .context("Failed to bind WebSocket server")?;
         
@@ -136,7 +123,13 @@ impl P2PSignaling {
                 let device_id_clone = device_id.clone();
                 
                 tokio::spawn(async move {
-                    match Self::handle_peer_websocket(stream, device_id_clone, active_connections_clone, event_tx_clone).await {
+                    let result = match accept_async(stream).await {
+                        Ok(ws_stream) => {
+                            Self::handle_peer_websocket(ws_stream, device_id_clone, active_connections_clone, event_tx_clone).await
+                        }
+                        Err(e) => Err(anyhow!("Failed to accept WebSocket connection: {}", e)),
+                    };
+                    match result {
                         Ok(peer_device_id) => {
                             info!("Successfully established WebSocket connection with peer: {}", peer_device_id);
                         }
@@ -151,19 +144,18 @@ impl P2PSignaling {
         Ok(())
     }
 
-    /// Handle incoming WebSocket connection from a peer (server-side)
-    async fn handle_peer_websocket(
-        stream: TcpStream,
+    /// Exchange signaling with a peer over an established WebSocket until it closes
+    async fn handle_peer_websocket<S>(
+        ws_stream: WebSocketStream<S>,
         my_device_id: DeviceId,
         active_connections: Arc<RwLock<HashMap<DeviceId, PeerWebSocketConnection>>>,
         event_tx: mpsc::UnboundedSender<P2PSignalingEvent>,
-    ) -> Result<DeviceId> {
-        let ws_stream = accept_async(stream).await
-            .context("Failed to accept WebSocket connection")?;
-        
+    ) -> Result<DeviceId>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         let (mut ws_sender, mut ws_receiver) = ws_stream.split();
         let (message_tx, mut message_rx) = mpsc::unbounded_channel::<Message>();
-        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<P2PSignalingMessage>();
         
         // Handle outgoing messages
         tokio::spawn(async move {
@@ -175,213 +167,87 @@ impl P2PSignaling {
             }
         });
         
-        // Handle incoming messages
-        let response_tx_clone = response_tx.clone();
-        tokio::spawn(async move {
-            while let Some(msg) = ws_receiver.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        if let Ok(signaling_msg) = serde_json::from_str::<P2PSignalingMessage>(&text) {
-                            let _ = response_tx_clone.send(signaling_msg);
-                        }
-                    }
-                    Ok(Message::Close(_)) => break,
-                    Err(_) => break,
-                    _ => {}
-                }
-            }
-        });
+        // Introduce ourselves; the peer does the same
+        send_envelope(&message_tx, &SignalingMessage::Register {
+            device_id: my_device_id,
+            device_name: None,
+            proof: None,
+        }.into())?;
         
-        // Process signaling messages
         let mut peer_device_id: Option<DeviceId> = None;
-        let mut connection_stored = false;
-        let active_connections_clone = active_connections.clone();
-        let event_tx_clone = event_tx.clone();
         
-        while let Some(signaling_msg) = response_rx.recv().await {
-            if peer_device_id.is_none() {
-                peer_device_id = Some(signaling_msg.from_device.clone());
-                
-                if !connection_stored {
-                    let connection = PeerWebSocketConnection {
-                        device_id: signaling_msg.from_device.clone(),
-                        websocket_tx: message_tx.clone(),
-                        message_rx: mpsc::unbounded_channel::<P2PSignalingMessage>().1,
-                        last_seen: chrono::Utc::now(),
-                    };
-                    
-                    let mut connections = active_connections_clone.write().await;
-                    connections.insert(signaling_msg.from_device.clone(), connection);
-                    connection_stored = true;
+        while let Some(msg) = ws_receiver.next().await {
+            let text = match msg {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) | Err(_) => break,
+                _ => continue,
+            };
+            let envelope = match serde_json::from_str::<SignalingEnvelope>(&text) {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    warn!("Ignoring malformed signaling message: {}", e);
+                    continue;
                 }
-                
-                let _ = event_tx_clone.send(P2PSignalingEvent::PeerConnected(signaling_msg.from_device.clone()));
-            }
+            };
             
-            match signaling_msg.message_type {
-                P2PMessageType::Offer => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::OfferReceived {
-                        from_device: signaling_msg.from_device,
-                        offer: signaling_msg.payload,
-                    });
-                }
-                P2PMessageType::Answer => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::AnswerReceived {
-                        from_device: signaling_msg.from_device,
-                        answer: signaling_msg.payload,
-                    });
-                }
-                P2PMessageType::IceCandidate => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::IceCandidateReceived {
-                        from_device: signaling_msg.from_device,
-                        candidate: signaling_msg.payload,
-                    });
-                }
-                P2PMessageType::Ping => {
-                    let pong_msg = P2PSignalingMessage {
-                        message_type: P2PMessageType::Pong,
-                        from_device: my_device_id.clone(),
-                        to_device: signaling_msg.from_device,
-                        offer_id: None,
-                        payload: "pong".to_string(),
-                        timestamp: std::time::SystemTime::now(),
-                    };
-                    if let Ok(text) = serde_json::to_string(&pong_msg) {
-                        let _ = message_tx.send(Message::Text(text));
-                    }
-                }
-                P2PMessageType::Pong => {
-                    debug!("Received pong from peer: {}", signaling_msg.from_device);
-                }
-            }
-        }
-        
-        if let Some(device_id) = peer_device_id {
-            let mut connections = active_connections.write().await;
-            connections.remove(&device_id);
-            let _ = event_tx.send(P2PSignalingEvent::PeerDisconnected(device_id.clone()));
-            return Ok(device_id);
-        }
-        
-        Err(anyhow!("Peer connection established but device ID not determined"))
-    }
-
-    /// Handle client-initiated WebSocket connection
-    async fn handle_client_websocket(
-        ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
-        my_device_id: DeviceId,
-        active_connections: Arc<RwLock<HashMap<DeviceId, PeerWebSocketConnection>>>,
-        event_tx: mpsc::UnboundedSender<P2PSignalingEvent>,
-    ) -> Result<DeviceId> {
-        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-        let (message_tx, mut message_rx) = mpsc::unbounded_channel::<Message>();
-        let (response_tx, mut response_rx) = mpsc::unbounded_channel::<P2PSignalingMessage>();
-        
-        // Handle outgoing messages
-        tokio::spawn(async move {
-            while let Some(msg) = message_rx.recv().await {
-                if let Err(e) = ws_sender.send(msg).await {
-                    error!("Failed to send WebSocket message: {:?}", e);
-                    break;
-                }
-            }
-        });
-        
-        // Handle incoming messages
-        let response_tx_clone = response_tx.clone();
-        tokio::spawn(async move {
-            while let Some(msg) = ws_receiver.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        if let Ok(signaling_msg) = serde_json::from_str::<P2PSignalingMessage>(&text) {
-                            let _ = response_tx_clone.send(signaling_msg);
-                        }
-                    }
-                    Ok(Message::Close(_)) => break,
-                    Err(_) => break,
-                    _ => {}
-                }
-            }
-        });
-        
-        // Process signaling messages
-        let mut peer_device_id: Option<DeviceId> = None;
-        let mut connection_stored = false;
-        let active_connections_clone = active_connections.clone();
-        let event_tx_clone = event_tx.clone();
-        
-        while let Some(signaling_msg) = response_rx.recv().await {
-            if peer_device_id.is_none() {
-                peer_device_id = Some(signaling_msg.from_device.clone());
-                
-                // Create a new channel for the connection
-                let (_conn_tx, _conn_rx) = mpsc::unbounded_channel::<Message>();
-                
-                // Store the connection (without moving response_rx)
-                if !connection_stored {
+            if let SignalingMessage::Register { device_id, .. } = &envelope.message {
+                if peer_device_id.is_none() {
                     let connection = PeerWebSocketConnection {
-                        device_id: signaling_msg.from_device.clone(),
+                        device_id: device_id.clone(),
                         websocket_tx: message_tx.clone(),
-                        message_rx: mpsc::unbounded_channel::<P2PSignalingMessage>().1, // Dummy receiver
                         last_seen: chrono::Utc::now(),
                     };
-                    
-                    let mut connections = active_connections_clone.write().await;
-                    connections.insert(signaling_msg.from_device.clone(), connection);
-                    drop(connections);
-                    connection_stored = true;
+                    active_connections.write().await.insert(device_id.clone(), connection);
+                    peer_device_id = Some(device_id.clone());
+                    let _ = event_tx.send(P2PSignalingEvent::PeerConnected(device_id.clone()));
                 }
-                
-                let _ = event_tx_clone.send(P2PSignalingEvent::PeerConnected(signaling_msg.from_device.clone()));
+                continue;
+            }
+            
+            let Some(from_device) = peer_device_id.clone() else {
+                warn!("Peer sent {:?} before registering", envelope.message);
+                continue;
+            };
+            if let Some(connection) = active_connections.write().await.get_mut(&from_device) {
+                connection.last_seen = chrono::Utc::now();
             }
             
-            // Process the signaling message
-            match signaling_msg.message_type {
-                P2PMessageType::Offer => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::OfferReceived {
-                        from_device: signaling_msg.from_device,
-                        offer: signaling_msg.payload,
+            match envelope.message {
+                SignalingMessage::Offer { sdp, .. } => {
+                    let _ = event_tx.send(P2PSignalingEvent::OfferReceived {
+                        from_device,
+                        offer: sdp,
+                        offer_id: envelope.id,
                     });
                 }
-                P2PMessageType::Answer => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::AnswerReceived {
-                        from_device: signaling_msg.from_device,
-                        answer: signaling_msg.payload,
+                SignalingMessage::Answer { sdp, .. } => {
+                    let _ = event_tx.send(P2PSignalingEvent::AnswerReceived {
+                        from_device,
+                        answer: sdp,
                     });
                 }
-                P2PMessageType::IceCandidate => {
-                    let _ = event_tx_clone.send(P2PSignalingEvent::IceCandidateReceived {
-                        from_device: signaling_msg.from_device,
-                        candidate: signaling_msg.payload,
+                SignalingMessage::IceCandidate { candidate, .. } => {
+                    let _ = event_tx.send(P2PSignalingEvent::IceCandidateReceived {
+                        from_device,
+                        candidate,
                     });
                 }
-                P2PMessageType::Ping => {
-                    // Send pong response
-                    let pong_msg = P2PSignalingMessage {
-                        message_type: P2PMessageType::Pong,
-                        from_device: my_device_id.clone(),
-                        to_device: signaling_msg.from_device,
-                        offer_id: None,
-                        payload: "pong".to_string(),
-                        timestamp: std::time::SystemTime::now(),
-                    };
-                    
-                    if let Ok(text) = serde_json::to_string(&pong_msg) {
-                        let _ = message_tx.send(Message::Text(text));
-                    }
+                SignalingMessage::Ping => {
+                    let pong = SignalingEnvelope { id: None, reply_to: envelope.id, message: SignalingMessage::Pong };
+                    let _ = send_envelope(&message_tx, &pong);
+                }
+                SignalingMessage::Pong => {
+                    debug!("Received pong from peer: {}", from_device);
                 }
-                P2PMessageType::Pong => {
-                    debug!("Received pong from peer: {}", signaling_msg.from_device);
+                other => {
+                    debug!("Ignoring {:?} from peer {}", other, from_device);
                 }
             }
         }
         
         // Cleanup - tasks will be dropped when function returns
         if let Some(device_id) = peer_device_id {
-            let mut connections = active_connections.write().await;
-            connections.remove(&device_id);
-            drop(connections);
-            
+            active_connections.write().await.remove(&device_id);
             let _ = event_tx.send(P2PSignalingEvent::PeerDisconnected(device_id.clone()));
             return Ok(device_id);
         }
@@ -403,7 +269,7 @@ impl P2PSignaling {
 
     /// Connect to a peer's WebSocket endpoint
     pub async fn connect_to_peer(&self, peer_info: &PeerInfo) -> Result<()> {
-        let peer_url = format!("ws://{}:{}/ws", peer_info.ip_address, peer_info.port);
+        let peer_url = format!("ws://{}:{}/ws", peer_info.ip_address, P2P_SIGNALING_PORT);
         info!("Connecting to peer WebSocket: {}", peer_url);
         
         let (ws_stream, _) = connect_async(&peer_url).await
@@ -414,7 +280,7 @@ impl P2PSignaling {
         let event_tx = self.event_tx.clone();
         
         tokio::spawn(async move {
-            match Self::handle_client_websocket(ws_stream, device_id, active_connections, event_tx).await {
+            match Self::handle_peer_websocket(ws_stream, device_id, active_connections, event_tx).await {
                 Ok(peer_device_id) => {
                     info!("Successfully connected to peer: {}", peer_device_id);
                 }
@@ -428,66 +294,57 @@ impl P2PSignaling {
     }
 
     /// Send an offer to a peer
-    pub async fn send_offer(&self, to_device: DeviceId, offer_sdp: String) -> Result<String> {
-        let offer_id = Uuid::new_v4().to_string();
-        
-        let message = P2PSignalingMessage {
-            message_type: P2PMessageType::Offer,
-            from_device: self.device_id.clone(),
-            to_device,
-            offer_id: Some(offer_id.clone()),
-            payload: offer_sdp,
-            timestamp: std::time::SystemTime::now(),
+    ///
+    /// Returns the offer's id, which the peer's answer refers to.
+    pub async fn send_offer(&self, to_device: DeviceId, offer_sdp: String) -> Result<MessageId> {
+        let offer_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = SignalingMessage::Offer {
+            sdp: offer_sdp,
+            from: self.device_id.clone(),
+            to: to_device.clone(),
         };
         
-        self.send_signaling_message(message).await?;
+        self.send_signaling_message(&to_device, SignalingEnvelope::request(offer_id, message)).await?;
         Ok(offer_id)
     }
 
-    /// Send an answer to a peer
-    pub async fn send_answer(&self, to_device: DeviceId, answer_sdp: String) -> Result<()> {
-        let message = P2PSignalingMessage {
-            message_type: P2PMessageType::Answer,
-            from_device: self.device_id.clone(),
-            to_device,
-            offer_id: None,
-            payload: answer_sdp,
-            timestamp: std::time::SystemTime::now(),
+    /// Send an answer to a peer, in reply to the offer with `offer_id`
+    pub async fn send_answer(&self, to_device: DeviceId, answer_sdp: String, offer_id: Option<MessageId>) -> Result<()> {
+        let envelope = SignalingEnvelope {
+            id: None,
+            reply_to: offer_id,
+            message: SignalingMessage::Answer {
+                sdp: answer_sdp,
+                from: self.device_id.clone(),
+                to: to_device.clone(),
+            },
         };
         
-        self.send_signaling_message(message).await
+        self.send_signaling_message(&to_device, envelope).await
     }
 
     /// Send ICE candidate to a peer
     pub async fn send_ice_candidate(&self, to_device: DeviceId, candidate: String) -> Result<()> {
-        let message = P2PSignalingMessage {
-            message_type: P2PMessageType::IceCandidate,
-            from_device: self.device_id.clone(),
-            to_device,
-            offer_id: None,
-            payload: candidate,
-            timestamp: std::time::SystemTime::now(),
+        let message = SignalingMessage::IceCandidate {
+            candidate,
+            sdp_mid: None,
+            sdp_m_line_index: None,
+            from: self.device_id.clone(),
+            to: to_device.clone(),
         };
         
-        self.send_signaling_message(message).await
+        self.send_signaling_message(&to_device, message.into()).await
     }
 
     /// Send a signaling message to a peer
-    async fn send_signaling_message(&self, message: P2PSignalingMessage) -> Result<()> {
+    async fn send_signaling_message(&self, to_device: &DeviceId, envelope: SignalingEnvelope) -> Result<()> {
         let connections = self.active_peer_connections.read().await;
         
-        if let Some(connection) = connections.get(&message.to_device) {
-            if let Ok(text) = serde_json::to_string(&message) {
-                if let Err(e) = connection.websocket_tx.send(Message::Text(text)) {
-                    error!("Failed to send signaling message: {}", e);
-                    return Err(anyhow!("Failed to send message to peer: {}", e));
-                }
-            }
+        if let Some(connection) = connections.get(to_device) {
+            send_envelope(&connection.websocket_tx, &envelope)
         } else {
-            return Err(anyhow!("No active connection to peer: {}", message.to_device));
+            Err(anyhow!("No active connection to peer: {}", to_device))
         }
-        
-        Ok(())
     }
 
     /// Get all active peer connections
@@ -524,3 +381,67 @@ impl P2PSignaling {
         connections.clear();
     }
 }
+
+fn send_envelope(websocket_tx: &mpsc::UnboundedSender<Message>, envelope: &SignalingEnvelope) -> Result<()> {
+    let text = serde_json::to_string(envelope).context("Failed to serialize signaling message")?;
+    websocket_tx.send(Message::Text(text)).map_err(|e| {
+        error!("Failed to send signaling message: {}", e);
+        anyhow!("Failed to send message to peer: {}", e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Connections = Arc<RwLock<HashMap<DeviceId, PeerWebSocketConnection>>>;
+
+    async fn next_event(events: &mut mpsc::UnboundedReceiver<P2PSignalingEvent>) -> P2PSignalingEvent {
+        tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .expect("timed out waiting for an event")
+            .expect("event channel closed")
+    }
+
+    #[tokio::test]
+    async fn test_peers_register_and_correlate_offers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (host_id, controller_id) = (DeviceId::new(), DeviceId::new());
+
+        let host_connections: Connections = Arc::new(RwLock::new(HashMap::new()));
+        let (host_tx, mut host_events) = mpsc::unbounded_channel();
+        let (id, connections) = (host_id.clone(), host_connections.clone());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let ws_stream = accept_async(stream).await.unwrap();
+            let _ = P2PSignaling::handle_peer_websocket(ws_stream, id, connections, host_tx).await;
+        });
+
+        let controller_connections: Connections = Arc::new(RwLock::new(HashMap::new()));
+        let (controller_tx, mut controller_events) = mpsc::unbounded_channel();
+        let (ws_stream, _) = connect_async(format!("ws://{}", address)).await.unwrap();
+        let (id, connections) = (controller_id.clone(), controller_connections.clone());
+        tokio::spawn(P2PSignaling::handle_peer_websocket(ws_stream, id, connections, controller_tx));
+
+        // Each side learns the other's ID from its Register
+        assert!(matches!(next_event(&mut host_events).await, P2PSignalingEvent::PeerConnected(ref id) if *id == controller_id));
+        assert!(matches!(next_event(&mut controller_events).await, P2PSignalingEvent::PeerConnected(ref id) if *id == host_id));
+
+        let offer = SignalingEnvelope::request(7, SignalingMessage::Offer {
+            sdp: "v=0".to_string(),
+            from: controller_id.clone(),
+            to: host_id.clone(),
+        });
+        send_envelope(&controller_connections.read().await[&host_id].websocket_tx, &offer).unwrap();
+
+        match next_event(&mut host_events).await {
+            P2PSignalingEvent::OfferReceived { from_device, offer, offer_id } => {
+                assert_eq!(from_device, controller_id);
+                assert_eq!(offer, "v=0");
+                assert_eq!(offer_id, Some(7));
+            }
+            other => panic!("expected an offer, got {:?}", other),
+        }
+    }
+}
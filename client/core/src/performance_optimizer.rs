@@ -1,5 +1,7 @@
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
 
 /// Performance metrics for streaming
 #[derive(Debug, Clone)]
@@ -27,6 +29,76 @@ impl Default for PerformanceMetrics {
     }
 }
 
+/// CPU and memory usage of the host, read from `/proc` on Linux
+///
+/// Reports zeros on platforms without `/proc`.
+pub struct SystemUsageSampler {
+    #[cfg(target_os = "linux")]
+    proc_root: PathBuf,
+    /// Busy and total jiffies at the previous sample
+    #[cfg(target_os = "linux")]
+    last_cpu_times: Option<(u64, u64)>,
+}
+
+impl SystemUsageSampler {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(target_os = "linux")]
+            proc_root: PathBuf::from("/proc"),
+            #[cfg(target_os = "linux")]
+            last_cpu_times: None,
+        }
+    }
+
+    /// Read from an alternative `/proc` (used by tests with fixture trees)
+    #[cfg(target_os = "linux")]
+    pub fn with_proc_root(proc_root: &Path) -> Self {
+        Self {
+            proc_root: proc_root.to_path_buf(),
+            last_cpu_times: None,
+        }
+    }
+
+    /// System-wide CPU usage in percent since the previous sample (0 on the
+    /// first) and the resident memory of this process in bytes
+    #[cfg(target_os = "linux")]
+    pub fn sample(&mut self) -> (f32, usize) {
+        use crate::system_info::linux_impl::{page_size, parse_cpu_times, parse_proc_stat};
+
+        let cpu_times = std::fs::read_to_string(self.proc_root.join("stat"))
+            .ok()
+            .and_then(|content| parse_cpu_times(&content));
+        let cpu_usage = match (self.last_cpu_times, cpu_times) {
+            (Some((last_busy, last_total)), Some((busy, total))) if total > last_total => {
+                busy.saturating_sub(last_busy) as f32 * 100.0 / (total - last_total) as f32
+            }
+            _ => 0.0,
+        };
+        if cpu_times.is_some() {
+            self.last_cpu_times = cpu_times;
+        }
+
+        let memory_usage = std::fs::read_to_string(self.proc_root.join("self/stat"))
+            .ok()
+            .and_then(|content| parse_proc_stat(&content))
+            .map(|stat| (stat.rss_pages * page_size()) as usize)
+            .unwrap_or(0);
+
+        (cpu_usage.min(100.0), memory_usage)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn sample(&mut self) -> (f32, usize) {
+        (0.0, 0)
+    }
+}
+
+impl Default for SystemUsageSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Performance monitor for tracking streaming performance
 pub struct PerformanceMonitor {
     frame_times: VecDeque<Duration>,
@@ -36,6 +108,9 @@ pub struct PerformanceMonitor {
     total_frames: u64,
     dropped_frames: u64,
     max_samples: usize,
+    usage: SystemUsageSampler,
+    cpu_usage: f32,
+    memory_usage: usize,
 }
 
 impl PerformanceMonitor {
@@ -49,6 +124,9 @@ impl PerformanceMonitor {
             total_frames: 0,
             dropped_frames: 0,
             max_samples: 60, // Track last 60 frames
+            usage: SystemUsageSampler::new(),
+            cpu_usage: 0.0,
+            memory_usage: 0,
         }
     }
 
@@ -79,6 +157,14 @@ impl PerformanceMonitor {
         self.dropped_frames += 1;
     }
 
+    /// Sample CPU and memory usage for the following metrics
+    ///
+    /// CPU usage covers the time since the previous call, so call this at a
+    /// steady interval (e.g. once a second).
+    pub fn sample_system_usage(&mut self) {
+        (self.cpu_usage, self.memory_usage) = self.usage.sample();
+    }
+
     /// Get current performance metrics
     pub fn get_metrics(&self) -> PerformanceMetrics {
         let elapsed = self.start_time.elapsed();
@@ -111,8 +197,8 @@ impl PerformanceMonitor {
             avg_encode_time,
             dropped_frames: self.dropped_frames,
             total_frames: self.total_frames,
-            cpu_usage: 0.0, // TODO: Implement CPU monitoring
-            memory_usage: 0, // TODO: Implement memory monitoring
+            cpu_usage: self.cpu_usage,
+            memory_usage: self.memory_usage,
         }
     }
 
@@ -221,7 +307,7 @@ impl QualityPreset {
 }
 
 /// Quality settings
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct QualitySettings {
     pub width: u32,
     pub height: u32,
@@ -344,6 +430,31 @@ mod tests {
         assert!(metrics.fps > 0.0);
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_system_usage_from_proc() {
+        let root = std::env::temp_dir().join(format!("genxlink-usage-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("self")).unwrap();
+        std::fs::write(
+            root.join("self/stat"),
+            "42 (genxlink) S 1 42 42 0 -1 0 0 0 0 0 10 5 0 0 20 0 4 0 100 1000000 256 0",
+        )
+        .unwrap();
+        std::fs::write(root.join("stat"), "cpu  100 0 100 800 0 0 0 0 0 0\n").unwrap();
+
+        let mut sampler = SystemUsageSampler::with_proc_root(&root);
+        let (cpu, memory) = sampler.sample();
+        assert_eq!(cpu, 0.0);
+        assert_eq!(memory, 256 * crate::system_info::linux_impl::page_size() as usize);
+
+        // 300 of the next 400 jiffies busy
+        std::fs::write(root.join("stat"), "cpu  250 0 250 900 0 0 0 0 0 0\n").unwrap();
+        let (cpu, _) = sampler.sample();
+        assert_eq!(cpu, 75.0);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
     #[test]
     fn test_quality_presets() {
         let low = QualityPreset::Low.get_settings();
@@ -55,6 +55,7 @@ pub enum Permission {
     
     // System
     SeeSystemInformation,
+    KillProcesses,
     DrawOnScreen,
     CreateTcpTunnels,
     
@@ -81,6 +82,7 @@ impl Permission {
             Self::AccessClipboardForFileTransfer => "Access my device's clipboard to transfer files",
             Self::UseFileManager => "Use File Manager",
             Self::SeeSystemInformation => "See my system information",
+            Self::KillProcesses => "End processes on my device",
             Self::DrawOnScreen => "Draw on my device's screen",
             Self::CreateTcpTunnels => "Create TCP tunnels",
             Self::RecordSession => "Record the session",
@@ -96,7 +98,8 @@ impl Permission {
             Self::EnablePrivacyMode | Self::ShowColoredCursor => PermissionCategory::Privacy,
             Self::AccessClipboard | Self::AccessClipboardForFileTransfer | 
             Self::UseFileManager => PermissionCategory::Files,
-            Self::SeeSystemInformation | Self::DrawOnScreen | Self::CreateTcpTunnels => PermissionCategory::System,
+            Self::SeeSystemInformation | Self::KillProcesses | Self::DrawOnScreen | 
+            Self::CreateTcpTunnels => PermissionCategory::System,
             Self::RecordSession => PermissionCategory::Recording,
             Self::InteractWithRestrictedWindows => PermissionCategory::Advanced,
         }
@@ -167,11 +170,13 @@ impl PermissionProfile {
                 self.set_permission(Permission::BlockInputDevices, true);
                 self.set_permission(Permission::LockDevice, true);
                 self.set_permission(Permission::SignOutUser, true);
+                self.set_permission(Permission::EnablePrivacyMode, true);
                 self.set_permission(Permission::ShowColoredCursor, true);
                 self.set_permission(Permission::AccessClipboard, true);
                 self.set_permission(Permission::AccessClipboardForFileTransfer, true);
                 self.set_permission(Permission::UseFileManager, true);
                 self.set_permission(Permission::SeeSystemInformation, true);
+                self.set_permission(Permission::KillProcesses, true);
                 self.set_permission(Permission::DrawOnScreen, true);
                 self.set_permission(Permission::CreateTcpTunnels, true);
                 self.set_permission(Permission::RecordSession, true);
@@ -184,11 +189,13 @@ impl PermissionProfile {
                 self.set_permission(Permission::SendCtrlAltDel, true);
                 self.set_permission(Permission::BlockInputDevices, true);
                 self.set_permission(Permission::LockDevice, true);
+                self.set_permission(Permission::EnablePrivacyMode, true);
                 self.set_permission(Permission::ShowColoredCursor, true);
                 self.set_permission(Permission::AccessClipboard, true);
                 self.set_permission(Permission::AccessClipboardForFileTransfer, true);
                 self.set_permission(Permission::UseFileManager, true);
                 self.set_permission(Permission::SeeSystemInformation, true);
+                self.set_permission(Permission::KillProcesses, true);
                 self.set_permission(Permission::DrawOnScreen, true);
                 self.set_permission(Permission::CreateTcpTunnels, true);
                 self.set_permission(Permission::RecordSession, true);
@@ -312,6 +319,7 @@ impl PermissionProfileManager {
             Permission::AccessClipboardForFileTransfer,
             Permission::UseFileManager,
             Permission::SeeSystemInformation,
+            Permission::KillProcesses,
             Permission::DrawOnScreen,
             Permission::CreateTcpTunnels,
             Permission::RecordSession,
@@ -0,0 +1,487 @@
+//! Connection quality measurement
+//!
+//! While streaming, the host samples its peer connection's statistics once a
+//! second: round trip time and loss from the RTCP receiver reports the viewer
+//! sends back, the outbound RTP counters for the rate actually sent, and the
+//! viewer's REMB bandwidth estimate when it sends one. Together with CPU load
+//! they feed `AdaptiveBitrateController`, whose bitrate picks the frame rate
+//! and resolution of the stream from a ladder. Every sample also becomes a
+//! `QualityReport` for the viewer.
+//!
+//! Once the viewer sends transport-cc feedback, the controller switches from
+//! its RTT and loss thresholds to the delay-based `GoogCcEstimator`.
+
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::packet::Packet as RtcpPacket;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_cc::TransportLayerCc;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::stats::{StatsReport, StatsReportType};
+
+use genxlink_protocol::QualityReport;
+
+use crate::adaptive_bitrate::AdaptiveBitrateController;
+use crate::congestion_control::{GoogCcEstimator, PacketFeedback, SendHistory};
+use crate::performance_optimizer::{QualitySettings, SystemUsageSampler};
+
+/// Time between samples
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// System CPU usage (percent) above which the frame rate is halved
+pub const HIGH_CPU_USAGE: f32 = 85.0;
+
+/// Bounds of the delay-based estimate in kbps
+const MIN_ESTIMATE_KBPS: u32 = 300;
+const MAX_ESTIMATE_KBPS: u32 = 20_000;
+
+/// Lowest frame rate the CPU limit goes down to
+const MIN_FPS: u32 = 5;
+
+/// Samples a better resolution or frame rate must stay affordable before
+/// switching to it, so a bitrate hovering at a ladder step doesn't flap
+const UPGRADE_SAMPLES: u32 = 5;
+
+/// Stream settings by bitrate, best first: (minimum kbps, width, height, fps)
+///
+/// Width and height bound the picture; the source aspect ratio is kept.
+const LADDER: [(u32, u32, u32, u32); 5] = [
+    (4000, 2560, 1440, 30),
+    (2000, 1920, 1080, 30),
+    (1000, 1280, 720, 30),
+    (600, 1280, 720, 15),
+    (0, 960, 540, 10),
+];
+
+/// Cumulative video counters from one stats report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RtpCounters {
+    /// Bytes sent on the video streams
+    pub bytes_sent: u64,
+    /// Packets the viewer reported as received
+    pub packets_received: u64,
+    /// Packets the viewer reported as lost
+    pub packets_lost: i64,
+    /// Fraction lost in the viewer's latest receiver report
+    pub fraction_lost: Option<f64>,
+    pub round_trip_time: Option<Duration>,
+}
+
+impl RtpCounters {
+    /// Video counters from a peer connection's stats
+    ///
+    /// The round trip time comes from RTCP receiver reports, or from the
+    /// nominated ICE candidate pair until the first report arrives.
+    pub fn from_report(report: &StatsReport) -> Self {
+        let mut counters = Self::default();
+        let mut pair_rtt = None;
+        for stats in report.reports.values() {
+            match stats {
+                StatsReportType::OutboundRTP(stats) if stats.kind == "video" => {
+                    counters.bytes_sent += stats.bytes_sent;
+                }
+                StatsReportType::RemoteInboundRTP(stats) if stats.kind == "video" => {
+                    counters.packets_received += stats.packets_received;
+                    counters.packets_lost += stats.packets_lost;
+                    counters.fraction_lost = Some(counters.fraction_lost.unwrap_or(0.0).max(stats.fraction_lost));
+                    if let Some(rtt) = stats.round_trip_time.filter(|rtt| *rtt > 0.0) {
+                        counters.round_trip_time = Some(Duration::from_secs_f64(rtt));
+                    }
+                }
+                StatsReportType::CandidatePair(stats) if stats.nominated && stats.current_round_trip_time > 0.0 => {
+                    pair_rtt = Some(Duration::from_secs_f64(stats.current_round_trip_time));
+                }
+                _ => {}
+            }
+        }
+        counters.round_trip_time = counters.round_trip_time.or(pair_rtt);
+        counters
+    }
+}
+
+/// Network conditions over one sample interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkSample {
+    pub rtt: Option<Duration>,
+    /// Fraction of packets lost, 0.0 to 1.0
+    pub packet_loss: f32,
+    /// Rate actually sent, in kbps
+    pub send_kbps: u32,
+}
+
+impl NetworkSample {
+    /// Conditions between two reads of the counters `elapsed` apart
+    pub fn between(previous: &RtpCounters, current: &RtpCounters, elapsed: Duration) -> Self {
+        let received = current.packets_received.saturating_sub(previous.packets_received);
+        // Duplicates can make the lost count go down
+        let lost = (current.packets_lost - previous.packets_lost).max(0) as u64;
+        let packet_loss = if received + lost > 0 {
+            lost as f32 / (received + lost) as f32
+        } else {
+            current.fraction_lost.unwrap_or(0.0) as f32
+        };
+
+        let seconds = elapsed.as_secs_f64();
+        let send_kbps = if seconds > 0.0 {
+            (current.bytes_sent.saturating_sub(previous.bytes_sent) as f64 * 8.0 / seconds / 1000.0) as u32
+        } else {
+            0
+        };
+
+        Self {
+            rtt: current.round_trip_time,
+            packet_loss: packet_loss.clamp(0.0, 1.0),
+            send_kbps,
+        }
+    }
+}
+
+/// Stream settings for `bitrate_kbps` with the system CPU at `cpu_usage` percent
+pub fn settings_for_bitrate(bitrate_kbps: u32, cpu_usage: f32) -> QualitySettings {
+    let (_, width, height, fps) = LADDER
+        .iter()
+        .copied()
+        .find(|(min_kbps, ..)| bitrate_kbps >= *min_kbps)
+        .unwrap_or(LADDER[LADDER.len() - 1]);
+
+    // An overloaded host can't capture and encode at full rate; fewer frames
+    // leave each one more bits too
+    let fps = if cpu_usage > HIGH_CPU_USAGE { (fps / 2).max(MIN_FPS) } else { fps };
+
+    QualitySettings {
+        width,
+        height,
+        fps,
+        bitrate: bitrate_kbps * 1000,
+    }
+}
+
+/// Result of one sample
+#[derive(Debug, Clone)]
+pub struct QualityUpdate {
+    pub report: QualityReport,
+    /// New stream settings, if they changed
+    pub settings: Option<QualitySettings>,
+}
+
+/// Turns stats samples into quality reports and stream settings
+pub struct QualityMonitor {
+    controller: AdaptiveBitrateController,
+    settings: QualitySettings,
+    previous: Option<(Instant, RtpCounters)>,
+    upgrade_samples: u32,
+    delay_based: bool,
+}
+
+impl QualityMonitor {
+    /// Start from `initial` settings
+    pub fn new(initial: QualitySettings) -> Self {
+        let mut controller = AdaptiveBitrateController::new(initial.bitrate / 1000);
+        // Samples already arrive at the adjustment pace
+        controller.set_adjustment_interval(Duration::ZERO);
+        Self {
+            controller,
+            settings: initial,
+            previous: None,
+            upgrade_samples: 0,
+            delay_based: false,
+        }
+    }
+
+    /// Current stream settings
+    pub fn settings(&self) -> QualitySettings {
+        self.settings
+    }
+
+    /// Account for transport-cc feedback from the viewer
+    ///
+    /// The first feedback switches bitrate control to delay-based
+    /// estimation, starting from the current bitrate.
+    pub fn on_packet_feedback(&mut self, feedback: &[PacketFeedback]) {
+        if !self.delay_based {
+            self.delay_based = true;
+            self.controller.set_estimator(Box::new(GoogCcEstimator::new(
+                self.controller.current_bitrate(),
+                MIN_ESTIMATE_KBPS,
+                MAX_ESTIMATE_KBPS,
+            )));
+        }
+        self.controller.on_packet_feedback(feedback);
+    }
+
+    /// Account for the counters read at `now`
+    ///
+    /// `remb_kbps` is the viewer's latest bandwidth estimate, `fps` the frame
+    /// rate actually sent and `cpu_usage` the system CPU usage in percent.
+    /// Returns `None` for the first read, which only sets the baseline.
+    pub fn update(
+        &mut self,
+        counters: RtpCounters,
+        remb_kbps: Option<u32>,
+        fps: u32,
+        cpu_usage: f32,
+        now: Instant,
+    ) -> Option<QualityUpdate> {
+        let (last_time, last) = self.previous.replace((now, counters))?;
+        let sample = NetworkSample::between(&last, &counters, now.saturating_duration_since(last_time));
+
+        // Without a round trip time there is no feedback from the viewer yet
+        if let Some(rtt) = sample.rtt {
+            self.controller.update_metrics(rtt, sample.packet_loss, remb_kbps.unwrap_or(0));
+            self.controller.adjust_bitrate();
+        }
+
+        let target = settings_for_bitrate(self.controller.current_bitrate(), cpu_usage);
+        let target = if pixel_rate(&target) <= pixel_rate(&self.settings) {
+            self.upgrade_samples = 0;
+            target
+        } else if self.upgrade_samples + 1 < UPGRADE_SAMPLES {
+            // Better settings are affordable; keep the bitrate but hold the picture
+            self.upgrade_samples += 1;
+            QualitySettings {
+                bitrate: target.bitrate,
+                ..self.settings
+            }
+        } else {
+            self.upgrade_samples = 0;
+            target
+        };
+
+        let changed = target != self.settings;
+        self.settings = target;
+
+        Some(QualityUpdate {
+            report: QualityReport {
+                latency_ms: sample.rtt.map_or(0, |rtt| rtt.as_millis() as u32),
+                packet_loss: sample.packet_loss,
+                fps,
+                bandwidth_kbps: remb_kbps.unwrap_or(sample.send_kbps),
+            },
+            settings: changed.then_some(target),
+        })
+    }
+}
+
+fn pixel_rate(settings: &QualitySettings) -> u64 {
+    settings.width as u64 * settings.height as u64 * settings.fps as u64
+}
+
+/// Sample `peer_connection` every `SAMPLE_INTERVAL` until it closes
+///
+/// `frames_sent` counts the frames written to `sender`'s track and
+/// `send_history` the send times of its transport-cc stamped packets.
+/// Changed stream settings are published to `settings` and every sample's
+/// report to `reports`. NACKs, keyframe requests and receiver reports go
+/// to `loss_feedback`, for `VideoStreamer::handle_rtcp`, when the stream
+/// recovers from loss itself.
+pub fn spawn_quality_monitor(
+    peer_connection: Arc<RTCPeerConnection>,
+    sender: Arc<RTCRtpSender>,
+    send_history: Arc<Mutex<SendHistory>>,
+    frames_sent: Arc<AtomicU64>,
+    settings: Arc<watch::Sender<QualitySettings>>,
+    reports: Arc<watch::Sender<Option<QualityReport>>>,
+    loss_feedback: Option<mpsc::UnboundedSender<Vec<Box<dyn RtcpPacket + Send + Sync>>>>,
+) -> JoinHandle<()> {
+    let remb_kbps = Arc::new(AtomicU32::new(0));
+    let (feedback_tx, mut feedback_rx) = mpsc::unbounded_channel();
+    tokio::spawn(read_rtcp(sender, send_history, Arc::clone(&remb_kbps), feedback_tx, loss_feedback));
+
+    tokio::spawn(async move {
+        let mut monitor = QualityMonitor::new(*settings.borrow());
+        let mut usage = SystemUsageSampler::new();
+        let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+        let mut last_frames = (Instant::now(), frames_sent.load(Ordering::Relaxed));
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                Some(feedback) = feedback_rx.recv() => {
+                    monitor.on_packet_feedback(&feedback);
+                    continue;
+                }
+            }
+            if peer_connection.connection_state() == RTCPeerConnectionState::Closed {
+                break;
+            }
+
+            let report = peer_connection.get_stats().await;
+            let now = Instant::now();
+            let frames = frames_sent.load(Ordering::Relaxed);
+            let elapsed = now.duration_since(last_frames.0).as_secs_f64();
+            let fps = if elapsed > 0.0 { ((frames - last_frames.1) as f64 / elapsed).round() as u32 } else { 0 };
+            last_frames = (now, frames);
+
+            let (cpu_usage, _) = usage.sample();
+            let remb = Some(remb_kbps.load(Ordering::Relaxed)).filter(|kbps| *kbps > 0);
+
+            let Some(update) = monitor.update(RtpCounters::from_report(&report), remb, fps, cpu_usage, now) else {
+                continue;
+            };
+            if let Some(new_settings) = update.settings {
+                tracing::debug!(
+                    "Stream quality now {}x{} at {} fps, {} kbps (loss {:.1}%, RTT {} ms, CPU {:.0}%)",
+                    new_settings.width,
+                    new_settings.height,
+                    new_settings.fps,
+                    new_settings.bitrate / 1000,
+                    update.report.packet_loss * 100.0,
+                    update.report.latency_ms,
+                    cpu_usage
+                );
+                settings.send_replace(new_settings);
+            }
+            reports.send_replace(Some(update.report));
+        }
+        tracing::debug!("Quality monitor stopped");
+    })
+}
+
+/// Read RTCP arriving for `sender`, keeping the latest REMB estimate and
+/// passing on transport-cc feedback matched against `send_history`, and
+/// loss feedback to `loss_feedback`
+///
+/// Reading is also what lets the interceptors act on receiver reports and
+/// NACKs. Ends when the sender stops.
+async fn read_rtcp(
+    sender: Arc<RTCRtpSender>,
+    send_history: Arc<Mutex<SendHistory>>,
+    remb_kbps: Arc<AtomicU32>,
+    feedback: mpsc::UnboundedSender<Vec<PacketFeedback>>,
+    loss_feedback: Option<mpsc::UnboundedSender<Vec<Box<dyn RtcpPacket + Send + Sync>>>>,
+) {
+    while let Ok((packets, _)) = sender.read_rtcp().await {
+        let mut loss = Vec::new();
+        for packet in packets {
+            let any = packet.as_any();
+            if let Some(remb) = any.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+                remb_kbps.store((remb.bitrate / 1000.0) as u32, Ordering::Relaxed);
+            } else if let Some(transport_cc) = any.downcast_ref::<TransportLayerCc>() {
+                let packets = send_history.lock().on_transport_feedback(transport_cc);
+                if !packets.is_empty() && feedback.send(packets).is_err() {
+                    return;
+                }
+            } else if any.is::<TransportLayerNack>()
+                || any.is::<PictureLossIndication>()
+                || any.is::<FullIntraRequest>()
+                || any.is::<ReceiverReport>()
+            {
+                loss.push(packet);
+            }
+        }
+        if let Some(loss_feedback) = loss_feedback.as_ref().filter(|_| !loss.is_empty()) {
+            // The stream may have ended before the peer connection
+            let _ = loss_feedback.send(loss);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(bytes_sent: u64, packets_received: u64, packets_lost: i64, rtt_ms: u64) -> RtpCounters {
+        RtpCounters {
+            bytes_sent,
+            packets_received,
+            packets_lost,
+            fraction_lost: None,
+            round_trip_time: Some(Duration::from_millis(rtt_ms)),
+        }
+    }
+
+    #[test]
+    fn test_sample_between_reads() {
+        let previous = counters(1_000_000, 900, 10, 30);
+        let current = counters(1_250_000, 1090, 20, 40);
+        let sample = NetworkSample::between(&previous, &current, Duration::from_secs(1));
+
+        assert_eq!(sample.rtt, Some(Duration::from_millis(40)));
+        assert_eq!(sample.packet_loss, 0.05);
+        assert_eq!(sample.send_kbps, 2000);
+
+        // No new receiver report: fall back to its fraction lost
+        let mut current = previous;
+        current.fraction_lost = Some(0.25);
+        assert_eq!(NetworkSample::between(&previous, &current, Duration::from_secs(1)).packet_loss, 0.25);
+    }
+
+    #[test]
+    fn test_counters_from_empty_report() {
+        let report = StatsReport {
+            reports: Default::default(),
+        };
+        assert_eq!(RtpCounters::from_report(&report), RtpCounters::default());
+    }
+
+    #[test]
+    fn test_settings_ladder() {
+        let best = settings_for_bitrate(8000, 10.0);
+        assert_eq!((best.width, best.height, best.fps, best.bitrate), (2560, 1440, 30, 8_000_000));
+
+        let poor = settings_for_bitrate(500, 10.0);
+        assert_eq!((poor.width, poor.height, poor.fps), (960, 540, 10));
+
+        // A busy host halves the frame rate
+        assert_eq!(settings_for_bitrate(2500, 95.0).fps, 15);
+    }
+
+    #[test]
+    fn test_loss_lowers_quality_and_recovery_waits() {
+        let mut monitor = QualityMonitor::new(settings_for_bitrate(2000, 0.0));
+        let start = Instant::now();
+        let at = |second: u64| start + Duration::from_secs(second);
+
+        assert!(monitor.update(counters(0, 0, 0, 20), None, 30, 10.0, at(0)).is_none());
+
+        // 20% loss every second
+        let mut second = 0;
+        let mut lowered = None;
+        while lowered.is_none() && second < 10 {
+            second += 1;
+            let update = monitor
+                .update(counters(second * 250_000, second * 80, second as i64 * 20, 20), None, 30, 10.0, at(second))
+                .unwrap();
+            assert_eq!(update.report.packet_loss, 0.2);
+            assert_eq!(update.report.latency_ms, 20);
+            assert_eq!(update.report.bandwidth_kbps, 2000);
+            lowered = update.settings;
+        }
+        let lowered = lowered.expect("loss should lower the settings");
+        assert!(lowered.height < 1080 || lowered.bitrate < 2_000_000);
+
+        // Clean again with a generous REMB: the bitrate climbs back, and the
+        // resolution follows once it has been affordable for a few samples
+        let lost = second as i64 * 20;
+        let base = second;
+        let mut affordable_at = None;
+        let mut upgraded_at = None;
+        for clean in 1..=40 {
+            second += 1;
+            let update = monitor
+                .update(counters(second * 250_000, base * 80 + clean * 100, lost, 20), Some(20_000), 30, 10.0, at(second))
+                .unwrap();
+            assert_eq!(update.report.packet_loss, 0.0);
+            assert_eq!(update.report.bandwidth_kbps, 20_000);
+            if affordable_at.is_none() && monitor.settings().bitrate >= 2_000_000 {
+                affordable_at = Some(clean);
+            }
+            if update.settings.is_some_and(|s| s.height > lowered.height) {
+                upgraded_at = Some(clean);
+                break;
+            }
+        }
+        assert_eq!(upgraded_at.unwrap() - affordable_at.unwrap(), UPGRADE_SAMPLES as u64 - 1);
+    }
+}
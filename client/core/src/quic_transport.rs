@@ -0,0 +1,904 @@
+//! QUIC transport
+//!
+//! [`Transport`] over a single QUIC connection. Control, input, clipboard
+//! and file messages each travel on their own stream, so a large file chunk
+//! never holds up a key press; video frames go out as unreliable datagrams,
+//! where a lost fragment drops that frame instead of stalling the ones
+//! behind it. Each side presents a self-signed certificate for its Ed25519
+//! device key: the dialer pins the host's key from its signed mDNS
+//! announcement, the host pins the dialer's key on first use. Dialers can
+//! move to a new local address mid-session (connection migration).
+//!
+//! The host listens on UDP at the same port number the LAN direct-connect
+//! listener uses over TCP, so one mDNS record advertises both.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::RwLock;
+use quinn::{Connection, Endpoint, RecvStream, SendStream};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::server::{ClientCertVerified, ClientCertVerifier};
+use rustls::{Certificate, CertificateError, DistinguishedName, PrivateKey, ServerName};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use genxlink_protocol::{
+    ConnectionRequest, ConnectionResponse, DeviceId, Message, MessagePayload, SessionId, MAX_MESSAGE_SIZE,
+    PROTOCOL_VERSION,
+};
+
+use crate::device_identity::{self, PUBLIC_KEY_LEN};
+use crate::lan_direct::{self, LocalDevice, RemoteDevice};
+use crate::mdns::PeerInfo;
+use crate::p2p_discovery::P2PDiscovery;
+use crate::session_password::SessionPasswordManager;
+use crate::transport::Transport;
+use crate::ClientError;
+
+/// ALPN protocol identifier
+pub const QUIC_ALPN: &[u8] = b"genxlink/1";
+
+/// Name both sides put in (and expect from) device certificates
+const SERVER_NAME: &str = "genxlink";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Received messages waiting for [`Transport::receive`]
+const INCOMING_QUEUE: usize = 256;
+
+/// Video frames kept while waiting for their missing fragments
+const MAX_PARTIAL_FRAMES: usize = 8;
+
+/// Frame ID (u32), fragment index (u16), fragment count (u16)
+const FRAGMENT_HEADER_LEN: usize = 8;
+
+/// Logical channel a message travels on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Control,
+    Input,
+    Clipboard,
+    File,
+    /// Unreliable datagrams
+    Video,
+}
+
+impl Channel {
+    /// Channels carried on reliable streams, in the order the dialer opens them
+    const STREAMS: [Channel; 4] = [Channel::Control, Channel::Input, Channel::Clipboard, Channel::File];
+
+    /// Channel a payload is sent on
+    pub fn for_payload(payload: &MessagePayload) -> Self {
+        match payload {
+            MessagePayload::VideoFrame(_) => Channel::Video,
+            MessagePayload::KeyboardEvent(_) | MessagePayload::MouseEvent(_) => Channel::Input,
+            MessagePayload::ClipboardSync(_) => Channel::Clipboard,
+            MessagePayload::FileTransferRequest(_)
+            | MessagePayload::FileTransferAccept(_)
+            | MessagePayload::FileTransferReject(_)
+            | MessagePayload::FileChunk(_)
+            | MessagePayload::FileTransferComplete(_)
+            | MessagePayload::FileTransferCancel(_) => Channel::File,
+            _ => Channel::Control,
+        }
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Channel::Control => 0,
+            Channel::Input => 1,
+            Channel::Clipboard => 2,
+            Channel::File => 3,
+            Channel::Video => 4,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        Self::STREAMS.into_iter().find(|channel| channel.tag() == tag)
+    }
+}
+
+/// An established QUIC session
+struct QuicSession {
+    connection: Connection,
+    session_id: SessionId,
+    sequence: u64,
+    remote: RemoteDevice,
+    streams: HashMap<Channel, SendStream>,
+    incoming: mpsc::Receiver<Message>,
+    next_frame_id: u32,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl QuicSession {
+    fn start(
+        connection: Connection,
+        session_id: SessionId,
+        remote: RemoteDevice,
+        streams: Vec<(Channel, SendStream, RecvStream)>,
+    ) -> Self {
+        let (incoming_tx, incoming) = mpsc::channel(INCOMING_QUEUE);
+
+        let mut send_streams = HashMap::new();
+        let mut readers = Vec::new();
+        for (channel, send, recv) in streams {
+            send_streams.insert(channel, send);
+            readers.push(tokio::spawn(read_stream(channel, recv, incoming_tx.clone())));
+        }
+        readers.push(tokio::spawn(read_datagrams(connection.clone(), incoming_tx)));
+
+        Self {
+            connection,
+            session_id,
+            sequence: 0,
+            remote,
+            streams: send_streams,
+            incoming,
+            next_frame_id: 0,
+            readers,
+        }
+    }
+
+    async fn send(&mut self, message: &Message) -> Result<(), ClientError> {
+        let channel = Channel::for_payload(&message.payload);
+        let data = serde_json::to_vec(message)
+            .map_err(|e| ClientError::TransportError(format!("Failed to encode message: {}", e)))?;
+
+        if channel == Channel::Video {
+            return self.send_datagrams(&data);
+        }
+
+        let stream = self
+            .streams
+            .get_mut(&channel)
+            .ok_or_else(|| ClientError::TransportError(format!("No {:?} stream", channel)))?;
+        lan_direct::write_frame(stream, &data).await
+    }
+
+    /// Split an encoded video message across datagrams
+    fn send_datagrams(&mut self, data: &[u8]) -> Result<(), ClientError> {
+        let max_size = self
+            .connection
+            .max_datagram_size()
+            .ok_or_else(|| ClientError::TransportError("Peer does not accept datagrams".to_string()))?;
+        let chunk_size = max_size.saturating_sub(FRAGMENT_HEADER_LEN).max(1);
+        let count = data.len().div_ceil(chunk_size).max(1);
+        let count = u16::try_from(count)
+            .map_err(|_| ClientError::TransportError(format!("Video frame of {} bytes is too large", data.len())))?;
+
+        let frame_id = self.next_frame_id;
+        self.next_frame_id = self.next_frame_id.wrapping_add(1);
+
+        for (index, fragment) in data.chunks(chunk_size).enumerate() {
+            let mut datagram = Vec::with_capacity(FRAGMENT_HEADER_LEN + fragment.len());
+            datagram.extend_from_slice(&frame_id.to_be_bytes());
+            datagram.extend_from_slice(&(index as u16).to_be_bytes());
+            datagram.extend_from_slice(&count.to_be_bytes());
+            datagram.extend_from_slice(fragment);
+            self.connection
+                .send_datagram(Bytes::from(datagram))
+                .map_err(|e| ClientError::TransportError(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for QuicSession {
+    fn drop(&mut self) {
+        for reader in &self.readers {
+            reader.abort();
+        }
+    }
+}
+
+/// [`Transport`] over QUIC to an mDNS-discovered peer
+pub struct QuicTransport {
+    local: LocalDevice,
+    endpoint: Endpoint,
+    discovery: Option<P2PDiscovery>,
+    password: String,
+    session: Option<QuicSession>,
+}
+
+impl QuicTransport {
+    /// Create a dialing transport bound to `address` (port 0 for any)
+    pub fn bind(local: LocalDevice, address: SocketAddr) -> Result<Self, ClientError> {
+        let endpoint = Endpoint::client(address).map_err(lan_direct::transport_error)?;
+        Ok(Self {
+            local,
+            endpoint,
+            discovery: None,
+            password: String::new(),
+            session: None,
+        })
+    }
+
+    /// Resolve device IDs passed to [`Transport::connect`] through LAN discovery
+    pub fn with_discovery(mut self, discovery: P2PDiscovery) -> Self {
+        self.discovery = Some(discovery);
+        self
+    }
+
+    /// Session password presented to the host
+    pub fn with_password(mut self, password: String) -> Self {
+        self.password = password;
+        self
+    }
+
+    /// Dial a discovered peer directly
+    pub async fn connect_to(&mut self, peer: &PeerInfo) -> Result<SessionId, ClientError> {
+        let mut last_error = ClientError::TransportError(format!("{} has no known address", peer.device_id));
+
+        for ip in &peer.addresses {
+            let address = SocketAddr::new(*ip, peer.port);
+            match tokio::time::timeout(CONNECT_TIMEOUT, self.dial(peer, address)).await {
+                Ok(Ok(session)) => {
+                    let session_id = session.session_id;
+                    self.session = Some(session);
+                    return Ok(session_id);
+                }
+                // The host itself refused the session; other addresses reach the same host
+                Ok(Err(e @ ClientError::PermissionDenied(_))) => return Err(e),
+                Ok(Err(e)) => {
+                    debug!("QUIC connection to {} failed: {}", address, e);
+                    last_error = e;
+                }
+                Err(_) => {
+                    debug!("QUIC connection to {} timed out", address);
+                    last_error = ClientError::TransportError(format!("Timed out connecting to {}", address));
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    async fn dial(&self, peer: &PeerInfo, address: SocketAddr) -> Result<QuicSession, ClientError> {
+        let config = client_config(&self.local, &peer.public_key)?;
+        let connection = self
+            .endpoint
+            .connect_with(config, address, SERVER_NAME)
+            .map_err(|e| ClientError::TransportError(e.to_string()))?
+            .await
+            .map_err(connection_error)?;
+
+        // The TLS handshake has already checked the host's key against the pinned one
+        let (mut control_send, mut control_recv) = open_stream(&connection, Channel::Control).await?;
+        let session_id = SessionId::new();
+        let request = Message {
+            session_id,
+            sequence: 0,
+            payload: MessagePayload::ConnectionRequest(ConnectionRequest {
+                device_id: self.local.device_id.clone(),
+                password: self.password.clone(),
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: self.local.capabilities.clone(),
+            }),
+        };
+        write_message(&mut control_send, &request).await?;
+
+        let reply = read_message(&mut control_recv)
+            .await?
+            .ok_or_else(|| ClientError::TransportError("Host closed the connection".to_string()))?;
+        let MessagePayload::ConnectionResponse(response) = reply.payload else {
+            return Err(ClientError::TransportError("Expected a connection response".to_string()));
+        };
+        if !response.accepted {
+            return Err(ClientError::PermissionDenied(
+                response.reason.unwrap_or_else(|| "Connection rejected".to_string()),
+            ));
+        }
+
+        let mut streams = vec![(Channel::Control, control_send, control_recv)];
+        for channel in &Channel::STREAMS[1..] {
+            let (send, recv) = open_stream(&connection, *channel).await?;
+            streams.push((*channel, send, recv));
+        }
+
+        let remote = RemoteDevice {
+            device_id: peer.device_id.clone(),
+            device_name: peer.device_name.clone(),
+            public_key: peer.public_key.clone(),
+            address,
+            capabilities: response.server_capabilities,
+        };
+        info!("Connected to {} over QUIC at {}", remote.device_id, address);
+        Ok(QuicSession::start(connection, session_id, remote, streams))
+    }
+
+    /// Move the connection to a new local address without interrupting the session
+    pub fn rebind(&self, address: SocketAddr) -> Result<(), ClientError> {
+        let socket = std::net::UdpSocket::bind(address).map_err(lan_direct::transport_error)?;
+        self.endpoint.rebind(socket).map_err(lan_direct::transport_error)
+    }
+
+    /// Local address of the underlying endpoint
+    pub fn local_addr(&self) -> Result<SocketAddr, ClientError> {
+        self.endpoint.local_addr().map_err(lan_direct::transport_error)
+    }
+
+    /// Current address of the remote end, which follows its migrations
+    pub fn remote_address(&self) -> Option<SocketAddr> {
+        self.session.as_ref().map(|session| session.connection.remote_address())
+    }
+
+    /// The authenticated remote device
+    pub fn remote(&self) -> Option<&RemoteDevice> {
+        self.session.as_ref().map(|session| &session.remote)
+    }
+
+    pub fn session_id(&self) -> Option<SessionId> {
+        self.session.as_ref().map(|session| session.session_id)
+    }
+
+    /// Send a payload in the current session
+    pub async fn send_payload(&mut self, payload: MessagePayload) -> Result<(), ClientError> {
+        let session = self.session()?;
+        session.sequence += 1;
+        let message = Message {
+            session_id: session.session_id,
+            sequence: session.sequence,
+            payload,
+        };
+        session.send(&message).await
+    }
+
+    fn session(&mut self) -> Result<&mut QuicSession, ClientError> {
+        self.session
+            .as_mut()
+            .ok_or_else(|| ClientError::TransportError("Not connected".to_string()))
+    }
+}
+
+#[async_trait]
+impl Transport for QuicTransport {
+    async fn connect(&mut self, remote_id: &DeviceId) -> Result<SessionId, ClientError> {
+        let discovery = self
+            .discovery
+            .as_ref()
+            .ok_or_else(|| ClientError::TransportError("LAN discovery is not configured".to_string()))?;
+        let peer = discovery.get_peer(remote_id).await.ok_or_else(|| {
+            ClientError::TransportError(format!("{} has not been discovered on the LAN", remote_id))
+        })?;
+
+        self.connect_to(&peer).await
+    }
+
+    async fn send(&mut self, message: &Message) -> Result<(), ClientError> {
+        self.session()?.send(message).await
+    }
+
+    async fn receive(&mut self) -> Result<Option<Message>, ClientError> {
+        Ok(self.session()?.incoming.recv().await)
+    }
+
+    async fn disconnect(&mut self) -> Result<(), ClientError> {
+        if let Some(mut session) = self.session.take() {
+            for stream in session.streams.values_mut() {
+                let _ = stream.finish().await;
+            }
+            session.connection.close(0u32.into(), b"disconnect");
+        }
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| session.connection.close_reason().is_none())
+    }
+}
+
+/// Listener accepting QUIC sessions
+pub struct QuicListener {
+    endpoint: Endpoint,
+    task: JoinHandle<()>,
+}
+
+struct ListenerState {
+    local: LocalDevice,
+    endpoint: Endpoint,
+    passwords: Arc<Mutex<SessionPasswordManager>>,
+    /// Keys of devices that have authenticated before, pinned on first use
+    pinned_keys: RwLock<HashMap<DeviceId, Vec<u8>>>,
+}
+
+impl QuicListener {
+    /// Listen on `address`; authenticated sessions arrive on the returned channel
+    pub fn start(
+        address: SocketAddr,
+        local: LocalDevice,
+        passwords: Arc<Mutex<SessionPasswordManager>>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<QuicTransport>), ClientError> {
+        let endpoint = Endpoint::server(server_config(&local)?, address).map_err(lan_direct::transport_error)?;
+        info!("Accepting QUIC sessions on {}", endpoint.local_addr().map_err(lan_direct::transport_error)?);
+
+        let state = Arc::new(ListenerState {
+            local,
+            endpoint: endpoint.clone(),
+            passwords,
+            pinned_keys: RwLock::new(HashMap::new()),
+        });
+        let (session_tx, session_rx) = mpsc::unbounded_channel();
+
+        let limits = Arc::new(lan_direct::HandshakeLimits::default());
+        let task = tokio::spawn(async move {
+            while let Some(connecting) = state.endpoint.accept().await {
+                let Some(slot) = limits.try_acquire(connecting.remote_address().ip()) else {
+                    debug!("Dropping QUIC connection from {}: too many handshakes in progress", connecting.remote_address());
+                    continue;
+                };
+                let state = state.clone();
+                let session_tx = session_tx.clone();
+                tokio::spawn(async move {
+                    let _slot = slot;
+                    match tokio::time::timeout(CONNECT_TIMEOUT, state.accept(connecting)).await {
+                        Ok(Ok(transport)) => {
+                            let _ = session_tx.send(transport);
+                        }
+                        Ok(Err(e)) => warn!("Rejected QUIC connection: {}", e),
+                        Err(_) => warn!("QUIC handshake timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok((Self { endpoint, task }, session_rx))
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, ClientError> {
+        self.endpoint.local_addr().map_err(lan_direct::transport_error)
+    }
+
+    /// Stop accepting connections; established sessions stay open
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+}
+
+impl Drop for QuicListener {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl ListenerState {
+    async fn accept(&self, connecting: quinn::Connecting) -> Result<QuicTransport, ClientError> {
+        let connection = connecting.await.map_err(connection_error)?;
+        let address = connection.remote_address();
+        let public_key = peer_key(&connection)?;
+
+        let (mut control_send, mut control_recv) = accept_stream(&connection, Channel::Control).await?;
+        let request = read_message(&mut control_recv)
+            .await?
+            .ok_or_else(|| ClientError::TransportError("Dialer closed the connection".to_string()))?;
+        let session_id = request.session_id;
+        let MessagePayload::ConnectionRequest(request) = request.payload else {
+            return Err(ClientError::TransportError("Expected a connection request".to_string()));
+        };
+
+        let verdict = match self.pinned_keys.read().get(&request.device_id) {
+            Some(pinned) if *pinned != public_key => Err("Device key does not match the pinned key".to_string()),
+            _ => Ok(()),
+        };
+        let verdict = match verdict {
+            Ok(()) => lan_direct::authorize_request(&self.passwords, &request, address.ip()).await,
+            rejected => rejected,
+        };
+
+        let response = Message {
+            session_id,
+            sequence: 0,
+            payload: MessagePayload::ConnectionResponse(ConnectionResponse {
+                accepted: verdict.is_ok(),
+                reason: verdict.as_ref().err().cloned(),
+                server_capabilities: self.local.capabilities.clone(),
+            }),
+        };
+        write_message(&mut control_send, &response).await?;
+        if let Err(reason) = verdict {
+            let _ = control_send.finish().await;
+            return Err(ClientError::PermissionDenied(reason));
+        }
+
+        self.pinned_keys
+            .write()
+            .entry(request.device_id.clone())
+            .or_insert_with(|| public_key.clone());
+
+        let mut streams = vec![(Channel::Control, control_send, control_recv)];
+        for channel in &Channel::STREAMS[1..] {
+            let (send, recv) = accept_stream(&connection, *channel).await?;
+            streams.push((*channel, send, recv));
+        }
+
+        let remote = RemoteDevice {
+            device_id: request.device_id,
+            device_name: String::new(),
+            public_key,
+            address,
+            capabilities: request.capabilities,
+        };
+        info!("Accepted QUIC session {} from {} ({})", session_id, remote.device_id, address);
+
+        Ok(QuicTransport {
+            local: self.local.clone(),
+            endpoint: self.endpoint.clone(),
+            discovery: None,
+            password: String::new(),
+            session: Some(QuicSession::start(connection, session_id, remote, streams)),
+        })
+    }
+}
+
+/// Reassembles fragmented video frames; frames missing a fragment are dropped
+#[derive(Default)]
+struct FrameAssembler {
+    partial: VecDeque<PartialFrame>,
+}
+
+struct PartialFrame {
+    id: u32,
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+impl FrameAssembler {
+    /// Add a datagram; returns the frame once all of its fragments have arrived
+    fn push(&mut self, datagram: &[u8]) -> Option<Vec<u8>> {
+        if datagram.len() < FRAGMENT_HEADER_LEN {
+            return None;
+        }
+        let id = u32::from_be_bytes(datagram[0..4].try_into().ok()?);
+        let index = u16::from_be_bytes(datagram[4..6].try_into().ok()?) as usize;
+        let count = u16::from_be_bytes(datagram[6..8].try_into().ok()?) as usize;
+        let fragment = &datagram[FRAGMENT_HEADER_LEN..];
+
+        if index >= count {
+            return None;
+        }
+        if count == 1 {
+            return Some(fragment.to_vec());
+        }
+
+        let position = match self.partial.iter().position(|frame| frame.id == id) {
+            Some(position) => position,
+            None => {
+                if self.partial.len() >= MAX_PARTIAL_FRAMES {
+                    self.partial.pop_front();
+                }
+                self.partial.push_back(PartialFrame {
+                    id,
+                    fragments: vec![None; count],
+                    received: 0,
+                });
+                self.partial.len() - 1
+            }
+        };
+
+        let frame = &mut self.partial[position];
+        if frame.fragments.len() != count {
+            return None;
+        }
+        if frame.fragments[index].is_none() {
+            frame.fragments[index] = Some(fragment.to_vec());
+            frame.received += 1;
+        }
+        if frame.received < count {
+            return None;
+        }
+
+        let frame = self.partial.remove(position)?;
+        Some(frame.fragments.into_iter().flatten().flatten().collect())
+    }
+}
+
+async fn read_stream(channel: Channel, mut recv: RecvStream, incoming: mpsc::Sender<Message>) {
+    loop {
+        match read_message(&mut recv).await {
+            Ok(Some(message)) => {
+                if incoming.send(message).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                debug!("{:?} stream closed: {}", channel, e);
+                break;
+            }
+        }
+    }
+}
+
+async fn read_datagrams(connection: Connection, incoming: mpsc::Sender<Message>) {
+    let mut assembler = FrameAssembler::default();
+    while let Ok(datagram) = connection.read_datagram().await {
+        let Some(frame) = assembler.push(&datagram) else {
+            continue;
+        };
+        match serde_json::from_slice::<Message>(&frame) {
+            // A late video frame is worthless; drop it rather than wait for room
+            Ok(message) => {
+                if let Err(mpsc::error::TrySendError::Closed(_)) = incoming.try_send(message) {
+                    break;
+                }
+            }
+            Err(e) => debug!("Dropped malformed video frame: {}", e),
+        }
+    }
+}
+
+/// Open a stream for `channel`; the first byte tells the host which one it is
+async fn open_stream(connection: &Connection, channel: Channel) -> Result<(SendStream, RecvStream), ClientError> {
+    let (mut send, recv) = connection.open_bi().await.map_err(connection_error)?;
+    send.write_all(&[channel.tag()]).await.map_err(connection_error)?;
+    Ok((send, recv))
+}
+
+async fn accept_stream(connection: &Connection, expected: Channel) -> Result<(SendStream, RecvStream), ClientError> {
+    let (send, mut recv) = connection.accept_bi().await.map_err(connection_error)?;
+    let tag = recv.read_u8().await.map_err(lan_direct::transport_error)?;
+    match Channel::from_tag(tag) {
+        Some(channel) if channel == expected => Ok((send, recv)),
+        _ => Err(ClientError::TransportError(format!(
+            "Expected the {:?} stream, got tag {}",
+            expected, tag
+        ))),
+    }
+}
+
+async fn write_message(stream: &mut SendStream, message: &Message) -> Result<(), ClientError> {
+    let data = serde_json::to_vec(message)
+        .map_err(|e| ClientError::TransportError(format!("Failed to encode message: {}", e)))?;
+    lan_direct::write_frame(stream, &data).await
+}
+
+async fn read_message(stream: &mut RecvStream) -> Result<Option<Message>, ClientError> {
+    let Some(data) = lan_direct::read_frame(stream, MAX_MESSAGE_SIZE).await? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| ClientError::TransportError(format!("Malformed message: {}", e)))
+}
+
+fn connection_error(e: impl std::fmt::Display) -> ClientError {
+    ClientError::TransportError(e.to_string())
+}
+
+/// Device key from the certificate the peer presented during the handshake
+fn peer_key(connection: &Connection) -> Result<Vec<u8>, ClientError> {
+    let certificates = connection
+        .peer_identity()
+        .and_then(|identity| identity.downcast::<Vec<Certificate>>().ok())
+        .ok_or_else(|| ClientError::AuthenticationError("Peer presented no certificate".to_string()))?;
+    let certificate = certificates
+        .first()
+        .ok_or_else(|| ClientError::AuthenticationError("Peer presented no certificate".to_string()))?;
+    certificate_key(certificate).map_err(|e| ClientError::AuthenticationError(e.to_string()))
+}
+
+/// The Ed25519 device key a certificate was issued for
+fn certificate_key(certificate: &Certificate) -> Result<Vec<u8>, rustls::Error> {
+    let (_, parsed) = x509_parser::parse_x509_certificate(&certificate.0)
+        .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+    let spki = parsed.public_key();
+    if spki.algorithm.algorithm != x509_parser::oid_registry::OID_SIG_ED25519
+        || spki.subject_public_key.data.len() != PUBLIC_KEY_LEN
+    {
+        return Err(rustls::Error::InvalidCertificate(CertificateError::BadEncoding));
+    }
+    Ok(spki.subject_public_key.data.to_vec())
+}
+
+/// PKCS#8 prefix of an Ed25519 private key, up to the 32-byte seed
+const ED25519_PKCS8_PREFIX: [u8; 14] = [
+    0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// Re-encode the device key as a PKCS#8 v1 document
+///
+/// The device key is stored as PKCS#8 v2 in the RFC 8410 layout, which the
+/// TLS stack (built on an older ring) does not parse; v1 carries only the
+/// seed and is read by both.
+fn tls_private_key(pkcs8: &[u8]) -> Result<Vec<u8>, ClientError> {
+    let seed = pkcs8
+        .get(2..48)
+        .filter(|body| body[0..2] == ED25519_PKCS8_PREFIX[0..2] && body[3..14] == ED25519_PKCS8_PREFIX[3..])
+        .map(|body| &body[14..46])
+        .ok_or_else(|| ClientError::TransportError("Device key is not an Ed25519 PKCS#8 key".to_string()))?;
+
+    let mut der = vec![0x30, 0x2e];
+    der.extend_from_slice(&ED25519_PKCS8_PREFIX);
+    der.extend_from_slice(seed);
+    Ok(der)
+}
+
+/// Self-signed certificate for the device key
+fn device_certificate(local: &LocalDevice) -> Result<(Certificate, PrivateKey), ClientError> {
+    let certificate_error = |e: rcgen::RcgenError| ClientError::TransportError(format!("Device certificate: {}", e));
+    let private_key = tls_private_key(local.identity.pkcs8())?;
+
+    let mut params = rcgen::CertificateParams::new(vec![SERVER_NAME.to_string()]);
+    params.alg = &rcgen::PKCS_ED25519;
+    params.key_pair = Some(rcgen::KeyPair::from_der(&private_key).map_err(certificate_error)?);
+    params
+        .distinguished_name
+        .push(rcgen::DnType::CommonName, local.device_id.0.clone());
+    let certificate = rcgen::Certificate::from_params(params).map_err(certificate_error)?;
+
+    Ok((
+        Certificate(certificate.serialize_der().map_err(certificate_error)?),
+        PrivateKey(private_key),
+    ))
+}
+
+fn transport_config() -> Arc<quinn::TransportConfig> {
+    let mut config = quinn::TransportConfig::default();
+    config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    config.max_idle_timeout(IDLE_TIMEOUT.try_into().ok());
+    Arc::new(config)
+}
+
+fn client_config(local: &LocalDevice, host_key: &[u8]) -> Result<quinn::ClientConfig, ClientError> {
+    let (certificate, key) = device_certificate(local)?;
+    let mut crypto = rustls::ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| ClientError::TransportError(e.to_string()))?
+        .with_custom_certificate_verifier(Arc::new(PinnedHostKey {
+            expected: host_key.to_vec(),
+        }))
+        .with_client_auth_cert(vec![certificate], key)
+        .map_err(|e| ClientError::TransportError(e.to_string()))?;
+    crypto.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+    let mut config = quinn::ClientConfig::new(Arc::new(crypto));
+    config.transport_config(transport_config());
+    Ok(config)
+}
+
+fn server_config(local: &LocalDevice) -> Result<quinn::ServerConfig, ClientError> {
+    let (certificate, key) = device_certificate(local)?;
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|e| ClientError::TransportError(e.to_string()))?
+        .with_client_cert_verifier(Arc::new(DeviceClientCert))
+        .with_single_cert(vec![certificate], key)
+        .map_err(|e| ClientError::TransportError(e.to_string()))?;
+    crypto.alpn_protocols = vec![QUIC_ALPN.to_vec()];
+
+    let mut config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+    config.transport_config(transport_config());
+    config.migration(true);
+    Ok(config)
+}
+
+/// Accepts only a host certificate for the key pinned from discovery
+struct PinnedHostKey {
+    expected: Vec<u8>,
+}
+
+impl ServerCertVerifier for PinnedHostKey {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let key = certificate_key(end_entity)?;
+        if key != self.expected {
+            warn!(
+                "Host presented key {}, expected {}",
+                device_identity::fingerprint(&key),
+                device_identity::fingerprint(&self.expected)
+            );
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Accepts any device certificate; the key is bound to a device ID once the
+/// session request names one
+struct DeviceClientCert;
+
+impl ClientCertVerifier for DeviceClientCert {
+    fn client_auth_root_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _now: SystemTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        certificate_key(end_entity).map(|_| ClientCertVerified::assertion())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(id: u32, data: &[u8], chunk: usize) -> Vec<Vec<u8>> {
+        let count = data.len().div_ceil(chunk) as u16;
+        data.chunks(chunk)
+            .enumerate()
+            .map(|(index, fragment)| {
+                [&id.to_be_bytes()[..], &(index as u16).to_be_bytes(), &count.to_be_bytes(), fragment].concat()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_reassembly_out_of_order() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut parts = fragments(7, &data, 100);
+        parts.reverse();
+
+        let mut assembler = FrameAssembler::default();
+        assert!(assembler.push(&parts[0]).is_none());
+        assert!(assembler.push(&parts[1]).is_none());
+        assert_eq!(assembler.push(&parts[2]), Some(data));
+        assert!(assembler.partial.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_frames_are_evicted() {
+        let mut assembler = FrameAssembler::default();
+        for id in 0..(MAX_PARTIAL_FRAMES as u32 + 4) {
+            let parts = fragments(id, &[1u8; 30], 10);
+            assert!(assembler.push(&parts[0]).is_none());
+        }
+        assert_eq!(assembler.partial.len(), MAX_PARTIAL_FRAMES);
+        assert!(assembler.partial.iter().all(|frame| frame.id >= 4));
+    }
+
+    #[test]
+    fn test_certificate_carries_device_key() {
+        let local = LocalDevice {
+            identity: Arc::new(crate::device_identity::DeviceIdentity::generate().unwrap()),
+            device_id: DeviceId::new(),
+            device_name: "Test".to_string(),
+            capabilities: Vec::new(),
+        };
+        let (certificate, _) = device_certificate(&local).unwrap();
+        assert_eq!(certificate_key(&certificate).unwrap(), local.identity.public_key());
+    }
+
+    #[test]
+    fn test_payload_channels() {
+        let keyboard = MessagePayload::KeyboardEvent(genxlink_protocol::KeyboardEvent {
+            key_code: 65,
+            scan_code: 30,
+            pressed: true,
+            modifiers: genxlink_protocol::messages::KeyModifiers {
+                ctrl: false,
+                alt: false,
+                shift: false,
+                meta: false,
+            },
+        });
+        assert_eq!(Channel::for_payload(&keyboard), Channel::Input);
+        assert_eq!(Channel::for_payload(&MessagePayload::Ping), Channel::Control);
+    }
+}
@@ -408,6 +408,7 @@ impl RoleBasedAccessControl {
             Permission::AccessClipboardForFileTransfer,
             Permission::UseFileManager,
             Permission::SeeSystemInformation,
+            Permission::KillProcesses,
             Permission::DrawOnScreen,
             Permission::CreateTcpTunnels,
             Permission::RecordSession,
@@ -1,54 +1,90 @@
 use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
-use webrtc::track::track_local::TrackLocal;
-use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
-use webrtc::api::media_engine::MIME_TYPE_VP8;
-use webrtc::rtp::packet::Packet;
-use bytes::Bytes;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch, Mutex};
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::packet::Packet as RtcpPacket;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
 
+use crate::encoder::{EncoderConfig, H264Encoder, VideoCodec, VideoEncoder};
+use crate::performance_optimizer::QualitySettings;
 use crate::screen_capture::{ScreenCapturer, CaptureFrame, CaptureConfig};
+use crate::streaming::VideoStreamer;
 
-/// Screen streaming manager - captures screen and streams via WebRTC
+/// Rate the screen is captured at; slower stream settings skip frames
+const CAPTURE_FPS: u32 = 30;
+
+/// Captured frames waiting for the encoder; more are dropped, not queued
+const FRAME_QUEUE: usize = 2;
+
+/// Screen streaming manager - captures the screen and streams it as H.264
+/// through a `VideoStreamer`
 pub struct ScreenStreamer {
-    track: Arc<TrackLocalStaticRTP>,
+    streamer: Arc<Mutex<VideoStreamer>>,
     capturer: Arc<Mutex<Option<ScreenCapturer>>>,
     is_streaming: Arc<Mutex<bool>>,
-    sequence_number: Arc<Mutex<u16>>,
-    timestamp: Arc<Mutex<u32>>,
-    ssrc: u32,
+    /// Resolution, frame rate and bitrate to stream at
+    settings: Arc<watch::Sender<QualitySettings>>,
+    frames_sent: Arc<AtomicU64>,
 }
 
 impl ScreenStreamer {
     /// Create a new screen streamer
     pub fn new() -> Result<Self> {
-        // Create VP8 video track (better browser support than H.264)
-        let track = Arc::new(TrackLocalStaticRTP::new(
-            RTCRtpCodecCapability {
-                mime_type: MIME_TYPE_VP8.to_owned(),
-                clock_rate: 90000, // Standard for video
-                channels: 0,
-                sdp_fmtp_line: "".to_owned(),
-                rtcp_feedback: vec![],
-            },
-            "video".to_owned(),
-            "genxlink_screen".to_owned(),
-        ));
+        let settings = QualitySettings::default();
+        let mut encoder = H264Encoder::new();
+        encoder.init(EncoderConfig {
+            width: settings.width,
+            height: settings.height,
+            fps: settings.fps,
+            bitrate: settings.bitrate,
+            codec: VideoCodec::H264,
+        })?;
+        let streamer = VideoStreamer::new(Box::new(encoder))?;
 
         Ok(Self {
-            track,
+            streamer: Arc::new(Mutex::new(streamer)),
             capturer: Arc::new(Mutex::new(None)),
             is_streaming: Arc::new(Mutex::new(false)),
-            sequence_number: Arc::new(Mutex::new(0)),
-            timestamp: Arc::new(Mutex::new(0)),
-            ssrc: rand::random(),
+            settings: Arc::new(watch::channel(settings).0),
+            frames_sent: Arc::new(AtomicU64::new(0)),
         })
     }
 
-    /// Get the video track for adding to peer connection
-    pub fn get_track(&self) -> Arc<TrackLocalStaticRTP> {
-        Arc::clone(&self.track)
+    /// Settings the stream currently follows
+    pub fn settings(&self) -> QualitySettings {
+        *self.settings.borrow()
+    }
+
+    /// Stream at `settings` from the next frame on
+    ///
+    /// Frames are scaled down to fit `width` x `height`, skipped to keep to
+    /// `fps` and encoded at `bitrate`; the frame rate can't go above the
+    /// capture rate.
+    pub fn apply_settings(&self, settings: QualitySettings) {
+        self.settings.send_replace(settings);
+    }
+
+    /// Shared handle for whoever adapts the settings (see `quality_monitor`)
+    pub fn settings_handle(&self) -> Arc<watch::Sender<QualitySettings>> {
+        Arc::clone(&self.settings)
+    }
+
+    /// Counter of frames written to the track
+    pub fn frames_sent(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.frames_sent)
+    }
+
+    /// Add the video and FEC tracks to `peer_connection`, see
+    /// `VideoStreamer::add_to_peer_connection`
+    pub async fn add_to_peer_connection(&self, peer_connection: &RTCPeerConnection) -> Result<Arc<RTCRtpSender>> {
+        Ok(self.streamer.lock().await.add_to_peer_connection(peer_connection).await?)
+    }
+
+    /// Act on loss feedback from the viewer, see `VideoStreamer::handle_rtcp`
+    pub async fn handle_rtcp(&self, packets: &[Box<dyn RtcpPacket + Send + Sync>]) -> Result<()> {
+        Ok(self.streamer.lock().await.handle_rtcp(packets).await?)
     }
 
     /// Start streaming from a specific monitor
@@ -62,30 +98,35 @@ impl ScreenStreamer {
         let config = CaptureConfig {
             monitor_index,
             capture_cursor: true,
-            target_fps: 30,
+            target_fps: CAPTURE_FPS,
         };
         let capturer = ScreenCapturer::new(config)
             .context("Failed to create screen capturer")?;
 
-        // Start capture with streaming callback
-        let track = Arc::clone(&self.track);
-        let seq_num = Arc::clone(&self.sequence_number);
-        let timestamp_arc = Arc::clone(&self.timestamp);
-        let ssrc = self.ssrc;
+        // Frames are encoded in order on one task; the capture callback
+        // only picks the frames to send
+        let (frame_tx, frame_rx) = mpsc::channel(FRAME_QUEUE);
+        tokio::spawn(encode_frames(
+            Arc::clone(&self.streamer),
+            self.settings.subscribe(),
+            Arc::clone(&self.frames_sent),
+            frame_rx,
+        ));
+
+        let settings = self.settings.subscribe();
+        let mut last_sent: Option<Instant> = None;
 
         capturer.start_capture(move |frame| {
-            // Send frame via RTP in a non-blocking way
-            let track = Arc::clone(&track);
-            let seq = Arc::clone(&seq_num);
-            let ts = Arc::clone(&timestamp_arc);
-            
-            // Spawn async task to send frame
-            tokio::spawn(async move {
-                if let Err(e) = Self::send_frame(track, frame, seq, ts, ssrc).await {
-                    tracing::error!("Failed to send frame: {}", e);
-                }
-            });
-            
+            let fps = settings.borrow().fps;
+            if !frame_due(last_sent, frame.timestamp, fps) {
+                return Ok(());
+            }
+            let captured = frame.timestamp;
+            match frame_tx.try_send(frame) {
+                Ok(()) => last_sent = Some(captured),
+                Err(mpsc::error::TrySendError::Full(_)) => tracing::trace!("Encoder busy, dropping frame"),
+                Err(mpsc::error::TrySendError::Closed(_)) => anyhow::bail!("Screen encoder stopped"),
+            }
             Ok(())
         }).await?;
 
@@ -100,49 +141,6 @@ impl ScreenStreamer {
         Ok(())
     }
 
-    /// Send a captured frame via RTP
-    async fn send_frame(
-        _track: Arc<TrackLocalStaticRTP>,
-        frame: CaptureFrame,
-        seq_num: Arc<Mutex<u16>>,
-        timestamp: Arc<Mutex<u32>>,
-        ssrc: u32,
-    ) -> Result<()> {
-        // For now, we'll send raw frame data
-        // TODO: Add VP8 encoding for better compression
-        
-        let mut seq = seq_num.lock().await;
-        let mut ts = timestamp.lock().await;
-
-        // Create RTP packet
-        let _packet = Packet {
-            header: webrtc::rtp::header::Header {
-                version: 2,
-                padding: false,
-                extension: false,
-                marker: true,
-                payload_type: 96, // VP8 payload type
-                sequence_number: *seq,
-                timestamp: *ts,
-                ssrc,
-                ..Default::default()
-            },
-            payload: Bytes::from(frame.data),
-        };
-
-        // TODO: Send packet via WebRTC track
-        // The webrtc crate's TrackLocalStaticRTP uses a different API
-        // We'll implement this when setting up the full peer connection
-        // For now, just log that we would send
-        tracing::trace!("Would send RTP packet: seq={}, ts={}", *seq, *ts);
-
-        // Increment sequence number and timestamp
-        *seq = seq.wrapping_add(1);
-        *ts = ts.wrapping_add(3000); // ~30 FPS (90000 / 30)
-
-        Ok(())
-    }
-
     /// Stop streaming
     pub async fn stop_streaming(&self) -> Result<()> {
         let mut is_streaming = self.is_streaming.lock().await;
@@ -155,7 +153,7 @@ impl ScreenStreamer {
         // Stop capturer
         let mut capturer_guard = self.capturer.lock().await;
         if let Some(capturer) = capturer_guard.as_ref() {
-            capturer.stop_capture();
+            capturer.stop_capture().await;
         }
         *capturer_guard = None;
 
@@ -169,3 +167,132 @@ impl ScreenStreamer {
         *self.is_streaming.lock().await
     }
 }
+
+/// Encode and stream captured frames until the capture stops
+///
+/// Picks up changed `settings` before each frame: the size bounds the
+/// picture and the bitrate and frame rate go to the encoder. Only frames
+/// that made it to the track count in `frames_sent`.
+async fn encode_frames(
+    streamer: Arc<Mutex<VideoStreamer>>,
+    mut settings: watch::Receiver<QualitySettings>,
+    frames_sent: Arc<AtomicU64>,
+    mut frames: mpsc::Receiver<CaptureFrame>,
+) {
+    let started = Instant::now();
+    while let Some(frame) = frames.recv().await {
+        let mut streamer = streamer.lock().await;
+        let current = *settings.borrow_and_update();
+        if let Err(e) = streamer.set_rate(current.bitrate, current.fps).await {
+            tracing::warn!("Failed to change the encoder rate: {}", e);
+        }
+
+        let frame = fit_frame(frame, current.width, current.height);
+        let frame = crate::Frame {
+            width: frame.width,
+            height: frame.height,
+            stride: frame.width * 4, // BGRA
+            timestamp: frame.timestamp.saturating_duration_since(started).as_micros() as u64,
+            data: frame.data,
+        };
+
+        // The encoder may skip a frame to keep to the bitrate
+        let before = streamer.get_stats().frames_sent;
+        match streamer.encode_frame(&frame).await {
+            Ok(()) if streamer.get_stats().frames_sent > before => {
+                frames_sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(()) => {}
+            Err(e) => tracing::error!("Failed to send frame: {}", e),
+        }
+    }
+    tracing::debug!("Screen encoder stopped");
+}
+
+/// Whether a frame captured at `captured` should be sent to keep to `fps`
+///
+/// Allows a little jitter so a 30 fps capture halves cleanly to 15.
+fn frame_due(last_sent: Option<Instant>, captured: Instant, fps: u32) -> bool {
+    let Some(last_sent) = last_sent else {
+        return true;
+    };
+    let interval = Duration::from_secs(1) / fps.max(1);
+    captured.saturating_duration_since(last_sent) >= interval.mul_f32(0.8)
+}
+
+/// Scale a BGRA frame down (nearest neighbour) to fit `max_width` x
+/// `max_height`, keeping its aspect ratio; smaller frames pass unchanged
+fn fit_frame(frame: CaptureFrame, max_width: u32, max_height: u32) -> CaptureFrame {
+    if (frame.width <= max_width && frame.height <= max_height) || frame.width == 0 || frame.height == 0 {
+        return frame;
+    }
+    let scale = (max_width as f64 / frame.width as f64).min(max_height as f64 / frame.height as f64);
+    // Even dimensions for the encoder's 4:2:0 subsampling
+    let width = (((frame.width as f64 * scale) as u32) & !1).max(2);
+    let height = (((frame.height as f64 * scale) as u32) & !1).max(2);
+
+    let src_stride = frame.width as usize * 4;
+    let mut data = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height as usize {
+        let src_y = y * frame.height as usize / height as usize;
+        let row = &frame.data[src_y * src_stride..(src_y + 1) * src_stride];
+        for x in 0..width as usize {
+            let src_x = x * frame.width as usize / width as usize * 4;
+            data.extend_from_slice(&row[src_x..src_x + 4]);
+        }
+    }
+
+    CaptureFrame {
+        width,
+        height,
+        data,
+        timestamp: frame.timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_pacing() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        // 30 fps capture streamed at 15 fps sends every other frame
+        let mut last_sent = None;
+        let sent: Vec<u64> = (0..6)
+            .map(|i| i * 33)
+            .filter(|&ms| {
+                let due = frame_due(last_sent, at(ms), 15);
+                if due {
+                    last_sent = Some(at(ms));
+                }
+                due
+            })
+            .collect();
+        assert_eq!(sent, vec![0, 66, 132]);
+    }
+
+    #[test]
+    fn test_fit_frame() {
+        // 4x4 frame whose pixels hold their own index
+        let data: Vec<u8> = (0..16u8).flat_map(|i| [i, i, i, 255]).collect();
+        let frame = CaptureFrame {
+            width: 4,
+            height: 4,
+            data,
+            timestamp: Instant::now(),
+        };
+
+        // Bounded by the height, aspect ratio kept
+        let small = fit_frame(frame.clone(), 16, 2);
+        assert_eq!((small.width, small.height), (2, 2));
+        let firsts: Vec<u8> = small.data.chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(firsts, vec![0, 2, 8, 10]);
+
+        // Already within the bounds
+        let same = fit_frame(frame, 1920, 1080);
+        assert_eq!((same.width, same.height), (4, 4));
+    }
+}
@@ -7,7 +7,7 @@ use tracing::{info, warn, error, debug};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::auth_service::{AuthService, AuthSession};
+use crate::auth_service::{AuthService, AuthSession, MfaPolicy};
 use crate::database::{UserAccount, UserPreferences, SubscriptionType};
 use crate::webrtc_integration::{WebRTCIntegration, IntegrationState, IntegrationEvent};
 use genxlink_protocol::DeviceId;
@@ -20,6 +20,8 @@ pub struct SessionManager {
     auth_service: Arc<Mutex<AuthService>>,
     webrtc_integration: Arc<Mutex<Option<WebRTCIntegration>>>,
     config: SessionConfig,
+    /// Built from `require_mfa_for_remote_control`, tracks the current login
+    mfa_policy: MfaPolicy,
     event_handlers: Arc<RwLock<Vec<Box<dyn SessionEventHandler>>>>,
 }
 
@@ -31,6 +33,8 @@ pub struct SessionConfig {
     pub require_reauth_after: Duration,
     pub enable_persistence: bool,
     pub encryption_key_rotation_interval: Duration,
+    /// Refuse outbound remote-control sessions until the login passed MFA
+    pub require_mfa_for_remote_control: bool,
 }
 
 impl Default for SessionConfig {
@@ -41,6 +45,7 @@ impl Default for SessionConfig {
             require_reauth_after: Duration::from_secs(24 * 60 * 60), // 24 hours
             enable_persistence: true,
             encryption_key_rotation_interval: Duration::from_secs(60 * 60), // 1 hour
+            require_mfa_for_remote_control: false,
         }
     }
 }
@@ -100,6 +105,8 @@ pub enum SessionEvent {
     ConnectionEstablished(DeviceId),
     ConnectionLost(DeviceId),
     AuthenticationRequired,
+    /// A second factor is required before this action
+    MfaRequired,
     SecurityViolation(String),
 }
 
@@ -116,11 +123,18 @@ impl SessionManager {
             active_connections: Arc::new(RwLock::new(HashMap::new())),
             auth_service: Arc::new(Mutex::new(auth_service)),
             webrtc_integration: Arc::new(Mutex::new(None)),
+            mfa_policy: MfaPolicy::new(config.require_mfa_for_remote_control),
             config,
             event_handlers: Arc::new(RwLock::new(Vec::new())),
         }
     }
 
+    /// MFA policy to hand to other connection paths, e.g.
+    /// `ConnectionManager::set_mfa_policy`, so they follow this session
+    pub fn mfa_policy(&self) -> MfaPolicy {
+        self.mfa_policy.clone()
+    }
+
     /// Initialize a user session
     pub async fn create_session(&self, auth_session: AuthSession, user: UserAccount, device_id: DeviceId) -> Result<String> {
         info!("Creating session for user: {}", user.id);
@@ -146,6 +160,7 @@ impl SessionManager {
         let session_id = session.session_id.clone();
         let mut session_guard = self.current_session.write().await;
         *session_guard = Some(session.clone());
+        self.mfa_policy.set_session(Some(&session.auth_session));
         drop(session_guard);
 
         // Persist session if enabled
@@ -180,6 +195,7 @@ impl SessionManager {
                 warn!("Session expired: {}", session.session_id);
                 let session_id = session.session_id.clone();
                 *session_guard = None;
+                self.mfa_policy.set_session(None);
                 drop(session_guard);
                 
                 self.emit_event(SessionEvent::SessionExpired(session_id)).await;
@@ -207,6 +223,7 @@ impl SessionManager {
         
         if let Some(session) = session_guard.take() {
             info!("Terminating session: {}", session.session_id);
+            self.mfa_policy.set_session(None);
             
             // Disconnect all active connections
             self.disconnect_all().await?;
@@ -231,6 +248,12 @@ impl SessionManager {
             return Err(anyhow::anyhow!("No valid session"));
         }
 
+        if let Err(e) = self.mfa_policy.check_remote_control() {
+            warn!("Refusing connection to {}: two-factor authentication required", remote_device_id);
+            self.emit_event(SessionEvent::MfaRequired).await;
+            return Err(e.into());
+        }
+
         // Check connection limit
         let connections = self.active_connections.read().await;
         if connections.len() >= self.config.max_concurrent_sessions {
@@ -490,6 +513,67 @@ mod tests {
         assert_eq!(session.session_id, session_id);
     }
 
+    #[tokio::test]
+    async fn test_mfa_required_for_remote_control() {
+        let auth_service = AuthService::new(
+            "http://localhost:8000".to_string(),
+            "test-key".to_string(),
+        );
+        let config = SessionConfig {
+            require_mfa_for_remote_control: true,
+            ..SessionConfig::default()
+        };
+        let manager = SessionManager::new(auth_service, config);
+
+        // Password-only session token
+        let auth_session = AuthSession {
+            access_token: "e30.eyJhYWwiOiJhYWwxIn0.sig".to_string(),
+            refresh_token: "test-refresh".to_string(),
+            expires_at: 0,
+            user_id: "test-user".to_string(),
+        };
+        let user = UserAccount {
+            id: "test-user".to_string(),
+            email: "test@example.com".to_string(),
+            username: "testuser".to_string(),
+            display_name: "Test User".to_string(),
+            avatar_url: None,
+            subscription_type: SubscriptionType::Free,
+            created_at: SystemTime::now(),
+            last_login: None,
+            preferences: UserPreferences::default(),
+            is_active: true,
+            is_verified: true,
+        };
+        manager.create_session(auth_session.clone(), user.clone(), DeviceId("test-device".to_string())).await.unwrap();
+
+        let result = manager
+            .establish_connection(DeviceId("remote-device".to_string()), "ws://localhost:8081".to_string())
+            .await;
+        assert!(result.is_err());
+        assert!(manager.get_active_connections().await.is_empty());
+
+        // The connection manager shares the policy and refuses as well
+        let (mut connections, _events) = crate::connection_manager::ConnectionManager::new();
+        connections.set_mfa_policy(manager.mfa_policy());
+        assert!(connections.connect_to_peer("123-456-789").await.is_err());
+        assert!(connections.connected_peers().await.is_empty());
+
+        // After the second factor both paths connect
+        let aal2_session = AuthSession {
+            access_token: "e30.eyJhYWwiOiJhYWwyIn0.sig".to_string(),
+            ..auth_session
+        };
+        manager.create_session(aal2_session, user, DeviceId("test-device".to_string())).await.unwrap();
+        manager
+            .establish_connection(DeviceId("remote-device".to_string()), "ws://localhost:8081".to_string())
+            .await
+            .unwrap();
+        assert_eq!(manager.get_active_connections().await.len(), 1);
+        connections.connect_to_peer("123-456-789").await.unwrap();
+        assert_eq!(connections.connected_peers().await.len(), 1);
+    }
+
     #[tokio::test]
     async fn test_session_validation() {
         let auth_service = AuthService::new(
@@ -1,12 +1,15 @@
+use crate::brute_force::{constant_time_eq, AttemptLimiter, LockoutEvent, LockoutPolicy, LOCAL_SOURCE};
 use crate::ClientError;
 use rand::Rng;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
 /// Session password manager
 pub struct SessionPasswordManager {
     current_password: Option<SessionPassword>,
     password_length: usize,
     timeout_duration: Duration,
+    limiter: AttemptLimiter,
 }
 
 impl SessionPasswordManager {
@@ -16,9 +19,23 @@ impl SessionPasswordManager {
             current_password: None,
             password_length: 6,
             timeout_duration: Duration::from_secs(300), // 5 minutes default
+            limiter: AttemptLimiter::default(),
         }
     }
 
+    /// Create a session password manager with a custom lockout policy
+    pub fn with_policy(policy: LockoutPolicy) -> Self {
+        Self {
+            limiter: AttemptLimiter::new(policy),
+            ..Self::new()
+        }
+    }
+
+    /// Deliver lockout notifications to the host
+    pub fn set_lockout_notifier(&mut self, notifier: mpsc::UnboundedSender<LockoutEvent>) {
+        self.limiter.set_notifier(notifier);
+    }
+
     /// Generate a new session password
     pub fn generate_password(&mut self) -> String {
         let password = Self::generate_random_password(self.password_length);
@@ -26,40 +43,55 @@ impl SessionPasswordManager {
             password: password.clone(),
             created_at: Instant::now(),
             expires_at: Instant::now() + self.timeout_duration,
-            attempts: 0,
         });
+        // A fresh password starts with a clean attempt history
+        self.limiter.reset();
         password
     }
 
-    /// Verify a password
+    /// Verify a password entered locally
     pub fn verify_password(&mut self, password: &str) -> Result<bool, ClientError> {
-        if let Some(ref mut session_pwd) = self.current_password {
-            // Check if expired
-            if Instant::now() > session_pwd.expires_at {
-                self.current_password = None;
-                return Err(ClientError::AuthenticationError("Password expired".to_string()));
-            }
+        self.verify_password_from(LOCAL_SOURCE, password)
+    }
 
-            // Increment attempts
-            session_pwd.attempts += 1;
+    /// Verify a password submitted by `source` (peer device or address)
+    ///
+    /// Failed attempts are tracked per source and globally. Sources that
+    /// guess too often are delayed and then locked out; once the global
+    /// budget is exhausted the password is discarded and a new one must be
+    /// generated by the host.
+    pub fn verify_password_from(&mut self, source: &str, password: &str) -> Result<bool, ClientError> {
+        let session_pwd = match self.current_password {
+            Some(ref session_pwd) => session_pwd,
+            None => return Err(ClientError::AuthenticationError("No active session".to_string())),
+        };
+
+        // Check if expired
+        if Instant::now() > session_pwd.expires_at {
+            self.current_password = None;
+            return Err(ClientError::AuthenticationError("Password expired".to_string()));
+        }
 
-            // Check max attempts
-            if session_pwd.attempts > 3 {
-                self.current_password = None;
-                return Err(ClientError::AuthenticationError("Too many attempts".to_string()));
-            }
+        self.limiter
+            .check(source)
+            .map_err(|e| ClientError::AuthenticationError(e.to_string()))?;
 
-            // Verify password
-            Ok(session_pwd.password == password)
-        } else {
-            Err(ClientError::AuthenticationError("No active session".to_string()))
+        if constant_time_eq(session_pwd.password.as_bytes(), password.as_bytes()) {
+            self.limiter.record_success(source);
+            return Ok(true);
         }
+
+        if let Some(LockoutEvent::GlobalLockout { .. }) = self.limiter.record_failure(source) {
+            self.current_password = None;
+        }
+
+        Ok(false)
     }
 
     /// Check if password is still valid
     pub fn is_valid(&self) -> bool {
         if let Some(ref session_pwd) = self.current_password {
-            Instant::now() <= session_pwd.expires_at && session_pwd.attempts <= 3
+            Instant::now() <= session_pwd.expires_at && !self.limiter.is_globally_locked()
         } else {
             false
         }
@@ -118,7 +150,6 @@ struct SessionPassword {
     password: String,
     created_at: Instant,
     expires_at: Instant,
-    attempts: u32,
 }
 
 #[cfg(test)]
@@ -142,6 +173,37 @@ mod tests {
         assert!(!manager.verify_password("WRONG").unwrap());
     }
 
+    #[test]
+    fn test_repeated_failures_are_throttled() {
+        let mut manager = SessionPasswordManager::new();
+        let password = manager.generate_password();
+
+        assert!(!manager.verify_password_from("peer-a", "WRONG").unwrap());
+        // Second guess inside the backoff window is refused without checking
+        assert!(manager.verify_password_from("peer-a", &password).is_err());
+        // Other sources are not affected
+        assert!(manager.verify_password_from("peer-b", &password).unwrap());
+    }
+
+    #[test]
+    fn test_global_lockout_discards_password() {
+        let mut manager = SessionPasswordManager::with_policy(LockoutPolicy {
+            max_failures_global: 3,
+            ..LockoutPolicy::default()
+        });
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager.set_lockout_notifier(tx);
+        let password = manager.generate_password();
+
+        for i in 0..3 {
+            let _ = manager.verify_password_from(&format!("peer-{}", i), "WRONG");
+        }
+
+        assert!(!manager.is_valid());
+        assert!(manager.verify_password_from("peer-x", &password).is_err());
+        assert!(matches!(rx.try_recv(), Ok(LockoutEvent::GlobalLockout { .. })));
+    }
+
     #[test]
     fn test_password_validity() {
         let mut manager = SessionPasswordManager::new();
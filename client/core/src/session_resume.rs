@@ -0,0 +1,336 @@
+//! Session resumption
+//!
+//! When the host admits a controller it hands out a resumption token with
+//! `ConnectionAccepted`. If the network drops, the controller's signaling
+//! client reconnects and presents the token; redeeming it here re-admits the
+//! same session without prompting the user again and gives back the state the
+//! session had (selected monitor, granted permissions, transfers in flight).
+//! Tokens are single-use, bound to the controller's device ID, and expire a
+//! short while after the host noticed the session was lost. The ID survives
+//! reconnects because the signaling client registers it again on every
+//! socket, and the signaling server only accepts that registration when it
+//! is signed with the key the ID was first registered with.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::info;
+
+use genxlink_protocol::{DeviceId, SessionId};
+
+use crate::file_transfer::{FileTransfer, TransferStatus};
+use crate::permission_profiles::Permission;
+
+/// How long a lost session can be resumed
+pub const RESUME_WINDOW: Duration = Duration::from_secs(120);
+
+const TOKEN_LEN: usize = 32;
+
+/// Session state restored on resumption
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub monitor_index: usize,
+    pub permissions: HashSet<Permission>,
+    /// Transfers that were still moving data
+    pub pending_transfers: Vec<FileTransfer>,
+}
+
+impl SessionSnapshot {
+    pub fn new(monitor_index: usize, permissions: HashSet<Permission>) -> Self {
+        Self {
+            monitor_index,
+            permissions,
+            pending_transfers: Vec::new(),
+        }
+    }
+
+    /// Record the transfers to pick up again, skipping finished ones
+    pub fn with_transfers(mut self, transfers: impl IntoIterator<Item = FileTransfer>) -> Self {
+        self.pending_transfers = transfers
+            .into_iter()
+            .filter(|t| matches!(t.status, TransferStatus::Pending | TransferStatus::InProgress))
+            .collect();
+        self
+    }
+}
+
+/// Why a resumption token was not honoured
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ResumeError {
+    #[error("Unknown or already used resumption token")]
+    UnknownToken,
+    #[error("Resumption token was issued to another device")]
+    WrongDevice,
+    #[error("Resumption window has expired")]
+    Expired,
+    #[error("Session is still active")]
+    StillActive,
+}
+
+/// A redeemed session
+#[derive(Debug, Clone)]
+pub struct Resumed {
+    pub session_id: SessionId,
+    pub snapshot: SessionSnapshot,
+    /// Replacement token to send back with `ConnectionAccepted`
+    pub token: String,
+}
+
+/// How the host should answer a connection request
+#[derive(Debug, Clone)]
+pub enum Admission {
+    /// Re-admit without prompting and restore the snapshot
+    Resume(Resumed),
+    /// Ask the user as for any new session; `rejected` says why a presented
+    /// token could not be used
+    Prompt { rejected: Option<ResumeError> },
+}
+
+struct ResumableSession {
+    controller: DeviceId,
+    token_hash: [u8; 32],
+    snapshot: SessionSnapshot,
+    lost_at: Option<Instant>,
+}
+
+/// Host-side record of sessions that can be resumed
+pub struct ResumptionRegistry {
+    window: Duration,
+    sessions: HashMap<SessionId, ResumableSession>,
+    tokens: HashMap<[u8; 32], SessionId>,
+}
+
+impl Default for ResumptionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResumptionRegistry {
+    pub fn new() -> Self {
+        Self::with_window(RESUME_WINDOW)
+    }
+
+    /// Registry whose lost sessions stay resumable for `window`
+    pub fn with_window(window: Duration) -> Self {
+        Self {
+            window,
+            sessions: HashMap::new(),
+            tokens: HashMap::new(),
+        }
+    }
+
+    /// Register an admitted session; returns the token for `ConnectionAccepted`
+    pub fn issue(&mut self, session_id: SessionId, controller: DeviceId, snapshot: SessionSnapshot) -> String {
+        self.revoke(&session_id);
+
+        let token = new_token();
+        let token_hash = hash(&token);
+        self.tokens.insert(token_hash, session_id);
+        self.sessions.insert(
+            session_id,
+            ResumableSession {
+                controller,
+                token_hash,
+                snapshot,
+                lost_at: None,
+            },
+        );
+        token
+    }
+
+    /// Keep the stored state current as the session changes
+    pub fn update(&mut self, session_id: &SessionId, snapshot: SessionSnapshot) -> bool {
+        match self.sessions.get_mut(session_id) {
+            Some(session) => {
+                session.snapshot = snapshot;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mark a session as lost; its token stays redeemable for the resume window
+    pub fn suspend(&mut self, session_id: &SessionId) {
+        if let Some(session) = self.sessions.get_mut(session_id) {
+            session.lost_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Forget a session that ended deliberately
+    pub fn revoke(&mut self, session_id: &SessionId) {
+        if let Some(session) = self.sessions.remove(session_id) {
+            self.tokens.remove(&session.token_hash);
+        }
+    }
+
+    /// Redeem `token` presented by `controller`, rotating it
+    ///
+    /// Only sessions marked lost with `suspend` can be redeemed, so a leaked
+    /// token can't be used to take over a session that is still running.
+    pub fn redeem(&mut self, controller: &DeviceId, token: &str) -> Result<Resumed, ResumeError> {
+        let token_hash = hash(token);
+        let session_id = *self.tokens.get(&token_hash).ok_or(ResumeError::UnknownToken)?;
+        let session = self.sessions.get_mut(&session_id).ok_or(ResumeError::UnknownToken)?;
+
+        if session.controller != *controller {
+            return Err(ResumeError::WrongDevice);
+        }
+        let Some(lost_at) = session.lost_at else {
+            return Err(ResumeError::StillActive);
+        };
+        if lost_at.elapsed() > self.window {
+            self.revoke(&session_id);
+            return Err(ResumeError::Expired);
+        }
+
+        let token = new_token();
+        self.tokens.remove(&token_hash);
+        session.token_hash = hash(&token);
+        session.lost_at = None;
+        self.tokens.insert(session.token_hash, session_id);
+
+        info!("Resumed session {} for {}", session_id, controller);
+        Ok(Resumed {
+            session_id,
+            snapshot: session.snapshot.clone(),
+            token,
+        })
+    }
+
+    /// Decide how to answer a connection request from `from`
+    pub fn admit(&mut self, from: &DeviceId, resume_token: Option<&str>) -> Admission {
+        match resume_token.map(|token| self.redeem(from, token)) {
+            Some(Ok(resumed)) => Admission::Resume(resumed),
+            Some(Err(e)) => Admission::Prompt { rejected: Some(e) },
+            None => Admission::Prompt { rejected: None },
+        }
+    }
+
+    /// Drop lost sessions whose resume window has passed
+    pub fn cleanup(&mut self) {
+        let window = self.window;
+        let expired: Vec<SessionId> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.lost_at.is_some_and(|lost_at| lost_at.elapsed() > window))
+            .map(|(session_id, _)| *session_id)
+            .collect();
+        for session_id in expired {
+            self.revoke(&session_id);
+        }
+    }
+
+    /// Whether `session_id` is still registered, live or lost
+    pub fn contains(&self, session_id: &SessionId) -> bool {
+        self.sessions.contains_key(session_id)
+    }
+
+    /// Number of sessions that can currently be resumed
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator failed");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> SessionSnapshot {
+        SessionSnapshot::new(1, HashSet::from([Permission::ControlDevice, Permission::AccessClipboard]))
+    }
+
+    #[test]
+    fn test_token_is_single_use_and_rotated() {
+        let mut registry = ResumptionRegistry::new();
+        let session_id = SessionId::new();
+        let controller = DeviceId::new();
+        let token = registry.issue(session_id, controller.clone(), snapshot());
+
+        // A live session can't be taken over with its token
+        assert_eq!(registry.redeem(&controller, &token).unwrap_err(), ResumeError::StillActive);
+
+        registry.suspend(&session_id);
+        let resumed = registry.redeem(&controller, &token).unwrap();
+        assert_eq!(resumed.session_id, session_id);
+        assert_eq!(resumed.snapshot.monitor_index, 1);
+        assert!(resumed.snapshot.permissions.contains(&Permission::ControlDevice));
+        assert_ne!(resumed.token, token);
+
+        assert_eq!(registry.redeem(&controller, &token).unwrap_err(), ResumeError::UnknownToken);
+        assert_eq!(registry.redeem(&controller, &resumed.token).unwrap_err(), ResumeError::StillActive);
+        registry.suspend(&session_id);
+        assert!(registry.redeem(&controller, &resumed.token).is_ok());
+    }
+
+    #[test]
+    fn test_token_is_bound_to_controller() {
+        let mut registry = ResumptionRegistry::new();
+        let session_id = SessionId::new();
+        let controller = DeviceId::new();
+        let token = registry.issue(session_id, controller.clone(), snapshot());
+        registry.suspend(&session_id);
+
+        assert_eq!(registry.redeem(&DeviceId::new(), &token).unwrap_err(), ResumeError::WrongDevice);
+        assert!(registry.redeem(&controller, &token).is_ok());
+    }
+
+    #[test]
+    fn test_lost_session_expires() {
+        let mut registry = ResumptionRegistry::with_window(Duration::ZERO);
+        let session_id = SessionId::new();
+        let controller = DeviceId::new();
+        let token = registry.issue(session_id, controller.clone(), snapshot());
+
+        registry.suspend(&session_id);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(registry.redeem(&controller, &token).unwrap_err(), ResumeError::Expired);
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_admission() {
+        let mut registry = ResumptionRegistry::new();
+        let session_id = SessionId::new();
+        let controller = DeviceId::new();
+        let token = registry.issue(session_id, controller.clone(), snapshot());
+        registry.update(&session_id, SessionSnapshot::new(2, HashSet::new()));
+        registry.suspend(&session_id);
+
+        assert!(matches!(registry.admit(&controller, None), Admission::Prompt { rejected: None }));
+        assert!(matches!(
+            registry.admit(&controller, Some("forged")),
+            Admission::Prompt { rejected: Some(ResumeError::UnknownToken) }
+        ));
+        match registry.admit(&controller, Some(&token)) {
+            Admission::Resume(resumed) => assert_eq!(resumed.snapshot.monitor_index, 2),
+            other => panic!("expected resumption, got {:?}", other),
+        }
+
+        assert!(registry.contains(&session_id));
+        registry.revoke(&session_id);
+        assert!(!registry.contains(&session_id));
+        assert!(registry.is_empty());
+    }
+}
@@ -1,17 +1,70 @@
+use crate::device_identity::{self, DeviceIdentity};
 use crate::ClientError;
-use genxlink_protocol::{SignalingMessage, DeviceId};
-use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use genxlink_protocol::{
+    register_proof_message, DeviceId, MessageId, PeerInfo, RegisterProof, SessionId, SignalingEnvelope,
+    SignalingErrorCode, SignalingMessage,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 use futures::{StreamExt, SinkExt};
+use parking_lot::Mutex;
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Interval between WebSocket pings
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Silence after which the socket is considered dead
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Outgoing messages kept while reconnecting; the oldest are dropped beyond this
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// Default time to wait for the reply to a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Id of the `Register` request on each socket; other requests count from 1
+const REGISTER_REQUEST_ID: MessageId = 0;
+
+/// Requests waiting for their reply, by id
+type PendingRequests = Arc<Mutex<HashMap<MessageId, oneshot::Sender<SignalingMessage>>>>;
 
 /// Signaling client for WebRTC connection setup
+///
+/// Once connected, the socket is supervised: when it drops, the client
+/// reconnects with exponential backoff, queues outgoing messages in the
+/// meantime, and asks every host it holds a resumption token for to re-admit
+/// the session. The receiver returned by [`SignalingClient::connect`] stays
+/// valid across reconnects.
+///
+/// Everything goes over the one socket. [`SignalingClient::request`] tags a
+/// message with an id and waits for the reply carrying it; replies are taken
+/// out of the stream, and the receiver gets everything else.
+///
+/// Each socket registers the device ID by signing the server's challenge
+/// with the device key, so the server only hands the ID to this device. A
+/// refused registration fails the client rather than reconnecting.
 pub struct SignalingClient {
     device_id: DeviceId,
     server_url: String,
-    state: Arc<RwLock<SignalingState>>,
-    message_tx: Option<mpsc::UnboundedSender<SignalingMessage>>,
+    /// Key that proves ownership of `device_id`; the device's own by default
+    identity: Option<Arc<DeviceIdentity>>,
+    policy: ReconnectPolicy,
+    request_timeout: Duration,
+    state: Arc<watch::Sender<SignalingState>>,
+    message_tx: Option<mpsc::UnboundedSender<SignalingEnvelope>>,
+    pending: PendingRequests,
+    next_id: AtomicU64,
+    /// Resumption tokens from hosts that accepted us, by host
+    resume_tokens: Arc<Mutex<HashMap<DeviceId, String>>>,
+    task: Option<JoinHandle<()>>,
 }
 
 /// Signaling connection state
@@ -24,108 +77,177 @@ pub enum SignalingState {
     Failed(String),
 }
 
+/// Exponential backoff between reconnection attempts
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Fraction of each delay randomized, so clients don't reconnect in lockstep
+    pub jitter: f64,
+    /// Give up after this many attempts; `None` retries forever
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before attempt `attempt` (starting at 0)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.min(32) as i32);
+        let base = base.min(self.max_delay.as_secs_f64());
+        let jitter = if self.jitter > 0.0 {
+            rand::thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        Duration::from_secs_f64((base * (1.0 + jitter)).clamp(0.0, self.max_delay.as_secs_f64()))
+    }
+}
+
 impl SignalingClient {
     /// Create a new signaling client
     pub fn new(device_id: DeviceId, server_url: String) -> Self {
+        let (state, _) = watch::channel(SignalingState::Disconnected);
         Self {
             device_id,
             server_url,
-            state: Arc::new(RwLock::new(SignalingState::Disconnected)),
+            identity: None,
+            policy: ReconnectPolicy::default(),
+            request_timeout: REQUEST_TIMEOUT,
+            state: Arc::new(state),
             message_tx: None,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(1),
+            resume_tokens: Arc::new(Mutex::new(HashMap::new())),
+            task: None,
         }
     }
 
+    /// Register with this key instead of the device identity
+    pub fn with_identity(mut self, identity: Arc<DeviceIdentity>) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Use a custom reconnection policy
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Wait this long for replies to requests
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
     /// Connect to the signaling server
-    pub async fn connect(&mut self) -> Result<mpsc::UnboundedReceiver<SignalingMessage>, ClientError> {
+    pub async fn connect(&mut self) -> Result<mpsc::UnboundedReceiver<SignalingEnvelope>, ClientError> {
         self.set_state(SignalingState::Connecting).await;
 
         // Connect to WebSocket server
-        let (ws_stream, _) = connect_async(&self.server_url)
-            .await
-            .map_err(|e| ClientError::TransportError(format!("WebSocket connection failed: {}", e)))?;
+        let ws_stream = match connect_async(&self.server_url).await {
+            Ok((ws_stream, _)) => ws_stream,
+            Err(e) => {
+                let message = format!("WebSocket connection failed: {}", e);
+                self.set_state(SignalingState::Failed(message.clone())).await;
+                return Err(ClientError::TransportError(message));
+            }
+        };
 
         tracing::info!("Connected to signaling server: {}", self.server_url);
 
-        let (mut write, mut read) = ws_stream.split();
-
         // Create channels for bidirectional communication
-        let (outgoing_tx, mut outgoing_rx) = mpsc::unbounded_channel::<SignalingMessage>();
-        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel::<SignalingMessage>();
+        let (outgoing_tx, outgoing_rx) = mpsc::unbounded_channel::<SignalingEnvelope>();
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel::<SignalingEnvelope>();
 
         self.message_tx = Some(outgoing_tx);
         self.set_state(SignalingState::Connected).await;
 
-        let state = self.state.clone();
-
-        // Spawn task to handle outgoing messages
-        tokio::spawn(async move {
-            while let Some(msg) = outgoing_rx.recv().await {
-                let json = match serde_json::to_string(&msg) {
-                    Ok(j) => j,
-                    Err(e) => {
-                        tracing::error!("Failed to serialize message: {}", e);
-                        continue;
-                    }
-                };
-
-                if let Err(e) = write.send(Message::Text(json)).await {
-                    tracing::error!("Failed to send message: {}", e);
-                    let mut s = state.write().await;
-                    *s = SignalingState::Failed(format!("Send failed: {}", e));
-                    break;
-                }
-            }
-        });
-
-        let state = self.state.clone();
-
-        // Spawn task to handle incoming messages
-        tokio::spawn(async move {
-            while let Some(msg_result) = read.next().await {
-                match msg_result {
-                    Ok(Message::Text(text)) => {
-                        match serde_json::from_str::<SignalingMessage>(&text) {
-                            Ok(msg) => {
-                                if let Err(e) = incoming_tx.send(msg) {
-                                    tracing::error!("Failed to forward message: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => {
-                                tracing::error!("Failed to deserialize message: {}", e);
-                            }
-                        }
-                    }
-                    Ok(Message::Close(_)) => {
-                        tracing::info!("WebSocket closed by server");
-                        let mut s = state.write().await;
-                        *s = SignalingState::Disconnected;
-                        break;
-                    }
-                    Ok(Message::Ping(data)) => {
-                        // Pongs are handled automatically by tungstenite
-                        tracing::debug!("Received ping: {} bytes", data.len());
-                    }
-                    Ok(_) => {
-                        // Ignore other message types
-                    }
-                    Err(e) => {
-                        tracing::error!("WebSocket error: {}", e);
-                        let mut s = state.write().await;
-                        *s = SignalingState::Failed(format!("WebSocket error: {}", e));
-                        break;
-                    }
-                }
-            }
-        });
+        let supervisor = Supervisor {
+            device_id: self.device_id.clone(),
+            identity: self.identity.get_or_insert_with(device_identity::get_device_identity).clone(),
+            server_url: self.server_url.clone(),
+            policy: self.policy.clone(),
+            state: self.state.clone(),
+            resume_tokens: self.resume_tokens.clone(),
+            pending: self.pending.clone(),
+            queue: VecDeque::new(),
+        };
+        if let Some(task) = self.task.replace(tokio::spawn(supervisor.run(ws_stream, outgoing_rx, incoming_tx))) {
+            task.abort();
+        }
 
         Ok(incoming_rx)
     }
 
     /// Send a signaling message
+    ///
+    /// While reconnecting, the message is queued and sent once the socket is back.
     pub async fn send(&self, message: SignalingMessage) -> Result<(), ClientError> {
+        self.send_envelope(SignalingEnvelope::new(message))
+    }
+
+    /// Answer a request received from the server or a peer
+    pub async fn reply(&self, request: &SignalingEnvelope, message: SignalingMessage) -> Result<(), ClientError> {
+        self.send_envelope(request.reply(message))
+    }
+
+    /// Send a request and wait for its reply
+    ///
+    /// `Error` replies and timeouts become [`ClientError::SignalingError`].
+    pub async fn request(&self, message: SignalingMessage) -> Result<SignalingMessage, ClientError> {
+        self.request_with_timeout(message, self.request_timeout).await
+    }
+
+    /// Send a request and wait up to `timeout` for its reply
+    pub async fn request_with_timeout(
+        &self,
+        message: SignalingMessage,
+        timeout: Duration,
+    ) -> Result<SignalingMessage, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending.lock().insert(id, reply_tx);
+
+        if let Err(e) = self.send_envelope(SignalingEnvelope::request(id, message)) {
+            self.pending.lock().remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, reply_rx).await {
+            Ok(Ok(SignalingMessage::Error { code, message, retry_after_secs })) => {
+                Err(ClientError::SignalingError { code, message, retry_after_secs })
+            }
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(ClientError::TransportError(
+                "Signaling connection lost before the reply".to_string(),
+            )),
+            Err(_) => {
+                self.pending.lock().remove(&id);
+                Err(ClientError::SignalingError {
+                    code: SignalingErrorCode::Timeout,
+                    message: format!("No reply within {:?}", timeout),
+                    retry_after_secs: None,
+                })
+            }
+        }
+    }
+
+    pub(crate) fn send_envelope(&self, envelope: SignalingEnvelope) -> Result<(), ClientError> {
         if let Some(tx) = &self.message_tx {
-            tx.send(message)
+            tx.send(envelope)
                 .map_err(|e| ClientError::TransportError(format!("Failed to send message: {}", e)))?;
             Ok(())
         } else {
@@ -140,28 +262,85 @@ impl SignalingClient {
 
     /// Get current connection state
     pub async fn get_state(&self) -> SignalingState {
-        self.state.read().await.clone()
+        self.state.borrow().clone()
+    }
+
+    /// Watch connection state changes, e.g. to restart ICE after a reconnect
+    pub fn watch_state(&self) -> watch::Receiver<SignalingState> {
+        self.state.subscribe()
     }
 
     /// Set connection state
     async fn set_state(&self, new_state: SignalingState) {
-        let mut state = self.state.write().await;
-        *state = new_state;
+        self.state.send_replace(new_state);
     }
 
-    /// Request list of available peers
-    pub async fn list_peers(&self) -> Result<(), ClientError> {
-        self.send(SignalingMessage::ListPeers).await
+    /// List the other peers connected to the server
+    pub async fn list_peers(&self) -> Result<Vec<PeerInfo>, ClientError> {
+        match self.request(SignalingMessage::ListPeers).await? {
+            SignalingMessage::PeerList { peers } => Ok(peers),
+            other => Err(unexpected_reply(other)),
+        }
     }
 
-    /// Request connection to a peer
+    /// Measure the round trip to the server
+    pub async fn ping(&self) -> Result<Duration, ClientError> {
+        let sent_at = Instant::now();
+        match self.request(SignalingMessage::Ping).await? {
+            SignalingMessage::Pong => Ok(sent_at.elapsed()),
+            other => Err(unexpected_reply(other)),
+        }
+    }
+
+    /// Ask `host` to admit us and wait for its decision
+    ///
+    /// A rejection comes back as [`ClientError::PermissionDenied`]. The
+    /// resumption token of an accepted session is kept for reconnects.
+    pub async fn open_session(&self, host: DeviceId) -> Result<SessionId, ClientError> {
+        let request = SignalingMessage::ConnectionRequest {
+            target: host,
+            from: self.device_id.clone(),
+            resume_token: None,
+        };
+        match self.request(request).await? {
+            SignalingMessage::ConnectionAccepted { session_id, .. } => Ok(session_id),
+            SignalingMessage::ConnectionRejected { reason, .. } => Err(ClientError::PermissionDenied(reason)),
+            other => Err(unexpected_reply(other)),
+        }
+    }
+
+    /// Send an offer and wait for the answer SDP
+    pub async fn negotiate(&self, sdp: String, to: DeviceId) -> Result<String, ClientError> {
+        let offer = SignalingMessage::Offer {
+            sdp,
+            from: self.device_id.clone(),
+            to,
+        };
+        match self.request(offer).await? {
+            SignalingMessage::Answer { sdp, .. } => Ok(sdp),
+            other => Err(unexpected_reply(other)),
+        }
+    }
+
+    /// Request connection to a peer without waiting for the reply
     pub async fn request_connection(&self, target: DeviceId) -> Result<(), ClientError> {
         self.send(SignalingMessage::ConnectionRequest {
             target,
             from: self.device_id.clone(),
+            resume_token: None,
         }).await
     }
 
+    /// Resumption token held for a session with `host`
+    pub fn resume_token(&self, host: &DeviceId) -> Option<String> {
+        self.resume_tokens.lock().get(host).cloned()
+    }
+
+    /// Stop resuming the session with `host` after reconnects
+    pub fn forget_session(&self, host: &DeviceId) {
+        self.resume_tokens.lock().remove(host);
+    }
+
     /// Send WebRTC offer
     pub async fn send_offer(&self, sdp: String, to: DeviceId) -> Result<(), ClientError> {
         self.send(SignalingMessage::Offer {
@@ -199,11 +378,293 @@ impl SignalingClient {
 
     /// Close the connection
     pub async fn close(&mut self) {
+        // Dropping the sender makes the supervisor close the socket and exit
         self.message_tx = None;
+        self.task = None;
+        self.resume_tokens.lock().clear();
         self.set_state(SignalingState::Disconnected).await;
     }
 }
 
+/// Why a socket stopped being pumped
+enum SocketEnd {
+    /// The client closed or dropped its receiver; don't reconnect
+    Closed,
+    /// The connection failed; reconnect
+    Lost(String),
+    /// The server refused our registration; reconnecting won't help
+    Refused(String),
+}
+
+/// Owns the WebSocket and reconnects it when it drops
+struct Supervisor {
+    device_id: DeviceId,
+    identity: Arc<DeviceIdentity>,
+    server_url: String,
+    policy: ReconnectPolicy,
+    state: Arc<watch::Sender<SignalingState>>,
+    resume_tokens: Arc<Mutex<HashMap<DeviceId, String>>>,
+    pending: PendingRequests,
+    /// Messages waiting for a connected socket
+    queue: VecDeque<SignalingEnvelope>,
+}
+
+impl Supervisor {
+    async fn run(
+        mut self,
+        mut ws_stream: WsStream,
+        mut outgoing: mpsc::UnboundedReceiver<SignalingEnvelope>,
+        incoming: mpsc::UnboundedSender<SignalingEnvelope>,
+    ) {
+        loop {
+            match self.pump(ws_stream, &mut outgoing, &incoming).await {
+                SocketEnd::Closed => {
+                    self.pending.lock().clear();
+                    self.state.send_replace(SignalingState::Disconnected);
+                    return;
+                }
+                SocketEnd::Lost(reason) => {
+                    tracing::warn!("Lost signaling connection: {}", reason);
+                }
+                SocketEnd::Refused(reason) => {
+                    tracing::error!("Signaling server refused registration: {}", reason);
+                    self.pending.lock().clear();
+                    self.state.send_replace(SignalingState::Failed(reason));
+                    return;
+                }
+            }
+
+            // Requests already sent won't be answered on a new socket; fail
+            // them now and keep the ones still queued
+            let queued: HashSet<MessageId> = self.queue.iter().filter_map(|envelope| envelope.id).collect();
+            self.pending.lock().retain(|id, _| queued.contains(id));
+
+            self.state.send_replace(SignalingState::Reconnecting);
+            ws_stream = match self.reconnect(&mut outgoing).await {
+                Some(ws_stream) => ws_stream,
+                None => {
+                    self.pending.lock().clear();
+                    return;
+                }
+            };
+            self.state.send_replace(SignalingState::Connected);
+
+            // Ask hosts to re-admit our sessions before anything else goes out
+            let resume_tokens: Vec<_> = self.resume_tokens.lock().drain().collect();
+            for (host, token) in resume_tokens {
+                tracing::info!("Resuming session with {}", host);
+                self.queue.push_front(SignalingEnvelope::new(SignalingMessage::ConnectionRequest {
+                    target: host,
+                    from: self.device_id.clone(),
+                    resume_token: Some(token),
+                }));
+            }
+        }
+    }
+
+    /// Move messages between the socket and the client until either side goes away
+    async fn pump(
+        &mut self,
+        ws_stream: WsStream,
+        outgoing: &mut mpsc::UnboundedReceiver<SignalingEnvelope>,
+        incoming: &mpsc::UnboundedSender<SignalingEnvelope>,
+    ) -> SocketEnd {
+        let (mut write, mut read) = ws_stream.split();
+
+        // Claim our device ID on every new socket before anything else, by
+        // signing the challenge the server opens it with
+        let nonce = loop {
+            let frame = match tokio::time::timeout(HEARTBEAT_TIMEOUT, read.next()).await {
+                Ok(Some(Ok(frame))) => frame,
+                Ok(Some(Err(e))) => return SocketEnd::Lost(format!("WebSocket error: {}", e)),
+                Ok(None) => return SocketEnd::Lost("Closed by server".to_string()),
+                Err(_) => return SocketEnd::Lost("No challenge from the server".to_string()),
+            };
+            let Message::Text(text) = frame else { continue };
+            match serde_json::from_str::<SignalingEnvelope>(&text) {
+                Ok(SignalingEnvelope { message: SignalingMessage::Challenge { nonce }, .. }) => break nonce,
+                Ok(other) => tracing::debug!("Ignoring {:?} before the challenge", other.message),
+                Err(e) => tracing::error!("Failed to deserialize message: {}", e),
+            }
+        };
+        let register = SignalingEnvelope::request(REGISTER_REQUEST_ID, SignalingMessage::Register {
+            device_id: self.device_id.clone(),
+            device_name: None,
+            proof: Some(RegisterProof {
+                public_key: self.identity.public_key().to_vec(),
+                signature: self.identity.sign(&register_proof_message(&nonce, &self.device_id)),
+            }),
+        });
+        if let Err(e) = send_json(&mut write, &register).await {
+            return SocketEnd::Lost(format!("Send failed: {}", e));
+        }
+
+        while let Some(msg) = self.queue.front() {
+            if let Err(e) = send_json(&mut write, msg).await {
+                return SocketEnd::Lost(format!("Send failed: {}", e));
+            }
+            self.queue.pop_front();
+        }
+
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        heartbeat.tick().await;
+        let mut last_heard = tokio::time::Instant::now();
+
+        loop {
+            tokio::select! {
+                msg = outgoing.recv() => {
+                    let Some(msg) = msg else {
+                        let _ = write.send(Message::Close(None)).await;
+                        return SocketEnd::Closed;
+                    };
+                    if let Err(e) = send_json(&mut write, &msg).await {
+                        // It may not have arrived; send it again after reconnecting
+                        self.enqueue(msg);
+                        return SocketEnd::Lost(format!("Send failed: {}", e));
+                    }
+                }
+                frame = read.next() => {
+                    last_heard = tokio::time::Instant::now();
+                    match frame {
+                        Some(Ok(Message::Text(text))) => {
+                            match serde_json::from_str::<SignalingEnvelope>(&text) {
+                                Ok(msg) if msg.reply_to == Some(REGISTER_REQUEST_ID) => {
+                                    if let SignalingMessage::Error { message, .. } = msg.message {
+                                        let _ = write.send(Message::Close(None)).await;
+                                        return SocketEnd::Refused(message);
+                                    }
+                                }
+                                Ok(msg) => {
+                                    self.observe(&msg.message);
+                                    let Some(msg) = self.resolve(msg) else { continue };
+                                    if incoming.send(msg).is_err() {
+                                        let _ = write.send(Message::Close(None)).await;
+                                        return SocketEnd::Closed;
+                                    }
+                                }
+                                Err(e) => {
+                                    tracing::error!("Failed to deserialize message: {}", e);
+                                }
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            return SocketEnd::Lost("Closed by server".to_string());
+                        }
+                        Some(Ok(Message::Ping(data))) => {
+                            // Pongs are handled automatically by tungstenite
+                            tracing::debug!("Received ping: {} bytes", data.len());
+                        }
+                        Some(Ok(_)) => {
+                            // Ignore other message types
+                        }
+                        Some(Err(e)) => {
+                            return SocketEnd::Lost(format!("WebSocket error: {}", e));
+                        }
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_heard.elapsed() > HEARTBEAT_TIMEOUT {
+                        return SocketEnd::Lost("Heartbeat timed out".to_string());
+                    }
+                    if let Err(e) = write.send(Message::Ping(Vec::new())).await {
+                        return SocketEnd::Lost(format!("Send failed: {}", e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reconnect with backoff, queueing outgoing messages while waiting
+    async fn reconnect(&mut self, outgoing: &mut mpsc::UnboundedReceiver<SignalingEnvelope>) -> Option<WsStream> {
+        let mut attempt = 0;
+        loop {
+            if self.policy.max_attempts.is_some_and(|max| attempt >= max) {
+                let reason = format!("Gave up after {} reconnection attempts", attempt);
+                tracing::error!("{}", reason);
+                self.state.send_replace(SignalingState::Failed(reason));
+                return None;
+            }
+
+            let delay = tokio::time::sleep(self.policy.delay(attempt));
+            tokio::pin!(delay);
+            loop {
+                tokio::select! {
+                    _ = &mut delay => break,
+                    msg = outgoing.recv() => match msg {
+                        Some(msg) => self.enqueue(msg),
+                        None => {
+                            self.state.send_replace(SignalingState::Disconnected);
+                            return None;
+                        }
+                    }
+                }
+            }
+
+            attempt += 1;
+            match connect_async(&self.server_url).await {
+                Ok((ws_stream, _)) => {
+                    tracing::info!("Reconnected to signaling server after {} attempt(s)", attempt);
+                    return Some(ws_stream);
+                }
+                Err(e) => tracing::warn!("Reconnection attempt {} failed: {}", attempt, e),
+            }
+        }
+    }
+
+    fn enqueue(&mut self, msg: SignalingEnvelope) {
+        if self.queue.len() >= MAX_QUEUED_MESSAGES {
+            tracing::warn!("Signaling queue full, dropping the oldest message");
+            if let Some(id) = self.queue.pop_front().and_then(|dropped| dropped.id) {
+                self.pending.lock().remove(&id);
+            }
+        }
+        self.queue.push_back(msg);
+    }
+
+    /// Hand a reply to the request waiting for it; returns anything else
+    fn resolve(&self, msg: SignalingEnvelope) -> Option<SignalingEnvelope> {
+        let waiting = msg.reply_to.and_then(|id| self.pending.lock().remove(&id));
+        match waiting {
+            Some(reply_tx) => {
+                // The requester may have timed out meanwhile
+                let _ = reply_tx.send(msg.message);
+                None
+            }
+            None => Some(msg),
+        }
+    }
+
+    /// Track resumption tokens handed out by hosts
+    fn observe(&self, msg: &SignalingMessage) {
+        match msg {
+            SignalingMessage::ConnectionAccepted { from, resume_token: Some(token), .. } => {
+                self.resume_tokens.lock().insert(from.clone(), token.clone());
+            }
+            SignalingMessage::ConnectionRejected { from, .. } => {
+                self.resume_tokens.lock().remove(from);
+            }
+            // An administrator ended the session; it must not come back
+            SignalingMessage::SessionTerminated { peer, .. } => {
+                self.resume_tokens.lock().remove(peer);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn unexpected_reply(reply: SignalingMessage) -> ClientError {
+    ClientError::TransportError(format!("Unexpected reply: {:?}", reply))
+}
+
+async fn send_json<S>(write: &mut S, msg: &SignalingEnvelope) -> Result<(), String>
+where
+    S: SinkExt<Message> + Unpin,
+    S::Error: std::fmt::Display,
+{
+    let json = serde_json::to_string(msg).map_err(|e| format!("Failed to serialize message: {}", e))?;
+    write.send(Message::Text(json)).await.map_err(|e| e.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -215,7 +676,7 @@ mod tests {
             device_id,
             "ws://localhost:8081/ws".to_string(),
         );
-        
+
         assert_eq!(client.server_url, "ws://localhost:8081/ws");
     }
 
@@ -226,8 +687,31 @@ mod tests {
             device_id,
             "ws://localhost:8081/ws".to_string(),
         );
-        
+
         let state = client.get_state().await;
         assert_eq!(state, SignalingState::Disconnected);
     }
+
+    #[test]
+    fn test_backoff_grows_to_the_cap() {
+        let policy = ReconnectPolicy {
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        };
+
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(20), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_in_bounds() {
+        let policy = ReconnectPolicy::default();
+        for _ in 0..100 {
+            let delay = policy.delay(2);
+            assert!(delay >= Duration::from_millis(1600) && delay <= Duration::from_millis(2400));
+        }
+    }
 }
@@ -1,12 +1,41 @@
 use crate::{ClientError, encoder::{VideoEncoder, EncodedFrame}};
+use crate::loss_recovery::{fec_packet, FecDecoder, FecEncoder, RetransmissionBuffer, MIME_TYPE_ULPFEC};
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
-use webrtc::track::track_local::TrackLocal;
+use webrtc::track::track_local::{TrackLocal, TrackLocalWriter};
+use webrtc::track::track_remote::TrackRemote;
 use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::rtp_transceiver::RTCRtpSendParameters;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::api::interceptor_registry::configure_rtcp_reports;
 use webrtc::api::media_engine::MIME_TYPE_H264;
+use webrtc::interceptor::nack::generator::Generator;
+use webrtc::interceptor::registry::Registry;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use webrtc::rtp::codecs::h264::H264Payloader;
+use webrtc::rtp::packetizer::Payloader;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
 use bytes::Bytes;
 
+/// Largest RTP payload, leaving room for SRTP and header extensions
+const RTP_MTU: usize = 1200;
+
+/// H.264 as `H264Payloader` packetizes it: constrained baseline in
+/// packetization mode 1
+const H264_FMTP: &str = "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f";
+
+/// Payload type `MediaEngine::register_default_codecs` gives `H264_FMTP`,
+/// until the sender's negotiated one is known
+const H264_PAYLOAD_TYPE: u8 = 125;
+
+/// RTP clock rate for video
+const VIDEO_CLOCK_RATE: u32 = 90000;
+
 /// Frame data structure
 #[derive(Clone)]
 pub struct Frame {
@@ -17,12 +46,34 @@ pub struct Frame {
 }
 
 /// Video streaming manager
+///
+/// Packetizes encoded frames to RTP and recovers from loss: ULPFEC packets
+/// follow each frame on a track of their own, NACKed packets are resent and
+/// PLI or FIR requests make the encoder send a keyframe. Feed it the RTCP the
+/// viewer sends with `handle_rtcp`; `VideoReceiver` is the other end.
 pub struct VideoStreamer {
     track: Arc<TrackLocalStaticRTP>,
+    fec_track: Arc<TrackLocalStaticRTP>,
     encoder: Arc<Mutex<Box<dyn VideoEncoder>>>,
+    output: Arc<dyn TrackLocalWriter + Send + Sync>,
+    fec_output: Arc<dyn TrackLocalWriter + Send + Sync>,
+    sender: Option<Arc<RTCRtpSender>>,
+    payloader: H264Payloader,
+    retransmissions: RetransmissionBuffer,
+    fec: FecEncoder,
     sequence_number: u16,
+    fec_sequence_number: u16,
     timestamp: u32,
+    /// RTP clock ticks between frames at the current frame rate
+    frame_ticks: u32,
     ssrc: u32,
+    fec_ssrc: u32,
+    payload_type: u8,
+    frames_sent: u64,
+    packets_sent: u64,
+    bytes_sent: u64,
+    packets_retransmitted: u64,
+    fec_packets_sent: u64,
 }
 
 impl VideoStreamer {
@@ -34,19 +85,46 @@ impl VideoStreamer {
                 mime_type: MIME_TYPE_H264.to_owned(),
                 clock_rate: 90000, // Standard for H.264
                 channels: 0,
-                sdp_fmtp_line: "".to_owned(),
+                sdp_fmtp_line: H264_FMTP.to_owned(),
                 rtcp_feedback: vec![],
             },
             "video".to_owned(),
             "genxlink_video".to_owned(),
         ));
+        let fec_track = Arc::new(TrackLocalStaticRTP::new(
+            RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_ULPFEC.to_owned(),
+                clock_rate: 90000,
+                channels: 0,
+                sdp_fmtp_line: "".to_owned(),
+                rtcp_feedback: vec![],
+            },
+            "video_fec".to_owned(),
+            "genxlink_video".to_owned(),
+        ));
 
         Ok(Self {
+            output: Arc::clone(&track) as Arc<dyn TrackLocalWriter + Send + Sync>,
+            fec_output: Arc::clone(&fec_track) as Arc<dyn TrackLocalWriter + Send + Sync>,
             track,
+            fec_track,
             encoder: Arc::new(Mutex::new(_encoder)),
+            sender: None,
+            payloader: H264Payloader::default(),
+            retransmissions: RetransmissionBuffer::default(),
+            fec: FecEncoder::new(),
             sequence_number: 0,
+            fec_sequence_number: 0,
             timestamp: 0,
+            frame_ticks: VIDEO_CLOCK_RATE / 30,
             ssrc: rand::random(),
+            fec_ssrc: rand::random(),
+            payload_type: H264_PAYLOAD_TYPE,
+            frames_sent: 0,
+            packets_sent: 0,
+            bytes_sent: 0,
+            packets_retransmitted: 0,
+            fec_packets_sent: 0,
         })
     }
 
@@ -55,59 +133,295 @@ impl VideoStreamer {
         Arc::clone(&self.track)
     }
 
+    /// Get the ULPFEC track, which goes on the same peer connection
+    pub fn get_fec_track(&self) -> Arc<TrackLocalStaticRTP> {
+        Arc::clone(&self.fec_track)
+    }
+
+    /// Add both tracks to `peer_connection`
+    ///
+    /// A track's binding writes its sender's SSRC and negotiated payload
+    /// type over every packet, so the streamer takes both from the sender
+    /// to match the viewer's RTCP and protect packets as they are sent.
+    /// Returns the media track's sender, whose RTCP goes to `handle_rtcp`,
+    /// for instance through `spawn_quality_monitor`. Build the peer
+    /// connection with `register_interceptors`, since the streamer answers
+    /// NACKs itself.
+    pub async fn add_to_peer_connection(
+        &mut self,
+        peer_connection: &RTCPeerConnection,
+    ) -> Result<Arc<RTCRtpSender>, ClientError> {
+        let sender = peer_connection
+            .add_track(Arc::clone(&self.track) as Arc<dyn TrackLocal + Send + Sync>)
+            .await
+            .map_err(|e| ClientError::WebRTCError(format!("Failed to add video track: {}", e)))?;
+        let fec_sender = peer_connection
+            .add_track(Arc::clone(&self.fec_track) as Arc<dyn TrackLocal + Send + Sync>)
+            .await
+            .map_err(|e| ClientError::WebRTCError(format!("Failed to add FEC track: {}", e)))?;
+
+        let parameters = sender.get_parameters().await;
+        self.ssrc = parameters.encodings.first().map_or(self.ssrc, |encoding| encoding.ssrc);
+        self.payload_type = negotiated_payload_type(&parameters).unwrap_or(self.payload_type);
+        let fec_parameters = fec_sender.get_parameters().await;
+        self.fec_ssrc = fec_parameters.encodings.first().map_or(self.fec_ssrc, |encoding| encoding.ssrc);
+        self.sender = Some(Arc::clone(&sender));
+
+        // The viewer only reports on the FEC stream; reading lets the
+        // interceptors see it
+        tokio::spawn(async move { while fec_sender.read_rtcp().await.is_ok() {} });
+        Ok(sender)
+    }
+
+    /// Write packets to `output` instead of the track
+    pub fn set_output(&mut self, output: Arc<dyn TrackLocalWriter + Send + Sync>) {
+        self.output = output;
+    }
+
+    /// Write FEC packets to `output` instead of the FEC track
+    pub fn set_fec_output(&mut self, output: Arc<dyn TrackLocalWriter + Send + Sync>) {
+        self.fec_output = output;
+    }
+
+    /// Encode at `bitrate` bits per second and `fps` from the next frame on
+    pub async fn set_rate(&mut self, bitrate: u32, fps: u32) -> Result<(), ClientError> {
+        self.encoder.lock().await.set_rate(bitrate, fps)?;
+        self.frame_ticks = VIDEO_CLOCK_RATE / fps.max(1);
+        Ok(())
+    }
+
+    /// Encode `frame` and stream it
+    pub async fn encode_frame(&mut self, frame: &crate::Frame) -> Result<(), ClientError> {
+        let encoded = self.encoder.lock().await.encode(frame)?;
+        self.stream_frame(encoded).await
+    }
+
     /// Stream an encoded frame
     pub async fn stream_frame(&mut self, frame: EncodedFrame) -> Result<(), ClientError> {
-        // For now, just update counters - actual RTP writing will be implemented
-        // when we integrate with the full WebRTC pipeline
+        let payloads = self.payloader.payload(RTP_MTU, &Bytes::from(frame.data))
+            .map_err(|e| ClientError::StreamingError(format!("Failed to packetize frame: {}", e)))?;
+        if payloads.is_empty() {
+            return Ok(());
+        }
+        // The payload type is negotiated after the track is added, and FEC
+        // must protect the header the viewer receives
+        if let Some(sender) = &self.sender {
+            self.payload_type = negotiated_payload_type(&sender.get_parameters().await).unwrap_or(self.payload_type);
+        }
         
-        // Update sequence number and timestamp
-        self.sequence_number = self.sequence_number.wrapping_add(1);
-        self.timestamp = self.timestamp.wrapping_add(3000); // 90000 Hz / 30 FPS = 3000
+        self.timestamp = self.timestamp.wrapping_add(self.frame_ticks);
+        let last = payloads.len() - 1;
+        let media: Vec<_> = payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| self.create_rtp_packet(payload, i == last))
+            .collect();
+        let fec: Vec<_> = self.fec.protect(&media)?
+            .into_iter()
+            .map(|payload| {
+                self.fec_sequence_number = self.fec_sequence_number.wrapping_add(1);
+                fec_packet(payload, self.fec_sequence_number, self.timestamp, self.fec_ssrc)
+            })
+            .collect();
+        
+        let now = std::time::Instant::now();
+        for packet in media {
+            self.write(&packet).await?;
+            self.retransmissions.insert(packet, now);
+        }
+        for packet in &fec {
+            let written = self.fec_output.write_rtp(packet).await
+                .map_err(|e| ClientError::StreamingError(format!("Failed to write FEC packet: {}", e)))?;
+            self.packets_sent += 1;
+            self.bytes_sent += written as u64;
+        }
+        self.frames_sent += 1;
+        self.fec_packets_sent += fec.len() as u64;
 
-        tracing::debug!("Frame streamed: seq={}, ts={}, keyframe={}", 
-            self.sequence_number, self.timestamp, frame.is_keyframe);
+        tracing::debug!("Frame streamed: seq={}, ts={}, keyframe={}, fec={}", 
+            self.sequence_number, self.timestamp, frame.is_keyframe, fec.len());
 
         Ok(())
     }
 
-    /// Create RTP packet from encoded frame
-    fn create_rtp_packet(&self, frame: &EncodedFrame) -> Result<webrtc::rtp::packet::Packet, ClientError> {
-        let packet = webrtc::rtp::packet::Packet {
+    /// Act on RTCP from the viewer
+    ///
+    /// Resends NACKed packets, requests a keyframe on PLI or FIR and adapts
+    /// the FEC overhead to the loss in receiver reports.
+    pub async fn handle_rtcp(
+        &mut self,
+        packets: &[Box<dyn webrtc::rtcp::packet::Packet + Send + Sync>],
+    ) -> Result<(), ClientError> {
+        let now = std::time::Instant::now();
+        let mut keyframe_requested = false;
+        for packet in packets {
+            let packet = packet.as_any();
+            if let Some(nack) = packet.downcast_ref::<TransportLayerNack>() {
+                if nack.media_ssrc != self.ssrc {
+                    continue;
+                }
+                for packet in self.retransmissions.on_nack(nack, now) {
+                    self.write(&packet).await?;
+                    self.packets_retransmitted += 1;
+                }
+            } else if let Some(pli) = packet.downcast_ref::<PictureLossIndication>() {
+                keyframe_requested |= pli.media_ssrc == self.ssrc;
+            } else if let Some(fir) = packet.downcast_ref::<FullIntraRequest>() {
+                keyframe_requested |= fir.fir.iter().any(|entry| entry.ssrc == self.ssrc);
+            } else if let Some(report) = packet.downcast_ref::<ReceiverReport>() {
+                for block in report.reports.iter().filter(|block| block.ssrc == self.ssrc) {
+                    self.fec.set_loss_rate(block.fraction_lost as f32 / 256.0);
+                }
+            }
+        }
+        
+        if keyframe_requested {
+            tracing::debug!("Viewer lost the picture, requesting a keyframe");
+            self.encoder.lock().await.request_keyframe();
+        }
+        Ok(())
+    }
+
+    /// FEC packets sent per media packet
+    pub fn fec_overhead(&self) -> f32 {
+        self.fec.overhead()
+    }
+
+    /// Create the next RTP packet of the current frame
+    fn create_rtp_packet(&mut self, payload: Bytes, marker: bool) -> webrtc::rtp::packet::Packet {
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        webrtc::rtp::packet::Packet {
             header: webrtc::rtp::header::Header {
                 version: 2,
                 padding: false,
                 extension: false,
-                marker: frame.is_keyframe,
-                payload_type: 96, // Dynamic payload type for H.264
+                marker, // Last packet of the frame
+                payload_type: self.payload_type,
                 sequence_number: self.sequence_number,
                 timestamp: self.timestamp,
                 ssrc: self.ssrc,
                 ..Default::default()
             },
-            payload: Bytes::copy_from_slice(&frame.data),
-        };
+            payload,
+        }
+    }
 
-        Ok(packet)
+    async fn write(&mut self, packet: &webrtc::rtp::packet::Packet) -> Result<(), ClientError> {
+        let written = self.output.write_rtp(packet).await
+            .map_err(|e| ClientError::StreamingError(format!("Failed to write RTP packet: {}", e)))?;
+        self.packets_sent += 1;
+        self.bytes_sent += written as u64;
+        Ok(())
     }
 
     /// Get current streaming statistics
     pub fn get_stats(&self) -> StreamingStats {
         StreamingStats {
-            frames_sent: self.sequence_number as u64,
-            bytes_sent: self.sequence_number as u64 * 1024, // Estimate
+            frames_sent: self.frames_sent,
+            bytes_sent: self.bytes_sent,
             current_fps: 30.0, // Default FPS
             average_bitrate: 1000000.0, // 1 Mbps estimate
             encoding_errors: 0,
             network_errors: 0,
             avg_encode_time_ms: 10.0, // 10ms estimate
             start_time: Some(std::time::Instant::now()),
-            packets_sent: self.sequence_number as u64,
+            packets_sent: self.packets_sent,
+            packets_retransmitted: self.packets_retransmitted,
+            fec_packets_sent: self.fec_packets_sent,
             timestamp: self.timestamp,
             ssrc: self.ssrc,
         }
     }
 }
 
+/// Payload type the sender's track binding writes for `H264_FMTP`
+///
+/// Like the binding, prefer the H.264 codec with the same packetization
+/// mode and profile, then any H.264.
+fn negotiated_payload_type(parameters: &RTCRtpSendParameters) -> Option<u8> {
+    let h264: Vec<_> = parameters.rtp_parameters.codecs.iter()
+        .filter(|codec| codec.capability.mime_type.eq_ignore_ascii_case(MIME_TYPE_H264))
+        .collect();
+    h264.iter()
+        .find(|codec| h264_format_matches(&codec.capability.sdp_fmtp_line))
+        .or(h264.first())
+        .map(|codec| codec.payload_type)
+}
+
+/// Whether an H.264 fmtp line has `H264_FMTP`'s packetization mode and
+/// profile; the level may differ
+fn h264_format_matches(fmtp: &str) -> bool {
+    fn parameter(line: &str, key: &str) -> Option<String> {
+        line.split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.to_ascii_lowercase())
+    }
+    let mode = |line| parameter(line, "packetization-mode");
+    let profile = |line| parameter(line, "profile-level-id").and_then(|id| id.get(..4).map(str::to_owned));
+    mode(fmtp).is_some() && mode(fmtp) == mode(H264_FMTP) && profile(fmtp).is_some() && profile(fmtp) == profile(H264_FMTP)
+}
+
+/// Interceptors for a peer connection carrying a `VideoStreamer`
+///
+/// webrtc's defaults without the NACK responder, which would resend every
+/// NACKed packet a second time. The default codecs already negotiate NACK,
+/// PLI, FIR and ULPFEC.
+pub fn register_interceptors(mut registry: Registry) -> Registry {
+    registry.add(Box::new(Generator::builder()));
+    configure_rtcp_reports(registry)
+}
+
+/// Viewer end of a `VideoStreamer`
+///
+/// Reads the media and ULPFEC tracks and passes on the media packets as
+/// they arrive, with lost ones rebuilt from FEC as soon as they can be.
+/// Retransmissions of packets FEC already rebuilt are dropped. NACKs and
+/// receiver reports are left to the peer connection's interceptors.
+pub struct VideoReceiver {
+    decoder: Arc<parking_lot::Mutex<FecDecoder>>,
+    packets: mpsc::UnboundedSender<webrtc::rtp::packet::Packet>,
+}
+
+impl VideoReceiver {
+    /// A receiver and the media packets it passes on
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<webrtc::rtp::packet::Packet>) {
+        let (packets, received) = mpsc::unbounded_channel();
+        let receiver = Self {
+            decoder: Arc::new(parking_lot::Mutex::new(FecDecoder::new())),
+            packets,
+        };
+        (receiver, received)
+    }
+
+    /// Read `track`, as passed to `on_track`, until it ends
+    pub fn receive(&self, track: Arc<TrackRemote>) -> JoinHandle<()> {
+        let is_fec = track.codec().capability.mime_type.eq_ignore_ascii_case(MIME_TYPE_ULPFEC);
+        let decoder = Arc::clone(&self.decoder);
+        let packets = self.packets.clone();
+        tokio::spawn(async move {
+            while let Ok((packet, _)) = track.read_rtp().await {
+                let delivered = {
+                    let mut decoder = decoder.lock();
+                    if is_fec {
+                        decoder.on_fec(&packet)
+                    } else if decoder.contains(packet.header.sequence_number) {
+                        continue;
+                    } else {
+                        let recovered = decoder.on_media(&packet);
+                        std::iter::once(packet).chain(recovered).collect()
+                    }
+                };
+                for packet in delivered {
+                    if packets.send(packet).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
 /// Streaming statistics
 #[derive(Debug, Clone)]
 pub struct StreamingStats {
@@ -129,6 +443,10 @@ pub struct StreamingStats {
     pub start_time: Option<std::time::Instant>,
     /// Packets sent (for RTP streaming)
     pub packets_sent: u64,
+    /// Packets resent on NACK
+    pub packets_retransmitted: u64,
+    /// ULPFEC packets sent
+    pub fec_packets_sent: u64,
     /// Current timestamp
     pub timestamp: u32,
     /// SSRC for RTP stream
@@ -147,6 +465,8 @@ impl Default for StreamingStats {
             avg_encode_time_ms: 0.0,
             start_time: None,
             packets_sent: 0,
+            packets_retransmitted: 0,
+            fec_packets_sent: 0,
             timestamp: 0,
             ssrc: 0,
         }
@@ -160,13 +480,13 @@ use std::sync::atomic::{AtomicU64, Ordering};
 pub struct StreamingPipeline {
     streamer: VideoStreamer,
     frame_rate: u32,
+    started: Instant,
     stats: Arc<InternalStreamingStats>,
 }
 
 #[derive(Debug, Default)]
 struct InternalStreamingStats {
     frames_sent: AtomicU64,
-    bytes_sent: AtomicU64,
     last_frame_time: parking_lot::Mutex<Option<Instant>>,
     avg_frame_time: parking_lot::Mutex<f64>,
 }
@@ -179,6 +499,7 @@ impl StreamingPipeline {
         Ok(Self {
             streamer,
             frame_rate,
+            started: Instant::now(),
             stats: Arc::new(InternalStreamingStats::default()),
         })
     }
@@ -188,6 +509,38 @@ impl StreamingPipeline {
         self.streamer.get_track()
     }
 
+    /// Get the ULPFEC track
+    pub fn get_fec_track(&self) -> Arc<TrackLocalStaticRTP> {
+        self.streamer.get_fec_track()
+    }
+
+    /// Add the video and FEC tracks to `peer_connection`, see
+    /// `VideoStreamer::add_to_peer_connection`
+    pub async fn add_to_peer_connection(
+        &mut self,
+        peer_connection: &RTCPeerConnection,
+    ) -> Result<Arc<RTCRtpSender>, ClientError> {
+        self.streamer.add_to_peer_connection(peer_connection).await
+    }
+
+    /// Write packets to `output` instead of the track
+    pub fn set_output(&mut self, output: Arc<dyn TrackLocalWriter + Send + Sync>) {
+        self.streamer.set_output(output);
+    }
+
+    /// Write FEC packets to `output` instead of the FEC track
+    pub fn set_fec_output(&mut self, output: Arc<dyn TrackLocalWriter + Send + Sync>) {
+        self.streamer.set_fec_output(output);
+    }
+
+    /// Act on RTCP from the viewer, as read from the track's `RTCRtpSender`
+    pub async fn handle_rtcp(
+        &mut self,
+        packets: &[Box<dyn webrtc::rtcp::packet::Packet + Send + Sync>],
+    ) -> Result<(), ClientError> {
+        self.streamer.handle_rtcp(packets).await
+    }
+
     /// Start streaming frames
     pub async fn stream_frame(&mut self, frame: &Frame) -> Result<(), ClientError> {
         let frame_start = Instant::now();
@@ -214,12 +567,15 @@ impl StreamingPipeline {
             *last_frame_time = Some(frame_start);
         }
         
-        // For now, just update statistics - actual encoding will be implemented later
-        self.stats.frames_sent.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        self.stats.bytes_sent.fetch_add(
-            frame.data.len() as u64,
-            std::sync::atomic::Ordering::Relaxed
-        );
+        let captured = crate::Frame {
+            width: frame.width,
+            height: frame.height,
+            stride: frame.width * 4, // BGRA
+            data: frame.data.clone(),
+            timestamp: frame.timestamp.saturating_duration_since(self.started).as_micros() as u64,
+        };
+        self.streamer.encode_frame(&captured).await?;
+        self.stats.frames_sent.fetch_add(1, Ordering::Relaxed);
         
         Ok(())
     }
@@ -229,8 +585,7 @@ impl StreamingPipeline {
         let mut stats = self.streamer.get_stats();
         
         // Add our additional stats
-        stats.frames_sent = self.stats.frames_sent.load(std::sync::atomic::Ordering::Relaxed);
-        stats.bytes_sent = self.stats.bytes_sent.load(std::sync::atomic::Ordering::Relaxed);
+        stats.frames_sent = self.stats.frames_sent.load(Ordering::Relaxed);
         
         // Calculate current FPS based on average frame time
         let avg_frame_time = *self.stats.avg_frame_time.lock();
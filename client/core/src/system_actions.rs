@@ -0,0 +1,650 @@
+//! Remote reboot, sign-out, lock and Ctrl+Alt+Del
+//!
+//! Requests from the controller are checked against the permission profile
+//! and, unless the host runs unattended, confirmed by the user at the host
+//! before they run. A reboot can carry a reconnect request: the host stores
+//! a one-time ticket before restarting, re-registers with its persistent
+//! connection ID when it comes back, and accepts the controller's resume
+//! request without prompting again.
+
+use crate::brute_force::constant_time_eq;
+use crate::permission_profiles::{Permission, PermissionProfile};
+use crate::ClientError;
+use chrono::{DateTime, Utc};
+use genxlink_protocol::{
+    MessagePayload, ReconnectTicket, SystemAction, SystemActionRequest, SystemActionResponse,
+    SystemActionStatus,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex};
+use uuid::Uuid;
+
+/// How long a request waits for the host user to confirm
+pub const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a reconnect ticket stays valid
+pub const RECONNECT_TICKET_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// Permission required for a system action
+pub fn required_permission(action: SystemAction) -> Permission {
+    match action {
+        SystemAction::Reboot | SystemAction::SafeModeReboot => Permission::RestartDevice,
+        SystemAction::SignOut => Permission::SignOutUser,
+        SystemAction::Lock => Permission::LockDevice,
+        SystemAction::CtrlAltDel => Permission::SendCtrlAltDel,
+    }
+}
+
+/// Platform backend for system actions
+pub trait SystemActionBackend: Send + Sync {
+    /// Perform the action (reboots return once the restart is scheduled)
+    fn execute(&self, action: SystemAction) -> Result<(), ClientError>;
+
+    /// Backend name for logging
+    fn name(&self) -> &'static str;
+}
+
+/// Backend that only records requested actions (for tests and dry runs)
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    pub executed: parking_lot::Mutex<Vec<SystemAction>>,
+}
+
+impl SystemActionBackend for RecordingBackend {
+    fn execute(&self, action: SystemAction) -> Result<(), ClientError> {
+        self.executed.lock().push(action);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+}
+
+/// Linux implementation using systemd and logind
+#[cfg(target_os = "linux")]
+pub mod linux_impl {
+    use super::*;
+    use std::process::Command;
+
+    pub struct SystemdActions;
+
+    impl SystemdActions {
+        fn run(program: &str, args: &[&str]) -> Result<(), ClientError> {
+            let output = Command::new(program)
+                .args(args)
+                .output()
+                .map_err(|e| ClientError::IoError(format!("Failed to run {}: {}", program, e)))?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(ClientError::IoError(format!(
+                    "{} failed: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )))
+            }
+        }
+
+        fn session_id() -> Result<String, ClientError> {
+            std::env::var("XDG_SESSION_ID")
+                .map_err(|_| ClientError::IoError("No logind session (XDG_SESSION_ID unset)".to_string()))
+        }
+    }
+
+    impl SystemActionBackend for SystemdActions {
+        fn execute(&self, action: SystemAction) -> Result<(), ClientError> {
+            match action {
+                SystemAction::Reboot => Self::run("systemctl", &["reboot"]),
+                SystemAction::SignOut => Self::run("loginctl", &["terminate-session", &Self::session_id()?]),
+                SystemAction::Lock => Self::run("loginctl", &["lock-session", &Self::session_id()?]),
+                // No portable safe mode or secure attention sequence on Linux
+                SystemAction::SafeModeReboot | SystemAction::CtrlAltDel => {
+                    Err(ClientError::PlatformNotSupported)
+                }
+            }
+        }
+
+        fn name(&self) -> &'static str {
+            "systemd"
+        }
+    }
+}
+
+/// Windows implementation using the built-in system tools
+///
+/// Safe mode boots with networking, but only starts services listed under
+/// `SafeBoot\Network`, so the GenXLink service is added there first to come
+/// back online. The `safeboot` flag stays in the BCD store until it is
+/// deleted, so before setting it a RunOnce entry (prefixed with `*` so it
+/// also runs in safe mode) is registered to delete it, and the service
+/// entry, again at the first administrator logon. The restart after the
+/// safe mode session is then a normal one. If that entry never runs, recover
+/// from an elevated prompt with `bcdedit /deletevalue {current} safeboot`
+/// (or untick "Safe boot" under `msconfig` > Boot) and restart.
+#[cfg(target_os = "windows")]
+pub mod windows_impl {
+    use super::*;
+    use std::process::Command;
+
+    const RUN_ONCE_KEY: &str = r"HKLM\SOFTWARE\Microsoft\Windows\CurrentVersion\RunOnce";
+    const LEAVE_SAFE_MODE_VALUE: &str = "*GenXLinkLeaveSafeMode";
+    const LEAVE_SAFE_MODE_COMMAND: &str = concat!(
+        r"cmd.exe /c bcdedit /deletevalue {current} safeboot",
+        r" & reg delete HKLM\SYSTEM\CurrentControlSet\Control\SafeBoot\Network\GenXLinkService /f",
+    );
+    /// Services started in safe mode with networking (name as installed by the MSI)
+    const SAFE_BOOT_SERVICE_KEY: &str = r"HKLM\SYSTEM\CurrentControlSet\Control\SafeBoot\Network\GenXLinkService";
+
+    pub struct WindowsActions;
+
+    impl WindowsActions {
+        fn run(program: &str, args: &[&str]) -> Result<(), ClientError> {
+            let status = Command::new(program)
+                .args(args)
+                .status()
+                .map_err(|e| ClientError::IoError(format!("Failed to run {}: {}", program, e)))?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(ClientError::IoError(format!("{} exited with {}", program, status)))
+            }
+        }
+
+        /// Restart into safe mode with networking, for one boot only
+        fn safe_mode_reboot() -> Result<(), ClientError> {
+            Self::run("reg", &[
+                "add", RUN_ONCE_KEY,
+                "/v", LEAVE_SAFE_MODE_VALUE,
+                "/t", "REG_SZ",
+                "/d", LEAVE_SAFE_MODE_COMMAND,
+                "/f",
+            ])?;
+            let result = Self::run("reg", &["add", SAFE_BOOT_SERVICE_KEY, "/ve", "/t", "REG_SZ", "/d", "Service", "/f"])
+                .and_then(|_| Self::run("bcdedit", &["/set", "{current}", "safeboot", "network"]))
+                .and_then(|_| Self::run("shutdown", &["/r", "/t", "5"]));
+            if result.is_err() {
+                // Don't leave the flag or the cleanup entries behind for a later boot
+                let _ = Self::run("bcdedit", &["/deletevalue", "{current}", "safeboot"]);
+                let _ = Self::run("reg", &["delete", SAFE_BOOT_SERVICE_KEY, "/f"]);
+                let _ = Self::run("reg", &["delete", RUN_ONCE_KEY, "/v", LEAVE_SAFE_MODE_VALUE, "/f"]);
+            }
+            result
+        }
+    }
+
+    impl SystemActionBackend for WindowsActions {
+        fn execute(&self, action: SystemAction) -> Result<(), ClientError> {
+            match action {
+                SystemAction::Reboot => Self::run("shutdown", &["/r", "/t", "5"]),
+                SystemAction::SafeModeReboot => Self::safe_mode_reboot(),
+                SystemAction::SignOut => Self::run("shutdown", &["/l"]),
+                SystemAction::Lock => Self::run("rundll32.exe", &["user32.dll,LockWorkStation"]),
+                // SendSAS is only honoured from a service with SoftwareSASGeneration enabled
+                SystemAction::CtrlAltDel => Err(ClientError::PlatformNotSupported),
+            }
+        }
+
+        fn name(&self) -> &'static str {
+            "windows"
+        }
+    }
+}
+
+/// Create the system action backend for the current platform
+pub fn create_system_action_backend() -> Box<dyn SystemActionBackend> {
+    #[cfg(target_os = "linux")]
+    {
+        Box::new(linux_impl::SystemdActions)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        Box::new(windows_impl::WindowsActions)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    {
+        Box::new(RecordingBackend::default())
+    }
+}
+
+/// Reconnect ticket persisted across a restart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingReconnect {
+    /// Controller allowed to resume the session
+    pub controller_id: String,
+    pub ticket: ReconnectTicket,
+}
+
+/// File-backed store for the pending reconnect ticket
+#[derive(Debug, Clone)]
+pub struct ReconnectStore {
+    path: PathBuf,
+}
+
+impl ReconnectStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Store in the GenXLink config directory
+    pub fn default_location() -> Result<Self, ClientError> {
+        let dir = dirs::config_dir()
+            .ok_or_else(|| ClientError::IoError("Could not find config directory".to_string()))?
+            .join("GenXLink");
+        Ok(Self::new(dir.join("pending_reconnect.json")))
+    }
+
+    /// Persist a ticket, replacing any previous one
+    ///
+    /// The ticket admits a controller without prompting, so only the owner
+    /// can read the file.
+    pub fn save(&self, pending: &PendingReconnect) -> Result<(), ClientError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| ClientError::IoError(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+        let json = serde_json::to_string_pretty(pending)
+            .map_err(|e| ClientError::IoError(format!("Failed to serialize reconnect ticket: {}", e)))?;
+        let write_error = |e: std::io::Error| ClientError::IoError(format!("Failed to write {}: {}", self.path.display(), e));
+
+        let mut file = Self::create_private(&self.path).map_err(write_error)?;
+        std::io::Write::write_all(&mut file, json.as_bytes()).map_err(write_error)
+    }
+
+    /// Create (or truncate) `path` readable and writable by the owner only
+    #[cfg(unix)]
+    fn create_private(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        // The mode only applies to new files
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+
+    /// Create (or truncate) `path` with an ACL granting only the current user and SYSTEM
+    #[cfg(windows)]
+    fn create_private(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let user = std::env::var("USERNAME").unwrap_or_else(|_| "SYSTEM".to_string());
+        let status = std::process::Command::new("icacls")
+            .arg(path)
+            .args(["/inheritance:r", "/grant:r"])
+            .arg(format!("{}:F", user))
+            .arg("*S-1-5-18:F")
+            .status()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("icacls exited with {}", status)));
+        }
+        Ok(file)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn create_private(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        std::fs::File::create(path)
+    }
+
+    /// Load the stored ticket if it has not expired
+    pub fn load(&self) -> Option<PendingReconnect> {
+        let json = std::fs::read_to_string(&self.path).ok()?;
+        let pending: PendingReconnect = serde_json::from_str(&json).ok()?;
+        if pending.ticket.expires_at <= Utc::now() {
+            self.clear();
+            return None;
+        }
+        Some(pending)
+    }
+
+    /// Remove the stored ticket
+    pub fn clear(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+
+    /// Consume the stored ticket if `controller_id` and `token` match
+    pub fn redeem(&self, controller_id: &str, token: &str) -> bool {
+        let Some(pending) = self.load() else { return false };
+        let valid = pending.controller_id == controller_id
+            && constant_time_eq(pending.ticket.token.as_bytes(), token.as_bytes());
+        if valid {
+            self.clear();
+        }
+        valid
+    }
+}
+
+/// Generate a reconnect ticket for this host
+pub fn issue_ticket(connection_id: &str, lifetime: Duration) -> ReconnectTicket {
+    let token: String = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    ReconnectTicket {
+        connection_id: connection_id.to_string(),
+        token,
+        expires_at: Utc::now()
+            + chrono::Duration::from_std(lifetime).unwrap_or_else(|_| chrono::Duration::minutes(15)),
+    }
+}
+
+/// Action waiting for the host user's decision
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingSystemAction {
+    pub request_id: Uuid,
+    pub action: SystemAction,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+struct PendingEntry {
+    request: SystemActionRequest,
+    requested_by: String,
+    received: Instant,
+}
+
+/// Host-side handler for system action requests
+pub struct SystemActionManager {
+    backend: Box<dyn SystemActionBackend>,
+    profile: PermissionProfile,
+    connection_id: String,
+    store: ReconnectStore,
+    require_confirmation: bool,
+    confirmation_timeout: Duration,
+    pending: Mutex<HashMap<Uuid, PendingEntry>>,
+    notifier: Option<mpsc::UnboundedSender<PendingSystemAction>>,
+}
+
+impl SystemActionManager {
+    /// Create a manager for the host with the given persistent connection ID
+    pub fn new(
+        backend: Box<dyn SystemActionBackend>,
+        profile: PermissionProfile,
+        connection_id: impl Into<String>,
+        store: ReconnectStore,
+    ) -> Self {
+        Self {
+            backend,
+            profile,
+            connection_id: connection_id.into(),
+            store,
+            require_confirmation: true,
+            confirmation_timeout: CONFIRMATION_TIMEOUT,
+            pending: Mutex::new(HashMap::new()),
+            notifier: None,
+        }
+    }
+
+    /// Run actions without asking the host user (unattended access)
+    pub fn set_require_confirmation(&mut self, required: bool) {
+        self.require_confirmation = required;
+    }
+
+    /// Override how long requests wait for confirmation
+    pub fn set_confirmation_timeout(&mut self, timeout: Duration) {
+        self.confirmation_timeout = timeout;
+    }
+
+    /// Deliver confirmation prompts to the host UI
+    pub fn set_confirmation_notifier(&mut self, notifier: mpsc::UnboundedSender<PendingSystemAction>) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Handle a request from `requested_by` (the controller's connection ID)
+    pub async fn handle_request(&self, requested_by: &str, request: SystemActionRequest) -> SystemActionResponse {
+        let permission = required_permission(request.action);
+        if !self.profile.has_permission(&permission) {
+            let error = ClientError::PermissionDenied(permission.name().to_string());
+            return Self::response(&request, SystemActionStatus::Failed(error.to_string()), None);
+        }
+
+        if !self.require_confirmation {
+            return self.execute(requested_by, &request);
+        }
+
+        let prompt = PendingSystemAction {
+            request_id: request.request_id,
+            action: request.action,
+            requested_by: requested_by.to_string(),
+            requested_at: Utc::now(),
+        };
+        self.pending.lock().await.insert(
+            request.request_id,
+            PendingEntry {
+                request: request.clone(),
+                requested_by: requested_by.to_string(),
+                received: Instant::now(),
+            },
+        );
+
+        match &self.notifier {
+            Some(notifier) if notifier.send(prompt).is_ok() => {
+                tracing::info!("Waiting for confirmation of {:?} from {}", request.action, requested_by);
+                Self::response(&request, SystemActionStatus::PendingConfirmation, None)
+            }
+            _ => {
+                // Nobody can confirm, so the request cannot proceed
+                self.pending.lock().await.remove(&request.request_id);
+                Self::response(&request, SystemActionStatus::Declined, None)
+            }
+        }
+    }
+
+    /// Record the host user's decision for a pending request
+    pub async fn confirm(&self, request_id: Uuid, approved: bool) -> Result<SystemActionResponse, ClientError> {
+        let entry = self
+            .pending
+            .lock()
+            .await
+            .remove(&request_id)
+            .ok_or_else(|| ClientError::InvalidInput(format!("No pending system action {}", request_id)))?;
+
+        if !approved || entry.received.elapsed() > self.confirmation_timeout {
+            return Ok(Self::response(&entry.request, SystemActionStatus::Declined, None));
+        }
+        Ok(self.execute(&entry.requested_by, &entry.request))
+    }
+
+    /// Drop requests nobody confirmed in time, returning their declined responses
+    pub async fn expire_pending(&self) -> Vec<SystemActionResponse> {
+        let timeout = self.confirmation_timeout;
+        let mut pending = self.pending.lock().await;
+        let expired: Vec<Uuid> = pending
+            .iter()
+            .filter(|(_, entry)| entry.received.elapsed() > timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| pending.remove(&id))
+            .map(|entry| Self::response(&entry.request, SystemActionStatus::Declined, None))
+            .collect()
+    }
+
+    /// Handle a system action message, returning the reply
+    pub async fn handle_message(&self, requested_by: &str, payload: &MessagePayload) -> Option<MessagePayload> {
+        match payload {
+            MessagePayload::SystemAction(request) => Some(MessagePayload::SystemActionResponse(
+                self.handle_request(requested_by, request.clone()).await,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Check a resume request presented after a restart
+    pub fn redeem_reconnect(&self, controller_id: &str, token: &str) -> bool {
+        self.store.redeem(controller_id, token)
+    }
+
+    fn execute(&self, requested_by: &str, request: &SystemActionRequest) -> SystemActionResponse {
+        let restarts = matches!(request.action, SystemAction::Reboot | SystemAction::SafeModeReboot);
+
+        let ticket = if request.reconnect && restarts {
+            let ticket = issue_ticket(&self.connection_id, RECONNECT_TICKET_LIFETIME);
+            let pending = PendingReconnect {
+                controller_id: requested_by.to_string(),
+                ticket: ticket.clone(),
+            };
+            if let Err(e) = self.store.save(&pending) {
+                return Self::response(request, SystemActionStatus::Failed(e.to_string()), None);
+            }
+            Some(ticket)
+        } else {
+            None
+        };
+
+        tracing::info!(
+            "Executing {:?} requested by {} ({} backend)",
+            request.action,
+            requested_by,
+            self.backend.name()
+        );
+        match self.backend.execute(request.action) {
+            Ok(()) => Self::response(request, SystemActionStatus::Executing, ticket),
+            Err(e) => {
+                if ticket.is_some() {
+                    self.store.clear();
+                }
+                Self::response(request, SystemActionStatus::Failed(e.to_string()), None)
+            }
+        }
+    }
+
+    fn response(
+        request: &SystemActionRequest,
+        status: SystemActionStatus,
+        reconnect: Option<ReconnectTicket>,
+    ) -> SystemActionResponse {
+        SystemActionResponse {
+            request_id: request.request_id,
+            action: request.action,
+            status,
+            reconnect,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission_profiles::PermissionProfileType;
+
+    fn temp_store() -> ReconnectStore {
+        ReconnectStore::new(
+            std::env::temp_dir().join(format!("genxlink-reconnect-{}.json", Uuid::new_v4())),
+        )
+    }
+
+    fn request(action: SystemAction, reconnect: bool) -> SystemActionRequest {
+        SystemActionRequest {
+            request_id: Uuid::new_v4(),
+            action,
+            reconnect,
+        }
+    }
+
+    fn manager(profile: PermissionProfileType, store: ReconnectStore) -> SystemActionManager {
+        SystemActionManager::new(
+            Box::new(RecordingBackend::default()),
+            PermissionProfile::new(profile),
+            "123-456-789",
+            store,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_requires_permission() {
+        let manager = manager(PermissionProfileType::ScreenSharing, temp_store());
+        let response = manager.handle_request("987-654-321", request(SystemAction::Reboot, false)).await;
+        assert!(matches!(response.status, SystemActionStatus::Failed(ref e) if e.contains("Permission denied")));
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_flow() {
+        let mut manager = manager(PermissionProfileType::FullAccess, temp_store());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager.set_confirmation_notifier(tx);
+
+        let req = request(SystemAction::SignOut, false);
+        let response = manager.handle_request("987-654-321", req.clone()).await;
+        assert_eq!(response.status, SystemActionStatus::PendingConfirmation);
+
+        let prompt = rx.try_recv().unwrap();
+        assert_eq!(prompt.action, SystemAction::SignOut);
+        assert_eq!(prompt.requested_by, "987-654-321");
+
+        let declined = manager.confirm(req.request_id, false).await.unwrap();
+        assert_eq!(declined.status, SystemActionStatus::Declined);
+        // A decided request cannot be confirmed again
+        assert!(manager.confirm(req.request_id, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_declined_without_confirmation_channel() {
+        let manager = manager(PermissionProfileType::FullAccess, temp_store());
+        let response = manager.handle_request("987-654-321", request(SystemAction::Lock, false)).await;
+        assert_eq!(response.status, SystemActionStatus::Declined);
+    }
+
+    #[tokio::test]
+    async fn test_unattended_reboot_issues_reconnect_ticket() {
+        let store = temp_store();
+        let mut manager = manager(PermissionProfileType::UnattendedAccess, store.clone());
+        manager.set_require_confirmation(false);
+
+        let response = manager.handle_request("987-654-321", request(SystemAction::Reboot, true)).await;
+        assert_eq!(response.status, SystemActionStatus::Executing);
+        let ticket = response.reconnect.unwrap();
+        assert_eq!(ticket.connection_id, "123-456-789");
+
+        // Survives the restart, only the requesting controller can redeem it, once
+        assert_eq!(store.load().unwrap().ticket, ticket);
+        assert!(!manager.redeem_reconnect("111-111-111", &ticket.token));
+        assert!(!manager.redeem_reconnect("987-654-321", "wrong"));
+        assert!(manager.redeem_reconnect("987-654-321", &ticket.token));
+        assert!(!manager.redeem_reconnect("987-654-321", &ticket.token));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ticket_file_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let store = temp_store();
+        std::fs::write(&store.path, "{}").unwrap();
+        std::fs::set_permissions(&store.path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let ticket = issue_ticket("123-456-789", RECONNECT_TICKET_LIFETIME);
+        store
+            .save(&PendingReconnect { controller_id: "987-654-321".to_string(), ticket: ticket.clone() })
+            .unwrap();
+        let mode = std::fs::metadata(&store.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(store.load().unwrap().ticket, ticket);
+        store.clear();
+    }
+
+    #[test]
+    fn test_expired_ticket_is_discarded() {
+        let store = temp_store();
+        let mut ticket = issue_ticket("123-456-789", RECONNECT_TICKET_LIFETIME);
+        ticket.expires_at = Utc::now() - chrono::Duration::seconds(1);
+        store
+            .save(&PendingReconnect { controller_id: "987-654-321".to_string(), ticket })
+            .unwrap();
+        assert!(store.load().is_none());
+    }
+}
@@ -0,0 +1,776 @@
+//! Remote system information and process inventory
+//!
+//! Collects OS, CPU, memory, disk, network, user, uptime and process
+//! information on the host, answers `SystemInfoRequest`s from the
+//! controller and optionally terminates processes. Snapshots can be
+//! exported as JSON for support tickets.
+
+use crate::permission_profiles::{Permission, PermissionProfile};
+use crate::ClientError;
+use genxlink_protocol::{
+    MessagePayload, ProcessKillRequest, ProcessKillResponse, SystemInfo, SystemInfoResponse,
+};
+use std::path::Path;
+
+/// Platform source of system information
+pub trait SystemInfoProvider: Send + Sync {
+    /// Collect a snapshot, optionally including the process list
+    fn collect(&self, include_processes: bool) -> Result<SystemInfo, ClientError>;
+
+    /// Terminate a process
+    fn kill_process(&self, pid: u32, force: bool) -> Result<(), ClientError>;
+}
+
+/// Provider for platforms without an implementation
+pub struct UnsupportedSystemInfo;
+
+impl SystemInfoProvider for UnsupportedSystemInfo {
+    fn collect(&self, _include_processes: bool) -> Result<SystemInfo, ClientError> {
+        Err(ClientError::PlatformNotSupported)
+    }
+
+    fn kill_process(&self, _pid: u32, _force: bool) -> Result<(), ClientError> {
+        Err(ClientError::PlatformNotSupported)
+    }
+}
+
+/// Linux implementation reading `/proc` and `/sys`
+#[cfg(target_os = "linux")]
+pub mod linux_impl {
+    use super::*;
+    use genxlink_protocol::{
+        CpuInfo, DiskInfo, LoggedInUser, MemoryInfo, NetworkInterfaceInfo, OsInfo, ProcessInfo,
+    };
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Size of a glibc `struct utmp` record
+    const UTMP_RECORD_SIZE: usize = 384;
+    /// `ut_type` of an interactive user session
+    const USER_PROCESS: i16 = 7;
+
+    /// Filesystems that are not interesting in a disk inventory
+    const PSEUDO_FILESYSTEMS: &[&str] = &[
+        "proc", "sysfs", "devtmpfs", "devpts", "tmpfs", "cgroup", "cgroup2", "securityfs",
+        "pstore", "debugfs", "tracefs", "configfs", "fusectl", "mqueue", "hugetlbfs",
+        "bpf", "autofs", "binfmt_misc", "overlay", "squashfs", "nsfs", "efivarfs",
+    ];
+
+    pub struct ProcSystemInfo {
+        proc_root: PathBuf,
+        sys_root: PathBuf,
+        etc_root: PathBuf,
+        utmp_path: PathBuf,
+    }
+
+    impl ProcSystemInfo {
+        pub fn new() -> Self {
+            Self {
+                proc_root: PathBuf::from("/proc"),
+                sys_root: PathBuf::from("/sys"),
+                etc_root: PathBuf::from("/etc"),
+                utmp_path: PathBuf::from("/var/run/utmp"),
+            }
+        }
+
+        /// Read from an alternative root (used by tests with fixture trees)
+        pub fn with_root(root: &Path) -> Self {
+            Self {
+                proc_root: root.join("proc"),
+                sys_root: root.join("sys"),
+                etc_root: root.join("etc"),
+                utmp_path: root.join("var/run/utmp"),
+            }
+        }
+
+        fn read(&self, path: PathBuf) -> Option<String> {
+            fs::read_to_string(path).ok()
+        }
+
+        fn hostname(&self) -> String {
+            self.read(self.proc_root.join("sys/kernel/hostname"))
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(whoami::devicename)
+        }
+
+        fn os_info(&self) -> OsInfo {
+            let release = self
+                .read(self.etc_root.join("os-release"))
+                .map(|s| parse_os_release(&s))
+                .unwrap_or_default();
+
+            OsInfo {
+                name: release.get("NAME").cloned().unwrap_or_else(|| "Linux".to_string()),
+                version: release
+                    .get("VERSION")
+                    .or_else(|| release.get("VERSION_ID"))
+                    .cloned()
+                    .unwrap_or_default(),
+                kernel: self
+                    .read(self.proc_root.join("sys/kernel/osrelease"))
+                    .map(|s| s.trim().to_string())
+                    .unwrap_or_default(),
+                arch: std::env::consts::ARCH.to_string(),
+            }
+        }
+
+        fn cpu_info(&self) -> CpuInfo {
+            let mut cpu = self
+                .read(self.proc_root.join("cpuinfo"))
+                .map(|s| parse_cpuinfo(&s))
+                .unwrap_or_default();
+            if let Some(loadavg) = self.read(self.proc_root.join("loadavg")) {
+                cpu.load_average = parse_loadavg(&loadavg);
+            }
+            cpu
+        }
+
+        fn memory_info(&self) -> MemoryInfo {
+            self.read(self.proc_root.join("meminfo"))
+                .map(|s| parse_meminfo(&s))
+                .unwrap_or_default()
+        }
+
+        fn uptime_secs(&self) -> u64 {
+            self.read(self.proc_root.join("uptime"))
+                .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+                .map(|secs| secs as u64)
+                .unwrap_or(0)
+        }
+
+        fn disks(&self) -> Vec<DiskInfo> {
+            let mounts = self.read(self.proc_root.join("mounts")).unwrap_or_default();
+            parse_mounts(&mounts)
+                .into_iter()
+                .map(|(device, mount_point, filesystem)| {
+                    let (total_bytes, available_bytes) = statvfs(&mount_point).unwrap_or((0, 0));
+                    DiskInfo {
+                        device,
+                        mount_point,
+                        filesystem,
+                        total_bytes,
+                        available_bytes,
+                    }
+                })
+                .collect()
+        }
+
+        fn network_interfaces(&self) -> Vec<NetworkInterfaceInfo> {
+            let net_dir = self.sys_root.join("class/net");
+            let mut addresses = interface_addresses();
+            let mut interfaces: Vec<NetworkInterfaceInfo> = fs::read_dir(&net_dir)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .map(|entry| {
+                            let name = entry.file_name().to_string_lossy().into_owned();
+                            let dir = entry.path();
+                            let read_u64 = |file: &str| {
+                                self.read(dir.join(file))
+                                    .and_then(|s| s.trim().parse().ok())
+                                    .unwrap_or(0)
+                            };
+                            NetworkInterfaceInfo {
+                                mac_address: self
+                                    .read(dir.join("address"))
+                                    .map(|s| s.trim().to_string())
+                                    .filter(|s| !s.is_empty() && s != "00:00:00:00:00:00"),
+                                addresses: addresses.remove(&name).unwrap_or_default(),
+                                is_up: self
+                                    .read(dir.join("operstate"))
+                                    .map(|s| s.trim() == "up")
+                                    .unwrap_or(false),
+                                rx_bytes: read_u64("statistics/rx_bytes"),
+                                tx_bytes: read_u64("statistics/tx_bytes"),
+                                name,
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+            interfaces
+        }
+
+        fn logged_in_users(&self) -> Vec<LoggedInUser> {
+            fs::read(&self.utmp_path)
+                .map(|data| parse_utmp(&data))
+                .unwrap_or_default()
+        }
+
+        fn processes(&self) -> Vec<ProcessInfo> {
+            let users = self
+                .read(self.etc_root.join("passwd"))
+                .map(|s| parse_passwd(&s))
+                .unwrap_or_default();
+            let ticks_per_sec = clock_ticks_per_second();
+            let page_size = page_size();
+
+            let mut processes: Vec<ProcessInfo> = fs::read_dir(&self.proc_root)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .filter_map(|entry| {
+                            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+                            let dir = entry.path();
+                            let stat = parse_proc_stat(&self.read(dir.join("stat"))?)?;
+                            let uid = self
+                                .read(dir.join("status"))
+                                .and_then(|s| parse_status_uid(&s));
+                            let command_line = self
+                                .read(dir.join("cmdline"))
+                                .map(|s| s.split('\0').filter(|a| !a.is_empty()).collect::<Vec<_>>().join(" "))
+                                .unwrap_or_default();
+
+                            Some(ProcessInfo {
+                                pid,
+                                parent_pid: stat.parent_pid,
+                                name: stat.name,
+                                command_line,
+                                user: uid.and_then(|uid| users.get(&uid).cloned()),
+                                state: stat.state,
+                                memory_bytes: stat.rss_pages * page_size,
+                                cpu_time_ms: (stat.utime + stat.stime) * 1000 / ticks_per_sec,
+                                threads: stat.threads,
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            processes.sort_by_key(|p| p.pid);
+            processes
+        }
+    }
+
+    impl Default for ProcSystemInfo {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl SystemInfoProvider for ProcSystemInfo {
+        fn collect(&self, include_processes: bool) -> Result<SystemInfo, ClientError> {
+            if !self.proc_root.join("meminfo").exists() {
+                return Err(ClientError::IoError(format!(
+                    "{} is not a procfs mount",
+                    self.proc_root.display()
+                )));
+            }
+
+            Ok(SystemInfo {
+                hostname: self.hostname(),
+                os: self.os_info(),
+                cpu: self.cpu_info(),
+                memory: self.memory_info(),
+                disks: self.disks(),
+                network_interfaces: self.network_interfaces(),
+                logged_in_users: self.logged_in_users(),
+                uptime_secs: self.uptime_secs(),
+                processes: if include_processes { self.processes() } else { Vec::new() },
+                collected_at: chrono::Utc::now(),
+            })
+        }
+
+        fn kill_process(&self, pid: u32, force: bool) -> Result<(), ClientError> {
+            // Pids that don't fit a pid_t would turn negative and signal
+            // process groups (or, for -1, every process we can reach)
+            let target = match libc::pid_t::try_from(pid) {
+                Ok(target) if target > 1 => target,
+                _ => return Err(ClientError::InvalidInput(format!("Refusing to kill pid {}", pid))),
+            };
+            let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+            // SAFETY: kill(2) has no memory-safety preconditions
+            let result = unsafe { libc::kill(target, signal) };
+            if result == 0 {
+                tracing::info!("Sent signal {} to process {}", signal, pid);
+                Ok(())
+            } else {
+                Err(ClientError::IoError(format!(
+                    "Failed to kill process {}: {}",
+                    pid,
+                    std::io::Error::last_os_error()
+                )))
+            }
+        }
+    }
+
+    /// Fields of interest from `/proc/[pid]/stat`
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ProcStat {
+        pub name: String,
+        pub state: String,
+        pub parent_pid: u32,
+        pub utime: u64,
+        pub stime: u64,
+        pub threads: u32,
+        pub rss_pages: u64,
+    }
+
+    /// Parse `/proc/[pid]/stat` (the command name may contain spaces and parentheses)
+    pub fn parse_proc_stat(content: &str) -> Option<ProcStat> {
+        let open = content.find('(')?;
+        let close = content.rfind(')')?;
+        let name = content[open + 1..close].to_string();
+        let fields: Vec<&str> = content[close + 1..].split_whitespace().collect();
+        // Fields after the name start at field 3 (state)
+        let field = |n: usize| fields.get(n - 3).copied();
+
+        Some(ProcStat {
+            name,
+            state: field(3)?.to_string(),
+            parent_pid: field(4)?.parse().ok()?,
+            utime: field(14)?.parse().ok()?,
+            stime: field(15)?.parse().ok()?,
+            threads: field(20)?.parse().ok()?,
+            rss_pages: field(24)?.parse().ok()?,
+        })
+    }
+
+    /// Real UID from `/proc/[pid]/status`
+    pub fn parse_status_uid(content: &str) -> Option<u32> {
+        content
+            .lines()
+            .find(|line| line.starts_with("Uid:"))?
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()
+    }
+
+    /// Map of UID to user name from `/etc/passwd`
+    pub fn parse_passwd(content: &str) -> HashMap<u32, String> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split(':');
+                let name = parts.next()?;
+                let uid = parts.nth(1)?.parse().ok()?;
+                Some((uid, name.to_string()))
+            })
+            .collect()
+    }
+
+    /// Parse `/proc/meminfo` (values are in kB)
+    pub fn parse_meminfo(content: &str) -> MemoryInfo {
+        let values: HashMap<&str, u64> = content
+            .lines()
+            .filter_map(|line| {
+                let (key, rest) = line.split_once(':')?;
+                let kb = rest.split_whitespace().next()?.parse::<u64>().ok()?;
+                Some((key, kb * 1024))
+            })
+            .collect();
+
+        MemoryInfo {
+            total_bytes: values.get("MemTotal").copied().unwrap_or(0),
+            available_bytes: values
+                .get("MemAvailable")
+                .or_else(|| values.get("MemFree"))
+                .copied()
+                .unwrap_or(0),
+            swap_total_bytes: values.get("SwapTotal").copied().unwrap_or(0),
+            swap_free_bytes: values.get("SwapFree").copied().unwrap_or(0),
+        }
+    }
+
+    /// Busy and total jiffies from the aggregate `cpu` line of `/proc/stat`
+    pub fn parse_cpu_times(content: &str) -> Option<(u64, u64)> {
+        let line = content.lines().find(|line| line.starts_with("cpu "))?;
+        // user nice system idle iowait irq softirq steal (guest time is already in user)
+        let values: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .take(8)
+            .map(|value| value.parse().ok())
+            .collect::<Option<_>>()?;
+        if values.len() < 4 {
+            return None;
+        }
+        let total: u64 = values.iter().sum();
+        let idle = values[3] + values.get(4).copied().unwrap_or(0);
+        Some((total - idle, total))
+    }
+
+    /// Parse `/proc/cpuinfo`
+    pub fn parse_cpuinfo(content: &str) -> CpuInfo {
+        let mut cpu = CpuInfo::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let value = value.trim();
+            match key.trim() {
+                "processor" => cpu.logical_cores += 1,
+                "model name" if cpu.model.is_empty() => cpu.model = value.to_string(),
+                "cpu MHz" if cpu.frequency_mhz.is_none() => cpu.frequency_mhz = value.parse().ok(),
+                _ => {}
+            }
+        }
+        cpu
+    }
+
+    /// Parse `/proc/loadavg`
+    pub fn parse_loadavg(content: &str) -> [f64; 3] {
+        let mut load = [0.0; 3];
+        for (slot, value) in load.iter_mut().zip(content.split_whitespace()) {
+            *slot = value.parse().unwrap_or(0.0);
+        }
+        load
+    }
+
+    /// Parse `/etc/os-release` into key/value pairs
+    pub fn parse_os_release(content: &str) -> HashMap<String, String> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                Some((key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+            })
+            .collect()
+    }
+
+    /// Real filesystems from `/proc/mounts` as (device, mount point, fs type)
+    pub fn parse_mounts(content: &str) -> Vec<(String, String, String)> {
+        content
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let device = parts.next()?;
+                let mount_point = parts.next()?.replace("\\040", " ");
+                let filesystem = parts.next()?;
+                if PSEUDO_FILESYSTEMS.contains(&filesystem) {
+                    return None;
+                }
+                Some((device.to_string(), mount_point, filesystem.to_string()))
+            })
+            .collect()
+    }
+
+    /// Parse glibc `utmp` records, keeping interactive user sessions
+    pub fn parse_utmp(data: &[u8]) -> Vec<LoggedInUser> {
+        let c_string = |bytes: &[u8]| {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+
+        data.chunks_exact(UTMP_RECORD_SIZE)
+            .filter(|record| i16::from_ne_bytes([record[0], record[1]]) == USER_PROCESS)
+            .map(|record| {
+                let host = c_string(&record[76..332]);
+                let tv_sec = i32::from_ne_bytes([record[340], record[341], record[342], record[343]]);
+                LoggedInUser {
+                    username: c_string(&record[44..76]),
+                    terminal: c_string(&record[8..40]),
+                    host: if host.is_empty() { None } else { Some(host) },
+                    login_time: u64::try_from(tv_sec).ok().filter(|&t| t > 0),
+                }
+            })
+            .collect()
+    }
+
+    fn clock_ticks_per_second() -> u64 {
+        // SAFETY: sysconf has no memory-safety preconditions
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if ticks > 0 { ticks as u64 } else { 100 }
+    }
+
+    pub fn page_size() -> u64 {
+        // SAFETY: sysconf has no memory-safety preconditions
+        let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        if size > 0 { size as u64 } else { 4096 }
+    }
+
+    /// Total and available bytes of the filesystem mounted at `path`
+    fn statvfs(path: &str) -> Option<(u64, u64)> {
+        let c_path = std::ffi::CString::new(path).ok()?;
+        // SAFETY: c_path is a valid NUL-terminated string and stat is a
+        // properly sized out-parameter
+        unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            if libc::statvfs(c_path.as_ptr(), &mut stat) != 0 {
+                return None;
+            }
+            let fragment = stat.f_frsize as u64;
+            Some((stat.f_blocks as u64 * fragment, stat.f_bavail as u64 * fragment))
+        }
+    }
+
+    /// IPv4 and IPv6 addresses per interface name
+    fn interface_addresses() -> HashMap<String, Vec<String>> {
+        let mut addresses: HashMap<String, Vec<String>> = HashMap::new();
+        // SAFETY: getifaddrs allocates a linked list that we walk read-only
+        // and release with freeifaddrs
+        unsafe {
+            let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+            if libc::getifaddrs(&mut head) != 0 {
+                return addresses;
+            }
+            let mut current = head;
+            while !current.is_null() {
+                let ifa = &*current;
+                current = ifa.ifa_next;
+                if ifa.ifa_addr.is_null() {
+                    continue;
+                }
+                let name = std::ffi::CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned();
+                let address = match (*ifa.ifa_addr).sa_family as i32 {
+                    libc::AF_INET => {
+                        let sin = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                        std::net::Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)).to_string()
+                    }
+                    libc::AF_INET6 => {
+                        let sin6 = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                        std::net::Ipv6Addr::from(sin6.sin6_addr.s6_addr).to_string()
+                    }
+                    _ => continue,
+                };
+                addresses.entry(name).or_default().push(address);
+            }
+            libc::freeifaddrs(head);
+        }
+        addresses
+    }
+}
+
+/// Create the system information provider for the current platform
+pub fn create_system_info_provider() -> Box<dyn SystemInfoProvider> {
+    #[cfg(target_os = "linux")]
+    {
+        Box::new(linux_impl::ProcSystemInfo::new())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Box::new(UnsupportedSystemInfo)
+    }
+}
+
+/// Serialize a snapshot as pretty JSON for support tickets
+pub fn export_json(info: &SystemInfo) -> Result<String, ClientError> {
+    serde_json::to_string_pretty(info)
+        .map_err(|e| ClientError::IoError(format!("Failed to serialize system info: {}", e)))
+}
+
+/// Write a snapshot as pretty JSON to `path`
+pub fn export_to_file(info: &SystemInfo, path: &Path) -> Result<(), ClientError> {
+    let json = export_json(info)?;
+    std::fs::write(path, json)
+        .map_err(|e| ClientError::IoError(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+/// Answers system information and process kill requests, enforcing permissions
+pub struct SystemInfoService {
+    provider: Box<dyn SystemInfoProvider>,
+    profile: PermissionProfile,
+}
+
+impl SystemInfoService {
+    /// Create a new system information service
+    pub fn new(provider: Box<dyn SystemInfoProvider>, profile: PermissionProfile) -> Self {
+        Self { provider, profile }
+    }
+
+    /// Collect a snapshot if `SeeSystemInformation` is granted
+    pub fn system_info(&self, include_processes: bool) -> Result<SystemInfo, ClientError> {
+        self.require(Permission::SeeSystemInformation)?;
+        self.provider.collect(include_processes)
+    }
+
+    /// Terminate a process if `KillProcesses` is granted
+    pub fn kill_process(&self, request: &ProcessKillRequest) -> Result<(), ClientError> {
+        self.require(Permission::KillProcesses)?;
+        self.provider.kill_process(request.pid, request.force)
+    }
+
+    /// Handle a system information message, returning the reply
+    ///
+    /// Returns `None` for messages this service does not handle.
+    pub fn handle_message(&self, payload: &MessagePayload) -> Option<MessagePayload> {
+        match payload {
+            MessagePayload::SystemInfoRequest(request) => {
+                let response = match self.system_info(request.include_processes) {
+                    Ok(info) => SystemInfoResponse { info: Some(info), error: None },
+                    Err(e) => SystemInfoResponse { info: None, error: Some(e.to_string()) },
+                };
+                Some(MessagePayload::SystemInfoResponse(Box::new(response)))
+            }
+            MessagePayload::ProcessKillRequest(request) => {
+                let result = self.kill_process(request);
+                Some(MessagePayload::ProcessKillResponse(ProcessKillResponse {
+                    pid: request.pid,
+                    success: result.is_ok(),
+                    error: result.err().map(|e| e.to_string()),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn require(&self, permission: Permission) -> Result<(), ClientError> {
+        if self.profile.has_permission(&permission) {
+            Ok(())
+        } else {
+            Err(ClientError::PermissionDenied(permission.name().to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::permission_profiles::PermissionProfileType;
+    use genxlink_protocol::SystemInfoRequest;
+
+    #[test]
+    fn test_permissions_enforced() {
+        let mut profile = PermissionProfile::new(PermissionProfileType::Default);
+        profile.set_permission(Permission::SeeSystemInformation, false);
+        let service = SystemInfoService::new(Box::new(UnsupportedSystemInfo), profile);
+
+        let reply = service.handle_message(&MessagePayload::SystemInfoRequest(SystemInfoRequest {
+            include_processes: false,
+        }));
+        match reply {
+            Some(MessagePayload::SystemInfoResponse(response)) => {
+                assert!(response.info.is_none());
+                assert!(response.error.unwrap().contains("Permission denied"));
+            }
+            _ => panic!("Expected system info response"),
+        }
+
+        // Default profile does not include killing processes
+        let reply = service.handle_message(&MessagePayload::ProcessKillRequest(ProcessKillRequest {
+            pid: 1234,
+            force: false,
+        }));
+        match reply {
+            Some(MessagePayload::ProcessKillResponse(response)) => assert!(!response.success),
+            _ => panic!("Expected process kill response"),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    mod linux {
+        use super::*;
+        use crate::system_info::linux_impl::*;
+
+        #[test]
+        fn test_parse_proc_stat() {
+            let stat = "1234 (Web Content (x)) S 1 1234 1234 0 -1 4194560 100 0 0 0 250 50 0 0 20 0 7 0 100 1000000 512 18446744073709551615";
+            let parsed = parse_proc_stat(stat).unwrap();
+            assert_eq!(parsed.name, "Web Content (x)");
+            assert_eq!(parsed.state, "S");
+            assert_eq!(parsed.parent_pid, 1);
+            assert_eq!(parsed.utime, 250);
+            assert_eq!(parsed.stime, 50);
+            assert_eq!(parsed.threads, 7);
+            assert_eq!(parsed.rss_pages, 512);
+        }
+
+        #[test]
+        fn test_parse_meminfo_and_cpuinfo() {
+            let meminfo = "MemTotal:       16384000 kB\nMemFree:         1000 kB\nMemAvailable:    8192000 kB\nSwapTotal:       2048 kB\nSwapFree:        1024 kB\n";
+            let memory = parse_meminfo(meminfo);
+            assert_eq!(memory.total_bytes, 16384000 * 1024);
+            assert_eq!(memory.available_bytes, 8192000 * 1024);
+            assert_eq!(memory.swap_free_bytes, 1024 * 1024);
+
+            let cpuinfo = "processor\t: 0\nmodel name\t: Test CPU @ 3.00GHz\ncpu MHz\t\t: 2999.998\n\nprocessor\t: 1\nmodel name\t: Test CPU @ 3.00GHz\n";
+            let cpu = parse_cpuinfo(cpuinfo);
+            assert_eq!(cpu.logical_cores, 2);
+            assert_eq!(cpu.model, "Test CPU @ 3.00GHz");
+            assert_eq!(cpu.frequency_mhz, Some(2999.998));
+        }
+
+        #[test]
+        fn test_parse_cpu_times() {
+            let stat = "cpu  100 5 50 800 20 3 2 0 10 0\ncpu0 50 2 25 400 10 1 1 0 5 0\nintr 12345\n";
+            // idle + iowait = 820 of 980
+            assert_eq!(parse_cpu_times(stat), Some((160, 980)));
+            assert_eq!(parse_cpu_times("intr 1\n"), None);
+        }
+
+        #[test]
+        fn test_parse_mounts_skips_pseudo_filesystems() {
+            let mounts = "proc /proc proc rw 0 0\n/dev/sda1 / ext4 rw 0 0\ntmpfs /run tmpfs rw 0 0\n/dev/sdb1 /mnt/my\\040disk vfat rw 0 0\n";
+            let parsed = parse_mounts(mounts);
+            assert_eq!(parsed.len(), 2);
+            assert_eq!(parsed[1].1, "/mnt/my disk");
+        }
+
+        #[test]
+        fn test_parse_utmp() {
+            let mut record = vec![0u8; 384];
+            record[0..2].copy_from_slice(&7i16.to_ne_bytes());
+            record[8..13].copy_from_slice(b"pts/0");
+            record[44..49].copy_from_slice(b"alice");
+            record[76..85].copy_from_slice(b"10.0.0.42");
+            record[340..344].copy_from_slice(&1_700_000_000i32.to_ne_bytes());
+            let mut boot = vec![0u8; 384];
+            boot[0..2].copy_from_slice(&2i16.to_ne_bytes());
+            record.extend(boot);
+
+            let users = parse_utmp(&record);
+            assert_eq!(users.len(), 1);
+            assert_eq!(users[0].username, "alice");
+            assert_eq!(users[0].terminal, "pts/0");
+            assert_eq!(users[0].host.as_deref(), Some("10.0.0.42"));
+            assert_eq!(users[0].login_time, Some(1_700_000_000));
+        }
+
+        #[test]
+        fn test_collect_from_fixture_tree() {
+            let root = std::env::temp_dir().join(format!("genxlink-sysinfo-{}", uuid::Uuid::new_v4()));
+            let write = |path: &str, content: &str| {
+                let path = root.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, content).unwrap();
+            };
+            write("proc/meminfo", "MemTotal: 2048 kB\nMemAvailable: 1024 kB\n");
+            write("proc/uptime", "3600.50 7000.00\n");
+            write("proc/loadavg", "0.50 0.25 0.10 1/100 4242\n");
+            write("proc/sys/kernel/hostname", "test-host\n");
+            write("proc/42/stat", "42 (worker) R 1 42 42 0 -1 0 0 0 0 0 100 100 0 0 20 0 3 0 1 0 10 0");
+            write("proc/42/status", "Name:\tworker\nUid:\t1000\t1000\t1000\t1000\n");
+            write("proc/42/cmdline", "/usr/bin/worker\0--fast\0");
+            write("etc/passwd", "root:x:0:0::/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n");
+            write("etc/os-release", "NAME=\"Test Linux\"\nVERSION_ID=\"1.0\"\n");
+            write("sys/class/net/eth0/address", "aa:bb:cc:dd:ee:ff\n");
+            write("sys/class/net/eth0/operstate", "up\n");
+
+            let provider = ProcSystemInfo::with_root(&root);
+            let info = provider.collect(true).unwrap();
+            std::fs::remove_dir_all(&root).ok();
+
+            assert_eq!(info.hostname, "test-host");
+            assert_eq!(info.os.name, "Test Linux");
+            assert_eq!(info.uptime_secs, 3600);
+            assert_eq!(info.memory.total_bytes, 2048 * 1024);
+            assert_eq!(info.cpu.load_average, [0.5, 0.25, 0.1]);
+            assert_eq!(info.network_interfaces[0].name, "eth0");
+            assert!(info.network_interfaces[0].is_up);
+            assert_eq!(info.processes.len(), 1);
+            assert_eq!(info.processes[0].user.as_deref(), Some("alice"));
+            assert_eq!(info.processes[0].command_line, "/usr/bin/worker --fast");
+
+            let json = export_json(&info).unwrap();
+            assert!(json.contains("\"hostname\": \"test-host\""));
+        }
+
+        #[test]
+        fn test_kill_requires_permission_and_valid_pid() {
+            let service = SystemInfoService::new(
+                Box::new(ProcSystemInfo::new()),
+                PermissionProfile::new(PermissionProfileType::FullAccess),
+            );
+            assert!(service.kill_process(&ProcessKillRequest { pid: 1, force: true }).is_err());
+
+            // Out of pid_t range: these would become kill(-1) and kill(-pgid)
+            for pid in [u32::MAX, 0x8000_0000] {
+                assert!(matches!(
+                    service.kill_process(&ProcessKillRequest { pid, force: true }),
+                    Err(ClientError::InvalidInput(_))
+                ));
+            }
+        }
+    }
+}
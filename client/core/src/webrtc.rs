@@ -4,8 +4,11 @@ use std::sync::Arc;
 use tokio::sync::{RwLock, mpsc};
 use webrtc::api::APIBuilder;
 use webrtc::api::media_engine::MediaEngine;
+use webrtc::ice_transport::ice_credential_type::RTCIceCredentialType;
 use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
 use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
@@ -14,6 +17,9 @@ use webrtc::track::track_local::TrackLocal;
 use webrtc::data_channel::RTCDataChannel;
 use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
 use bytes::Bytes;
+use genxlink_protocol::IceConfiguration;
+
+pub use genxlink_protocol::{IceServer, IceTransportPolicy};
 
 /// WebRTC connection manager
 pub struct WebRTCManager {
@@ -21,7 +27,11 @@ pub struct WebRTCManager {
     state: Arc<RwLock<ConnectionState>>,
     config: WebRTCConfig,
     peer_connection: Option<Arc<RTCPeerConnection>>,
+    /// Connection being replaced, closed once the peer answers its successor
+    retiring: Option<Arc<RTCPeerConnection>>,
     data_channels: Arc<RwLock<HashMap<String, Arc<RTCDataChannel>>>>,
+    /// Receiver of local candidates, see `on_ice_candidate`
+    candidate_tx: Option<mpsc::UnboundedSender<RTCIceCandidate>>,
 }
 
 /// Connection state
@@ -44,21 +54,6 @@ pub struct WebRTCConfig {
     pub ice_transport_policy: IceTransportPolicy,
 }
 
-/// ICE server configuration
-#[derive(Debug, Clone)]
-pub struct IceServer {
-    pub urls: Vec<String>,
-    pub username: Option<String>,
-    pub credential: Option<String>,
-}
-
-/// ICE transport policy
-#[derive(Debug, Clone)]
-pub enum IceTransportPolicy {
-    All,
-    Relay,  // Force TURN
-}
-
 impl Default for WebRTCConfig {
     fn default() -> Self {
         Self {
@@ -77,6 +72,41 @@ impl Default for WebRTCConfig {
     }
 }
 
+impl WebRTCConfig {
+    /// Configuration for a new `webrtc` peer connection
+    pub fn rtc_configuration(&self) -> RTCConfiguration {
+        let ice_servers = self.ice_servers
+            .iter()
+            .map(|server| RTCIceServer {
+                urls: server.urls.clone(),
+                username: server.username.clone().unwrap_or_default(),
+                credential: server.credential.clone().unwrap_or_default(),
+                // TURN servers are refused without a credential type
+                credential_type: RTCIceCredentialType::Password,
+            })
+            .collect();
+        
+        RTCConfiguration {
+            ice_servers,
+            ice_transport_policy: match self.ice_transport_policy {
+                IceTransportPolicy::All => RTCIceTransportPolicy::All,
+                IceTransportPolicy::Relay => RTCIceTransportPolicy::Relay,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<IceConfiguration> for WebRTCConfig {
+    /// Servers and policy issued by the API for a session
+    fn from(config: IceConfiguration) -> Self {
+        Self {
+            ice_servers: config.ice_servers,
+            ice_transport_policy: config.ice_transport_policy,
+        }
+    }
+}
+
 impl WebRTCManager {
     /// Create a new WebRTC manager
     pub fn new(device_id: String, config: WebRTCConfig) -> Self {
@@ -85,32 +115,75 @@ impl WebRTCManager {
             state: Arc::new(RwLock::new(ConnectionState::Disconnected)),
             config,
             peer_connection: None,
+            retiring: None,
             data_channels: Arc::new(RwLock::new(HashMap::new())),
+            candidate_tx: None,
         }
     }
     
+    /// Replace the ICE servers, e.g. after the API issued fresh TURN credentials
+    ///
+    /// Before `initialize` this only configures the connection to come. A
+    /// live connection can't change its servers, and an ICE restart keeps
+    /// gathering on the old ones, so it is replaced: a new connection on the
+    /// new servers takes over its tracks and data channels, and the returned
+    /// offer has to be sent to the peer. The old connection keeps running
+    /// until `set_remote_answer` completes the switch.
+    pub async fn set_ice_configuration(&mut self, config: IceConfiguration) -> Result<Option<String>, ClientError> {
+        self.config = config.into();
+        let Some(previous) = self.peer_connection.clone() else {
+            return Ok(None);
+        };
+        
+        let peer_connection = self.new_peer_connection().await?;
+        for sender in previous.get_senders().await {
+            if let Some(track) = sender.track().await {
+                peer_connection.add_track(track)
+                    .await
+                    .map_err(|e| ClientError::WebRTCError(format!("Failed to move track: {}", e)))?;
+            }
+        }
+        let mut channels = self.data_channels.write().await;
+        for (label, channel) in channels.iter_mut() {
+            *channel = peer_connection.create_data_channel(label, None)
+                .await
+                .map_err(|e| ClientError::TransportError(format!("Failed to create data channel: {}", e)))?;
+        }
+        drop(channels);
+        
+        let offer = peer_connection.create_offer(None)
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to create offer: {}", e)))?;
+        peer_connection.set_local_description(offer.clone())
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to set local description: {}", e)))?;
+        
+        if let Some(abandoned) = self.retiring.replace(previous) {
+            retire(&abandoned).await;
+        }
+        self.peer_connection = Some(peer_connection);
+        tracing::info!("Replacing peer connection for new ICE servers");
+        Ok(Some(offer.sdp))
+    }
+    
     /// Initialize peer connection
     pub async fn initialize(&mut self) -> Result<(), ClientError> {
         self.set_state(ConnectionState::Connecting).await;
         
+        let peer_connection = self.new_peer_connection().await?;
+        
+        self.peer_connection = Some(peer_connection);
+        self.set_state(ConnectionState::SignalingConnected).await;
+        
+        Ok(())
+    }
+    
+    /// Create a peer connection on the configured ICE servers
+    async fn new_peer_connection(&self) -> Result<Arc<RTCPeerConnection>, ClientError> {
         // Create API with default settings
         let api = APIBuilder::new().build();
         
-        // Convert config to WebRTC format
-        let ice_servers: Vec<RTCIceServer> = self.config.ice_servers
-            .iter()
-            .map(|server| RTCIceServer {
-                urls: server.urls.clone(),
-                username: server.username.clone().unwrap_or_default(),
-                credential: server.credential.clone().unwrap_or_default(),
-                ..Default::default()
-            })
-            .collect();
-        
-        let rtc_config = RTCConfiguration {
-            ice_servers,
-            ..Default::default()
-        };
+        let rtc_config = self.config.rtc_configuration();
         
         // Create peer connection
         let peer_connection = Arc::new(
@@ -121,11 +194,11 @@ impl WebRTCManager {
         
         // Set up event handlers
         self.setup_event_handlers(&peer_connection).await?;
+        if let Some(tx) = &self.candidate_tx {
+            forward_ice_candidates(&peer_connection, tx.clone());
+        }
         
-        self.peer_connection = Some(peer_connection);
-        self.set_state(ConnectionState::SignalingConnected).await;
-        
-        Ok(())
+        Ok(peer_connection)
     }
     
     /// Setup event handlers for peer connection
@@ -200,6 +273,31 @@ impl WebRTCManager {
         Ok(offer.sdp)
     }
 
+    /// Create an ICE-restart offer after a network change (initiator side)
+    ///
+    /// The offer carries fresh ICE credentials, so both ends gather new
+    /// candidates while the media session and its tracks are kept.
+    pub async fn restart_ice(&self) -> Result<String, ClientError> {
+        let pc = self.peer_connection.as_ref()
+            .ok_or_else(|| ClientError::TransportError("Peer connection not initialized".to_string()))?;
+        
+        self.set_state(ConnectionState::Reconnecting).await;
+        
+        let offer = pc.create_offer(Some(RTCOfferOptions {
+                ice_restart: true,
+                ..Default::default()
+            }))
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to create ICE restart offer: {}", e)))?;
+        
+        pc.set_local_description(offer.clone())
+            .await
+            .map_err(|e| ClientError::TransportError(format!("Failed to set local description: {}", e)))?;
+        
+        tracing::info!("Restarting ICE");
+        Ok(offer.sdp)
+    }
+
     /// Set remote answer (initiator side)
     pub async fn set_remote_answer(&mut self, sdp: String) -> Result<(), ClientError> {
         let pc = self.peer_connection.as_ref()
@@ -212,6 +310,11 @@ impl WebRTCManager {
             .await
             .map_err(|e| ClientError::TransportError(format!("Failed to set remote description: {}", e)))?;
         
+        // The replacement is established; the connection it replaced can go
+        if let Some(previous) = self.retiring.take() {
+            retire(&previous).await;
+        }
+        
         Ok(())
     }
 
@@ -288,26 +391,24 @@ impl WebRTCManager {
     }
     
     /// Get ICE candidates channel
-    pub async fn on_ice_candidate(&self) -> Result<mpsc::UnboundedReceiver<RTCIceCandidate>, ClientError> {
+    ///
+    /// Also receives the candidates of connections that replace this one.
+    pub async fn on_ice_candidate(&mut self) -> Result<mpsc::UnboundedReceiver<RTCIceCandidate>, ClientError> {
         let pc = self.peer_connection.as_ref()
             .ok_or_else(|| ClientError::TransportError("Peer connection not initialized".to_string()))?;
         
         let (tx, rx) = mpsc::unbounded_channel();
-        
-        pc.on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
-            let tx = tx.clone();
-            Box::pin(async move {
-                if let Some(c) = candidate {
-                    let _ = tx.send(c);
-                }
-            })
-        }));
+        forward_ice_candidates(pc, tx.clone());
+        self.candidate_tx = Some(tx);
         
         Ok(rx)
     }
 
     /// Close the connection
     pub async fn close(&mut self) -> Result<(), ClientError> {
+        if let Some(previous) = self.retiring.take() {
+            retire(&previous).await;
+        }
         if let Some(pc) = &self.peer_connection {
             pc.close()
                 .await
@@ -321,6 +422,27 @@ impl WebRTCManager {
     }
 }
 
+/// Send the local candidates `pc` gathers to `tx`
+fn forward_ice_candidates(pc: &RTCPeerConnection, tx: mpsc::UnboundedSender<RTCIceCandidate>) {
+    pc.on_ice_candidate(Box::new(move |candidate: Option<RTCIceCandidate>| {
+        let tx = tx.clone();
+        Box::pin(async move {
+            if let Some(c) = candidate {
+                let _ = tx.send(c);
+            }
+        })
+    }));
+}
+
+/// Close a connection that has been replaced
+async fn retire(pc: &RTCPeerConnection) {
+    // Its state no longer says anything about the manager's connection
+    pc.on_peer_connection_state_change(Box::new(|_| Box::pin(async {})));
+    if let Err(e) = pc.close().await {
+        tracing::debug!("Failed to close replaced peer connection: {}", e);
+    }
+}
+
 /// Data channel handler trait
 pub trait DataChannelHandler: Send + Sync {
     /// Handle incoming data
@@ -372,4 +494,19 @@ mod tests {
         let state = manager.get_state().await;
         assert_eq!(state, ConnectionState::Connecting);
     }
+
+    #[test]
+    fn test_config_from_issued_servers() {
+        let config = WebRTCConfig::from(IceConfiguration {
+            ice_servers: vec![IceServer {
+                urls: vec!["turn:turn.example.com:3478".to_string()],
+                username: Some("1700000000:session".to_string()),
+                credential: Some("secret".to_string()),
+            }],
+            ice_transport_policy: IceTransportPolicy::Relay,
+            expires_at: chrono::Utc::now(),
+        });
+        assert_eq!(config.ice_transport_policy, IceTransportPolicy::Relay);
+        assert_eq!(config.ice_servers[0].username.as_deref(), Some("1700000000:session"));
+    }
 }
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, RwLock, mpsc};
 use tracing::{info, error, warn, debug};
 
@@ -7,11 +8,19 @@ use crate::webrtc_session::{WebRTCSession, SessionState};
 use crate::streaming::{VideoStreamer, Frame};
 use crate::encoder::{H264Encoder, EncoderConfig, VideoCodec};
 use crate::capture::{ScreenCapture, DxgiCapture};
-use crate::signaling_client::{SignalingClient, SignalingState};
+use crate::signaling_client::{SignalingClient, SignalingState, ReconnectPolicy};
 use crate::auth_service::{AuthService, AuthSession};
 use crate::p2p_integration::{P2PIntegration, P2PIntegrationEvent, P2PConfiguration};
 use crate::config::ServerConfig;
+use crate::ice_provisioning::IceProvider;
 use genxlink_protocol::{SignalingMessage, DeviceId};
+use uuid::Uuid;
+
+/// In-place recovery attempts before starting a fresh session
+const RECOVERY_ATTEMPTS: u32 = 5;
+
+/// Time an ICE restart gets to bring the peer connection back
+const ICE_RESTART_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Complete WebRTC integration manager
 /// Handles the full flow: Authentication → Signaling → WebRTC → Streaming
@@ -26,6 +35,18 @@ pub struct WebRTCIntegration {
     connection_mode: ConnectionMode,
     state: Arc<RwLock<IntegrationState>>,
     event_tx: mpsc::UnboundedSender<IntegrationEvent>,
+    /// What is being streamed, kept so the session can be re-established
+    active_stream: Arc<Mutex<Option<ActiveStream>>>,
+    reconnect_policy: ReconnectPolicy,
+}
+
+/// Parameters of the current streaming session
+#[derive(Debug, Clone)]
+struct ActiveStream {
+    remote_device_id: DeviceId,
+    monitor_index: usize,
+    server_config: Option<ServerConfig>,
+    api_session_id: Option<Uuid>,
 }
 
 /// Connection mode for WebRTC integration
@@ -45,6 +66,8 @@ pub enum IntegrationState {
     SignalingConnected,
     WebRTCConnecting,
     Streaming,
+    /// Connection lost; recovering the session
+    Reconnecting,
     Disconnected,
     Failed(String),
 }
@@ -57,6 +80,8 @@ pub enum IntegrationEvent {
     Connected,
     StreamingStarted,
     StreamingStopped,
+    Reconnecting { attempt: u32 },
+    Reconnected,
     Error(String),
     StateChanged(IntegrationState),
 }
@@ -80,6 +105,11 @@ impl WebRTCIntegration {
             connection_mode: ConnectionMode::ServerBased(server_config.api_server_url),
             state: Arc::new(RwLock::new(IntegrationState::Unauthenticated)),
             event_tx,
+            active_stream: Arc::new(Mutex::new(None)),
+            reconnect_policy: ReconnectPolicy {
+                max_attempts: Some(RECOVERY_ATTEMPTS),
+                ..ReconnectPolicy::default()
+            },
         };
         
         (integration, event_rx)
@@ -105,6 +135,11 @@ impl WebRTCIntegration {
             connection_mode: ConnectionMode::P2P(p2p_config),
             state: Arc::new(RwLock::new(IntegrationState::Unauthenticated)),
             event_tx,
+            active_stream: Arc::new(Mutex::new(None)),
+            reconnect_policy: ReconnectPolicy {
+                max_attempts: Some(RECOVERY_ATTEMPTS),
+                ..ReconnectPolicy::default()
+            },
         };
         
         (integration, event_rx)
@@ -138,18 +173,30 @@ impl WebRTCIntegration {
     }
 
     /// Start a complete streaming session
+    ///
+    /// In server-based mode, `api_session_id` is the session the API
+    /// recorded for this connection; the ICE servers it issues for that
+    /// session are used and kept fresh. Public STUN is used without one.
     pub async fn start_streaming(
         &self,
         remote_device_id: DeviceId,
         monitor_index: usize,
         server_config: Option<ServerConfig>,
+        api_session_id: Option<Uuid>,
     ) -> Result<()> {
         self.set_state(IntegrationState::Connecting).await;
         
+        *self.active_stream.lock().await = Some(ActiveStream {
+            remote_device_id: remote_device_id.clone(),
+            monitor_index,
+            server_config: server_config.clone(),
+            api_session_id,
+        });
+        
         match &self.connection_mode {
             ConnectionMode::ServerBased(_) => {
                 let config = server_config.unwrap_or_else(|| ServerConfig::default());
-                self.start_server_based_streaming(remote_device_id, monitor_index, config).await
+                self.start_server_based_streaming(remote_device_id, monitor_index, config, api_session_id).await
             }
             ConnectionMode::P2P(_) => {
                 self.start_p2p_streaming(remote_device_id, monitor_index).await
@@ -163,10 +210,20 @@ impl WebRTCIntegration {
         remote_device_id: DeviceId,
         monitor_index: usize,
         server_config: ServerConfig,
+        api_session_id: Option<Uuid>,
     ) -> Result<()> {
         // Create WebRTC session with server-based signaling
         let session = WebRTCSession::new(self.device_id.clone(), server_config.signaling_server_url);
         
+        // ICE servers issued for the session, TURN credentials included
+        if let Some(api_session_id) = api_session_id {
+            if let Err(e) = self.provision_ice(&session, &server_config.api_server_url, api_session_id).await {
+                error!("Failed to get ICE servers for session {}: {}", api_session_id, e);
+                self.set_state(IntegrationState::Failed(e.to_string())).await;
+                return Err(e);
+            }
+        }
+        
         if let Err(e) = session.start_streaming(monitor_index, remote_device_id.clone()).await {
             error!("Failed to start server-based streaming: {}", e);
             self.set_state(IntegrationState::Failed(e.to_string())).await;
@@ -185,6 +242,17 @@ impl WebRTCIntegration {
         Ok(())
     }
 
+    /// Have `session` use and refresh the ICE servers the API issues for `api_session_id`
+    async fn provision_ice(&self, session: &WebRTCSession, api_url: &str, api_session_id: Uuid) -> Result<()> {
+        let access_token = self.auth_service.lock().await
+            .get_current_session()
+            .map(|auth| auth.access_token.clone())
+            .context("Authentication required")?;
+        let provider = IceProvider::fetch(api_url, &access_token, api_session_id).await?;
+        session.use_ice_provider(Arc::new(provider)).await;
+        Ok(())
+    }
+
     /// Start P2P streaming
     async fn start_p2p_streaming(
         &self,
@@ -216,6 +284,18 @@ impl WebRTCIntegration {
     pub async fn stop_streaming(&self) -> Result<()> {
         info!("Stopping streaming session");
         
+        *self.active_stream.lock().await = None;
+        self.teardown().await;
+        
+        self.set_state(IntegrationState::Disconnected).await;
+        self.send_event(IntegrationEvent::StreamingStopped).await;
+        
+        info!("Streaming session stopped");
+        Ok(())
+    }
+
+    /// Release the streaming components, keeping what is needed to restart
+    async fn teardown(&self) {
         // Stop P2P streaming if in P2P mode
         if let Some(p2p_integration) = &self.p2p_integration {
             let p2p = p2p_integration.lock().await;
@@ -238,14 +318,11 @@ impl WebRTCIntegration {
         
         // Stop WebRTC session
         let mut session_guard = self.session.lock().await;
-        *session_guard = None;
-        drop(session_guard);
-        
-        self.set_state(IntegrationState::Disconnected).await;
-        self.send_event(IntegrationEvent::StreamingStopped).await;
-        
-        info!("Streaming session stopped");
-        Ok(())
+        if let Some(session) = session_guard.take() {
+            if let Err(e) = session.stop_streaming().await {
+                debug!("Failed to close WebRTC session: {}", e);
+            }
+        }
     }
 
     /// Set up video streaming components
@@ -343,30 +420,79 @@ impl WebRTCIntegration {
     }
 
     /// Handle connection lost
+    ///
+    /// Recovers the session in place first (an ICE restart over the
+    /// reconnected signaling socket, or re-dialing the peer in P2P mode),
+    /// with backoff between attempts. If that keeps failing, a fresh session
+    /// is started with the same peer and monitor.
     pub async fn on_connection_lost(&self) -> Result<()> {
-        warn!("Connection lost, attempting cleanup");
+        let Some(active) = self.active_stream.lock().await.clone() else {
+            self.set_state(IntegrationState::Disconnected).await;
+            return Ok(());
+        };
+        
+        warn!("Connection to {} lost, attempting to recover", active.remote_device_id);
+        self.set_state(IntegrationState::Reconnecting).await;
         
-        // Stop streaming if active
-        if self.is_streaming().await {
-            self.stop_streaming().await?;
+        let mut attempt = 0;
+        while self.reconnect_policy.max_attempts.map_or(true, |max| attempt < max) {
+            tokio::time::sleep(self.reconnect_policy.delay(attempt)).await;
+            attempt += 1;
+            self.send_event(IntegrationEvent::Reconnecting { attempt }).await;
+            
+            let recovered = match &self.connection_mode {
+                ConnectionMode::ServerBased(_) => self.recover_webrtc_session().await,
+                ConnectionMode::P2P(_) => self.connect_to_p2p_peer(active.remote_device_id.clone()).await,
+            };
+            
+            match recovered {
+                Ok(()) => {
+                    info!("Session with {} recovered after {} attempt(s)", active.remote_device_id, attempt);
+                    self.set_state(IntegrationState::Streaming).await;
+                    self.send_event(IntegrationEvent::Reconnected).await;
+                    return Ok(());
+                }
+                Err(e) => warn!("Recovery attempt {} failed: {}", attempt, e),
+            }
         }
         
-        self.set_state(IntegrationState::Disconnected).await;
+        // The old session is beyond repair; start over with the same parameters
+        if let Err(e) = self.restart_streaming().await {
+            error!("Failed to re-establish the session: {}", e);
+            self.set_state(IntegrationState::Failed(e.to_string())).await;
+            self.send_event(IntegrationEvent::Error(e.to_string())).await;
+            return Err(e);
+        }
+        self.send_event(IntegrationEvent::Reconnected).await;
         Ok(())
     }
 
+    /// Restart ICE on the current WebRTC session and wait for it to reconnect
+    async fn recover_webrtc_session(&self) -> Result<()> {
+        let session_guard = self.session.lock().await;
+        let session = session_guard.as_ref().context("No active WebRTC session")?;
+        session.restart_ice().await?;
+        
+        let deadline = Instant::now() + ICE_RESTART_TIMEOUT;
+        loop {
+            match session.get_state().await {
+                SessionState::Connected | SessionState::Streaming => return Ok(()),
+                SessionState::Failed(reason) => return Err(anyhow::anyhow!(reason)),
+                _ if Instant::now() >= deadline => return Err(anyhow::anyhow!("ICE restart timed out")),
+                _ => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
     /// Restart streaming with current session
     pub async fn restart_streaming(&self) -> Result<()> {
         info!("Restarting streaming session");
         
-        if self.is_streaming().await {
-            self.stop_streaming().await?;
-        }
+        let active = self.active_stream.lock().await.clone()
+            .context("No streaming session to restart")?;
         
-        // TODO: Implement restart logic with stored remote device ID
-        warn!("Restart streaming not fully implemented - need to store remote device ID");
-        
-        Ok(())
+        self.teardown().await;
+        self.start_streaming(active.remote_device_id, active.monitor_index, active.server_config, active.api_session_id).await
     }
 }
 
@@ -1,29 +1,56 @@
 use anyhow::{Context, Result};
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{mpsc, watch, Mutex, RwLock};
 use webrtc::api::APIBuilder;
-use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_VP8};
-use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::api::interceptor_registry::configure_twcc_sender_only;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::interceptor::registry::Registry;
 use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
-use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 use webrtc::peer_connection::RTCPeerConnection;
-use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecCapability, RTPCodecType};
+use webrtc::rtp_transceiver::RTCPFeedback;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 
+use crate::congestion_control::{SendHistory, SendTimeRecorderBuilder};
+use crate::control_channel::ControlChannel;
+use crate::ice_provisioning::IceProvider;
+use crate::input::create_input_injector;
+use crate::quality_monitor::spawn_quality_monitor;
+use crate::remote_control::RemoteControlHandler;
 use crate::screen_streamer::ScreenStreamer;
 use crate::signaling_client::{SignalingClient, SignalingState};
-use genxlink_protocol::{SignalingMessage, DeviceId};
+use crate::streaming::register_interceptors;
+use crate::webrtc::WebRTCConfig;
+use genxlink_protocol::{IceConfiguration, QualityReport, SignalingMessage, DeviceId};
 
 /// WebRTC streaming session
 /// Manages the complete flow: signaling → peer connection → streaming
+///
+/// Clones are handles to the same session.
+#[derive(Clone)]
 pub struct WebRTCSession {
     device_id: DeviceId,
     signaling: Arc<Mutex<SignalingClient>>,
     peer_connection: Arc<Mutex<Option<Arc<RTCPeerConnection>>>>,
+    /// Replacement offered to the peer; takes over when its answer arrives
+    pending_connection: Arc<Mutex<Option<Arc<RTCPeerConnection>>>>,
     screen_streamer: Arc<Mutex<Option<ScreenStreamer>>>,
     state: Arc<RwLock<SessionState>>,
+    /// Peer of the current session, needed to renegotiate after network changes
+    remote_device_id: Arc<Mutex<Option<DeviceId>>>,
+    /// ICE servers for new peer connections
+    ice_config: Arc<RwLock<WebRTCConfig>>,
+    /// Issues and refreshes the session's ICE servers
+    ice_provider: Arc<Mutex<Option<Arc<IceProvider>>>>,
+    /// Latest measured connection quality while streaming
+    quality: Arc<watch::Sender<Option<QualityReport>>>,
+    /// Injects the viewer's input; the platform injector when not set
+    remote_control: Arc<Mutex<Option<Arc<RemoteControlHandler>>>>,
+    /// Control channel of the current session
+    control_channel: Arc<Mutex<Option<Arc<ControlChannel>>>>,
 }
 
 /// Session state
@@ -37,6 +64,8 @@ pub enum SessionState {
     GatheringCandidates,
     Connected,
     Streaming,
+    /// Network changed; restarting ICE while the session is kept
+    Reconnecting,
     Disconnecting,
     Disconnected,
     Failed(String),
@@ -46,85 +75,142 @@ impl WebRTCSession {
     /// Create a new WebRTC session
     pub fn new(device_id: DeviceId, signaling_server_url: String) -> Self {
         let signaling = SignalingClient::new(device_id.clone(), signaling_server_url);
-        
+        Self::with_signaling(device_id, signaling)
+    }
+
+    /// Create a session signaling through `signaling`, e.g. one registering with a given key
+    pub fn with_signaling(device_id: DeviceId, signaling: SignalingClient) -> Self {
         Self {
             device_id,
             signaling: Arc::new(Mutex::new(signaling)),
             peer_connection: Arc::new(Mutex::new(None)),
+            pending_connection: Arc::new(Mutex::new(None)),
             screen_streamer: Arc::new(Mutex::new(None)),
             state: Arc::new(RwLock::new(SessionState::Idle)),
+            remote_device_id: Arc::new(Mutex::new(None)),
+            ice_config: Arc::new(RwLock::new(WebRTCConfig::default())),
+            ice_provider: Arc::new(Mutex::new(None)),
+            quality: Arc::new(watch::channel(None).0),
+            remote_control: Arc::new(Mutex::new(None)),
+            control_channel: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Inject the viewer's input through `handler`; call it before
+    /// `start_streaming`
+    pub async fn set_remote_control(&self, handler: Arc<RemoteControlHandler>) {
+        *self.remote_control.lock().await = Some(handler);
+    }
+
+    /// Control channel of the current session, once streaming has started
+    pub async fn control_channel(&self) -> Option<Arc<ControlChannel>> {
+        self.control_channel.lock().await.clone()
+    }
+
+    /// Connection quality measured once a second while streaming
+    ///
+    /// The session also sends these to the viewer on the control channel.
+    pub fn subscribe_quality(&self) -> watch::Receiver<Option<QualityReport>> {
+        self.quality.subscribe()
+    }
+
+    /// Use the ICE servers `provider` issues for this session
+    ///
+    /// Call it before `start_streaming`. The provider refreshes the TURN
+    /// credentials before they expire, and every refresh is applied with
+    /// `set_ice_configuration`, until `stop_streaming`.
+    pub async fn use_ice_provider(&self, provider: Arc<IceProvider>) {
+        *self.ice_config.write().await = provider.current().into();
+        let mut updates = provider.subscribe();
+        let refresher = provider.spawn_refresh();
+        *self.ice_provider.lock().await = Some(provider);
+
+        // Ends once `stop_streaming` drops the provider
+        let session = self.clone();
+        tokio::spawn(async move {
+            while updates.changed().await.is_ok() {
+                let config = updates.borrow_and_update().clone();
+                if let Err(e) = session.set_ice_configuration(config).await {
+                    tracing::error!("Failed to apply refreshed ICE servers: {}", e);
+                }
+            }
+            refresher.abort();
+        });
+    }
+
+    /// Use new ICE servers, e.g. after the API issued fresh TURN credentials
+    ///
+    /// A live peer connection can't change its servers, and an ICE restart
+    /// keeps gathering on the old ones, so while streaming the session moves
+    /// onto a new peer connection (see `renew_peer_connection`).
+    pub async fn set_ice_configuration(&self, config: IceConfiguration) -> Result<()> {
+        *self.ice_config.write().await = config.into();
+        if self.peer_connection.lock().await.is_some() {
+            self.renew_peer_connection().await?;
+        }
+        Ok(())
+    }
+
     /// Start a streaming session
     pub async fn start_streaming(&self, monitor_index: usize, remote_device_id: DeviceId) -> Result<()> {
         self.set_state(SessionState::ConnectingToSignaling).await;
+        *self.remote_device_id.lock().await = Some(remote_device_id.clone());
         
         // Step 1: Connect to signaling server
         tracing::info!("Connecting to signaling server...");
         let mut signaling = self.signaling.lock().await;
         let mut incoming_rx = signaling.connect().await
             .context("Failed to connect to signaling server")?;
+        let mut signaling_states = signaling.watch_state();
         drop(signaling);
         
         self.set_state(SessionState::SignalingConnected).await;
         
-        // Step 2: Create peer connection
-        tracing::info!("Creating peer connection...");
-        let peer_connection = self.create_peer_connection().await?;
-        
-        // Step 3: Create screen streamer and add track
+        // Step 2: Create the screen streamer
         tracing::info!("Setting up screen streaming...");
-        let streamer = ScreenStreamer::new()?;
-        let video_track = streamer.get_track();
+        *self.screen_streamer.lock().await = Some(ScreenStreamer::new()?);
         
-        // Add track to peer connection
-        peer_connection.add_track(Arc::clone(&video_track) as Arc<dyn webrtc::track::track_local::TrackLocal + Send + Sync>).await
-            .context("Failed to add video track")?;
-        
-        // Store streamer
-        let mut streamer_guard = self.screen_streamer.lock().await;
-        *streamer_guard = Some(streamer);
-        drop(streamer_guard);
+        // Step 3: Create peer connection carrying the stream
+        tracing::info!("Creating peer connection...");
+        let peer_connection = self.open_peer_connection().await?;
+        *self.peer_connection.lock().await = Some(Arc::clone(&peer_connection));
         
         // Step 4: Create and send offer
         self.set_state(SessionState::CreatingOffer).await;
         tracing::info!("Creating SDP offer...");
-        
-        let offer = peer_connection.create_offer(None).await
-            .context("Failed to create offer")?;
-        
-        peer_connection.set_local_description(offer.clone()).await
-            .context("Failed to set local description")?;
-        
-        // Send offer via signaling
-        let signaling = self.signaling.lock().await;
-        signaling.send_message(SignalingMessage::Offer {
-            from: self.device_id.clone(),
-            to: remote_device_id.clone(),
-            sdp: offer.sdp,
-        }).await?;
-        drop(signaling);
+        send_offer(&peer_connection, &self.signaling, self.device_id.clone(), remote_device_id.clone(), None).await?;
         
         self.set_state(SessionState::WaitingForAnswer).await;
         tracing::info!("Offer sent, waiting for answer...");
         
         // Step 5: Handle incoming signaling messages
-        let peer_conn_clone = Arc::clone(&peer_connection);
+        let current = Arc::clone(&self.peer_connection);
+        let pending = Arc::clone(&self.pending_connection);
         let state_clone = Arc::clone(&self.state);
         let streamer_clone = Arc::clone(&self.screen_streamer);
         
         tokio::spawn(async move {
+            let mut streaming_started = false;
             while let Some(msg) = incoming_rx.recv().await {
-                match msg {
+                match msg.message {
                     SignalingMessage::Answer { sdp, .. } => {
                         tracing::info!("Received answer");
                         
                         let answer = RTCSessionDescription::answer(sdp)
                             .expect("Failed to create answer description");
                         
-                        if let Err(e) = peer_conn_clone.set_remote_description(answer).await {
+                        // An answer to a renewal is for the replacement connection
+                        let renewed = pending.lock().await.take();
+                        let peer_connection = match &renewed {
+                            Some(renewed) => Some(Arc::clone(renewed)),
+                            None => current.lock().await.clone(),
+                        };
+                        let Some(peer_connection) = peer_connection else { continue };
+                        if let Err(e) = peer_connection.set_remote_description(answer).await {
                             tracing::error!("Failed to set remote description: {}", e);
+                            if renewed.is_some() {
+                                retire(&peer_connection).await;
+                            }
                             continue;
                         }
                         
@@ -133,6 +219,21 @@ impl WebRTCSession {
                         *state = SessionState::Streaming;
                         drop(state);
                         
+                        if let Some(renewed) = renewed {
+                            if let Some(previous) = current.lock().await.replace(renewed) {
+                                retire(&previous).await;
+                            }
+                            tracing::info!("Session moved onto the renewed peer connection");
+                            continue;
+                        }
+                        
+                        // Answers to ICE restarts keep the running capture
+                        if streaming_started {
+                            tracing::info!("ICE restart negotiated");
+                            continue;
+                        }
+                        streaming_started = true;
+                        
                         // Start screen capture
                         if let Some(streamer) = streamer_clone.lock().await.as_ref() {
                             if let Err(e) = streamer.start_streaming(monitor_index).await {
@@ -153,8 +254,14 @@ impl WebRTCSession {
                             username_fragment: None,
                         };
                         
-                        if let Err(e) = peer_conn_clone.add_ice_candidate(ice_init).await {
-                            tracing::error!("Failed to add ICE candidate: {}", e);
+                        let peer_connection = match pending.lock().await.clone() {
+                            Some(renewed) => Some(renewed),
+                            None => current.lock().await.clone(),
+                        };
+                        if let Some(peer_connection) = peer_connection {
+                            if let Err(e) = peer_connection.add_ice_candidate(ice_init).await {
+                                tracing::error!("Failed to add ICE candidate: {}", e);
+                            }
                         }
                     }
                     _ => {}
@@ -162,75 +269,159 @@ impl WebRTCSession {
             }
         });
         
-        // Store peer connection
-        let mut pc_guard = self.peer_connection.lock().await;
-        *pc_guard = Some(peer_connection);
+        // Step 6: Restart ICE whenever the signaling socket comes back after a drop
+        let current = Arc::clone(&self.peer_connection);
+        let signaling = Arc::clone(&self.signaling);
+        let state_clone = Arc::clone(&self.state);
+        let device_id = self.device_id.clone();
+        
+        tokio::spawn(async move {
+            let mut was_reconnecting = false;
+            while signaling_states.changed().await.is_ok() {
+                let signaling_state = signaling_states.borrow_and_update().clone();
+                match signaling_state {
+                    SignalingState::Reconnecting => {
+                        was_reconnecting = true;
+                        *state_clone.write().await = SessionState::Reconnecting;
+                    }
+                    SignalingState::Connected if was_reconnecting => {
+                        was_reconnecting = false;
+                        let Some(peer_connection) = current.lock().await.clone() else { break };
+                        if let Err(e) = send_ice_restart(&peer_connection, &signaling, device_id.clone(), remote_device_id.clone()).await {
+                            tracing::error!("ICE restart failed: {}", e);
+                        }
+                    }
+                    SignalingState::Disconnected | SignalingState::Failed(_) => break,
+                    _ => {}
+                }
+            }
+        });
         
         Ok(())
     }
 
+    /// Offer the peer a new peer connection on the current ICE servers
+    ///
+    /// It carries the same screen stream and a new control channel. The old
+    /// connection keeps streaming until the peer answers, then it is closed.
+    async fn renew_peer_connection(&self) -> Result<()> {
+        let remote_device_id = self.remote_device_id.lock().await.clone()
+            .context("No remote device to renegotiate with")?;
+        
+        tracing::info!("Moving the session with {} onto new ICE servers", remote_device_id);
+        let peer_connection = self.open_peer_connection().await?;
+        if let Some(abandoned) = self.pending_connection.lock().await.replace(Arc::clone(&peer_connection)) {
+            retire(&abandoned).await;
+        }
+        send_offer(&peer_connection, &self.signaling, self.device_id.clone(), remote_device_id, None).await
+    }
+
+    /// Create a peer connection carrying the screen stream and a control channel
+    async fn open_peer_connection(&self) -> Result<Arc<RTCPeerConnection>> {
+        let (peer_connection, send_history) = self.create_peer_connection().await?;
+        
+        let streamer_guard = self.screen_streamer.lock().await;
+        let streamer = streamer_guard.as_ref().context("Screen streamer not set up")?;
+        let rtp_sender = streamer.add_to_peer_connection(&peer_connection).await
+            .context("Failed to add video tracks")?;
+        
+        // Adapt bitrate, frame rate and resolution to the measured connection;
+        // the streamer recovers from the loss the viewer reports
+        let (loss_tx, mut loss_rx) = mpsc::unbounded_channel();
+        spawn_quality_monitor(
+            Arc::clone(&peer_connection),
+            rtp_sender,
+            send_history,
+            streamer.frames_sent(),
+            streamer.settings_handle(),
+            Arc::clone(&self.quality),
+            Some(loss_tx),
+        );
+        drop(streamer_guard);
+        let streamer_clone = Arc::clone(&self.screen_streamer);
+        tokio::spawn(async move {
+            while let Some(packets) = loss_rx.recv().await {
+                if let Some(streamer) = streamer_clone.lock().await.as_ref() {
+                    if let Err(e) = streamer.handle_rtcp(&packets).await {
+                        tracing::warn!("Failed to handle viewer feedback: {}", e);
+                    }
+                }
+            }
+        });
+        
+        // Control channel for the viewer's input, which also carries the
+        // quality reports back to it
+        let data_channel = peer_connection.create_data_channel("control", None).await
+            .context("Failed to create control channel")?;
+        let handler = match self.remote_control.lock().await.clone() {
+            Some(handler) => Some(handler),
+            None => match create_input_injector() {
+                Ok(injector) => Some(Arc::new(RemoteControlHandler::new(injector))),
+                Err(e) => {
+                    tracing::warn!("Remote input not available: {}", e);
+                    None
+                }
+            },
+        };
+        let control_channel = Arc::new(match handler {
+            Some(handler) => ControlChannel::new(data_channel, handler),
+            None => ControlChannel::without_input(data_channel),
+        });
+        control_channel.start().await?;
+        control_channel.forward_quality_reports(self.quality.subscribe());
+        *self.control_channel.lock().await = Some(control_channel);
+        
+        Ok(peer_connection)
+    }
+
+    /// Renegotiate the connection with fresh ICE credentials after a network change
+    pub async fn restart_ice(&self) -> Result<()> {
+        let peer_connection = self.peer_connection.lock().await.clone()
+            .context("No peer connection to restart")?;
+        let remote_device_id = self.remote_device_id.lock().await.clone()
+            .context("No remote device to renegotiate with")?;
+        
+        self.set_state(SessionState::Reconnecting).await;
+        send_ice_restart(&peer_connection, &self.signaling, self.device_id.clone(), remote_device_id).await
+    }
+
+    /// Remote device of the current session
+    pub async fn remote_device_id(&self) -> Option<DeviceId> {
+        self.remote_device_id.lock().await.clone()
+    }
+
     /// Create a peer connection with proper configuration
-    async fn create_peer_connection(&self) -> Result<Arc<RTCPeerConnection>> {
-        // Create media engine with VP8 support
+    ///
+    /// Also returns the send times of its transport-cc stamped packets.
+    async fn create_peer_connection(&self) -> Result<(Arc<RTCPeerConnection>, Arc<parking_lot::Mutex<SendHistory>>)> {
+        // Create media engine with H.264 and ULPFEC support
         let mut media_engine = MediaEngine::default();
         media_engine.register_default_codecs()
             .context("Failed to register codecs")?;
         
+        // RTCP receiver reports and NACKs for the quality monitor and the
+        // streamer, and ask the viewer for REMB bandwidth estimates
+        let mut registry = register_interceptors(Registry::new());
+        
+        // Stamp transport-wide sequence numbers for delay-based bandwidth
+        // estimation; the recorder goes first so it sees the stamped packets
+        let send_history = Arc::new(parking_lot::Mutex::new(SendHistory::new()));
+        registry.add(Box::new(SendTimeRecorderBuilder::new(Arc::clone(&send_history))));
+        let registry = configure_twcc_sender_only(registry, &mut media_engine)
+            .context("Failed to register transport-cc")?;
+        media_engine.register_feedback(RTCPFeedback {
+            typ: "goog-remb".to_owned(),
+            parameter: "".to_owned(),
+        }, RTPCodecType::Video);
+        
         // Create API
         let api = APIBuilder::new()
             .with_media_engine(media_engine)
+            .with_interceptor_registry(registry)
             .build();
         
-        // Configure ICE servers with multiple public STUN/TURN servers
-        let config = RTCConfiguration {
-            ice_servers: vec![
-                // Google STUN servers
-                RTCIceServer {
-                    urls: vec![
-                        "stun:stun.l.google.com:19302".to_owned(),
-                        "stun:stun1.l.google.com:19302".to_owned(),
-                        "stun:stun2.l.google.com:19302".to_owned(),
-                        "stun:stun3.l.google.com:19302".to_owned(),
-                        "stun:stun4.l.google.com:19302".to_owned(),
-                    ],
-                    ..Default::default()
-                },
-                // Mozilla STUN servers
-                RTCIceServer {
-                    urls: vec!["stun:stun.services.mozilla.com:3478".to_owned()],
-                    ..Default::default()
-                },
-                // Twilio STUN servers
-                RTCIceServer {
-                    urls: vec![
-                        "stun:global.stun.twilio.com:3478".to_owned(),
-                        "stun:global.stun.twilio.com:3478?transport=udp".to_owned(),
-                    ],
-                    ..Default::default()
-                },
-                // Public TURN servers (for restricted networks)
-                RTCIceServer {
-                    urls: vec![
-                        "turn:openrelay.metered.ca:80".to_owned(),
-                        "turn:openrelay.metered.ca:443".to_owned(),
-                    ],
-                    username: "openrelayproject".to_owned(),
-                    credential: "openrelayproject".to_owned(),
-                    ..Default::default()
-                },
-                // Additional TURN servers
-                RTCIceServer {
-                    urls: vec![
-                        "turn:turn.p2pshare.com:3478".to_owned(),
-                        "turn:turn.p2pshare.com:443".to_owned(),
-                    ],
-                    username: "p2pshare".to_owned(),
-                    credential: "p2pshare".to_owned(),
-                    ..Default::default()
-                },
-            ],
-            ..Default::default()
-        };
+        // Servers issued for the session, or public STUN until then
+        let config = self.ice_config.read().await.rtc_configuration();
         
         // Create peer connection
         let peer_connection = Arc::new(
@@ -240,8 +431,16 @@ impl WebRTCSession {
         
         // Set up connection state handler
         let state_clone = Arc::clone(&self.state);
+        let weak_peer_connection = Arc::downgrade(&peer_connection);
+        let signaling = Arc::clone(&self.signaling);
+        let remote_device_id = Arc::clone(&self.remote_device_id);
+        let device_id = self.device_id.clone();
         peer_connection.on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
             let state = state_clone.clone();
+            let weak_peer_connection = weak_peer_connection.clone();
+            let signaling = signaling.clone();
+            let remote_device_id = remote_device_id.clone();
+            let device_id = device_id.clone();
             Box::pin(async move {
                 tracing::info!("Peer connection state changed: {:?}", s);
                 
@@ -251,10 +450,26 @@ impl WebRTCSession {
                         *state_guard = SessionState::Connected;
                     }
                     RTCPeerConnectionState::Disconnected => {
-                        *state_guard = SessionState::Disconnected;
+                        // Usually transient; ICE recovers or moves on to Failed
+                        *state_guard = SessionState::Reconnecting;
                     }
                     RTCPeerConnectionState::Failed => {
-                        *state_guard = SessionState::Failed("Connection failed".to_string());
+                        // A failed ICE transport can still be restarted while signaling is up
+                        let remote = remote_device_id.lock().await.clone();
+                        let signaling_up = signaling.lock().await.get_state().await == SignalingState::Connected;
+                        match (weak_peer_connection.upgrade(), remote) {
+                            (Some(pc), Some(remote)) if signaling_up => {
+                                *state_guard = SessionState::Reconnecting;
+                                drop(state_guard);
+                                if let Err(e) = send_ice_restart(&pc, &signaling, device_id, remote).await {
+                                    tracing::error!("ICE restart failed: {}", e);
+                                    *state.write().await = SessionState::Failed("Connection failed".to_string());
+                                }
+                            }
+                            _ => {
+                                *state_guard = SessionState::Failed("Connection failed".to_string());
+                            }
+                        }
                     }
                     _ => {}
                 }
@@ -288,7 +503,7 @@ impl WebRTCSession {
             })
         }));
         
-        Ok(peer_connection)
+        Ok((peer_connection, send_history))
     }
 
     /// Stop streaming
@@ -300,7 +515,13 @@ impl WebRTCSession {
             streamer.stop_streaming().await?;
         }
         
+        // Stop refreshing ICE servers
+        self.ice_provider.lock().await.take();
+        
         // Close peer connection
+        if let Some(pc) = self.pending_connection.lock().await.take() {
+            pc.close().await?;
+        }
         if let Some(pc) = self.peer_connection.lock().await.as_ref() {
             pc.close().await?;
         }
@@ -321,3 +542,49 @@ impl WebRTCSession {
         *s = state;
     }
 }
+
+/// Send an ICE-restart offer to `to` over signaling
+async fn send_ice_restart(
+    peer_connection: &RTCPeerConnection,
+    signaling: &Mutex<SignalingClient>,
+    from: DeviceId,
+    to: DeviceId,
+) -> Result<()> {
+    tracing::info!("Restarting ICE with {}", to);
+    send_offer(peer_connection, signaling, from, to, Some(RTCOfferOptions {
+        ice_restart: true,
+        ..Default::default()
+    })).await
+}
+
+/// Create an offer, apply it locally and send it to `to` over signaling
+async fn send_offer(
+    peer_connection: &RTCPeerConnection,
+    signaling: &Mutex<SignalingClient>,
+    from: DeviceId,
+    to: DeviceId,
+    options: Option<RTCOfferOptions>,
+) -> Result<()> {
+    let offer = peer_connection.create_offer(options).await
+        .context("Failed to create offer")?;
+    
+    peer_connection.set_local_description(offer.clone()).await
+        .context("Failed to set local description")?;
+    
+    signaling.lock().await.send_message(SignalingMessage::Offer {
+        from,
+        to,
+        sdp: offer.sdp,
+    }).await?;
+    
+    Ok(())
+}
+
+/// Close a peer connection the session has moved off
+async fn retire(peer_connection: &RTCPeerConnection) {
+    // Its state no longer says anything about the session
+    peer_connection.on_peer_connection_state_change(Box::new(|_| Box::pin(async {})));
+    if let Err(e) = peer_connection.close().await {
+        tracing::debug!("Failed to close replaced peer connection: {}", e);
+    }
+}
@@ -1,5 +1,10 @@
+use crate::brute_force::{constant_time_eq, AttemptError, AttemptLimiter, LockoutEvent, LockoutPolicy, LOCAL_SOURCE};
+use parking_lot::Mutex;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
 
 /// Zero-setup access method
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -80,22 +85,14 @@ impl TemporarySession {
     
     /// Generate a random access code (9 digits)
     fn generate_access_code() -> String {
-        use std::time::UNIX_EPOCH;
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        format!("{:09}", timestamp % 1_000_000_000)
+        let code: u32 = rand::thread_rng().gen_range(0..1_000_000_000);
+        format!("{:09}", code)
     }
     
     /// Generate a random PIN (6 digits)
     fn generate_pin() -> String {
-        use std::time::UNIX_EPOCH;
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_micros();
-        format!("{:06}", timestamp % 1_000_000)
+        let pin: u32 = rand::thread_rng().gen_range(0..1_000_000);
+        format!("{:06}", pin)
     }
     
     /// Check if session is expired
@@ -130,10 +127,10 @@ impl TemporarySession {
         }
     }
     
-    /// Verify PIN
+    /// Verify PIN (constant time)
     pub fn verify_pin(&self, pin: &str) -> bool {
         if let Some(ref session_pin) = self.pin {
-            session_pin == pin
+            constant_time_eq(session_pin.as_bytes(), pin.as_bytes())
         } else {
             true // No PIN required
         }
@@ -150,6 +147,20 @@ impl TemporarySession {
 /// Zero-setup manager
 pub struct ZeroSetupManager {
     active_sessions: Vec<TemporarySession>,
+    attempts: Mutex<AttemptState>,
+    lockout_policy: LockoutPolicy,
+    lockout_notifier: Option<mpsc::UnboundedSender<LockoutEvent>>,
+}
+
+/// Failed attempt tracking, updated by `verify_access` through `&self`
+struct AttemptState {
+    /// Failed attempts against unknown access codes, per source and from
+    /// all sources together
+    unknown_codes: AttemptLimiter,
+    /// Failed attempts per access code across all sources
+    sessions: HashMap<String, AttemptLimiter>,
+    /// Access codes revoked after exhausting their budget
+    revoked: HashSet<String>,
 }
 
 impl Default for ZeroSetupManager {
@@ -160,20 +171,56 @@ impl Default for ZeroSetupManager {
 
 impl ZeroSetupManager {
     pub fn new() -> Self {
+        Self::with_policy(LockoutPolicy::default())
+    }
+    
+    /// Create a manager with a custom lockout policy
+    ///
+    /// Wrong PINs count against their access code, which is revoked once
+    /// the global budget is spent. Unknown access codes count against a
+    /// budget of their own: when many sources together spend it, every
+    /// code is locked until the lockout ends, so codes can't be
+    /// enumerated, while wrong PINs for one code don't lock out the others.
+    pub fn with_policy(lockout_policy: LockoutPolicy) -> Self {
         Self {
             active_sessions: Vec::new(),
+            attempts: Mutex::new(AttemptState {
+                unknown_codes: AttemptLimiter::new(lockout_policy.clone()),
+                sessions: HashMap::new(),
+                revoked: HashSet::new(),
+            }),
+            lockout_policy,
+            lockout_notifier: None,
+        }
+    }
+    
+    /// Deliver lockout notifications to the host
+    pub fn set_lockout_notifier(&mut self, notifier: mpsc::UnboundedSender<LockoutEvent>) {
+        let attempts = self.attempts.get_mut();
+        attempts.unknown_codes.set_notifier(notifier.clone());
+        for limiter in attempts.sessions.values_mut() {
+            limiter.set_notifier(notifier.clone());
         }
+        self.lockout_notifier = Some(notifier);
     }
     
     /// Create a new temporary session
     pub fn create_session(&mut self, duration_minutes: u32) -> TemporarySession {
         let session = TemporarySession::new(duration_minutes);
+        let mut limiter = AttemptLimiter::new(self.lockout_policy.clone());
+        if let Some(ref notifier) = self.lockout_notifier {
+            limiter.set_notifier(notifier.clone());
+        }
+        self.attempts.get_mut().sessions.insert(session.access_code.clone(), limiter);
         self.active_sessions.push(session.clone());
         session
     }
     
     /// Get session by access code
     pub fn get_session(&self, access_code: &str) -> Option<&TemporarySession> {
+        if self.attempts.lock().revoked.contains(access_code) {
+            return None;
+        }
         self.active_sessions
             .iter()
             .find(|s| s.access_code == access_code && !s.is_expired())
@@ -181,21 +228,71 @@ impl ZeroSetupManager {
     
     /// Get session by access code (mutable)
     pub fn get_session_mut(&mut self, access_code: &str) -> Option<&mut TemporarySession> {
+        self.apply_revocations();
         self.active_sessions
             .iter_mut()
             .find(|s| s.access_code == access_code && !s.is_expired())
     }
     
-    /// Verify access code and PIN
+    /// Verify access code and PIN entered locally
     pub fn verify_access(&self, access_code: &str, pin: Option<&str>) -> bool {
-        if let Some(session) = self.get_session(access_code) {
-            if let Some(pin_str) = pin {
-                session.verify_pin(pin_str)
-            } else {
-                session.pin.is_none()
-            }
-        } else {
-            false
+        self.verify_access_from(LOCAL_SOURCE, access_code, pin)
+            .unwrap_or(false)
+    }
+    
+    /// Verify access code and PIN submitted by `source` (peer device or address)
+    ///
+    /// Unknown access codes are tracked per source and from all sources
+    /// together (so codes cannot be enumerated, even from many sources),
+    /// wrong PINs per access code across all sources (so a PIN cannot be
+    /// guessed by many sources). When an access code exhausts its global
+    /// budget the session is revoked.
+    pub fn verify_access_from(
+        &self,
+        source: &str,
+        access_code: &str,
+        pin: Option<&str>,
+    ) -> Result<bool, AttemptError> {
+        let mut attempts = self.attempts.lock();
+        if let Some(limiter) = attempts.sessions.get_mut(access_code) {
+            limiter.check(source)?;
+        }
+        attempts.unknown_codes.check(source)?;
+        
+        let session = self
+            .active_sessions
+            .iter()
+            .find(|s| s.access_code == access_code && !s.is_expired())
+            .filter(|s| s.is_active && !attempts.revoked.contains(access_code));
+        let Some(session) = session else {
+            attempts.unknown_codes.record_failure(source);
+            return Ok(false);
+        };
+        let verified = match pin {
+            Some(pin_str) => session.verify_pin(pin_str),
+            None => session.pin.is_none(),
+        };
+        
+        let Some(limiter) = attempts.sessions.get_mut(access_code) else {
+            return Ok(verified);
+        };
+        if verified {
+            limiter.record_success(source);
+            return Ok(true);
+        }
+        if let Some(LockoutEvent::GlobalLockout { .. }) = limiter.record_failure(source) {
+            tracing::warn!("Revoking zero-setup session {} after repeated failures", access_code);
+            attempts.revoked.insert(access_code.to_string());
+        }
+        
+        Ok(false)
+    }
+    
+    /// Mark sessions revoked by `verify_access` inactive
+    fn apply_revocations(&mut self) {
+        let revoked = &self.attempts.get_mut().revoked;
+        for session in self.active_sessions.iter_mut().filter(|s| revoked.contains(&s.access_code)) {
+            session.is_active = false;
         }
     }
     
@@ -232,13 +329,22 @@ impl ZeroSetupManager {
     /// Clean up expired sessions
     pub fn cleanup_expired(&mut self) {
         self.active_sessions.retain(|s| !s.is_expired());
+        let sessions = &self.active_sessions;
+        let attempts = self.attempts.get_mut();
+        attempts
+            .sessions
+            .retain(|code, _| sessions.iter().any(|s| &s.access_code == code));
+        attempts
+            .revoked
+            .retain(|code| sessions.iter().any(|s| &s.access_code == code));
     }
     
     /// Get all active sessions
     pub fn active_sessions(&self) -> Vec<&TemporarySession> {
+        let revoked = &self.attempts.lock().revoked;
         self.active_sessions
             .iter()
-            .filter(|s| !s.is_expired() && s.is_active)
+            .filter(|s| !s.is_expired() && s.is_active && !revoked.contains(&s.access_code))
             .collect()
     }
 }
@@ -271,4 +377,154 @@ mod tests {
         assert!(manager.verify_access(&session.access_code, session.pin.as_deref()));
         assert!(manager.accept_connection(&session.access_code).is_ok());
     }
+    
+    #[test]
+    fn test_generated_codes_are_random() {
+        let a = TemporarySession::new(30);
+        let b = TemporarySession::new(30);
+        assert_eq!(a.access_code.len(), 9);
+        assert_eq!(a.pin.as_ref().unwrap().len(), 6);
+        assert!(a.access_code != b.access_code || a.pin != b.pin);
+    }
+    
+    #[test]
+    fn test_single_source_cannot_enumerate_pins() {
+        // A backoff that outlasts the loop, however slow the build
+        let mut manager = ZeroSetupManager::with_policy(LockoutPolicy {
+            base_delay: Duration::from_secs(60),
+            ..LockoutPolicy::default()
+        });
+        let session = manager.create_session(30);
+        let real_pin = session.pin.clone().unwrap();
+        
+        let mut evaluated = 0;
+        for guess in 0..1_000_000u32 {
+            let guess = format!("{:06}", guess);
+            if guess == real_pin {
+                continue;
+            }
+            if manager.verify_access_from("attacker", &session.access_code, Some(&guess)).is_ok() {
+                evaluated += 1;
+            }
+        }
+        
+        // Backoff stops the attacker after the first wrong guess
+        assert_eq!(evaluated, 1);
+        assert!(manager
+            .verify_access_from("attacker", &session.access_code, Some(&real_pin))
+            .is_err());
+    }
+    
+    #[test]
+    fn test_distributed_sources_cannot_enumerate_pins() {
+        let mut manager = ZeroSetupManager::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager.set_lockout_notifier(tx);
+        let session = manager.create_session(30);
+        let real_pin = session.pin.clone().unwrap();
+        let budget = LockoutPolicy::default().max_failures_global;
+        
+        let mut evaluated = 0;
+        for guess in 0..10_000u32 {
+            let guess = format!("{:06}", guess);
+            if guess == real_pin {
+                continue;
+            }
+            let source = format!("attacker-{}", guess);
+            if manager.verify_access_from(&source, &session.access_code, Some(&guess)).is_ok() {
+                evaluated += 1;
+            }
+        }
+        
+        // Only the global budget of guesses is ever checked, then the session is revoked
+        assert_eq!(evaluated, budget);
+        assert!(manager.active_sessions().is_empty());
+        assert!(!manager.verify_access_from("late", &session.access_code, Some(&real_pin)).unwrap_or(false));
+        
+        let mut saw_global_lockout = false;
+        while let Ok(event) = rx.try_recv() {
+            saw_global_lockout |= matches!(event, LockoutEvent::GlobalLockout { .. });
+        }
+        assert!(saw_global_lockout);
+    }
+    
+    #[test]
+    fn test_single_source_cannot_enumerate_access_codes() {
+        // A backoff that outlasts the loop, however slow the build
+        let mut manager = ZeroSetupManager::with_policy(LockoutPolicy {
+            base_delay: Duration::from_secs(60),
+            ..LockoutPolicy::default()
+        });
+        let session = manager.create_session(30);
+        
+        let mut evaluated = 0;
+        for code in 0..100_000u32 {
+            let code = format!("{:09}", code);
+            if code == session.access_code {
+                continue;
+            }
+            if manager.verify_access_from("attacker", &code, Some("000000")).is_ok() {
+                evaluated += 1;
+            }
+        }
+        
+        assert_eq!(evaluated, 1);
+        // The real session is untouched
+        assert!(manager.verify_access(&session.access_code, session.pin.as_deref()));
+    }
+
+    #[test]
+    fn test_distributed_sources_cannot_enumerate_access_codes() {
+        let mut manager = ZeroSetupManager::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        manager.set_lockout_notifier(tx);
+        let session = manager.create_session(30);
+        let budget = LockoutPolicy::default().max_failures_global;
+        
+        let mut evaluated = 0;
+        for code in 0..100_000u32 {
+            let code = format!("{:09}", code);
+            if code == session.access_code {
+                continue;
+            }
+            let source = format!("attacker-{}", code);
+            if manager.verify_access_from(&source, &code, Some("000000")).is_ok() {
+                evaluated += 1;
+            }
+        }
+        
+        // Only the global budget of codes is ever looked up, then every code is locked
+        assert_eq!(evaluated, budget);
+        assert!(matches!(
+            manager.verify_access_from("user", &session.access_code, session.pin.as_deref()),
+            Err(AttemptError::GloballyLocked { .. })
+        ));
+        
+        let mut saw_global_lockout = false;
+        while let Ok(event) = rx.try_recv() {
+            saw_global_lockout |= matches!(event, LockoutEvent::GlobalLockout { .. });
+        }
+        assert!(saw_global_lockout);
+    }
+
+    #[test]
+    fn test_failures_elsewhere_do_not_lock_out_valid_codes() {
+        let mut manager = ZeroSetupManager::new();
+        let session = manager.create_session(30);
+        let other = manager.create_session(30);
+        let budget = LockoutPolicy::default().max_failures_global;
+
+        // Exhaust another session's global budget from many sources, with
+        // unknown codes in between that stay within theirs
+        for attempt in 0..(budget - 1) * 2 {
+            let source = format!("attacker-{}", attempt);
+            let code = if attempt % 2 == 0 { format!("{:09}", attempt) } else { other.access_code.clone() };
+            let _ = manager.verify_access_from(&source, &code, Some("000000"));
+        }
+
+        assert_eq!(
+            manager.verify_access_from("user", &session.access_code, session.pin.as_deref()),
+            Ok(true)
+        );
+    }
 }
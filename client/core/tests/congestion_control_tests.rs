@@ -0,0 +1,218 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use genxlink_client_core::adaptive_bitrate::AdaptiveBitrateController;
+use genxlink_client_core::congestion_control::{BandwidthEstimator, GoogCcEstimator, PacketFeedback};
+
+const PACKET_SIZE: usize = 1200;
+const PROPAGATION_MS: u64 = 20;
+const FEEDBACK_INTERVAL_MS: u64 = 100;
+/// Bottleneck buffer, as queuing delay
+const MAX_QUEUE_DELAY_US: u64 = 300_000;
+/// Receiver clock is unrelated to the sender's
+const RECEIVER_CLOCK_OFFSET: Duration = Duration::from_secs(1234);
+
+/// Deterministic pseudo-random numbers in 0..1
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Paced sender, bottleneck link and feedback path in 1 ms steps
+///
+/// The link serializes packets at the capacity the trace gives for the
+/// current time, drops them when its queue would exceed
+/// `MAX_QUEUE_DELAY_US` and loses `loss` of them at random besides.
+struct Simulation {
+    controller: AdaptiveBitrateController,
+    capacity_kbps: Box<dyn Fn(u64) -> u32>,
+    loss: f64,
+    rng: XorShift,
+    now_ms: u64,
+    budget_bits: f64,
+    next_sequence_number: i64,
+    link_free_at_us: u64,
+    /// Sent packets the receiver hasn't reported yet, in sequence order
+    in_flight: VecDeque<PacketFeedback>,
+    /// Feedback on its way back: (delivery time, packets)
+    feedback: VecDeque<(u64, Vec<PacketFeedback>)>,
+    /// (time, target kbps, queue delay ms) every feedback interval
+    trace: Vec<(u64, u32, u64)>,
+}
+
+impl Simulation {
+    fn new(start_kbps: u32, capacity_kbps: impl Fn(u64) -> u32 + 'static, loss: f64) -> Self {
+        let mut controller =
+            AdaptiveBitrateController::new(start_kbps).with_estimator(Box::new(GoogCcEstimator::new(start_kbps, 300, 20_000)));
+        controller.set_adjustment_interval(Duration::ZERO);
+        controller.set_limits(300, 20_000);
+        Self {
+            controller,
+            capacity_kbps: Box::new(capacity_kbps),
+            loss,
+            rng: XorShift(0x9e37_79b9_7f4a_7c15),
+            now_ms: 0,
+            budget_bits: 0.0,
+            next_sequence_number: 0,
+            link_free_at_us: 0,
+            in_flight: VecDeque::new(),
+            feedback: VecDeque::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    fn run_until(&mut self, end_ms: u64) {
+        while self.now_ms < end_ms {
+            self.now_ms += 1;
+            self.send();
+            if self.now_ms.is_multiple_of(FEEDBACK_INTERVAL_MS) {
+                self.report();
+            }
+            while self.feedback.front().is_some_and(|(at, _)| *at <= self.now_ms) {
+                let (_, packets) = self.feedback.pop_front().unwrap();
+                self.controller.on_packet_feedback(&packets);
+                self.controller.adjust_bitrate();
+            }
+        }
+    }
+
+    fn send(&mut self) {
+        let now_us = self.now_ms * 1000;
+        self.budget_bits += self.controller.current_bitrate() as f64;
+        while self.budget_bits >= (PACKET_SIZE * 8) as f64 {
+            self.budget_bits -= (PACKET_SIZE * 8) as f64;
+
+            let capacity_bps = (self.capacity_kbps)(self.now_ms) as u64 * 1000;
+            let start_us = self.link_free_at_us.max(now_us);
+            let departure_us = start_us + PACKET_SIZE as u64 * 8 * 1_000_000 / capacity_bps;
+            let arrival_time = if departure_us - now_us > MAX_QUEUE_DELAY_US || self.rng.next() < self.loss {
+                None
+            } else {
+                self.link_free_at_us = departure_us;
+                Some(Duration::from_micros(departure_us + PROPAGATION_MS * 1000) + RECEIVER_CLOCK_OFFSET)
+            };
+
+            self.in_flight.push_back(PacketFeedback {
+                sequence_number: self.next_sequence_number,
+                size: PACKET_SIZE,
+                send_time: Duration::from_millis(self.now_ms),
+                arrival_time,
+            });
+            self.next_sequence_number += 1;
+        }
+    }
+
+    /// Report everything up to the last packet that has arrived; later
+    /// losses can't be told from packets still in flight
+    fn report(&mut self) {
+        let receiver_now = Duration::from_millis(self.now_ms) + RECEIVER_CLOCK_OFFSET;
+        let Some(last) = self
+            .in_flight
+            .iter()
+            .rposition(|packet| packet.arrival_time.is_some_and(|arrival| arrival <= receiver_now))
+        else {
+            return;
+        };
+        let packets: Vec<_> = self.in_flight.drain(..=last).collect();
+        self.feedback.push_back((self.now_ms + PROPAGATION_MS, packets));
+
+        let queue_delay_ms = self.link_free_at_us.saturating_sub(self.now_ms * 1000) / 1000;
+        self.trace.push((self.now_ms, self.controller.current_bitrate(), queue_delay_ms));
+    }
+
+    /// Targets reported between `from_ms` and `to_ms`
+    fn targets(&self, from_ms: u64, to_ms: u64) -> Vec<u32> {
+        self.trace
+            .iter()
+            .filter(|(at, _, _)| (from_ms..to_ms).contains(at))
+            .map(|(_, target, _)| *target)
+            .collect()
+    }
+
+    fn max_queue_delay_ms(&self, from_ms: u64, to_ms: u64) -> u64 {
+        self.trace
+            .iter()
+            .filter(|(at, _, _)| (from_ms..to_ms).contains(at))
+            .map(|(_, _, delay)| *delay)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn mean(values: &[u32]) -> f64 {
+    values.iter().map(|&v| v as f64).sum::<f64>() / values.len() as f64
+}
+
+#[test]
+fn test_converges_to_capacity() {
+    let mut sim = Simulation::new(1000, |_| 3000, 0.0);
+    sim.run_until(60_000);
+
+    // Ramps up from 1 Mbps and then tracks the 3 Mbps link
+    let settled = sim.targets(30_000, 60_000);
+    let average = mean(&settled);
+    assert!((2100.0..=3300.0).contains(&average), "average {} kbps", average);
+    assert!(settled.iter().all(|&target| (1500..=3600).contains(&target)), "{:?}", settled);
+    assert!(sim.max_queue_delay_ms(30_000, 60_000) < 200);
+}
+
+#[test]
+fn test_follows_capacity_drop() {
+    let mut sim = Simulation::new(3000, |now_ms| if now_ms < 30_000 { 4000 } else { 1500 }, 0.0);
+    sim.run_until(30_000);
+    assert!(mean(&sim.targets(20_000, 30_000)) > 2800.0);
+
+    // Below the new capacity within a few seconds, and the queue drains
+    sim.run_until(60_000);
+    let after_drop = sim.targets(33_000, 60_000);
+    assert!(after_drop.iter().all(|&target| target <= 1800), "{:?}", after_drop);
+    assert!(mean(&sim.targets(45_000, 60_000)) > 1000.0);
+    assert!(sim.max_queue_delay_ms(40_000, 60_000) < 150);
+}
+
+#[test]
+fn test_follows_capacity_rise() {
+    let mut sim = Simulation::new(800, |now_ms| if now_ms < 20_000 { 1000 } else { 3000 }, 0.0);
+    sim.run_until(20_000);
+    assert!(mean(&sim.targets(10_000, 20_000)) < 1100.0);
+
+    sim.run_until(60_000);
+    let average = mean(&sim.targets(45_000, 60_000));
+    assert!(average > 2000.0, "average {} kbps", average);
+}
+
+#[test]
+fn test_steady_on_lossy_wifi() {
+    // 3% random loss is not congestion
+    let mut sim = Simulation::new(1000, |_| 3000, 0.03);
+    sim.run_until(60_000);
+
+    let settled = sim.targets(30_000, 60_000);
+    let (min, max) = (*settled.iter().min().unwrap(), *settled.iter().max().unwrap());
+    assert!(mean(&settled) > 2000.0, "{:?}", settled);
+    assert!(max as f64 / (min as f64) < 2.0, "oscillating between {} and {} kbps", min, max);
+}
+
+#[test]
+fn test_backs_off_on_heavy_loss() {
+    let mut clean = Simulation::new(2000, |_| 5000, 0.0);
+    clean.run_until(30_000);
+    let mut lossy = Simulation::new(2000, |_| 5000, 0.2);
+    lossy.run_until(30_000);
+
+    let clean = mean(&clean.targets(20_000, 30_000));
+    let lossy = mean(&lossy.targets(20_000, 30_000));
+    assert!(lossy < clean / 2.0, "{} kbps with 20% loss, {} kbps without", lossy, clean);
+}
+
+#[test]
+fn test_estimator_without_feedback_keeps_start_rate() {
+    let estimator = GoogCcEstimator::new(2000, 300, 20_000);
+    assert_eq!(estimator.estimate_kbps(), 2000);
+}
@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use genxlink_client_core::device_identity::DeviceIdentity;
+use genxlink_client_core::ice_provisioning::IceProvider;
+use genxlink_client_core::signaling_client::SignalingClient;
+use genxlink_client_core::webrtc::{WebRTCConfig, WebRTCManager};
+use genxlink_client_core::webrtc_session::WebRTCSession;
+use genxlink_client_core::ClientError;
+use genxlink_protocol::{DeviceId, IceConfiguration, IceServer, IceTransportPolicy, SignalingEnvelope, SignalingMessage};
+use genxlink_signaling_server::PeerManager;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+const TOKEN: &str = "access-token";
+
+/// API stand-in answering every request with `status` and a configuration
+/// whose credentials last `ttl`; counts requests and checks the path and token
+struct ApiStandIn {
+    url: String,
+    requests: Arc<AtomicUsize>,
+}
+
+impl ApiStandIn {
+    async fn start(session_id: Uuid, status: u16, ttl: chrono::Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let head = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+                let issued = counter.fetch_add(1, Ordering::SeqCst) + 1;
+
+                let expected = format!("get /api/sessions/{}/ice-servers ", session_id);
+                let (status, body) = if !head.starts_with(&expected) {
+                    (404, String::new())
+                } else if !head.contains(&format!("authorization: bearer {}", TOKEN)) {
+                    (401, String::new())
+                } else {
+                    (status, serde_json::to_string(&configuration(issued, ttl)).unwrap())
+                };
+                let response = format!(
+                    "HTTP/1.1 {} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        Self { url, requests }
+    }
+}
+
+/// The `issued`-th configuration, told apart by its TURN username
+fn configuration(issued: usize, ttl: chrono::Duration) -> IceConfiguration {
+    IceConfiguration {
+        ice_servers: vec![IceServer {
+            urls: vec!["turn:turn.example.com:3478".to_string()],
+            username: Some(format!("user-{}", issued)),
+            credential: Some("secret".to_string()),
+        }],
+        ice_transport_policy: IceTransportPolicy::Relay,
+        expires_at: Utc::now() + ttl,
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_issued_servers() {
+    let session_id = Uuid::new_v4();
+    let api = ApiStandIn::start(session_id, 200, chrono::Duration::hours(1)).await;
+
+    let provider = IceProvider::fetch(&api.url, TOKEN, session_id).await.unwrap();
+    let config = provider.current();
+    assert_eq!(config.ice_transport_policy, IceTransportPolicy::Relay);
+    assert_eq!(config.ice_servers[0].username.as_deref(), Some("user-1"));
+
+    let refreshed = provider.refresh().await.unwrap();
+    assert_eq!(refreshed.ice_servers[0].username.as_deref(), Some("user-2"));
+    assert_eq!(provider.current().ice_servers[0].username.as_deref(), Some("user-2"));
+
+    assert!(matches!(
+        IceProvider::fetch(&api.url, "wrong-token", session_id).await,
+        Err(ClientError::AuthenticationError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_relay_without_turn_is_an_error() {
+    let session_id = Uuid::new_v4();
+    let api = ApiStandIn::start(session_id, 503, chrono::Duration::hours(1)).await;
+
+    let Err(ClientError::TransportError(message)) = IceProvider::fetch(&api.url, TOKEN, session_id).await else {
+        panic!("expected a transport error");
+    };
+    assert!(message.contains("TURN"));
+}
+
+#[tokio::test]
+async fn test_refreshes_before_expiry() {
+    let session_id = Uuid::new_v4();
+    // Refreshed halfway through, about every 1.5 seconds
+    let api = ApiStandIn::start(session_id, 200, chrono::Duration::seconds(3)).await;
+
+    let provider = Arc::new(IceProvider::fetch(&api.url, TOKEN, session_id).await.unwrap());
+    let mut updates = provider.subscribe();
+    let refresher = provider.spawn_refresh();
+
+    tokio::time::timeout(Duration::from_secs(5), updates.changed()).await.unwrap().unwrap();
+    let config = updates.borrow_and_update().clone();
+    assert_eq!(config.ice_servers[0].username.as_deref(), Some("user-2"));
+    assert!(config.expires_at > Utc::now());
+
+    // Dropping the provider stops the refresh task
+    drop(provider);
+    tokio::time::timeout(Duration::from_secs(5), refresher).await.unwrap().unwrap();
+    assert_eq!(api.requests.load(Ordering::SeqCst), 2);
+}
+
+fn signaling_client(server: std::net::SocketAddr, device_id: &DeviceId) -> SignalingClient {
+    SignalingClient::new(device_id.clone(), format!("ws://{}/ws", server))
+        .with_identity(Arc::new(DeviceIdentity::generate().unwrap()))
+}
+
+/// Next offer the viewer is sent
+async fn next_offer(incoming: &mut tokio::sync::mpsc::UnboundedReceiver<SignalingEnvelope>) -> (SignalingEnvelope, String) {
+    loop {
+        let envelope = tokio::time::timeout(Duration::from_secs(5), incoming.recv()).await.unwrap().unwrap();
+        if let SignalingMessage::Offer { sdp, .. } = &envelope.message {
+            let sdp = sdp.clone();
+            return (envelope, sdp);
+        }
+    }
+}
+
+fn fingerprint(sdp: &str) -> Option<&str> {
+    sdp.lines().find_map(|line| line.strip_prefix("a=fingerprint:"))
+}
+
+#[tokio::test]
+async fn test_refreshed_servers_reach_the_live_session() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap();
+    tokio::spawn(genxlink_signaling_server::serve(listener, PeerManager::new()));
+
+    let viewer_id = DeviceId::new();
+    let mut viewer = signaling_client(server, &viewer_id);
+    let mut incoming = viewer.connect().await.unwrap();
+    viewer.ping().await.unwrap();
+
+    let session_id = Uuid::new_v4();
+    let api = ApiStandIn::start(session_id, 200, chrono::Duration::seconds(3)).await;
+    let host_id = DeviceId::new();
+    let session = WebRTCSession::with_signaling(host_id.clone(), signaling_client(server, &host_id));
+    let provider = IceProvider::fetch(&api.url, TOKEN, session_id).await.unwrap();
+    session.use_ice_provider(Arc::new(provider)).await;
+    session.start_streaming(0, viewer_id.clone()).await.unwrap();
+
+    // The refresh moves the session onto a new peer connection, offered to the viewer
+    let (_, offer) = next_offer(&mut incoming).await;
+    let (renewal, renewed) = next_offer(&mut incoming).await;
+    assert_ne!(fingerprint(&renewed), fingerprint(&offer));
+    assert_eq!(api.requests.load(Ordering::SeqCst), 2);
+
+    let mut answerer = WebRTCManager::new(viewer_id.0.clone(), WebRTCConfig::default());
+    answerer.initialize().await.unwrap();
+    let answer = answerer.create_answer(renewed).await.unwrap();
+    viewer.reply(&renewal, SignalingMessage::Answer { sdp: answer, from: viewer_id.clone(), to: host_id }).await.unwrap();
+
+    // Stopping the session stops the refreshes
+    session.stop_streaming().await.unwrap();
+    let requests = api.requests.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(api.requests.load(Ordering::SeqCst), requests);
+}
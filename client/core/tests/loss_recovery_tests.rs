@@ -0,0 +1,656 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use genxlink_client_core::encoder::{EncodedFrame, EncoderConfig, H264Encoder, VideoCodec, VideoEncoder};
+use genxlink_client_core::congestion_control::SendHistory;
+use genxlink_client_core::control_channel::ControlChannel;
+use genxlink_client_core::loss_recovery::{FecDecoder, MIME_TYPE_ULPFEC};
+use genxlink_client_core::quality_monitor::spawn_quality_monitor;
+use genxlink_client_core::streaming::{register_interceptors, Frame, StreamingPipeline, VideoReceiver, VideoStreamer};
+use genxlink_client_core::ClientError;
+use genxlink_protocol::QualityReport;
+use tokio::sync::{mpsc, watch};
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_H264};
+use webrtc::api::APIBuilder;
+use webrtc::interceptor::registry::Registry;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::packet::Packet as RtcpPacket;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::{FirEntry, FullIntraRequest};
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::reception_report::ReceptionReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::{nack_pairs_from_sequence_numbers, TransportLayerNack};
+use webrtc::rtp::codecs::h264::H264Packet;
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp::packetizer::Depacketizer;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+use webrtc::track::track_local::TrackLocalWriter;
+use webrtc::track::track_remote::TrackRemote;
+use webrtc::util::{MarshalSize, Unmarshal};
+
+const VIEWER_SSRC: u32 = 0xfeed;
+
+/// In-memory link dropping packets at random, deterministically
+#[derive(Debug)]
+struct LossyLink {
+    state: Mutex<LinkState>,
+}
+
+#[derive(Debug)]
+struct LinkState {
+    loss: f64,
+    rng: u64,
+    delivered: Vec<Packet>,
+    dropped: usize,
+}
+
+impl LossyLink {
+    fn new(loss: f64) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(LinkState {
+                loss,
+                rng: 0x2545_f491_4f6c_dd1d,
+                delivered: Vec::new(),
+                dropped: 0,
+            }),
+        })
+    }
+
+    fn take_delivered(&self) -> Vec<Packet> {
+        std::mem::take(&mut self.state.lock().unwrap().delivered)
+    }
+
+    fn dropped(&self) -> usize {
+        self.state.lock().unwrap().dropped
+    }
+}
+
+#[async_trait::async_trait]
+impl TrackLocalWriter for LossyLink {
+    async fn write_rtp(&self, p: &Packet) -> webrtc::error::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.rng ^= state.rng << 13;
+        state.rng ^= state.rng >> 7;
+        state.rng ^= state.rng << 17;
+        let sample = (state.rng >> 11) as f64 / (1u64 << 53) as f64;
+        if sample < state.loss {
+            state.dropped += 1;
+        } else {
+            state.delivered.push(p.clone());
+        }
+        Ok(p.marshal_size())
+    }
+
+    async fn write(&self, b: &[u8]) -> webrtc::error::Result<usize> {
+        let packet = Packet::unmarshal(&mut Bytes::copy_from_slice(b))?;
+        self.write_rtp(&packet).await
+    }
+}
+
+/// Viewer end of the links: recovers with FEC and finds what to NACK
+#[derive(Default)]
+struct Viewer {
+    fec: FecDecoder,
+    media: BTreeMap<u16, Packet>,
+    seen: HashSet<u16>,
+    highest: Option<u16>,
+    recovered_by_fec: usize,
+}
+
+impl Viewer {
+    fn receive(&mut self, packets: Vec<Packet>) {
+        for packet in packets {
+            let sequence_number = packet.header.sequence_number;
+            self.seen.insert(sequence_number);
+            self.highest = Some(self.highest.map_or(sequence_number, |highest| highest.max(sequence_number)));
+            let recovered = self.fec.on_media(&packet);
+            self.media.insert(sequence_number, packet);
+            self.insert_recovered(recovered);
+        }
+    }
+
+    fn receive_fec(&mut self, packets: Vec<Packet>) {
+        for packet in packets {
+            let recovered = self.fec.on_fec(&packet);
+            self.insert_recovered(recovered);
+        }
+    }
+
+    fn insert_recovered(&mut self, recovered: Vec<Packet>) {
+        for packet in recovered {
+            if self.seen.insert(packet.header.sequence_number) {
+                self.recovered_by_fec += 1;
+            }
+            self.media.insert(packet.header.sequence_number, packet);
+        }
+    }
+
+    /// Sequence numbers missing up to the highest one seen
+    fn missing(&self) -> Vec<u16> {
+        self.highest
+            .map(|highest| (1..highest).filter(|seq| !self.seen.contains(seq)).collect())
+            .unwrap_or_default()
+    }
+
+    fn nack(&self, media_ssrc: u32) -> Vec<Box<dyn RtcpPacket + Send + Sync>> {
+        vec![Box::new(TransportLayerNack {
+            sender_ssrc: VIEWER_SSRC,
+            media_ssrc,
+            nacks: nack_pairs_from_sequence_numbers(&self.missing()),
+        })]
+    }
+
+    /// Frames in the order sent, Annex B, if all their packets are here
+    fn frames(&self) -> Vec<Option<Vec<u8>>> {
+        let mut frames: BTreeMap<u32, Vec<&Packet>> = BTreeMap::new();
+        for packet in self.media.values() {
+            frames.entry(packet.header.timestamp).or_default().push(packet);
+        }
+        frames
+            .values()
+            .map(|packets| {
+                let complete = packets.last().is_some_and(|last| last.header.marker)
+                    && packets.windows(2).all(|pair| pair[1].header.sequence_number == pair[0].header.sequence_number + 1);
+                complete.then(|| {
+                    let mut depacketizer = H264Packet::default();
+                    packets
+                        .iter()
+                        .flat_map(|packet| depacketizer.depacketize(&packet.payload).unwrap().to_vec())
+                        .collect()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Annex B access unit for frame `index`, with SPS and PPS before keyframes
+fn access_unit(index: usize, keyframe: bool) -> Vec<u8> {
+    let nal = |header: u8, len: usize| {
+        let mut nal = vec![0, 0, 0, 1, header];
+        // Stay clear of start code emulation
+        nal.extend((0..len).map(|j| 0x10 + ((index * 7 + j) % 0xe0) as u8));
+        nal
+    };
+    if keyframe {
+        [nal(0x67, 12), nal(0x68, 4), nal(0x65, 6000)].concat()
+    } else {
+        nal(0x41, 2500 + index * 13 % 800)
+    }
+}
+
+fn encoded(index: usize) -> EncodedFrame {
+    EncodedFrame {
+        data: access_unit(index, index.is_multiple_of(30)),
+        timestamp: index as u64,
+        is_keyframe: index.is_multiple_of(30),
+    }
+}
+
+fn receiver_report(ssrc: u32, fraction_lost: u8) -> Vec<Box<dyn RtcpPacket + Send + Sync>> {
+    vec![Box::new(ReceiverReport {
+        ssrc: VIEWER_SSRC,
+        reports: vec![ReceptionReport {
+            ssrc,
+            fraction_lost,
+            ..Default::default()
+        }],
+        ..Default::default()
+    })]
+}
+
+/// Encoder stand-in counting keyframe requests
+struct RecordingEncoder {
+    config: EncoderConfig,
+    keyframes_requested: Arc<AtomicUsize>,
+}
+
+impl VideoEncoder for RecordingEncoder {
+    fn init(&mut self, config: EncoderConfig) -> Result<(), ClientError> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn encode(&mut self, frame: &genxlink_client_core::Frame) -> Result<EncodedFrame, ClientError> {
+        Ok(encoded(frame.timestamp as usize))
+    }
+
+    fn flush(&mut self) -> Result<Vec<EncodedFrame>, ClientError> {
+        Ok(vec![])
+    }
+
+    fn request_keyframe(&mut self) {
+        self.keyframes_requested.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn get_config(&self) -> &EncoderConfig {
+        &self.config
+    }
+}
+
+fn recording_streamer() -> (VideoStreamer, Arc<AtomicUsize>) {
+    let keyframes_requested = Arc::new(AtomicUsize::new(0));
+    let encoder = RecordingEncoder {
+        config: EncoderConfig::default(),
+        keyframes_requested: Arc::clone(&keyframes_requested),
+    };
+    (VideoStreamer::new(Box::new(encoder)).unwrap(), keyframes_requested)
+}
+
+#[tokio::test]
+async fn test_nack_repairs_lossy_link() {
+    let link = LossyLink::new(0.1);
+    let (mut streamer, _) = recording_streamer();
+    streamer.set_output(link.clone());
+    let ssrc = streamer.get_stats().ssrc;
+    let mut viewer = Viewer::default();
+
+    for index in 0..60 {
+        streamer.stream_frame(encoded(index)).await.unwrap();
+        viewer.receive(link.take_delivered());
+
+        // Resent packets can be lost again; NACK until nothing is missing
+        for _ in 0..10 {
+            if viewer.missing().is_empty() {
+                break;
+            }
+            streamer.handle_rtcp(&viewer.nack(ssrc)).await.unwrap();
+            viewer.receive(link.take_delivered());
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+    }
+
+    // The last frame's trailing losses can't be seen yet
+    let frames = viewer.frames();
+    assert_eq!(frames.len(), 60);
+    for (index, frame) in frames.iter().enumerate().take(59) {
+        assert_eq!(frame.as_deref(), Some(access_unit(index, index.is_multiple_of(30)).as_slice()), "frame {}", index);
+    }
+    let stats = streamer.get_stats();
+    assert!(link.dropped() > 20);
+    assert!(stats.packets_retransmitted >= link.dropped() as u64 / 2);
+    assert_eq!(stats.fec_packets_sent, 0);
+}
+
+#[tokio::test]
+async fn test_fec_repairs_without_round_trip() {
+    let link = LossyLink::new(0.05);
+    let fec_link = LossyLink::new(0.05);
+    let (mut streamer, _) = recording_streamer();
+    streamer.set_output(link.clone());
+    streamer.set_fec_output(fec_link.clone());
+    let ssrc = streamer.get_stats().ssrc;
+    assert_eq!(streamer.fec_overhead(), 0.0);
+
+    // The viewer reports 10% loss: about a quarter as many FEC packets as media
+    streamer.handle_rtcp(&receiver_report(ssrc, 26)).await.unwrap();
+    assert!((streamer.fec_overhead() - 0.25).abs() < 0.01);
+
+    let mut viewer = Viewer::default();
+    for index in 0..120 {
+        streamer.stream_frame(encoded(index)).await.unwrap();
+        viewer.receive(link.take_delivered());
+        viewer.receive_fec(fec_link.take_delivered());
+    }
+
+    // Most lost media packets come back without a single NACK
+    let stats = streamer.get_stats();
+    assert!(stats.fec_packets_sent > 0);
+    let intact = viewer.frames().iter().take(119).filter(|frame| frame.is_some()).count();
+    let media_lost = viewer.missing().len() + viewer.recovered_by_fec;
+    assert!(viewer.recovered_by_fec * 2 > media_lost, "{} of {} recovered", viewer.recovered_by_fec, media_lost);
+    assert!(intact >= 110, "{} of 119 frames intact", intact);
+    assert_eq!(stats.packets_retransmitted, 0);
+
+    // Loss gone: FEC stops
+    streamer.handle_rtcp(&receiver_report(ssrc, 0)).await.unwrap();
+    assert_eq!(streamer.fec_overhead(), 0.0);
+}
+
+#[tokio::test]
+async fn test_picture_loss_requests_keyframe() {
+    let (mut streamer, keyframes_requested) = recording_streamer();
+    let ssrc = streamer.get_stats().ssrc;
+
+    let pli: Vec<Box<dyn RtcpPacket + Send + Sync>> = vec![Box::new(PictureLossIndication {
+        sender_ssrc: VIEWER_SSRC,
+        media_ssrc: ssrc,
+    })];
+    streamer.handle_rtcp(&pli).await.unwrap();
+    assert_eq!(keyframes_requested.load(Ordering::SeqCst), 1);
+
+    let fir: Vec<Box<dyn RtcpPacket + Send + Sync>> = vec![Box::new(FullIntraRequest {
+        sender_ssrc: VIEWER_SSRC,
+        media_ssrc: 0,
+        fir: vec![FirEntry { ssrc, sequence_number: 1 }],
+    })];
+    streamer.handle_rtcp(&fir).await.unwrap();
+    assert_eq!(keyframes_requested.load(Ordering::SeqCst), 2);
+
+    // Another stream's picture loss
+    let other: Vec<Box<dyn RtcpPacket + Send + Sync>> = vec![Box::new(PictureLossIndication {
+        sender_ssrc: VIEWER_SSRC,
+        media_ssrc: ssrc.wrapping_add(1),
+    })];
+    streamer.handle_rtcp(&other).await.unwrap();
+    assert_eq!(keyframes_requested.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_h264_encoder_sends_keyframe_after_pli() {
+    let mut encoder = Box::new(H264Encoder::new());
+    encoder
+        .init(EncoderConfig {
+            width: 320,
+            height: 240,
+            fps: 30,
+            bitrate: 500_000,
+            codec: VideoCodec::H264,
+        })
+        .unwrap();
+    let link = LossyLink::new(0.0);
+    let mut pipeline = StreamingPipeline::new(encoder, 30).unwrap();
+    pipeline.set_output(link.clone());
+    let ssrc = pipeline.get_stats().ssrc;
+
+    let frame = Frame {
+        width: 320,
+        height: 240,
+        data: vec![0u8; 320 * 240 * 4],
+        timestamp: std::time::Instant::now(),
+    };
+    let has_idr = |packets: &[Packet]| {
+        packets.iter().any(|packet| {
+            let nal_type = packet.payload[0] & 0x1f;
+            // Single NAL, or the type inside a STAP-A or FU-A
+            nal_type == 5
+                || (nal_type == 28 && packet.payload[1] & 0x1f == 5)
+                || (nal_type == 24 && packet.payload[3] & 0x1f == 5)
+        })
+    };
+
+    // The stream starts with a keyframe
+    pipeline.stream_frame(&frame).await.unwrap();
+    assert!(has_idr(&link.take_delivered()));
+    pipeline.stream_frame(&frame).await.unwrap();
+    assert!(!has_idr(&link.take_delivered()));
+
+    let pli: Vec<Box<dyn RtcpPacket + Send + Sync>> = vec![Box::new(PictureLossIndication {
+        sender_ssrc: VIEWER_SSRC,
+        media_ssrc: ssrc,
+    })];
+    pipeline.handle_rtcp(&pli).await.unwrap();
+    pipeline.stream_frame(&frame).await.unwrap();
+    assert!(has_idr(&link.take_delivered()));
+}
+
+/// BGRA frame of noise, different for every `seed`
+fn noise_frame(width: u32, height: u32, seed: u64) -> genxlink_client_core::Frame {
+    let mut rng = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let data = (0..width * height)
+        .flat_map(|_| {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            let [b, g, r, ..] = rng.to_le_bytes();
+            [b, g, r, 255]
+        })
+        .collect();
+    genxlink_client_core::Frame {
+        width,
+        height,
+        stride: width * 4,
+        data,
+        timestamp: seed,
+    }
+}
+
+#[test]
+fn test_h264_encoder_follows_rate() {
+    let config = EncoderConfig {
+        width: 320,
+        height: 240,
+        fps: 30,
+        bitrate: 2_000_000,
+        codec: VideoCodec::H264,
+    };
+    let mut high = H264Encoder::new();
+    high.init(config.clone()).unwrap();
+    let mut low = H264Encoder::new();
+    low.init(config).unwrap();
+
+    assert!(high.encode(&noise_frame(320, 240, 0)).unwrap().is_keyframe);
+    assert!(low.encode(&noise_frame(320, 240, 0)).unwrap().is_keyframe);
+
+    // Lowered in place: no new keyframe, and far fewer bytes
+    low.set_rate(100_000, 15).unwrap();
+    assert_eq!((low.get_config().bitrate, low.get_config().fps), (100_000, 15));
+    let (mut high_bytes, mut low_bytes) = (0, 0);
+    for seed in 1..30 {
+        high_bytes += high.encode(&noise_frame(320, 240, seed)).unwrap().data.len();
+        let frame = low.encode(&noise_frame(320, 240, seed)).unwrap();
+        assert!(!frame.is_keyframe);
+        low_bytes += frame.data.len();
+    }
+    assert!(low_bytes * 4 < high_bytes, "{} bytes at 100 kbps, {} at 2 Mbps", low_bytes, high_bytes);
+
+    // A new size starts over with a keyframe, still at the lowered rate
+    let frame = low.encode(&noise_frame(160, 120, 30)).unwrap();
+    assert!(frame.is_keyframe);
+    assert_eq!((low.get_config().width, low.get_config().height, low.get_config().bitrate), (160, 120, 100_000));
+}
+
+/// The streamer's real track, losing every tenth packet for good and every
+/// other fifth packet once, while `dropping`
+#[derive(Debug)]
+struct DroppingTrack {
+    track: Arc<TrackLocalStaticRTP>,
+    dropping: AtomicBool,
+    written: Mutex<HashMap<u16, Packet>>,
+}
+
+#[async_trait::async_trait]
+impl TrackLocalWriter for DroppingTrack {
+    async fn write_rtp(&self, p: &Packet) -> webrtc::error::Result<usize> {
+        let sequence_number = p.header.sequence_number;
+        let first = self.written.lock().unwrap().insert(sequence_number, p.clone()).is_none();
+        if self.dropping.load(Ordering::SeqCst)
+            && (sequence_number.is_multiple_of(10) || (sequence_number % 10 == 5 && first))
+        {
+            return Ok(p.marshal_size());
+        }
+        self.track.write_rtp(p).await
+    }
+
+    async fn write(&self, b: &[u8]) -> webrtc::error::Result<usize> {
+        let packet = Packet::unmarshal(&mut Bytes::copy_from_slice(b))?;
+        self.write_rtp(&packet).await
+    }
+}
+
+async fn peer_connection(registry: impl FnOnce(&mut MediaEngine) -> Registry) -> Arc<RTCPeerConnection> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().unwrap();
+    let registry = registry(&mut media_engine);
+    let api = APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .build();
+    Arc::new(api.new_peer_connection(RTCConfiguration::default()).await.unwrap())
+}
+
+/// Offer from `host`, answer from `viewer`, candidates in the descriptions
+async fn negotiate(host: &RTCPeerConnection, viewer: &RTCPeerConnection) {
+    let offer = host.create_offer(None).await.unwrap();
+    let mut gathered = host.gathering_complete_promise().await;
+    host.set_local_description(offer).await.unwrap();
+    gathered.recv().await;
+    viewer.set_remote_description(host.local_description().await.unwrap()).await.unwrap();
+
+    let answer = viewer.create_answer(None).await.unwrap();
+    let mut gathered = viewer.gathering_complete_promise().await;
+    viewer.set_local_description(answer).await.unwrap();
+    gathered.recv().await;
+    host.set_remote_description(viewer.local_description().await.unwrap()).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_loss_recovery_over_peer_connection() {
+    let host = peer_connection(|_| register_interceptors(Registry::new())).await;
+    let viewer = peer_connection(|media_engine| register_default_interceptors(Registry::new(), media_engine).unwrap()).await;
+
+    let (mut streamer, keyframes_requested) = recording_streamer();
+    let sender = streamer.add_to_peer_connection(&host).await.unwrap();
+    let ssrc = streamer.get_stats().ssrc;
+    assert_eq!(ssrc, sender.get_parameters().await.encodings[0].ssrc);
+    let link = Arc::new(DroppingTrack {
+        track: streamer.get_track(),
+        dropping: AtomicBool::new(false),
+        written: Mutex::new(HashMap::new()),
+    });
+    streamer.set_output(link.clone());
+
+    // NACKs, PLIs and receiver reports reach the streamer through the
+    // quality monitor's RTCP reader
+    let (loss_feedback, mut loss_rx) = mpsc::unbounded_channel();
+    spawn_quality_monitor(
+        Arc::clone(&host),
+        sender,
+        Arc::new(parking_lot::Mutex::new(SendHistory::new())),
+        Arc::new(AtomicU64::new(0)),
+        Arc::new(watch::channel(Default::default()).0),
+        Arc::new(watch::channel(None).0),
+        Some(loss_feedback),
+    );
+
+    let (receiver, mut received) = VideoReceiver::new();
+    let (tracks_tx, mut tracks) = mpsc::unbounded_channel::<Arc<TrackRemote>>();
+    viewer.on_track(Box::new(move |track, _, _| {
+        receiver.receive(Arc::clone(&track));
+        let _ = tracks_tx.send(track);
+        Box::pin(async {})
+    }));
+    negotiate(&host, &viewer).await;
+
+    // Stream until the media track shows up at the viewer
+    let mut index = 0;
+    let media_track = loop {
+        streamer.stream_frame(encoded(index)).await.unwrap();
+        index += 1;
+        if let Ok(track) = tokio::time::timeout(Duration::from_millis(50), tracks.recv()).await {
+            break track.unwrap();
+        }
+        assert!(index < 200, "the media track never arrived");
+    };
+    assert!(media_track.codec().capability.mime_type.eq_ignore_ascii_case(MIME_TYPE_H264));
+    assert_eq!(media_track.ssrc(), ssrc);
+
+    // About 20% loss, reported by hand until the viewer's reports come in
+    streamer.handle_rtcp(&receiver_report(ssrc, 51)).await.unwrap();
+    let before_loss: HashSet<u16> = link.written.lock().unwrap().keys().copied().collect();
+    link.dropping.store(true, Ordering::SeqCst);
+    for _ in 0..60 {
+        streamer.stream_frame(encoded(index)).await.unwrap();
+        index += 1;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        while let Ok(packets) = loss_rx.try_recv() {
+            streamer.handle_rtcp(&packets).await.unwrap();
+        }
+    }
+    // A few clean frames so the last losses are NACKed
+    link.dropping.store(false, Ordering::SeqCst);
+    let lossy: HashSet<u16> = link.written.lock().unwrap().keys().copied().filter(|seq| !before_loss.contains(seq)).collect();
+    for _ in 0..3 {
+        streamer.stream_frame(encoded(index)).await.unwrap();
+        index += 1;
+    }
+
+    // The FEC track arrives with the negotiated ULPFEC codec
+    let fec_track = tokio::time::timeout(Duration::from_secs(5), tracks.recv()).await.unwrap().unwrap();
+    assert!(fec_track.codec().capability.mime_type.eq_ignore_ascii_case(MIME_TYPE_ULPFEC));
+    assert_ne!(fec_track.ssrc(), ssrc);
+
+    // Every packet arrives as sent, rebuilt by FEC when it was lost for good
+    let mut packets = HashMap::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !lossy.iter().all(|seq| packets.contains_key(seq)) {
+        assert!(Instant::now() < deadline, "{} packets never arrived", lossy.iter().filter(|seq| !packets.contains_key(*seq)).count());
+        tokio::select! {
+            Some(packet) = received.recv() => {
+                packets.insert(packet.header.sequence_number, packet);
+            }
+            Some(feedback) = loss_rx.recv() => streamer.handle_rtcp(&feedback).await.unwrap(),
+        }
+    }
+    let written = link.written.lock().unwrap().clone();
+    for seq in &lossy {
+        let (packet, sent) = (&packets[seq], &written[seq]);
+        assert_eq!(packet.header.ssrc, ssrc);
+        assert_eq!(packet.header.payload_type, media_track.payload_type());
+        assert_eq!((packet.header.timestamp, packet.header.marker), (sent.header.timestamp, sent.header.marker));
+        assert_eq!(packet.payload, sent.payload);
+    }
+    let stats = streamer.get_stats();
+    assert!(stats.fec_packets_sent > 0);
+    assert!(stats.packets_retransmitted > 0);
+
+    // A picture loss at the viewer makes for a keyframe
+    assert_eq!(keyframes_requested.load(Ordering::SeqCst), 0);
+    let pli: Vec<Box<dyn RtcpPacket + Send + Sync>> = vec![Box::new(PictureLossIndication {
+        sender_ssrc: 0,
+        media_ssrc: media_track.ssrc(),
+    })];
+    viewer.write_rtcp(&pli).await.unwrap();
+    while keyframes_requested.load(Ordering::SeqCst) == 0 {
+        let feedback = tokio::time::timeout(Duration::from_secs(5), loss_rx.recv()).await.unwrap().unwrap();
+        streamer.handle_rtcp(&feedback).await.unwrap();
+    }
+
+    host.close().await.unwrap();
+    viewer.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_quality_reports_reach_viewer() {
+    let host = peer_connection(|_| register_interceptors(Registry::new())).await;
+    let viewer = peer_connection(|media_engine| register_default_interceptors(Registry::new(), media_engine).unwrap()).await;
+
+    // The host forwards what its quality monitor publishes
+    let control = Arc::new(ControlChannel::without_input(host.create_data_channel("control", None).await.unwrap()));
+    control.start().await.unwrap();
+    let quality = watch::channel(None).0;
+    control.forward_quality_reports(quality.subscribe());
+
+    let (reports_tx, mut reports) = mpsc::unbounded_channel();
+    viewer.on_data_channel(Box::new(move |data_channel| {
+        let mut channel = ControlChannel::without_input(data_channel);
+        channel.set_quality_reports(reports_tx.clone());
+        Box::pin(async move { channel.start().await.unwrap() })
+    }));
+    negotiate(&host, &viewer).await;
+
+    // Reports published before the channel opens are skipped; the monitor
+    // publishes every second
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let received = loop {
+        quality.send_replace(Some(QualityReport {
+            latency_ms: 42,
+            packet_loss: 0.02,
+            fps: 24,
+            bandwidth_kbps: 1500,
+        }));
+        if let Ok(report) = tokio::time::timeout(Duration::from_millis(100), reports.recv()).await {
+            break report.unwrap();
+        }
+        assert!(Instant::now() < deadline, "no quality report reached the viewer");
+    };
+    assert_eq!((received.latency_ms, received.fps, received.bandwidth_kbps), (42, 24, 1500));
+    assert_eq!(received.packet_loss, 0.02);
+
+    host.close().await.unwrap();
+    viewer.close().await.unwrap();
+}
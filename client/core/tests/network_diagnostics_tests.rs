@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use genxlink_client_core::network_diagnostics::{
+    DiagnosticsConfig, FilteringBehavior, MappingBehavior, NatType, NetworkDiagnostics, StunAttribute,
+    StunMessage, StunMessageType,
+};
+use parking_lot::Mutex;
+use tokio::net::{TcpListener, UdpSocket};
+
+const PRIMARY_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+const ALTERNATE_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+const PUBLIC_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+/// NAT behavior the stand-in simulates in front of the client
+#[derive(Clone, Copy)]
+enum Nat {
+    None,
+    Behind(MappingBehavior, FilteringBehavior),
+}
+
+#[derive(Default)]
+struct NatState {
+    /// Public address per (client, mapping key)
+    mappings: HashMap<(SocketAddr, Option<SocketAddr>), SocketAddr>,
+    /// Server addresses each client has sent to
+    contacted: HashMap<SocketAddr, HashSet<SocketAddr>>,
+    next_port: u16,
+}
+
+/// Local RFC 5780 STUN server on two IPs and two ports that pretends the
+/// client sits behind a NAT with the given behavior
+struct StunStandIn {
+    primary: SocketAddr,
+}
+
+impl StunStandIn {
+    async fn start(nat: Nat) -> Self {
+        let sockets = loop {
+            let primary = UdpSocket::bind(SocketAddr::new(PRIMARY_IP, 0)).await.unwrap();
+            let alternate_port = UdpSocket::bind(SocketAddr::new(PRIMARY_IP, 0)).await.unwrap();
+            let (port, other_port) = (primary.local_addr().unwrap().port(), alternate_port.local_addr().unwrap().port());
+            // The alternate IP listens on the same two ports
+            let (Ok(alternate_ip), Ok(alternate_both)) = (
+                UdpSocket::bind(SocketAddr::new(ALTERNATE_IP, port)).await,
+                UdpSocket::bind(SocketAddr::new(ALTERNATE_IP, other_port)).await,
+            ) else {
+                continue;
+            };
+            break [primary, alternate_port, alternate_ip, alternate_both].map(Arc::new);
+        };
+        let primary = sockets[0].local_addr().unwrap();
+        let other = sockets[3].local_addr().unwrap();
+
+        let state = Arc::new(Mutex::new(NatState { next_port: 40000, ..Default::default() }));
+        for socket in &sockets {
+            tokio::spawn(serve(socket.clone(), sockets.clone(), (primary, other), nat, state.clone()));
+        }
+        Self { primary }
+    }
+}
+
+async fn serve(
+    socket: Arc<UdpSocket>,
+    sockets: [Arc<UdpSocket>; 4],
+    (primary, other): (SocketAddr, SocketAddr),
+    nat: Nat,
+    state: Arc<Mutex<NatState>>,
+) {
+    let local = socket.local_addr().unwrap();
+    let mut buf = [0u8; 1500];
+    while let Ok((len, client)) = socket.recv_from(&mut buf).await {
+        let Ok(request) = StunMessage::decode(&buf[..len]) else { continue };
+        if request.message_type != StunMessageType::BindingRequest {
+            continue;
+        }
+
+        // Answer from the alternate IP and/or port when asked to
+        let (change_ip, change_port) = request.change_request().unwrap_or((false, false));
+        let origin = SocketAddr::new(
+            match (change_ip, local.ip() == PRIMARY_IP) {
+                (true, true) => ALTERNATE_IP,
+                (true, false) => PRIMARY_IP,
+                (false, _) => local.ip(),
+            },
+            match (change_port, local.port() == primary.port()) {
+                (true, true) => other.port(),
+                (true, false) => primary.port(),
+                (false, _) => local.port(),
+            },
+        );
+        let responder = sockets.iter().find(|s| s.local_addr().unwrap() == origin).unwrap();
+
+        let mapped = {
+            let mut state = state.lock();
+            state.contacted.entry(client).or_default().insert(local);
+            match nat {
+                Nat::None => client,
+                Nat::Behind(mapping, filtering) => {
+                    let allowed = match filtering {
+                        FilteringBehavior::AddressDependent => state.contacted[&client].iter().any(|a| a.ip() == origin.ip()),
+                        FilteringBehavior::AddressAndPortDependent => state.contacted[&client].contains(&origin),
+                        _ => true,
+                    };
+                    if !allowed {
+                        continue;
+                    }
+                    let key = match mapping {
+                        MappingBehavior::AddressDependent => Some(SocketAddr::new(local.ip(), 0)),
+                        MappingBehavior::AddressAndPortDependent => Some(local),
+                        _ => None,
+                    };
+                    let next_port = state.next_port;
+                    let mapped = *state
+                        .mappings
+                        .entry((client, key))
+                        .or_insert(SocketAddr::new(PUBLIC_IP, next_port));
+                    if mapped.port() == next_port {
+                        state.next_port += 1;
+                    }
+                    mapped
+                }
+            }
+        };
+
+        let response = StunMessage {
+            message_type: StunMessageType::BindingResponse,
+            transaction_id: request.transaction_id,
+            attributes: vec![
+                StunAttribute::XorMappedAddress(mapped),
+                StunAttribute::OtherAddress(other),
+                StunAttribute::ResponseOrigin(origin),
+            ],
+        };
+        let _ = responder.send_to(&response.encode(), client).await;
+    }
+}
+
+fn config(stun_servers: Vec<String>) -> DiagnosticsConfig {
+    DiagnosticsConfig {
+        stun_servers,
+        bind_address: SocketAddr::new(PRIMARY_IP, 0),
+        stun_timeout: Duration::from_millis(150),
+        stun_attempts: 2,
+        probe_count: 3,
+        probe_timeout: Duration::from_millis(500),
+        ..DiagnosticsConfig::default()
+    }
+}
+
+async fn detect(nat: Nat) -> genxlink_client_core::network_diagnostics::NatReport {
+    let server = StunStandIn::start(nat).await;
+    NetworkDiagnostics::new(config(vec![format!("stun:{}", server.primary)]))
+        .detect_nat()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_open_internet() {
+    let report = detect(Nat::None).await;
+    assert_eq!(report.nat_type, NatType::OpenInternet);
+    assert_eq!(report.mapping, MappingBehavior::NoNat);
+    assert_eq!(report.public_address, report.local_address);
+    assert!(report.rfc5780);
+    assert_eq!(report.udp_latency.received, 3);
+}
+
+#[tokio::test]
+async fn test_cone_nats() {
+    let cases = [
+        (FilteringBehavior::EndpointIndependent, NatType::FullCone),
+        (FilteringBehavior::AddressDependent, NatType::RestrictedCone),
+        (FilteringBehavior::AddressAndPortDependent, NatType::PortRestrictedCone),
+    ];
+    for (filtering, expected) in cases {
+        let report = detect(Nat::Behind(MappingBehavior::EndpointIndependent, filtering)).await;
+        assert_eq!(report.mapping, MappingBehavior::EndpointIndependent);
+        assert_eq!(report.filtering, filtering);
+        assert_eq!(report.nat_type, expected);
+        assert_eq!(report.public_address.unwrap().ip(), PUBLIC_IP);
+        assert!(report.nat_type.direct_connection_likely());
+    }
+}
+
+#[tokio::test]
+async fn test_symmetric_nats() {
+    for mapping in [MappingBehavior::AddressDependent, MappingBehavior::AddressAndPortDependent] {
+        let report = detect(Nat::Behind(mapping, FilteringBehavior::AddressAndPortDependent)).await;
+        assert_eq!(report.mapping, mapping);
+        assert_eq!(report.nat_type, NatType::Symmetric);
+        assert!(!report.nat_type.direct_connection_likely());
+    }
+}
+
+#[tokio::test]
+async fn test_udp_blocked() {
+    // Bound but silent, like a firewall dropping UDP
+    let silent = UdpSocket::bind(SocketAddr::new(PRIMARY_IP, 0)).await.unwrap();
+    let diagnostics = NetworkDiagnostics::new(config(vec![silent.local_addr().unwrap().to_string()]));
+
+    let report = diagnostics.run().await;
+    assert!(report.udp_blocked());
+    assert_eq!(report.nat.udp_latency.received, 0);
+    assert!(report.findings.iter().any(|f| f.contains("relay")));
+}
+
+#[tokio::test]
+async fn test_full_report() {
+    let server = StunStandIn::start(Nat::Behind(
+        MappingBehavior::AddressAndPortDependent,
+        FilteringBehavior::AddressAndPortDependent,
+    ))
+    .await;
+    let signaling = TcpListener::bind(SocketAddr::new(PRIMARY_IP, 0)).await.unwrap();
+    let closed = TcpListener::bind(SocketAddr::new(PRIMARY_IP, 0)).await.unwrap().local_addr().unwrap();
+
+    let diagnostics = NetworkDiagnostics::new(DiagnosticsConfig {
+        signaling_url: Some(format!("ws://{}/ws", signaling.local_addr().unwrap())),
+        relay_url: Some(format!("http://{}", closed)),
+        ..config(vec![server.primary.to_string()])
+    });
+    let report = diagnostics.run().await;
+
+    assert_eq!(report.nat.nat_type, NatType::Symmetric);
+    let [signaling_probe, relay_probe] = &report.services[..] else {
+        panic!("expected two service probes");
+    };
+    assert!(signaling_probe.reachable);
+    assert_eq!(signaling_probe.address, Some(signaling.local_addr().unwrap()));
+    assert_eq!(signaling_probe.latency.received, 3);
+    assert!(!relay_probe.reachable);
+    assert!(relay_probe.error.is_some());
+
+    assert!(report.findings.iter().any(|f| f.starts_with("Symmetric NAT")));
+    assert!(report.findings.iter().any(|f| f.contains("relay server")));
+    assert!(report.network_condition(10_000).is_some());
+
+    let text = report.to_string();
+    assert!(text.contains("NAT type:       Symmetric"));
+    assert!(text.contains("UNREACHABLE"));
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["nat"]["nat_type"], "Symmetric");
+    assert_eq!(json["services"][0]["reachable"], true);
+}
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use genxlink_client_core::device_identity::DeviceIdentity;
+use genxlink_client_core::lan_direct::LocalDevice;
+use genxlink_client_core::mdns::PeerInfo;
+use genxlink_client_core::quic_transport::{Channel, QuicListener, QuicTransport};
+use genxlink_client_core::session_password::SessionPasswordManager;
+use genxlink_client_core::transport::Transport;
+use genxlink_client_core::ClientError;
+use genxlink_protocol::messages::{FrameType, KeyModifiers};
+use genxlink_protocol::{
+    ClipboardData, DeviceId, FileChunk, KeyboardEvent, Message, MessagePayload, VideoFrame,
+};
+use tokio::sync::{mpsc, Mutex};
+
+fn loopback() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)
+}
+
+fn device(name: &str) -> LocalDevice {
+    LocalDevice {
+        identity: Arc::new(DeviceIdentity::generate().unwrap()),
+        device_id: DeviceId::new(),
+        device_name: name.to_string(),
+        capabilities: vec!["screen-share".to_string()],
+    }
+}
+
+fn discovered(host: &LocalDevice, address: SocketAddr) -> PeerInfo {
+    PeerInfo {
+        device_id: host.device_id.clone(),
+        device_name: host.device_name.clone(),
+        ip_address: address.ip().to_string(),
+        addresses: vec![address.ip()],
+        port: address.port(),
+        last_seen: SystemTime::now(),
+        capabilities: host.capabilities.clone(),
+        public_key: host.identity.public_key().to_vec(),
+    }
+}
+
+fn start_host(host: &LocalDevice) -> (QuicListener, mpsc::UnboundedReceiver<QuicTransport>, String) {
+    let mut passwords = SessionPasswordManager::new();
+    let password = passwords.generate_password();
+    let (listener, incoming) =
+        QuicListener::start(loopback(), host.clone(), Arc::new(Mutex::new(passwords))).unwrap();
+    (listener, incoming, password)
+}
+
+async fn dial(viewer: &LocalDevice, peer: &PeerInfo, password: &str) -> Result<QuicTransport, ClientError> {
+    let mut transport = QuicTransport::bind(viewer.clone(), loopback())?.with_password(password.to_string());
+    transport.connect_to(peer).await?;
+    Ok(transport)
+}
+
+fn payloads() -> Vec<MessagePayload> {
+    vec![
+        MessagePayload::Ping,
+        MessagePayload::KeyboardEvent(KeyboardEvent {
+            key_code: 65,
+            scan_code: 30,
+            pressed: true,
+            modifiers: KeyModifiers { ctrl: false, alt: false, shift: false, meta: false },
+        }),
+        MessagePayload::ClipboardSync(ClipboardData {
+            content_type: "text/plain".to_string(),
+            data: b"copied".to_vec(),
+        }),
+        MessagePayload::FileChunk(FileChunk {
+            file_id: "file-1".to_string(),
+            chunk_index: 0,
+            total_chunks: 1,
+            data: vec![7u8; 64 * 1024],
+        }),
+        // Larger than one datagram, so it is fragmented
+        MessagePayload::VideoFrame(VideoFrame {
+            timestamp: 42,
+            frame_type: FrameType::KeyFrame,
+            width: 1920,
+            height: 1080,
+            data: (0..20_000).map(|i| i as u8).collect(),
+        }),
+    ]
+}
+
+async fn receive(transport: &mut QuicTransport) -> Message {
+    tokio::time::timeout(Duration::from_secs(5), transport.receive())
+        .await
+        .expect("timed out waiting for a message")
+        .unwrap()
+        .expect("session closed")
+}
+
+/// Send one message per channel and check they all arrive intact
+async fn exchange(sender: &mut QuicTransport, receiver: &mut QuicTransport) {
+    for payload in payloads() {
+        sender.send_payload(payload).await.unwrap();
+    }
+
+    let mut channels = HashSet::new();
+    for _ in 0..payloads().len() {
+        let message = receive(receiver).await;
+        assert_eq!(Some(message.session_id), receiver.session_id());
+        match &message.payload {
+            MessagePayload::KeyboardEvent(event) => assert_eq!(event.key_code, 65),
+            MessagePayload::ClipboardSync(clipboard) => assert_eq!(clipboard.data, b"copied"),
+            MessagePayload::FileChunk(chunk) => assert_eq!(chunk.data.len(), 64 * 1024),
+            MessagePayload::VideoFrame(frame) => {
+                assert_eq!(frame.data.len(), 20_000);
+                assert_eq!(frame.data[19_999], (19_999 % 256) as u8);
+            }
+            MessagePayload::Ping => {}
+            other => panic!("unexpected payload {:?}", other),
+        }
+        channels.insert(Channel::for_payload(&message.payload));
+    }
+    assert_eq!(channels.len(), 5);
+}
+
+#[tokio::test]
+async fn test_quic_messages_both_ways() {
+    let host = device("Host");
+    let viewer = device("Viewer");
+    let (listener, mut incoming, password) = start_host(&host);
+    let peer = discovered(&host, listener.local_addr().unwrap());
+
+    let mut dialer = dial(&viewer, &peer, &password).await.unwrap();
+    let mut accepted = incoming.recv().await.unwrap();
+
+    assert!(dialer.is_connected());
+    assert_eq!(dialer.session_id(), accepted.session_id());
+    assert_eq!(dialer.remote().unwrap().capabilities, host.capabilities);
+    assert_eq!(accepted.remote().unwrap().device_id, viewer.device_id);
+    assert_eq!(accepted.remote().unwrap().public_key, viewer.identity.public_key());
+
+    exchange(&mut dialer, &mut accepted).await;
+    exchange(&mut accepted, &mut dialer).await;
+
+    dialer.disconnect().await.unwrap();
+    assert!(!dialer.is_connected());
+    let closed = tokio::time::timeout(Duration::from_secs(5), accepted.receive()).await.unwrap();
+    assert!(matches!(closed, Ok(None)));
+}
+
+#[tokio::test]
+async fn test_quic_connection_migration() {
+    let host = device("Host");
+    let viewer = device("Viewer");
+    let (listener, mut incoming, password) = start_host(&host);
+    let peer = discovered(&host, listener.local_addr().unwrap());
+
+    let mut dialer = dial(&viewer, &peer, &password).await.unwrap();
+    let mut accepted = incoming.recv().await.unwrap();
+    let old_address = dialer.local_addr().unwrap();
+    assert_eq!(accepted.remote_address(), Some(old_address));
+
+    dialer.rebind(loopback()).unwrap();
+    let new_address = dialer.local_addr().unwrap();
+    assert_ne!(new_address, old_address);
+
+    exchange(&mut dialer, &mut accepted).await;
+    assert_eq!(accepted.remote_address(), Some(new_address));
+    exchange(&mut accepted, &mut dialer).await;
+}
+
+#[tokio::test]
+async fn test_quic_host_must_hold_pinned_key() {
+    let host = device("Host");
+    let viewer = device("Viewer");
+    let (listener, _incoming, password) = start_host(&host);
+
+    let mut peer = discovered(&host, listener.local_addr().unwrap());
+    peer.public_key = DeviceIdentity::generate().unwrap().public_key().to_vec();
+
+    assert!(dial(&viewer, &peer, &password).await.is_err());
+}
+
+#[tokio::test]
+async fn test_quic_wrong_password_is_rejected() {
+    let host = device("Host");
+    let viewer = device("Viewer");
+    let (listener, _incoming, _password) = start_host(&host);
+    let peer = discovered(&host, listener.local_addr().unwrap());
+
+    let result = dial(&viewer, &peer, "wrong").await;
+    assert!(matches!(result, Err(ClientError::PermissionDenied(ref reason)) if reason == "Invalid password"));
+}
+
+#[tokio::test]
+async fn test_quic_dialer_key_is_pinned_after_first_session() {
+    let host = device("Host");
+    let viewer = device("Viewer");
+    let (listener, mut incoming, password) = start_host(&host);
+    let peer = discovered(&host, listener.local_addr().unwrap());
+
+    let _session = dial(&viewer, &peer, &password).await.unwrap();
+    incoming.recv().await.unwrap();
+
+    let impostor = LocalDevice {
+        identity: Arc::new(DeviceIdentity::generate().unwrap()),
+        ..viewer.clone()
+    };
+    let result = dial(&impostor, &peer, &password).await;
+    assert!(matches!(result, Err(ClientError::PermissionDenied(_))));
+    assert!(dial(&viewer, &peer, &password).await.is_ok());
+}
@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use genxlink_client_core::connection_manager::{ConnectionEvent, ConnectionManager};
+use genxlink_client_core::device_identity::DeviceIdentity;
+use genxlink_client_core::permission_profiles::Permission;
+use genxlink_client_core::session_resume::{Admission, ResumptionRegistry, SessionSnapshot};
+use genxlink_client_core::signaling_client::{ReconnectPolicy, SignalingClient, SignalingState};
+use genxlink_client_core::system_actions::{issue_ticket, PendingReconnect, ReconnectStore};
+use genxlink_protocol::{DeviceId, SessionId, SignalingEnvelope, SignalingMessage};
+use genxlink_signaling_server::PeerManager;
+use parking_lot::Mutex;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start `server/signaling` on a free port
+async fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(genxlink_signaling_server::serve(listener, PeerManager::new()));
+    address
+}
+
+fn identity() -> Arc<DeviceIdentity> {
+    Arc::new(DeviceIdentity::generate().unwrap())
+}
+
+/// A host on the signaling server: admits controllers, resumes lost sessions
+/// from tokens and answers offers
+struct Host {
+    device_id: DeviceId,
+    registry: Mutex<ResumptionRegistry>,
+    sessions: Mutex<HashMap<DeviceId, SessionId>>,
+    prompts: AtomicUsize,
+    restored: Mutex<Vec<SessionSnapshot>>,
+}
+
+impl Host {
+    async fn start(server: SocketAddr) -> Arc<Self> {
+        let device_id = DeviceId::new();
+        let mut client = SignalingClient::new(device_id.clone(), format!("ws://{}/ws", server)).with_identity(identity());
+        let mut incoming = client.connect().await.unwrap();
+        // Registration goes out first on the same socket, so it's done once this returns
+        client.ping().await.unwrap();
+
+        let host = Arc::new(Self {
+            device_id,
+            registry: Mutex::new(ResumptionRegistry::new()),
+            sessions: Mutex::new(HashMap::new()),
+            prompts: AtomicUsize::new(0),
+            restored: Mutex::new(Vec::new()),
+        });
+        let serving = host.clone();
+        tokio::spawn(async move {
+            while let Some(request) = incoming.recv().await {
+                let Some(reply) = serving.answer(request.message.clone()) else { continue };
+                if client.reply(&request, reply).await.is_err() {
+                    break;
+                }
+            }
+        });
+        host
+    }
+
+    fn answer(&self, message: SignalingMessage) -> Option<SignalingMessage> {
+        match message {
+            SignalingMessage::ConnectionRequest { from, resume_token, .. } => {
+                let admission = self.registry.lock().admit(&from, resume_token.as_deref());
+                let (session_id, token) = match admission {
+                    Admission::Resume(resumed) => {
+                        self.restored.lock().push(resumed.snapshot);
+                        (resumed.session_id, resumed.token)
+                    }
+                    Admission::Prompt { .. } => {
+                        self.prompts.fetch_add(1, Ordering::SeqCst);
+                        let session_id = SessionId::new();
+                        self.sessions.lock().insert(from.clone(), session_id);
+                        let snapshot = SessionSnapshot::new(1, HashSet::from([Permission::ControlDevice]));
+                        (session_id, self.registry.lock().issue(session_id, from, snapshot))
+                    }
+                };
+                Some(SignalingMessage::ConnectionAccepted {
+                    session_id,
+                    from: self.device_id.clone(),
+                    resume_token: Some(token),
+                })
+            }
+            SignalingMessage::PeerLeft { device_id } => {
+                if let Some(session_id) = self.sessions.lock().get(&device_id) {
+                    self.registry.lock().suspend(session_id);
+                }
+                None
+            }
+            SignalingMessage::Offer { sdp, from, to } => Some(SignalingMessage::Answer {
+                sdp: format!("answer to {}", sdp),
+                from: to,
+                to: from,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// TCP proxy between the client and the signaling server that can cut
+/// every connection and refuse new ones, like a dropped Wi-Fi link
+struct Proxy {
+    address: SocketAddr,
+    connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    refusing: Arc<AtomicBool>,
+}
+
+impl Proxy {
+    async fn start(upstream: SocketAddr) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let refusing = Arc::new(AtomicBool::new(false));
+
+        let (tracked, refuse) = (connections.clone(), refusing.clone());
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                if refuse.load(Ordering::SeqCst) {
+                    continue;
+                }
+                tracked.lock().push(tokio::spawn(async move {
+                    if let Ok(mut server) = TcpStream::connect(upstream).await {
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                    }
+                }));
+            }
+        });
+
+        Self { address, connections, refusing }
+    }
+
+    fn url(&self) -> String {
+        format!("ws://{}/ws", self.address)
+    }
+
+    /// Cut every open connection and refuse new ones
+    fn go_down(&self) {
+        self.refusing.store(true, Ordering::SeqCst);
+        for connection in self.connections.lock().drain(..) {
+            connection.abort();
+        }
+    }
+
+    fn come_back(&self) {
+        self.refusing.store(false, Ordering::SeqCst);
+    }
+}
+
+fn fast_policy(max_attempts: Option<u32>) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_delay: Duration::from_millis(20),
+        max_delay: Duration::from_millis(200),
+        multiplier: 2.0,
+        jitter: 0.0,
+        max_attempts,
+    }
+}
+
+async fn wait_for_state(states: &mut watch::Receiver<SignalingState>, expected: SignalingState) {
+    tokio::time::timeout(TIMEOUT, states.wait_for(|state| *state == expected))
+        .await
+        .unwrap_or_else(|_| panic!("timed out waiting for {:?}", expected))
+        .unwrap();
+}
+
+/// Next message from a peer, skipping presence notifications
+async fn next_message(incoming: &mut mpsc::UnboundedReceiver<SignalingEnvelope>) -> SignalingMessage {
+    loop {
+        let message = tokio::time::timeout(TIMEOUT, incoming.recv())
+            .await
+            .expect("timed out waiting for a signaling message")
+            .expect("signaling receiver closed")
+            .message;
+        if !matches!(message, SignalingMessage::PeerJoined { .. } | SignalingMessage::PeerLeft { .. }) {
+            return message;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_session_survives_dropped_connection() {
+    let server = start_server().await;
+    let host = Host::start(server).await;
+    let proxy = Proxy::start(server).await;
+
+    let mut client = SignalingClient::new(DeviceId::new(), proxy.url())
+        .with_identity(identity())
+        .with_reconnect_policy(fast_policy(None));
+    let mut incoming = client.connect().await.unwrap();
+    let mut states = client.watch_state();
+
+    client.request_connection(host.device_id.clone()).await.unwrap();
+    let SignalingMessage::ConnectionAccepted { session_id, resume_token: Some(first_token), .. } =
+        next_message(&mut incoming).await
+    else {
+        panic!("expected the host to accept");
+    };
+    assert_eq!(client.resume_token(&host.device_id), Some(first_token.clone()));
+    assert_eq!(host.prompts.load(Ordering::SeqCst), 1);
+
+    // The link drops mid-session; messages sent meanwhile are queued
+    proxy.go_down();
+    wait_for_state(&mut states, SignalingState::Reconnecting).await;
+    client.send_offer("restart".to_string(), host.device_id.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    proxy.come_back();
+    wait_for_state(&mut states, SignalingState::Connected).await;
+
+    // The host re-admits the same session without prompting and restores its state
+    let SignalingMessage::ConnectionAccepted { session_id: resumed_id, resume_token: Some(next_token), .. } =
+        next_message(&mut incoming).await
+    else {
+        panic!("expected the session to be resumed");
+    };
+    assert_eq!(resumed_id, session_id);
+    assert_ne!(next_token, first_token);
+    assert_eq!(host.prompts.load(Ordering::SeqCst), 1);
+    {
+        let restored = host.restored.lock();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].monitor_index, 1);
+        assert!(restored[0].permissions.contains(&Permission::ControlDevice));
+    }
+
+    // The queued offer went out after the resumption
+    match next_message(&mut incoming).await {
+        SignalingMessage::Answer { sdp, .. } => assert_eq!(sdp, "answer to restart"),
+        other => panic!("expected an answer, got {:?}", other),
+    }
+
+    client.close().await;
+    wait_for_state(&mut states, SignalingState::Disconnected).await;
+}
+
+/// Next event from a connection manager, skipping state changes
+async fn next_event(events: &mut mpsc::UnboundedReceiver<ConnectionEvent>) -> ConnectionEvent {
+    loop {
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .expect("timed out waiting for a connection event")
+            .expect("connection events closed");
+        if !matches!(event, ConnectionEvent::StateChanged(_)) {
+            return event;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_connection_manager_resumes_dropped_session() {
+    let server = start_server().await;
+    let proxy = Proxy::start(server).await;
+
+    let (mut host, mut events) = ConnectionManager::new();
+    host.set_signaling_url(format!("ws://{}/ws", server));
+    let host_id = DeviceId::from_string(host.my_connection_id().to_string());
+    let host = Arc::new(host);
+    host.connect_to_signaling().await.unwrap();
+
+    let controller_id = DeviceId::new();
+    let mut client = SignalingClient::new(controller_id.clone(), proxy.url())
+        .with_identity(identity())
+        .with_reconnect_policy(fast_policy(None));
+    let mut incoming = client.connect().await.unwrap();
+    let mut states = client.watch_state();
+    wait_for_peer(&client, &host_id).await;
+
+    // The user is prompted once and grants control on the second monitor
+    client.request_connection(host_id.clone()).await.unwrap();
+    match next_event(&mut events).await {
+        ConnectionEvent::ConnectionRequested(id) => assert_eq!(id, controller_id.0),
+        other => panic!("expected a connection request, got {:?}", other),
+    }
+    let snapshot = SessionSnapshot::new(1, HashSet::from([Permission::ControlDevice]));
+    let session_id = host.accept_connection(&controller_id.0, snapshot).await.unwrap();
+    let SignalingMessage::ConnectionAccepted { resume_token: Some(first_token), .. } = next_message(&mut incoming).await else {
+        panic!("expected the host to accept");
+    };
+
+    // The controller's link drops and comes back
+    proxy.go_down();
+    wait_for_state(&mut states, SignalingState::Reconnecting).await;
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    proxy.come_back();
+    wait_for_state(&mut states, SignalingState::Connected).await;
+
+    // The host re-admits the same session without prompting and restores its state
+    let SignalingMessage::ConnectionAccepted { session_id: resumed_id, resume_token: Some(next_token), .. } =
+        next_message(&mut incoming).await
+    else {
+        panic!("expected the session to be resumed");
+    };
+    assert_eq!(resumed_id, session_id);
+    assert_ne!(next_token, first_token);
+    loop {
+        match next_event(&mut events).await {
+            ConnectionEvent::SessionResumed { peer, snapshot } => {
+                assert_eq!(peer, controller_id.0);
+                assert_eq!(snapshot.monitor_index, 1);
+                assert!(snapshot.permissions.contains(&Permission::ControlDevice));
+                break;
+            }
+            ConnectionEvent::ConnectionRequested(_) => panic!("the host prompted again"),
+            _ => {}
+        }
+    }
+    assert_eq!(host.connected_peers().await.len(), 1);
+
+    client.close().await;
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_attempts() {
+    let proxy = Proxy::start(start_server().await).await;
+
+    let mut client = SignalingClient::new(DeviceId::new(), proxy.url())
+        .with_identity(identity())
+        .with_reconnect_policy(fast_policy(Some(3)));
+    let mut incoming = client.connect().await.unwrap();
+    let mut states = client.watch_state();
+
+    proxy.go_down();
+    tokio::time::timeout(TIMEOUT, states.wait_for(|state| matches!(state, SignalingState::Failed(_))))
+        .await
+        .expect("timed out waiting for the client to give up")
+        .unwrap();
+    assert!(tokio::time::timeout(TIMEOUT, incoming.recv()).await.unwrap().is_none());
+}
+
+/// Wait until `device_id` is registered on the server
+async fn wait_for_peer(client: &SignalingClient, device_id: &DeviceId) {
+    tokio::time::timeout(TIMEOUT, async {
+        while !client.list_peers().await.unwrap().iter().any(|peer| peer.device_id == *device_id) {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("timed out waiting for the peer to register");
+}
+
+#[tokio::test]
+async fn test_host_admits_controller_after_restart() {
+    let server = start_server().await;
+    let store = ReconnectStore::new(std::env::temp_dir().join(format!("genxlink-reconnect-{}.json", uuid::Uuid::new_v4())));
+    let controller_id = DeviceId::new();
+
+    // The host saved a ticket for this controller before restarting
+    let (mut host, _events) = ConnectionManager::new();
+    host.set_signaling_url(format!("ws://{}/ws", server));
+    host.set_reconnect_store(store.clone());
+    let host_id = DeviceId::from_string(host.my_connection_id().to_string());
+    let ticket = issue_ticket(host.my_connection_id(), Duration::from_secs(60));
+    store
+        .save(&PendingReconnect { controller_id: controller_id.0.clone(), ticket: ticket.clone() })
+        .unwrap();
+
+    let host = Arc::new(host);
+    host.connect_to_signaling().await.unwrap();
+
+    let mut controller = SignalingClient::new(controller_id.clone(), format!("ws://{}/ws", server)).with_identity(identity());
+    let _incoming = controller.connect().await.unwrap();
+    wait_for_peer(&controller, &host_id).await;
+
+    let resume = |token: &str| SignalingMessage::ConnectionRequest {
+        target: host_id.clone(),
+        from: controller_id.clone(),
+        resume_token: Some(token.to_string()),
+    };
+
+    // A wrong ticket is refused and leaves the real one in place
+    match controller.request(resume("wrong")).await.unwrap() {
+        SignalingMessage::ConnectionRejected { .. } => {}
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+    match controller.request(resume(&ticket.token)).await.unwrap() {
+        SignalingMessage::ConnectionAccepted { from, .. } => assert_eq!(from, host_id),
+        other => panic!("expected the host to accept, got {:?}", other),
+    }
+
+    // The ticket is single use
+    assert!(store.load().is_none());
+    match controller.request(resume(&ticket.token)).await.unwrap() {
+        SignalingMessage::ConnectionRejected { .. } => {}
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use genxlink_client_core::device_identity::DeviceIdentity;
+use genxlink_client_core::signaling_client::{SignalingClient, SignalingState};
+use genxlink_client_core::ClientError;
+use genxlink_protocol::{DeviceId, SessionId, SignalingEnvelope, SignalingErrorCode, SignalingMessage};
+use genxlink_signaling_server::{PeerManager, SignalingConfig};
+use tokio::sync::mpsc;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start `server/signaling` on a free port
+async fn start_server(config: SignalingConfig) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(genxlink_signaling_server::serve(listener, PeerManager::with_config(config)));
+    url
+}
+
+/// A client with a throwaway device key
+fn client(device_id: DeviceId, url: &str) -> SignalingClient {
+    SignalingClient::new(device_id, url.to_string()).with_identity(Arc::new(DeviceIdentity::generate().unwrap()))
+}
+
+/// Connect a client and wait until the server has registered it
+async fn connect(url: &str) -> (SignalingClient, DeviceId, mpsc::UnboundedReceiver<SignalingEnvelope>) {
+    let device_id = DeviceId::new();
+    let mut client = client(device_id.clone(), url);
+    let incoming = client.connect().await.unwrap();
+    // Registration goes out first on the same socket, so it's done once this returns
+    client.ping().await.unwrap();
+    (client, device_id, incoming)
+}
+
+/// Next request from a peer, skipping presence notifications
+async fn next_request(incoming: &mut mpsc::UnboundedReceiver<SignalingEnvelope>) -> SignalingEnvelope {
+    loop {
+        let envelope = tokio::time::timeout(TIMEOUT, incoming.recv())
+            .await
+            .expect("timed out waiting for a request")
+            .expect("signaling receiver closed");
+        if envelope.id.is_some() {
+            return envelope;
+        }
+    }
+}
+
+fn error_code(result: Result<impl std::fmt::Debug, ClientError>) -> SignalingErrorCode {
+    match result {
+        Err(ClientError::SignalingError { code, .. }) => code,
+        other => panic!("expected a signaling error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_list_peers() {
+    let url = start_server(SignalingConfig::default()).await;
+    let (controller, _, _controller_incoming) = connect(&url).await;
+    let (_host, host_id, _host_incoming) = connect(&url).await;
+
+    let peers = controller.list_peers().await.unwrap();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].device_id, host_id);
+}
+
+#[tokio::test]
+async fn test_open_session_returns_the_hosts_decision() {
+    let url = start_server(SignalingConfig::default()).await;
+    let (controller, controller_id, _controller_incoming) = connect(&url).await;
+    let (host, host_id, mut host_incoming) = connect(&url).await;
+
+    let session_id = SessionId::new();
+    let answering_host = host_id.clone();
+    tokio::spawn(async move {
+        // Admit the first request, turn down the second
+        for admit in [true, false] {
+            let request = next_request(&mut host_incoming).await;
+            let SignalingMessage::ConnectionRequest { from, .. } = &request.message else {
+                panic!("expected a connection request, got {:?}", request);
+            };
+            assert_eq!(*from, controller_id);
+            let reply = if admit {
+                SignalingMessage::ConnectionAccepted {
+                    session_id,
+                    from: answering_host.clone(),
+                    resume_token: Some("token".to_string()),
+                }
+            } else {
+                SignalingMessage::ConnectionRejected {
+                    reason: "Declined by user".to_string(),
+                    from: answering_host.clone(),
+                }
+            };
+            host.reply(&request, reply).await.unwrap();
+        }
+    });
+
+    assert_eq!(controller.open_session(host_id.clone()).await.unwrap(), session_id);
+    assert_eq!(controller.resume_token(&host_id).as_deref(), Some("token"));
+
+    match controller.open_session(host_id.clone()).await {
+        Err(ClientError::PermissionDenied(reason)) => assert_eq!(reason, "Declined by user"),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+    assert_eq!(controller.resume_token(&host_id), None);
+}
+
+#[tokio::test]
+async fn test_concurrent_offers_get_their_own_answers() {
+    let url = start_server(SignalingConfig::default()).await;
+    let (controller, _, _controller_incoming) = connect(&url).await;
+    let (host, host_id, mut host_incoming) = connect(&url).await;
+
+    tokio::spawn(async move {
+        let first = next_request(&mut host_incoming).await;
+        let second = next_request(&mut host_incoming).await;
+        for offer in [second, first] {
+            let SignalingMessage::Offer { sdp, from, .. } = &offer.message else {
+                panic!("expected an offer, got {:?}", offer);
+            };
+            let answer = SignalingMessage::Answer {
+                sdp: format!("answer to {}", sdp),
+                from: DeviceId::new(),
+                to: from.clone(),
+            };
+            host.reply(&offer, answer).await.unwrap();
+        }
+    });
+
+    let (audio, video) = tokio::join!(
+        controller.negotiate("audio".to_string(), host_id.clone()),
+        controller.negotiate("video".to_string(), host_id.clone()),
+    );
+    assert_eq!(audio.unwrap(), "answer to audio");
+    assert_eq!(video.unwrap(), "answer to video");
+}
+
+#[tokio::test]
+async fn test_server_errors_are_typed() {
+    let url = start_server(SignalingConfig::default()).await;
+    let (controller, _, _controller_incoming) = connect(&url).await;
+
+    assert_eq!(error_code(controller.open_session(DeviceId::new()).await), SignalingErrorCode::PeerNotFound);
+}
+
+#[tokio::test]
+async fn test_unanswered_request_times_out() {
+    let url = start_server(SignalingConfig::default()).await;
+    let mut controller = client(DeviceId::new(), &url).with_request_timeout(Duration::from_millis(200));
+    let _controller_incoming = controller.connect().await.unwrap();
+    let (_host, host_id, mut host_incoming) = connect(&url).await;
+
+    assert_eq!(error_code(controller.open_session(host_id).await), SignalingErrorCode::Timeout);
+
+    // The host saw the request; it just never answered
+    assert!(matches!(next_request(&mut host_incoming).await.message, SignalingMessage::ConnectionRequest { .. }));
+}
+
+#[tokio::test]
+async fn test_relay_timeout_reaches_the_requester() {
+    let url = start_server(SignalingConfig {
+        relay_timeout: Duration::from_millis(200),
+        ..SignalingConfig::default()
+    }).await;
+    let (controller, _, _controller_incoming) = connect(&url).await;
+    let (_host, host_id, _host_incoming) = connect(&url).await;
+
+    let sent_at = std::time::Instant::now();
+    assert_eq!(error_code(controller.negotiate("v=0".to_string(), host_id).await), SignalingErrorCode::Timeout);
+    assert!(sent_at.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_registration_with_another_key_fails_the_client() {
+    let url = start_server(SignalingConfig::default()).await;
+    let (_owner, owner_id, _owner_incoming) = connect(&url).await;
+
+    let mut impostor = client(owner_id, &url);
+    let mut incoming = impostor.connect().await.unwrap();
+    let mut states = impostor.watch_state();
+    tokio::time::timeout(TIMEOUT, states.wait_for(|state| matches!(state, SignalingState::Failed(_))))
+        .await
+        .expect("timed out waiting for the registration to be refused")
+        .unwrap();
+    assert!(tokio::time::timeout(TIMEOUT, incoming.recv()).await.unwrap().is_none());
+}
@@ -1,4 +1,5 @@
 use genxlink_client_core::webrtc::{WebRTCManager, WebRTCConfig, ConnectionState};
+use genxlink_protocol::{IceConfiguration, IceServer, IceTransportPolicy};
 
 #[tokio::test]
 async fn test_webrtc_manager_creation() {
@@ -67,3 +68,75 @@ async fn test_connection_close() {
     let state = manager.get_state().await;
     assert_eq!(state, ConnectionState::Closed);
 }
+
+fn ice_ufrag(sdp: &str) -> Option<&str> {
+    sdp.lines().find_map(|line| line.strip_prefix("a=ice-ufrag:"))
+}
+
+#[tokio::test]
+async fn test_ice_restart_offer() {
+    let mut offerer = WebRTCManager::new("test-device-ice1".to_string(), WebRTCConfig::default());
+    let mut answerer = WebRTCManager::new("test-device-ice2".to_string(), WebRTCConfig::default());
+    offerer.initialize().await.expect("Failed to initialize");
+    answerer.initialize().await.expect("Failed to initialize");
+    offerer.create_data_channel("control").await.expect("Failed to create data channel");
+    
+    let offer = offerer.create_offer().await.expect("Failed to create offer");
+    let answer = answerer.create_answer(offer.clone()).await.expect("Failed to create answer");
+    offerer.set_remote_answer(answer).await.expect("Failed to set answer");
+    
+    let restart = offerer.restart_ice().await.expect("ICE restart should succeed");
+    assert!(ice_ufrag(&restart).is_some(), "Restart offer should carry ICE credentials");
+    assert_ne!(ice_ufrag(&restart), ice_ufrag(&offer), "ICE restart should use new credentials");
+    assert_eq!(offerer.get_state().await, ConnectionState::Reconnecting);
+}
+
+fn fingerprint(sdp: &str) -> Option<&str> {
+    sdp.lines().find_map(|line| line.strip_prefix("a=fingerprint:"))
+}
+
+#[tokio::test]
+async fn test_new_ice_servers_replace_live_connection() {
+    let mut offerer = WebRTCManager::new("test-device-ice3".to_string(), WebRTCConfig::default());
+    let mut answerer = WebRTCManager::new("test-device-ice4".to_string(), WebRTCConfig::default());
+    offerer.initialize().await.expect("Failed to initialize");
+    answerer.initialize().await.expect("Failed to initialize");
+    offerer.create_data_channel("control").await.expect("Failed to create data channel");
+    
+    let offer = offerer.create_offer().await.expect("Failed to create offer");
+    let answer = answerer.create_answer(offer.clone()).await.expect("Failed to create answer");
+    offerer.set_remote_answer(answer).await.expect("Failed to set answer");
+    
+    // Fresh credentials need a new connection, offered to the peer
+    let refreshed = IceConfiguration {
+        ice_servers: vec![IceServer {
+            urls: vec!["stun:stun.example.com:3478".to_string()],
+            username: None,
+            credential: None,
+        }],
+        ice_transport_policy: IceTransportPolicy::All,
+        expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
+    };
+    let renewal = offerer.set_ice_configuration(refreshed).await
+        .expect("Replacing the connection should succeed")
+        .expect("A live connection should be renegotiated");
+    assert_ne!(fingerprint(&renewal), fingerprint(&offer), "Renewal should be a new connection");
+    assert!(renewal.contains("m=application"), "Data channels should move to the new connection");
+    
+    let mut replacement = WebRTCManager::new("test-device-ice4".to_string(), WebRTCConfig::default());
+    replacement.initialize().await.expect("Failed to initialize");
+    let answer = replacement.create_answer(renewal).await.expect("Failed to answer renewal");
+    offerer.set_remote_answer(answer).await.expect("Failed to complete renewal");
+    assert_ne!(offerer.get_state().await, ConnectionState::Closed);
+}
+
+#[tokio::test]
+async fn test_ice_servers_before_initialize_need_no_renegotiation() {
+    let mut manager = WebRTCManager::new("test-device-ice5".to_string(), WebRTCConfig::default());
+    let config = IceConfiguration {
+        ice_servers: Vec::new(),
+        ice_transport_policy: IceTransportPolicy::All,
+        expires_at: chrono::Utc::now(),
+    };
+    assert!(manager.set_ice_configuration(config).await.unwrap().is_none());
+}
@@ -164,7 +164,7 @@ impl DevicesPanel {
         
         tokio::spawn(async move {
             let mut manager = discovery_manager.lock().await;
-            manager.stop_discovery();
+            manager.stop_discovery().await;
         });
         
         info!("⏹ Stopped device discovery scanning");
@@ -0,0 +1,67 @@
+//! Command-line subcommands of the `genxlink` binary
+//!
+//! Without a subcommand the GUI starts as usual.
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+
+use genxlink_client_core::config::ServerConfig;
+use genxlink_client_core::network_diagnostics::{DiagnosticsConfig, NetworkDiagnostics};
+
+#[derive(Parser, Debug)]
+#[command(name = "genxlink")]
+#[command(about = "GenXLink Remote Desktop")]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Detect the NAT type and check that the GenXLink servers are reachable
+    Diagnose(DiagnoseArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DiagnoseArgs {
+    /// STUN server as host:port (repeatable; defaults to the public ones)
+    #[arg(long = "stun")]
+    stun_servers: Vec<String>,
+
+    /// Signaling server URL (defaults to the configured server)
+    #[arg(long)]
+    signaling: Option<String>,
+
+    /// Relay server URL (defaults to the configured server)
+    #[arg(long)]
+    relay: Option<String>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+/// Run the connectivity diagnostics and print the report
+pub fn diagnose(args: DiagnoseArgs) -> Result<()> {
+    let mut config = DiagnosticsConfig::for_servers(&ServerConfig::default());
+    if !args.stun_servers.is_empty() {
+        config.stun_servers = args.stun_servers;
+    }
+    if args.signaling.is_some() {
+        config.signaling_url = args.signaling;
+    }
+    if args.relay.is_some() {
+        config.relay_url = args.relay;
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let report = runtime.block_on(NetworkDiagnostics::new(config).run());
+
+    if args.json {
+        println!("{}", report.to_json()?);
+    } else {
+        print!("{}", report);
+    }
+    Ok(())
+}
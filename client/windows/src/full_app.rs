@@ -7,6 +7,9 @@
 //! - Settings and configuration
 //! - Premium features
 
+mod cli;
+
+use clap::Parser;
 use eframe::egui;
 use anyhow::Result;
 use std::time::{Duration, Instant};
@@ -758,6 +761,10 @@ impl GenXLinkApp {
 fn main() -> Result<()> {
     env_logger::init();
     
+    if let Some(cli::Command::Diagnose(args)) = cli::Cli::parse().command {
+        return cli::diagnose(args);
+    }
+    
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1200.0, 800.0])
@@ -430,6 +430,7 @@ impl AccessControlPanel {
             ],
             PermissionCategory::System => vec![
                 Permission::SeeSystemInformation,
+                Permission::KillProcesses,
                 Permission::DrawOnScreen,
                 Permission::CreateTcpTunnels,
             ],
@@ -71,7 +71,7 @@ impl StreamingPanel {
                         self.start_streaming();
                     }
                 }
-                SessionState::Streaming | SessionState::Connected => {
+                SessionState::Streaming | SessionState::Connected | SessionState::Reconnecting => {
                     if ui.button("⏹ Stop Streaming").clicked() {
                         self.stop_streaming();
                     }
@@ -113,6 +113,7 @@ impl StreamingPanel {
             SessionState::GatheringCandidates => ("🟡 Gathering ICE candidates...", egui::Color32::YELLOW),
             SessionState::Connected => ("🟢 Peer connected", egui::Color32::GREEN),
             SessionState::Streaming => ("🟢 Streaming active!", egui::Color32::from_rgb(0, 200, 0)),
+            SessionState::Reconnecting => ("🟡 Network changed, reconnecting...", egui::Color32::YELLOW),
             SessionState::Disconnecting => ("🟡 Disconnecting...", egui::Color32::YELLOW),
             SessionState::Disconnected => ("⚪ Disconnected", egui::Color32::GRAY),
             SessionState::Failed(err) => {
@@ -113,6 +113,12 @@ impl P2PClient {
             genxlink_client_core::webrtc_integration::IntegrationEvent::StreamingStopped => {
                 info!("Streaming stopped");
             }
+            genxlink_client_core::webrtc_integration::IntegrationEvent::Reconnecting { attempt } => {
+                info!("Connection lost, reconnecting (attempt {})", attempt);
+            }
+            genxlink_client_core::webrtc_integration::IntegrationEvent::Reconnected => {
+                info!("Connection recovered");
+            }
             genxlink_client_core::webrtc_integration::IntegrationEvent::Error(error) => {
                 error!("Integration error: {}", error);
             }
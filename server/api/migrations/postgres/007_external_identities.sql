@@ -0,0 +1,9 @@
+-- Users signing in through an external identity provider (LDAP, SSO)
+-- Local accounts keep auth_provider 'local' and no external_id; external
+-- accounts are matched on (auth_provider, external_id) and have no usable
+-- password.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS auth_provider TEXT NOT NULL DEFAULT 'local';
+ALTER TABLE users ADD COLUMN IF NOT EXISTS external_id TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_external_identity ON users(auth_provider, external_id);
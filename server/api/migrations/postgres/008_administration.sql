@@ -0,0 +1,7 @@
+-- Platform administrators for the admin API
+-- Administrators can see every device, session and license; usage reports
+-- aggregate connections per user and day.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT false;
+
+CREATE INDEX IF NOT EXISTS idx_connections_started_at ON connections(started_at);
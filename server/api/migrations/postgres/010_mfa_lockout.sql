@@ -0,0 +1,7 @@
+-- Failed second-factor attempts per account
+-- Counted on the user row rather than per challenge, so signing in again
+-- for a fresh challenge doesn't reset them; reaching the limit locks the
+-- second factor until mfa_locked_until.
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS mfa_failed_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS mfa_locked_until TIMESTAMPTZ;
@@ -0,0 +1,150 @@
+-- Initial database schema for GenXLink API Server (SQLite)
+-- Mirrors migrations/postgres/001_initial_schema.sql. UUIDs are stored as
+-- 16-byte BLOBs, timestamps as RFC 3339 text and JSON documents as text.
+
+CREATE TABLE IF NOT EXISTS users (
+    id BLOB PRIMARY KEY NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    username TEXT UNIQUE NOT NULL,
+    display_name TEXT NOT NULL,
+    avatar_url TEXT,
+    password_hash TEXT NOT NULL,
+    is_active INTEGER DEFAULT 1,
+    is_verified INTEGER DEFAULT 0,
+    subscription_type TEXT DEFAULT 'free' CHECK (subscription_type IN ('free', 'premium', 'enterprise')),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    last_login TEXT,
+    preferences TEXT DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS devices (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE CASCADE,
+    device_id TEXT UNIQUE NOT NULL,
+    device_name TEXT NOT NULL,
+    device_type TEXT NOT NULL CHECK (device_type IN ('desktop', 'laptop', 'server', 'mobile', 'tablet', 'iot', 'unknown')),
+    os_version TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    mac_address TEXT,
+    last_seen TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    is_online INTEGER DEFAULT 0,
+    capabilities TEXT DEFAULT '{}',
+    metadata TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE CASCADE,
+    device_id BLOB REFERENCES devices(id) ON DELETE CASCADE,
+    remote_device_id BLOB REFERENCES devices(id) ON DELETE CASCADE,
+    session_type TEXT NOT NULL CHECK (session_type IN ('remote_control', 'screen_sharing', 'file_transfer', 'audio_streaming', 'multi_session')),
+    started_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    ended_at TEXT,
+    duration_seconds INTEGER,
+    status TEXT DEFAULT 'active' CHECK (status IN ('active', 'ended', 'failed', 'terminated')),
+    connection_quality TEXT,
+    metadata TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS connections (
+    id BLOB PRIMARY KEY NOT NULL,
+    session_id BLOB REFERENCES sessions(id) ON DELETE CASCADE,
+    from_device_id BLOB REFERENCES devices(id) ON DELETE CASCADE,
+    to_device_id BLOB REFERENCES devices(id) ON DELETE CASCADE,
+    connection_type TEXT NOT NULL,
+    started_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    ended_at TEXT,
+    duration_seconds INTEGER,
+    bytes_sent INTEGER DEFAULT 0,
+    bytes_received INTEGER DEFAULT 0,
+    status TEXT DEFAULT 'active' CHECK (status IN ('active', 'ended', 'failed', 'terminated')),
+    error_message TEXT,
+    metadata TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS licenses (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE CASCADE,
+    license_key TEXT UNIQUE NOT NULL,
+    license_type TEXT NOT NULL CHECK (license_type IN ('trial', 'basic', 'pro', 'enterprise')),
+    expires_at TEXT NOT NULL,
+    is_active INTEGER DEFAULT 1,
+    max_devices INTEGER DEFAULT 1,
+    max_concurrent_sessions INTEGER DEFAULT 1,
+    features TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    key_hash TEXT UNIQUE NOT NULL,
+    permissions TEXT DEFAULT '{}',
+    expires_at TEXT,
+    last_used TEXT,
+    is_active INTEGER DEFAULT 1,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS usage_stats (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE CASCADE,
+    session_id BLOB REFERENCES sessions(id) ON DELETE CASCADE,
+    stat_type TEXT NOT NULL CHECK (stat_type IN ('api_calls', 'bandwidth', 'session_duration', 'connections')),
+    value INTEGER NOT NULL,
+    unit TEXT NOT NULL DEFAULT 'count',
+    recorded_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    metadata TEXT DEFAULT '{}'
+);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB REFERENCES users(id) ON DELETE SET NULL,
+    action TEXT NOT NULL,
+    resource_type TEXT NOT NULL,
+    resource_id BLOB,
+    ip_address TEXT,
+    user_agent TEXT,
+    details TEXT DEFAULT '{}',
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_devices_user_id ON devices(user_id);
+CREATE INDEX IF NOT EXISTS idx_devices_is_online ON devices(is_online);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_device_id ON sessions(device_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_status ON sessions(status);
+CREATE INDEX IF NOT EXISTS idx_sessions_started_at ON sessions(started_at);
+CREATE INDEX IF NOT EXISTS idx_connections_session_id ON connections(session_id);
+CREATE INDEX IF NOT EXISTS idx_connections_from_device_id ON connections(from_device_id);
+CREATE INDEX IF NOT EXISTS idx_connections_to_device_id ON connections(to_device_id);
+CREATE INDEX IF NOT EXISTS idx_licenses_user_id ON licenses(user_id);
+CREATE INDEX IF NOT EXISTS idx_api_keys_user_id ON api_keys(user_id);
+CREATE INDEX IF NOT EXISTS idx_usage_stats_user_id ON usage_stats(user_id);
+CREATE INDEX IF NOT EXISTS idx_usage_stats_recorded_at ON usage_stats(recorded_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_user_id ON audit_log(user_id);
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+
+-- Keep updated_at current (recursive triggers are off, so this doesn't loop)
+CREATE TRIGGER IF NOT EXISTS update_users_updated_at AFTER UPDATE ON users FOR EACH ROW
+BEGIN UPDATE users SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
+CREATE TRIGGER IF NOT EXISTS update_devices_updated_at AFTER UPDATE ON devices FOR EACH ROW
+BEGIN UPDATE devices SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
+CREATE TRIGGER IF NOT EXISTS update_sessions_updated_at AFTER UPDATE ON sessions FOR EACH ROW
+BEGIN UPDATE sessions SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
+CREATE TRIGGER IF NOT EXISTS update_connections_updated_at AFTER UPDATE ON connections FOR EACH ROW
+BEGIN UPDATE connections SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
+CREATE TRIGGER IF NOT EXISTS update_licenses_updated_at AFTER UPDATE ON licenses FOR EACH ROW
+BEGIN UPDATE licenses SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
+CREATE TRIGGER IF NOT EXISTS update_api_keys_updated_at AFTER UPDATE ON api_keys FOR EACH ROW
+BEGIN UPDATE api_keys SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
@@ -0,0 +1,16 @@
+-- Password credentials
+-- Tracks when passwords change and stores single-use password reset tokens.
+-- Only a SHA-256 hash of each reset token is kept.
+
+ALTER TABLE users ADD COLUMN password_changed_at TEXT;
+
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user_id ON password_reset_tokens(user_id);
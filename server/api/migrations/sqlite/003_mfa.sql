@@ -0,0 +1,18 @@
+-- Two-factor authentication
+-- TOTP secrets live on the user row; recovery codes are stored as SHA-256 hashes.
+
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled INTEGER NOT NULL DEFAULT 0;
+-- Last accepted TOTP time step, prevents code replay
+ALTER TABLE users ADD COLUMN totp_last_step INTEGER;
+
+CREATE TABLE IF NOT EXISTS mfa_recovery_codes (
+    id BLOB PRIMARY KEY NOT NULL,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (user_id, code_hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_mfa_recovery_codes_user_id ON mfa_recovery_codes(user_id);
@@ -0,0 +1,7 @@
+-- Personal API keys
+-- Keys are stored as SHA-256 hashes; the clear-text prefix lets users tell them apart.
+-- `permissions` holds a JSON array of scope names such as "devices:read".
+
+ALTER TABLE api_keys ADD COLUMN key_prefix TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_expires_at ON api_keys(expires_at) WHERE is_active = 1;
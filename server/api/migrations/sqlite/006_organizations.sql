@@ -0,0 +1,82 @@
+-- Organizations with members, invitations, teams and device groups
+-- Devices can be enrolled in an organization. Admins reach every device of
+-- their organization; other members reach the devices of groups granted to
+-- one of their teams.
+
+CREATE TABLE IF NOT EXISTS organizations (
+    id BLOB PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    created_by BLOB REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS organization_members (
+    org_id BLOB NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'operator', 'member')),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (org_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS organization_invitations (
+    id BLOB PRIMARY KEY NOT NULL,
+    org_id BLOB NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'operator', 'member')),
+    token_hash TEXT UNIQUE NOT NULL,
+    invited_by BLOB REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TEXT NOT NULL,
+    accepted_at TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS teams (
+    id BLOB PRIMARY KEY NOT NULL,
+    org_id BLOB NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (org_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS team_members (
+    team_id BLOB NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    user_id BLOB NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (team_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS device_groups (
+    id BLOB PRIMARY KEY NOT NULL,
+    org_id BLOB NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    description TEXT,
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (org_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS device_group_devices (
+    group_id BLOB NOT NULL REFERENCES device_groups(id) ON DELETE CASCADE,
+    device_id BLOB NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+    PRIMARY KEY (group_id, device_id)
+);
+
+CREATE TABLE IF NOT EXISTS device_group_grants (
+    group_id BLOB NOT NULL REFERENCES device_groups(id) ON DELETE CASCADE,
+    team_id BLOB NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    access TEXT NOT NULL CHECK (access IN ('view', 'control')),
+    created_at TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    PRIMARY KEY (group_id, team_id)
+);
+
+ALTER TABLE devices ADD COLUMN org_id BLOB REFERENCES organizations(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_organization_members_user_id ON organization_members(user_id);
+CREATE INDEX IF NOT EXISTS idx_organization_invitations_org_id ON organization_invitations(org_id);
+CREATE INDEX IF NOT EXISTS idx_team_members_user_id ON team_members(user_id);
+CREATE INDEX IF NOT EXISTS idx_device_group_devices_device_id ON device_group_devices(device_id);
+CREATE INDEX IF NOT EXISTS idx_device_group_grants_team_id ON device_group_grants(team_id);
+CREATE INDEX IF NOT EXISTS idx_devices_org_id ON devices(org_id);
+
+CREATE TRIGGER IF NOT EXISTS update_organizations_updated_at AFTER UPDATE ON organizations FOR EACH ROW
+BEGIN UPDATE organizations SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id; END;
@@ -0,0 +1,5 @@
+-- Relay-only connectivity per organization
+-- Sessions involving a device of an organization with force_relay set are
+-- issued TURN servers only and must not use direct candidates.
+
+ALTER TABLE organizations ADD COLUMN force_relay INTEGER NOT NULL DEFAULT 0;
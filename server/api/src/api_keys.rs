@@ -0,0 +1,249 @@
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::password;
+
+/// Prefix identifying API keys in the Authorization header
+pub const API_KEY_PREFIX: &str = "gxl_";
+
+/// Characters of the key kept in clear text so users can tell keys apart
+const DISPLAY_PREFIX_LENGTH: usize = 12;
+
+/// Longest lifetime a key can be created with
+pub const MAX_API_KEY_LIFETIME_DAYS: u32 = 365;
+
+/// Permission granted to an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+    #[serde(rename = "devices:read")]
+    DevicesRead,
+    #[serde(rename = "devices:write")]
+    DevicesWrite,
+    #[serde(rename = "sessions:read")]
+    SessionsRead,
+    #[serde(rename = "sessions:write")]
+    SessionsWrite,
+    #[serde(rename = "licenses:read")]
+    LicensesRead,
+    #[serde(rename = "orgs:read")]
+    OrgsRead,
+    /// Platform administration; only administrators can create such keys
+    #[serde(rename = "admin:read")]
+    AdminRead,
+    #[serde(rename = "admin:write")]
+    AdminWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 9] = [
+        Scope::ProfileRead,
+        Scope::DevicesRead,
+        Scope::DevicesWrite,
+        Scope::SessionsRead,
+        Scope::SessionsWrite,
+        Scope::LicensesRead,
+        Scope::OrgsRead,
+        Scope::AdminRead,
+        Scope::AdminWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProfileRead => "profile:read",
+            Scope::DevicesRead => "devices:read",
+            Scope::DevicesWrite => "devices:write",
+            Scope::SessionsRead => "sessions:read",
+            Scope::SessionsWrite => "sessions:write",
+            Scope::LicensesRead => "licenses:read",
+            Scope::OrgsRead => "orgs:read",
+            Scope::AdminRead => "admin:read",
+            Scope::AdminWrite => "admin:write",
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self, Scope::AdminRead | Scope::AdminWrite)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown scope: {}", s))
+    }
+}
+
+/// How a request was authenticated, stored in the request extensions
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// Interactive login (JWT) within a login session
+    Session { session_id: Uuid },
+    /// Personal API key with a fixed set of scopes
+    ApiKey { id: Uuid, scopes: Vec<Scope> },
+}
+
+impl Credential {
+    /// Whether this credential may call `method path` (path relative to `/api`)
+    pub fn permits(&self, method: &Method, path: &str) -> bool {
+        match self {
+            Credential::Session { .. } => true,
+            Credential::ApiKey { scopes, .. } => {
+                required_scope(method, path).is_some_and(|scope| scopes.contains(&scope))
+            }
+        }
+    }
+}
+
+/// Scope an API key needs for an endpoint
+///
+/// Endpoints not listed here (key management, password and MFA settings)
+/// are only reachable with an interactive login.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (&Method::GET, ["profile"]) => Some(Scope::ProfileRead),
+        (&Method::GET, ["devices"]) => Some(Scope::DevicesRead),
+        (&Method::POST, ["devices"]) => Some(Scope::DevicesWrite),
+        (&Method::POST, ["devices", _, "status"]) => Some(Scope::DevicesWrite),
+        (&Method::GET, ["sessions"] | ["sessions", _, "ice-servers"]) => Some(Scope::SessionsRead),
+        (&Method::POST, ["sessions"]) => Some(Scope::SessionsWrite),
+        (&Method::POST, ["sessions", _, "end"]) => Some(Scope::SessionsWrite),
+        (&Method::GET, ["stats", "usage"]) => Some(Scope::SessionsRead),
+        (&Method::GET, ["license", "status"]) => Some(Scope::LicensesRead),
+        (&Method::GET, ["orgs"] | ["orgs", _] | ["orgs", _, "teams" | "device-groups" | "devices" | "sessions"]) => {
+            Some(Scope::OrgsRead)
+        }
+        (&Method::GET, ["admin", ..]) => Some(Scope::AdminRead),
+        (&Method::POST, ["admin", ..]) => Some(Scope::AdminWrite),
+        _ => None,
+    }
+}
+
+/// Parse and de-duplicate requested scopes
+pub fn parse_scopes(scopes: &[String]) -> Result<Vec<Scope>, String> {
+    let mut parsed = Vec::new();
+    for scope in scopes {
+        let scope: Scope = scope.parse()?;
+        if !parsed.contains(&scope) {
+            parsed.push(scope);
+        }
+    }
+    if parsed.is_empty() {
+        return Err("At least one scope is required".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Scopes stored in the `permissions` column (a JSON array of scope names)
+///
+/// Unknown entries are ignored so a removed scope can't grant anything.
+pub fn scopes_from_permissions(permissions: &serde_json::Value) -> Vec<Scope> {
+    permissions
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|value| value.as_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn scopes_to_permissions(scopes: &[Scope]) -> serde_json::Value {
+    serde_json::Value::Array(
+        scopes
+            .iter()
+            .map(|scope| serde_json::Value::String(scope.as_str().to_string()))
+            .collect(),
+    )
+}
+
+/// Newly generated key
+pub struct GeneratedKey {
+    /// Full key, shown to the user once
+    pub key: String,
+    /// Clear-text prefix for display
+    pub prefix: String,
+    /// SHA-256 hash stored in the database
+    pub hash: String,
+}
+
+pub fn generate_api_key() -> GeneratedKey {
+    let key = format!("{}{}", API_KEY_PREFIX, password::generate_token());
+    GeneratedKey {
+        prefix: key[..DISPLAY_PREFIX_LENGTH].to_string(),
+        hash: password::hash_token(&key),
+        key,
+    }
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_parsing() {
+        assert_eq!("devices:read".parse::<Scope>().unwrap(), Scope::DevicesRead);
+        assert!("devices:admin".parse::<Scope>().is_err());
+
+        let scopes = parse_scopes(&["sessions:write".to_string(), "sessions:write".to_string()]).unwrap();
+        assert_eq!(scopes, vec![Scope::SessionsWrite]);
+        assert!(parse_scopes(&[]).is_err());
+
+        let stored = scopes_to_permissions(&[Scope::DevicesRead, Scope::SessionsWrite]);
+        assert_eq!(stored, serde_json::json!(["devices:read", "sessions:write"]));
+        assert_eq!(scopes_from_permissions(&stored), vec![Scope::DevicesRead, Scope::SessionsWrite]);
+        assert!(scopes_from_permissions(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_credential_permits() {
+        let key = Credential::ApiKey {
+            id: Uuid::new_v4(),
+            scopes: vec![Scope::DevicesRead],
+        };
+        assert!(key.permits(&Method::GET, "/devices"));
+        assert!(!key.permits(&Method::POST, "/devices"));
+        assert!(!key.permits(&Method::POST, "/devices/abc/status"));
+        assert!(!key.permits(&Method::GET, "/keys"));
+        assert!(!key.permits(&Method::POST, "/auth/change-password"));
+
+        assert!(Credential::Session { session_id: Uuid::new_v4() }.permits(&Method::GET, "/keys"));
+        assert_eq!(required_scope(&Method::POST, "/sessions/123/end"), Some(Scope::SessionsWrite));
+        assert_eq!(required_scope(&Method::GET, "/sessions/123/ice-servers"), Some(Scope::SessionsRead));
+        assert_eq!(required_scope(&Method::GET, "/orgs/123/devices"), Some(Scope::OrgsRead));
+        assert_eq!(required_scope(&Method::GET, "/orgs/123/invitations"), None);
+        assert_eq!(required_scope(&Method::POST, "/orgs"), None);
+        assert_eq!(required_scope(&Method::GET, "/admin/usage"), Some(Scope::AdminRead));
+        assert_eq!(required_scope(&Method::POST, "/admin/sessions/123/disconnect"), Some(Scope::AdminWrite));
+    }
+
+    #[test]
+    fn test_generate_api_key() {
+        let generated = generate_api_key();
+        assert!(is_api_key(&generated.key));
+        assert!(generated.key.starts_with(&generated.prefix));
+        assert_eq!(generated.hash, password::hash_token(&generated.key));
+        assert!(!is_api_key("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+}
@@ -4,20 +4,45 @@ use axum::{
     http::{header, StatusCode, request::Parts},
     middleware::Next,
     response::Response,
-    Json,
     async_trait,
 };
-use bcrypt::{hash, verify, DEFAULT_COST};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::sync::Arc;
 use std::time::SystemTime;
 use time::{Duration, OffsetDateTime};
+use tokio::sync::mpsc;
 use tracing::{info, error, warn};
 use uuid::Uuid;
 
-use crate::models::User;
-use crate::db::Database;
+use crate::api_keys::{self, Credential, Scope};
+use crate::models::{ApiKey, MfaSettings, User};
+use crate::db::{Database, RefreshOutcome};
+use crate::jwt_keys::JwtKeys;
+use crate::ldap_auth::{DirectoryAuth, DirectoryOutcome};
+use crate::oidc::{self, OidcOutcome, OidcProvider};
+use crate::password;
+use crate::totp;
+
+/// How long a password reset token stays valid
+const RESET_TOKEN_LIFETIME: chrono::Duration = chrono::Duration::hours(1);
+
+/// How long an MFA challenge token stays valid
+const MFA_CHALLENGE_LIFETIME: Duration = Duration::minutes(5);
+
+/// Wrong codes per account before the second factor is locked, across
+/// challenges so signing in again doesn't earn new attempts
+const MAX_MFA_ATTEMPTS: i32 = 5;
+
+/// How long the second factor stays locked after too many wrong codes
+const MFA_LOCKOUT: chrono::Duration = chrono::Duration::minutes(15);
+
+const MFA_PURPOSE: &str = "mfa";
+
+/// How long an access token stays valid; clients renew it with a refresh token
+const ACCESS_TOKEN_LIFETIME: Duration = Duration::minutes(15);
+
+/// Absolute lifetime of a login session, regardless of refreshes
+const SESSION_LIFETIME: chrono::Duration = chrono::Duration::days(30);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -25,6 +50,18 @@ pub struct Claims {
     pub email: String,
     pub username: String,
     pub subscription_type: String,
+    /// Login session the token belongs to
+    pub sid: String,
+    pub exp: i64,
+    pub iat: i64,
+}
+
+/// Claims of the short-lived token handed out between password and MFA steps
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaChallengeClaims {
+    pub sub: String, // User ID
+    pub purpose: String,
+    pub jti: String,
     pub exp: i64,
     pub iat: i64,
 }
@@ -50,6 +87,43 @@ pub struct AuthResponse {
     pub user: Option<User>,
     pub token: Option<String>,
     pub expires_at: Option<String>,
+    /// Challenge token for the second login step, set when MFA is required
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa_token: Option<String>,
+    /// Single-use token for obtaining the next access token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcAuthorizeRequest {
+    /// Where the identity provider sends the browser back, usually a
+    /// loopback address the client listens on
+    pub redirect_uri: String,
+    /// S256 PKCE challenge; the client keeps the verifier
+    pub code_challenge: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcAuthorizeResponse {
+    pub success: bool,
+    pub message: String,
+    /// Identity provider page to open in the browser
+    pub authorization_url: Option<String>,
+    /// Value the redirect must carry back
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+    pub code_verifier: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,17 +132,154 @@ pub struct PasswordChangeRequest {
     pub new_password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaDisableRequest {
+    pub password: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnrollResponse {
+    pub success: bool,
+    pub message: String,
+    pub secret: Option<String>,
+    pub otpauth_uri: Option<String>,
+    /// Provisioning URI as an SVG QR code
+    pub qr_code_svg: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub success: bool,
+    pub message: String,
+    /// Plaintext codes, only ever shown once
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaStatusResponse {
+    pub totp_enabled: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Scope names, e.g. `devices:read`
+    pub scopes: Vec<String>,
+    /// Lifetime in days; keys without one never expire
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyResponse {
+    pub success: bool,
+    pub message: String,
+    /// Full key, only returned at creation
+    pub key: Option<String>,
+    pub api_key: Option<ApiKey>,
+}
+
+/// Password reset token issued for delivery to the account owner
+#[derive(Debug, Clone)]
+pub struct PasswordResetIssued {
+    pub user_id: Uuid,
+    pub email: String,
+    /// Plaintext token; only its hash is stored
+    pub token: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl AuthResponse {
+    fn failure(message: &str) -> Self {
+        AuthResponse {
+            success: false,
+            message: message.to_string(),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
+        }
+    }
+}
+
+impl OidcAuthorizeResponse {
+    fn failure(message: &str) -> Self {
+        OidcAuthorizeResponse {
+            success: false,
+            message: message.to_string(),
+            authorization_url: None,
+            state: None,
+        }
+    }
+}
+
+impl RecoveryCodesResponse {
+    fn failure(message: &str) -> Self {
+        RecoveryCodesResponse {
+            success: false,
+            message: message.to_string(),
+            recovery_codes: Vec::new(),
+        }
+    }
+}
+
 pub struct AuthService {
-    jwt_secret: String,
+    keys: JwtKeys,
     db: Database,
+    reset_notifier: Option<mpsc::UnboundedSender<PasswordResetIssued>>,
+    /// Directory checked before local accounts on login
+    directory: Option<DirectoryAuth>,
+    /// OpenID Connect identity provider for single sign-on
+    oidc: Option<OidcProvider>,
 }
 
 impl AuthService {
-    pub fn new(db: Database) -> Self {
-        let jwt_secret = env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your-super-secret-jwt-key-change-in-production".to_string());
-        
-        AuthService { jwt_secret, db }
+    pub fn new(db: Database, keys: JwtKeys) -> Self {
+        AuthService { keys, db, reset_notifier: None, directory: None, oidc: None }
+    }
+
+    /// Deliver issued password reset tokens (e.g. to a mailer) through `notifier`
+    pub fn with_reset_notifier(mut self, notifier: mpsc::UnboundedSender<PasswordResetIssued>) -> Self {
+        self.reset_notifier = Some(notifier);
+        self
+    }
+
+    /// Sign users in against an LDAP/Active Directory server
+    pub fn with_directory(mut self, directory: DirectoryAuth) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Offer single sign-on through an OpenID Connect provider
+    pub fn with_oidc(mut self, oidc: OidcProvider) -> Self {
+        self.oidc = Some(oidc);
+        self
     }
 
     pub async fn register(&self, request: RegisterRequest) -> Result<AuthResponse> {
@@ -80,9 +291,15 @@ impl AuthService {
                 user: None,
                 token: None,
                 expires_at: None,
+                mfa_token: None,
+                refresh_token: None,
             });
         }
 
+        if let Err(message) = password::validate_new_password(&request.password) {
+            return Ok(AuthResponse::failure(&message));
+        }
+
         // Check if user already exists
         if let Some(_existing_user) = self.db.get_user_by_email(&request.email).await? {
             return Ok(AuthResponse {
@@ -91,11 +308,13 @@ impl AuthService {
                 user: None,
                 token: None,
                 expires_at: None,
+                mfa_token: None,
+                refresh_token: None,
             });
         }
 
         // Hash password
-        let password_hash = hash(&request.password, DEFAULT_COST)?;
+        let password_hash = password::hash_password(&request.password).await?;
 
         // Create user
         let user = User {
@@ -107,27 +326,17 @@ impl AuthService {
             is_active: true,
             is_verified: false,
             subscription_type: "free".to_string(),
+            auth_provider: "local".to_string(),
+            is_admin: false,
             created_at: SystemTime::now().into(),
             updated_at: SystemTime::now().into(),
             last_login: None,
             preferences: serde_json::json!({}),
         };
 
-        // Note: In a real implementation, you'd store the password hash in the database
-        // For now, we'll create the user without password storage
-        let created_user = self.db.create_user(&user).await?;
+        let created_user = self.db.create_user(&user, &password_hash).await?;
 
-        // Generate JWT token
-        let token = self.generate_token(&created_user)?;
-        let expires_at = OffsetDateTime::now_utc() + Duration::days(7);
-
-        Ok(AuthResponse {
-            success: true,
-            message: "User registered successfully".to_string(),
-            user: Some(created_user),
-            token: Some(token),
-            expires_at: Some(expires_at.to_string()),
-        })
+        self.start_session(created_user, "User registered successfully").await
     }
 
     pub async fn login(&self, request: LoginRequest) -> Result<AuthResponse> {
@@ -139,116 +348,649 @@ impl AuthService {
                 user: None,
                 token: None,
                 expires_at: None,
+                mfa_token: None,
+                refresh_token: None,
             });
         }
 
-        // Get user from database
-        let user = match self.db.get_user_by_email(&request.email).await? {
-            Some(user) => user,
+        if let Some(directory) = &self.directory {
+            match directory.authenticate(&request.email, &request.password).await {
+                Ok(DirectoryOutcome::Authenticated(entry)) => {
+                    return match directory.sync_user_to_database(&self.db, &entry).await? {
+                        Some(user) => self.finish_login(user).await,
+                        None => Ok(AuthResponse::failure("This directory account can't sign in, contact your administrator")),
+                    };
+                }
+                Ok(DirectoryOutcome::InvalidPassword) => {
+                    warn!("Failed directory login attempt for {}", request.email);
+                    return Ok(AuthResponse::failure("Invalid email or password"));
+                }
+                Ok(DirectoryOutcome::UnknownUser) if directory.allow_local_fallback => {}
+                Ok(DirectoryOutcome::UnknownUser) => {
+                    return Ok(AuthResponse::failure("Invalid email or password"));
+                }
+                Err(e) if directory.allow_local_fallback => {
+                    warn!("Directory login failed, trying local accounts: {}", e);
+                }
+                Err(e) => {
+                    error!("Directory login failed: {}", e);
+                    return Ok(AuthResponse::failure("Sign-in is temporarily unavailable"));
+                }
+            }
+        }
+
+        // Get user from database; directory users have no usable local password
+        let (user, password_hash) = match self.db.get_user_credentials(&request.email).await? {
+            Some(credentials) => credentials,
             None => {
-                return Ok(AuthResponse {
-                    success: false,
-                    message: "Invalid email or password".to_string(),
-                    user: None,
-                    token: None,
-                    expires_at: None,
-                });
+                // Same hashing cost as a real account, so timing doesn't reveal which emails exist
+                password::verify_dummy(&request.password).await;
+                return Ok(AuthResponse::failure("Invalid email or password"));
+            }
+        };
+
+        let verification = password::verify_password(&request.password, &password_hash).await;
+        if !verification.valid {
+            warn!("Failed login attempt for {}", user.email);
+            return Ok(AuthResponse::failure("Invalid email or password"));
+        }
+
+        // Upgrade bcrypt or outdated Argon2 hashes while the plaintext is at hand
+        if verification.needs_rehash && user.is_active {
+            let upgraded = password::hash_password(&request.password).await?;
+            self.db.update_password_hash(user.id, &upgraded, false).await?;
+            info!("Upgraded password hash for {}", user.email);
+        }
+
+        self.finish_login(user).await
+    }
+
+    /// First single sign-on step: where to send the browser
+    pub async fn oidc_authorize(&self, request: OidcAuthorizeRequest) -> Result<OidcAuthorizeResponse> {
+        let Some(provider) = &self.oidc else {
+            return Ok(OidcAuthorizeResponse::failure("Single sign-on is not configured"));
+        };
+        if !provider.accepts_redirect_uri(&request.redirect_uri) {
+            return Ok(OidcAuthorizeResponse::failure("Redirect URI not allowed"));
+        }
+        if !oidc::is_valid_code_challenge(&request.code_challenge) {
+            return Ok(OidcAuthorizeResponse::failure("Invalid code challenge"));
+        }
+
+        match provider.begin(&request.redirect_uri, &request.code_challenge).await {
+            Ok(authorization) => Ok(OidcAuthorizeResponse {
+                success: true,
+                message: "Continue at the identity provider".to_string(),
+                authorization_url: Some(authorization.url),
+                state: Some(authorization.state),
+            }),
+            Err(e) => {
+                error!("Identity provider unavailable: {}", e);
+                Ok(OidcAuthorizeResponse::failure("Single sign-on is temporarily unavailable"))
+            }
+        }
+    }
+
+    /// Second single sign-on step: redeem the code the browser came back
+    /// with and sign the user in
+    pub async fn oidc_callback(&self, request: OidcCallbackRequest) -> Result<AuthResponse> {
+        let Some(provider) = &self.oidc else {
+            return Ok(AuthResponse::failure("Single sign-on is not configured"));
+        };
+
+        match provider.finish(&request.code, &request.state, &request.code_verifier).await {
+            Ok(OidcOutcome::Authenticated(identity)) => match provider.sync_user_to_database(&self.db, &identity).await? {
+                Some(user) => self.finish_login(user).await,
+                None => Ok(AuthResponse::failure("This single sign-on account can't sign in, contact your administrator")),
+            },
+            Ok(OidcOutcome::Rejected(message)) => Ok(AuthResponse::failure(message)),
+            Err(e) => {
+                error!("Single sign-on failed: {}", e);
+                Ok(AuthResponse::failure("Single sign-on is temporarily unavailable"))
             }
+        }
+    }
+
+    /// Continue a login whose password checked out: ask for the second
+    /// factor if enabled, otherwise open the session
+    async fn finish_login(&self, user: User) -> Result<AuthResponse> {
+        if !user.is_active {
+            return Ok(AuthResponse::failure("Account is disabled"));
+        }
+
+        // Second factor required: hand out a challenge instead of a session
+        if self.db.get_mfa_settings(user.id).await?.totp_enabled {
+            let mfa_token = self.generate_mfa_challenge(&user)?;
+            return Ok(AuthResponse {
+                success: false,
+                message: "Two-factor authentication required".to_string(),
+                user: None,
+                token: None,
+                expires_at: None,
+                mfa_token: Some(mfa_token),
+                refresh_token: None,
+            });
+        }
+
+        self.complete_login(user).await
+    }
+
+    /// Second login step: exchange an MFA challenge and a code for a session
+    pub async fn verify_mfa(&self, request: MfaVerifyRequest) -> Result<AuthResponse> {
+        let claims = match self.keys.decode::<MfaChallengeClaims>(&request.mfa_token) {
+            Ok(claims) if claims.purpose == MFA_PURPOSE => claims,
+            _ => return Ok(AuthResponse::failure("Invalid or expired MFA challenge")),
         };
 
-        // Note: In a real implementation, you'd verify the password hash
-        // For now, we'll accept any password (this is insecure!)
-        // TODO: Implement proper password verification
-        
+        let user_id = Uuid::parse_str(&claims.sub)?;
+        let user = match self.db.get_user_by_id(user_id).await? {
+            Some(user) if user.is_active => user,
+            _ => return Ok(AuthResponse::failure("Invalid or expired MFA challenge")),
+        };
+
+        let settings = self.db.get_mfa_settings(user.id).await?;
+        if settings.locked_until.is_some_and(|until| until > chrono::Utc::now()) {
+            return Ok(AuthResponse::failure("Too many attempts, try again later"));
+        }
+        if !self.check_second_factor(user.id, &settings, &request.code).await? {
+            self.db
+                .record_mfa_failure(user.id, MAX_MFA_ATTEMPTS, chrono::Utc::now() + MFA_LOCKOUT)
+                .await?;
+            warn!("Failed MFA attempt for {}", user.email);
+            return Ok(AuthResponse::failure("Invalid authentication code"));
+        }
+
+        if settings.failed_attempts > 0 || settings.locked_until.is_some() {
+            self.db.reset_mfa_failures(user.id).await?;
+        }
+        self.complete_login(user).await
+    }
+
+    async fn complete_login(&self, user: User) -> Result<AuthResponse> {
         // Update last login
         self.db.update_user_last_login(user.id).await?;
 
-        // Generate JWT token
-        let token = self.generate_token(&user)?;
-        let expires_at = OffsetDateTime::now_utc() + Duration::days(7);
-
         info!("User {} logged in successfully", user.email);
 
+        self.start_session(user, "Login successful").await
+    }
+
+    /// Open a login session and issue its first access and refresh tokens
+    async fn start_session(&self, user: User, message: &str) -> Result<AuthResponse> {
+        let session_id = Uuid::new_v4();
+        let refresh_token = password::generate_token();
+        self.db
+            .create_auth_session(
+                user.id,
+                session_id,
+                &password::hash_token(&refresh_token),
+                chrono::Utc::now() + SESSION_LIFETIME,
+            )
+            .await?;
+
+        let (token, expires_at) = self.generate_token(&user, session_id)?;
+
         Ok(AuthResponse {
             success: true,
-            message: "Login successful".to_string(),
+            message: message.to_string(),
             user: Some(user),
             token: Some(token),
             expires_at: Some(expires_at.to_string()),
+            mfa_token: None,
+            refresh_token: Some(refresh_token),
         })
     }
 
-    pub async fn verify_token(&self, token: &str) -> Result<Option<User>> {
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.jwt_secret.as_ref()),
-            &Validation::default(),
-        );
+    fn generate_mfa_challenge(&self, user: &User) -> Result<String> {
+        let now = OffsetDateTime::now_utc();
+        let claims = MfaChallengeClaims {
+            sub: user.id.to_string(),
+            purpose: MFA_PURPOSE.to_string(),
+            jti: Uuid::new_v4().to_string(),
+            exp: (now + MFA_CHALLENGE_LIFETIME).unix_timestamp(),
+            iat: now.unix_timestamp(),
+        };
+
+        self.keys.encode(&claims)
+    }
+
+    /// Check a TOTP code or, failing that, consume a recovery code
+    async fn check_second_factor(&self, user_id: Uuid, settings: &MfaSettings, code: &str) -> Result<bool> {
+        let secret = match (&settings.totp_secret, settings.totp_enabled) {
+            (Some(secret), true) => secret,
+            _ => return Ok(false),
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+        let last_step = settings.totp_last_step.map(|step| step as u64);
+        if let Some(step) = totp::verify_code(secret, code, now, last_step) {
+            // Conditional update, so a racing request can't reuse the same code
+            return self.db.record_totp_step(user_id, step as i64).await;
+        }
+
+        let normalized = totp::normalize_recovery_code(code);
+        if normalized.len() < 10 {
+            return Ok(false);
+        }
+        let used = self.db
+            .consume_recovery_code(user_id, &password::hash_token(&normalized))
+            .await?;
+        if used {
+            info!("Recovery code used for user {}", user_id);
+        }
+        Ok(used)
+    }
 
-        match token_data {
-            Ok(data) => {
-                let user_id = Uuid::parse_str(&data.claims.sub)?;
-                self.db.get_user_by_id(user_id).await
+    /// Start TOTP enrollment with a fresh secret
+    pub async fn enroll_totp(&self, user: &User) -> Result<TotpEnrollResponse> {
+        if self.db.get_mfa_settings(user.id).await?.totp_enabled {
+            return Ok(TotpEnrollResponse {
+                success: false,
+                message: "Two-factor authentication is already enabled".to_string(),
+                secret: None,
+                otpauth_uri: None,
+                qr_code_svg: None,
+            });
+        }
+
+        let secret = totp::generate_secret();
+        self.db.set_pending_totp_secret(user.id, &secret).await?;
+
+        let uri = totp::provisioning_uri(&user.email, &secret);
+        let qr_code_svg = totp::provisioning_qr_svg(&uri)?;
+
+        Ok(TotpEnrollResponse {
+            success: true,
+            message: "Scan the QR code and confirm with a code from your authenticator".to_string(),
+            secret: Some(secret),
+            otpauth_uri: Some(uri),
+            qr_code_svg: Some(qr_code_svg),
+        })
+    }
+
+    /// Confirm TOTP enrollment and issue the first recovery codes
+    pub async fn activate_totp(&self, user_id: Uuid, request: MfaCodeRequest) -> Result<RecoveryCodesResponse> {
+        let settings = self.db.get_mfa_settings(user_id).await?;
+        let secret = match (&settings.totp_secret, settings.totp_enabled) {
+            (_, true) => return Ok(RecoveryCodesResponse::failure("Two-factor authentication is already enabled")),
+            (None, false) => return Ok(RecoveryCodesResponse::failure("Start enrollment first")),
+            (Some(secret), false) => secret,
+        };
+
+        let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+        let step = match totp::verify_code(secret, &request.code, now, None) {
+            Some(step) => step,
+            None => return Ok(RecoveryCodesResponse::failure("Invalid authentication code")),
+        };
+
+        self.db.record_totp_step(user_id, step as i64).await?;
+        self.db.enable_totp(user_id).await?;
+        let recovery_codes = self.issue_recovery_codes(user_id).await?;
+
+        info!("Two-factor authentication enabled for user {}", user_id);
+
+        Ok(RecoveryCodesResponse {
+            success: true,
+            message: "Two-factor authentication enabled".to_string(),
+            recovery_codes,
+        })
+    }
+
+    /// Replace recovery codes; requires a current second factor
+    pub async fn regenerate_recovery_codes(&self, user_id: Uuid, request: MfaCodeRequest) -> Result<RecoveryCodesResponse> {
+        let settings = self.db.get_mfa_settings(user_id).await?;
+        if !settings.totp_enabled {
+            return Ok(RecoveryCodesResponse::failure("Two-factor authentication is not enabled"));
+        }
+        if !self.check_second_factor(user_id, &settings, &request.code).await? {
+            return Ok(RecoveryCodesResponse::failure("Invalid authentication code"));
+        }
+
+        let recovery_codes = self.issue_recovery_codes(user_id).await?;
+        Ok(RecoveryCodesResponse {
+            success: true,
+            message: "Recovery codes regenerated".to_string(),
+            recovery_codes,
+        })
+    }
+
+    /// Turn off two-factor authentication; requires the password and a second factor
+    pub async fn disable_mfa(&self, user_id: Uuid, request: MfaDisableRequest) -> Result<AuthResponse> {
+        let password_hash = self.db.get_password_hash(user_id).await?
+            .ok_or_else(|| anyhow!("User not found"))?;
+        if !password::verify_password(&request.password, &password_hash).await.valid {
+            return Ok(AuthResponse::failure("Password is incorrect"));
+        }
+
+        let settings = self.db.get_mfa_settings(user_id).await?;
+        if !settings.totp_enabled {
+            return Ok(AuthResponse::failure("Two-factor authentication is not enabled"));
+        }
+        if !self.check_second_factor(user_id, &settings, &request.code).await? {
+            return Ok(AuthResponse::failure("Invalid authentication code"));
+        }
+
+        self.db.disable_mfa(user_id).await?;
+        info!("Two-factor authentication disabled for user {}", user_id);
+
+        Ok(AuthResponse {
+            success: true,
+            message: "Two-factor authentication disabled".to_string(),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
+        })
+    }
+
+    pub async fn mfa_status(&self, user_id: Uuid) -> Result<MfaStatusResponse> {
+        let settings = self.db.get_mfa_settings(user_id).await?;
+        let recovery_codes_remaining = if settings.totp_enabled {
+            self.db.count_recovery_codes(user_id).await?
+        } else {
+            0
+        };
+
+        Ok(MfaStatusResponse {
+            totp_enabled: settings.totp_enabled,
+            recovery_codes_remaining,
+        })
+    }
+
+    async fn issue_recovery_codes(&self, user_id: Uuid) -> Result<Vec<String>> {
+        let codes = totp::generate_recovery_codes();
+        let hashes: Vec<String> = codes
+            .iter()
+            .map(|code| password::hash_token(&totp::normalize_recovery_code(code)))
+            .collect();
+        self.db.replace_recovery_codes(user_id, &hashes).await?;
+        Ok(codes)
+    }
+
+    /// Mint a personal API key for `user_id`
+    pub async fn create_api_key(&self, user: &User, request: CreateApiKeyRequest) -> Result<CreateApiKeyResponse> {
+        let failure = |message: String| CreateApiKeyResponse {
+            success: false,
+            message,
+            key: None,
+            api_key: None,
+        };
+
+        let name = request.name.trim();
+        if name.is_empty() || name.len() > 100 {
+            return Ok(failure("Name must be between 1 and 100 characters".to_string()));
+        }
+        let scopes = match api_keys::parse_scopes(&request.scopes) {
+            Ok(scopes) => scopes,
+            Err(message) => return Ok(failure(message)),
+        };
+        if !user.is_admin && scopes.iter().any(Scope::is_admin) {
+            return Ok(failure("Admin scopes require an administrator".to_string()));
+        }
+        let expires_at = match request.expires_in_days {
+            Some(0) => return Ok(failure("Expiry must be at least one day".to_string())),
+            Some(days) if days > api_keys::MAX_API_KEY_LIFETIME_DAYS => {
+                return Ok(failure(format!(
+                    "Expiry can be at most {} days",
+                    api_keys::MAX_API_KEY_LIFETIME_DAYS
+                )))
             }
+            Some(days) => Some(chrono::Utc::now() + chrono::Duration::days(days as i64)),
+            None => None,
+        };
+
+        let generated = api_keys::generate_api_key();
+        let api_key = self.db.create_api_key(&ApiKey {
+            id: Uuid::new_v4(),
+            user_id: user.id,
+            name: name.to_string(),
+            key_prefix: generated.prefix,
+            key_hash: generated.hash,
+            permissions: api_keys::scopes_to_permissions(&scopes),
+            expires_at,
+            last_used: None,
+            is_active: true,
+            created_at: chrono::Utc::now(),
+        }).await?;
+
+        info!("API key {} created for user {}", api_key.id, user.id);
+
+        Ok(CreateApiKeyResponse {
+            success: true,
+            message: "API key created; it will not be shown again".to_string(),
+            key: Some(generated.key),
+            api_key: Some(api_key),
+        })
+    }
+
+    /// Resolve an API key to its owner and scopes
+    pub async fn verify_api_key(&self, key: &str) -> Result<Option<(User, Credential)>> {
+        let api_key = match self.db.use_api_key(&password::hash_token(key)).await? {
+            Some(api_key) => api_key,
+            None => return Ok(None),
+        };
+
+        let user = match self.db.get_user_by_id(api_key.user_id).await? {
+            Some(user) if user.is_active => user,
+            _ => return Ok(None),
+        };
+
+        let scopes: Vec<Scope> = api_keys::scopes_from_permissions(&api_key.permissions);
+        Ok(Some((user, Credential::ApiKey { id: api_key.id, scopes })))
+    }
+
+    /// Verify an access token, returning its user and login session
+    ///
+    /// Tokens of revoked or expired sessions are rejected even before they expire.
+    pub async fn verify_token(&self, token: &str) -> Result<Option<(User, Uuid)>> {
+        let claims = match self.keys.decode::<Claims>(token) {
+            Ok(claims) => claims,
             Err(e) => {
                 warn!("Token verification failed: {}", e);
-                Ok(None)
+                return Ok(None);
             }
-        }
+        };
+
+        let user_id = Uuid::parse_str(&claims.sub)?;
+        let session_id = Uuid::parse_str(&claims.sid)?;
+        Ok(self
+            .db
+            .get_session_user(user_id, session_id)
+            .await?
+            .filter(|user| user.is_active)
+            .map(|user| (user, session_id)))
     }
 
-    fn generate_token(&self, user: &User) -> Result<String> {
+    fn generate_token(&self, user: &User, session_id: Uuid) -> Result<(String, OffsetDateTime)> {
         let now = OffsetDateTime::now_utc();
-        let exp = now + Duration::days(7);
+        let exp = now + ACCESS_TOKEN_LIFETIME;
 
         let claims = Claims {
             sub: user.id.to_string(),
             email: user.email.clone(),
             username: user.username.clone(),
             subscription_type: user.subscription_type.clone(),
+            sid: session_id.to_string(),
             exp: exp.unix_timestamp(),
             iat: now.unix_timestamp(),
         };
 
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_ref()),
-        ).map_err(|e| anyhow::anyhow!("Failed to generate token: {}", e))
+        Ok((self.keys.encode(&claims)?, exp))
     }
 
-    pub async fn refresh_token(&self, user_id: Uuid) -> Result<AuthResponse> {
-        // Get user from database
-        let user = self.db.get_user_by_id(user_id).await
-            .map_err(|_| anyhow!("Database error"))?
-            .ok_or_else(|| anyhow!("User not found"))?;
-        
-        // Generate new token
-        let token = self.generate_token(&user)?;
-        let expires_at = OffsetDateTime::now_utc() + Duration::days(7);
-        
+    /// Exchange a refresh token for a new access token and refresh token
+    ///
+    /// Each refresh token works once. Presenting one again revokes the session,
+    /// which signs out both the legitimate client and whoever copied the token.
+    pub async fn refresh(&self, request: RefreshRequest) -> Result<AuthResponse> {
+        let new_refresh_token = password::generate_token();
+        let outcome = self
+            .db
+            .rotate_refresh_token(
+                &password::hash_token(&request.refresh_token),
+                &password::hash_token(&new_refresh_token),
+            )
+            .await?;
+
+        let (user_id, session_id) = match outcome {
+            RefreshOutcome::Rotated { user_id, session_id } => (user_id, session_id),
+            RefreshOutcome::Reused { user_id, session_id } => {
+                warn!("Refresh token reused for user {}; revoked session {}", user_id, session_id);
+                return Ok(AuthResponse::failure("Invalid or expired refresh token"));
+            }
+            RefreshOutcome::Invalid => return Ok(AuthResponse::failure("Invalid or expired refresh token")),
+        };
+
+        let user = match self.db.get_user_by_id(user_id).await? {
+            Some(user) if user.is_active => user,
+            _ => return Ok(AuthResponse::failure("Invalid or expired refresh token")),
+        };
+        let (token, expires_at) = self.generate_token(&user, session_id)?;
+
         Ok(AuthResponse {
             success: true,
             message: "Token refreshed successfully".to_string(),
             user: Some(user),
             token: Some(token),
             expires_at: Some(expires_at.to_string()),
+            mfa_token: None,
+            refresh_token: Some(new_refresh_token),
+        })
+    }
+
+    /// Sign out the current login session
+    pub async fn logout(&self, user_id: Uuid, session_id: Uuid) -> Result<AuthResponse> {
+        self.db.revoke_auth_session(user_id, session_id, "logout").await?;
+
+        Ok(AuthResponse {
+            success: true,
+            message: "Signed out".to_string(),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
+        })
+    }
+
+    /// Sign out every login session of the user
+    pub async fn logout_all(&self, user_id: Uuid) -> Result<AuthResponse> {
+        let revoked = self.db.revoke_user_sessions(user_id, None, "logout_all").await?;
+        info!("Revoked {} sessions for user {}", revoked, user_id);
+
+        Ok(AuthResponse {
+            success: true,
+            message: format!("Signed out of {} sessions", revoked),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
         })
     }
 
-    pub async fn change_password(&self, user_id: Uuid, request: PasswordChangeRequest) -> Result<AuthResponse> {
-        // TODO: Implement password change functionality
-        // This would involve:
-        // 1. Verify current password
-        // 2. Hash new password
-        // 3. Update in database
-        
+    /// Change the password, signing out every other session
+    pub async fn change_password(&self, user_id: Uuid, current_session: Option<Uuid>, request: PasswordChangeRequest) -> Result<AuthResponse> {
+        let current_hash = self.db.get_password_hash(user_id).await?
+            .ok_or_else(|| anyhow!("User not found"))?;
+
+        if !password::verify_password(&request.current_password, &current_hash).await.valid {
+            return Ok(AuthResponse::failure("Current password is incorrect"));
+        }
+
+        if let Err(message) = password::validate_new_password(&request.new_password) {
+            return Ok(AuthResponse::failure(&message));
+        }
+
+        let new_hash = password::hash_password(&request.new_password).await?;
+        self.db.update_password_hash(user_id, &new_hash, true).await?;
+        self.db.revoke_password_reset_tokens(user_id).await?;
+        self.db.revoke_user_sessions(user_id, current_session, "password_change").await?;
+
+        info!("Password changed for user {}", user_id);
+
         Ok(AuthResponse {
             success: true,
             message: "Password changed successfully".to_string(),
             user: None,
             token: None,
             expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
+        })
+    }
+
+    /// Issue a single-use password reset token
+    ///
+    /// The response is identical whether or not the email is registered.
+    pub async fn request_password_reset(&self, request: PasswordResetRequest) -> Result<AuthResponse> {
+        let response = AuthResponse {
+            success: true,
+            message: "If the account exists, a password reset link has been sent".to_string(),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
+        };
+
+        // Directory users change their password in the directory
+        let user = match self.db.get_user_by_email(&request.email).await? {
+            Some(user) if user.is_active && user.auth_provider == "local" => user,
+            _ => return Ok(response),
+        };
+
+        let token = password::generate_token();
+        let expires_at = chrono::Utc::now() + RESET_TOKEN_LIFETIME;
+        self.db
+            .create_password_reset_token(user.id, &password::hash_token(&token), expires_at)
+            .await?;
+
+        match &self.reset_notifier {
+            Some(notifier) => {
+                let _ = notifier.send(PasswordResetIssued {
+                    user_id: user.id,
+                    email: user.email.clone(),
+                    token,
+                    expires_at,
+                });
+            }
+            None => warn!("Password reset requested for {} but no reset delivery is configured", user.email),
+        }
+
+        Ok(response)
+    }
+
+    /// Set a new password using a reset token
+    pub async fn confirm_password_reset(&self, request: PasswordResetConfirmRequest) -> Result<AuthResponse> {
+        if let Err(message) = password::validate_new_password(&request.new_password) {
+            return Ok(AuthResponse::failure(&message));
+        }
+
+        let user_id = match self.db
+            .consume_password_reset_token(&password::hash_token(&request.token))
+            .await?
+        {
+            Some(user_id) => user_id,
+            None => return Ok(AuthResponse::failure("Invalid or expired reset token")),
+        };
+
+        let new_hash = password::hash_password(&request.new_password).await?;
+        self.db.update_password_hash(user_id, &new_hash, true).await?;
+        self.db.revoke_password_reset_tokens(user_id).await?;
+        self.db.revoke_user_sessions(user_id, None, "password_reset").await?;
+
+        info!("Password reset for user {}", user_id);
+
+        Ok(AuthResponse {
+            success: true,
+            message: "Password has been reset".to_string(),
+            user: None,
+            token: None,
+            expires_at: None,
+            mfa_token: None,
+            refresh_token: None,
         })
     }
 }
@@ -259,25 +1001,54 @@ pub async fn auth_middleware(
     mut request: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    let auth_header = request
+    let token = request
         .headers()
         .get(header::AUTHORIZATION)
-        .and_then(|header| header.to_str().ok());
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .or_else(|| {
+            request
+                .headers()
+                .get("x-api-key")
+                .and_then(|header| header.to_str().ok())
+        })
+        .map(str::to_string);
 
-    if let Some(auth_header) = auth_header {
-        if let Some(token) = auth_header.strip_prefix("Bearer ") {
-            match auth_service.verify_token(token).await {
-                Ok(Some(user)) => {
-                    // Add user to request extensions
+    if let Some(token) = token {
+        if api_keys::is_api_key(&token) {
+            return match auth_service.verify_api_key(&token).await {
+                Ok(Some((user, credential))) => {
+                    if !credential.permits(request.method(), request.uri().path()) {
+                        warn!("API key lacks scope for {} {}", request.method(), request.uri().path());
+                        return Err(StatusCode::FORBIDDEN);
+                    }
                     request.extensions_mut().insert(user);
-                    return Ok(next.run(request).await);
+                    request.extensions_mut().insert(credential);
+                    Ok(next.run(request).await)
                 }
                 Ok(None) => {
-                    warn!("Invalid token provided");
+                    warn!("Invalid or expired API key provided");
+                    Err(StatusCode::UNAUTHORIZED)
                 }
                 Err(e) => {
-                    error!("Token verification error: {}", e);
+                    error!("API key verification error: {}", e);
+                    Err(StatusCode::UNAUTHORIZED)
                 }
+            };
+        }
+
+        match auth_service.verify_token(&token).await {
+            Ok(Some((user, session_id))) => {
+                // Add user to request extensions
+                request.extensions_mut().insert(user);
+                request.extensions_mut().insert(Credential::Session { session_id });
+                return Ok(next.run(request).await);
+            }
+            Ok(None) => {
+                warn!("Invalid token provided");
+            }
+            Err(e) => {
+                error!("Token verification error: {}", e);
             }
         }
     }
@@ -285,6 +1056,43 @@ pub async fn auth_middleware(
     Err(StatusCode::UNAUTHORIZED)
 }
 
+/// Admin middleware: only platform administrators pass
+///
+/// Runs after `auth_middleware`, which puts the user in the extensions.
+pub async fn admin_middleware(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let user = extract_user(&request)?;
+    if !user.is_admin {
+        warn!("User {} denied access to {}", user.id, request.uri().path());
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Grant platform administration to the accounts listed in `emails`
+/// (comma-separated, e.g. from `ADMIN_EMAILS`)
+///
+/// Only accounts whose address is known to belong to their owner are
+/// promoted: verified accounts and those from the directory or single
+/// sign-on. Anyone can register an unverified local account for a listed
+/// address before its owner does, so those are skipped.
+pub async fn grant_admins(db: &Database, emails: &str) -> Result<()> {
+    for email in emails.split(',').map(str::trim).filter(|email| !email.is_empty()) {
+        match db.get_user_by_email(email).await? {
+            Some(user) if user.is_admin => {}
+            Some(user) if user.is_verified
+                || user.auth_provider == crate::ldap_auth::LDAP_PROVIDER
+                || user.auth_provider == oidc::OIDC_PROVIDER =>
+            {
+                db.set_user_admin(user.id, true).await?;
+                info!("Granted admin rights to {}", email);
+            }
+            Some(_) => warn!("ADMIN_EMAILS lists {}, whose account is not verified; not granting admin rights", email),
+            None => warn!("ADMIN_EMAILS lists {}, which has no account yet", email),
+        }
+    }
+    Ok(())
+}
+
 // Helper function to extract user from request
 pub fn extract_user(request: &Request) -> Result<&User, StatusCode> {
     request
@@ -313,61 +1121,3 @@ where
             .ok_or(StatusCode::UNAUTHORIZED)
     }
 }
-
-// Rate limiting middleware
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tokio::time::sleep;
-
-pub struct RateLimiter {
-    requests: Arc<Mutex<HashMap<String, Vec<OffsetDateTime>>>>,
-    max_requests: usize,
-    window: Duration,
-}
-
-impl RateLimiter {
-    pub fn new(max_requests: usize, window: Duration) -> Self {
-        RateLimiter {
-            requests: Arc::new(Mutex::new(HashMap::new())),
-            max_requests,
-            window,
-        }
-    }
-
-    pub async fn check_rate_limit(&self, key: &str) -> bool {
-        let now = OffsetDateTime::now_utc();
-        let mut requests = self.requests.lock().unwrap();
-        
-        let user_requests = requests.entry(key.to_string()).or_insert_with(Vec::new);
-        
-        // Remove old requests outside the window
-        user_requests.retain(|&timestamp| now - timestamp < self.window);
-        
-        // Check if under limit
-        if user_requests.len() < self.max_requests {
-            user_requests.push(now);
-            true
-        } else {
-            false
-        }
-    }
-}
-
-pub async fn rate_limit_middleware(
-    State(rate_limiter): State<Arc<RateLimiter>>,
-    request: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    let client_ip = request
-        .headers()
-        .get("x-real-ip")
-        .or_else(|| request.headers().get("x-forwarded-for"))
-        .and_then(|header| header.to_str().ok())
-        .unwrap_or("unknown");
-
-    if rate_limiter.check_rate_limit(client_ip).await {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::TOO_MANY_REQUESTS)
-    }
-}
@@ -0,0 +1,1699 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use tracing::info;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+use super::{RefreshOutcome, Repository};
+use crate::models::*;
+use crate::password;
+use crate::orgs::{DeviceAccess, OrgRole};
+
+// INET and MACADDR columns are read and written as text
+const USER_COLUMNS: &str = "id, email, username, display_name, avatar_url, is_active, is_verified, \
+    subscription_type, auth_provider, is_admin, created_at, updated_at, last_login, preferences";
+const DEVICE_COLUMNS: &str = "id, user_id, device_id, org_id, device_name, device_type, os_version, \
+    ip_address::TEXT AS ip_address, mac_address::TEXT AS mac_address, last_seen, is_online, \
+    capabilities, metadata, created_at, updated_at";
+const SESSION_COLUMNS: &str = "id, user_id, device_id, remote_device_id, session_type, started_at, \
+    ended_at, duration_seconds, status, connection_quality, metadata";
+const API_KEY_COLUMNS: &str = "id, user_id, name, key_prefix, key_hash, permissions, expires_at, \
+    last_used, is_active, created_at";
+const ORG_COLUMNS: &str = "id, name, created_by, force_relay, created_at, updated_at";
+const INVITATION_COLUMNS: &str = "id, org_id, email, role, token_hash, invited_by, expires_at, \
+    accepted_at, created_at";
+const LICENSE_COLUMNS: &str = "id, user_id, license_key, license_type, expires_at, is_active, \
+    max_devices, max_concurrent_sessions, features, created_at, updated_at";
+
+#[derive(Clone)]
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    /// Connect and run the Postgres migrations
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        info!("Connecting to Postgres...");
+        let pool = PgPoolOptions::new()
+            .max_connections(10)
+            .connect(database_url)
+            .await?;
+
+        // Run migrations
+        sqlx::migrate!("./migrations/postgres").run(&pool).await?;
+
+        info!("Database connected and migrations completed");
+        Ok(PgRepository { pool })
+    }
+}
+
+#[async_trait]
+impl Repository for PgRepository {
+    // User operations
+    async fn create_user(&self, user: &User, password_hash: &str) -> Result<User> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO users (id, email, username, display_name, avatar_url, password_hash, is_active, is_verified, subscription_type, preferences, password_changed_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, NOW())
+            RETURNING {USER_COLUMNS}
+            "#
+        ))
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(&user.username)
+        .bind(&user.display_name)
+        .bind(&user.avatar_url)
+        .bind(password_hash)
+        .bind(user.is_active)
+        .bind(user.is_verified)
+        .bind(&user.subscription_type)
+        .bind(&user.preferences)
+        .fetch_one(&self.pool)
+        .await?;
+
+        user_from_row(&row)
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<Option<User>> {
+        let row = sqlx::query(&format!("SELECT {USER_COLUMNS} FROM users WHERE email = $1"))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
+        let row = sqlx::query(&format!("SELECT {USER_COLUMNS} FROM users WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    async fn update_user_last_login(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE users SET last_login = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn upsert_external_user(&self, user: &User, external_id: &str) -> Result<User> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO users (id, email, username, display_name, password_hash, is_active, is_verified, subscription_type, preferences, auth_provider, external_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (auth_provider, external_id) DO UPDATE SET
+                email = EXCLUDED.email,
+                username = EXCLUDED.username,
+                display_name = EXCLUDED.display_name
+            RETURNING {USER_COLUMNS}
+            "#
+        ))
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(&user.username)
+        .bind(&user.display_name)
+        .bind(password::NO_PASSWORD)
+        .bind(user.is_active)
+        .bind(user.is_verified)
+        .bind(&user.subscription_type)
+        .bind(&user.preferences)
+        .bind(&user.auth_provider)
+        .bind(external_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        user_from_row(&row)
+    }
+
+    // Credential operations
+    async fn get_user_credentials(&self, email: &str) -> Result<Option<(User, String)>> {
+        let row = sqlx::query(&format!(
+            "SELECT {USER_COLUMNS}, password_hash FROM users WHERE email = $1"
+        ))
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| Ok((user_from_row(&row)?, row.try_get("password_hash")?)))
+            .transpose()
+    }
+
+    async fn get_password_hash(&self, user_id: Uuid) -> Result<Option<String>> {
+        let hash = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(hash)
+    }
+
+    async fn update_password_hash(&self, user_id: Uuid, password_hash: &str, changed: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $2,
+                password_changed_at = CASE WHEN $3 THEN NOW() ELSE password_changed_at END
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(password_hash)
+        .bind(changed)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn create_password_reset_token(&self, user_id: Uuid, token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)",
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn consume_password_reset_token(&self, token_hash: &str) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar(
+            r#"
+            UPDATE password_reset_tokens
+            SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    async fn revoke_password_reset_tokens(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Login sessions
+    async fn create_auth_session(&self, user_id: Uuid, session_id: Uuid, refresh_token_hash: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO auth_sessions (id, user_id, expires_at) VALUES ($1, $2, $3)")
+            .bind(session_id)
+            .bind(user_id)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO refresh_tokens (session_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+            .bind(session_id)
+            .bind(refresh_token_hash)
+            .bind(expires_at)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn rotate_refresh_token(&self, token_hash: &str, new_token_hash: &str) -> Result<RefreshOutcome> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT rt.id, rt.session_id, rt.used_at, rt.expires_at, s.user_id, s.revoked_at, s.expires_at AS session_expires_at
+            FROM refresh_tokens rt
+            JOIN auth_sessions s ON s.id = rt.session_id
+            WHERE rt.token_hash = $1
+            FOR UPDATE OF rt, s
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = match row {
+            Some(row) => row,
+            None => return Ok(RefreshOutcome::Invalid),
+        };
+        let token_id: Uuid = row.try_get("id")?;
+        let session_id: Uuid = row.try_get("session_id")?;
+        let user_id: Uuid = row.try_get("user_id")?;
+        let used_at: Option<DateTime<Utc>> = row.try_get("used_at")?;
+        let expires_at: DateTime<Utc> = row.try_get("expires_at")?;
+        let revoked_at: Option<DateTime<Utc>> = row.try_get("revoked_at")?;
+        let session_expires_at: DateTime<Utc> = row.try_get("session_expires_at")?;
+
+        if revoked_at.is_some() {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        if used_at.is_some() {
+            sqlx::query(
+                "UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = 'refresh_token_reuse' WHERE id = $1",
+            )
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            return Ok(RefreshOutcome::Reused { user_id, session_id });
+        }
+
+        if expires_at <= Utc::now() || session_expires_at <= Utc::now() {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE id = $1")
+            .bind(token_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO refresh_tokens (session_id, token_hash, expires_at) VALUES ($1, $2, $3)")
+            .bind(session_id)
+            .bind(new_token_hash)
+            .bind(session_expires_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE auth_sessions SET last_refreshed_at = NOW() WHERE id = $1")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(RefreshOutcome::Rotated { user_id, session_id })
+    }
+
+    async fn get_session_user(&self, user_id: Uuid, session_id: Uuid) -> Result<Option<User>> {
+        let row = sqlx::query(&format!(
+            r#"
+            SELECT {USER_COLUMNS} FROM users
+            WHERE id = $1 AND EXISTS (
+                SELECT 1 FROM auth_sessions s
+                WHERE s.id = $2 AND s.user_id = users.id AND s.revoked_at IS NULL AND s.expires_at > NOW()
+            )
+            "#
+        ))
+        .bind(user_id)
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    async fn revoke_auth_session(&self, user_id: Uuid, session_id: Uuid, reason: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = $3
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn revoke_user_sessions(&self, user_id: Uuid, except: Option<Uuid>, reason: &str) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE auth_sessions SET revoked_at = NOW(), revoked_reason = $3
+            WHERE user_id = $1 AND revoked_at IS NULL AND ($2::UUID IS NULL OR id <> $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(except)
+        .bind(reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Two-factor authentication
+    async fn get_mfa_settings(&self, user_id: Uuid) -> Result<MfaSettings> {
+        let row = sqlx::query(
+            "SELECT totp_secret, totp_enabled, totp_last_step, mfa_failed_attempts, mfa_locked_until FROM users WHERE id = $1",
+        )
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(MfaSettings {
+                totp_secret: row.try_get("totp_secret")?,
+                totp_enabled: row.try_get("totp_enabled")?,
+                totp_last_step: row.try_get("totp_last_step")?,
+                failed_attempts: row.try_get("mfa_failed_attempts")?,
+                locked_until: row.try_get("mfa_locked_until")?,
+            }),
+            None => Ok(MfaSettings::default()),
+        }
+    }
+
+    async fn set_pending_totp_secret(&self, user_id: Uuid, secret: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE users SET totp_secret = $2, totp_last_step = NULL WHERE id = $1 AND totp_enabled = false",
+        )
+        .bind(user_id)
+        .bind(secret)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn enable_totp(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE users SET totp_enabled = true WHERE id = $1 AND totp_secret IS NOT NULL")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn record_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users SET totp_last_step = $2
+            WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn record_mfa_failure(&self, user_id: Uuid, lock_after: i32, locked_until: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE users SET
+                mfa_failed_attempts = CASE WHEN mfa_failed_attempts + 1 >= $2 THEN 0 ELSE mfa_failed_attempts + 1 END,
+                mfa_locked_until = CASE WHEN mfa_failed_attempts + 1 >= $2 THEN $3 ELSE mfa_locked_until END
+            WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(lock_after)
+        .bind(locked_until)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn reset_mfa_failures(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query("UPDATE users SET mfa_failed_attempts = 0, mfa_locked_until = NULL WHERE id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn disable_mfa(&self, user_id: Uuid) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "UPDATE users SET totp_secret = NULL, totp_enabled = false, totp_last_step = NULL WHERE id = $1",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for code_hash in code_hashes {
+            sqlx::query("INSERT INTO mfa_recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE mfa_recovery_codes SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(code_hash)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn count_recovery_codes(&self, user_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM mfa_recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    // API key operations
+    async fn create_api_key(&self, api_key: &ApiKey) -> Result<ApiKey> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO api_keys (id, user_id, name, key_prefix, key_hash, permissions, expires_at, is_active)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        ))
+        .bind(api_key.id)
+        .bind(api_key.user_id)
+        .bind(&api_key.name)
+        .bind(&api_key.key_prefix)
+        .bind(&api_key.key_hash)
+        .bind(&api_key.permissions)
+        .bind(api_key.expires_at)
+        .bind(api_key.is_active)
+        .fetch_one(&self.pool)
+        .await?;
+
+        api_key_from_row(&row)
+    }
+
+    async fn get_user_api_keys(&self, user_id: Uuid) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {API_KEY_COLUMNS} FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC"
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(api_key_from_row).collect()
+    }
+
+    async fn revoke_api_key(&self, user_id: Uuid, key_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET is_active = false WHERE id = $1 AND user_id = $2 AND is_active = true",
+        )
+        .bind(key_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn use_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE api_keys SET last_used = NOW()
+            WHERE key_hash = $1
+              AND is_active = true
+              AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING {API_KEY_COLUMNS}
+            "#
+        ))
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(api_key_from_row).transpose()
+    }
+
+    async fn deactivate_expired_api_keys(&self) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET is_active = false WHERE is_active = true AND expires_at <= NOW()",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    // Device operations
+    async fn register_device(&self, device: &Device) -> Result<Option<Device>> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO devices (user_id, device_id, org_id, device_name, device_type, os_version, ip_address, mac_address, capabilities, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6, $7::INET, $8::MACADDR, $9, $10)
+            ON CONFLICT (device_id) DO UPDATE SET
+                org_id = COALESCE(EXCLUDED.org_id, devices.org_id),
+                device_name = EXCLUDED.device_name,
+                device_type = EXCLUDED.device_type,
+                os_version = EXCLUDED.os_version,
+                ip_address = EXCLUDED.ip_address,
+                mac_address = EXCLUDED.mac_address,
+                last_seen = NOW(),
+                capabilities = EXCLUDED.capabilities,
+                metadata = EXCLUDED.metadata,
+                updated_at = NOW()
+            WHERE devices.user_id = EXCLUDED.user_id
+            RETURNING {DEVICE_COLUMNS}
+            "#
+        ))
+        .bind(device.user_id)
+        .bind(&device.device_id)
+        .bind(device.org_id)
+        .bind(&device.device_name)
+        .bind(&device.device_type)
+        .bind(&device.os_version)
+        .bind(&device.ip_address)
+        .bind(&device.mac_address)
+        .bind(&device.capabilities)
+        .bind(&device.metadata)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(device) = row.as_ref().map(device_from_row).transpose()? else {
+            return Ok(None);
+        };
+
+        // A device that changed organization leaves the groups of the old one
+        sqlx::query(
+            r#"
+            DELETE FROM device_group_devices
+            WHERE device_id = $1 AND group_id IN (
+                SELECT id FROM device_groups WHERE org_id IS DISTINCT FROM $2
+            )
+            "#,
+        )
+        .bind(device.id)
+        .bind(device.org_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Some(device))
+    }
+
+    async fn get_device(&self, id: Uuid) -> Result<Option<Device>> {
+        let row = sqlx::query(&format!("SELECT {DEVICE_COLUMNS} FROM devices WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(device_from_row).transpose()
+    }
+
+    async fn get_device_by_device_id(&self, device_id: &str) -> Result<Option<Device>> {
+        let row = sqlx::query(&format!("SELECT {DEVICE_COLUMNS} FROM devices WHERE device_id = $1"))
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(device_from_row).transpose()
+    }
+
+    async fn get_user_devices(&self, user_id: Uuid) -> Result<Vec<Device>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {DEVICE_COLUMNS} FROM devices WHERE user_id = $1 ORDER BY last_seen DESC"
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(device_from_row).collect()
+    }
+
+    async fn get_org_devices(&self, org_id: Uuid) -> Result<Vec<Device>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {DEVICE_COLUMNS} FROM devices WHERE org_id = $1 ORDER BY last_seen DESC"
+        ))
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(device_from_row).collect()
+    }
+
+    async fn get_team_granted_devices(&self, user_id: Uuid) -> Result<Vec<Device>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {DEVICE_COLUMNS} FROM devices
+            WHERE id IN (
+                SELECT gd.device_id FROM device_group_devices gd
+                JOIN devices d ON d.id = gd.device_id
+                JOIN device_groups g ON g.id = gd.group_id AND g.org_id = d.org_id
+                JOIN device_group_grants gg ON gg.group_id = g.id
+                JOIN team_members tm ON tm.team_id = gg.team_id
+                WHERE tm.user_id = $1
+            )
+            ORDER BY last_seen DESC
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(device_from_row).collect()
+    }
+
+    async fn get_team_device_access(&self, user_id: Uuid, device_id: Uuid) -> Result<Option<DeviceAccess>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT gg.access FROM device_group_devices gd
+            JOIN devices d ON d.id = gd.device_id
+            JOIN device_groups g ON g.id = gd.group_id AND g.org_id = d.org_id
+            JOIN device_group_grants gg ON gg.group_id = g.id
+            JOIN team_members tm ON tm.team_id = gg.team_id
+            WHERE gd.device_id = $1 AND tm.user_id = $2
+            "#,
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        strongest_access(&rows)
+    }
+
+    async fn remove_org_device(&self, org_id: Uuid, device_id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("UPDATE devices SET org_id = NULL WHERE id = $1 AND org_id = $2")
+            .bind(device_id)
+            .bind(org_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "DELETE FROM device_group_devices WHERE device_id = $1 AND group_id IN (SELECT id FROM device_groups WHERE org_id = $2)",
+        )
+        .bind(device_id)
+        .bind(org_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn update_device_online_status(&self, device_id: &str, is_online: bool) -> Result<()> {
+        sqlx::query("UPDATE devices SET is_online = $1, last_seen = NOW() WHERE device_id = $2")
+            .bind(is_online)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Session operations
+    async fn create_session(&self, session: &Session) -> Result<Session> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO sessions (user_id, device_id, remote_device_id, session_type, status, metadata)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING {SESSION_COLUMNS}
+            "#
+        ))
+        .bind(session.user_id)
+        .bind(session.device_id)
+        .bind(session.remote_device_id)
+        .bind(&session.session_type)
+        .bind(&session.status)
+        .bind(&session.metadata)
+        .fetch_one(&mut *tx)
+        .await?;
+        let created = session_from_row(&row)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO connections (session_id, from_device_id, to_device_id, connection_type, status)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(created.id)
+        .bind(created.device_id)
+        .bind(created.remote_device_id)
+        .bind(&created.session_type)
+        .bind(&created.status)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> Result<Option<Session>> {
+        let row = sqlx::query(&format!("SELECT {SESSION_COLUMNS} FROM sessions WHERE id = $1"))
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(session_from_row).transpose()
+    }
+
+    async fn get_user_sessions(&self, user_id: Uuid, limit: i64) -> Result<Vec<Session>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {SESSION_COLUMNS} FROM sessions WHERE user_id = $1 ORDER BY started_at DESC LIMIT $2"
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn get_org_sessions(&self, org_id: Uuid, limit: i64) -> Result<Vec<Session>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {SESSION_COLUMNS} FROM sessions
+            WHERE device_id IN (SELECT id FROM devices WHERE org_id = $1)
+               OR remote_device_id IN (SELECT id FROM devices WHERE org_id = $1)
+            ORDER BY started_at DESC LIMIT $2
+            "#
+        ))
+        .bind(org_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn end_session(&self, session_id: Uuid, status: &str, traffic: ConnectionTraffic) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        let ended = sqlx::query(
+            r#"
+            UPDATE sessions
+            SET ended_at = NOW(),
+                duration_seconds = EXTRACT(EPOCH FROM (NOW() - started_at))::INTEGER,
+                status = $2
+            WHERE id = $1 AND status = 'active'
+            RETURNING user_id
+            "#,
+        )
+        .bind(session_id)
+        .bind(status)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(ended) = ended else {
+            return Ok(false);
+        };
+        let user_id: Uuid = ended.try_get("user_id")?;
+
+        let connection = sqlx::query(
+            r#"
+            UPDATE connections
+            SET ended_at = NOW(),
+                duration_seconds = EXTRACT(EPOCH FROM (NOW() - started_at))::INTEGER,
+                bytes_sent = $2,
+                bytes_received = $3,
+                status = $4
+            WHERE session_id = $1 AND status = 'active'
+            RETURNING duration_seconds
+            "#,
+        )
+        .bind(session_id)
+        .bind(traffic.bytes_sent)
+        .bind(traffic.bytes_received)
+        .bind(status)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(connection) = connection {
+            let duration: i32 = connection.try_get("duration_seconds")?;
+            sqlx::query(
+                r#"
+                INSERT INTO usage_stats (user_id, session_id, stat_type, value, unit)
+                VALUES ($1, $2, 'connections', 1, 'count'),
+                       ($1, $2, 'session_duration', $3, 'seconds'),
+                       ($1, $2, 'bandwidth', $4, 'bytes')
+                "#,
+            )
+            .bind(user_id)
+            .bind(session_id)
+            .bind(i64::from(duration))
+            .bind(traffic.bytes_sent + traffic.bytes_received)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn get_user_usage(&self, user_id: Uuid) -> Result<UsageStats> {
+        let row = sqlx::query(
+            r#"
+            SELECT COALESCE(SUM(value) FILTER (WHERE stat_type = 'connections'), 0)::BIGINT AS connections,
+                   COALESCE(SUM(value) FILTER (WHERE stat_type = 'session_duration'), 0)::BIGINT AS session_duration,
+                   COALESCE(SUM(value) FILTER (WHERE stat_type = 'bandwidth'), 0)::BIGINT AS bandwidth_used
+            FROM usage_stats WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(UsageStats {
+            connections: row.try_get("connections")?,
+            session_duration: row.try_get("session_duration")?,
+            bandwidth_used: row.try_get("bandwidth_used")?,
+        })
+    }
+
+    // Organization operations
+    async fn create_organization(&self, name: &str, owner_id: Uuid) -> Result<Organization> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(&format!(
+            "INSERT INTO organizations (name, created_by) VALUES ($1, $2) RETURNING {ORG_COLUMNS}"
+        ))
+        .bind(name)
+        .bind(owner_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        let org = org_from_row(&row)?;
+
+        sqlx::query("INSERT INTO organization_members (org_id, user_id, role) VALUES ($1, $2, $3)")
+            .bind(org.id)
+            .bind(owner_id)
+            .bind(OrgRole::Owner.as_str())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(org)
+    }
+
+    async fn get_organization(&self, org_id: Uuid) -> Result<Option<Organization>> {
+        let row = sqlx::query(&format!("SELECT {ORG_COLUMNS} FROM organizations WHERE id = $1"))
+            .bind(org_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(org_from_row).transpose()
+    }
+
+    async fn delete_organization(&self, org_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(org_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_org_force_relay(&self, org_id: Uuid, force_relay: bool) -> Result<bool> {
+        let result = sqlx::query("UPDATE organizations SET force_relay = $2, updated_at = NOW() WHERE id = $1")
+            .bind(org_id)
+            .bind(force_relay)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_user_organizations(&self, user_id: Uuid) -> Result<Vec<(Organization, OrgRole)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT o.id, o.name, o.created_by, o.force_relay, o.created_at, o.updated_at, m.role
+            FROM organizations o
+            JOIN organization_members m ON m.org_id = o.id
+            WHERE m.user_id = $1
+            ORDER BY o.name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| Ok((org_from_row(row)?, parse_column(row, "role")?)))
+            .collect()
+    }
+
+    async fn get_member_role(&self, org_id: Uuid, user_id: Uuid) -> Result<Option<OrgRole>> {
+        let row = sqlx::query("SELECT role FROM organization_members WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(|row| parse_column(row, "role")).transpose()
+    }
+
+    async fn get_org_members(&self, org_id: Uuid) -> Result<Vec<OrgMember>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT u.id AS user_id, u.email, u.username, u.display_name, m.role, m.created_at
+            FROM organization_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.org_id = $1
+            ORDER BY m.created_at
+            "#,
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(member_from_row).collect()
+    }
+
+    async fn set_member_role(&self, org_id: Uuid, user_id: Uuid, role: OrgRole) -> Result<bool> {
+        let result = sqlx::query("UPDATE organization_members SET role = $3 WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id)
+            .bind(user_id)
+            .bind(role.as_str())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_org_member(&self, org_id: Uuid, user_id: Uuid, role: OrgRole) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO organization_members (org_id, user_id, role) VALUES ($1, $2, $3)
+            ON CONFLICT (org_id, user_id) DO UPDATE SET role = EXCLUDED.role
+            "#,
+        )
+        .bind(org_id)
+        .bind(user_id)
+        .bind(role.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_org_member(&self, org_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM team_members WHERE user_id = $2 AND team_id IN (SELECT id FROM teams WHERE org_id = $1)",
+        )
+        .bind(org_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        let result = sqlx::query("DELETE FROM organization_members WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn count_org_owners(&self, org_id: Uuid) -> Result<i64> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM organization_members WHERE org_id = $1 AND role = 'owner'",
+        )
+        .bind(org_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    // Invitations
+    async fn create_org_invitation(&self, invitation: &OrgInvitation) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO organization_invitations (id, org_id, email, role, token_hash, invited_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(invitation.id)
+        .bind(invitation.org_id)
+        .bind(&invitation.email)
+        .bind(invitation.role.as_str())
+        .bind(&invitation.token_hash)
+        .bind(invitation.invited_by)
+        .bind(invitation.expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_pending_invitations(&self, org_id: Uuid) -> Result<Vec<OrgInvitation>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {INVITATION_COLUMNS} FROM organization_invitations
+            WHERE org_id = $1 AND accepted_at IS NULL AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#
+        ))
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(invitation_from_row).collect()
+    }
+
+    async fn revoke_org_invitation(&self, org_id: Uuid, invitation_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM organization_invitations WHERE id = $1 AND org_id = $2 AND accepted_at IS NULL",
+        )
+        .bind(invitation_id)
+        .bind(org_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn accept_org_invitation(&self, token_hash: &str, user_id: Uuid, email: &str) -> Result<Option<(Uuid, OrgRole)>> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            r#"
+            UPDATE organization_invitations SET accepted_at = NOW()
+            WHERE token_hash = $1 AND LOWER(email) = LOWER($2) AND accepted_at IS NULL AND expires_at > NOW()
+            RETURNING org_id, role
+            "#,
+        )
+        .bind(token_hash)
+        .bind(email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let org_id: Uuid = row.try_get("org_id")?;
+        let role: OrgRole = parse_column(&row, "role")?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO organization_members (org_id, user_id, role) VALUES ($1, $2, $3)
+            ON CONFLICT (org_id, user_id) DO NOTHING
+            "#,
+        )
+        .bind(org_id)
+        .bind(user_id)
+        .bind(role.as_str())
+        .execute(&mut *tx)
+        .await?;
+        let role: String = sqlx::query_scalar("SELECT role FROM organization_members WHERE org_id = $1 AND user_id = $2")
+            .bind(org_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some((org_id, role.parse().map_err(anyhow::Error::msg)?)))
+    }
+
+    // Teams
+    async fn create_team(&self, org_id: Uuid, name: &str) -> Result<Team> {
+        let row = sqlx::query("INSERT INTO teams (org_id, name) VALUES ($1, $2) RETURNING id, org_id, name, created_at")
+            .bind(org_id)
+            .bind(name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        team_from_row(&row, Vec::new())
+    }
+
+    async fn get_org_teams(&self, org_id: Uuid) -> Result<Vec<Team>> {
+        let rows = sqlx::query("SELECT id, org_id, name, created_at FROM teams WHERE org_id = $1 ORDER BY name")
+            .bind(org_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let members = sqlx::query(
+            "SELECT tm.team_id, tm.user_id FROM team_members tm JOIN teams t ON t.id = tm.team_id WHERE t.org_id = $1 ORDER BY tm.created_at",
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let team_id: Uuid = row.try_get("id")?;
+                let team_members = members
+                    .iter()
+                    .filter(|member| member.try_get::<Uuid, _>("team_id").ok() == Some(team_id))
+                    .map(|member| member.try_get("user_id"))
+                    .collect::<Result<Vec<Uuid>, _>>()?;
+                team_from_row(row, team_members)
+            })
+            .collect()
+    }
+
+    async fn delete_team(&self, org_id: Uuid, team_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM teams WHERE id = $1 AND org_id = $2")
+            .bind(team_id)
+            .bind(org_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn add_team_member(&self, org_id: Uuid, team_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO team_members (team_id, user_id)
+            SELECT t.id, m.user_id FROM teams t
+            JOIN organization_members m ON m.org_id = t.org_id
+            WHERE t.id = $1 AND t.org_id = $2 AND m.user_id = $3
+            ON CONFLICT (team_id, user_id) DO UPDATE SET user_id = EXCLUDED.user_id
+            "#,
+        )
+        .bind(team_id)
+        .bind(org_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remove_team_member(&self, org_id: Uuid, team_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM team_members WHERE team_id = $1 AND user_id = $3 AND team_id IN (SELECT id FROM teams WHERE org_id = $2)",
+        )
+        .bind(team_id)
+        .bind(org_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    // Device groups
+    async fn create_device_group(&self, org_id: Uuid, name: &str, description: Option<&str>) -> Result<DeviceGroup> {
+        let row = sqlx::query(
+            "INSERT INTO device_groups (org_id, name, description) VALUES ($1, $2, $3) RETURNING id, org_id, name, description, created_at",
+        )
+        .bind(org_id)
+        .bind(name)
+        .bind(description)
+        .fetch_one(&self.pool)
+        .await?;
+
+        device_group_from_row(&row, Vec::new(), Vec::new())
+    }
+
+    async fn get_org_device_groups(&self, org_id: Uuid) -> Result<Vec<DeviceGroup>> {
+        let rows = sqlx::query(
+            "SELECT id, org_id, name, description, created_at FROM device_groups WHERE org_id = $1 ORDER BY name",
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let devices = sqlx::query(
+            "SELECT gd.group_id, gd.device_id FROM device_group_devices gd JOIN device_groups g ON g.id = gd.group_id WHERE g.org_id = $1",
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let grants = sqlx::query(
+            "SELECT gg.group_id, gg.team_id, gg.access FROM device_group_grants gg JOIN device_groups g ON g.id = gg.group_id WHERE g.org_id = $1",
+        )
+        .bind(org_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let group_id: Uuid = row.try_get("id")?;
+                let in_group = |r: &&PgRow| r.try_get::<Uuid, _>("group_id").ok() == Some(group_id);
+                let group_devices = devices
+                    .iter()
+                    .filter(in_group)
+                    .map(|r| r.try_get("device_id"))
+                    .collect::<Result<Vec<Uuid>, _>>()?;
+                let group_grants = grants
+                    .iter()
+                    .filter(in_group)
+                    .map(|r| {
+                        Ok(DeviceGroupGrant {
+                            team_id: r.try_get("team_id")?,
+                            access: parse_column(r, "access")?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                device_group_from_row(row, group_devices, group_grants)
+            })
+            .collect()
+    }
+
+    async fn delete_device_group(&self, org_id: Uuid, group_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM device_groups WHERE id = $1 AND org_id = $2")
+            .bind(group_id)
+            .bind(org_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn add_group_device(&self, org_id: Uuid, group_id: Uuid, device_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO device_group_devices (group_id, device_id)
+            SELECT g.id, d.id FROM device_groups g
+            JOIN devices d ON d.org_id = g.org_id
+            WHERE g.id = $1 AND g.org_id = $2 AND d.id = $3
+            ON CONFLICT (group_id, device_id) DO UPDATE SET device_id = EXCLUDED.device_id
+            "#,
+        )
+        .bind(group_id)
+        .bind(org_id)
+        .bind(device_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remove_group_device(&self, org_id: Uuid, group_id: Uuid, device_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM device_group_devices WHERE group_id = $1 AND device_id = $3 AND group_id IN (SELECT id FROM device_groups WHERE org_id = $2)",
+        )
+        .bind(group_id)
+        .bind(org_id)
+        .bind(device_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_group_grant(&self, org_id: Uuid, group_id: Uuid, team_id: Uuid, access: DeviceAccess) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO device_group_grants (group_id, team_id, access)
+            SELECT g.id, t.id, $4 FROM device_groups g
+            JOIN teams t ON t.org_id = g.org_id
+            WHERE g.id = $1 AND g.org_id = $2 AND t.id = $3
+            ON CONFLICT (group_id, team_id) DO UPDATE SET access = EXCLUDED.access
+            "#,
+        )
+        .bind(group_id)
+        .bind(org_id)
+        .bind(team_id)
+        .bind(access.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remove_group_grant(&self, org_id: Uuid, group_id: Uuid, team_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM device_group_grants WHERE group_id = $1 AND team_id = $3 AND group_id IN (SELECT id FROM device_groups WHERE org_id = $2)",
+        )
+        .bind(group_id)
+        .bind(org_id)
+        .bind(team_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    // License operations
+    async fn create_license(&self, license: &License) -> Result<License> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO licenses (user_id, license_key, license_type, expires_at, is_active, max_devices, max_concurrent_sessions, features)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING {LICENSE_COLUMNS}
+            "#
+        ))
+        .bind(license.user_id)
+        .bind(&license.license_key)
+        .bind(&license.license_type)
+        .bind(license.expires_at)
+        .bind(license.is_active)
+        .bind(license.max_devices)
+        .bind(license.max_concurrent_sessions)
+        .bind(&license.features)
+        .fetch_one(&self.pool)
+        .await?;
+
+        license_from_row(&row)
+    }
+
+    async fn get_license_by_key(&self, license_key: &str) -> Result<Option<License>> {
+        let row = sqlx::query(&format!(
+            "SELECT {LICENSE_COLUMNS} FROM licenses WHERE license_key = $1 AND is_active = true"
+        ))
+        .bind(license_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(license_from_row).transpose()
+    }
+
+    async fn get_user_licenses(&self, user_id: Uuid) -> Result<Vec<License>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {LICENSE_COLUMNS} FROM licenses WHERE user_id = $1 ORDER BY created_at DESC"
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(license_from_row).collect()
+    }
+
+    // Administration
+    async fn set_user_admin(&self, user_id: Uuid, is_admin: bool) -> Result<bool> {
+        let result = sqlx::query("UPDATE users SET is_admin = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(is_admin)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_system_stats(&self) -> Result<SystemStats> {
+        let row = sqlx::query(
+            r#"
+            SELECT (SELECT COUNT(*) FROM users) AS total_users,
+                   (SELECT COUNT(*) FROM devices) AS total_devices,
+                   (SELECT COUNT(*) FROM devices WHERE is_online = true) AS online_devices,
+                   (SELECT COUNT(*) FROM sessions WHERE status = 'active') AS active_sessions,
+                   (SELECT COUNT(*) FROM connections) AS total_connections
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(SystemStats {
+            total_users: row.try_get("total_users")?,
+            total_devices: row.try_get("total_devices")?,
+            online_devices: row.try_get("online_devices")?,
+            active_sessions: row.try_get("active_sessions")?,
+            total_connections: row.try_get("total_connections")?,
+        })
+    }
+
+    async fn get_all_devices(&self, online_only: bool, limit: i64) -> Result<Vec<Device>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {DEVICE_COLUMNS} FROM devices WHERE is_online = true OR NOT $1 ORDER BY last_seen DESC LIMIT $2"
+        ))
+        .bind(online_only)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(device_from_row).collect()
+    }
+
+    async fn get_all_sessions(&self, status: Option<&str>, limit: i64) -> Result<Vec<Session>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {SESSION_COLUMNS} FROM sessions WHERE $1::TEXT IS NULL OR status = $1 ORDER BY started_at DESC LIMIT $2"
+        ))
+        .bind(status)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn get_daily_usage(&self, from: DateTime<Utc>, to: DateTime<Utc>, user_id: Option<Uuid>) -> Result<Vec<DailyUsage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.user_id, u.email, (c.started_at AT TIME ZONE 'UTC')::DATE AS day,
+                   COUNT(*) AS connections,
+                   COALESCE(SUM(c.duration_seconds), 0)::BIGINT AS duration_seconds,
+                   COALESCE(SUM(c.bytes_sent), 0)::BIGINT AS bytes_sent,
+                   COALESCE(SUM(c.bytes_received), 0)::BIGINT AS bytes_received
+            FROM connections c
+            JOIN sessions s ON s.id = c.session_id
+            JOIN users u ON u.id = s.user_id
+            WHERE c.started_at >= $1 AND c.started_at < $2
+              AND ($3::UUID IS NULL OR s.user_id = $3)
+            GROUP BY s.user_id, u.email, day
+            ORDER BY day, u.email
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(DailyUsage {
+                    user_id: row.try_get("user_id")?,
+                    email: row.try_get("email")?,
+                    day: row.try_get("day")?,
+                    connections: row.try_get("connections")?,
+                    duration_seconds: row.try_get("duration_seconds")?,
+                    bytes_sent: row.try_get("bytes_sent")?,
+                    bytes_received: row.try_get("bytes_received")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_licenses(&self, user_id: Option<Uuid>, limit: i64) -> Result<Vec<License>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {LICENSE_COLUMNS} FROM licenses WHERE $1::UUID IS NULL OR user_id = $1 ORDER BY created_at DESC LIMIT $2"
+        ))
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(license_from_row).collect()
+    }
+
+    async fn update_license(&self, license_id: Uuid, update: &LicenseUpdate) -> Result<Option<License>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE licenses
+            SET is_active = COALESCE($2, is_active),
+                expires_at = COALESCE($3, expires_at),
+                max_devices = COALESCE($4, max_devices),
+                max_concurrent_sessions = COALESCE($5, max_concurrent_sessions),
+                features = COALESCE($6, features)
+            WHERE id = $1
+            RETURNING {LICENSE_COLUMNS}
+            "#
+        ))
+        .bind(license_id)
+        .bind(update.is_active)
+        .bind(update.expires_at)
+        .bind(update.max_devices)
+        .bind(update.max_concurrent_sessions)
+        .bind(&update.features)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.as_ref().map(license_from_row).transpose()
+    }
+}
+
+fn user_from_row(row: &PgRow) -> Result<User> {
+    Ok(User {
+        id: row.try_get("id")?,
+        email: row.try_get("email")?,
+        username: row.try_get("username")?,
+        display_name: row.try_get("display_name")?,
+        avatar_url: row.try_get("avatar_url")?,
+        is_active: row.try_get::<Option<bool>, _>("is_active")?.unwrap_or(true),
+        is_verified: row.try_get::<Option<bool>, _>("is_verified")?.unwrap_or(false),
+        subscription_type: row
+            .try_get::<Option<String>, _>("subscription_type")?
+            .unwrap_or_else(|| "free".to_string()),
+        auth_provider: row
+            .try_get::<Option<String>, _>("auth_provider")?
+            .unwrap_or_else(|| "local".to_string()),
+        is_admin: row.try_get::<Option<bool>, _>("is_admin")?.unwrap_or(false),
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+        updated_at: row.try_get::<Option<DateTime<Utc>>, _>("updated_at")?.unwrap_or_else(Utc::now),
+        last_login: row.try_get("last_login")?,
+        preferences: row
+            .try_get::<Option<serde_json::Value>, _>("preferences")?
+            .unwrap_or_else(|| serde_json::json!({})),
+    })
+}
+
+fn device_from_row(row: &PgRow) -> Result<Device> {
+    Ok(Device {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        device_id: row.try_get("device_id")?,
+        org_id: row.try_get("org_id")?,
+        device_name: row.try_get("device_name")?,
+        device_type: row.try_get("device_type")?,
+        os_version: row.try_get("os_version")?,
+        ip_address: row.try_get("ip_address")?,
+        mac_address: row.try_get("mac_address")?,
+        last_seen: row.try_get::<Option<DateTime<Utc>>, _>("last_seen")?.unwrap_or_else(Utc::now),
+        is_online: row.try_get::<Option<bool>, _>("is_online")?.unwrap_or(false),
+        capabilities: row
+            .try_get::<Option<serde_json::Value>, _>("capabilities")?
+            .unwrap_or_else(|| serde_json::json!({})),
+        metadata: row
+            .try_get::<Option<serde_json::Value>, _>("metadata")?
+            .unwrap_or_else(|| serde_json::json!({})),
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+        updated_at: row.try_get::<Option<DateTime<Utc>>, _>("updated_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn session_from_row(row: &PgRow) -> Result<Session> {
+    Ok(Session {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        device_id: row.try_get("device_id")?,
+        remote_device_id: row.try_get("remote_device_id")?,
+        session_type: row.try_get("session_type")?,
+        started_at: row.try_get::<Option<DateTime<Utc>>, _>("started_at")?.unwrap_or_else(Utc::now),
+        ended_at: row.try_get("ended_at")?,
+        duration_seconds: row.try_get("duration_seconds")?,
+        status: row
+            .try_get::<Option<String>, _>("status")?
+            .unwrap_or_else(|| "active".to_string()),
+        connection_quality: row.try_get("connection_quality")?,
+        metadata: row
+            .try_get::<Option<serde_json::Value>, _>("metadata")?
+            .unwrap_or_else(|| serde_json::json!({})),
+    })
+}
+
+fn license_from_row(row: &PgRow) -> Result<License> {
+    Ok(License {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        license_key: row.try_get("license_key")?,
+        license_type: row.try_get("license_type")?,
+        expires_at: row.try_get("expires_at")?,
+        is_active: row.try_get::<Option<bool>, _>("is_active")?.unwrap_or(true),
+        max_devices: row.try_get::<Option<i32>, _>("max_devices")?.unwrap_or(1),
+        max_concurrent_sessions: row.try_get::<Option<i32>, _>("max_concurrent_sessions")?.unwrap_or(1),
+        features: row
+            .try_get::<Option<serde_json::Value>, _>("features")?
+            .unwrap_or_else(|| serde_json::json!({})),
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+        updated_at: row.try_get::<Option<DateTime<Utc>>, _>("updated_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn api_key_from_row(row: &PgRow) -> Result<ApiKey> {
+    Ok(ApiKey {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        name: row.try_get("name")?,
+        key_prefix: row.try_get("key_prefix")?,
+        key_hash: row.try_get("key_hash")?,
+        permissions: row
+            .try_get::<Option<serde_json::Value>, _>("permissions")?
+            .unwrap_or_else(|| serde_json::json!([])),
+        expires_at: row.try_get("expires_at")?,
+        last_used: row.try_get("last_used")?,
+        is_active: row.try_get::<Option<bool>, _>("is_active")?.unwrap_or(false),
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn org_from_row(row: &PgRow) -> Result<Organization> {
+    Ok(Organization {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        created_by: row.try_get("created_by")?,
+        force_relay: row.try_get::<Option<bool>, _>("force_relay")?.unwrap_or(false),
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+        updated_at: row.try_get::<Option<DateTime<Utc>>, _>("updated_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn member_from_row(row: &PgRow) -> Result<OrgMember> {
+    Ok(OrgMember {
+        user_id: row.try_get("user_id")?,
+        email: row.try_get("email")?,
+        username: row.try_get("username")?,
+        display_name: row.try_get("display_name")?,
+        role: parse_column(row, "role")?,
+        joined_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn invitation_from_row(row: &PgRow) -> Result<OrgInvitation> {
+    Ok(OrgInvitation {
+        id: row.try_get("id")?,
+        org_id: row.try_get("org_id")?,
+        email: row.try_get("email")?,
+        role: parse_column(row, "role")?,
+        token_hash: row.try_get("token_hash")?,
+        invited_by: row.try_get("invited_by")?,
+        expires_at: row.try_get("expires_at")?,
+        accepted_at: row.try_get("accepted_at")?,
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn team_from_row(row: &PgRow, members: Vec<Uuid>) -> Result<Team> {
+    Ok(Team {
+        id: row.try_get("id")?,
+        org_id: row.try_get("org_id")?,
+        name: row.try_get("name")?,
+        members,
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+fn device_group_from_row(row: &PgRow, devices: Vec<Uuid>, grants: Vec<DeviceGroupGrant>) -> Result<DeviceGroup> {
+    Ok(DeviceGroup {
+        id: row.try_get("id")?,
+        org_id: row.try_get("org_id")?,
+        name: row.try_get("name")?,
+        description: row.try_get("description")?,
+        devices,
+        grants,
+        created_at: row.try_get::<Option<DateTime<Utc>>, _>("created_at")?.unwrap_or_else(Utc::now),
+    })
+}
+
+/// Strongest of the `access` values in `rows`
+fn strongest_access(rows: &[PgRow]) -> Result<Option<DeviceAccess>> {
+    let mut strongest = None;
+    for row in rows {
+        let access: DeviceAccess = parse_column(row, "access")?;
+        strongest = strongest.max(Some(access));
+    }
+    Ok(strongest)
+}
+
+/// Read a text column holding an enum value
+fn parse_column<T: std::str::FromStr<Err = String>>(row: &PgRow, column: &str) -> Result<T> {
+    let value: String = row.try_get(column)?;
+    value.parse().map_err(anyhow::Error::msg)
+}
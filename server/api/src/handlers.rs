@@ -2,18 +2,22 @@ use axum::{
     Json,
     http::StatusCode,
     response::IntoResponse,
-    extract::{Request, State, Path},
+    extract::{State, Path, Query},
+    Extension,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tracing::{info, error, warn};
+use tracing::{error, info};
 use uuid::Uuid;
-use std::time::SystemTime;
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use genxlink_licensing::LicenseKeyGenerator;
 
 use crate::models::*;
-use crate::db::Database;
-use crate::auth::{AuthService, extract_user, LoginRequest, RegisterRequest, AuthResponse, PasswordChangeRequest, AuthenticatedUser};
+use crate::api_keys::Credential;
+use crate::db;
+use crate::openapi;
+use crate::orgs::{self, DeviceAccess, OrgRole};
+use crate::password;
+use crate::auth::{LoginRequest, RegisterRequest, AuthResponse, PasswordChangeRequest, RefreshRequest, PasswordResetRequest, PasswordResetConfirmRequest, AuthenticatedUser, MfaVerifyRequest, MfaCodeRequest, MfaDisableRequest, TotpEnrollResponse, RecoveryCodesResponse, MfaStatusResponse, CreateApiKeyRequest, CreateApiKeyResponse, OidcAuthorizeRequest, OidcAuthorizeResponse, OidcCallbackRequest};
 
 // Application state
 use crate::AppState;
@@ -56,16 +60,144 @@ pub async fn login(
     }
 }
 
-/// Refresh JWT token
-pub async fn refresh_token(
+/// Start a single sign-on login at the identity provider
+pub async fn oidc_authorize(
+    State(app_state): State<AppState>,
+    Json(request): Json<OidcAuthorizeRequest>,
+) -> Result<Json<OidcAuthorizeResponse>, StatusCode> {
+    match app_state.auth_service.oidc_authorize(request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("OIDC authorize error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Finish a single sign-on login with the code the browser brought back
+pub async fn oidc_callback(
     State(app_state): State<AppState>,
-    request: Request,
+    Json(request): Json<OidcCallbackRequest>,
 ) -> Result<Json<AuthResponse>, StatusCode> {
-    let user = extract_user(&request)?;
-    match app_state.auth_service.refresh_token(user.id).await {
+    match app_state.auth_service.oidc_callback(request).await {
         Ok(response) => Ok(Json(response)),
         Err(e) => {
-            error!("Token refresh error: {}", e);
+            error!("OIDC callback error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Request a password reset token
+pub async fn request_password_reset(
+    State(app_state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    match app_state.auth_service.request_password_reset(request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Password reset request error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Set a new password with a reset token
+pub async fn confirm_password_reset(
+    State(app_state): State<AppState>,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    match app_state.auth_service.confirm_password_reset(request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Password reset error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Second login step with a TOTP or recovery code
+pub async fn verify_mfa(
+    State(app_state): State<AppState>,
+    Json(request): Json<MfaVerifyRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    match app_state.auth_service.verify_mfa(request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("MFA verification error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Get two-factor authentication status
+pub async fn mfa_status(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<MfaStatusResponse>, StatusCode> {
+    match app_state.auth_service.mfa_status(user.id).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("MFA status error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Start TOTP enrollment
+pub async fn enroll_totp(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<TotpEnrollResponse>, StatusCode> {
+    match app_state.auth_service.enroll_totp(&user).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("TOTP enrollment error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Confirm TOTP enrollment
+pub async fn activate_totp(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<MfaCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, StatusCode> {
+    match app_state.auth_service.activate_totp(user.id, request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("TOTP activation error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Regenerate recovery codes
+pub async fn regenerate_recovery_codes(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<MfaCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, StatusCode> {
+    match app_state.auth_service.regenerate_recovery_codes(user.id, request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Recovery code regeneration error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Disable two-factor authentication
+pub async fn disable_mfa(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<MfaDisableRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    match app_state.auth_service.disable_mfa(user.id, request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Disable MFA error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
@@ -90,12 +222,12 @@ pub async fn get_profile(
     })))
 }
 
-/// Refresh JWT token
+/// Exchange a refresh token for new tokens
 pub async fn refresh_token(
     State(app_state): State<AppState>,
-    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<RefreshRequest>,
 ) -> Result<Json<AuthResponse>, StatusCode> {
-    match app_state.auth_service.refresh_token(user.id).await {
+    match app_state.auth_service.refresh(request).await {
         Ok(response) => Ok(Json(response)),
         Err(e) => {
             error!("Token refresh error: {}", e);
@@ -104,6 +236,40 @@ pub async fn refresh_token(
     }
 }
 
+/// Sign out the current session
+pub async fn logout(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Extension(credential): Extension<Credential>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    let session_id = match credential {
+        Credential::Session { session_id } => session_id,
+        Credential::ApiKey { .. } => return Err(StatusCode::FORBIDDEN),
+    };
+
+    match app_state.auth_service.logout(user.id, session_id).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Logout error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Sign out every session of the user
+pub async fn logout_all(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    match app_state.auth_service.logout_all(user.id).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Logout error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 /// Update user profile
 #[derive(Debug, Deserialize)]
 pub struct UpdateProfileRequest {
@@ -113,9 +279,9 @@ pub struct UpdateProfileRequest {
 }
 
 pub async fn update_profile(
-    State(app_state): State<AppState>,
-    AuthenticatedUser(user): AuthenticatedUser,
-    Json(update): Json<UpdateProfileRequest>,
+    State(_app_state): State<AppState>,
+    AuthenticatedUser(_user): AuthenticatedUser,
+    Json(_update): Json<UpdateProfileRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     
     // TODO: Implement profile update in database
@@ -127,6 +293,8 @@ pub async fn update_profile(
 }
 
 /// Register a new device
+///
+/// Enrolling a device in an organization requires an admin role there.
 pub async fn register_device(
     State(app_state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
@@ -137,9 +305,14 @@ pub async fn register_device(
     if device.user_id != user.id {
         return Err(StatusCode::FORBIDDEN);
     }
+    if let Some(org_id) = device.org_id {
+        require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    }
     
     match app_state.db.register_device(&device).await {
-        Ok(device) => Ok(Json(device)),
+        Ok(Some(device)) => Ok(Json(device)),
+        // The device id belongs to someone else's device
+        Ok(None) => Err(StatusCode::CONFLICT),
         Err(e) => {
             error!("Device registration error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -147,12 +320,12 @@ pub async fn register_device(
     }
 }
 
-/// Get user devices
+/// Get the devices the user owns or can reach through organizations
 pub async fn get_devices(
     State(app_state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
 ) -> Result<Json<Vec<Device>>, StatusCode> {
-    match app_state.db.get_user_devices(user.id).await {
+    match orgs::accessible_devices(&app_state.db, user.id).await {
         Ok(devices) => Ok(Json(devices)),
         Err(e) => {
             error!("Get devices error: {}", e);
@@ -170,9 +343,18 @@ pub struct UpdateDeviceStatusRequest {
 pub async fn update_device_status(
     State(app_state): State<AppState>,
     Path(device_id): Path<String>,
-    AuthenticatedUser(_user): AuthenticatedUser,
+    AuthenticatedUser(user): AuthenticatedUser,
     Json(status): Json<UpdateDeviceStatusRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
+    let device = match app_state.db.get_device_by_device_id(&device_id).await {
+        Ok(Some(device)) => device,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get device error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    require_device_access(&app_state, user.id, &device, DeviceAccess::Control).await?;
     
     match app_state.db.update_device_online_status(&device_id, status.is_online).await {
         Ok(_) => Ok(Json(serde_json::json!({
@@ -187,6 +369,8 @@ pub async fn update_device_status(
 }
 
 /// Create a new session
+///
+/// The user needs control of both the local and the remote device.
 pub async fn create_session(
     State(app_state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
@@ -197,6 +381,10 @@ pub async fn create_session(
     if session.user_id != user.id {
         return Err(StatusCode::FORBIDDEN);
     }
+    for device_id in [session.device_id, session.remote_device_id] {
+        let device = get_device(&app_state, device_id).await?;
+        require_device_access(&app_state, user.id, &device, DeviceAccess::Control).await?;
+    }
     
     match app_state.db.create_session(&session).await {
         Ok(session) => Ok(Json(session)),
@@ -222,14 +410,38 @@ pub async fn get_sessions(
 }
 
 /// End a session
+///
+/// Sessions can be ended by the user who started them, or by an admin of
+/// the organization the remote device is enrolled in. The body optionally
+/// reports the connection's traffic; ending an ended session is a no-op.
 pub async fn end_session(
     State(app_state): State<AppState>,
     Path(session_id): Path<Uuid>,
     AuthenticatedUser(user): AuthenticatedUser,
+    traffic: Option<Json<ConnectionTraffic>>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
+    let session = match app_state.db.get_session(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get session error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if session.user_id != user.id {
+        let remote = get_device(&app_state, session.remote_device_id).await?;
+        let org_id = remote.org_id.ok_or(StatusCode::NOT_FOUND)?;
+        require_org_role(&app_state, org_id, user.id, OrgRole::Admin)
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+    }
     
-    // TODO: Verify session belongs to user before ending
-    match app_state.db.end_session(session_id).await {
+    let traffic = traffic.map(|Json(traffic)| traffic).unwrap_or_default();
+    if traffic.bytes_sent < 0 || traffic.bytes_received < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match app_state.db.end_session(session_id, "ended", traffic).await {
         Ok(_) => Ok(Json(serde_json::json!({
             "success": true,
             "message": "Session ended"
@@ -241,206 +453,1104 @@ pub async fn end_session(
     }
 }
 
-/// License activation request
-#[derive(Debug, Deserialize)]
-pub struct LicenseActivationRequest {
-    pub license_key: String,
+/// STUN and TURN servers for a session
+///
+/// Both ends ask for their own list: the user who started the session, and
+/// the host, which can control the remote device. TURN credentials are bound
+/// to the session and expire; clients fetch a new list before `expires_at`.
+/// If either device belongs to an organization that forces relaying, only
+/// TURN servers are returned.
+pub async fn get_session_ice_servers(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<genxlink_protocol::IceConfiguration>, StatusCode> {
+    let session = match app_state.db.get_session(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get session error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let remote = get_device(&app_state, session.remote_device_id).await?;
+    if session.user_id != user.id {
+        require_device_access(&app_state, user.id, &remote, DeviceAccess::Control)
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+    }
+    if session.ended_at.is_some() {
+        return Err(StatusCode::GONE);
+    }
+
+    let local = get_device(&app_state, session.device_id).await?;
+    let force_relay = requires_relay(&app_state, &local).await? || requires_relay(&app_state, &remote).await?;
+
+    let configuration = match app_state.ice.issue(session.id, force_relay, Utc::now()) {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            error!("ICE configuration error for session {}: {}", session.id, e);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+
+    // A client could ignore the relay-only transport policy, so the signaling
+    // server has to keep direct candidates from reaching the other device
+    if force_relay {
+        let Some(signaling) = app_state.signaling.as_ref() else {
+            error!("Cannot enforce relay policy for session {}: no signaling server admin endpoint configured", session.id);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        };
+        signaling
+            .require_relay([&local.device_id, &remote.device_id], configuration.expires_at)
+            .await
+            .map_err(|e| {
+                error!("Failed to enforce relay policy for session {} through signaling: {}", session.id, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+    }
+    Ok(Json(configuration))
 }
 
-#[derive(Debug, Serialize)]
-pub struct LicenseActivationResponse {
-    pub success: bool,
-    pub message: String,
-    pub license: Option<License>,
+/// Whether the device's organization only allows relayed connections
+async fn requires_relay(app_state: &AppState, device: &Device) -> Result<bool, StatusCode> {
+    let Some(org_id) = device.org_id else {
+        return Ok(false);
+    };
+    match app_state.db.get_organization(org_id).await {
+        Ok(org) => Ok(org.is_some_and(|org| org.force_relay)),
+        Err(e) => {
+            error!("Get organization error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-pub async fn activate_license(
-    State(app_state): State<AppState>,
-    AuthenticatedUser(user): AuthenticatedUser,
-    Json(license_request): Json<LicenseActivationRequest>,
-) -> Result<Json<LicenseActivationResponse>, StatusCode> {
-    
-    match app_state.db.get_license_by_key(&license_request.license_key).await {
-        Ok(Some(license)) => {
-            if license.user_id != user.id {
-                return Ok(Json(LicenseActivationResponse {
-                    success: false,
-                    message: "License does not belong to this user".to_string(),
-                    license: None,
-                }));
-            }
-            
-            Ok(Json(LicenseActivationResponse {
-                success: true,
-                message: "License activated successfully".to_string(),
-                license: Some(license),
-            }))
+async fn get_device(app_state: &AppState, id: Uuid) -> Result<Device, StatusCode> {
+    match app_state.db.get_device(id).await {
+        Ok(Some(device)) => Ok(device),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get device error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
-        Ok(None) => Ok(Json(LicenseActivationResponse {
-            success: false,
-            message: "Invalid license key".to_string(),
-            license: None,
-        })),
+    }
+}
+
+/// Require at least `needed` access to a device
+///
+/// Devices the user can't see at all are reported as not found.
+async fn require_device_access(
+    app_state: &AppState,
+    user_id: Uuid,
+    device: &Device,
+    needed: DeviceAccess,
+) -> Result<(), StatusCode> {
+    match orgs::device_access(&app_state.db, user_id, device).await {
+        Ok(Some(access)) if access >= needed => Ok(()),
+        Ok(Some(_)) => Err(StatusCode::FORBIDDEN),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            error!("License activation error: {}", e);
+            error!("Device access error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-/// Get license status
-pub async fn license_status(
-    State(app_state): State<AppState>,
-    AuthenticatedUser(user): AuthenticatedUser,
-) -> Result<Json<Vec<License>>, StatusCode> {
-    match app_state.db.get_user_licenses(user.id).await {
-        Ok(licenses) => Ok(Json(licenses)),
+/// Role of the user in an organization, requiring at least `minimum`
+///
+/// Non-members get 404 so organization ids can't be probed.
+async fn require_org_role(
+    app_state: &AppState,
+    org_id: Uuid,
+    user_id: Uuid,
+    minimum: OrgRole,
+) -> Result<OrgRole, StatusCode> {
+    match app_state.db.get_member_role(org_id, user_id).await {
+        Ok(Some(role)) if role >= minimum => Ok(role),
+        Ok(Some(_)) => Err(StatusCode::FORBIDDEN),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            error!("Get license status error: {}", e);
+            error!("Organization role error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-/// Change password
-pub async fn change_password(
-    State(app_state): State<AppState>,
-    AuthenticatedUser(user): AuthenticatedUser,
-    Json(request): Json<PasswordChangeRequest>,
-) -> Result<Json<AuthResponse>, StatusCode> {
-    match app_state.auth_service.change_password(user.id, request).await {
-        Ok(response) => Ok(Json(response)),
+/// Map a repository result to 404 when nothing matched
+fn found(result: anyhow::Result<bool>, context: &str) -> Result<Json<serde_json::Value>, StatusCode> {
+    match result {
+        Ok(true) => Ok(Json(serde_json::json!({ "success": true }))),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
         Err(e) => {
-            error!("Change password error: {}", e);
+            error!("{} error: {}", context, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-/// Start connection request
+/// Map a failed insert to 409 if it hit a unique constraint
+fn conflict_or_internal(e: anyhow::Error, context: &str) -> StatusCode {
+    if db::is_unique_violation(&e) {
+        return StatusCode::CONFLICT;
+    }
+    error!("{} error: {}", context, e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Create organization request
 #[derive(Debug, Deserialize)]
-pub struct StartConnectionRequest {
-    pub from_device_id: Uuid,
-    pub to_device_id: Uuid,
-    pub connection_type: String,
+pub struct CreateOrganizationRequest {
+    pub name: String,
 }
 
+/// Organization together with the caller's role in it
 #[derive(Debug, Serialize)]
-pub struct StartConnectionResponse {
-    pub success: bool,
-    pub message: String,
-    pub session_id: Option<Uuid>,
+pub struct OrganizationSummary {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrganizationDetails {
+    #[serde(flatten)]
+    pub organization: Organization,
+    pub role: OrgRole,
+    pub members: Vec<OrgMember>,
 }
 
-pub async fn start_connection(
+/// Create an organization owned by the caller
+pub async fn create_organization(
     State(app_state): State<AppState>,
     AuthenticatedUser(user): AuthenticatedUser,
-    Json(connection_request): Json<StartConnectionRequest>,
-) -> Result<Json<StartConnectionResponse>, StatusCode> {
-    
-    // Create a new session for the connection
-    let session = Session {
-        id: Uuid::new_v4(),
-        user_id: user.id,
-        device_id: connection_request.from_device_id,
-        remote_device_id: connection_request.to_device_id,
-        session_type: connection_request.connection_type,
-        started_at: SystemTime::now().into(),
-        ended_at: None,
-        duration_seconds: None,
-        status: "active".to_string(),
-        connection_quality: None,
-        metadata: serde_json::json!({}),
-    };
-    
-    match app_state.db.create_session(&session).await {
-        Ok(created_session) => Ok(Json(StartConnectionResponse {
-            success: true,
-            message: "Connection started successfully".to_string(),
-            session_id: Some(created_session.id),
-        })),
+    Json(request): Json<CreateOrganizationRequest>,
+) -> Result<Json<OrganizationSummary>, StatusCode> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match app_state.db.create_organization(name, user.id).await {
+        Ok(organization) => Ok(Json(OrganizationSummary { organization, role: OrgRole::Owner })),
         Err(e) => {
-            error!("Start connection error: {}", e);
+            error!("Create organization error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-/// End connection request
-#[derive(Debug, Deserialize)]
-pub struct EndConnectionRequest {
-    pub session_id: Uuid,
-}
-
-#[derive(Debug, Serialize)]
-pub struct EndConnectionResponse {
-    pub success: bool,
-    pub message: String,
+/// List the caller's organizations
+pub async fn get_organizations(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<OrganizationSummary>>, StatusCode> {
+    match app_state.db.get_user_organizations(user.id).await {
+        Ok(orgs) => Ok(Json(
+            orgs.into_iter()
+                .map(|(organization, role)| OrganizationSummary { organization, role })
+                .collect(),
+        )),
+        Err(e) => {
+            error!("Get organizations error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
-pub async fn end_connection(
+/// Get an organization with its members
+pub async fn get_organization(
     State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
     AuthenticatedUser(user): AuthenticatedUser,
-    Json(connection_request): Json<EndConnectionRequest>,
-) -> Result<Json<EndConnectionResponse>, StatusCode> {
-    
-    // TODO: Verify session belongs to user before ending
-    match app_state.db.end_session(connection_request.session_id).await {
-        Ok(_) => Ok(Json(EndConnectionResponse {
-            success: true,
-            message: "Connection ended successfully".to_string(),
-        })),
+) -> Result<Json<OrganizationDetails>, StatusCode> {
+    let role = require_org_role(&app_state, org_id, user.id, OrgRole::Member).await?;
+
+    let organization = match app_state.db.get_organization(org_id).await {
+        Ok(Some(organization)) => organization,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get organization error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    match app_state.db.get_org_members(org_id).await {
+        Ok(members) => Ok(Json(OrganizationDetails { organization, role, members })),
         Err(e) => {
-            error!("End connection error: {}", e);
+            error!("Get organization members error: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
 }
 
-/// Get system statistics
-#[derive(Debug, Serialize)]
-pub struct SystemStats {
-    pub total_users: i64,
-    pub total_devices: i64,
-    pub active_sessions: i64,
-    pub total_connections: i64,
+/// Delete an organization (owners only)
+///
+/// Enrolled devices stay with their owners.
+pub async fn delete_organization(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Owner).await?;
+    found(app_state.db.delete_organization(org_id).await, "Delete organization")
 }
 
-pub async fn get_system_stats(
-    State(_app_state): State<AppState>,
-    AuthenticatedUser(_user): AuthenticatedUser,
-) -> Result<Json<SystemStats>, StatusCode> {
-    
-    // TODO: Implement actual statistics queries
-    let stats = SystemStats {
-        total_users: 0,
-        total_devices: 0,
-        active_sessions: 0,
-        total_connections: 0,
-    };
-    
-    Ok(Json(stats))
+#[derive(Debug, Deserialize)]
+pub struct RelayPolicyRequest {
+    pub force_relay: bool,
 }
 
-/// Get API usage statistics
-#[derive(Debug, Serialize)]
-pub struct UsageStats {
-    pub api_calls: i64,
-    pub bandwidth_used: i64,
-    pub session_duration: i64,
+/// Require sessions with the organization's devices to go through TURN
+pub async fn set_org_relay_policy(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<RelayPolicyRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    if request.force_relay && !app_state.ice.has_turn() {
+        // Every session would fail to get ICE servers
+        return Err(StatusCode::CONFLICT);
+    }
+    info!("Organization {} relay-only: {}", org_id, request.force_relay);
+    found(app_state.db.set_org_force_relay(org_id, request.force_relay).await, "Set relay policy")
 }
 
-pub async fn get_usage_stats(
-    State(_app_state): State<AppState>,
-    AuthenticatedUser(_user): AuthenticatedUser,
-) -> Result<Json<UsageStats>, StatusCode> {
-    
-    // TODO: Implement actual usage statistics for the user
-    let stats = UsageStats {
-        api_calls: 0,
-        bandwidth_used: 0,
-        session_duration: 0,
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRoleRequest {
+    pub role: OrgRole,
+}
+
+/// Change a member's role
+pub async fn update_member_role(
+    State(app_state): State<AppState>,
+    Path((org_id, member_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<UpdateMemberRoleRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let role = require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    let current = member_role(&app_state, org_id, member_id).await?;
+    if !role.can_assign(current) || !role.can_assign(request.role) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if current == OrgRole::Owner && request.role != OrgRole::Owner {
+        require_other_owner(&app_state, org_id).await?;
+    }
+
+    found(app_state.db.set_member_role(org_id, member_id, request.role).await, "Update member role")
+}
+
+/// Remove a member, or leave the organization when removing oneself
+pub async fn remove_member(
+    State(app_state): State<AppState>,
+    Path((org_id, member_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let current = if member_id == user.id {
+        require_org_role(&app_state, org_id, user.id, OrgRole::Member).await?
+    } else {
+        let role = require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+        let current = member_role(&app_state, org_id, member_id).await?;
+        if !role.can_assign(current) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        current
+    };
+    if current == OrgRole::Owner {
+        require_other_owner(&app_state, org_id).await?;
+    }
+
+    found(app_state.db.remove_org_member(org_id, member_id).await, "Remove member")
+}
+
+async fn member_role(app_state: &AppState, org_id: Uuid, user_id: Uuid) -> Result<OrgRole, StatusCode> {
+    match app_state.db.get_member_role(org_id, user_id).await {
+        Ok(Some(role)) => Ok(role),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get member role error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Organizations must keep at least one owner
+async fn require_other_owner(app_state: &AppState, org_id: Uuid) -> Result<(), StatusCode> {
+    match app_state.db.count_org_owners(org_id).await {
+        Ok(owners) if owners > 1 => Ok(()),
+        Ok(_) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            error!("Count owners error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    pub role: OrgRole,
+}
+
+/// Created invitation; the token is only ever returned here
+#[derive(Debug, Serialize)]
+pub struct CreateInvitationResponse {
+    pub invitation: OrgInvitation,
+    pub token: String,
+}
+
+/// Invite someone to the organization by email
+pub async fn create_invitation(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<CreateInvitationRequest>,
+) -> Result<Json<CreateInvitationResponse>, StatusCode> {
+    let role = require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    if !role.can_assign(request.role) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let email = request.email.trim().to_lowercase();
+    if !email.contains('@') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let token = password::generate_token();
+    let invitation = OrgInvitation {
+        id: Uuid::new_v4(),
+        org_id,
+        email,
+        role: request.role,
+        token_hash: password::hash_token(&token),
+        invited_by: Some(user.id),
+        expires_at: Utc::now() + orgs::INVITATION_LIFETIME,
+        accepted_at: None,
+        created_at: Utc::now(),
+    };
+    match app_state.db.create_org_invitation(&invitation).await {
+        Ok(()) => Ok(Json(CreateInvitationResponse { invitation, token })),
+        Err(e) => {
+            error!("Create invitation error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// List pending invitations
+pub async fn get_invitations(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<OrgInvitation>>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    match app_state.db.get_pending_invitations(org_id).await {
+        Ok(invitations) => Ok(Json(invitations)),
+        Err(e) => {
+            error!("Get invitations error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Revoke a pending invitation
+pub async fn revoke_invitation(
+    State(app_state): State<AppState>,
+    Path((org_id, invitation_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.revoke_org_invitation(org_id, invitation_id).await, "Revoke invitation")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptInvitationRequest {
+    pub token: String,
+}
+
+/// Join an organization with an invitation sent to the caller's email
+pub async fn accept_invitation(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<AcceptInvitationRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let token_hash = password::hash_token(&request.token);
+    match app_state.db.accept_org_invitation(&token_hash, user.id, &user.email).await {
+        Ok(Some((org_id, role))) => Ok(Json(serde_json::json!({
+            "success": true,
+            "org_id": org_id,
+            "role": role
+        }))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Accept invitation error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTeamRequest {
+    pub name: String,
+}
+
+pub async fn create_team(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<CreateTeamRequest>,
+) -> Result<Json<Team>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    app_state.db.create_team(org_id, name).await
+        .map(Json)
+        .map_err(|e| conflict_or_internal(e, "Create team"))
+}
+
+pub async fn get_teams(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<Team>>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Member).await?;
+    match app_state.db.get_org_teams(org_id).await {
+        Ok(teams) => Ok(Json(teams)),
+        Err(e) => {
+            error!("Get teams error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn delete_team(
+    State(app_state): State<AppState>,
+    Path((org_id, team_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.delete_team(org_id, team_id).await, "Delete team")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddTeamMemberRequest {
+    pub user_id: Uuid,
+}
+
+/// Add an organization member to a team
+pub async fn add_team_member(
+    State(app_state): State<AppState>,
+    Path((org_id, team_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<AddTeamMemberRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.add_team_member(org_id, team_id, request.user_id).await, "Add team member")
+}
+
+pub async fn remove_team_member(
+    State(app_state): State<AppState>,
+    Path((org_id, team_id, member_id)): Path<(Uuid, Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.remove_team_member(org_id, team_id, member_id).await, "Remove team member")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceGroupRequest {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+pub async fn create_device_group(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<CreateDeviceGroupRequest>,
+) -> Result<Json<DeviceGroup>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    app_state.db.create_device_group(org_id, name, request.description.as_deref()).await
+        .map(Json)
+        .map_err(|e| conflict_or_internal(e, "Create device group"))
+}
+
+pub async fn get_device_groups(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<DeviceGroup>>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Member).await?;
+    match app_state.db.get_org_device_groups(org_id).await {
+        Ok(groups) => Ok(Json(groups)),
+        Err(e) => {
+            error!("Get device groups error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn delete_device_group(
+    State(app_state): State<AppState>,
+    Path((org_id, group_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.delete_device_group(org_id, group_id).await, "Delete device group")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddGroupDeviceRequest {
+    /// `Device::id` of a device enrolled in the organization
+    pub device_id: Uuid,
+}
+
+pub async fn add_group_device(
+    State(app_state): State<AppState>,
+    Path((org_id, group_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<AddGroupDeviceRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.add_group_device(org_id, group_id, request.device_id).await, "Add group device")
+}
+
+pub async fn remove_group_device(
+    State(app_state): State<AppState>,
+    Path((org_id, group_id, device_id)): Path<(Uuid, Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.remove_group_device(org_id, group_id, device_id).await, "Remove group device")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupGrantRequest {
+    pub team_id: Uuid,
+    pub access: DeviceAccess,
+}
+
+/// Grant a team access to a device group, replacing any earlier grant
+pub async fn set_group_grant(
+    State(app_state): State<AppState>,
+    Path((org_id, group_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<GroupGrantRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(
+        app_state.db.set_group_grant(org_id, group_id, request.team_id, request.access).await,
+        "Set group grant",
+    )
+}
+
+pub async fn remove_group_grant(
+    State(app_state): State<AppState>,
+    Path((org_id, group_id, team_id)): Path<(Uuid, Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.remove_group_grant(org_id, group_id, team_id).await, "Remove group grant")
+}
+
+/// Devices of the organization visible to the caller
+pub async fn get_org_devices(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<Device>>, StatusCode> {
+    let role = require_org_role(&app_state, org_id, user.id, OrgRole::Member).await?;
+    let devices = if role.is_admin() {
+        app_state.db.get_org_devices(org_id).await
+    } else {
+        orgs::accessible_devices(&app_state.db, user.id).await
     };
+
+    match devices {
+        Ok(devices) => Ok(Json(
+            devices.into_iter().filter(|device| device.org_id == Some(org_id)).collect(),
+        )),
+        Err(e) => {
+            error!("Get organization devices error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Take a device out of the organization; it stays with its owner
+pub async fn remove_org_device(
+    State(app_state): State<AppState>,
+    Path((org_id, device_id)): Path<(Uuid, Uuid)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    found(app_state.db.remove_org_device(org_id, device_id).await, "Remove organization device")
+}
+
+/// Sessions to or from devices of the organization (admins only)
+pub async fn get_org_sessions(
+    State(app_state): State<AppState>,
+    Path(org_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<Session>>, StatusCode> {
+    require_org_role(&app_state, org_id, user.id, OrgRole::Admin).await?;
+    match app_state.db.get_org_sessions(org_id, 50).await {
+        Ok(sessions) => Ok(Json(sessions)),
+        Err(e) => {
+            error!("Get organization sessions error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Create a personal API key
+pub async fn create_api_key(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreateApiKeyResponse>, StatusCode> {
+    match app_state.auth_service.create_api_key(&user, request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("API key creation error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// List the user's API keys (without the keys themselves)
+pub async fn get_api_keys(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<ApiKey>>, StatusCode> {
+    match app_state.db.get_user_api_keys(user.id).await {
+        Ok(keys) => Ok(Json(keys)),
+        Err(e) => {
+            error!("Get API keys error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Revoke an API key
+pub async fn revoke_api_key(
+    State(app_state): State<AppState>,
+    Path(key_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match app_state.db.revoke_api_key(user.id, key_id).await {
+        Ok(true) => Ok(Json(serde_json::json!({
+            "success": true,
+            "message": "API key revoked"
+        }))),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Revoke API key error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// License activation request
+#[derive(Debug, Deserialize)]
+pub struct LicenseActivationRequest {
+    pub license_key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LicenseActivationResponse {
+    pub success: bool,
+    pub message: String,
+    pub license: Option<License>,
+}
+
+pub async fn activate_license(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Json(license_request): Json<LicenseActivationRequest>,
+) -> Result<Json<LicenseActivationResponse>, StatusCode> {
     
-    Ok(Json(stats))
+    match app_state.db.get_license_by_key(&license_request.license_key).await {
+        Ok(Some(license)) => {
+            if license.user_id != user.id {
+                return Ok(Json(LicenseActivationResponse {
+                    success: false,
+                    message: "License does not belong to this user".to_string(),
+                    license: None,
+                }));
+            }
+            
+            Ok(Json(LicenseActivationResponse {
+                success: true,
+                message: "License activated successfully".to_string(),
+                license: Some(license),
+            }))
+        }
+        Ok(None) => Ok(Json(LicenseActivationResponse {
+            success: false,
+            message: "Invalid license key".to_string(),
+            license: None,
+        })),
+        Err(e) => {
+            error!("License activation error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Get license status
+pub async fn license_status(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<License>>, StatusCode> {
+    match app_state.db.get_user_licenses(user.id).await {
+        Ok(licenses) => Ok(Json(licenses)),
+        Err(e) => {
+            error!("Get license status error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Change password
+pub async fn change_password(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Extension(credential): Extension<Credential>,
+    Json(request): Json<PasswordChangeRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    let current_session = match credential {
+        Credential::Session { session_id } => Some(session_id),
+        Credential::ApiKey { .. } => None,
+    };
+
+    match app_state.auth_service.change_password(user.id, current_session, request).await {
+        Ok(response) => Ok(Json(response)),
+        Err(e) => {
+            error!("Change password error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Get the user's recorded usage
+pub async fn get_usage_stats(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<UsageStats>, StatusCode> {
+    match app_state.db.get_user_usage(user.id).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => {
+            error!("Get usage stats error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Administration (`/api/admin`, platform administrators only)
+
+/// Default and largest page size of admin listings
+const ADMIN_LIST_LIMIT: i64 = 100;
+const ADMIN_LIST_MAX_LIMIT: i64 = 1000;
+
+/// Longest range of a usage report, in days
+const USAGE_REPORT_MAX_DAYS: i64 = 366;
+
+fn admin_list_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(ADMIN_LIST_LIMIT).clamp(1, ADMIN_LIST_MAX_LIMIT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminDevicesQuery {
+    /// Only devices currently online
+    #[serde(default)]
+    pub online: bool,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminSessionsQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Usage report range; both days are inclusive and default to the last 30 days
+#[derive(Debug, Deserialize)]
+pub struct AdminUsageQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminLicensesQuery {
+    pub user_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssueLicenseRequest {
+    pub user_id: Uuid,
+    pub license_type: String,
+    pub expires_at: DateTime<Utc>,
+    pub max_devices: Option<i32>,
+    pub max_concurrent_sessions: Option<i32>,
+    pub features: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetAdminRequest {
+    pub is_admin: bool,
+}
+
+const LICENSE_TYPES: [&str; 4] = ["trial", "basic", "pro", "enterprise"];
+
+/// OpenAPI description of the admin API
+pub async fn admin_openapi() -> Json<serde_json::Value> {
+    Json(openapi::admin_spec())
+}
+
+/// Platform-wide counts
+pub async fn admin_system_stats(
+    State(app_state): State<AppState>,
+) -> Result<Json<SystemStats>, StatusCode> {
+    match app_state.db.get_system_stats().await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => {
+            error!("Get system stats error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Devices of all users, most recently seen first
+pub async fn admin_devices(
+    State(app_state): State<AppState>,
+    Query(query): Query<AdminDevicesQuery>,
+) -> Result<Json<Vec<Device>>, StatusCode> {
+    match app_state.db.get_all_devices(query.online, admin_list_limit(query.limit)).await {
+        Ok(devices) => Ok(Json(devices)),
+        Err(e) => {
+            error!("Get all devices error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Sessions of all users, newest first
+pub async fn admin_sessions(
+    State(app_state): State<AppState>,
+    Query(query): Query<AdminSessionsQuery>,
+) -> Result<Json<Vec<Session>>, StatusCode> {
+    match app_state.db.get_all_sessions(query.status.as_deref(), admin_list_limit(query.limit)).await {
+        Ok(sessions) => Ok(Json(sessions)),
+        Err(e) => {
+            error!("Get all sessions error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Force-disconnect an active session
+///
+/// Both devices are told through the signaling server to close the session,
+/// then it is marked `terminated`; traffic is not known and recorded as zero.
+pub async fn admin_disconnect_session(
+    State(app_state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+    AuthenticatedUser(admin): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let session = match app_state.db.get_session(session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get session error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if session.status != "active" {
+        return Err(StatusCode::CONFLICT);
+    }
+    let Some(signaling) = app_state.signaling.as_ref() else {
+        error!("Cannot disconnect session {}: no signaling server admin endpoint configured", session_id);
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    };
+
+    // The devices close the session; the record only follows once they've been told
+    let mut peers = Vec::with_capacity(2);
+    for device_id in [session.device_id, session.remote_device_id] {
+        match app_state.db.get_device(device_id).await {
+            Ok(Some(device)) => peers.push(device.device_id),
+            Ok(None) => {
+                error!("Session {} refers to missing device {}", session_id, device_id);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            Err(e) => {
+                error!("Get device error: {}", e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+    let notified = signaling
+        .terminate_session([&peers[0], &peers[1]], "Terminated by an administrator")
+        .await
+        .map_err(|e| {
+            error!("Failed to disconnect session {} through signaling: {}", session_id, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    match app_state.db.end_session(session_id, "terminated", ConnectionTraffic::default()).await {
+        Ok(true) => {
+            info!("Session {} terminated by admin {}", session_id, admin.id);
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Session terminated",
+                "notified": notified
+            })))
+        }
+        // Ended by someone else in the meantime
+        Ok(false) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            error!("Terminate session error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Connections per user and day (UTC)
+pub async fn admin_usage(
+    State(app_state): State<AppState>,
+    Query(query): Query<AdminUsageQuery>,
+) -> Result<Json<Vec<DailyUsage>>, StatusCode> {
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(29));
+    let days = (to - from).num_days() + 1;
+    if !(1..=USAGE_REPORT_MAX_DAYS).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let start = from.and_time(NaiveTime::MIN).and_utc();
+    let end = (to + chrono::Duration::days(1)).and_time(NaiveTime::MIN).and_utc();
+
+    match app_state.db.get_daily_usage(start, end, query.user_id).await {
+        Ok(usage) => Ok(Json(usage)),
+        Err(e) => {
+            error!("Get daily usage error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Licenses of all users (or one), newest first
+pub async fn admin_licenses(
+    State(app_state): State<AppState>,
+    Query(query): Query<AdminLicensesQuery>,
+) -> Result<Json<Vec<License>>, StatusCode> {
+    match app_state.db.get_licenses(query.user_id, admin_list_limit(query.limit)).await {
+        Ok(licenses) => Ok(Json(licenses)),
+        Err(e) => {
+            error!("Get licenses error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Issue a license with a newly generated key
+pub async fn admin_issue_license(
+    State(app_state): State<AppState>,
+    AuthenticatedUser(admin): AuthenticatedUser,
+    Json(request): Json<IssueLicenseRequest>,
+) -> Result<Json<License>, StatusCode> {
+    if !LICENSE_TYPES.contains(&request.license_type.as_str())
+        || request.max_devices.is_some_and(|n| n < 1)
+        || request.max_concurrent_sessions.is_some_and(|n| n < 1)
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match app_state.db.get_user_by_id(request.user_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Get user error: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let now = Utc::now();
+    let license = License {
+        id: Uuid::new_v4(),
+        user_id: request.user_id,
+        license_key: LicenseKeyGenerator::generate(),
+        license_type: request.license_type,
+        expires_at: request.expires_at,
+        is_active: true,
+        max_devices: request.max_devices.unwrap_or(1),
+        max_concurrent_sessions: request.max_concurrent_sessions.unwrap_or(1),
+        features: request.features.unwrap_or_else(|| serde_json::json!({})),
+        created_at: now,
+        updated_at: now,
+    };
+    match app_state.db.create_license(&license).await {
+        Ok(license) => {
+            info!("License {} issued to user {} by admin {}", license.id, license.user_id, admin.id);
+            Ok(Json(license))
+        }
+        Err(e) => {
+            error!("Issue license error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Change a license (activate, revoke, extend, resize)
+pub async fn admin_update_license(
+    State(app_state): State<AppState>,
+    Path(license_id): Path<Uuid>,
+    Json(update): Json<LicenseUpdate>,
+) -> Result<Json<License>, StatusCode> {
+    if update.max_devices.is_some_and(|n| n < 1) || update.max_concurrent_sessions.is_some_and(|n| n < 1) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match app_state.db.update_license(license_id, &update).await {
+        Ok(Some(license)) => Ok(Json(license)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Update license error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Grant or revoke platform administration
+///
+/// Admins can't revoke their own rights, so the last admin can't lock everyone out.
+pub async fn admin_set_user_admin(
+    State(app_state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    AuthenticatedUser(admin): AuthenticatedUser,
+    Json(request): Json<SetAdminRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if user_id == admin.id && !request.is_admin {
+        return Err(StatusCode::CONFLICT);
+    }
+    match app_state.db.set_user_admin(user_id, request.is_admin).await {
+        Ok(true) => {
+            info!("Admin rights of user {} set to {} by admin {}", user_id, request.is_admin, admin.id);
+            Ok(Json(serde_json::json!({
+                "success": true,
+                "message": "Admin rights updated"
+            })))
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Set user admin error: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
@@ -0,0 +1,175 @@
+//! ICE server provisioning
+//!
+//! Clients get their STUN and TURN servers from the API for each session
+//! instead of a list built into the client. TURN credentials follow the TURN
+//! REST API shared-secret scheme (coturn's `use-auth-secret`): the username is
+//! `<expiry unix time>:<session id>` and the password is
+//! base64(HMAC-SHA1(secret, username)), so the TURN server checks them without
+//! calling back and they lapse on their own.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::env;
+use uuid::Uuid;
+
+use genxlink_protocol::{IceConfiguration, IceServer, IceTransportPolicy, NatConfig};
+
+/// How long issued TURN credentials stay valid by default
+pub const DEFAULT_CREDENTIAL_TTL: Duration = Duration::hours(1);
+
+/// STUN and TURN servers handed out to clients
+#[derive(Clone)]
+pub struct IceConfig {
+    pub stun_urls: Vec<String>,
+    pub turn_urls: Vec<String>,
+    turn_secret: Option<String>,
+    pub credential_ttl: Duration,
+}
+
+impl Default for IceConfig {
+    /// Public STUN servers only
+    fn default() -> Self {
+        Self {
+            stun_urls: NatConfig::default().stun_servers,
+            turn_urls: Vec::new(),
+            turn_secret: None,
+            credential_ttl: DEFAULT_CREDENTIAL_TTL,
+        }
+    }
+}
+
+impl IceConfig {
+    /// Hand out `urls` with credentials derived from the TURN server's shared secret
+    pub fn with_turn(mut self, urls: Vec<String>, shared_secret: &str) -> Self {
+        self.turn_urls = urls;
+        self.turn_secret = Some(shared_secret.to_string());
+        self
+    }
+
+    pub fn with_credential_ttl(mut self, ttl: Duration) -> Self {
+        self.credential_ttl = ttl;
+        self
+    }
+
+    /// Read `ICE_STUN_URLS`, `TURN_URLS`, `TURN_SHARED_SECRET` and
+    /// `TURN_CREDENTIAL_TTL_SECS`
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(urls) = env::var("ICE_STUN_URLS") {
+            config.stun_urls = split_urls(&urls);
+        }
+        if let Ok(ttl) = env::var("TURN_CREDENTIAL_TTL_SECS") {
+            let seconds: i64 = ttl.parse().map_err(|_| anyhow!("TURN_CREDENTIAL_TTL_SECS must be a number of seconds"))?;
+            config.credential_ttl = Duration::seconds(seconds.max(60));
+        }
+
+        let turn_urls = split_urls(&env::var("TURN_URLS").unwrap_or_default());
+        if !turn_urls.is_empty() {
+            let secret = env::var("TURN_SHARED_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty())
+                .ok_or_else(|| anyhow!("TURN_SHARED_SECRET is required when TURN_URLS is set"))?;
+            config = config.with_turn(turn_urls, &secret);
+        }
+        Ok(config)
+    }
+
+    pub fn has_turn(&self) -> bool {
+        !self.turn_urls.is_empty() && self.turn_secret.is_some()
+    }
+
+    /// ICE servers for one session
+    ///
+    /// With `force_relay` only TURN servers are listed and clients must not
+    /// use host or server-reflexive candidates. Fails if relaying is required
+    /// but no TURN server is configured.
+    pub fn issue(&self, session_id: Uuid, force_relay: bool, now: DateTime<Utc>) -> Result<IceConfiguration> {
+        if force_relay && !self.has_turn() {
+            return Err(anyhow!("Relay is required but no TURN server is configured"));
+        }
+
+        let expires_at = now + self.credential_ttl;
+        let mut ice_servers = Vec::new();
+        if !force_relay && !self.stun_urls.is_empty() {
+            ice_servers.push(IceServer {
+                urls: self.stun_urls.clone(),
+                username: None,
+                credential: None,
+            });
+        }
+        if let Some(secret) = self.turn_secret.as_ref().filter(|_| !self.turn_urls.is_empty()) {
+            let username = format!("{}:{}", expires_at.timestamp(), session_id);
+            ice_servers.push(IceServer {
+                urls: self.turn_urls.clone(),
+                credential: Some(turn_credential(secret, &username)),
+                username: Some(username),
+            });
+        }
+
+        Ok(IceConfiguration {
+            ice_servers,
+            ice_transport_policy: if force_relay { IceTransportPolicy::Relay } else { IceTransportPolicy::All },
+            expires_at,
+        })
+    }
+}
+
+/// TURN REST API password for `username`
+pub fn turn_credential(shared_secret: &str, username: &str) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(shared_secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(username.as_bytes());
+    BASE64.encode(&mac.finalize().into_bytes())
+}
+
+fn split_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> IceConfig {
+        IceConfig::default().with_turn(vec!["turn:turn.example.com:3478?transport=udp".to_string()], "north")
+    }
+
+    #[test]
+    fn test_turn_credential() {
+        // base64(HMAC-SHA1("north", "1700000000:alice"))
+        assert_eq!(turn_credential("north", "1700000000:alice"), "Cd/49soE35ICqcJF/bCTn8Z4OyE=");
+    }
+
+    #[test]
+    fn test_issue_binds_credentials_to_session_and_expiry() {
+        let now = Utc::now();
+        let session_id = Uuid::new_v4();
+        let issued = config().issue(session_id, false, now).unwrap();
+
+        assert_eq!(issued.ice_transport_policy, IceTransportPolicy::All);
+        assert_eq!(issued.expires_at, now + DEFAULT_CREDENTIAL_TTL);
+        assert_eq!(issued.ice_servers.len(), 2);
+        assert!(issued.ice_servers[0].username.is_none());
+
+        let turn = &issued.ice_servers[1];
+        let username = turn.username.as_deref().unwrap();
+        assert_eq!(username, format!("{}:{}", issued.expires_at.timestamp(), session_id));
+        assert_eq!(turn.credential.as_deref(), Some(turn_credential("north", username).as_str()));
+    }
+
+    #[test]
+    fn test_forced_relay_lists_only_turn() {
+        let issued = config().issue(Uuid::new_v4(), true, Utc::now()).unwrap();
+        assert_eq!(issued.ice_transport_policy, IceTransportPolicy::Relay);
+        assert_eq!(issued.ice_servers.len(), 1);
+        assert!(issued.ice_servers[0].urls[0].starts_with("turn:"));
+
+        assert!(IceConfig::default().issue(Uuid::new_v4(), true, Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, bail, Result};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use tracing::warn;
+
+use crate::password;
+
+/// Shortest secret accepted in production
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+/// Placeholder values shipped in sample configuration
+const PLACEHOLDER_SECRETS: &[&str] = &[
+    "your-super-secret-jwt-key-change-in-production",
+    "your_jwt_secret_minimum_32_characters_long",
+];
+
+/// HMAC signing keys identified by `kid`
+///
+/// Tokens are signed with the current key; retired keys stay valid for
+/// verification so tokens issued before a rotation keep working until they expire.
+pub struct JwtKeys {
+    current_kid: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl JwtKeys {
+    /// Key ring with a single signing secret
+    pub fn new(secret: &str) -> Self {
+        Self::with_previous(secret, &[])
+    }
+
+    /// Key ring signing with `secret` and still accepting `previous` secrets
+    pub fn with_previous(secret: &str, previous: &[&str]) -> Self {
+        let mut keys = HashMap::new();
+        for old in previous {
+            keys.insert(key_id(old), old.as_bytes().to_vec());
+        }
+        let current_kid = key_id(secret);
+        keys.insert(current_kid.clone(), secret.as_bytes().to_vec());
+        JwtKeys { current_kid, keys }
+    }
+
+    /// Load keys from `JWT_SECRET` and `JWT_PREVIOUS_SECRETS` (comma separated)
+    ///
+    /// With `GENXLINK_ENV=production` a missing, short or placeholder secret is
+    /// an error; elsewhere a random per-process secret is used instead.
+    pub fn from_env() -> Result<Self> {
+        let production = env::var("GENXLINK_ENV").is_ok_and(|value| value.eq_ignore_ascii_case("production"));
+        let previous = env::var("JWT_PREVIOUS_SECRETS").unwrap_or_default();
+        let previous: Vec<&str> = previous
+            .split(',')
+            .map(str::trim)
+            .filter(|secret| !secret.is_empty())
+            .collect();
+
+        match env::var("JWT_SECRET") {
+            Ok(secret) => {
+                if let Err(problem) = check_secret(&secret) {
+                    if production {
+                        bail!("JWT_SECRET {}", problem);
+                    }
+                    warn!("JWT_SECRET {}; this is only acceptable in development", problem);
+                }
+                Ok(Self::with_previous(&secret, &previous))
+            }
+            Err(_) if production => Err(anyhow!("JWT_SECRET must be set when GENXLINK_ENV=production")),
+            Err(_) => {
+                warn!("JWT_SECRET not set; using a random secret, tokens won't survive a restart");
+                Ok(Self::new(&password::generate_token()))
+            }
+        }
+    }
+
+    pub fn current_kid(&self) -> &str {
+        &self.current_kid
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> Result<String> {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(self.current_kid.clone());
+        encode(&header, claims, &EncodingKey::from_secret(&self.keys[&self.current_kid]))
+            .map_err(|e| anyhow!("Failed to sign token: {}", e))
+    }
+
+    /// Verify a token with the key named in its `kid` header
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T> {
+        let header = decode_header(token)?;
+        let kid = header.kid.ok_or_else(|| anyhow!("Token has no key id"))?;
+        let secret = self.keys.get(&kid).ok_or_else(|| anyhow!("Unknown key id {}", kid))?;
+
+        let data = decode::<T>(token, &DecodingKey::from_secret(secret), &Validation::new(Algorithm::HS256))?;
+        Ok(data.claims)
+    }
+}
+
+/// Key id derived from the secret, so rotation needs no extra configuration
+fn key_id(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .take(8)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn check_secret(secret: &str) -> std::result::Result<(), String> {
+    if PLACEHOLDER_SECRETS.contains(&secret) {
+        return Err("is a placeholder value".to_string());
+    }
+    if secret.len() < MIN_SECRET_LENGTH {
+        return Err(format!("must be at least {} characters", MIN_SECRET_LENGTH));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestClaims {
+        sub: String,
+        exp: i64,
+    }
+
+    fn claims() -> TestClaims {
+        TestClaims {
+            sub: "user".to_string(),
+            exp: chrono::Utc::now().timestamp() + 60,
+        }
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_tokens_valid() {
+        let old = JwtKeys::new("old-secret-old-secret-old-secret-old");
+        let token = old.encode(&claims()).unwrap();
+        assert_eq!(decode_header(&token).unwrap().kid.as_deref(), Some(old.current_kid()));
+
+        let rotated = JwtKeys::with_previous(
+            "new-secret-new-secret-new-secret-new",
+            &["old-secret-old-secret-old-secret-old"],
+        );
+        assert_ne!(rotated.current_kid(), old.current_kid());
+        assert_eq!(rotated.decode::<TestClaims>(&token).unwrap().sub, "user");
+
+        // Once the old key is dropped its tokens are rejected
+        let retired = JwtKeys::new("new-secret-new-secret-new-secret-new");
+        assert!(retired.decode::<TestClaims>(&token).is_err());
+        let fresh = rotated.encode(&claims()).unwrap();
+        assert!(retired.decode::<TestClaims>(&fresh).is_ok());
+    }
+
+    #[test]
+    fn test_tokens_without_kid_rejected() {
+        let keys = JwtKeys::new("some-secret-some-secret-some-secret");
+        let token = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"some-secret-some-secret-some-secret"),
+        )
+        .unwrap();
+        assert!(keys.decode::<TestClaims>(&token).is_err());
+    }
+
+    #[test]
+    fn test_secret_checks() {
+        assert!(check_secret("your-super-secret-jwt-key-change-in-production").is_err());
+        assert!(check_secret("short").is_err());
+        assert!(check_secret(&"x".repeat(MIN_SECRET_LENGTH)).is_ok());
+    }
+}
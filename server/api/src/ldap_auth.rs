@@ -1,50 +1,54 @@
 use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
+use async_trait::async_trait;
 use tracing::{info, warn, error, debug};
-use ldap3::{LdapConn, LdapConnAsync, LdapSettings, Scope, SearchEntry, ldap_escape};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry, ldap_escape};
+use uuid::Uuid;
 
-/// LDAP/Active Directory integration for enterprise authentication
-pub struct LdapAuthProvider {
-    config: LdapConfig,
-    connection_pool: Option<LdapConnectionPool>,
-}
+use crate::db::{self, Database};
+use crate::models::User;
+use crate::orgs::OrgRole;
+
+/// `auth_provider` of users provisioned from the directory
+pub const LDAP_PROVIDER: &str = "ldap";
+
+/// LDAP result code for a failed bind
+const INVALID_CREDENTIALS: u32 = 49;
 
+/// LDAP/Active Directory connection and schema settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LdapConfig {
-    /// LDAP server URL (e.g., "ldap://ad.company.com:389")
+    /// LDAP server URL (e.g., "ldap://ad.company.com:389" or "ldaps://...")
     pub server_url: String,
-    /// Use SSL/TLS connection
-    pub use_ssl: bool,
-    /// Use StartTLS
+    /// Upgrade plain `ldap://` connections with StartTLS
     pub use_starttls: bool,
-    /// Bind DN for authentication
+    /// Service account used to look users up
     pub bind_dn: String,
-    /// Bind password
     pub bind_password: String,
     /// Base DN for user searches
     pub user_base_dn: String,
     /// Base DN for group searches
     pub group_base_dn: String,
-    /// User search filter
+    /// User search filter; `{username}` is replaced with the escaped login name
     pub user_search_filter: String,
-    /// Group search filter
+    /// Group search filter; `{user_dn}` is replaced with the escaped user DN
     pub group_search_filter: String,
-    /// User attributes to retrieve
-    pub user_attributes: Vec<String>,
-    /// Group attributes to retrieve
-    pub group_attributes: Vec<String>,
+    /// Attribute holding the login name (`sAMAccountName` on AD, `uid` on OpenLDAP)
+    pub username_attribute: String,
+    pub email_attribute: String,
+    pub display_name_attribute: String,
     /// Connection timeout in seconds
     pub connection_timeout: u64,
-    /// Pool size for connections
-    pub pool_size: usize,
 }
 
 impl Default for LdapConfig {
     fn default() -> Self {
         Self {
             server_url: "ldap://localhost:389".to_string(),
-            use_ssl: false,
             use_starttls: false,
             bind_dn: "cn=admin,dc=company,dc=com".to_string(),
             bind_password: "".to_string(),
@@ -52,495 +56,475 @@ impl Default for LdapConfig {
             group_base_dn: "ou=Groups,dc=company,dc=com".to_string(),
             user_search_filter: "(&(objectClass=user)(sAMAccountName={username}))".to_string(),
             group_search_filter: "(&(objectClass=group)(member={user_dn}))".to_string(),
-            user_attributes: vec![
-                "cn".to_string(),
-                "sAMAccountName".to_string(),
-                "mail".to_string(),
-                "memberOf".to_string(),
-                "displayName".to_string(),
-                "telephoneNumber".to_string(),
-                "department".to_string(),
-                "title".to_string(),
-            ],
-            group_attributes: vec![
-                "cn".to_string(),
-                "description".to_string(),
-                "member".to_string(),
-            ],
+            username_attribute: "sAMAccountName".to_string(),
+            email_attribute: "mail".to_string(),
+            display_name_attribute: "displayName".to_string(),
             connection_timeout: 30,
-            pool_size: 5,
         }
     }
 }
 
+impl LdapConfig {
+    /// Read `LDAP_*` variables; `None` when `LDAP_URL` is not set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(server_url) = env::var("LDAP_URL") else {
+            return Ok(None);
+        };
+
+        let defaults = LdapConfig::default();
+        let var = |name: &str, default: String| env::var(name).unwrap_or(default);
+        Ok(Some(LdapConfig {
+            server_url,
+            use_starttls: env::var("LDAP_STARTTLS").is_ok_and(|value| value == "true"),
+            bind_dn: env::var("LDAP_BIND_DN").map_err(|_| anyhow!("LDAP_BIND_DN must be set when LDAP_URL is"))?,
+            bind_password: var("LDAP_BIND_PASSWORD", defaults.bind_password),
+            user_base_dn: env::var("LDAP_USER_BASE_DN").map_err(|_| anyhow!("LDAP_USER_BASE_DN must be set when LDAP_URL is"))?,
+            group_base_dn: var("LDAP_GROUP_BASE_DN", defaults.group_base_dn),
+            user_search_filter: var("LDAP_USER_FILTER", defaults.user_search_filter),
+            group_search_filter: var("LDAP_GROUP_FILTER", defaults.group_search_filter),
+            username_attribute: var("LDAP_USERNAME_ATTRIBUTE", defaults.username_attribute),
+            email_attribute: var("LDAP_EMAIL_ATTRIBUTE", defaults.email_attribute),
+            display_name_attribute: var("LDAP_DISPLAY_NAME_ATTRIBUTE", defaults.display_name_attribute),
+            connection_timeout: defaults.connection_timeout,
+        }))
+    }
+}
+
+/// Directory account of a user who just signed in
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LdapUser {
     pub dn: String,
     pub username: String,
-    pub email: String,
+    pub email: Option<String>,
     pub display_name: String,
-    pub first_name: Option<String>,
-    pub last_name: Option<String>,
-    pub phone: Option<String>,
-    pub department: Option<String>,
-    pub title: Option<String>,
+    /// Names (`cn`) of the groups the user belongs to
     pub groups: Vec<String>,
-    pub attributes: HashMap<String, Vec<String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LdapGroup {
-    pub dn: String,
-    pub name: String,
-    pub description: Option<String>,
-    pub members: Vec<String>,
-    pub attributes: HashMap<String, Vec<String>>,
+/// Result of checking credentials against a directory
+#[derive(Debug, Clone)]
+pub enum DirectoryOutcome {
+    Authenticated(LdapUser),
+    /// The account exists but the password is wrong
+    InvalidPassword,
+    /// No such account; local accounts may still match
+    UnknownUser,
 }
 
-/// LDAP connection pool for efficient connection management
-struct LdapConnectionPool {
-    connections: Vec<LdapConn>,
-    config: LdapConfig,
+/// Source of directory logins
+///
+/// Implemented by [`LdapAuthProvider`]; tests substitute an in-process directory.
+#[async_trait]
+pub trait Directory: Send + Sync {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<DirectoryOutcome>;
 }
 
-impl LdapConnectionPool {
-    fn new(config: LdapConfig) -> Result<Self> {
-        let mut connections = Vec::new();
-        
-        for _ in 0..config.pool_size {
-            let conn = create_ldap_connection(&config)?;
-            connections.push(conn);
-        }
-        
-        Ok(Self {
-            connections,
-            config,
-        })
-    }
-    
-    fn get_connection(&mut self) -> Result<&mut LdapConn> {
-        self.connections.first_mut()
-            .ok_or_else(|| anyhow!("No available connections in pool"))
-    }
-    
-    fn return_connection(&mut self, _conn: LdapConn) {
-        // In a real implementation, we'd handle connection validation
-        // and potential reconnection here
-    }
+/// LDAP/Active Directory integration for enterprise authentication
+///
+/// Each login opens its own connection: look the user up with the service
+/// account, bind as the user to check the password, then read their groups.
+pub struct LdapAuthProvider {
+    config: LdapConfig,
 }
 
 impl LdapAuthProvider {
-    pub fn new(config: LdapConfig) -> Result<Self> {
-        let connection_pool = Some(LdapConnectionPool::new(config.clone())?);
-        
+    pub fn new(config: LdapConfig) -> Self {
         info!("LDAP Auth Provider initialized for server: {}", config.server_url);
-        
-        Ok(Self {
-            config,
-            connection_pool,
-        })
-    }
-    
-    /// Authenticate a user against LDAP/AD
-    pub async fn authenticate_user(&mut self, username: &str, password: &str) -> Result<LdapUser> {
-        debug!("Attempting LDAP authentication for user: {}", username);
-        
-        // First, find the user's DN
-        let user_dn = self.find_user_dn(username).await?;
-        
-        // Then, attempt to bind with user credentials
-        let user = self.authenticate_with_dn(&user_dn, password).await?;
-        
-        info!("LDAP authentication successful for user: {}", username);
-        Ok(user)
-    }
-    
-    /// Search for a user by username
-    pub async fn find_user(&self, username: &str) -> Result<Option<LdapUser>> {
-        debug!("Searching for LDAP user: {}", username);
-        
-        let mut conn = self.get_connection().await?;
-        let search_filter = self.config.user_search_filter
-            .replace("{username}", &ldap_escape(username));
-        
-        let search_results = conn.search(
-            &self.config.user_base_dn,
-            Scope::Subtree,
-            &search_filter,
-            &self.config.user_attributes,
-        )?.await.success()?;
-        
-        for entry in search_results {
-            let user = self.parse_ldap_user(entry)?;
-            return Ok(Some(user));
-        }
-        
-        Ok(None)
-    }
-    
-    /// Get user groups
-    pub async fn get_user_groups(&self, user_dn: &str) -> Result<Vec<String>> {
-        debug!("Getting groups for user: {}", user_dn);
-        
-        let mut conn = self.get_connection().await?;
-        let search_filter = self.config.group_search_filter
-            .replace("{user_dn}", &ldap_escape(user_dn));
-        
-        let search_results = conn.search(
-            &self.config.group_base_dn,
-            Scope::Subtree,
-            &search_filter,
-            &self.config.group_attributes,
-        )?.await.success()?;
-        
-        let mut groups = Vec::new();
-        for entry in search_results {
-            let group = self.parse_ldap_group(entry)?;
-            groups.push(group.name);
-        }
-        
-        Ok(groups)
-    }
-    
-    /// Check if user is member of specific group
-    pub async fn is_user_in_group(&self, username: &str, group_name: &str) -> Result<bool> {
-        if let Some(user) = self.find_user(username).await? {
-            Ok(user.groups.contains(&group_name.to_string()))
-        } else {
-            Ok(false)
-        }
-    }
-    
-    /// Create or update user in local database from LDAP
-    pub async fn sync_user_to_database(&self, ldap_user: &LdapUser) -> Result<()> {
-        info!("Syncing LDAP user to database: {}", ldap_user.username);
-        
-        // TODO: Implement database synchronization
-        // This would typically:
-        // 1. Check if user exists in local database
-        // 2. Create or update user record
-        // 3. Sync group memberships
-        // 4. Update last sync timestamp
-        
-        debug!("User sync completed for: {}", ldap_user.username);
-        Ok(())
+        Self { config }
     }
-    
-    /// Test LDAP connection
-    pub async fn test_connection(&self) -> Result<bool> {
-        info!("Testing LDAP connection");
-        
-        match self.get_connection().await {
-            Ok(mut conn) => {
-                // Try a simple search to test the connection
-                let result = conn.search(
-                    &self.config.user_base_dn,
-                    Scope::Base,
-                    "(objectClass=*)",
-                    &["objectClass"],
-                )?.await.success();
-                
+
+    /// Test LDAP connection and service account
+    pub async fn test_connection(&self) -> bool {
+        match self.service_connection().await {
+            Ok(mut ldap) => {
+                let result = ldap
+                    .search(&self.config.user_base_dn, Scope::Base, "(objectClass=*)", vec!["objectClass"])
+                    .await
+                    .and_then(|result| result.success());
+                let _ = ldap.unbind().await;
                 match result {
-                    Ok(_) => {
-                        info!("LDAP connection test successful");
-                        Ok(true)
-                    }
+                    Ok(_) => true,
                     Err(e) => {
                         error!("LDAP connection test failed: {}", e);
-                        Ok(false)
+                        false
                     }
                 }
             }
             Err(e) => {
                 error!("Failed to establish LDAP connection: {}", e);
-                Ok(false)
+                false
             }
         }
     }
-    
-    /// Get connection from pool
-    async fn get_connection(&self) -> Result<&mut LdapConn> {
-        if let Some(pool) = &self.connection_pool {
-            // In a real async implementation, we'd need proper async connection handling
-            // For now, we'll create a new connection each time
-            create_ldap_connection(&self.config)
-        } else {
-            create_ldap_connection(&self.config)
-        }
+
+    /// Open a connection bound as the service account
+    async fn service_connection(&self) -> Result<Ldap> {
+        let settings = LdapConnSettings::new()
+            .set_conn_timeout(Duration::from_secs(self.config.connection_timeout))
+            .set_starttls(self.config.use_starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.config.server_url).await?;
+        ldap3::drive!(conn);
+
+        ldap.simple_bind(&self.config.bind_dn, &self.config.bind_password)
+            .await?
+            .success()?;
+        Ok(ldap)
     }
-    
-    /// Find user DN by username
-    async fn find_user_dn(&self, username: &str) -> Result<String> {
-        let mut conn = self.get_connection().await?;
-        let search_filter = self.config.user_search_filter
-            .replace("{username}", &ldap_escape(username));
-        
-        let search_results = conn.search(
-            &self.config.user_base_dn,
-            Scope::Subtree,
-            &search_filter,
-            &["dn"],
-        )?.await.success()?;
-        
-        if let Some(entry) = search_results.first() {
-            Ok(entry.dn.clone())
-        } else {
-            Err(anyhow!("User not found: {}", username))
+
+    async fn find_user(&self, ldap: &mut Ldap, username: &str) -> Result<Option<SearchEntry>> {
+        let filter = self.config.user_search_filter.replace("{username}", &ldap_escape(username));
+        let attributes = vec![
+            self.config.username_attribute.as_str(),
+            self.config.email_attribute.as_str(),
+            self.config.display_name_attribute.as_str(),
+        ];
+        let (entries, _) = ldap
+            .search(&self.config.user_base_dn, Scope::Subtree, &filter, attributes)
+            .await?
+            .success()?;
+
+        if entries.len() > 1 {
+            warn!("LDAP user filter matched {} entries for {}, refusing login", entries.len(), username);
+            return Ok(None);
         }
+        Ok(entries.into_iter().next().map(SearchEntry::construct))
     }
-    
-    /// Authenticate with user DN and password
-    async fn authenticate_with_dn(&self, user_dn: &str, password: &str) -> Result<LdapUser> {
-        // Create a new connection for user authentication
-        let mut user_conn = create_ldap_connection(&self.config)?;
-        
-        // Try to bind with user credentials
-        user_conn.simple_bind(user_dn, password)?.await.success()?;
-        
-        // If bind succeeds, fetch user details
-        let search_results = user_conn.search(
-            user_dn,
-            Scope::Base,
-            "(objectClass=*)",
-            &self.config.user_attributes,
-        )?.await.success()?;
-        
-        if let Some(entry) = search_results.first() {
-            let mut user = self.parse_ldap_user(entry.clone())?;
-            
-            // Get user groups
-            user.groups = self.get_user_groups(user_dn).await?;
-            
-            Ok(user)
-        } else {
-            Err(anyhow!("Failed to fetch user details after authentication"))
-        }
+
+    async fn get_user_groups(&self, ldap: &mut Ldap, user_dn: &str) -> Result<Vec<String>> {
+        let filter = self.config.group_search_filter.replace("{user_dn}", &ldap_escape(user_dn));
+        let (entries, _) = ldap
+            .search(&self.config.group_base_dn, Scope::Subtree, &filter, vec!["cn"])
+            .await?
+            .success()?;
+
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| first_value(&SearchEntry::construct(entry).attrs, "cn"))
+            .collect())
     }
-    
-    /// Parse LDAP search entry into LdapUser
-    fn parse_ldap_user(&self, entry: SearchEntry) -> Result<LdapUser> {
-        let attributes = entry.attrs;
-        
-        let username = attributes.get("sAMAccountName")
-            .and_then(|vals| vals.first())
-            .unwrap_or(&"".to_string())
-            .clone();
-        
-        let email = attributes.get("mail")
-            .and_then(|vals| vals.first())
-            .unwrap_or(&"".to_string())
-            .clone();
-        
-        let display_name = attributes.get("displayName")
-            .and_then(|vals| vals.first())
-            .unwrap_or(&username)
-            .clone();
-        
-        let phone = attributes.get("telephoneNumber")
-            .and_then(|vals| vals.first())
-            .cloned();
-        
-        let department = attributes.get("department")
-            .and_then(|vals| vals.first())
-            .cloned();
-        
-        let title = attributes.get("title")
-            .and_then(|vals| vals.first())
-            .cloned();
-        
-        // Split display name into first and last name
-        let (first_name, last_name) = if display_name.contains(' ') {
-            let parts: Vec<&str> = display_name.splitn(2, ' ').collect();
-            (Some(parts[0].to_string()), Some(parts[1].to_string()))
-        } else {
-            (Some(display_name.clone()), None)
-        };
-        
-        Ok(LdapUser {
+
+    /// Convert a user entry, falling back to the login name for missing attributes
+    fn parse_ldap_user(&self, entry: SearchEntry, login: &str, groups: Vec<String>) -> LdapUser {
+        let username = first_value(&entry.attrs, &self.config.username_attribute).unwrap_or_else(|| login.to_string());
+        let display_name = first_value(&entry.attrs, &self.config.display_name_attribute).unwrap_or_else(|| username.clone());
+        LdapUser {
             dn: entry.dn,
+            email: first_value(&entry.attrs, &self.config.email_attribute),
             username,
-            email,
             display_name,
-            first_name,
-            last_name,
-            phone,
-            department,
-            title,
-            groups: Vec::new(), // Will be populated separately
-            attributes,
-        })
+            groups,
+        }
     }
-    
-    /// Parse LDAP search entry into LdapGroup
-    fn parse_ldap_group(&self, entry: SearchEntry) -> Result<LdapGroup> {
-        let attributes = entry.attrs;
-        
-        let name = attributes.get("cn")
-            .and_then(|vals| vals.first())
-            .unwrap_or(&"".to_string())
-            .clone();
-        
-        let description = attributes.get("description")
-            .and_then(|vals| vals.first())
-            .cloned();
-        
-        let members = attributes.get("member")
-            .map(|vals| vals.clone())
-            .unwrap_or_default();
-        
-        Ok(LdapGroup {
-            dn: entry.dn,
-            name,
-            description,
-            members,
-            attributes,
+}
+
+#[async_trait]
+impl Directory for LdapAuthProvider {
+    async fn authenticate(&self, username: &str, password: &str) -> Result<DirectoryOutcome> {
+        debug!("Attempting LDAP authentication for user: {}", username);
+
+        // An empty password would be an anonymous bind, which "succeeds"
+        if password.is_empty() {
+            return Ok(DirectoryOutcome::InvalidPassword);
+        }
+
+        let mut ldap = self.service_connection().await?;
+        let outcome = async {
+            let Some(entry) = self.find_user(&mut ldap, username).await? else {
+                return Ok(DirectoryOutcome::UnknownUser);
+            };
+
+            match ldap.simple_bind(&entry.dn, password).await?.success() {
+                Ok(_) => {}
+                Err(LdapError::LdapResult { result }) if result.rc == INVALID_CREDENTIALS => {
+                    return Ok(DirectoryOutcome::InvalidPassword);
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            // Group lookups may not be readable by the user, so go back to the service account
+            ldap.simple_bind(&self.config.bind_dn, &self.config.bind_password)
+                .await?
+                .success()?;
+            let groups = self.get_user_groups(&mut ldap, &entry.dn).await?;
+
+            info!("LDAP authentication successful for user: {}", username);
+            Ok(DirectoryOutcome::Authenticated(self.parse_ldap_user(entry, username, groups)))
+        }
+        .await;
+
+        let _ = ldap.unbind().await;
+        outcome
+    }
+}
+
+fn first_value(attributes: &HashMap<String, Vec<String>>, name: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .filter(|value| !value.is_empty())
+        .cloned()
+}
+
+/// Organization role given to members of a directory group
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupRoleMapping {
+    pub group: String,
+    pub org_id: Uuid,
+    pub role: OrgRole,
+}
+
+/// Parse `LDAP_GROUP_ROLES`: `group=org_id:role` entries separated by `;`
+///
+/// e.g. `Helpdesk=7d9c...:operator;IT Admins=7d9c...:admin`
+pub fn parse_group_roles(value: &str) -> Result<Vec<GroupRoleMapping>> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (group, target) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| anyhow!("Invalid group role mapping: {}", entry))?;
+            let (org_id, role) = target
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid group role mapping: {}", entry))?;
+            Ok(GroupRoleMapping {
+                group: group.trim().to_string(),
+                org_id: Uuid::parse_str(org_id.trim())?,
+                role: role.trim().parse().map_err(anyhow::Error::msg)?,
+            })
         })
+        .collect()
+}
+
+/// Roles a user's groups give them, the strongest per organization
+///
+/// Group names compare case-insensitively, as they do in Active Directory.
+pub fn roles_for_groups(mappings: &[GroupRoleMapping], groups: &[String]) -> HashMap<Uuid, OrgRole> {
+    let mut roles: HashMap<Uuid, OrgRole> = HashMap::new();
+    for mapping in mappings {
+        if groups.iter().any(|group| group.eq_ignore_ascii_case(&mapping.group)) {
+            let role = roles.entry(mapping.org_id).or_insert(mapping.role);
+            *role = (*role).max(mapping.role);
+        }
+    }
+    roles
+}
+
+/// Make the memberships of `user_id` in every organization named by
+/// `mappings` match what `groups` grant
+///
+/// Memberships in organizations the mappings don't mention are left alone.
+pub async fn apply_group_roles(db: &Database, user_id: Uuid, mappings: &[GroupRoleMapping], groups: &[String]) -> Result<()> {
+    let roles = roles_for_groups(mappings, groups);
+    let mut managed: Vec<Uuid> = mappings.iter().map(|mapping| mapping.org_id).collect();
+    managed.sort();
+    managed.dedup();
+    for org_id in managed {
+        let current = db.get_member_role(org_id, user_id).await?;
+        match (roles.get(&org_id), current) {
+            (Some(&role), current) if current != Some(role) => db.set_org_member(org_id, user_id, role).await?,
+            (None, Some(_)) => {
+                db.remove_org_member(org_id, user_id).await?;
+            }
+            _ => {}
+        }
     }
+    Ok(())
+}
+
+/// Create or update the account of an externally authenticated user
+///
+/// `None` when the email or username already belongs to another account.
+pub async fn provision_external_user(db: &Database, profile: &User, external_id: &str) -> Result<Option<User>> {
+    match db.upsert_external_user(profile, external_id).await {
+        Ok(user) => Ok(Some(user)),
+        Err(e) if db::is_unique_violation(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Directory login as configured for the API server
+pub struct DirectoryAuth {
+    directory: Arc<dyn Directory>,
+    group_roles: Vec<GroupRoleMapping>,
+    /// Accept local accounts for users the directory doesn't know, or when
+    /// it is unreachable
+    pub allow_local_fallback: bool,
 }
 
-/// Create LDAP connection based on configuration
-fn create_ldap_connection(config: &LdapConfig) -> Result<LdapConn> {
-    let mut settings = LdapSettings::new();
-    settings.set_conn_timeout(config.connection_timeout);
-    
-    if config.use_ssl {
-        settings.set_starttls(false);
-        settings.set_port(636);
-    } else if config.use_starttls {
-        settings.set_starttls(true);
-        settings.set_port(389);
-    } else {
-        settings.set_starttls(false);
-        settings.set_port(389);
+impl DirectoryAuth {
+    pub fn new(directory: Arc<dyn Directory>, group_roles: Vec<GroupRoleMapping>, allow_local_fallback: bool) -> Self {
+        DirectoryAuth { directory, group_roles, allow_local_fallback }
     }
-    
-    let mut conn = LdapConn::with_settings(settings, &config.server_url)?;
-    
-    // Perform initial bind
-    conn.simple_bind(&config.bind_dn, &config.bind_password)?.success()?;
-    
-    Ok(conn)
+
+    /// LDAP login from `LDAP_*` variables; `None` when LDAP isn't configured
+    ///
+    /// `LDAP_GROUP_ROLES` maps groups to organization roles and
+    /// `LDAP_LOCAL_FALLBACK=false` turns off local accounts.
+    pub async fn from_env() -> Result<Option<Self>> {
+        let Some(config) = LdapConfig::from_env()? else {
+            return Ok(None);
+        };
+        let group_roles = parse_group_roles(&env::var("LDAP_GROUP_ROLES").unwrap_or_default())?;
+        let allow_local_fallback = env::var("LDAP_LOCAL_FALLBACK").map_or(true, |value| value != "false");
+
+        let provider = LdapAuthProvider::new(config);
+        if !provider.test_connection().await {
+            warn!("LDAP server not reachable at startup; directory logins will fail until it is");
+        }
+        Ok(Some(Self::new(Arc::new(provider), group_roles, allow_local_fallback)))
+    }
+
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<DirectoryOutcome> {
+        self.directory.authenticate(username, password).await
+    }
+
+    /// Create or update the local account of a directory user and apply
+    /// their group roles
+    ///
+    /// Returns `None` if the account can't be provisioned: the directory has
+    /// no email for it, or the email or username belongs to a local account.
+    pub async fn sync_user_to_database(&self, db: &Database, ldap_user: &LdapUser) -> Result<Option<User>> {
+        info!("Syncing LDAP user to database: {}", ldap_user.username);
+
+        let Some(email) = &ldap_user.email else {
+            warn!("LDAP user {} has no email address", ldap_user.username);
+            return Ok(None);
+        };
+        let now = chrono::Utc::now();
+        let profile = User {
+            id: Uuid::new_v4(),
+            email: email.to_lowercase(),
+            username: ldap_user.username.clone(),
+            display_name: ldap_user.display_name.clone(),
+            avatar_url: None,
+            is_active: true,
+            is_verified: true,
+            subscription_type: "free".to_string(),
+            auth_provider: LDAP_PROVIDER.to_string(),
+            is_admin: false,
+            created_at: now,
+            updated_at: now,
+            last_login: None,
+            preferences: serde_json::json!({}),
+        };
+        let Some(user) = provision_external_user(db, &profile, &ldap_user.username.to_lowercase()).await? else {
+            warn!("LDAP user {} collides with a local account", ldap_user.username);
+            return Ok(None);
+        };
+        apply_group_roles(db, user.id, &self.group_roles, &ldap_user.groups).await?;
+
+        debug!("User sync completed for: {}", ldap_user.username);
+        Ok(Some(user))
+    }
+}
+
+/// How a user was authenticated
+#[deprecated(note = "logins go through `AuthService`; see `User::auth_provider`")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AuthMethod {
+    Ldap,
+    Local,
+    Sso,
 }
 
-/// Enterprise authentication manager that combines LDAP with local authentication
+/// Outcome of [`EnterpriseAuthManager::authenticate`]
+#[deprecated(note = "use `DirectoryAuth::authenticate`, which returns a `DirectoryOutcome`")]
+#[allow(deprecated)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthResult {
+    pub success: bool,
+    pub user_id: String,
+    pub email: String,
+    pub display_name: String,
+    pub groups: Vec<String>,
+    pub auth_method: AuthMethod,
+}
+
+#[deprecated(note = "use `LdapAuthProvider::test_connection`")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthSystemStatus {
+    pub ldap_available: bool,
+    pub ldap_working: bool,
+    pub local_available: bool,
+    pub local_working: bool,
+    pub fallback_enabled: bool,
+}
+
+/// LDAP login without account provisioning
+///
+/// Superseded by [`DirectoryAuth`], which provisions local accounts, maps
+/// groups to roles and is wired into `/auth/login`. Local accounts are only
+/// checked by `AuthService`, so the fallback here always fails.
+#[deprecated(note = "use `DirectoryAuth` with `AuthService::with_directory`")]
 pub struct EnterpriseAuthManager {
     ldap_provider: Option<LdapAuthProvider>,
     enable_ldap_fallback: bool,
 }
 
+#[allow(deprecated)]
 impl EnterpriseAuthManager {
     pub fn new(ldap_config: Option<LdapConfig>, enable_fallback: bool) -> Result<Self> {
-        let ldap_provider = if let Some(config) = ldap_config {
-            Some(LdapAuthProvider::new(config)?)
-        } else {
-            None
-        };
-        
-        info!("Enterprise Auth Manager initialized (LDAP: {}, Fallback: {})", 
+        let ldap_provider = ldap_config.map(LdapAuthProvider::new);
+
+        info!("Enterprise Auth Manager initialized (LDAP: {}, Fallback: {})",
               ldap_provider.is_some(), enable_fallback);
-        
+
         Ok(Self {
             ldap_provider,
             enable_ldap_fallback: enable_fallback,
         })
     }
-    
-    /// Authenticate user using LDAP first, then fallback to local if enabled
+
+    /// Authenticate against LDAP, then fall back to local if enabled
     pub async fn authenticate(&mut self, username: &str, password: &str) -> Result<AuthResult> {
-        // Try LDAP authentication first
-        if let Some(ldap_provider) = &mut self.ldap_provider {
-            match ldap_provider.authenticate_user(username, password).await {
-                Ok(ldap_user) => {
-                    // Sync user to local database
-                    let _ = ldap_provider.sync_user_to_database(&ldap_user).await;
-                    
+        if let Some(ldap_provider) = &self.ldap_provider {
+            match ldap_provider.authenticate(username, password).await {
+                Ok(DirectoryOutcome::Authenticated(ldap_user)) => {
                     return Ok(AuthResult {
                         success: true,
-                        user_id: ldap_user.username.clone(),
-                        email: ldap_user.email,
+                        user_id: ldap_user.username,
+                        email: ldap_user.email.unwrap_or_default(),
                         display_name: ldap_user.display_name,
                         groups: ldap_user.groups,
                         auth_method: AuthMethod::Ldap,
                     });
                 }
+                Ok(_) => warn!("LDAP authentication failed for {}: invalid credentials", username),
                 Err(e) => {
                     warn!("LDAP authentication failed for {}: {}", username, e);
-                    
                     if !self.enable_ldap_fallback {
                         return Err(e);
                     }
                 }
             }
         }
-        
-        // Fallback to local authentication
+
         if self.enable_ldap_fallback {
-            info!("Falling back to local authentication for: {}", username);
-            self.authenticate_local(username, password).await
+            Err(anyhow!("Local authentication is only available through AuthService"))
         } else {
             Err(anyhow!("Authentication failed and fallback is disabled"))
         }
     }
-    
-    /// Local authentication fallback
-    async fn authenticate_local(&self, username: &str, password: &str) -> Result<AuthResult> {
-        // TODO: Implement local database authentication
-        // This would check against the local user database
-        
-        // For now, return an error
-        Err(anyhow!("Local authentication not implemented"))
-    }
-    
+
     /// Test enterprise authentication system
     pub async fn test_system(&self) -> Result<AuthSystemStatus> {
-        let mut status = AuthSystemStatus {
-            ldap_available: false,
-            ldap_working: false,
+        let (ldap_available, ldap_working) = match &self.ldap_provider {
+            Some(provider) => (true, provider.test_connection().await),
+            None => (false, false),
+        };
+        Ok(AuthSystemStatus {
+            ldap_available,
+            ldap_working,
             local_available: false,
             local_working: false,
             fallback_enabled: self.enable_ldap_fallback,
-        };
-        
-        // Test LDAP
-        if let Some(ldap_provider) = &self.ldap_provider {
-            status.ldap_available = true;
-            status.ldap_working = ldap_provider.test_connection().await?;
-        }
-        
-        // Test local authentication
-        status.local_available = true;
-        // TODO: Test local auth system
-        
-        Ok(status)
+        })
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthResult {
-    pub success: bool,
-    pub user_id: String,
-    pub email: String,
-    pub display_name: String,
-    pub groups: Vec<String>,
-    pub auth_method: AuthMethod,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum AuthMethod {
-    Ldap,
-    Local,
-    Sso,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthSystemStatus {
-    pub ldap_available: bool,
-    pub ldap_working: bool,
-    pub local_available: bool,
-    pub local_working: bool,
-    pub fallback_enabled: bool,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -549,20 +533,37 @@ mod tests {
     fn test_ldap_config_default() {
         let config = LdapConfig::default();
         assert_eq!(config.server_url, "ldap://localhost:389");
-        assert_eq!(config.pool_size, 5);
+        assert!(config.user_search_filter.contains("{username}"));
     }
 
     #[tokio::test]
+    #[allow(deprecated)]
     async fn test_enterprise_auth_manager_creation() {
-        let manager = EnterpriseAuthManager::new(None, true);
-        assert!(manager.is_ok());
+        let manager = EnterpriseAuthManager::new(None, true).unwrap();
+        let status = manager.test_system().await.unwrap();
+        assert!(!status.ldap_available);
+        assert!(status.fallback_enabled);
     }
 
-    #[tokio::test]
-    async fn test_ldap_user_parsing() {
-        // This would require a mock LDAP entry for testing
-        // For now, we'll just test the structure
-        let config = LdapConfig::default();
-        assert!(!config.user_attributes.is_empty());
+    #[test]
+    fn test_parse_group_roles() {
+        let org = Uuid::new_v4();
+        let mappings = parse_group_roles(&format!("Helpdesk={org}:operator; IT Admins = {org}:admin;")).unwrap();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1], GroupRoleMapping { group: "IT Admins".to_string(), org_id: org, role: OrgRole::Admin });
+
+        assert!(parse_group_roles("").unwrap().is_empty());
+        assert!(parse_group_roles(&format!("Helpdesk={org}:root")).is_err());
+        assert!(parse_group_roles("Helpdesk").is_err());
+    }
+
+    #[test]
+    fn test_roles_for_groups_takes_strongest() {
+        let org = Uuid::new_v4();
+        let mappings = parse_group_roles(&format!("Helpdesk={org}:operator;IT Admins={org}:admin")).unwrap();
+
+        let roles = roles_for_groups(&mappings, &["helpdesk".to_string(), "IT Admins".to_string()]);
+        assert_eq!(roles.get(&org), Some(&OrgRole::Admin));
+        assert!(roles_for_groups(&mappings, &["Sales".to_string()]).is_empty());
     }
 }
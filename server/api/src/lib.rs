@@ -0,0 +1,132 @@
+use axum::{
+    routing::{delete, get, post},
+    Router,
+    middleware,
+};
+use std::sync::Arc;
+
+pub mod handlers;
+pub mod models;
+pub mod orgs;
+pub mod db;
+pub mod auth;
+pub mod api_keys;
+pub mod ice;
+pub mod jwt_keys;
+pub mod ldap_auth;
+pub mod oidc;
+pub mod openapi;
+pub mod password;
+pub mod rate_limit;
+pub mod reset_delivery;
+pub mod signaling_admin;
+pub mod totp;
+
+use handlers::*;
+use db::Database;
+use ice::IceConfig;
+use auth::{AuthService, admin_middleware, auth_middleware};
+use rate_limit::{RateLimiter, account_rate_limit_middleware, rate_limit_middleware};
+use signaling_admin::SignalingAdmin;
+
+// Application state
+#[derive(Clone)]
+pub struct AppState {
+    pub db: Arc<Database>,
+    pub auth_service: Arc<AuthService>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub ice: Arc<IceConfig>,
+    /// Signaling server admin client, needed to end sessions on the devices
+    pub signaling: Option<Arc<SignalingAdmin>>,
+}
+
+/// Build the API router
+pub fn router(app_state: AppState) -> Router {
+    Router::new()
+        // Public endpoints (no auth required)
+        .route("/health", get(health_check))
+        .route("/auth/register", post(register))
+        .route("/auth/login", post(login))
+        .route("/auth/refresh", post(refresh_token))
+        .route("/auth/mfa/verify", post(verify_mfa))
+        .route("/auth/oidc/authorize", post(oidc_authorize))
+        .route("/auth/oidc/callback", post(oidc_callback))
+        .route("/auth/password-reset", post(request_password_reset))
+        .route("/auth/password-reset/confirm", post(confirm_password_reset))
+        .nest("/api", Router::new()
+            // Protected endpoints (auth required)
+            .route("/auth/logout", post(logout))
+            .route("/auth/logout-all", post(logout_all))
+            .route("/auth/change-password", post(change_password))
+            .route("/mfa", get(mfa_status))
+            .route("/mfa/totp/enroll", post(enroll_totp))
+            .route("/mfa/totp/activate", post(activate_totp))
+            .route("/mfa/recovery-codes", post(regenerate_recovery_codes))
+            .route("/mfa/disable", post(disable_mfa))
+            .route("/keys", get(get_api_keys))
+            .route("/keys", post(create_api_key))
+            .route("/keys/:key_id", delete(revoke_api_key))
+            .route("/profile", get(get_profile))
+            .route("/profile", post(update_profile))
+            .route("/devices", get(get_devices))
+            .route("/devices", post(register_device))
+            .route("/devices/:device_id/status", post(update_device_status))
+            .route("/sessions", get(get_sessions))
+            .route("/sessions", post(create_session))
+            .route("/sessions/:session_id/end", post(end_session))
+            .route("/sessions/:session_id/ice-servers", get(get_session_ice_servers))
+            .route("/orgs", get(get_organizations))
+            .route("/orgs", post(create_organization))
+            .route("/orgs/:org_id", get(get_organization))
+            .route("/orgs/:org_id", delete(delete_organization))
+            .route("/orgs/:org_id/relay-policy", post(set_org_relay_policy))
+            .route("/orgs/:org_id/members/:user_id", delete(remove_member))
+            .route("/orgs/:org_id/members/:user_id/role", post(update_member_role))
+            .route("/orgs/:org_id/invitations", get(get_invitations))
+            .route("/orgs/:org_id/invitations", post(create_invitation))
+            .route("/orgs/:org_id/invitations/:invitation_id", delete(revoke_invitation))
+            .route("/orgs/:org_id/teams", get(get_teams))
+            .route("/orgs/:org_id/teams", post(create_team))
+            .route("/orgs/:org_id/teams/:team_id", delete(delete_team))
+            .route("/orgs/:org_id/teams/:team_id/members", post(add_team_member))
+            .route("/orgs/:org_id/teams/:team_id/members/:user_id", delete(remove_team_member))
+            .route("/orgs/:org_id/device-groups", get(get_device_groups))
+            .route("/orgs/:org_id/device-groups", post(create_device_group))
+            .route("/orgs/:org_id/device-groups/:group_id", delete(delete_device_group))
+            .route("/orgs/:org_id/device-groups/:group_id/devices", post(add_group_device))
+            .route("/orgs/:org_id/device-groups/:group_id/devices/:device_id", delete(remove_group_device))
+            .route("/orgs/:org_id/device-groups/:group_id/grants", post(set_group_grant))
+            .route("/orgs/:org_id/device-groups/:group_id/grants/:team_id", delete(remove_group_grant))
+            .route("/orgs/:org_id/devices", get(get_org_devices))
+            .route("/orgs/:org_id/devices/:device_id", delete(remove_org_device))
+            .route("/orgs/:org_id/sessions", get(get_org_sessions))
+            .route("/invitations/accept", post(accept_invitation))
+            .route("/license/activate", post(activate_license))
+            .route("/license/status", get(license_status))
+            .route("/stats/usage", get(get_usage_stats))
+            .nest("/admin", admin_router())
+            // Layers run bottom-up: authenticate first, then apply per-account limits
+            .layer(middleware::from_fn_with_state(app_state.rate_limiter.clone(), account_rate_limit_middleware))
+            .layer(middleware::from_fn_with_state(app_state.auth_service.clone(), auth_middleware))
+        )
+        .layer(middleware::from_fn_with_state(app_state.rate_limiter.clone(), rate_limit_middleware))
+        .with_state(app_state)
+}
+
+/// Platform administration, nested under `/api/admin`
+///
+/// Paths are listed in the OpenAPI document served at `/api/admin/openapi.json`.
+fn admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/openapi.json", get(admin_openapi))
+        .route("/stats", get(admin_system_stats))
+        .route("/devices", get(admin_devices))
+        .route("/sessions", get(admin_sessions))
+        .route("/sessions/:session_id/disconnect", post(admin_disconnect_session))
+        .route("/usage", get(admin_usage))
+        .route("/licenses", get(admin_licenses))
+        .route("/licenses", post(admin_issue_license))
+        .route("/licenses/:license_id", post(admin_update_license))
+        .route("/users/:user_id/admin", post(admin_set_user_admin))
+        .layer(middleware::from_fn(admin_middleware))
+}
@@ -1,29 +1,18 @@
-use anyhow::Result;
-use axum::{
-    routing::{get, post},
-    Router,
-    middleware,
-};
+use anyhow::{bail, Result};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::info;
-use time::Duration as TimeDuration;
+use tracing::{info, warn};
 
-mod handlers;
-mod models;
-mod db;
-mod auth;
-
-use handlers::*;
-use db::Database;
-use auth::{AuthService, RateLimiter, auth_middleware, rate_limit_middleware};
-
-// Application state
-#[derive(Clone)]
-struct AppState {
-    db: Arc<Database>,
-    auth_service: Arc<AuthService>,
-}
+use genxlink_api_server::{router, AppState};
+use genxlink_api_server::db::Database;
+use genxlink_api_server::auth::{self, AuthService};
+use genxlink_api_server::ice::IceConfig;
+use genxlink_api_server::jwt_keys::JwtKeys;
+use genxlink_api_server::ldap_auth::DirectoryAuth;
+use genxlink_api_server::oidc::OidcProvider;
+use genxlink_api_server::rate_limit::RateLimiter;
+use genxlink_api_server::reset_delivery::ResetWebhook;
+use genxlink_api_server::signaling_admin::SignalingAdmin;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -38,47 +27,79 @@ async fn main() -> Result<()> {
     let db = Database::new().await?;
     let db = Arc::new(db);
     
-    // Initialize authentication service
-    let auth_service = Arc::new(AuthService::new((*db).clone()));
+    // Load token signing keys; refuses to start in production without a real secret
+    let production = std::env::var("GENXLINK_ENV").is_ok_and(|value| value.eq_ignore_ascii_case("production"));
+    let jwt_keys = JwtKeys::from_env()?;
+    info!("Signing tokens with key {}", jwt_keys.current_kid());
+    
+    // Grant platform administration to the verified accounts listed in ADMIN_EMAILS
+    if let Ok(emails) = std::env::var("ADMIN_EMAILS") {
+        auth::grant_admins(&db, &emails).await?;
+    }
+    
+    // Initialize authentication service, with LDAP login when LDAP_URL is set
+    // and single sign-on when OIDC_ISSUER is set
+    let mut auth_service = AuthService::new((*db).clone(), jwt_keys);
+    if let Some(directory) = DirectoryAuth::from_env().await? {
+        info!("LDAP login enabled (local fallback: {})", directory.allow_local_fallback);
+        auth_service = auth_service.with_directory(directory);
+    }
+    if let Some(oidc) = OidcProvider::from_env()? {
+        info!("OpenID Connect single sign-on enabled");
+        auth_service = auth_service.with_oidc(oidc);
+    }
+    
+    // Password reset tokens go to PASSWORD_RESET_WEBHOOK_URL; without it
+    // nobody could ever use one, so production refuses to start
+    match ResetWebhook::from_env()? {
+        Some(webhook) => auth_service = auth_service.with_reset_notifier(webhook.spawn()),
+        None if production => bail!("PASSWORD_RESET_WEBHOOK_URL must be set when GENXLINK_ENV=production"),
+        None => warn!("No password reset delivery configured (PASSWORD_RESET_WEBHOOK_URL); password resets can't be completed"),
+    }
+    let auth_service = Arc::new(auth_service);
+    
+    // Rate limits (TRUSTED_PROXIES, optional RATE_LIMIT_REDIS_URL)
+    let rate_limiter = Arc::new(RateLimiter::from_env().await?);
+    
+    // STUN/TURN servers handed to clients (ICE_STUN_URLS, TURN_URLS, TURN_SHARED_SECRET)
+    let ice = IceConfig::from_env()?;
+    if ice.has_turn() {
+        info!("TURN relay enabled: {}", ice.turn_urls.join(", "));
+    } else {
+        warn!("No TURN server configured; sessions behind symmetric NATs cannot be relayed");
+    }
+    
+    // Signaling server admin endpoint, used to end sessions (SIGNALING_ADMIN_URL, SIGNALING_ADMIN_TOKEN)
+    let signaling = SignalingAdmin::from_env()?.map(Arc::new);
+    if signaling.is_none() {
+        warn!("No signaling server admin endpoint configured; admins cannot force sessions to disconnect");
+    }
     
     // Initialize application state
     let app_state = AppState {
         db: db.clone(),
         auth_service: auth_service.clone(),
+        rate_limiter,
+        ice: Arc::new(ice),
+        signaling,
     };
     
-    // Initialize rate limiter (100 requests per minute per IP)
-    let rate_limiter = Arc::new(RateLimiter::new(100, TimeDuration::minutes(1)));
+    // Deactivate expired API keys in the background
+    let expiry_db = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match expiry_db.deactivate_expired_api_keys().await {
+                Ok(0) => {}
+                Ok(count) => info!("Deactivated {} expired API keys", count),
+                Err(e) => warn!("API key expiry sweep failed: {}", e),
+            }
+        }
+    });
     
     // Build router with middleware
-    let app = Router::new()
-        // Public endpoints (no auth required)
-        .route("/health", get(health_check))
-        .route("/auth/register", post(register))
-        .route("/auth/login", post(login))
-        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit_middleware))
-        .nest("/api", Router::new()
-            // Protected endpoints (auth required)
-            .route("/auth/refresh", post(refresh_token))
-            .route("/auth/change-password", post(change_password))
-            .route("/profile", get(get_profile))
-            .route("/profile", post(update_profile))
-            .route("/devices", get(get_devices))
-            .route("/devices", post(register_device))
-            .route("/devices/:device_id/status", post(update_device_status))
-            .route("/sessions", get(get_sessions))
-            .route("/sessions", post(create_session))
-            .route("/sessions/:session_id/end", post(end_session))
-            .route("/license/activate", post(activate_license))
-            .route("/license/status", get(license_status))
-            // Temporarily comment out problematic handlers
-            // .route("/connection/start", post(start_connection))
-            // .route("/connection/end", post(end_connection))
-            // .route("/stats/system", get(get_system_stats))
-            // .route("/stats/usage", get(get_usage_stats))
-            .layer(middleware::from_fn_with_state(app_state.auth_service.clone(), auth_middleware))
-        )
-        .with_state(app_state);
+    let app = router(app_state);
     
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
@@ -88,8 +109,23 @@ async fn main() -> Result<()> {
     info!("  Authentication:");
     info!("    Register: POST /auth/register");
     info!("    Login: POST /auth/login");
-    info!("    Refresh token: POST /api/auth/refresh");
+    info!("    Verify MFA code: POST /auth/mfa/verify");
+    info!("    Request password reset: POST /auth/password-reset");
+    info!("    Confirm password reset: POST /auth/password-reset/confirm");
+    info!("    Refresh token: POST /auth/refresh");
+    info!("    Sign out: POST /api/auth/logout");
+    info!("    Sign out everywhere: POST /api/auth/logout-all");
     info!("    Change password: POST /api/auth/change-password");
+    info!("  Two-factor authentication:");
+    info!("    Status: GET /api/mfa");
+    info!("    Enroll TOTP: POST /api/mfa/totp/enroll");
+    info!("    Activate TOTP: POST /api/mfa/totp/activate");
+    info!("    Regenerate recovery codes: POST /api/mfa/recovery-codes");
+    info!("    Disable: POST /api/mfa/disable");
+    info!("  API keys (Bearer or X-API-Key, scoped):");
+    info!("    List keys: GET /api/keys");
+    info!("    Create key: POST /api/keys");
+    info!("    Revoke key: DELETE /api/keys/:key_id");
     info!("  Profile:");
     info!("    Get profile: GET /api/profile");
     info!("    Update profile: POST /api/profile");
@@ -101,12 +137,23 @@ async fn main() -> Result<()> {
     info!("    Get sessions: GET /api/sessions");
     info!("    Create session: POST /api/sessions");
     info!("    End session: POST /api/sessions/:session_id/end");
+    info!("    ICE servers: GET /api/sessions/:session_id/ice-servers");
+    info!("    Usage totals: GET /api/stats/usage");
     info!("  Licenses:");
     info!("    Activate license: POST /api/license/activate");
     info!("    Get license status: GET /api/license/status");
+    info!("  Administration (admins only, OpenAPI at GET /api/admin/openapi.json):");
+    info!("    System stats: GET /api/admin/stats");
+    info!("    Devices: GET /api/admin/devices");
+    info!("    Sessions: GET /api/admin/sessions");
+    info!("    Force disconnect: POST /api/admin/sessions/:session_id/disconnect");
+    info!("    Daily usage: GET /api/admin/usage");
+    info!("    Licenses: GET/POST /api/admin/licenses, POST /api/admin/licenses/:license_id");
+    info!("    Grant or revoke admin: POST /api/admin/users/:user_id/admin");
     
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Peer addresses are needed to rate limit by client
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
     
     Ok(())
 }
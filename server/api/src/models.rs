@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
 
+use crate::orgs::{DeviceAccess, OrgRole};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: Uuid,
@@ -12,17 +14,30 @@ pub struct User {
     pub is_active: bool,
     pub is_verified: bool,
     pub subscription_type: String,
+    /// Where the user signs in: `local` (password) or an external provider
+    #[serde(default = "default_auth_provider")]
+    pub auth_provider: String,
+    /// Platform administrator, allowed to use `/api/admin`
+    #[serde(default)]
+    pub is_admin: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
     pub preferences: serde_json::Value,
 }
 
+fn default_auth_provider() -> String {
+    "local".to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub id: Uuid,
     pub user_id: Uuid,
     pub device_id: String,
+    /// Organization the device is enrolled in, if any
+    #[serde(default)]
+    pub org_id: Option<Uuid>,
     pub device_name: String,
     pub device_type: String,
     pub os_version: String,
@@ -83,11 +98,65 @@ pub struct License {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Changes to a license; unset fields are kept
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LicenseUpdate {
+    pub is_active: Option<bool>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_devices: Option<i32>,
+    pub max_concurrent_sessions: Option<i32>,
+    pub features: Option<serde_json::Value>,
+}
+
+/// Traffic of a connection, reported when its session ends
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ConnectionTraffic {
+    #[serde(default)]
+    pub bytes_sent: i64,
+    #[serde(default)]
+    pub bytes_received: i64,
+}
+
+/// Platform-wide counts for administrators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemStats {
+    pub total_users: i64,
+    pub total_devices: i64,
+    pub online_devices: i64,
+    pub active_sessions: i64,
+    pub total_connections: i64,
+}
+
+/// A user's recorded usage, summed over all ended connections
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageStats {
+    pub connections: i64,
+    /// Seconds
+    pub session_duration: i64,
+    /// Bytes sent and received
+    pub bandwidth_used: i64,
+}
+
+/// Connections of one user on one day (UTC)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub user_id: Uuid,
+    pub email: String,
+    pub day: NaiveDate,
+    pub connections: i64,
+    pub duration_seconds: i64,
+    pub bytes_sent: i64,
+    pub bytes_received: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKey {
     pub id: Uuid,
     pub user_id: Uuid,
     pub name: String,
+    /// Clear-text start of the key, for display
+    pub key_prefix: String,
+    #[serde(skip_serializing)]
     pub key_hash: String,
     pub permissions: serde_json::Value,
     pub expires_at: Option<DateTime<Utc>>,
@@ -95,3 +164,82 @@ pub struct ApiKey {
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
 }
+
+/// Two-factor authentication state of a user
+#[derive(Debug, Clone, Default)]
+pub struct MfaSettings {
+    /// TOTP secret, set once enrollment starts
+    pub totp_secret: Option<String>,
+    /// Enrollment confirmed with a valid code
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    /// Wrong codes since the last success or lockout
+    pub failed_attempts: i32,
+    /// Second factor refused until then after too many wrong codes
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    /// Sessions with the organization's devices may only use TURN relays
+    pub force_relay: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Member of an organization, with the user details shown in member lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: String,
+    pub display_name: String,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgInvitation {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub email: String,
+    pub role: OrgRole,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Team {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub name: String,
+    /// User ids of the team members
+    pub members: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Set of organization devices that can be granted to teams as a whole
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    /// Ids (`Device::id`) of the devices in the group
+    pub devices: Vec<Uuid>,
+    pub grants: Vec<DeviceGroupGrant>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceGroupGrant {
+    pub team_id: Uuid,
+    pub access: DeviceAccess,
+}
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::{OnceCell, RwLock};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::ldap_auth::{self, GroupRoleMapping};
+use crate::models::User;
+use crate::password;
+
+/// `auth_provider` of users provisioned through single sign-on
+pub const OIDC_PROVIDER: &str = "oidc";
+
+/// How long a started sign-in can be completed
+const AUTHORIZATION_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// Most sign-ins that can be waiting for their callback at once
+const MAX_PENDING_AUTHORIZATIONS: usize = 10_000;
+
+/// How long fetched signing keys are trusted before fetching them again
+const JWKS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Minimum time between key fetches triggered by unknown key ids
+const JWKS_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OpenID Connect identity provider settings
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL; discovery reads `<issuer>/.well-known/openid-configuration`
+    pub issuer: String,
+    pub client_id: String,
+    /// Sent with code exchanges when the client is confidential
+    pub client_secret: Option<String>,
+    pub scopes: String,
+    /// Claim listing the user's groups or roles; dots descend into objects
+    /// (e.g. `realm_access.roles`)
+    pub role_claim: String,
+    /// Organization roles given to holders of role claim values
+    pub role_mapping: Vec<GroupRoleMapping>,
+    /// Redirect URIs accepted besides loopback addresses
+    pub redirect_uris: Vec<String>,
+}
+
+impl OidcConfig {
+    /// Settings from `OIDC_*` variables; `None` when `OIDC_ISSUER` isn't set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(issuer) = env::var("OIDC_ISSUER").ok().filter(|value| !value.is_empty()) else {
+            return Ok(None);
+        };
+        let client_id = env::var("OIDC_CLIENT_ID")
+            .map_err(|_| anyhow!("OIDC_CLIENT_ID is required when OIDC_ISSUER is set"))?;
+
+        Ok(Some(Self {
+            issuer,
+            client_id,
+            client_secret: env::var("OIDC_CLIENT_SECRET").ok().filter(|value| !value.is_empty()),
+            scopes: env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+            role_claim: env::var("OIDC_ROLE_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+            role_mapping: ldap_auth::parse_group_roles(&env::var("OIDC_ROLE_MAPPING").unwrap_or_default())?,
+            redirect_uris: env::var("OIDC_REDIRECT_URIS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|uri| !uri.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }))
+    }
+}
+
+/// The parts of the provider's discovery document the login flow needs
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// A user as described by a verified ID token
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcIdentity {
+    /// `sub` claim, stable per user at the provider
+    pub subject: String,
+    pub email: String,
+    pub username: String,
+    pub display_name: String,
+    /// Values of the configured role claim
+    pub groups: Vec<String>,
+}
+
+/// Result of completing a sign-in at the identity provider
+#[derive(Debug, Clone)]
+pub enum OidcOutcome {
+    Authenticated(OidcIdentity),
+    /// The request or the provider's answer isn't acceptable; the message is
+    /// safe to show to the user
+    Rejected(&'static str),
+}
+
+/// A started sign-in: send the browser to `url`, expect `state` back
+#[derive(Debug, Clone)]
+pub struct Authorization {
+    pub url: String,
+    pub state: String,
+}
+
+struct PendingAuthorization {
+    redirect_uri: String,
+    code_challenge: String,
+    nonce: String,
+    expires_at: Instant,
+}
+
+struct CachedJwks {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+/// OpenID Connect authorization code login with PKCE
+///
+/// Clients create the PKCE verifier and receive the browser redirect, the
+/// server keeps the nonce, exchanges the code and verifies the ID token.
+/// Started sign-ins are kept in memory, so the callback has to reach the
+/// same instance as the authorization request.
+pub struct OidcProvider {
+    config: OidcConfig,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+    jwks: RwLock<Option<CachedJwks>>,
+    /// Started sign-ins by `state`
+    pending: Mutex<HashMap<String, PendingAuthorization>>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig) -> Result<Self> {
+        info!("OIDC provider configured for issuer: {}", config.issuer);
+        Ok(Self {
+            config,
+            http: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(None),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Provider from `OIDC_*` variables; `None` when OIDC isn't configured
+    pub fn from_env() -> Result<Option<Self>> {
+        OidcConfig::from_env()?.map(Self::new).transpose()
+    }
+
+    /// Whether the browser may be sent back to `uri` after signing in
+    pub fn accepts_redirect_uri(&self, uri: &str) -> bool {
+        self.config.redirect_uris.iter().any(|allowed| allowed == uri) || is_loopback_redirect(uri)
+    }
+
+    /// Discovery document, fetched on first use
+    pub async fn metadata(&self) -> Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+                let metadata: ProviderMetadata = self.http.get(&url).send().await?.error_for_status()?.json().await?;
+                if metadata.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/') {
+                    return Err(anyhow!("Discovery document is for issuer {}", metadata.issuer));
+                }
+                debug!("Loaded OIDC discovery document from {}", url);
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Start a sign-in for a client that will receive the browser at
+    /// `redirect_uri` and holds the verifier for `code_challenge` (S256)
+    pub async fn begin(&self, redirect_uri: &str, code_challenge: &str) -> Result<Authorization> {
+        let metadata = self.metadata().await?;
+        let state = password::generate_token();
+        let nonce = password::generate_token();
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("scope", self.config.scopes.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        self.hold(
+            state.clone(),
+            PendingAuthorization {
+                redirect_uri: redirect_uri.to_string(),
+                code_challenge: code_challenge.to_string(),
+                nonce,
+                expires_at: Instant::now() + AUTHORIZATION_LIFETIME,
+            },
+        )?;
+
+        Ok(Authorization { url: url.to_string(), state })
+    }
+
+    /// Keep a started sign-in until its callback, dropping expired ones;
+    /// fails when `MAX_PENDING_AUTHORIZATIONS` are still waiting
+    fn hold(&self, state: String, authorization: PendingAuthorization) -> Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+        let now = Instant::now();
+        pending.retain(|_, authorization| authorization.expires_at > now);
+        if pending.len() >= MAX_PENDING_AUTHORIZATIONS {
+            warn!("Refusing OIDC sign-in: {} sign-ins already pending", pending.len());
+            return Err(anyhow!("Too many sign-ins in progress"));
+        }
+        pending.insert(state, authorization);
+        Ok(())
+    }
+
+    /// Finish a sign-in: redeem the authorization code and verify the ID token
+    ///
+    /// Each `state` can be used once. Errors mean the provider couldn't be
+    /// reached or answered unexpectedly.
+    pub async fn finish(&self, code: &str, state: &str, code_verifier: &str) -> Result<OidcOutcome> {
+        let pending = self.pending.lock().unwrap().remove(state);
+        let Some(pending) = pending.filter(|pending| pending.expires_at > Instant::now()) else {
+            return Ok(OidcOutcome::Rejected("Sign-in expired, please start again"));
+        };
+        if pkce_challenge(code_verifier) != pending.code_challenge {
+            warn!("OIDC callback with a code verifier that doesn't match the challenge");
+            return Ok(OidcOutcome::Rejected("Invalid sign-in request"));
+        }
+
+        let metadata = self.metadata().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", pending.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let response = self.http.post(&metadata.token_endpoint).form(&form).send().await?;
+        if response.status().is_client_error() {
+            warn!("OIDC code exchange refused: {}", response.text().await.unwrap_or_default());
+            return Ok(OidcOutcome::Rejected("The identity provider refused the sign-in"));
+        }
+        let tokens: TokenResponse = response.error_for_status()?.json().await?;
+        let Some(id_token) = tokens.id_token else {
+            return Err(anyhow!("Token response has no ID token"));
+        };
+
+        let Some(claims) = self.verify_id_token(&id_token, &pending.nonce).await? else {
+            return Ok(OidcOutcome::Rejected("The identity provider's answer couldn't be verified"));
+        };
+        Ok(match identity_from_claims(&claims, &self.config.role_claim) {
+            Some(identity) => OidcOutcome::Authenticated(identity),
+            None => OidcOutcome::Rejected("The identity provider didn't share a verified email address"),
+        })
+    }
+
+    /// Claims of `token` if it is signed by the provider, issued for this
+    /// client and carries `nonce`
+    async fn verify_id_token(&self, token: &str, nonce: &str) -> Result<Option<Value>> {
+        let Ok(header) = decode_header(token) else {
+            warn!("Malformed ID token");
+            return Ok(None);
+        };
+        // Shared-secret algorithms would let anyone holding the client secret mint tokens
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            warn!("ID token signed with {:?}, refusing", header.alg);
+            return Ok(None);
+        }
+        let Some(key) = self.signing_key(header.kid.as_deref()).await? else {
+            warn!("ID token signed with unknown key {:?}", header.kid);
+            return Ok(None);
+        };
+
+        let metadata = self.metadata().await?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = match decode::<Value>(token, &key, &validation) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                warn!("ID token rejected: {}", e);
+                return Ok(None);
+            }
+        };
+
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            warn!("ID token nonce doesn't match the sign-in");
+            return Ok(None);
+        }
+        Ok(Some(claims))
+    }
+
+    /// Key named `kid` from the provider's key set
+    ///
+    /// The key set is cached for an hour. An unknown key id fetches it again,
+    /// since the provider may have rotated keys, but at most once a minute.
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>> {
+        let fresh = |cached: &CachedJwks, max_age| cached.fetched_at.elapsed() < max_age;
+
+        if let Some(cached) = self.jwks.read().await.as_ref().filter(|cached| fresh(cached, JWKS_MAX_AGE)) {
+            if let Some(jwk) = find_key(&cached.keys, kid) {
+                return Ok(Some(DecodingKey::from_jwk(jwk)?));
+            }
+            if fresh(cached, JWKS_MIN_REFRESH_INTERVAL) {
+                return Ok(None);
+            }
+        }
+
+        let mut cache = self.jwks.write().await;
+        // Another request may have refreshed the keys while we waited
+        if let Some(cached) = cache.as_ref().filter(|cached| fresh(cached, JWKS_MIN_REFRESH_INTERVAL)) {
+            return find_key(&cached.keys, kid).map(DecodingKey::from_jwk).transpose().map_err(Into::into);
+        }
+
+        let metadata = self.metadata().await?;
+        let keys: JwkSet = self.http.get(&metadata.jwks_uri).send().await?.error_for_status()?.json().await?;
+        debug!("Fetched {} signing keys from {}", keys.keys.len(), metadata.jwks_uri);
+        let key = find_key(&keys, kid).map(DecodingKey::from_jwk).transpose()?;
+        *cache = Some(CachedJwks { keys, fetched_at: Instant::now() });
+        Ok(key)
+    }
+
+    /// Create or update the local account of a single sign-on user and
+    /// apply their role claims
+    ///
+    /// Returns `None` if the email or username belongs to another account.
+    pub async fn sync_user_to_database(&self, db: &Database, identity: &OidcIdentity) -> Result<Option<User>> {
+        let now = chrono::Utc::now();
+        let profile = User {
+            id: Uuid::new_v4(),
+            email: identity.email.to_lowercase(),
+            username: identity.username.clone(),
+            display_name: identity.display_name.clone(),
+            avatar_url: None,
+            is_active: true,
+            is_verified: true,
+            subscription_type: "free".to_string(),
+            auth_provider: OIDC_PROVIDER.to_string(),
+            is_admin: false,
+            created_at: now,
+            updated_at: now,
+            last_login: None,
+            preferences: serde_json::json!({}),
+        };
+        let Some(user) = ldap_auth::provision_external_user(db, &profile, &identity.subject).await? else {
+            warn!("OIDC user {} collides with another account", identity.email);
+            return Ok(None);
+        };
+        ldap_auth::apply_group_roles(db, user.id, &self.config.role_mapping, &identity.groups).await?;
+        Ok(Some(user))
+    }
+}
+
+/// Key named `kid`, or the only key when the token doesn't name one
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+/// S256 PKCE challenge for `verifier` (RFC 7636)
+pub fn pkce_challenge(verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// Whether `challenge` looks like an S256 PKCE challenge
+pub fn is_valid_code_challenge(challenge: &str) -> bool {
+    (43..=128).contains(&challenge.len())
+        && challenge.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c))
+}
+
+/// Whether `uri` is a plain-HTTP address on this machine, as used by native
+/// apps receiving the redirect themselves (RFC 8252)
+pub fn is_loopback_redirect(uri: &str) -> bool {
+    let Ok(url) = Url::parse(uri) else {
+        return false;
+    };
+    let loopback = match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    };
+    url.scheme() == "http" && loopback && url.fragment().is_none()
+}
+
+/// String values at `path` (dot separated) in `claims`; a single string
+/// counts as one value
+pub fn claim_values(claims: &Value, path: &str) -> Vec<String> {
+    let value = path.split('.').try_fold(claims, |value, key| value.get(key));
+    match value {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        Some(Value::String(value)) => vec![value.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Identity described by verified ID token claims
+///
+/// `None` without a subject or an email, or unless the provider says the
+/// email is verified.
+pub fn identity_from_claims(claims: &Value, role_claim: &str) -> Option<OidcIdentity> {
+    let text = |name: &str| claims.get(name).and_then(Value::as_str).filter(|value| !value.is_empty());
+
+    let subject = text("sub")?;
+    let email = text("email")?;
+    if claims.get("email_verified").and_then(Value::as_bool) != Some(true) {
+        return None;
+    }
+    let username = text("preferred_username")
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email))
+        .to_string();
+
+    Some(OidcIdentity {
+        subject: subject.to_string(),
+        email: email.to_string(),
+        display_name: text("name").map_or_else(|| username.clone(), str::to_string),
+        username,
+        groups: claim_values(claims, role_claim),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636, appendix B
+        let challenge = pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+        assert!(is_valid_code_challenge(&challenge));
+        assert!(!is_valid_code_challenge("too-short"));
+        assert!(!is_valid_code_challenge(&"a+b".repeat(20)));
+    }
+
+    #[test]
+    fn test_loopback_redirects() {
+        assert!(is_loopback_redirect("http://127.0.0.1:53682/callback"));
+        assert!(is_loopback_redirect("http://[::1]:8000/"));
+        assert!(is_loopback_redirect("http://localhost:8000/callback"));
+        assert!(!is_loopback_redirect("https://127.0.0.1/callback"));
+        assert!(!is_loopback_redirect("http://example.com/callback"));
+        assert!(!is_loopback_redirect("http://127.0.0.1.example.com/callback"));
+        assert!(!is_loopback_redirect("not a url"));
+    }
+
+    #[test]
+    fn test_identity_from_claims() {
+        let claims = json!({
+            "sub": "248289761001",
+            "email": "jane@example.com",
+            "email_verified": true,
+            "name": "Jane Doe",
+            "realm_access": { "roles": ["helpdesk", "staff"] },
+            "groups": "admins",
+        });
+        let identity = identity_from_claims(&claims, "realm_access.roles").unwrap();
+        assert_eq!(identity.username, "jane");
+        assert_eq!(identity.display_name, "Jane Doe");
+        assert_eq!(identity.groups, ["helpdesk", "staff"]);
+        assert_eq!(claim_values(&claims, "groups"), ["admins"]);
+        assert!(claim_values(&claims, "roles").is_empty());
+
+        let unverified = json!({ "sub": "1", "email": "jane@example.com", "email_verified": false });
+        assert!(identity_from_claims(&unverified, "groups").is_none());
+        let unstated = json!({ "sub": "1", "email": "jane@example.com" });
+        assert!(identity_from_claims(&unstated, "groups").is_none());
+        assert!(identity_from_claims(&json!({ "sub": "1" }), "groups").is_none());
+    }
+
+    #[test]
+    fn test_pending_sign_ins_expire_and_are_capped() {
+        let provider = OidcProvider::new(OidcConfig {
+            issuer: "https://idp.example.com".to_string(),
+            client_id: "genxlink".to_string(),
+            client_secret: None,
+            scopes: "openid email".to_string(),
+            role_claim: "groups".to_string(),
+            role_mapping: Vec::new(),
+            redirect_uris: Vec::new(),
+        })
+        .unwrap();
+        let authorization = |expires_at| PendingAuthorization {
+            redirect_uri: "http://127.0.0.1:8000/".to_string(),
+            code_challenge: String::new(),
+            nonce: String::new(),
+            expires_at,
+        };
+
+        let now = Instant::now();
+        for n in 0..MAX_PENDING_AUTHORIZATIONS {
+            provider.hold(n.to_string(), authorization(now + AUTHORIZATION_LIFETIME)).unwrap();
+        }
+        assert!(provider.hold("full".to_string(), authorization(now + AUTHORIZATION_LIFETIME)).is_err());
+
+        // Expired sign-ins make room again
+        provider.pending.lock().unwrap().get_mut("0").unwrap().expires_at = now;
+        provider.hold("room".to_string(), authorization(now + AUTHORIZATION_LIFETIME)).unwrap();
+        assert!(!provider.pending.lock().unwrap().contains_key("0"));
+    }
+}
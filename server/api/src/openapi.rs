@@ -0,0 +1,566 @@
+//! OpenAPI 3 description of the admin API
+//!
+//! Written by hand next to `admin_router`; the tests check it against the
+//! router and against the serialized models, so the two can't drift apart.
+
+use serde_json::{json, Map, Value};
+
+/// Where the admin API is mounted
+pub const ADMIN_BASE_PATH: &str = "/api/admin";
+
+enum Body {
+    /// One instance of a component schema
+    Object(&'static str),
+    /// An array of a component schema
+    Array(&'static str),
+    /// `{ "success": ..., "message": ... }`
+    Message,
+}
+
+enum In {
+    Path,
+    Query,
+}
+
+struct Param {
+    name: &'static str,
+    location: In,
+    schema: fn() -> Value,
+    description: &'static str,
+}
+
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    summary: &'static str,
+    params: &'static [Param],
+    request: Option<&'static str>,
+    response: Body,
+    /// Error statuses besides 401 and 403, which every operation can return
+    errors: &'static [(u16, &'static str)],
+}
+
+const LIMIT: Param = Param {
+    name: "limit",
+    location: In::Query,
+    schema: || json!({ "type": "integer", "minimum": 1, "maximum": 1000, "default": 100 }),
+    description: "Maximum number of results",
+};
+
+const OPERATIONS: &[Operation] = &[
+    Operation {
+        method: "get",
+        path: "/openapi.json",
+        summary: "This document",
+        params: &[],
+        request: None,
+        response: Body::Object("OpenApi"),
+        errors: &[],
+    },
+    Operation {
+        method: "get",
+        path: "/stats",
+        summary: "Platform-wide counts",
+        params: &[],
+        request: None,
+        response: Body::Object("SystemStats"),
+        errors: &[],
+    },
+    Operation {
+        method: "get",
+        path: "/devices",
+        summary: "Devices of all users, most recently seen first",
+        params: &[
+            Param {
+                name: "online",
+                location: In::Query,
+                schema: || json!({ "type": "boolean", "default": false }),
+                description: "Only devices currently online",
+            },
+            LIMIT,
+        ],
+        request: None,
+        response: Body::Array("Device"),
+        errors: &[],
+    },
+    Operation {
+        method: "get",
+        path: "/sessions",
+        summary: "Sessions of all users, newest first",
+        params: &[
+            Param {
+                name: "status",
+                location: In::Query,
+                schema: session_status,
+                description: "Only sessions with this status",
+            },
+            LIMIT,
+        ],
+        request: None,
+        response: Body::Array("Session"),
+        errors: &[],
+    },
+    Operation {
+        method: "post",
+        path: "/sessions/{session_id}/disconnect",
+        summary: "Force-disconnect an active session",
+        params: &[Param {
+            name: "session_id",
+            location: In::Path,
+            schema: uuid,
+            description: "Session to terminate",
+        }],
+        request: None,
+        response: Body::Message,
+        errors: &[
+            (404, "Unknown session"),
+            (409, "Session is not active"),
+            (502, "The signaling server could not be reached"),
+            (503, "No signaling server admin endpoint configured"),
+        ],
+    },
+    Operation {
+        method: "get",
+        path: "/usage",
+        summary: "Connections per user and day (UTC)",
+        params: &[
+            Param {
+                name: "from",
+                location: In::Query,
+                schema: date,
+                description: "First day, inclusive; defaults to 29 days before `to`",
+            },
+            Param {
+                name: "to",
+                location: In::Query,
+                schema: date,
+                description: "Last day, inclusive; defaults to today",
+            },
+            Param {
+                name: "user_id",
+                location: In::Query,
+                schema: uuid,
+                description: "Only this user",
+            },
+        ],
+        request: None,
+        response: Body::Array("DailyUsage"),
+        errors: &[(400, "Empty range or longer than 366 days")],
+    },
+    Operation {
+        method: "get",
+        path: "/licenses",
+        summary: "Licenses of all users, newest first",
+        params: &[
+            Param {
+                name: "user_id",
+                location: In::Query,
+                schema: uuid,
+                description: "Only this user's licenses",
+            },
+            LIMIT,
+        ],
+        request: None,
+        response: Body::Array("License"),
+        errors: &[],
+    },
+    Operation {
+        method: "post",
+        path: "/licenses",
+        summary: "Issue a license with a newly generated key",
+        params: &[],
+        request: Some("IssueLicenseRequest"),
+        response: Body::Object("License"),
+        errors: &[(400, "Invalid license type or limits"), (404, "Unknown user")],
+    },
+    Operation {
+        method: "post",
+        path: "/licenses/{license_id}",
+        summary: "Change a license; omitted fields are kept",
+        params: &[Param {
+            name: "license_id",
+            location: In::Path,
+            schema: uuid,
+            description: "License to change",
+        }],
+        request: Some("LicenseUpdate"),
+        response: Body::Object("License"),
+        errors: &[(400, "Invalid limits"), (404, "Unknown license")],
+    },
+    Operation {
+        method: "post",
+        path: "/users/{user_id}/admin",
+        summary: "Grant or revoke platform administration",
+        params: &[Param {
+            name: "user_id",
+            location: In::Path,
+            schema: uuid,
+            description: "User to change",
+        }],
+        request: Some("SetAdminRequest"),
+        response: Body::Message,
+        errors: &[(404, "Unknown user"), (409, "Admins can't revoke their own rights")],
+    },
+];
+
+fn uuid() -> Value {
+    json!({ "type": "string", "format": "uuid" })
+}
+
+fn date() -> Value {
+    json!({ "type": "string", "format": "date" })
+}
+
+fn date_time() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+fn nullable(mut schema: Value) -> Value {
+    schema["nullable"] = json!(true);
+    schema
+}
+
+fn session_status() -> Value {
+    json!({ "type": "string", "enum": ["active", "ended", "failed", "terminated"] })
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn components() -> Value {
+    let int = json!({ "type": "integer", "format": "int64" });
+    let int32 = json!({ "type": "integer", "format": "int32", "minimum": 1 });
+    let string = json!({ "type": "string" });
+    let boolean = json!({ "type": "boolean" });
+    let any_object = json!({ "type": "object" });
+    let license_type = json!({ "type": "string", "enum": ["trial", "basic", "pro", "enterprise"] });
+
+    json!({
+        "OpenApi": { "type": "object", "description": "OpenAPI 3 document" },
+        "Message": object(&["success", "message"], json!({
+            "success": boolean,
+            "message": string,
+        })),
+        "SystemStats": object(
+            &["total_users", "total_devices", "online_devices", "active_sessions", "total_connections"],
+            json!({
+                "total_users": int,
+                "total_devices": int,
+                "online_devices": int,
+                "active_sessions": int,
+                "total_connections": int,
+            }),
+        ),
+        "Device": object(
+            &["id", "user_id", "device_id", "device_name", "device_type", "os_version", "ip_address",
+              "last_seen", "is_online", "capabilities", "metadata", "created_at", "updated_at"],
+            json!({
+                "id": uuid(),
+                "user_id": uuid(),
+                "device_id": string,
+                "org_id": nullable(uuid()),
+                "device_name": string,
+                "device_type": string,
+                "os_version": string,
+                "ip_address": string,
+                "mac_address": nullable(string.clone()),
+                "last_seen": date_time(),
+                "is_online": boolean,
+                "capabilities": any_object,
+                "metadata": any_object,
+                "created_at": date_time(),
+                "updated_at": date_time(),
+            }),
+        ),
+        "Session": object(
+            &["id", "user_id", "device_id", "remote_device_id", "session_type", "started_at", "status", "metadata"],
+            json!({
+                "id": uuid(),
+                "user_id": uuid(),
+                "device_id": uuid(),
+                "remote_device_id": uuid(),
+                "session_type": string,
+                "started_at": date_time(),
+                "ended_at": nullable(date_time()),
+                "duration_seconds": nullable(json!({ "type": "integer", "format": "int32" })),
+                "status": session_status(),
+                "connection_quality": nullable(any_object.clone()),
+                "metadata": any_object,
+            }),
+        ),
+        "DailyUsage": object(
+            &["user_id", "email", "day", "connections", "duration_seconds", "bytes_sent", "bytes_received"],
+            json!({
+                "user_id": uuid(),
+                "email": string,
+                "day": date(),
+                "connections": int,
+                "duration_seconds": int,
+                "bytes_sent": int,
+                "bytes_received": int,
+            }),
+        ),
+        "License": object(
+            &["id", "user_id", "license_key", "license_type", "expires_at", "is_active", "max_devices",
+              "max_concurrent_sessions", "features", "created_at", "updated_at"],
+            json!({
+                "id": uuid(),
+                "user_id": uuid(),
+                "license_key": string,
+                "license_type": license_type,
+                "expires_at": date_time(),
+                "is_active": boolean,
+                "max_devices": int32,
+                "max_concurrent_sessions": int32,
+                "features": any_object,
+                "created_at": date_time(),
+                "updated_at": date_time(),
+            }),
+        ),
+        "IssueLicenseRequest": object(
+            &["user_id", "license_type", "expires_at"],
+            json!({
+                "user_id": uuid(),
+                "license_type": license_type,
+                "expires_at": date_time(),
+                "max_devices": int32,
+                "max_concurrent_sessions": int32,
+                "features": any_object,
+            }),
+        ),
+        "LicenseUpdate": object(&[], json!({
+            "is_active": boolean,
+            "expires_at": date_time(),
+            "max_devices": int32,
+            "max_concurrent_sessions": int32,
+            "features": any_object,
+        })),
+        "SetAdminRequest": object(&["is_admin"], json!({
+            "is_admin": boolean,
+        })),
+    })
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({ "application/json": { "schema": schema } })
+}
+
+fn operation(op: &Operation) -> Value {
+    let response_schema = match op.response {
+        Body::Object(name) => schema_ref(name),
+        Body::Array(name) => json!({ "type": "array", "items": schema_ref(name) }),
+        Body::Message => schema_ref("Message"),
+    };
+    let mut responses = Map::new();
+    responses.insert(
+        "200".to_string(),
+        json!({ "description": "OK", "content": json_content(response_schema) }),
+    );
+    for (status, description) in op.errors.iter().chain(&[
+        (401, "Missing or invalid credentials"),
+        (403, "Not an administrator, or the API key lacks the admin scope"),
+    ]) {
+        responses.insert(status.to_string(), json!({ "description": description }));
+    }
+
+    let parameters: Vec<Value> = op
+        .params
+        .iter()
+        .map(|param| {
+            json!({
+                "name": param.name,
+                "in": match param.location { In::Path => "path", In::Query => "query" },
+                "required": matches!(param.location, In::Path),
+                "description": param.description,
+                "schema": (param.schema)(),
+            })
+        })
+        .collect();
+
+    let mut value = json!({
+        "summary": op.summary,
+        "security": [{ "bearer": [] }, { "apiKey": [] }],
+        "responses": responses,
+    });
+    if !parameters.is_empty() {
+        value["parameters"] = Value::Array(parameters);
+    }
+    if let Some(request) = op.request {
+        value["requestBody"] = json!({ "required": true, "content": json_content(schema_ref(request)) });
+    }
+    value
+}
+
+/// The admin API as an OpenAPI 3.0 document
+pub fn admin_spec() -> Value {
+    let mut paths = Map::new();
+    for op in OPERATIONS {
+        let entry = paths
+            .entry(format!("{}{}", ADMIN_BASE_PATH, op.path))
+            .or_insert_with(|| json!({}));
+        entry[op.method] = operation(op);
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "GenXLink Admin API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Fleet, usage, session and license management for platform administrators. \
+                API keys need the `admin:read` scope for GET and `admin:write` for POST operations.",
+        },
+        "paths": paths,
+        "components": {
+            "schemas": components(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" },
+                "apiKey": { "type": "apiKey", "in": "header", "name": "X-API-Key" },
+            },
+        },
+    })
+}
+
+/// Every `(method, path)` in the document, with `{param}` placeholders
+pub fn admin_operations() -> Vec<(&'static str, String)> {
+    OPERATIONS
+        .iter()
+        .map(|op| (op.method, format!("{}{}", ADMIN_BASE_PATH, op.path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::{IssueLicenseRequest, SetAdminRequest};
+    use crate::models::*;
+    use chrono::{NaiveDate, Utc};
+    use uuid::Uuid;
+
+    fn assert_matches_schema(name: &str, sample: impl serde::Serialize) {
+        let spec = admin_spec();
+        let schema = &spec["components"]["schemas"][name];
+        let mut documented: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+        let sample = serde_json::to_value(sample).unwrap();
+        let mut serialized: Vec<&String> = sample.as_object().unwrap().keys().collect();
+        documented.sort();
+        serialized.sort();
+        assert_eq!(documented, serialized, "schema {} is out of date", name);
+
+        for required in schema["required"].as_array().unwrap() {
+            assert!(!sample[required.as_str().unwrap()].is_null(), "{}.{} is required", name, required);
+        }
+    }
+
+    #[test]
+    fn test_schemas_match_models() {
+        let now = Utc::now();
+        assert_matches_schema("SystemStats", SystemStats {
+            total_users: 1,
+            total_devices: 2,
+            online_devices: 1,
+            active_sessions: 0,
+            total_connections: 3,
+        });
+        assert_matches_schema("Device", Device {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            device_id: "device".to_string(),
+            org_id: None,
+            device_name: "Laptop".to_string(),
+            device_type: "desktop".to_string(),
+            os_version: "Windows 11".to_string(),
+            ip_address: "192.0.2.1".to_string(),
+            mac_address: None,
+            last_seen: now,
+            is_online: true,
+            capabilities: json!({}),
+            metadata: json!({}),
+            created_at: now,
+            updated_at: now,
+        });
+        assert_matches_schema("Session", Session {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            device_id: Uuid::new_v4(),
+            remote_device_id: Uuid::new_v4(),
+            session_type: "remote_control".to_string(),
+            started_at: now,
+            ended_at: None,
+            duration_seconds: None,
+            status: "active".to_string(),
+            connection_quality: None,
+            metadata: json!({}),
+        });
+        assert_matches_schema("DailyUsage", DailyUsage {
+            user_id: Uuid::new_v4(),
+            email: "user@example.com".to_string(),
+            day: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            connections: 1,
+            duration_seconds: 60,
+            bytes_sent: 10,
+            bytes_received: 20,
+        });
+        let license = License {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            license_key: "AAAAA-BBBBB-CCCCC-DDDDD-EEEEE".to_string(),
+            license_type: "pro".to_string(),
+            expires_at: now,
+            is_active: true,
+            max_devices: 5,
+            max_concurrent_sessions: 2,
+            features: json!({}),
+            created_at: now,
+            updated_at: now,
+        };
+        assert_matches_schema("License", license);
+        assert_matches_schema("LicenseUpdate", LicenseUpdate::default());
+        assert_matches_schema("IssueLicenseRequest", IssueLicenseRequest {
+            user_id: Uuid::new_v4(),
+            license_type: "pro".to_string(),
+            expires_at: now,
+            max_devices: None,
+            max_concurrent_sessions: None,
+            features: None,
+        });
+        assert_matches_schema("SetAdminRequest", SetAdminRequest { is_admin: true });
+    }
+
+    #[test]
+    fn test_spec_references_resolve() {
+        let spec = admin_spec();
+        let text = spec.to_string();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("#/components/schemas/") {
+            rest = &rest[start + "#/components/schemas/".len()..];
+            let name = &rest[..rest.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "unknown schema {}", name);
+        }
+
+        for (method, path) in admin_operations() {
+            let op = &spec["paths"][&path][method];
+            assert!(op.is_object(), "{} {} missing", method, path);
+            let documented: Vec<&str> = op["parameters"]
+                .as_array()
+                .map(|params| {
+                    params
+                        .iter()
+                        .filter(|param| param["in"] == "path")
+                        .map(|param| param["name"].as_str().unwrap())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let placeholders: Vec<&str> = path
+                .split('/')
+                .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+                .collect();
+            assert_eq!(documented, placeholders, "{} {}", method, path);
+        }
+    }
+}
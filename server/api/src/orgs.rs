@@ -0,0 +1,195 @@
+use anyhow::Result;
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::models::Device;
+
+/// How long an organization invitation stays valid
+pub const INVITATION_LIFETIME: Duration = Duration::days(7);
+
+/// Role of a user within an organization, weakest first
+///
+/// Owners and admins map to the client's `RoleCategory::Administrator`,
+/// operators to `RoleCategory::Operator` and members to `RoleCategory::User`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Member,
+    Operator,
+    Admin,
+    Owner,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Member => "member",
+            OrgRole::Operator => "operator",
+            OrgRole::Admin => "admin",
+            OrgRole::Owner => "owner",
+        }
+    }
+
+    /// Manage members, invitations, teams and device groups, and reach
+    /// every device of the organization
+    pub fn is_admin(&self) -> bool {
+        *self >= OrgRole::Admin
+    }
+
+    /// Whether this role may give `role` to someone, or change the role of
+    /// someone who has it
+    ///
+    /// Admins manage operators and members; only owners manage admins and
+    /// other owners.
+    pub fn can_assign(&self, role: OrgRole) -> bool {
+        match self {
+            OrgRole::Owner => true,
+            OrgRole::Admin => role < OrgRole::Admin,
+            _ => false,
+        }
+    }
+
+    /// Strongest access this role can receive through a team grant
+    ///
+    /// Members only ever watch; remote control needs an operator.
+    pub fn max_granted_access(&self) -> DeviceAccess {
+        match self {
+            OrgRole::Member => DeviceAccess::View,
+            _ => DeviceAccess::Control,
+        }
+    }
+}
+
+impl fmt::Display for OrgRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrgRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "member" => Ok(OrgRole::Member),
+            "operator" => Ok(OrgRole::Operator),
+            "admin" => Ok(OrgRole::Admin),
+            "owner" => Ok(OrgRole::Owner),
+            _ => Err(format!("Unknown organization role: {}", s)),
+        }
+    }
+}
+
+/// Access a device group grant gives a team, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceAccess {
+    /// See the device and watch its screen
+    View,
+    /// Start remote control sessions
+    Control,
+}
+
+impl DeviceAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceAccess::View => "view",
+            DeviceAccess::Control => "control",
+        }
+    }
+}
+
+impl fmt::Display for DeviceAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeviceAccess {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "view" => Ok(DeviceAccess::View),
+            "control" => Ok(DeviceAccess::Control),
+            _ => Err(format!("Unknown device access: {}", s)),
+        }
+    }
+}
+
+/// Access `user_id` has to `device`, if any
+///
+/// Owners of a device control it. Organization devices are controlled by the
+/// organization's admins, and reachable by other members only through a
+/// device group granted to one of their teams.
+pub async fn device_access(db: &Database, user_id: Uuid, device: &Device) -> Result<Option<DeviceAccess>> {
+    if device.user_id == user_id {
+        return Ok(Some(DeviceAccess::Control));
+    }
+    let Some(org_id) = device.org_id else {
+        return Ok(None);
+    };
+    let Some(role) = db.get_member_role(org_id, user_id).await? else {
+        return Ok(None);
+    };
+    if role.is_admin() {
+        return Ok(Some(DeviceAccess::Control));
+    }
+
+    let granted = db.get_team_device_access(user_id, device.id).await?;
+    Ok(granted.map(|access| access.min(role.max_granted_access())))
+}
+
+/// Every device `user_id` can see, most recently seen first
+pub async fn accessible_devices(db: &Database, user_id: Uuid) -> Result<Vec<Device>> {
+    let mut devices = db.get_user_devices(user_id).await?;
+    for (org, role) in db.get_user_organizations(user_id).await? {
+        if role.is_admin() {
+            devices.extend(db.get_org_devices(org.id).await?);
+        }
+    }
+    devices.extend(db.get_team_granted_devices(user_id).await?);
+
+    let mut seen = HashSet::new();
+    devices.retain(|device| seen.insert(device.id));
+    devices.sort_by_key(|device| std::cmp::Reverse(device.last_seen));
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_ordering_and_assignment() {
+        assert!(OrgRole::Owner > OrgRole::Admin);
+        assert!(OrgRole::Admin.is_admin());
+        assert!(!OrgRole::Operator.is_admin());
+
+        assert!(OrgRole::Owner.can_assign(OrgRole::Owner));
+        assert!(OrgRole::Admin.can_assign(OrgRole::Operator));
+        assert!(!OrgRole::Admin.can_assign(OrgRole::Admin));
+        assert!(!OrgRole::Operator.can_assign(OrgRole::Member));
+    }
+
+    #[test]
+    fn test_member_grants_capped_at_view() {
+        assert_eq!(DeviceAccess::Control.min(OrgRole::Member.max_granted_access()), DeviceAccess::View);
+        assert_eq!(DeviceAccess::Control.min(OrgRole::Operator.max_granted_access()), DeviceAccess::Control);
+    }
+
+    #[test]
+    fn test_role_round_trip() {
+        for role in [OrgRole::Member, OrgRole::Operator, OrgRole::Admin, OrgRole::Owner] {
+            assert_eq!(role.as_str().parse::<OrgRole>(), Ok(role));
+            assert_eq!(serde_json::to_value(role).unwrap(), role.as_str());
+        }
+        assert!("superuser".parse::<OrgRole>().is_err());
+        assert_eq!("control".parse::<DeviceAccess>(), Ok(DeviceAccess::Control));
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Minimum accepted password length (in characters)
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Maximum accepted password length, bounds hashing cost
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+
+/// Argon2id parameters (OWASP recommendation: 19 MiB, 2 iterations)
+const ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_PARALLELISM: u32 = 1;
+
+/// Result of checking a password against a stored hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    pub valid: bool,
+    /// Hash uses a legacy algorithm or outdated parameters
+    pub needs_rehash: bool,
+}
+
+fn argon2() -> Argon2<'static> {
+    let params = Params::new(ARGON2_MEMORY_KIB, ARGON2_ITERATIONS, ARGON2_PARALLELISM, None)
+        .expect("valid Argon2 parameters");
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Check length requirements for a new password
+pub fn validate_new_password(password: &str) -> std::result::Result<(), String> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LENGTH));
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(format!("Password must be at most {} characters", MAX_PASSWORD_LENGTH));
+    }
+    Ok(())
+}
+
+/// Hash a password with Argon2id
+///
+/// Hashing takes tens of milliseconds of CPU, so it runs on the blocking
+/// thread pool rather than stalling the async workers.
+pub async fn hash_password(password: &str) -> Result<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || hash_blocking(&password))
+        .await
+        .map_err(|e| anyhow!("Password hashing task failed: {}", e))?
+}
+
+/// Verify a password against a stored Argon2 or bcrypt hash
+///
+/// Runs on the blocking thread pool, like `hash_password`.
+pub async fn verify_password(password: &str, stored_hash: &str) -> Verification {
+    let password = password.to_string();
+    let stored_hash = stored_hash.to_string();
+    tokio::task::spawn_blocking(move || verify_blocking(&password, &stored_hash))
+        .await
+        .unwrap_or(Verification { valid: false, needs_rehash: false })
+}
+
+/// Burn the same time as a real verification when the account doesn't exist
+pub async fn verify_dummy(password: &str) {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    let password = password.to_string();
+    let _ = tokio::task::spawn_blocking(move || {
+        let hash = DUMMY_HASH.get_or_init(|| {
+            hash_blocking("genxlink-dummy-password").expect("hash dummy password")
+        });
+        verify_blocking(&password, hash)
+    })
+    .await;
+}
+
+fn hash_blocking(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow!("Failed to hash password: {}", e))
+}
+
+fn verify_blocking(password: &str, stored_hash: &str) -> Verification {
+    if password.len() > MAX_PASSWORD_LENGTH * 4 {
+        return Verification { valid: false, needs_rehash: false };
+    }
+
+    if is_bcrypt(stored_hash) {
+        let valid = bcrypt::verify(password, stored_hash).unwrap_or(false);
+        return Verification { valid, needs_rehash: valid };
+    }
+
+    let parsed = match PasswordHash::new(stored_hash) {
+        Ok(parsed) => parsed,
+        Err(_) => return Verification { valid: false, needs_rehash: false },
+    };
+
+    let valid = argon2().verify_password(password.as_bytes(), &parsed).is_ok();
+    let needs_rehash = valid && !has_current_params(&parsed);
+    Verification { valid, needs_rehash }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    hash.starts_with("$2a$") || hash.starts_with("$2b$") || hash.starts_with("$2y$")
+}
+
+fn has_current_params(hash: &PasswordHash) -> bool {
+    if hash.algorithm.as_str() != "argon2id" || hash.version != Some(Version::V0x13 as u32) {
+        return false;
+    }
+
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() == ARGON2_MEMORY_KIB
+                && params.t_cost() == ARGON2_ITERATIONS
+                && params.p_cost() == ARGON2_PARALLELISM
+        }
+        Err(_) => false,
+    }
+}
+
+/// Stored as the hash of accounts without a local password (e.g. directory
+/// users); it never verifies
+pub const NO_PASSWORD: &str = "!";
+
+/// Generate a random URL-safe token (256 bits, hex encoded)
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// SHA-256 hash of a token, as stored in the database
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_and_verify() {
+        let hash = hash_password("correct horse battery").await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+
+        let ok = verify_password("correct horse battery", &hash).await;
+        assert!(ok.valid);
+        assert!(!ok.needs_rehash);
+
+        assert!(!verify_password("wrong password", &hash).await.valid);
+        assert!(!verify_password("correct horse battery", "not-a-hash").await.valid);
+    }
+
+    #[tokio::test]
+    async fn test_bcrypt_upgrade() {
+        let legacy = bcrypt::hash("legacy password", 4).unwrap();
+
+        let ok = verify_password("legacy password", &legacy).await;
+        assert!(ok.valid);
+        assert!(ok.needs_rehash);
+
+        let bad = verify_password("other password", &legacy).await;
+        assert!(!bad.valid);
+        assert!(!bad.needs_rehash);
+    }
+
+    #[tokio::test]
+    async fn test_outdated_params_need_rehash() {
+        let weak = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(1024, 1, 1, None).unwrap());
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = weak.hash_password(b"some password", &salt).unwrap().to_string();
+
+        let result = verify_password("some password", &hash).await;
+        assert!(result.valid);
+        assert!(result.needs_rehash);
+    }
+
+    #[test]
+    fn test_validate_new_password() {
+        assert!(validate_new_password("short").is_err());
+        assert!(validate_new_password("long enough").is_ok());
+        assert!(validate_new_password(&"x".repeat(MAX_PASSWORD_LENGTH + 1)).is_err());
+    }
+
+    #[test]
+    fn test_tokens() {
+        let a = generate_token();
+        let b = generate_token();
+        assert_eq!(a.len(), 64);
+        assert_ne!(a, b);
+        assert_eq!(hash_token(&a), hash_token(&a));
+        assert_ne!(hash_token(&a), a);
+    }
+}
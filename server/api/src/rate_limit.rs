@@ -0,0 +1,727 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, OriginalUri, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::models::User;
+
+/// Largest request body read to find the account a request is for
+const MAX_ACCOUNT_BODY_BYTES: usize = 64 * 1024;
+
+/// Buckets kept in memory before idle ones are swept
+const MIN_SWEEP_THRESHOLD: usize = 1024;
+
+/// Token bucket: up to `burst` requests at once, refilled at `burst` per `period`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitPolicy {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl RateLimitPolicy {
+    pub const fn new(burst: u32, period: Duration) -> Self {
+        RateLimitPolicy { burst, period }
+    }
+
+    pub const fn per_minute(burst: u32) -> Self {
+        Self::new(burst, Duration::from_secs(60))
+    }
+
+    fn period_ms(&self) -> u64 {
+        (self.period.as_millis() as u64).max(1)
+    }
+
+    fn tokens_per_ms(&self) -> f64 {
+        self.burst as f64 / self.period_ms() as f64
+    }
+}
+
+/// What a rule counts requests by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKey {
+    /// Client address (IPv6 clients are grouped by /64)
+    ClientIp,
+    /// `email` field of the JSON body, on public endpoints such as
+    /// `/auth/password-reset`
+    Email,
+    /// `email` field of the JSON body from one client address, so nobody
+    /// else can use up an account's budget and lock its owner out
+    EmailFromIp,
+    /// Signed-in user
+    User,
+}
+
+impl LimitKey {
+    /// Whether the key is read from the request body
+    fn needs_body(self) -> bool {
+        matches!(self, LimitKey::Email | LimitKey::EmailFromIp)
+    }
+}
+
+/// A policy applied to the requests matching `method` and `path`
+///
+/// `path` is matched against the full request path; a trailing `*` matches
+/// any suffix. Every matching rule is enforced, each with its own buckets.
+#[derive(Debug, Clone)]
+pub struct RateLimitRule {
+    pub name: String,
+    pub method: Option<Method>,
+    pub path: String,
+    pub key: LimitKey,
+    pub policy: RateLimitPolicy,
+}
+
+impl RateLimitRule {
+    pub fn new(name: &str, method: Option<Method>, path: &str, key: LimitKey, policy: RateLimitPolicy) -> Self {
+        RateLimitRule {
+            name: name.to_string(),
+            method,
+            path: path.to_string(),
+            key,
+            policy,
+        }
+    }
+
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        if self.method.as_ref().is_some_and(|m| m != method) {
+            return false;
+        }
+        match self.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.path,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub rules: Vec<RateLimitRule>,
+    /// Proxies whose `X-Forwarded-For` / `X-Real-IP` headers are believed
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let post = Some(Method::POST);
+        RateLimitConfig {
+            rules: vec![
+                // Credential stuffing: limit per address and per targeted account from each address
+                RateLimitRule::new("login-ip", post.clone(), "/auth/login", LimitKey::ClientIp, RateLimitPolicy::per_minute(20)),
+                RateLimitRule::new("login-account", post.clone(), "/auth/login", LimitKey::EmailFromIp, RateLimitPolicy::new(5, Duration::from_secs(5 * 60))),
+                RateLimitRule::new("mfa-ip", post.clone(), "/auth/mfa/verify", LimitKey::ClientIp, RateLimitPolicy::per_minute(10)),
+                RateLimitRule::new("register-ip", post.clone(), "/auth/register", LimitKey::ClientIp, RateLimitPolicy::new(10, Duration::from_secs(60 * 60))),
+                RateLimitRule::new("password-reset-ip", post.clone(), "/auth/password-reset*", LimitKey::ClientIp, RateLimitPolicy::new(10, Duration::from_secs(15 * 60))),
+                RateLimitRule::new("password-reset-account", post, "/auth/password-reset", LimitKey::Email, RateLimitPolicy::new(3, Duration::from_secs(60 * 60))),
+                RateLimitRule::new("auth-ip", None, "/auth/*", LimitKey::ClientIp, RateLimitPolicy::per_minute(60)),
+                RateLimitRule::new("api-ip", None, "/api/*", LimitKey::ClientIp, RateLimitPolicy::per_minute(1200)),
+                RateLimitRule::new("api-account", None, "/api/*", LimitKey::User, RateLimitPolicy::per_minute(600)),
+                RateLimitRule::new("health-ip", None, "/health", LimitKey::ClientIp, RateLimitPolicy::per_minute(100)),
+            ],
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Default rules, with trusted proxies from `TRUSTED_PROXIES`
+    /// (comma-separated addresses or CIDR ranges)
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(proxies) = env::var("TRUSTED_PROXIES") {
+            config.trusted_proxies = parse_trusted_proxies(&proxies)?;
+        }
+        Ok(config)
+    }
+}
+
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpNet>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| anyhow!("Invalid trusted proxy: {}", entry))
+        })
+        .collect()
+}
+
+/// Outcome of taking a token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    /// Requests left right now
+    pub remaining: u32,
+    /// Until the bucket is full again
+    pub reset: Duration,
+    /// Until the next request is allowed, when denied
+    pub retry_after: Option<Duration>,
+    pub period: Duration,
+}
+
+impl Decision {
+    fn new(policy: &RateLimitPolicy, tokens: f64, allowed: bool) -> Self {
+        let rate = policy.tokens_per_ms();
+        let ms = |tokens: f64| Duration::from_millis((tokens / rate).ceil().max(0.0) as u64);
+        Decision {
+            allowed,
+            limit: policy.burst,
+            remaining: tokens.floor().max(0.0) as u32,
+            reset: ms(policy.burst as f64 - tokens),
+            retry_after: (!allowed).then(|| ms(1.0 - tokens)),
+            period: policy.period,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_ms: u64,
+    /// When the bucket will be full again; after that it can be forgotten
+    full_at_ms: u64,
+}
+
+/// Refill a bucket up to `now_ms` and try to take one token
+fn take_token(bucket: Option<Bucket>, policy: &RateLimitPolicy, now_ms: u64) -> (Bucket, Decision) {
+    let burst = policy.burst as f64;
+    let rate = policy.tokens_per_ms();
+    let mut tokens = match bucket {
+        Some(bucket) => (bucket.tokens + now_ms.saturating_sub(bucket.updated_ms) as f64 * rate).min(burst),
+        None => burst,
+    };
+
+    let allowed = tokens >= 1.0;
+    if allowed {
+        tokens -= 1.0;
+    }
+
+    let bucket = Bucket {
+        tokens,
+        updated_ms: now_ms,
+        full_at_ms: now_ms + ((burst - tokens) / rate).ceil() as u64,
+    };
+    (bucket, Decision::new(policy, tokens, allowed))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Buckets held by this process
+struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    sweep_threshold: Mutex<usize>,
+}
+
+impl MemoryStore {
+    fn new() -> Self {
+        MemoryStore {
+            buckets: Mutex::new(HashMap::new()),
+            sweep_threshold: Mutex::new(MIN_SWEEP_THRESHOLD),
+        }
+    }
+
+    fn take(&self, key: &str, policy: &RateLimitPolicy, now_ms: u64) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+        let (bucket, decision) = take_token(buckets.get(key).copied(), policy, now_ms);
+        buckets.insert(key.to_string(), bucket);
+
+        // Full buckets behave exactly like missing ones, so they can go
+        let mut threshold = self.sweep_threshold.lock().unwrap();
+        if buckets.len() > *threshold {
+            buckets.retain(|_, bucket| bucket.full_at_ms > now_ms);
+            *threshold = (buckets.len() * 2).max(MIN_SWEEP_THRESHOLD);
+        }
+
+        decision
+    }
+
+    fn len(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+}
+
+/// Same algorithm as `take_token`, run atomically in Redis with the server's clock
+const REDIS_TAKE_SCRIPT: &str = r#"
+local burst = tonumber(ARGV[1])
+local period_ms = tonumber(ARGV[2])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated')
+local tokens = tonumber(state[1])
+if tokens == nil then
+    tokens = burst
+else
+    local elapsed = math.max(0, now - tonumber(state[2]))
+    tokens = math.min(burst, tokens + elapsed * burst / period_ms)
+end
+local allowed = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', tostring(now))
+redis.call('PEXPIRE', KEYS[1], period_ms)
+return {allowed, tostring(tokens)}
+"#;
+
+/// Buckets shared by all replicas through Redis
+struct RedisStore {
+    client: redis::Client,
+    connection: RwLock<Option<redis::aio::MultiplexedConnection>>,
+    script: redis::Script,
+}
+
+impl RedisStore {
+    async fn connect(url: &str) -> Result<Self> {
+        let client = redis::Client::open(url)?;
+        let connection = client.get_multiplexed_tokio_connection().await?;
+        Ok(RedisStore {
+            client,
+            connection: RwLock::new(Some(connection)),
+            script: redis::Script::new(REDIS_TAKE_SCRIPT),
+        })
+    }
+
+    async fn connection(&self) -> Result<redis::aio::MultiplexedConnection> {
+        if let Some(connection) = self.connection.read().await.as_ref() {
+            return Ok(connection.clone());
+        }
+        let connection = self.client.get_multiplexed_tokio_connection().await?;
+        *self.connection.write().await = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn take(&self, key: &str, policy: &RateLimitPolicy) -> Result<Decision> {
+        let mut connection = self.connection().await?;
+        let result: redis::RedisResult<(i64, String)> = self
+            .script
+            .key(format!("genxlink:ratelimit:{}", key))
+            .arg(policy.burst)
+            .arg(policy.period_ms())
+            .invoke_async(&mut connection)
+            .await;
+
+        let (allowed, tokens) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                // Reconnect on the next request
+                *self.connection.write().await = None;
+                return Err(e.into());
+            }
+        };
+        let tokens: f64 = tokens.parse()?;
+        Ok(Decision::new(policy, tokens, allowed == 1))
+    }
+}
+
+/// Token-bucket rate limiter shared by the rate limiting middleware
+///
+/// Buckets live in memory unless a Redis backend is configured, in which case
+/// all replicas share them. If Redis is unreachable the limiter falls back to
+/// local buckets rather than rejecting traffic.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    memory: MemoryStore,
+    redis: Option<RedisStore>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            memory: MemoryStore::new(),
+            redis: None,
+        }
+    }
+
+    pub async fn with_redis(config: RateLimitConfig, redis_url: &str) -> Result<Self> {
+        Ok(RateLimiter {
+            redis: Some(RedisStore::connect(redis_url).await?),
+            ..Self::new(config)
+        })
+    }
+
+    /// Configure from `TRUSTED_PROXIES` and, for a shared backend, `RATE_LIMIT_REDIS_URL`
+    pub async fn from_env() -> Result<Self> {
+        let config = RateLimitConfig::from_env()?;
+        match env::var("RATE_LIMIT_REDIS_URL") {
+            Ok(url) if !url.is_empty() => {
+                let limiter = Self::with_redis(config, &url).await?;
+                info!("Rate limits shared through Redis");
+                Ok(limiter)
+            }
+            _ => Ok(Self::new(config)),
+        }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Take a token from `rule`'s bucket for `key`
+    pub async fn check(&self, rule: &RateLimitRule, key: &str) -> Decision {
+        let bucket = format!("{}:{}", rule.name, key);
+        if let Some(redis) = &self.redis {
+            match redis.take(&bucket, &rule.policy).await {
+                Ok(decision) => return decision,
+                Err(e) => warn!("Redis rate limiting unavailable, using local limits: {}", e),
+            }
+        }
+        self.memory.take(&bucket, &rule.policy, now_ms())
+    }
+
+    /// Number of buckets held in memory
+    pub fn local_buckets(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Address of the client, looking through trusted proxies only
+    ///
+    /// `X-Forwarded-For` is read right to left, skipping trusted proxies; the
+    /// first untrusted hop is the client, or the leftmost hop if all are
+    /// trusted. A hop that isn't an address ends the walk at the last trusted
+    /// one. `X-Real-IP` is only read from a trusted proxy that sent no
+    /// `X-Forwarded-For`: proxies that append to that header may pass a
+    /// client's own `X-Real-IP` through. Headers from untrusted peers are ignored.
+    pub fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let trusted = |ip: &IpAddr| self.config.trusted_proxies.iter().any(|net| net.contains(ip));
+        let mut client = peer?;
+        if !trusted(&client) {
+            return Some(client);
+        }
+
+        if !headers.contains_key("x-forwarded-for") {
+            return Some(
+                headers
+                    .get("x-real-ip")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok())
+                    .unwrap_or(client),
+            );
+        }
+
+        let hops: Vec<Option<IpAddr>> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .flat_map(|value| match value.to_str() {
+                Ok(value) => value.split(',').map(|hop| hop.trim().parse().ok()).collect(),
+                Err(_) => vec![None],
+            })
+            .collect();
+
+        for hop in hops.into_iter().rev() {
+            if !trusted(&client) {
+                break;
+            }
+            match hop {
+                Some(hop) => client = hop,
+                None => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Key for per-address limits; IPv6 clients usually own a whole /64
+fn ip_key(ip: Option<IpAddr>) -> String {
+    match ip {
+        Some(IpAddr::V6(v6)) if v6.to_ipv4_mapped().is_none() => {
+            let segments = v6.segments();
+            let prefix = Ipv6Addr::new(segments[0], segments[1], segments[2], segments[3], 0, 0, 0, 0);
+            format!("{}/64", prefix)
+        }
+        Some(IpAddr::V6(v6)) => v6.to_ipv4_mapped().map(|v4| v4.to_string()).unwrap_or_default(),
+        Some(ip) => ip.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    /// Before authentication: address limits and body-identified accounts
+    Public,
+    /// After authentication: per-user limits
+    Authenticated,
+}
+
+/// Enforce address limits and per-account limits on public endpoints
+///
+/// Wraps the whole router so unauthenticated traffic to `/api` is limited too.
+pub async fn rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    enforce(limiter, Stage::Public, request, next).await
+}
+
+/// Enforce per-account limits for signed-in users; runs after `auth_middleware`
+pub async fn account_rate_limit_middleware(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    enforce(limiter, Stage::Authenticated, request, next).await
+}
+
+async fn enforce(limiter: Arc<RateLimiter>, stage: Stage, mut request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let method = request.method().clone();
+
+    let rules: Vec<&RateLimitRule> = limiter
+        .config
+        .rules
+        .iter()
+        .filter(|rule| rule.matches(&method, &path))
+        .filter(|rule| match stage {
+            Stage::Public => rule.key != LimitKey::User,
+            Stage::Authenticated => rule.key == LimitKey::User,
+        })
+        .collect();
+    if rules.is_empty() {
+        return next.run(request).await;
+    }
+
+    let user = match stage {
+        Stage::Authenticated => request.extensions().get::<User>().map(|user| format!("user:{}", user.id)),
+        Stage::Public => None,
+    };
+
+    // Only the endpoints limited per email pay for buffering the body
+    let email = if rules.iter().any(|rule| rule.key.needs_body()) {
+        let (parts, body) = request.into_parts();
+        let bytes = match to_bytes(body, MAX_ACCOUNT_BODY_BYTES).await {
+            Ok(bytes) => bytes,
+            Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        };
+        let email = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|body| body.get("email")?.as_str().map(|email| email.trim().to_lowercase()))
+            .filter(|email| !email.is_empty());
+        request = Request::from_parts(parts, Body::from(bytes));
+        email.map(|email| format!("email:{}", email))
+    } else {
+        None
+    };
+
+    let client_ip = match stage {
+        Stage::Public => {
+            let peer = request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip());
+            Some(ip_key(limiter.client_ip(peer, request.headers())))
+        }
+        Stage::Authenticated => None,
+    };
+    let email_from_ip = email
+        .as_deref()
+        .zip(client_ip.as_deref())
+        .map(|(email, ip)| format!("{}@{}", email, ip));
+
+    let mut tightest: Option<Decision> = None;
+    let mut denied: Option<Decision> = None;
+    for rule in rules {
+        let key = match rule.key {
+            LimitKey::ClientIp => client_ip.as_deref(),
+            LimitKey::Email => email.as_deref(),
+            LimitKey::EmailFromIp => email_from_ip.as_deref(),
+            LimitKey::User => user.as_deref(),
+        };
+        let Some(key) = key else { continue };
+
+        let decision = limiter.check(rule, key).await;
+        if !decision.allowed {
+            warn!("Rate limit {} exceeded by {} on {} {}", rule.name, key, method, path);
+            if denied.is_none_or(|d| d.retry_after < decision.retry_after) {
+                denied = Some(decision);
+            }
+        } else if tightest.is_none_or(|t| decision.remaining < t.remaining) {
+            tightest = Some(decision);
+        }
+    }
+
+    if let Some(decision) = denied {
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            axum::Json(serde_json::json!({
+                "success": false,
+                "message": "Too many requests, try again later"
+            })),
+        )
+            .into_response();
+        let retry_after = decision.retry_after.unwrap_or_default();
+        insert_header(response.headers_mut(), "retry-after", seconds(retry_after));
+        insert_rate_limit_headers(response.headers_mut(), &decision);
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    if let Some(decision) = tightest {
+        insert_rate_limit_headers(response.headers_mut(), &decision);
+    }
+    response
+}
+
+/// Whole seconds, rounded up so clients don't retry too early
+fn seconds(duration: Duration) -> u64 {
+    duration.as_millis().div_ceil(1000) as u64
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &'static str, value: impl ToString) {
+    if let Ok(value) = HeaderValue::from_str(&value.to_string()) {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+/// `RateLimit-*` headers (IETF draft-ietf-httpapi-ratelimit-headers)
+fn insert_rate_limit_headers(headers: &mut HeaderMap, decision: &Decision) {
+    insert_header(headers, "ratelimit-limit", decision.limit);
+    insert_header(headers, "ratelimit-remaining", decision.remaining);
+    insert_header(headers, "ratelimit-reset", seconds(decision.reset));
+    insert_header(
+        headers,
+        "ratelimit-policy",
+        format!("{};w={}", decision.limit, decision.period.as_secs()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let policy = RateLimitPolicy::new(3, Duration::from_secs(3));
+        let mut bucket = None;
+        for remaining in [2, 1, 0] {
+            let (next, decision) = take_token(bucket, &policy, 0);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+            bucket = Some(next);
+        }
+
+        let (next, decision) = take_token(bucket, &policy, 0);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Some(Duration::from_secs(1)));
+        assert_eq!(decision.reset, Duration::from_secs(3));
+
+        // One token back after a second, never more than the burst
+        let (_, decision) = take_token(Some(next), &policy, 1000);
+        assert!(decision.allowed);
+        let (_, decision) = take_token(Some(next), &policy, 60_000);
+        assert_eq!(decision.remaining, 2);
+    }
+
+    #[test]
+    fn test_memory_store_sweeps_full_buckets() {
+        let store = MemoryStore::new();
+        let policy = RateLimitPolicy::new(10, Duration::from_secs(1));
+        for i in 0..MIN_SWEEP_THRESHOLD {
+            store.take(&format!("client-{}", i), &policy, 0);
+        }
+        // All earlier buckets have refilled by now
+        store.take("late", &policy, 5_000);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_rule_matching() {
+        let config = RateLimitConfig::default();
+        let names = |method: Method, path: &str| -> Vec<String> {
+            config
+                .rules
+                .iter()
+                .filter(|rule| rule.matches(&method, path))
+                .map(|rule| rule.name.clone())
+                .collect()
+        };
+        assert_eq!(names(Method::POST, "/auth/login"), ["login-ip", "login-account", "auth-ip"]);
+        assert_eq!(names(Method::GET, "/auth/login"), ["auth-ip"]);
+        assert_eq!(names(Method::POST, "/auth/password-reset/confirm"), ["password-reset-ip", "auth-ip"]);
+        assert_eq!(names(Method::GET, "/api/devices"), ["api-ip", "api-account"]);
+
+        // Only the login and reset endpoints read the body
+        let reads_body = |method: Method, path: &str| {
+            config.rules.iter().any(|rule| rule.matches(&method, path) && rule.key.needs_body())
+        };
+        assert!(reads_body(Method::POST, "/auth/login"));
+        assert!(reads_body(Method::POST, "/auth/password-reset"));
+        assert!(!reads_body(Method::POST, "/api/devices"));
+        assert!(!reads_body(Method::POST, "/auth/register"));
+    }
+
+    #[test]
+    fn test_client_ip_through_trusted_proxies() {
+        let config = RateLimitConfig {
+            trusted_proxies: parse_trusted_proxies("10.0.0.0/8, 192.168.1.1").unwrap(),
+            ..RateLimitConfig::default()
+        };
+        let limiter = RateLimiter::new(config);
+        let proxy: IpAddr = "10.0.0.5".parse().unwrap();
+        let stranger: IpAddr = "203.0.113.9".parse().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "198.51.100.7, 192.168.1.1".parse().unwrap());
+
+        // Spoofed header from an untrusted peer is ignored
+        assert_eq!(limiter.client_ip(Some(stranger), &headers), Some(stranger));
+        // Through two trusted proxies
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some("198.51.100.7".parse().unwrap()));
+
+        // A client prepending fake hops can't get past the first untrusted one
+        headers.insert("x-forwarded-for", "1.1.1.1, 198.51.100.7".parse().unwrap());
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some("198.51.100.7".parse().unwrap()));
+
+        // An unreadable hop stops the walk instead of skipping to what a client put before it
+        headers.insert("x-forwarded-for", "1.1.1.1, garbage, 10.0.0.7".parse().unwrap());
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some("10.0.0.7".parse().unwrap()));
+
+        // Only the leftmost hop left when every hop is trusted
+        headers.insert("x-forwarded-for", "10.0.0.8, 10.0.0.7".parse().unwrap());
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some("10.0.0.8".parse().unwrap()));
+
+        // X-Real-IP next to X-Forwarded-For may come from the client
+        headers.insert("x-forwarded-for", "garbage".parse().unwrap());
+        headers.insert("x-real-ip", "192.0.2.1".parse().unwrap());
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some(proxy));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", "198.51.100.8".parse().unwrap());
+        assert_eq!(limiter.client_ip(Some(proxy), &headers), Some("198.51.100.8".parse().unwrap()));
+        assert_eq!(limiter.client_ip(Some(stranger), &headers), Some(stranger));
+
+        assert!(parse_trusted_proxies("not-an-ip").is_err());
+    }
+
+    #[test]
+    fn test_ipv6_clients_grouped_by_prefix() {
+        assert_eq!(ip_key(Some("2001:db8:1:2:aaaa::1".parse().unwrap())), "2001:db8:1:2::/64");
+        assert_eq!(ip_key(Some("::ffff:198.51.100.7".parse().unwrap())), "198.51.100.7");
+        assert_eq!(ip_key(None), "unknown");
+    }
+}
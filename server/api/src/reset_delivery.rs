@@ -0,0 +1,114 @@
+//! Password reset delivery
+//!
+//! The API doesn't send mail itself. Issued reset tokens are posted as JSON
+//! to a webhook (`PASSWORD_RESET_WEBHOOK_URL`), typically a mailer service
+//! that turns them into the reset link sent to the account's email.
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use std::env;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::auth::PasswordResetIssued;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook that receives issued password reset tokens
+pub struct ResetWebhook {
+    url: Url,
+    /// Sent as a bearer token when set
+    token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl ResetWebhook {
+    pub fn new(url: &str, token: Option<String>) -> Result<Self> {
+        let url = Url::parse(url).map_err(|e| anyhow!("Invalid password reset webhook URL {}: {}", url, e))?;
+        Ok(Self {
+            url,
+            token: token.filter(|token| !token.is_empty()),
+            http: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+        })
+    }
+
+    /// Webhook from `PASSWORD_RESET_WEBHOOK_URL` and the optional
+    /// `PASSWORD_RESET_WEBHOOK_TOKEN`; `None` when not configured
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(url) = env::var("PASSWORD_RESET_WEBHOOK_URL").ok().filter(|url| !url.is_empty()) else {
+            return Ok(None);
+        };
+        Self::new(&url, env::var("PASSWORD_RESET_WEBHOOK_TOKEN").ok()).map(Some)
+    }
+
+    /// Post reset tokens sent through the returned notifier until it's dropped
+    pub fn spawn(self) -> mpsc::UnboundedSender<PasswordResetIssued> {
+        let (notifier, mut issued) = mpsc::unbounded_channel::<PasswordResetIssued>();
+        tokio::spawn(async move {
+            while let Some(reset) = issued.recv().await {
+                match self.deliver(&reset).await {
+                    Ok(()) => info!("Password reset for user {} handed to the webhook", reset.user_id),
+                    Err(e) => warn!("Failed to deliver password reset for user {}: {}", reset.user_id, e),
+                }
+            }
+        });
+        notifier
+    }
+
+    async fn deliver(&self, reset: &PasswordResetIssued) -> Result<()> {
+        let mut request = self.http.post(self.url.clone()).json(&serde_json::json!({
+            "user_id": reset.user_id,
+            "email": reset.email,
+            "token": reset.token,
+            "expires_at": reset.expires_at,
+        }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_reset_tokens_are_posted_to_the_webhook() {
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/resets",
+            post(move |headers: HeaderMap, Json(body): Json<Value>| async move {
+                let authorization = headers.get("authorization").and_then(|value| value.to_str().ok()).map(str::to_string);
+                let _ = received_tx.send((authorization, body));
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/resets", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let notifier = ResetWebhook::new(&url, Some("hook-secret".to_string())).unwrap().spawn();
+        let user_id = Uuid::new_v4();
+        notifier
+            .send(PasswordResetIssued {
+                user_id,
+                email: "jane@example.com".to_string(),
+                token: "reset-token".to_string(),
+                expires_at: chrono::Utc::now(),
+            })
+            .unwrap();
+
+        let (authorization, body) = tokio::time::timeout(Duration::from_secs(5), received.recv()).await.unwrap().unwrap();
+        assert_eq!(authorization.as_deref(), Some("Bearer hook-secret"));
+        assert_eq!(body["user_id"], user_id.to_string());
+        assert_eq!(body["email"], "jane@example.com");
+        assert_eq!(body["token"], "reset-token");
+
+        assert!(ResetWebhook::new("not a url", None).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! Session control through the signaling server
+//!
+//! Sessions run peer to peer, so the API can't close one itself. To end a
+//! session it asks the signaling server to tell both devices to close it,
+//! through `POST /admin/sessions/terminate` authorized with the token the
+//! signaling server was started with (`SIGNALING_ADMIN_TOKEN` on both).
+//! Sessions under a relay-only policy are registered the same way, through
+//! `POST /admin/sessions/relay-only`, so the signaling server strips direct
+//! candidates between the two devices.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+struct TerminateSessionResponse {
+    notified: Vec<String>,
+}
+
+/// Client for the signaling server's admin endpoints
+pub struct SignalingAdmin {
+    terminate_url: Url,
+    relay_only_url: Url,
+    token: String,
+    http: reqwest::Client,
+}
+
+impl SignalingAdmin {
+    /// Client for the signaling server at `base_url` (e.g. `https://signaling.example.com`)
+    pub fn new(base_url: &str, token: &str) -> Result<Self> {
+        let base = Url::parse(base_url).map_err(|e| anyhow!("Invalid signaling server URL {}: {}", base_url, e))?;
+        if token.is_empty() {
+            return Err(anyhow!("The signaling admin token must not be empty"));
+        }
+        Ok(Self {
+            terminate_url: base.join("/admin/sessions/terminate")?,
+            relay_only_url: base.join("/admin/sessions/relay-only")?,
+            token: token.to_string(),
+            http: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+        })
+    }
+
+    /// Client from `SIGNALING_ADMIN_URL` and `SIGNALING_ADMIN_TOKEN`; `None` when not configured
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(url) = env::var("SIGNALING_ADMIN_URL").ok().filter(|url| !url.is_empty()) else {
+            return Ok(None);
+        };
+        let token = env::var("SIGNALING_ADMIN_TOKEN")
+            .map_err(|_| anyhow!("SIGNALING_ADMIN_TOKEN is required when SIGNALING_ADMIN_URL is set"))?;
+        Self::new(&url, &token).map(Some)
+    }
+
+    /// Tell the two devices of a session (by signaling device ID) to close it
+    ///
+    /// Returns the devices that were online to be told.
+    pub async fn terminate_session(&self, peers: [&str; 2], reason: &str) -> Result<Vec<String>> {
+        let response = self
+            .http
+            .post(self.terminate_url.clone())
+            .bearer_auth(&self.token)
+            .json(&serde_json::json!({ "peers": peers, "reason": reason }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.json::<TerminateSessionResponse>().await?.notified)
+    }
+
+    /// Only let the two devices of a session exchange TURN relay candidates until `until`
+    pub async fn require_relay(&self, peers: [&str; 2], until: DateTime<Utc>) -> Result<()> {
+        self.http
+            .post(self.relay_only_url.clone())
+            .bearer_auth(&self.token)
+            .json(&serde_json::json!({ "peers": peers, "until": until }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Result};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use sha1::Sha1;
+
+/// Time step length in seconds (RFC 6238 default)
+pub const TOTP_PERIOD: u64 = 30;
+
+/// Number of digits in a code
+pub const TOTP_DIGITS: u32 = 6;
+
+/// Accepted clock drift, in time steps either side of now
+const TOTP_SKEW: u64 = 1;
+
+/// Secret length in bytes (160 bits, as recommended for HMAC-SHA1)
+const SECRET_LENGTH: usize = 20;
+
+/// Issuer shown in authenticator apps
+pub const ISSUER: &str = "GenXLink";
+
+/// Number of recovery codes issued at once
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generate a new base32-encoded TOTP secret
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+fn decode_secret(secret: &str) -> Result<Vec<u8>> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    BASE32_NOPAD
+        .decode(normalized.as_bytes())
+        .map_err(|e| anyhow!("Invalid TOTP secret: {}", e))
+}
+
+/// HOTP value (RFC 4226) for a counter
+fn hotp(key: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(digits)
+}
+
+/// Time step containing `unix_time`
+pub fn time_step(unix_time: u64) -> u64 {
+    unix_time / TOTP_PERIOD
+}
+
+/// Code for a secret at a given time
+pub fn generate_code(secret: &str, unix_time: u64) -> Result<String> {
+    let key = decode_secret(secret)?;
+    Ok(format!(
+        "{:0width$}",
+        hotp(&key, time_step(unix_time), TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    ))
+}
+
+/// Check a code, returning the matched time step
+///
+/// Steps at or before `last_used_step` are rejected so a code can't be replayed.
+pub fn verify_code(secret: &str, code: &str, unix_time: u64, last_used_step: Option<u64>) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let expected: u32 = code.parse().ok()?;
+    let key = decode_secret(secret).ok()?;
+
+    let current = time_step(unix_time);
+    let mut matched = None;
+    for step in current.saturating_sub(TOTP_SKEW)..=current + TOTP_SKEW {
+        // Check every step so timing doesn't reveal which one matched
+        if hotp(&key, step, TOTP_DIGITS) == expected {
+            matched = Some(step);
+        }
+    }
+
+    matched.filter(|step| last_used_step.is_none_or(|last| *step > last))
+}
+
+/// `otpauth://` URI for authenticator apps (the QR code payload)
+pub fn provisioning_uri(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = percent_encode(ISSUER),
+        account = percent_encode(account),
+        secret = secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_PERIOD,
+    )
+}
+
+/// Provisioning URI rendered as an SVG QR code
+pub fn provisioning_qr_svg(uri: &str) -> Result<String> {
+    let code = qrcode::QrCode::new(uri.as_bytes())
+        .map_err(|e| anyhow!("Failed to encode QR code: {}", e))?;
+    Ok(code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Generate a set of one-time recovery codes (`xxxxx-xxxxx`)
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = OsRng;
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10)
+                .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+                .collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// Canonical form of a user-entered recovery code, used before hashing
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B test secret ("12345678901234567890")
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        assert_eq!(generate_code(RFC_SECRET, 59).unwrap(), "287082");
+        assert_eq!(generate_code(RFC_SECRET, 1111111109).unwrap(), "081804");
+        assert_eq!(generate_code(RFC_SECRET, 1234567890).unwrap(), "005924");
+        assert_eq!(generate_code(RFC_SECRET, 2000000000).unwrap(), "279037");
+    }
+
+    #[test]
+    fn test_verify_with_skew_and_replay() {
+        let secret = generate_secret();
+        let now = 1_700_000_000;
+        let code = generate_code(&secret, now).unwrap();
+
+        let step = verify_code(&secret, &code, now, None).unwrap();
+        assert_eq!(step, time_step(now));
+        assert_eq!(verify_code(&secret, &code, now + TOTP_PERIOD, None), Some(step));
+        assert_eq!(verify_code(&secret, &code, now + 3 * TOTP_PERIOD, None), None);
+
+        // Already used
+        assert_eq!(verify_code(&secret, &code, now, Some(step)), None);
+
+        assert_eq!(verify_code(&secret, "12345", now, None), None);
+        assert_eq!(verify_code(&secret, "abcdef", now, None), None);
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let uri = provisioning_uri("alice smith@example.com", "ABCDEF");
+        assert_eq!(
+            uri,
+            "otpauth://totp/GenXLink:alice%20smith@example.com?secret=ABCDEF&issuer=GenXLink&algorithm=SHA1&digits=6&period=30"
+        );
+        assert!(provisioning_qr_svg(&uri).unwrap().starts_with("<?xml"));
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes.iter().all(|c| c.len() == 11 && c.as_bytes()[5] == b'-'));
+        assert_eq!(normalize_recovery_code(" ABCDE-fghjk "), "abcdefghjk");
+    }
+}
@@ -1,17 +1,36 @@
-use reqwest;
 use serde_json::{json, Value};
 
+use axum::body::{to_bytes, Body};
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use genxlink_api_server::auth::{self, AuthService, PasswordResetIssued};
+use genxlink_api_server::db::Database;
+use genxlink_api_server::ice::{self, IceConfig};
+use genxlink_api_server::jwt_keys::JwtKeys;
+use genxlink_api_server::ldap_auth::{self, Directory, DirectoryAuth, DirectoryOutcome, LdapUser};
+use genxlink_api_server::rate_limit::{RateLimitConfig, RateLimitPolicy, RateLimiter};
+use genxlink_api_server::models::{ApiKey, User};
+use genxlink_api_server::oidc::{self, OidcConfig, OidcProvider};
+use genxlink_api_server::signaling_admin::SignalingAdmin;
+use genxlink_api_server::{api_keys, password, totp};
+use genxlink_api_server::{router, AppState};
+
 #[tokio::test]
 async fn test_health_check() {
     // This test will be run when the server is running
     let base_url = std::env::var("API_BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
     let client = reqwest::Client::new();
-    
+
     let response = client
-        .get(&format!("{}/health", base_url))
+        .get(format!("{}/health", base_url))
         .send()
         .await;
-    
+
     match response {
         Ok(resp) => {
             assert_eq!(resp.status(), 200);
@@ -31,3 +50,1949 @@ async fn test_compilation() {
     assert!(true);
     println!("✓ Compilation test passed");
 }
+
+// Router tests against a fresh in-memory SQLite database, or the database
+// in TEST_DATABASE_URL (e.g. Postgres) when set
+
+struct TestApp {
+    app: Router,
+    db: Arc<Database>,
+    resets: mpsc::UnboundedReceiver<PasswordResetIssued>,
+    /// Signaling server the app ends sessions through
+    signaling: std::net::SocketAddr,
+}
+
+const SIGNALING_ADMIN_TOKEN: &str = "signaling-admin-test-token";
+
+/// Start `server/signaling` with its admin endpoint enabled
+async fn start_signaling_server() -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let config = genxlink_signaling_server::SignalingConfig {
+        admin_token: Some(SIGNALING_ADMIN_TOKEN.to_string()),
+        ..Default::default()
+    };
+    tokio::spawn(genxlink_signaling_server::serve(listener, genxlink_signaling_server::PeerManager::with_config(config)));
+    address
+}
+
+async fn test_app() -> Option<TestApp> {
+    let url = std::env::var("TEST_DATABASE_URL").unwrap_or_else(|_| "sqlite::memory:".to_string());
+    let db = match Database::connect(&url).await {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            println!("⚠ Database unavailable ({}) - skipping", e);
+            return None;
+        }
+    };
+
+    let (tx, resets) = mpsc::unbounded_channel();
+    let auth_service = Arc::new(AuthService::new((*db).clone(), JwtKeys::new(&password::generate_token())).with_reset_notifier(tx));
+    let rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+    let signaling = start_signaling_server().await;
+    let signaling_admin = SignalingAdmin::new(&format!("http://{}", signaling), SIGNALING_ADMIN_TOKEN).unwrap();
+    let app = router(AppState {
+        db: db.clone(),
+        auth_service,
+        rate_limiter,
+        ice: Arc::new(test_ice_config()),
+        signaling: Some(Arc::new(signaling_admin)),
+    });
+
+    Some(TestApp { app, db, resets, signaling })
+}
+
+const TURN_SECRET: &str = "turn-test-secret";
+
+fn test_ice_config() -> IceConfig {
+    IceConfig::default().with_turn(vec!["turn:turn.example.com:3478?transport=udp".to_string()], TURN_SECRET)
+}
+
+async fn call(app: &Router, method: &str, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+    call_with_headers(app, method, uri, token, &[], body).await
+}
+
+async fn call_with_headers(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    token: Option<&str>,
+    headers: &[(&str, &str)],
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+fn unique_email() -> String {
+    format!("test-{}@example.com", Uuid::new_v4())
+}
+
+async fn register(app: &Router, email: &str, password: &str) -> Value {
+    let (status, body) = call(app, "POST", "/auth/register", None, json!({
+        "email": email,
+        "username": email,
+        "password": password,
+        "display_name": "Test User"
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+async fn login(app: &Router, email: &str, password: &str) -> Value {
+    let (status, body) = call(app, "POST", "/auth/login", None, json!({
+        "email": email,
+        "password": password
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+#[tokio::test]
+async fn test_register_and_login() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+
+    let body = register(&test.app, &email, "hunter2hunter2").await;
+    assert_eq!(body["success"], true);
+
+    let stored = test.db.get_user_credentials(&email).await.unwrap().unwrap().1;
+    assert!(stored.starts_with("$argon2id$"));
+
+    assert_eq!(login(&test.app, &email, "hunter2hunter2").await["success"], true);
+    assert_eq!(login(&test.app, &email, "wrong-password").await["success"], false);
+    assert_eq!(login(&test.app, &unique_email(), "hunter2hunter2").await["success"], false);
+
+    // Too short
+    let body = register(&test.app, &unique_email(), "short").await;
+    assert_eq!(body["success"], false);
+}
+
+#[tokio::test]
+async fn test_bcrypt_hash_upgraded_on_login() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    register(&test.app, &email, "original-password").await;
+
+    let user = test.db.get_user_by_email(&email).await.unwrap().unwrap();
+    let legacy = bcrypt::hash("legacy-password", 4).unwrap();
+    test.db.update_password_hash(user.id, &legacy, false).await.unwrap();
+
+    assert_eq!(login(&test.app, &email, "legacy-password").await["success"], true);
+
+    let upgraded = test.db.get_password_hash(user.id).await.unwrap().unwrap();
+    assert!(upgraded.starts_with("$argon2id$"));
+    assert!(password::verify_password("legacy-password", &upgraded).await.valid);
+    assert_eq!(login(&test.app, &email, "legacy-password").await["success"], true);
+}
+
+#[tokio::test]
+async fn test_change_password() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    let token = register(&test.app, &email, "first-password").await["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, body) = call(&test.app, "POST", "/api/auth/change-password", Some(&token), json!({
+        "current_password": "not-my-password",
+        "new_password": "second-password"
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["success"], false);
+
+    let (_, body) = call(&test.app, "POST", "/api/auth/change-password", Some(&token), json!({
+        "current_password": "first-password",
+        "new_password": "second-password"
+    })).await;
+    assert_eq!(body["success"], true);
+
+    assert_eq!(login(&test.app, &email, "first-password").await["success"], false);
+    assert_eq!(login(&test.app, &email, "second-password").await["success"], true);
+
+    // The session that changed the password stays signed in, others are signed out
+    let other = login(&test.app, &email, "second-password").await["token"].as_str().unwrap().to_string();
+    let (_, body) = call(&test.app, "POST", "/api/auth/change-password", Some(&token), json!({
+        "current_password": "second-password",
+        "new_password": "third-password"
+    })).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&token), json!({})).await.0, StatusCode::OK);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&other), json!({})).await.0, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(&test.app, "POST", "/api/auth/change-password", None, json!({
+        "current_password": "third-password",
+        "new_password": "fourth-password"
+    })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_password_reset() {
+    let Some(mut test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    register(&test.app, &email, "forgotten-password").await;
+
+    // Unknown emails get the same answer and issue nothing
+    let (_, unknown) = call(&test.app, "POST", "/auth/password-reset", None, json!({ "email": unique_email() })).await;
+    let (_, known) = call(&test.app, "POST", "/auth/password-reset", None, json!({ "email": email })).await;
+    assert_eq!(unknown, known);
+
+    let issued = test.resets.try_recv().unwrap();
+    assert_eq!(issued.email, email);
+    assert!(test.resets.try_recv().is_err());
+
+    let (_, body) = call(&test.app, "POST", "/auth/password-reset/confirm", None, json!({
+        "token": "not-a-real-token",
+        "new_password": "brand-new-password"
+    })).await;
+    assert_eq!(body["success"], false);
+
+    let (_, body) = call(&test.app, "POST", "/auth/password-reset/confirm", None, json!({
+        "token": issued.token,
+        "new_password": "brand-new-password"
+    })).await;
+    assert_eq!(body["success"], true);
+
+    assert_eq!(login(&test.app, &email, "forgotten-password").await["success"], false);
+    assert_eq!(login(&test.app, &email, "brand-new-password").await["success"], true);
+
+    // Tokens are single-use
+    let (_, body) = call(&test.app, "POST", "/auth/password-reset/confirm", None, json!({
+        "token": issued.token,
+        "new_password": "another-password"
+    })).await;
+    assert_eq!(body["success"], false);
+}
+
+#[tokio::test]
+async fn test_totp_two_factor_login() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    let token = register(&test.app, &email, "two-factor-password").await["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (_, enroll) = call(&test.app, "POST", "/api/mfa/totp/enroll", Some(&token), json!({})).await;
+    assert_eq!(enroll["success"], true);
+    assert!(enroll["otpauth_uri"].as_str().unwrap().starts_with("otpauth://totp/GenXLink:"));
+    assert!(enroll["qr_code_svg"].as_str().unwrap().contains("<svg"));
+    let secret = enroll["secret"].as_str().unwrap().to_string();
+
+    // Not enabled until confirmed
+    assert_eq!(login(&test.app, &email, "two-factor-password").await["success"], true);
+
+    let now = chrono::Utc::now().timestamp() as u64;
+    let (_, activated) = call(&test.app, "POST", "/api/mfa/totp/activate", Some(&token), json!({
+        "code": totp::generate_code(&secret, now).unwrap()
+    })).await;
+    assert_eq!(activated["success"], true);
+    let recovery_codes: Vec<String> = serde_json::from_value(activated["recovery_codes"].clone()).unwrap();
+    assert_eq!(recovery_codes.len(), totp::RECOVERY_CODE_COUNT);
+
+    // Password alone now only yields a challenge
+    let challenge = login(&test.app, &email, "two-factor-password").await;
+    assert_eq!(challenge["success"], false);
+    assert!(challenge["token"].is_null());
+    let mfa_token = challenge["mfa_token"].as_str().unwrap().to_string();
+
+    // A challenge is not an access token
+    let (status, _) = call(&test.app, "GET", "/api/profile", Some(&mfa_token), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": "000000"
+    })).await;
+    assert_eq!(body["success"], false);
+
+    // The code used for activation can't be replayed
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": totp::generate_code(&secret, now).unwrap()
+    })).await;
+    assert_eq!(body["success"], false);
+
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": totp::generate_code(&secret, now + totp::TOTP_PERIOD).unwrap()
+    })).await;
+    assert_eq!(body["success"], true);
+    assert!(body["token"].is_string());
+
+    // Recovery codes work exactly once
+    let mfa_token = login(&test.app, &email, "two-factor-password").await["mfa_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": recovery_codes[0].to_uppercase()
+    })).await;
+    assert_eq!(body["success"], true);
+
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": recovery_codes[0]
+    })).await;
+    assert_eq!(body["success"], false);
+
+    let (_, status) = call(&test.app, "GET", "/api/mfa", Some(&token), json!({})).await;
+    assert_eq!(status["totp_enabled"], true);
+    assert_eq!(status["recovery_codes_remaining"], totp::RECOVERY_CODE_COUNT as i64 - 1);
+
+    let (_, body) = call(&test.app, "POST", "/api/mfa/disable", Some(&token), json!({
+        "password": "two-factor-password",
+        "code": recovery_codes[1]
+    })).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(login(&test.app, &email, "two-factor-password").await["success"], true);
+}
+
+#[tokio::test]
+async fn test_mfa_challenge_attempt_limit() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    let token = register(&test.app, &email, "limited-password").await["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (_, enroll) = call(&test.app, "POST", "/api/mfa/totp/enroll", Some(&token), json!({})).await;
+    let secret = enroll["secret"].as_str().unwrap().to_string();
+    let now = chrono::Utc::now().timestamp() as u64;
+    call(&test.app, "POST", "/api/mfa/totp/activate", Some(&token), json!({
+        "code": totp::generate_code(&secret, now).unwrap()
+    })).await;
+
+    let mfa_token = login(&test.app, &email, "limited-password").await["mfa_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for _ in 0..5 {
+        call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+            "mfa_token": mfa_token,
+            "code": "abcde-fghjk"
+        })).await;
+    }
+
+    // Even a valid code is refused once the account is locked, also with a
+    // fresh challenge from signing in again
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": mfa_token,
+        "code": totp::generate_code(&secret, now + totp::TOTP_PERIOD).unwrap()
+    })).await;
+    assert_eq!(body["success"], false);
+
+    let fresh_token = login(&test.app, &email, "limited-password").await["mfa_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_ne!(fresh_token, mfa_token);
+    let (_, body) = call(&test.app, "POST", "/auth/mfa/verify", None, json!({
+        "mfa_token": fresh_token,
+        "code": totp::generate_code(&secret, now + totp::TOTP_PERIOD).unwrap()
+    })).await;
+    assert_eq!(body["success"], false);
+    assert_eq!(body["message"], "Too many attempts, try again later");
+}
+
+#[tokio::test]
+async fn test_scoped_api_keys() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    let token = register(&test.app, &email, "api-key-password").await["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (_, body) = call(&test.app, "POST", "/api/keys", Some(&token), json!({
+        "name": "automation",
+        "scopes": ["devices:admin"]
+    })).await;
+    assert_eq!(body["success"], false);
+
+    let (_, created) = call(&test.app, "POST", "/api/keys", Some(&token), json!({
+        "name": "automation",
+        "scopes": ["devices:read", "sessions:write"],
+        "expires_in_days": 30
+    })).await;
+    assert_eq!(created["success"], true);
+    let key = created["key"].as_str().unwrap().to_string();
+    let key_id = created["api_key"]["id"].as_str().unwrap().to_string();
+    assert!(key.starts_with(created["api_key"]["key_prefix"].as_str().unwrap()));
+    assert!(created["api_key"].get("key_hash").is_none());
+
+    // In scope, via either header
+    let (status, _) = call(&test.app, "GET", "/api/devices", Some(&key), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call_with_headers(&test.app, "GET", "/api/devices", None, &[("x-api-key", &key)], json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Out of scope, and never allowed for keys
+    let (status, _) = call(&test.app, "GET", "/api/profile", Some(&key), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&test.app, "POST", "/api/keys", Some(&key), json!({
+        "name": "escalation",
+        "scopes": ["profile:read"]
+    })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, keys) = call(&test.app, "GET", "/api/keys", Some(&token), json!({})).await;
+    let listed = &keys.as_array().unwrap()[0];
+    assert_eq!(listed["permissions"], json!(["devices:read", "sessions:write"]));
+    assert!(listed["last_used"].is_string());
+
+    let (status, _) = call(&test.app, "DELETE", &format!("/api/keys/{}", key_id), Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&test.app, "GET", "/api/devices", Some(&key), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&test.app, "DELETE", &format!("/api/keys/{}", key_id), Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_expired_api_keys_rejected() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    register(&test.app, &email, "api-key-password").await;
+    let user = test.db.get_user_by_email(&email).await.unwrap().unwrap();
+
+    // A key that expired a minute ago
+    let generated = api_keys::generate_api_key();
+    test.db.create_api_key(&ApiKey {
+        id: Uuid::new_v4(),
+        user_id: user.id,
+        name: "short-lived".to_string(),
+        key_prefix: generated.prefix,
+        key_hash: generated.hash,
+        permissions: json!(["devices:read"]),
+        expires_at: Some(chrono::Utc::now() - chrono::Duration::minutes(1)),
+        last_used: None,
+        is_active: true,
+        created_at: chrono::Utc::now(),
+    }).await.unwrap();
+
+    let (status, _) = call(&test.app, "GET", "/api/devices", Some(&generated.key), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert!(test.db.deactivate_expired_api_keys().await.unwrap() >= 1);
+    let keys = test.db.get_user_api_keys(user.id).await.unwrap();
+    assert!(!keys[0].is_active);
+}
+
+async fn refresh(app: &Router, refresh_token: &str) -> Value {
+    let (status, body) = call(app, "POST", "/auth/refresh", None, json!({ "refresh_token": refresh_token })).await;
+    assert_eq!(status, StatusCode::OK);
+    body
+}
+
+#[tokio::test]
+async fn test_refresh_token_rotation_and_reuse() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    register(&test.app, &email, "refresh-password").await;
+
+    let session = login(&test.app, &email, "refresh-password").await;
+    let first = session["refresh_token"].as_str().unwrap().to_string();
+
+    let rotated = refresh(&test.app, &first).await;
+    assert_eq!(rotated["success"], true);
+    let second = rotated["refresh_token"].as_str().unwrap().to_string();
+    let access = rotated["token"].as_str().unwrap().to_string();
+    assert_ne!(first, second);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&access), json!({})).await.0, StatusCode::OK);
+
+    // Replaying the consumed token revokes the whole session
+    assert_eq!(refresh(&test.app, &first).await["success"], false);
+    assert_eq!(refresh(&test.app, &second).await["success"], false);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&access), json!({})).await.0, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(refresh(&test.app, "not-a-refresh-token").await["success"], false);
+}
+
+#[tokio::test]
+async fn test_logout_and_logout_all() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    register(&test.app, &email, "logout-password").await;
+
+    let first = login(&test.app, &email, "logout-password").await;
+    let second = login(&test.app, &email, "logout-password").await;
+    let third = login(&test.app, &email, "logout-password").await;
+    let token = |session: &Value| session["token"].as_str().unwrap().to_string();
+
+    let (_, body) = call(&test.app, "POST", "/api/auth/logout", Some(&token(&first)), json!({})).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&token(&first)), json!({})).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&test.app, first["refresh_token"].as_str().unwrap()).await["success"], false);
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&token(&second)), json!({})).await.0, StatusCode::OK);
+
+    let (_, body) = call(&test.app, "POST", "/api/auth/logout-all", Some(&token(&second)), json!({})).await;
+    assert_eq!(body["success"], true);
+    for session in [&second, &third] {
+        assert_eq!(call(&test.app, "GET", "/api/profile", Some(&token(session)), json!({})).await.0, StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[tokio::test]
+async fn test_tokens_signed_with_unknown_key_rejected() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+    let session = register(&test.app, &email, "foreign-key-password").await;
+
+    // Same claims, signed by a different server
+    let other = AuthService::new((*test.db).clone(), JwtKeys::new(&password::generate_token()));
+    let other_app = router(AppState {
+        db: test.db.clone(),
+        auth_service: Arc::new(other),
+        rate_limiter: Arc::new(RateLimiter::new(RateLimitConfig::default())),
+        ice: Arc::new(IceConfig::default()),
+        signaling: None,
+    });
+    let foreign = login(&other_app, &email, "foreign-key-password").await["token"].as_str().unwrap().to_string();
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(&foreign), json!({})).await.0, StatusCode::UNAUTHORIZED);
+
+    let own = session["token"].as_str().unwrap();
+    assert_eq!(call(&test.app, "GET", "/api/profile", Some(own), json!({})).await.0, StatusCode::OK);
+}
+
+fn device_json(user_id: &str, device_id: &str, name: &str) -> Value {
+    json!({
+        "id": Uuid::new_v4(),
+        "user_id": user_id,
+        "device_id": device_id,
+        "device_name": name,
+        "device_type": "desktop",
+        "os_version": "Windows 11",
+        "ip_address": "192.168.1.20",
+        "mac_address": null,
+        "last_seen": chrono::Utc::now(),
+        "is_online": false,
+        "capabilities": { "screen_sharing": true },
+        "metadata": {},
+        "created_at": chrono::Utc::now(),
+        "updated_at": chrono::Utc::now()
+    })
+}
+
+#[tokio::test]
+async fn test_devices_and_sessions() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let body = register(&test.app, &unique_email(), "device-password").await;
+    let token = body["token"].as_str().unwrap().to_string();
+    let user_id = body["user"]["id"].as_str().unwrap().to_string();
+    let local_id = format!("dev-{}", Uuid::new_v4());
+    let remote_id = format!("dev-{}", Uuid::new_v4());
+
+    let (status, local) = call(&test.app, "POST", "/api/devices", Some(&token), device_json(&user_id, &local_id, "Office PC")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, remote) = call(&test.app, "POST", "/api/devices", Some(&token), device_json(&user_id, &remote_id, "Laptop")).await;
+
+    // Registering the same device again updates it in place
+    let (_, renamed) = call(&test.app, "POST", "/api/devices", Some(&token), device_json(&user_id, &local_id, "Front Desk")).await;
+    assert_eq!(renamed["id"], local["id"]);
+    assert_eq!(renamed["device_name"], "Front Desk");
+
+    let (status, _) = call(&test.app, "POST", &format!("/api/devices/{}/status", local_id), Some(&token), json!({ "is_online": true })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, devices) = call(&test.app, "GET", "/api/devices", Some(&token), json!({})).await;
+    let devices = devices.as_array().unwrap();
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0]["device_id"], local_id.as_str());
+    assert_eq!(devices[0]["is_online"], true);
+
+    let (status, session) = call(&test.app, "POST", "/api/sessions", Some(&token), json!({
+        "id": Uuid::new_v4(),
+        "user_id": user_id,
+        "device_id": local["id"],
+        "remote_device_id": remote["id"],
+        "session_type": "remote_control",
+        "started_at": chrono::Utc::now(),
+        "ended_at": null,
+        "duration_seconds": null,
+        "status": "active",
+        "connection_quality": null,
+        "metadata": {}
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let session_id = session["id"].as_str().unwrap();
+    let (status, _) = call(&test.app, "POST", &format!("/api/sessions/{}/end", session_id), Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, sessions) = call(&test.app, "GET", "/api/sessions", Some(&token), json!({})).await;
+    assert_eq!(sessions[0]["status"], "ended");
+    assert!(sessions[0]["ended_at"].is_string());
+    assert!(sessions[0]["duration_seconds"].as_i64().unwrap() >= 0);
+}
+
+fn session_json(user_id: &str, device: &Value, remote: &Value) -> Value {
+    json!({
+        "id": Uuid::new_v4(),
+        "user_id": user_id,
+        "device_id": device["id"],
+        "remote_device_id": remote["id"],
+        "session_type": "remote_control",
+        "started_at": chrono::Utc::now(),
+        "ended_at": null,
+        "duration_seconds": null,
+        "status": "active",
+        "connection_quality": null,
+        "metadata": {}
+    })
+}
+
+/// Register a user and return (token, user id, email)
+async fn member_account(app: &Router) -> (String, String, String) {
+    let email = unique_email();
+    let body = register(app, &email, "member-password").await;
+    (body["token"].as_str().unwrap().to_string(), body["user"]["id"].as_str().unwrap().to_string(), email)
+}
+
+#[tokio::test]
+async fn test_organization_device_groups() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let app = &test.app;
+    let (owner, owner_id, _) = member_account(app).await;
+    let (operator, operator_id, operator_email) = member_account(app).await;
+    let (member, member_id, member_email) = member_account(app).await;
+
+    let (status, org) = call(app, "POST", "/api/orgs", Some(&owner), json!({ "name": "Support" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(org["role"], "owner");
+    let org_id = org["id"].as_str().unwrap().to_string();
+
+    // Only admins of the organization can enroll devices in it
+    let mut kiosk = device_json(&owner_id, &format!("dev-{}", Uuid::new_v4()), "Kiosk");
+    kiosk["org_id"] = json!(org_id);
+    let mut hijack = device_json(&operator_id, &format!("dev-{}", Uuid::new_v4()), "Own PC");
+    hijack["org_id"] = json!(org_id);
+    assert_eq!(call(app, "POST", "/api/devices", Some(&operator), hijack).await.0, StatusCode::NOT_FOUND);
+    let (status, kiosk) = call(app, "POST", "/api/devices", Some(&owner), kiosk).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(kiosk["org_id"], org_id.as_str());
+
+    // A device id already registered by someone else can't be taken over
+    let taken = device_json(&operator_id, kiosk["device_id"].as_str().unwrap(), "Mine now");
+    assert_eq!(call(app, "POST", "/api/devices", Some(&operator), taken).await.0, StatusCode::CONFLICT);
+
+    // Invitations are bound to the invited email
+    for (token, email, role) in [(&operator, &operator_email, "operator"), (&member, &member_email, "member")] {
+        let (status, invite) = call(app, "POST", &format!("/api/orgs/{}/invitations", org_id), Some(&owner), json!({
+            "email": email.to_uppercase(),
+            "role": role
+        })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(invite["invitation"].get("token_hash").is_none());
+        let accept = json!({ "token": invite["token"] });
+        let other = if token == &operator { &member } else { &operator };
+        assert_eq!(call(app, "POST", "/api/invitations/accept", Some(other), accept.clone()).await.0, StatusCode::NOT_FOUND);
+        let (status, joined) = call(app, "POST", "/api/invitations/accept", Some(token), accept.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(joined["role"], role);
+        assert_eq!(call(app, "POST", "/api/invitations/accept", Some(token), accept).await.0, StatusCode::NOT_FOUND);
+    }
+    let (_, details) = call(app, "GET", &format!("/api/orgs/{}", org_id), Some(&member), json!({})).await;
+    assert_eq!(details["members"].as_array().unwrap().len(), 3);
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/teams", org_id), Some(&operator), json!({ "name": "x" })).await.0, StatusCode::FORBIDDEN);
+
+    // Membership alone gives no access to organization devices
+    let (_, own) = call(app, "POST", "/api/devices", Some(&operator), device_json(&operator_id, &format!("dev-{}", Uuid::new_v4()), "Desk")).await;
+    let (_, member_device) = call(app, "POST", "/api/devices", Some(&member), device_json(&member_id, &format!("dev-{}", Uuid::new_v4()), "Desk")).await;
+    let (_, devices) = call(app, "GET", "/api/devices", Some(&operator), json!({})).await;
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_eq!(call(app, "POST", "/api/sessions", Some(&operator), session_json(&operator_id, &own, &kiosk)).await.0, StatusCode::NOT_FOUND);
+
+    // Grant the support team control of the kiosk group
+    let (status, team) = call(app, "POST", &format!("/api/orgs/{}/teams", org_id), Some(&owner), json!({ "name": "Helpdesk" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let team_id = team["id"].as_str().unwrap();
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/teams", org_id), Some(&owner), json!({ "name": "Helpdesk" })).await.0, StatusCode::CONFLICT);
+    for user_id in [&operator_id, &member_id] {
+        let (status, _) = call(app, "POST", &format!("/api/orgs/{}/teams/{}/members", org_id, team_id), Some(&owner), json!({ "user_id": user_id })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (_, group) = call(app, "POST", &format!("/api/orgs/{}/device-groups", org_id), Some(&owner), json!({ "name": "Kiosks" })).await;
+    let group_id = group["id"].as_str().unwrap();
+    // Only devices enrolled in the organization can join its groups
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/device-groups/{}/devices", org_id, group_id), Some(&owner), json!({ "device_id": own["id"] })).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/device-groups/{}/devices", org_id, group_id), Some(&owner), json!({ "device_id": kiosk["id"] })).await.0, StatusCode::OK);
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/device-groups/{}/grants", org_id, group_id), Some(&owner), json!({ "team_id": team_id, "access": "control" })).await.0, StatusCode::OK);
+
+    let (_, groups) = call(app, "GET", &format!("/api/orgs/{}/device-groups", org_id), Some(&member), json!({})).await;
+    assert_eq!(groups[0]["devices"], json!([kiosk["id"]]));
+    assert_eq!(groups[0]["grants"][0]["access"], "control");
+
+    // Operators get control through the grant, members only view
+    let (_, devices) = call(app, "GET", &format!("/api/orgs/{}/devices", org_id), Some(&member), json!({})).await;
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_eq!(call(app, "POST", "/api/sessions", Some(&member), session_json(&member_id, &member_device, &kiosk)).await.0, StatusCode::FORBIDDEN);
+    let (status, session) = call(app, "POST", "/api/sessions", Some(&operator), session_json(&operator_id, &own, &kiosk)).await;
+    assert_eq!(status, StatusCode::OK);
+    let session_id = session["id"].as_str().unwrap();
+
+    // Org admins see and can end sessions on org devices; other members can't
+    let (_, sessions) = call(app, "GET", &format!("/api/orgs/{}/sessions", org_id), Some(&owner), json!({})).await;
+    assert_eq!(sessions.as_array().unwrap().len(), 1);
+    assert_eq!(call(app, "GET", &format!("/api/orgs/{}/sessions", org_id), Some(&member), json!({})).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(call(app, "POST", &format!("/api/sessions/{}/end", session_id), Some(&member), json!({})).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(call(app, "POST", &format!("/api/sessions/{}/end", session_id), Some(&owner), json!({})).await.0, StatusCode::OK);
+
+    // Removing a member revokes access through their teams
+    assert_eq!(call(app, "DELETE", &format!("/api/orgs/{}/members/{}", org_id, operator_id), Some(&owner), json!({})).await.0, StatusCode::OK);
+    let (_, devices) = call(app, "GET", "/api/devices", Some(&operator), json!({})).await;
+    assert_eq!(devices.as_array().unwrap().len(), 1);
+    assert_eq!(call(app, "POST", "/api/sessions", Some(&operator), session_json(&operator_id, &own, &kiosk)).await.0, StatusCode::NOT_FOUND);
+
+    // The last owner can't leave or step down
+    assert_eq!(call(app, "DELETE", &format!("/api/orgs/{}/members/{}", org_id, owner_id), Some(&owner), json!({})).await.0, StatusCode::CONFLICT);
+    assert_eq!(call(app, "POST", &format!("/api/orgs/{}/members/{}/role", org_id, owner_id), Some(&owner), json!({ "role": "admin" })).await.0, StatusCode::CONFLICT);
+    assert_eq!(call(app, "DELETE", &format!("/api/orgs/{}/members/{}", org_id, member_id), Some(&member), json!({})).await.0, StatusCode::OK);
+    assert_eq!(call(app, "GET", &format!("/api/orgs/{}", org_id), Some(&member), json!({})).await.0, StatusCode::NOT_FOUND);
+}
+
+async fn login_from(app: &Router, peer: &str, forwarded_for: Option<&str>, email: &str) -> axum::response::Response {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri("/auth/login")
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("x-forwarded-for", forwarded_for);
+    }
+    let mut request = request
+        .body(Body::from(json!({ "email": email, "password": "wrong-password" }).to_string()))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(axum::extract::ConnectInfo(peer.parse::<std::net::SocketAddr>().unwrap()));
+    app.clone().oneshot(request).await.unwrap()
+}
+
+/// App whose per-address login limit is 3 an hour, so refills don't race the test
+fn app_with_login_limit(db: &Arc<Database>, trusted_proxies: &str) -> Router {
+    let mut config = RateLimitConfig {
+        trusted_proxies: genxlink_api_server::rate_limit::parse_trusted_proxies(trusted_proxies).unwrap(),
+        ..RateLimitConfig::default()
+    };
+    for rule in config.rules.iter_mut().filter(|rule| rule.name == "login-ip") {
+        rule.policy = RateLimitPolicy::new(3, std::time::Duration::from_secs(60 * 60));
+    }
+    let auth_service = AuthService::new((**db).clone(), JwtKeys::new(&password::generate_token()));
+    router(AppState {
+        db: db.clone(),
+        auth_service: Arc::new(auth_service),
+        rate_limiter: Arc::new(RateLimiter::new(config)),
+        ice: Arc::new(IceConfig::default()),
+        signaling: None,
+    })
+}
+
+#[tokio::test]
+async fn test_login_rate_limits() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let email = unique_email();
+
+    // Per account from one address: five attempts
+    for i in 0..5 {
+        let response = login_from(&test.app, "198.51.100.1:4000", None, &email).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-remaining"], (4 - i).to_string().as_str());
+    }
+    let response = login_from(&test.app, "198.51.100.1:4000", None, &email.to_uppercase()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    assert_eq!(response.headers()["ratelimit-limit"], "5");
+    assert_eq!(response.headers()["ratelimit-policy"], "5;w=300");
+
+    // Someone else guessing can't lock the owner out
+    let response = login_from(&test.app, "198.51.100.2:4000", None, &email).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Per address: other accounts from one client
+    let app = app_with_login_limit(&test.db, "");
+    for _ in 0..3 {
+        let response = login_from(&app, "203.0.113.1:4000", None, &unique_email()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = login_from(&app, "203.0.113.1:4000", None, &unique_email()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // X-Forwarded-For from an untrusted peer doesn't get a fresh budget
+    let response = login_from(&app, "203.0.113.1:4000", Some("192.0.2.50"), &unique_email()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = login_from(&app, "203.0.113.2:4000", None, &unique_email()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_trusted_proxy_forwarded_clients_limited_separately() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let app = app_with_login_limit(&test.db, "10.0.0.0/8");
+
+    for _ in 0..3 {
+        let response = login_from(&app, "10.0.0.2:4000", Some("198.51.100.1"), &unique_email()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = login_from(&app, "10.0.0.2:4000", Some("198.51.100.1"), &unique_email()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Another client behind the same proxy has its own budget
+    let response = login_from(&app, "10.0.0.2:4000", Some("198.51.100.2"), &unique_email()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// In-process stand-in for an LDAP server: login name -> (password, account)
+struct FakeDirectory {
+    accounts: std::sync::Mutex<std::collections::HashMap<String, (String, LdapUser)>>,
+}
+
+impl FakeDirectory {
+    fn set(&self, password: &str, user: LdapUser) {
+        self.accounts.lock().unwrap().insert(user.username.clone(), (password.to_string(), user));
+    }
+}
+
+#[async_trait::async_trait]
+impl Directory for FakeDirectory {
+    async fn authenticate(&self, username: &str, password: &str) -> anyhow::Result<DirectoryOutcome> {
+        Ok(match self.accounts.lock().unwrap().get(username) {
+            Some((expected, user)) if expected == password => DirectoryOutcome::Authenticated(user.clone()),
+            Some(_) => DirectoryOutcome::InvalidPassword,
+            None => DirectoryOutcome::UnknownUser,
+        })
+    }
+}
+
+fn ldap_user(username: &str, email: &str, groups: &[&str]) -> LdapUser {
+    LdapUser {
+        dn: format!("uid={},ou=Users,dc=example,dc=com", username),
+        username: username.to_string(),
+        email: Some(email.to_string()),
+        display_name: format!("{} (Directory)", username),
+        groups: groups.iter().map(|group| group.to_string()).collect(),
+    }
+}
+
+fn app_with_directory(
+    db: &Arc<Database>,
+    directory: Arc<dyn Directory>,
+    group_roles: &str,
+    allow_local_fallback: bool,
+) -> (Router, mpsc::UnboundedReceiver<PasswordResetIssued>) {
+    let (tx, resets) = mpsc::unbounded_channel();
+    let directory = DirectoryAuth::new(directory, ldap_auth::parse_group_roles(group_roles).unwrap(), allow_local_fallback);
+    let auth_service = AuthService::new((**db).clone(), JwtKeys::new(&password::generate_token()))
+        .with_reset_notifier(tx)
+        .with_directory(directory);
+    let app = router(AppState {
+        db: db.clone(),
+        auth_service: Arc::new(auth_service),
+        rate_limiter: Arc::new(RateLimiter::new(RateLimitConfig::default())),
+        ice: Arc::new(IceConfig::default()),
+        signaling: None,
+    });
+    (app, resets)
+}
+
+async fn try_login(app: &Router, login: &str, password: &str) -> Value {
+    call(app, "POST", "/auth/login", None, json!({ "email": login, "password": password })).await.1
+}
+
+#[tokio::test]
+async fn test_ldap_login_provisions_users_and_maps_groups() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (owner, _, _) = member_account(&test.app).await;
+    let (_, org) = call(&test.app, "POST", "/api/orgs", Some(&owner), json!({ "name": "IT" })).await;
+    let org_id = org["id"].as_str().unwrap().to_string();
+
+    let directory = Arc::new(FakeDirectory { accounts: Default::default() });
+    let username = format!("jdoe-{}", &Uuid::new_v4().to_string()[..8]);
+    let email = unique_email();
+    directory.set("directory-password", ldap_user(&username, &email, &["Staff", "helpdesk"]));
+    let mappings = format!("Helpdesk={org_id}:operator;IT Admins={org_id}:admin");
+    let (app, mut resets) = app_with_directory(&test.db, directory.clone(), &mappings, true);
+
+    // First login creates the account and applies the group roles
+    let body = try_login(&app, &username, "directory-password").await;
+    assert_eq!(body["success"], true, "{}", body);
+    assert_eq!(body["user"]["auth_provider"], "ldap");
+    assert_eq!(body["user"]["email"], email.as_str());
+    let user_id = body["user"]["id"].as_str().unwrap().to_string();
+    let token = body["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert_eq!(orgs[0]["id"], org_id.as_str());
+    assert_eq!(orgs[0]["role"], "operator");
+
+    assert_eq!(try_login(&app, &username, "wrong-password").await["success"], false);
+
+    // Later logins update the same account and follow group changes
+    directory.set("directory-password", ldap_user(&username, &email, &["IT Admins"]));
+    let body = try_login(&app, &username, "directory-password").await;
+    assert_eq!(body["user"]["id"], user_id.as_str());
+    let token = body["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert_eq!(orgs[0]["role"], "admin");
+
+    directory.set("directory-password", ldap_user(&username, &email, &["Staff"]));
+    let token = try_login(&app, &username, "directory-password").await["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert!(orgs.as_array().unwrap().is_empty());
+
+    // Directory accounts have no local password to sign in with or reset
+    assert_eq!(try_login(&test.app, &email, "directory-password").await["success"], false);
+    call(&app, "POST", "/auth/password-reset", None, json!({ "email": email })).await;
+    assert!(resets.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_ldap_login_local_fallback() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let local_email = unique_email();
+    register(&test.app, &local_email, "local-password").await;
+
+    let directory = Arc::new(FakeDirectory { accounts: Default::default() });
+    // A directory account whose email is already taken by a local account
+    let username = format!("clash-{}", &Uuid::new_v4().to_string()[..8]);
+    directory.set("directory-password", ldap_user(&username, &local_email, &[]));
+
+    let (app, _) = app_with_directory(&test.db, directory.clone(), "", true);
+    assert_eq!(try_login(&app, &local_email, "local-password").await["success"], true);
+    let body = try_login(&app, &username, "directory-password").await;
+    assert_eq!(body["success"], false);
+    assert!(body["token"].is_null());
+
+    let (app, _) = app_with_directory(&test.db, directory, "", false);
+    assert_eq!(try_login(&app, &local_email, "local-password").await["success"], false);
+}
+
+/// In-process LDAP server over a small fixed directory
+///
+/// Speaks enough of the protocol for `LdapAuthProvider`: simple binds,
+/// searches with and/or/not/equality/presence filters, and unbind. Only the
+/// service account may search, like directories that hide groups from users.
+struct MockLdap {
+    url: String,
+}
+
+struct MockEntry {
+    dn: String,
+    password: Option<&'static str>,
+    attributes: Vec<(&'static str, Vec<String>)>,
+}
+
+const LDAP_SERVICE_DN: &str = "cn=admin,dc=example,dc=com";
+const LDAP_SERVICE_PASSWORD: &str = "service-secret";
+const LDAP_ALICE_DN: &str = "uid=alice,ou=Users,dc=example,dc=com";
+/// Parentheses only match in a filter when they are escaped
+const LDAP_BOB_DN: &str = "cn=Bob (IT),ou=Users,dc=example,dc=com";
+
+impl MockLdap {
+    async fn start() -> Self {
+        let person = |dn: &str, password, uid: &str, mail: &str, cn: &str| MockEntry {
+            dn: dn.to_string(),
+            password: Some(password),
+            attributes: vec![
+                ("objectClass", vec!["inetOrgPerson".to_string()]),
+                ("uid", vec![uid.to_string()]),
+                ("mail", vec![mail.to_string()]),
+                ("cn", vec![cn.to_string()]),
+            ],
+        };
+        let group = |name: &str, members: &[&str]| MockEntry {
+            dn: format!("cn={},ou=Groups,dc=example,dc=com", name),
+            password: None,
+            attributes: vec![
+                ("objectClass", vec!["groupOfNames".to_string()]),
+                ("cn", vec![name.to_string()]),
+                ("member", members.iter().map(|member| member.to_string()).collect()),
+            ],
+        };
+        let entries = Arc::new(vec![
+            MockEntry {
+                dn: LDAP_SERVICE_DN.to_string(),
+                password: Some(LDAP_SERVICE_PASSWORD),
+                attributes: vec![("objectClass", vec!["organizationalRole".to_string()])],
+            },
+            MockEntry {
+                dn: "ou=Users,dc=example,dc=com".to_string(),
+                password: None,
+                attributes: vec![("objectClass", vec!["organizationalUnit".to_string()])],
+            },
+            person(LDAP_ALICE_DN, "alice-password", "alice", "alice@example.com", "Alice Liddell"),
+            person(LDAP_BOB_DN, "bob-password", "bob", "bob@example.com", "Bob"),
+            // Two accounts share a login name
+            person("uid=dup,ou=Users,dc=example,dc=com", "dup-password", "dup", "dup@example.com", "Dup"),
+            person("uid=dup,ou=Contractors,ou=Users,dc=example,dc=com", "dup-password", "dup", "dup2@example.com", "Dup"),
+            group("Helpdesk", &[LDAP_ALICE_DN, LDAP_BOB_DN]),
+            group("IT Admins", &[LDAP_BOB_DN]),
+            group("Sales", &["uid=carol,ou=Users,dc=example,dc=com"]),
+        ]);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(stream, entries.clone()));
+            }
+        });
+        MockLdap { url }
+    }
+
+    fn config(&self) -> ldap_auth::LdapConfig {
+        ldap_auth::LdapConfig {
+            server_url: self.url.clone(),
+            use_starttls: false,
+            bind_dn: LDAP_SERVICE_DN.to_string(),
+            bind_password: LDAP_SERVICE_PASSWORD.to_string(),
+            user_base_dn: "ou=Users,dc=example,dc=com".to_string(),
+            group_base_dn: "ou=Groups,dc=example,dc=com".to_string(),
+            user_search_filter: "(&(objectClass=inetOrgPerson)(uid={username}))".to_string(),
+            group_search_filter: "(&(objectClass=groupOfNames)(member={user_dn}))".to_string(),
+            username_attribute: "uid".to_string(),
+            email_attribute: "mail".to_string(),
+            display_name_attribute: "cn".to_string(),
+            connection_timeout: 5,
+        }
+    }
+
+    async fn serve(mut stream: tokio::net::TcpStream, entries: Arc<Vec<MockEntry>>) {
+        use lber::structure::PL;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut buffer = Vec::new();
+        let mut bound: Option<String> = None;
+        loop {
+            let (message, consumed) = match lber::parse::parse_tag(&buffer) {
+                Ok((rest, message)) => (message, buffer.len() - rest.len()),
+                Err(lber::Err::Incomplete(_)) => {
+                    let mut chunk = [0u8; 4096];
+                    match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                    }
+                    continue;
+                }
+                Err(_) => return,
+            };
+            buffer.drain(..consumed);
+
+            let PL::C(parts) = message.payload else { return };
+            let id = ber_primitive(&parts[0]).to_vec();
+            let op = &parts[1];
+            let PL::C(fields) = &op.payload else { return }; // UnbindRequest is primitive
+
+            let mut reply = Vec::new();
+            match op.id {
+                // BindRequest: version, name, simple password
+                0 => {
+                    let dn = ber_string(&fields[1]);
+                    let password = ber_string(&fields[2]);
+                    let code = if password.is_empty() {
+                        // Unauthenticated bind: succeeds, but grants nothing
+                        bound = None;
+                        0
+                    } else if entries.iter().any(|entry| entry.dn.eq_ignore_ascii_case(&dn) && entry.password == Some(password.as_str())) {
+                        bound = Some(dn);
+                        0
+                    } else {
+                        bound = None;
+                        49
+                    };
+                    reply.extend(ldap_message(&id, ber(1, true, 1, ldap_result(code))));
+                }
+                // SearchRequest: base, scope, deref, size, time, typesOnly, filter, attributes
+                3 => {
+                    if !bound.as_deref().is_some_and(|dn| dn.eq_ignore_ascii_case(LDAP_SERVICE_DN)) {
+                        reply.extend(ldap_message(&id, ber(1, true, 5, ldap_result(50))));
+                    } else {
+                        let base = ber_string(&fields[0]).to_lowercase();
+                        let scope = ber_primitive(&fields[1]).first().copied().unwrap_or(0);
+                        let wanted: Vec<String> = match &fields[7].payload {
+                            PL::C(attributes) => attributes.iter().map(ber_string).collect(),
+                            PL::P(_) => Vec::new(),
+                        };
+                        for entry in entries.iter() {
+                            let dn = entry.dn.to_lowercase();
+                            let in_scope = dn == base || (scope != 0 && dn.ends_with(&format!(",{}", base)));
+                            if in_scope && ldap_filter_matches(&fields[6], entry) {
+                                reply.extend(ldap_message(&id, ldap_search_entry(entry, &wanted)));
+                            }
+                        }
+                        reply.extend(ldap_message(&id, ber(1, true, 5, ldap_result(0))));
+                    }
+                }
+                _ => return,
+            }
+            if stream.write_all(&reply).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn ber_primitive(tag: &lber::structure::StructureTag) -> &[u8] {
+    match &tag.payload {
+        lber::structure::PL::P(bytes) => bytes,
+        lber::structure::PL::C(_) => &[],
+    }
+}
+
+fn ber_string(tag: &lber::structure::StructureTag) -> String {
+    String::from_utf8_lossy(ber_primitive(tag)).into_owned()
+}
+
+/// Encode a BER element (class: 0 universal, 1 application, 2 context)
+fn ber(class: u8, constructed: bool, id: u8, content: Vec<u8>) -> Vec<u8> {
+    let mut out = vec![class << 6 | (constructed as u8) << 5 | id];
+    if content.len() < 128 {
+        out.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u32).to_be_bytes();
+        let length: Vec<u8> = length.into_iter().skip_while(|byte| *byte == 0).collect();
+        out.push(0x80 | length.len() as u8);
+        out.extend(length);
+    }
+    out.extend(content);
+    out
+}
+
+fn ber_octets(value: &str) -> Vec<u8> {
+    ber(0, false, 4, value.as_bytes().to_vec())
+}
+
+fn ldap_message(id: &[u8], op: Vec<u8>) -> Vec<u8> {
+    let mut content = ber(0, false, 2, id.to_vec());
+    content.extend(op);
+    ber(0, true, 16, content)
+}
+
+/// resultCode, matchedDN, diagnosticMessage
+fn ldap_result(code: u8) -> Vec<u8> {
+    let mut content = ber(0, false, 10, vec![code]);
+    content.extend(ber_octets(""));
+    content.extend(ber_octets(""));
+    content
+}
+
+fn ldap_search_entry(entry: &MockEntry, wanted: &[String]) -> Vec<u8> {
+    let mut attributes = Vec::new();
+    for (name, values) in &entry.attributes {
+        if !wanted.is_empty() && !wanted.iter().any(|wanted| wanted.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        let mut attribute = ber_octets(name);
+        attribute.extend(ber(0, true, 17, values.iter().flat_map(|value| ber_octets(value)).collect()));
+        attributes.extend(ber(0, true, 16, attribute));
+    }
+    let mut content = ber_octets(&entry.dn);
+    content.extend(ber(0, true, 16, attributes));
+    ber(1, true, 4, content)
+}
+
+fn ldap_filter_matches(filter: &lber::structure::StructureTag, entry: &MockEntry) -> bool {
+    use lber::structure::PL;
+
+    let values = |name: &str| {
+        entry
+            .attributes
+            .iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, values)| values.clone())
+    };
+    match (filter.id, &filter.payload) {
+        (0, PL::C(filters)) => filters.iter().all(|filter| ldap_filter_matches(filter, entry)),
+        (1, PL::C(filters)) => filters.iter().any(|filter| ldap_filter_matches(filter, entry)),
+        (2, PL::C(filters)) => !ldap_filter_matches(&filters[0], entry),
+        // equalityMatch: attribute, value (escapes already decoded)
+        (3, PL::C(assertion)) => values(&ber_string(&assertion[0]))
+            .is_some_and(|values| values.iter().any(|value| value.eq_ignore_ascii_case(&ber_string(&assertion[1])))),
+        // present
+        (7, PL::P(attribute)) => values(&String::from_utf8_lossy(attribute)).is_some(),
+        _ => false,
+    }
+}
+
+#[tokio::test]
+async fn test_ldap_provider_against_directory_server() {
+    let mock = MockLdap::start().await;
+    let provider = ldap_auth::LdapAuthProvider::new(mock.config());
+    assert!(provider.test_connection().await);
+
+    // Bind as the user, then read groups back as the service account
+    match provider.authenticate("alice", "alice-password").await.unwrap() {
+        DirectoryOutcome::Authenticated(user) => {
+            assert_eq!(user.dn, LDAP_ALICE_DN);
+            assert_eq!(user.username, "alice");
+            assert_eq!(user.email.as_deref(), Some("alice@example.com"));
+            assert_eq!(user.display_name, "Alice Liddell");
+            assert_eq!(user.groups, vec!["Helpdesk".to_string()]);
+        }
+        other => panic!("expected alice to sign in, got {:?}", other),
+    }
+
+    // The user DN is escaped in the group filter
+    match provider.authenticate("bob", "bob-password").await.unwrap() {
+        DirectoryOutcome::Authenticated(user) => {
+            assert_eq!(user.dn, LDAP_BOB_DN);
+            assert_eq!(user.groups, vec!["Helpdesk".to_string(), "IT Admins".to_string()]);
+        }
+        other => panic!("expected bob to sign in, got {:?}", other),
+    }
+
+    assert!(matches!(provider.authenticate("alice", "wrong-password").await.unwrap(), DirectoryOutcome::InvalidPassword));
+    // The server would accept this as an unauthenticated bind
+    assert!(matches!(provider.authenticate("alice", "").await.unwrap(), DirectoryOutcome::InvalidPassword));
+    assert!(matches!(provider.authenticate("nobody", "alice-password").await.unwrap(), DirectoryOutcome::UnknownUser));
+    // Ambiguous login names are refused
+    assert!(matches!(provider.authenticate("dup", "dup-password").await.unwrap(), DirectoryOutcome::UnknownUser));
+    // Filter syntax in the login name is escaped, not injected
+    assert!(matches!(provider.authenticate("alice)(uid=*", "alice-password").await.unwrap(), DirectoryOutcome::UnknownUser));
+    assert!(matches!(provider.authenticate("*", "alice-password").await.unwrap(), DirectoryOutcome::UnknownUser));
+
+    // A wrong service account password is an error, not a failed login
+    let mut config = mock.config();
+    config.bind_password = "wrong".to_string();
+    let misconfigured = ldap_auth::LdapAuthProvider::new(config);
+    assert!(!misconfigured.test_connection().await);
+    assert!(misconfigured.authenticate("alice", "alice-password").await.is_err());
+}
+
+#[tokio::test]
+async fn test_ldap_login_through_directory_server() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (owner, _, _) = member_account(&test.app).await;
+    let (_, org) = call(&test.app, "POST", "/api/orgs", Some(&owner), json!({ "name": "IT" })).await;
+    let org_id = org["id"].as_str().unwrap().to_string();
+
+    let mock = MockLdap::start().await;
+    let provider = Arc::new(ldap_auth::LdapAuthProvider::new(mock.config()));
+    let mappings = format!("Helpdesk={org_id}:operator;IT Admins={org_id}:admin");
+    let (app, _) = app_with_directory(&test.db, provider, &mappings, false);
+
+    let body = try_login(&app, "bob", "bob-password").await;
+    assert_eq!(body["success"], true, "{}", body);
+    assert_eq!(body["user"]["auth_provider"], "ldap");
+    assert_eq!(body["user"]["email"], "bob@example.com");
+    let token = body["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert_eq!(orgs[0]["id"], org_id.as_str());
+    assert_eq!(orgs[0]["role"], "admin");
+
+    assert_eq!(try_login(&app, "bob", "alice-password").await["success"], false);
+    assert_eq!(try_login(&app, "bob)(uid=*", "bob-password").await["success"], false);
+}
+
+/// In-process OpenID Connect provider signing ID tokens with an ES256 key
+struct MockIdp {
+    issuer: String,
+    key: jsonwebtoken::EncodingKey,
+    jwk: Value,
+    /// Issued authorization codes: code -> (challenge, redirect URI, ID token claims)
+    codes: std::sync::Mutex<std::collections::HashMap<String, (String, String, Value)>>,
+    jwks_fetches: std::sync::atomic::AtomicUsize,
+}
+
+impl MockIdp {
+    async fn start() -> Arc<Self> {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        // Uncompressed point: 0x04 || x || y
+        let point = pair.public_key().as_ref();
+        let coordinate = |bytes: &[u8]| data_encoding::BASE64URL_NOPAD.encode(bytes);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = Arc::new(MockIdp {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            key: jsonwebtoken::EncodingKey::from_ec_der(pkcs8.as_ref()),
+            jwk: json!({
+                "kty": "EC", "crv": "P-256", "kid": "mock-1", "alg": "ES256", "use": "sig",
+                "x": coordinate(&point[1..33]), "y": coordinate(&point[33..]),
+            }),
+            codes: Default::default(),
+            jwks_fetches: Default::default(),
+        });
+
+        let routes = Router::new()
+            .route("/.well-known/openid-configuration", axum::routing::get(|axum::extract::State(idp): axum::extract::State<Arc<MockIdp>>| async move {
+                axum::Json(json!({
+                    "issuer": idp.issuer,
+                    "authorization_endpoint": format!("{}/authorize", idp.issuer),
+                    "token_endpoint": format!("{}/token", idp.issuer),
+                    "jwks_uri": format!("{}/jwks", idp.issuer),
+                }))
+            }))
+            .route("/jwks", axum::routing::get(|axum::extract::State(idp): axum::extract::State<Arc<MockIdp>>| async move {
+                idp.jwks_fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                axum::Json(json!({ "keys": [idp.jwk] }))
+            }))
+            .route("/token", axum::routing::post(MockIdp::token))
+            .with_state(idp.clone());
+        tokio::spawn(async move { axum::serve(listener, routes).await.unwrap() });
+        idp
+    }
+
+    async fn token(
+        axum::extract::State(idp): axum::extract::State<Arc<MockIdp>>,
+        axum::Form(form): axum::Form<std::collections::HashMap<String, String>>,
+    ) -> Result<axum::Json<Value>, StatusCode> {
+        let (challenge, redirect_uri, claims) = idp.codes.lock().unwrap().remove(&form["code"]).ok_or(StatusCode::BAD_REQUEST)?;
+        if oidc::pkce_challenge(&form["code_verifier"]) != challenge || form["redirect_uri"] != redirect_uri || form["client_id"] != "genxlink" {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::ES256);
+        header.kid = Some("mock-1".to_string());
+        let id_token = jsonwebtoken::encode(&header, &claims, &idp.key).unwrap();
+        Ok(axum::Json(json!({ "access_token": "opaque", "token_type": "Bearer", "id_token": id_token })))
+    }
+
+    /// What the provider does when the user signs in at `authorization_url`:
+    /// remember the request and hand out a code for an ID token with `claims`
+    fn sign_in(&self, authorization_url: &str, mut claims: Value) -> String {
+        let url = reqwest::Url::parse(authorization_url).unwrap();
+        let params: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["response_type"], "code");
+
+        let now = chrono::Utc::now().timestamp();
+        let defaults = json!({ "iss": self.issuer, "aud": "genxlink", "iat": now, "exp": now + 300, "nonce": params["nonce"] });
+        for (name, value) in defaults.as_object().unwrap() {
+            claims.as_object_mut().unwrap().entry(name.clone()).or_insert(value.clone());
+        }
+        let code = Uuid::new_v4().to_string();
+        self.codes.lock().unwrap().insert(code.clone(), (params["code_challenge"].clone(), params["redirect_uri"].clone(), claims));
+        code
+    }
+}
+
+fn app_with_oidc(db: &Arc<Database>, idp: &MockIdp, role_mapping: &str) -> Router {
+    let provider = OidcProvider::new(OidcConfig {
+        issuer: idp.issuer.clone(),
+        client_id: "genxlink".to_string(),
+        client_secret: None,
+        scopes: "openid email profile".to_string(),
+        role_claim: "groups".to_string(),
+        role_mapping: ldap_auth::parse_group_roles(role_mapping).unwrap(),
+        redirect_uris: Vec::new(),
+    })
+    .unwrap();
+    let auth_service = AuthService::new((**db).clone(), JwtKeys::new(&password::generate_token())).with_oidc(provider);
+    router(AppState {
+        db: db.clone(),
+        auth_service: Arc::new(auth_service),
+        rate_limiter: Arc::new(RateLimiter::new(RateLimitConfig::default())),
+        ice: Arc::new(IceConfig::default()),
+        signaling: None,
+    })
+}
+
+/// Run the single sign-on flow as a client would, with the provider
+/// issuing `claims`; returns the login response
+async fn oidc_login(app: &Router, idp: &MockIdp, claims: Value) -> Value {
+    let verifier = password::generate_token();
+    let (_, started) = call(app, "POST", "/auth/oidc/authorize", None, json!({
+        "redirect_uri": "http://127.0.0.1:53682/callback",
+        "code_challenge": oidc::pkce_challenge(&verifier),
+    })).await;
+    assert_eq!(started["success"], true, "{}", started);
+
+    let code = idp.sign_in(started["authorization_url"].as_str().unwrap(), claims);
+    call(app, "POST", "/auth/oidc/callback", None, json!({
+        "code": code,
+        "state": started["state"],
+        "code_verifier": verifier,
+    })).await.1
+}
+
+#[tokio::test]
+async fn test_oidc_login_provisions_users_and_maps_claims() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (owner, _, _) = member_account(&test.app).await;
+    let (_, org) = call(&test.app, "POST", "/api/orgs", Some(&owner), json!({ "name": "Support" })).await;
+    let org_id = org["id"].as_str().unwrap().to_string();
+
+    let idp = MockIdp::start().await;
+    let app = app_with_oidc(&test.db, &idp, &format!("support-staff={org_id}:operator"));
+    let subject = Uuid::new_v4().to_string();
+    let email = unique_email();
+    let claims = |groups: Value| json!({
+        "sub": subject, "email": email, "email_verified": true, "name": "Jane Doe",
+        "preferred_username": format!("jane-{}", &subject[..8]), "groups": groups,
+    });
+
+    let body = oidc_login(&app, &idp, claims(json!(["support-staff"]))).await;
+    assert_eq!(body["success"], true, "{}", body);
+    assert_eq!(body["user"]["auth_provider"], "oidc");
+    assert_eq!(body["user"]["display_name"], "Jane Doe");
+    let user_id = body["user"]["id"].clone();
+    let token = body["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert_eq!(orgs[0]["role"], "operator");
+
+    // Same subject, same account; leaving the group drops the membership
+    let body = oidc_login(&app, &idp, claims(json!([]))).await;
+    assert_eq!(body["user"]["id"], user_id);
+    let token = body["token"].as_str().unwrap().to_string();
+    let (_, orgs) = call(&app, "GET", "/api/orgs", Some(&token), json!({})).await;
+    assert!(orgs.as_array().unwrap().is_empty());
+
+    // Signing keys were fetched once and reused
+    assert_eq!(idp.jwks_fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_oidc_login_rejections() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let idp = MockIdp::start().await;
+    let app = app_with_oidc(&test.db, &idp, "");
+    let claims = || json!({ "sub": Uuid::new_v4().to_string(), "email": unique_email() });
+
+    // Not configured on the default app
+    let (_, body) = call(&test.app, "POST", "/auth/oidc/authorize", None, json!({
+        "redirect_uri": "http://127.0.0.1:53682/callback",
+        "code_challenge": oidc::pkce_challenge("verifier"),
+    })).await;
+    assert_eq!(body["success"], false);
+
+    // Only loopback redirects unless configured
+    let (_, body) = call(&app, "POST", "/auth/oidc/authorize", None, json!({
+        "redirect_uri": "https://attacker.example.com/callback",
+        "code_challenge": oidc::pkce_challenge("verifier"),
+    })).await;
+    assert_eq!(body["success"], false);
+    assert!(body["authorization_url"].is_null());
+
+    // Tokens for another client, expired tokens and replayed nonces are refused
+    for overrides in [json!({ "aud": "another-client" }), json!({ "exp": 1_000_000 }), json!({ "nonce": "replayed" }), json!({ "email_verified": false })] {
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        let body = oidc_login(&app, &idp, claims).await;
+        assert_eq!(body["success"], false, "{}", overrides);
+        assert!(body["token"].is_null());
+    }
+
+    // The code verifier must match the challenge, and each state works once
+    let verifier = password::generate_token();
+    let (_, started) = call(&app, "POST", "/auth/oidc/authorize", None, json!({
+        "redirect_uri": "http://127.0.0.1:53682/callback",
+        "code_challenge": oidc::pkce_challenge(&verifier),
+    })).await;
+    let code = idp.sign_in(started["authorization_url"].as_str().unwrap(), claims());
+    let callback = |verifier: &str| json!({ "code": code, "state": started["state"], "code_verifier": verifier });
+    assert_eq!(call(&app, "POST", "/auth/oidc/callback", None, callback("wrong-verifier")).await.1["success"], false);
+    assert_eq!(call(&app, "POST", "/auth/oidc/callback", None, callback(&verifier)).await.1["success"], false);
+}
+
+#[tokio::test]
+async fn test_session_ice_servers_and_relay_policy() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let app = &test.app;
+    let (owner, owner_id, _) = member_account(app).await;
+    let (stranger, _, _) = member_account(app).await;
+
+    let (_, org) = call(app, "POST", "/api/orgs", Some(&owner), json!({ "name": "Bank" })).await;
+    let org_id = org["id"].as_str().unwrap().to_string();
+    let mut teller = device_json(&owner_id, &format!("dev-{}", Uuid::new_v4()), "Teller");
+    teller["org_id"] = json!(org_id);
+    let (_, teller) = call(app, "POST", "/api/devices", Some(&owner), teller).await;
+    let (_, laptop) = call(app, "POST", "/api/devices", Some(&owner), device_json(&owner_id, &format!("dev-{}", Uuid::new_v4()), "Laptop")).await;
+    let (_, session) = call(app, "POST", "/api/sessions", Some(&owner), session_json(&owner_id, &laptop, &teller)).await;
+    let session_id = session["id"].as_str().unwrap();
+    let uri = format!("/api/sessions/{}/ice-servers", session_id);
+
+    // STUN plus TURN with credentials bound to the session
+    let (status, config) = call(app, "GET", &uri, Some(&owner), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(config["ice_transport_policy"], "all");
+    let servers = config["ice_servers"].as_array().unwrap();
+    assert_eq!(servers.len(), 2);
+    assert!(servers[0]["urls"][0].as_str().unwrap().starts_with("stun:"));
+    assert!(servers[0].get("username").is_none());
+    let expires_at: chrono::DateTime<chrono::Utc> = serde_json::from_value(config["expires_at"].clone()).unwrap();
+    let username = servers[1]["username"].as_str().unwrap();
+    assert_eq!(username, format!("{}:{}", expires_at.timestamp(), session_id));
+    assert_eq!(servers[1]["credential"], ice::turn_credential(TURN_SECRET, username));
+    assert_eq!(call(app, "GET", &uri, Some(&stranger), json!({})).await.0, StatusCode::NOT_FOUND);
+
+    // Relay-only organizations get TURN servers alone
+    let policy = format!("/api/orgs/{}/relay-policy", org_id);
+    assert_eq!(call(app, "POST", &policy, Some(&stranger), json!({ "force_relay": true })).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(call(app, "POST", &policy, Some(&owner), json!({ "force_relay": true })).await.0, StatusCode::OK);
+    let (_, details) = call(app, "GET", &format!("/api/orgs/{}", org_id), Some(&owner), json!({})).await;
+    assert_eq!(details["force_relay"], true);
+    let teller_id = teller["device_id"].as_str().unwrap();
+    let laptop_id = laptop["device_id"].as_str().unwrap();
+    let mut teller_peer = SignalingPeer::register(test.signaling, teller_id).await;
+    let mut laptop_peer = SignalingPeer::register(test.signaling, laptop_id).await;
+    let (_, config) = call(app, "GET", &uri, Some(&owner), json!({})).await;
+    assert_eq!(config["ice_transport_policy"], "relay");
+    let servers = config["ice_servers"].as_array().unwrap();
+    assert_eq!(servers.len(), 1);
+    assert!(servers[0]["urls"][0].as_str().unwrap().starts_with("turn:"));
+
+    // The signaling server holds back direct candidates even from a client ignoring the policy
+    let relay = "candidate:2 1 udp 41885439 203.0.113.7 3478 typ relay raddr 0.0.0.0 rport 0";
+    for (id, candidate) in [(1, "candidate:1 1 udp 2122260223 10.0.0.2 50000 typ host"), (2, relay)] {
+        laptop_peer.send(genxlink_protocol::SignalingEnvelope::request(id, genxlink_protocol::SignalingMessage::IceCandidate {
+            candidate: candidate.to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_m_line_index: Some(0),
+            from: genxlink_protocol::DeviceId::from_string(laptop_id.to_string()),
+            to: genxlink_protocol::DeviceId::from_string(teller_id.to_string()),
+        })).await;
+        assert!(matches!(laptop_peer.recv().await, genxlink_protocol::SignalingMessage::Ack));
+    }
+    match teller_peer.recv().await {
+        genxlink_protocol::SignalingMessage::IceCandidate { candidate, .. } => assert_eq!(candidate, relay),
+        other => panic!("expected the relay candidate, got {:?}", other),
+    }
+
+    // Nothing is issued once the session is over
+    assert_eq!(call(app, "POST", &format!("/api/sessions/{}/end", session_id), Some(&owner), json!({})).await.0, StatusCode::OK);
+    assert_eq!(call(app, "GET", &uri, Some(&owner), json!({})).await.0, StatusCode::GONE);
+}
+
+/// Register a user, grant platform admin rights and return (token, user id)
+async fn admin_account(test: &TestApp) -> (String, String) {
+    let (token, user_id, _) = member_account(&test.app).await;
+    assert!(test.db.set_user_admin(user_id.parse().unwrap(), true).await.unwrap());
+    (token, user_id)
+}
+
+/// Register two devices for a user and start a session between them
+///
+/// Returns the session and its local and remote devices.
+async fn start_session(app: &Router, token: &str, user_id: &str) -> (Value, Value, Value) {
+    let (_, local) = call(app, "POST", "/api/devices", Some(token), device_json(user_id, &format!("dev-{}", Uuid::new_v4()), "Office PC")).await;
+    let (_, remote) = call(app, "POST", "/api/devices", Some(token), device_json(user_id, &format!("dev-{}", Uuid::new_v4()), "Laptop")).await;
+    let (status, session) = call(app, "POST", "/api/sessions", Some(token), session_json(user_id, &local, &remote)).await;
+    assert_eq!(status, StatusCode::OK);
+    (session, local, remote)
+}
+
+/// A device connected to the signaling server
+struct SignalingPeer {
+    ws: tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
+}
+
+impl SignalingPeer {
+    /// Connect and register `device_id`, signing the server's challenge with a new key
+    async fn register(address: std::net::SocketAddr, device_id: &str) -> Self {
+        use genxlink_protocol::{register_proof_message, DeviceId, RegisterProof, SignalingEnvelope, SignalingMessage};
+        use ring::signature::{Ed25519KeyPair, KeyPair};
+
+        let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        let mut peer = Self { ws };
+        let SignalingMessage::Challenge { nonce } = peer.recv().await else {
+            panic!("expected a challenge");
+        };
+
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let device_id = DeviceId::from_string(device_id.to_string());
+        let register = SignalingEnvelope::request(0, SignalingMessage::Register {
+            proof: Some(RegisterProof {
+                public_key: key.public_key().as_ref().to_vec(),
+                signature: key.sign(&register_proof_message(&nonce, &device_id)).as_ref().to_vec(),
+            }),
+            device_id,
+            device_name: None,
+        });
+        peer.send(register).await;
+        assert!(matches!(peer.recv().await, SignalingMessage::Ack));
+        peer
+    }
+
+    async fn send(&mut self, envelope: genxlink_protocol::SignalingEnvelope) {
+        futures::SinkExt::send(&mut self.ws, tokio_tungstenite::tungstenite::Message::Text(serde_json::to_string(&envelope).unwrap()))
+            .await
+            .unwrap();
+    }
+
+    /// Next message that isn't a presence notification
+    async fn recv(&mut self) -> genxlink_protocol::SignalingMessage {
+        use genxlink_protocol::{SignalingEnvelope, SignalingMessage};
+
+        loop {
+            let frame = tokio::time::timeout(std::time::Duration::from_secs(5), futures::StreamExt::next(&mut self.ws))
+                .await
+                .expect("timed out waiting for a signaling message")
+                .expect("signaling connection closed")
+                .unwrap();
+            let tokio_tungstenite::tungstenite::Message::Text(text) = frame else { continue };
+            let envelope: SignalingEnvelope = serde_json::from_str(&text).unwrap();
+            if !matches!(envelope.message, SignalingMessage::PeerJoined { .. } | SignalingMessage::PeerLeft { .. }) {
+                return envelope.message;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_admin_api_requires_admin() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (token, user_id, _) = member_account(&test.app).await;
+
+    let (status, _) = call(&test.app, "GET", "/api/admin/stats", None, json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&test.app, "GET", "/api/admin/stats", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/users/{}/admin", user_id), Some(&token), json!({ "is_admin": true })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Admin scopes can't be handed to keys of ordinary users
+    let (_, body) = call(&test.app, "POST", "/api/keys", Some(&token), json!({
+        "name": "tooling",
+        "scopes": ["admin:read"]
+    })).await;
+    assert_eq!(body["success"], false);
+
+    // Rights are checked on every request, not baked into the token
+    let (admin_token, _) = admin_account(&test).await;
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/users/{}/admin", user_id), Some(&admin_token), json!({ "is_admin": true })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, stats) = call(&test.app, "GET", "/api/admin/stats", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(stats["total_users"].as_i64().unwrap() >= 2);
+
+    // Admins can't lock themselves out
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/users/{}/admin", user_id), Some(&token), json!({ "is_admin": false })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/users/{}/admin", user_id), Some(&admin_token), json!({ "is_admin": false })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&test.app, "GET", "/api/admin/stats", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+fn account_profile(email: &str, is_verified: bool, auth_provider: &str) -> User {
+    let now = chrono::Utc::now();
+    User {
+        id: Uuid::new_v4(),
+        email: email.to_string(),
+        username: email.to_string(),
+        display_name: "Listed Admin".to_string(),
+        avatar_url: None,
+        is_active: true,
+        is_verified,
+        subscription_type: "free".to_string(),
+        auth_provider: auth_provider.to_string(),
+        is_admin: false,
+        created_at: now,
+        updated_at: now,
+        last_login: None,
+        preferences: json!({}),
+    }
+}
+
+#[tokio::test]
+async fn test_admin_emails_only_promote_verified_accounts() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    // Self-registered before the real owner could, so never verified
+    let squatted = unique_email();
+    register(&test.app, &squatted, "squatter-password").await;
+    let verified = unique_email();
+    test.db.create_user(&account_profile(&verified, true, "local"), password::NO_PASSWORD).await.unwrap();
+    let sso = unique_email();
+    test.db.upsert_external_user(&account_profile(&sso, false, oidc::OIDC_PROVIDER), &Uuid::new_v4().to_string()).await.unwrap();
+    let missing = unique_email();
+
+    auth::grant_admins(&test.db, &format!("{}, {},{},{}", squatted, verified, sso, missing)).await.unwrap();
+
+    let is_admin = |email: String| {
+        let db = test.db.clone();
+        async move { db.get_user_by_email(&email).await.unwrap().unwrap().is_admin }
+    };
+    assert!(!is_admin(squatted).await);
+    assert!(is_admin(verified).await);
+    assert!(is_admin(sso).await);
+    assert!(test.db.get_user_by_email(&missing).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_admin_fleet_and_force_disconnect() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (admin_token, _) = admin_account(&test).await;
+    let (token, user_id, _) = member_account(&test.app).await;
+    let (session, local, remote) = start_session(&test.app, &token, &user_id).await;
+    let (local_id, remote_id) = (local["device_id"].as_str().unwrap(), remote["device_id"].as_str().unwrap());
+    let mut host = SignalingPeer::register(test.signaling, local_id).await;
+    let mut controller = SignalingPeer::register(test.signaling, remote_id).await;
+    let (status, _) = call(&test.app, "POST", &format!("/api/devices/{}/status", local_id), Some(&token), json!({ "is_online": true })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, online) = call(&test.app, "GET", "/api/admin/devices?online=true", Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let online = online.as_array().unwrap();
+    assert!(online.iter().all(|device| device["is_online"] == true));
+    assert!(online.iter().any(|device| device["id"] == local["id"]));
+    let (_, all) = call(&test.app, "GET", "/api/admin/devices?limit=1000", Some(&admin_token), json!({})).await;
+    assert!(all.as_array().unwrap().len() > online.len());
+
+    let (_, active) = call(&test.app, "GET", "/api/admin/sessions?status=active", Some(&admin_token), json!({})).await;
+    assert!(active.as_array().unwrap().iter().any(|s| s["id"] == session["id"]));
+
+    let session_id = session["id"].as_str().unwrap();
+    let disconnect = format!("/api/admin/sessions/{}/disconnect", session_id);
+    let (status, _) = call(&test.app, "POST", &disconnect, Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, body) = call(&test.app, "POST", &disconnect, Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["notified"].as_array().unwrap().len(), 2);
+
+    // Both devices are told to close the session with the other
+    for (peer, other) in [(&mut host, remote_id), (&mut controller, local_id)] {
+        match peer.recv().await {
+            genxlink_protocol::SignalingMessage::SessionTerminated { peer, .. } => assert_eq!(peer.0, other),
+            message => panic!("expected the session to be terminated, got {:?}", message),
+        }
+    }
+
+    let (status, _) = call(&test.app, "POST", &disconnect, Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/sessions/{}/disconnect", Uuid::new_v4()), Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, sessions) = call(&test.app, "GET", "/api/sessions", Some(&token), json!({})).await;
+    assert_eq!(sessions[0]["status"], "terminated");
+    // The user ending it afterwards changes nothing
+    let (status, _) = call(&test.app, "POST", &format!("/api/sessions/{}/end", session_id), Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, sessions) = call(&test.app, "GET", "/api/sessions", Some(&token), json!({})).await;
+    assert_eq!(sessions[0]["status"], "terminated");
+}
+
+#[tokio::test]
+async fn test_usage_recorded_when_sessions_end() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (admin_token, _) = admin_account(&test).await;
+    let (token, user_id, email) = member_account(&test.app).await;
+
+    for (sent, received) in [(1000, 5000), (200, 300)] {
+        let (session, _, _) = start_session(&test.app, &token, &user_id).await;
+        let (status, _) = call(&test.app, "POST", &format!("/api/sessions/{}/end", session["id"].as_str().unwrap()), Some(&token), json!({
+            "bytes_sent": sent,
+            "bytes_received": received
+        })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    // Still running, so not counted yet
+    start_session(&test.app, &token, &user_id).await;
+
+    let (status, usage) = call(&test.app, "GET", "/api/stats/usage", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(usage["connections"], 2);
+    assert_eq!(usage["bandwidth_used"], 6500);
+    assert!(usage["session_duration"].as_i64().unwrap() >= 0);
+
+    let (status, daily) = call(&test.app, "GET", &format!("/api/admin/usage?user_id={}", user_id), Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let daily = daily.as_array().unwrap();
+    assert_eq!(daily.len(), 1);
+    assert_eq!(daily[0]["email"], email.as_str());
+    assert_eq!(daily[0]["day"], chrono::Utc::now().date_naive().to_string());
+    assert_eq!(daily[0]["connections"], 3);
+    assert_eq!(daily[0]["bytes_sent"], 1200);
+    assert_eq!(daily[0]["bytes_received"], 5300);
+
+    let (_, past) = call(&test.app, "GET", &format!("/api/admin/usage?user_id={}&from=2020-01-01&to=2020-01-31", user_id), Some(&admin_token), json!({})).await;
+    assert!(past.as_array().unwrap().is_empty());
+    let (status, _) = call(&test.app, "GET", "/api/admin/usage?from=2024-02-01&to=2024-01-01", Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&test.app, "GET", "/api/admin/usage?from=2020-01-01&to=2024-01-01", Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_admin_license_management() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (admin_token, _) = admin_account(&test).await;
+    let (token, user_id, _) = member_account(&test.app).await;
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(365);
+
+    let (status, _) = call(&test.app, "POST", "/api/admin/licenses", Some(&admin_token), json!({
+        "user_id": user_id,
+        "license_type": "platinum",
+        "expires_at": expires_at
+    })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&test.app, "POST", "/api/admin/licenses", Some(&admin_token), json!({
+        "user_id": Uuid::new_v4(),
+        "license_type": "pro",
+        "expires_at": expires_at
+    })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, license) = call(&test.app, "POST", "/api/admin/licenses", Some(&admin_token), json!({
+        "user_id": user_id,
+        "license_type": "pro",
+        "expires_at": expires_at,
+        "max_devices": 5
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(license["max_devices"], 5);
+    assert_eq!(license["max_concurrent_sessions"], 1);
+    assert_eq!(license["license_key"].as_str().unwrap().len(), 29);
+
+    // The user can activate it with the generated key
+    let (_, activated) = call(&test.app, "POST", "/api/license/activate", Some(&token), json!({
+        "license_key": license["license_key"]
+    })).await;
+    assert_eq!(activated["success"], true);
+
+    let license_id = license["id"].as_str().unwrap();
+    let (status, updated) = call(&test.app, "POST", &format!("/api/admin/licenses/{}", license_id), Some(&admin_token), json!({
+        "is_active": false,
+        "max_concurrent_sessions": 3
+    })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["is_active"], false);
+    assert_eq!(updated["max_concurrent_sessions"], 3);
+    assert_eq!(updated["max_devices"], 5);
+    assert_eq!(updated["license_type"], "pro");
+
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/licenses/{}", license_id), Some(&admin_token), json!({ "max_devices": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/licenses/{}", Uuid::new_v4()), Some(&admin_token), json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, licenses) = call(&test.app, "GET", &format!("/api/admin/licenses?user_id={}", user_id), Some(&admin_token), json!({})).await;
+    let licenses = licenses.as_array().unwrap();
+    assert_eq!(licenses.len(), 1);
+    assert_eq!(licenses[0]["is_active"], false);
+}
+
+#[tokio::test]
+async fn test_admin_api_keys_and_openapi() {
+    let Some(test) = test_app().await else {
+        println!("⚠ No test database configured - skipping");
+        return;
+    };
+    let (admin_token, _) = admin_account(&test).await;
+
+    let (_, created) = call(&test.app, "POST", "/api/keys", Some(&admin_token), json!({
+        "name": "fleet dashboard",
+        "scopes": ["admin:read"]
+    })).await;
+    assert_eq!(created["success"], true);
+    let key = created["key"].as_str().unwrap().to_string();
+
+    let (status, spec) = call(&test.app, "GET", "/api/admin/openapi.json", Some(&key), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(spec["openapi"], "3.0.3");
+    let (status, _) = call(&test.app, "POST", &format!("/api/admin/sessions/{}/disconnect", Uuid::new_v4()), Some(&key), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Every documented operation is routed; only unknown ids may give 404
+    for (method, path) in genxlink_api_server::openapi::admin_operations() {
+        let has_id = path.contains('{');
+        let path = path
+            .split('/')
+            .map(|segment| if segment.starts_with('{') { Uuid::new_v4().to_string() } else { segment.to_string() })
+            .collect::<Vec<_>>()
+            .join("/");
+        let (status, _) = call(&test.app, &method.to_uppercase(), &path, Some(&admin_token), json!({ "is_admin": true })).await;
+        assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{} {}", method, path);
+        assert!(status != StatusCode::NOT_FOUND || has_id, "{} {} is not routed", method, path);
+    }
+}
@@ -0,0 +1,116 @@
+//! Device ID ownership
+//!
+//! A device ID belongs to the first key that registers it. The pins are
+//! appended to a file, one JSON object per line, so a restart doesn't hand
+//! every ID to whoever registers it first afterwards.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use genxlink_protocol::DeviceId;
+use serde::{Deserialize, Serialize};
+
+/// File the pins are kept in unless `SIGNALING_KEY_PINS` names another
+pub const DEFAULT_PATH: &str = "device-keys.jsonl";
+
+/// One line of the pin file
+#[derive(Serialize, Deserialize)]
+struct Pin {
+    device_id: DeviceId,
+    public_key: String,
+}
+
+/// Public key each device ID was first registered with
+pub struct KeyPins {
+    pins: HashMap<DeviceId, Vec<u8>>,
+    /// Where new pins are appended; `None` keeps them in memory only
+    path: Option<PathBuf>,
+}
+
+impl KeyPins {
+    /// Pins that last as long as the process
+    pub fn in_memory() -> Self {
+        Self { pins: HashMap::new(), path: None }
+    }
+
+    /// Pins stored in the file at `path`, created if it doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut pins = HashMap::new();
+        match std::fs::File::open(&path) {
+            Ok(file) => {
+                for (number, line) in io::BufReader::new(file).lines().enumerate() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let pin: Pin = serde_json::from_str(&line).map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), number + 1, e))
+                    })?;
+                    let key = decode_hex(&pin.public_key).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: invalid key", path.display(), number + 1))
+                    })?;
+                    // The first pin wins, as it did when it was recorded
+                    pins.entry(pin.device_id).or_insert(key);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self { pins, path: Some(path) })
+    }
+
+    /// Pins stored in `SIGNALING_KEY_PINS`, or `DEFAULT_PATH`
+    pub fn from_env() -> io::Result<Self> {
+        let path = std::env::var("SIGNALING_KEY_PINS")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| DEFAULT_PATH.to_string());
+        Self::open(path)
+    }
+
+    /// Key `device_id` is pinned to
+    pub fn get(&self, device_id: &DeviceId) -> Option<&[u8]> {
+        self.pins.get(device_id).map(Vec::as_slice)
+    }
+
+    /// Number of pinned device IDs
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Pin `device_id` to `public_key`, recording it before it takes effect
+    pub fn pin(&mut self, device_id: &DeviceId, public_key: &[u8]) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(&Pin {
+                device_id: device_id.clone(),
+                public_key: encode_hex(public_key),
+            })?;
+            line.push('\n');
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(line.as_bytes())?;
+            file.sync_data()?;
+        }
+        self.pins.insert(device_id.clone(), public_key.to_vec());
+        Ok(())
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
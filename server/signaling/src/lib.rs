@@ -0,0 +1,142 @@
+// GenXLink Signaling Server
+//
+// Routes `genxlink_protocol::SignalingMessage`s between registered peers
+// over one WebSocket per peer. See `PeerManager` for the request/response
+// rules.
+use std::net::SocketAddr;
+use axum::{
+    extract::{ws::WebSocketUpgrade, ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+    Json,
+};
+use genxlink_protocol::DeviceId;
+use serde::{Deserialize, Serialize};
+
+pub mod key_pins;
+pub mod peer_manager;
+pub mod rate_limit;
+
+pub use key_pins::KeyPins;
+pub use peer_manager::{PeerManager, SignalingConfig};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub service: String,
+    pub version: String,
+    pub timestamp: String,
+}
+
+/// Body of `POST /admin/sessions/terminate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminateSessionRequest {
+    /// The two peers of the session
+    pub peers: [DeviceId; 2],
+    pub reason: String,
+}
+
+/// Reply to `POST /admin/sessions/terminate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminateSessionResponse {
+    /// Peers that were online and were told to close the session
+    pub notified: Vec<DeviceId>,
+}
+
+/// Body of `POST /admin/sessions/relay-only`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayOnlyRequest {
+    /// The two peers of the session
+    pub peers: [DeviceId; 2],
+    /// When the session's TURN credentials expire
+    pub until: chrono::DateTime<chrono::Utc>,
+}
+
+/// Routes of the signaling server
+///
+/// Needs connection info, so serve it with
+/// `into_make_service_with_connect_info::<SocketAddr>()`.
+pub fn router(peers: PeerManager) -> Router {
+    Router::new()
+        .route("/ws", get(websocket_handler))
+        .route("/health", get(health_check))
+        .route("/peers", get(list_peers))
+        .route("/admin/sessions/terminate", post(terminate_session))
+        .route("/admin/sessions/relay-only", post(require_relay))
+        .with_state(peers)
+}
+
+/// Serve the signaling routes on `listener` until it fails
+pub async fn serve(listener: tokio::net::TcpListener, peers: PeerManager) -> std::io::Result<()> {
+    axum::serve(listener, router(peers).into_make_service_with_connect_info::<SocketAddr>()).await
+}
+
+async fn websocket_handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    State(peers): State<PeerManager>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move { peers.serve(socket, remote_addr).await })
+}
+
+async fn health_check() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "healthy".to_string(),
+        service: "genxlink-signaling".to_string(),
+        version: "1.0.0".to_string(),
+        timestamp: chrono::Utc::now().to_rfc3339(),
+    })
+}
+
+async fn list_peers(State(peers): State<PeerManager>) -> Json<Vec<String>> {
+    let peer_list = peers.get_connected_peers().await.into_iter().map(|id| id.0).collect();
+    Json(peer_list)
+}
+
+/// End a session on behalf of the API server (`Authorization: Bearer <SIGNALING_ADMIN_TOKEN>`)
+async fn terminate_session(
+    State(peers): State<PeerManager>,
+    headers: HeaderMap,
+    Json(request): Json<TerminateSessionRequest>,
+) -> Result<Json<TerminateSessionResponse>, StatusCode> {
+    check_admin(&peers, &headers)?;
+    let [first, second] = &request.peers;
+    let notified = peers.terminate_session(first, second, &request.reason).await;
+    Ok(Json(TerminateSessionResponse { notified }))
+}
+
+/// Keep a session on TURN relays on behalf of the API server
+async fn require_relay(
+    State(peers): State<PeerManager>,
+    headers: HeaderMap,
+    Json(request): Json<RelayOnlyRequest>,
+) -> Result<StatusCode, StatusCode> {
+    check_admin(&peers, &headers)?;
+    let [first, second] = &request.peers;
+    peers.require_relay(first, second, request.until).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check the admin bearer token; the endpoints don't exist without one configured
+fn check_admin(peers: &PeerManager, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(token) = peers.admin_token() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let presented = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+        Ok(())
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// Compare secrets without revealing how much of them matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
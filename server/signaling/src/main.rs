@@ -1,12 +1,6 @@
-use axum::{
-    extract::ws::{WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
-    routing::get,
-    Router,
-};
 use std::net::SocketAddr;
 use tracing::info;
-use futures::{sink::SinkExt, stream::StreamExt};
+use genxlink_signaling_server::{KeyPins, PeerManager, SignalingConfig};
 
 #[tokio::main]
 async fn main() {
@@ -17,11 +11,6 @@ async fn main() {
     
     info!("🚀 GenXLink Signaling Server starting...");
     
-    // Build router
-    let app = Router::new()
-        .route("/ws", get(websocket_handler))
-        .route("/health", get(health_check));
-    
     // Start server
     let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let port: u16 = port.parse().expect("PORT must be a number");
@@ -30,41 +19,10 @@ async fn main() {
     println!("📡 WebSocket endpoint: ws://{}:{}/ws", addr.ip(), addr.port());
     println!("🔍 Health check: http://{}:{}/health", addr.ip(), addr.port());
     
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
-
-/// WebSocket handler
-async fn websocket_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(handle_socket)
-}
-
-/// Handle WebSocket connection
-async fn handle_socket(socket: WebSocket) {
-    info!("🔌 New WebSocket connection");
+    // Device IDs stay with the keys that registered them across restarts
+    let key_pins = KeyPins::from_env().expect("Failed to load device key pins");
+    let peers = PeerManager::with_key_pins(SignalingConfig::from_env(), key_pins);
     
-    let (mut sender, mut receiver) = socket.split();
-    
-    // Simple echo server for now
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(msg) => {
-                if let Err(e) = sender.send(msg).await {
-                    info!("Error sending message: {}", e);
-                    break;
-                }
-            }
-            Err(e) => {
-                info!("Error receiving message: {}", e);
-                break;
-            }
-        }
-    }
-    
-    info!("WebSocket connection closed");
-}
-
-/// Health check endpoint
-async fn health_check() -> &'static str {
-    "{\"status\":\"healthy\",\"service\":\"genxlink-signaling\"}"
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    genxlink_signaling_server::serve(listener, peers).await.unwrap();
 }
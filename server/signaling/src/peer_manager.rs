@@ -1,210 +1,613 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock, mpsc};
 use axum::extract::ws::{WebSocket, Message};
 use futures::{sink::SinkExt, stream::StreamExt};
-use genxlink_protocol::{DeviceId, SignalingMessage};
+use genxlink_protocol::{
+    register_proof_message, DeviceId, DeviceType, MessageId, PeerInfo as ProtocolPeerInfo,
+    RegisterProof, SignalingEnvelope, SignalingErrorCode, SignalingMessage,
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{UnparsedPublicKey, ED25519};
 use tracing::{info, error, debug, warn};
 
-/// Manages connected peers
+use crate::key_pins::KeyPins;
+use crate::rate_limit::{ConnectRateLimiter, RateLimitConfig};
+
+/// Time a peer has to answer a relayed request
+pub const DEFAULT_RELAY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Length of the nonce a connection's `Register` has to sign
+const CHALLENGE_LEN: usize = 32;
+
+/// Device IDs one connection may register over its lifetime
+pub const DEFAULT_MAX_DEVICE_IDS_PER_CONNECTION: usize = 4;
+
+/// Peer manager configuration
+#[derive(Debug, Clone)]
+pub struct SignalingConfig {
+    /// Time a peer has to answer a relayed `ConnectionRequest` or `Offer`
+    pub relay_timeout: Duration,
+    pub rate_limit: RateLimitConfig,
+    /// Bearer token for the admin endpoints; they are disabled without one
+    pub admin_token: Option<String>,
+    /// Device IDs one connection may register, so a socket can't pin IDs in bulk
+    pub max_device_ids_per_connection: usize,
+}
+
+impl Default for SignalingConfig {
+    fn default() -> Self {
+        Self {
+            relay_timeout: DEFAULT_RELAY_TIMEOUT,
+            rate_limit: RateLimitConfig::default(),
+            admin_token: None,
+            max_device_ids_per_connection: DEFAULT_MAX_DEVICE_IDS_PER_CONNECTION,
+        }
+    }
+}
+
+impl SignalingConfig {
+    /// Defaults, with the admin token from `SIGNALING_ADMIN_TOKEN`
+    pub fn from_env() -> Self {
+        Self {
+            admin_token: std::env::var("SIGNALING_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Manages connected peers and routes signaling between them
+///
+/// Every socket gets a `Challenge` nonce when it opens. Peers `Register` a
+/// device ID by signing that nonce with their device key, then address each
+/// other by it. The first key to register an ID owns it (see `KeyPins`,
+/// which keeps the pins across restarts): a registration signed by any
+/// other key is refused, so nobody can take over a live ID or claim it while
+/// its owner is reconnecting.
+/// Requests the addressed peer has to answer (`ConnectionRequest`,
+/// `Offer`) are relayed under a server-assigned id; the peer's reply is
+/// routed back to the requester with the requester's own id in `reply_to`,
+/// or answered with a `Timeout` error if it doesn't come in time.
+///
+/// Sessions the API server marks relay-only only ever see each other's TURN
+/// relay candidates: host and server-reflexive candidates are dropped from
+/// trickled `IceCandidate`s and stripped from offer and answer SDP, so a
+/// modified client can't learn its peer's addresses.
+#[derive(Clone)]
 pub struct PeerManager {
+    config: SignalingConfig,
     peers: Arc<RwLock<HashMap<DeviceId, PeerInfo>>>,
+    /// Public key each device ID was first registered with
+    owners: Arc<Mutex<KeyPins>>,
+    relays: Arc<Mutex<HashMap<MessageId, PendingRelay>>>,
+    /// Peer pairs (in sorted order) limited to relay candidates, until when
+    relay_only: Arc<Mutex<HashMap<PeerPair, chrono::DateTime<chrono::Utc>>>>,
+    rate_limiter: Arc<Mutex<ConnectRateLimiter>>,
+    next_id: Arc<AtomicU64>,
 }
 
+/// Two peers, in sorted order
+type PeerPair = (DeviceId, DeviceId);
+
 /// Information about a connected peer
 pub struct PeerInfo {
     pub device_id: DeviceId,
+    pub device_name: Option<String>,
     pub sender: mpsc::UnboundedSender<Message>,
     pub connected_at: chrono::DateTime<chrono::Utc>,
+    /// Socket the device registered on
+    connection: u64,
+}
+
+/// A request waiting for the addressed peer's reply
+struct PendingRelay {
+    requester: DeviceId,
+    /// The requester's id for the request, if it wants a reply
+    request_id: Option<MessageId>,
+    target: DeviceId,
+}
+
+/// One WebSocket connection
+struct Connection {
+    id: u64,
+    remote_addr: SocketAddr,
+    sender: mpsc::UnboundedSender<Message>,
+    device_id: Option<DeviceId>,
+    /// Device IDs registered on this socket so far
+    claimed: Vec<DeviceId>,
+    /// Challenge sent when the socket opened
+    nonce: [u8; CHALLENGE_LEN],
+}
+
+impl Connection {
+    fn send(&self, envelope: &SignalingEnvelope) {
+        send_envelope(&self.sender, envelope);
+    }
+
+    fn reply(&self, request: &SignalingEnvelope, message: SignalingMessage) {
+        if request.id.is_some() {
+            self.send(&request.reply(message));
+        }
+    }
 }
 
 impl PeerManager {
     pub fn new() -> Self {
+        Self::with_config(SignalingConfig::default())
+    }
+
+    /// Peer manager whose device key pins only last as long as the process
+    pub fn with_config(config: SignalingConfig) -> Self {
+        Self::with_key_pins(config, KeyPins::in_memory())
+    }
+
+    /// Peer manager checking registrations against `owners`
+    pub fn with_key_pins(config: SignalingConfig, owners: KeyPins) -> Self {
         Self {
+            rate_limiter: Arc::new(Mutex::new(ConnectRateLimiter::new(config.rate_limit.clone()))),
+            config,
             peers: Arc::new(RwLock::new(HashMap::new())),
+            owners: Arc::new(Mutex::new(owners)),
+            relays: Arc::new(Mutex::new(HashMap::new())),
+            relay_only: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
         }
     }
-    
-    /// Register a new peer with WebSocket connection
-    pub async fn register_peer(&self, socket: WebSocket) -> DeviceId {
-        let device_id = DeviceId::new();
+
+    /// Serve a peer's WebSocket until it disconnects
+    pub async fn serve(&self, socket: WebSocket, remote_addr: SocketAddr) {
         let (mut sender, mut receiver) = socket.split();
-        
+
         // Create channel for sending messages to this peer
         let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
-        
-        // Store peer info
-        let peer_info = PeerInfo {
-            device_id: device_id.clone(),
+        let mut nonce = [0u8; CHALLENGE_LEN];
+        if SystemRandom::new().fill(&mut nonce).is_err() {
+            error!("Failed to generate a challenge for {}", remote_addr);
+            return;
+        }
+        let mut connection = Connection {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            remote_addr,
             sender: tx,
-            connected_at: chrono::Utc::now(),
+            device_id: None,
+            claimed: Vec::new(),
+            nonce,
         };
-        
-        let mut peers = self.peers.write().await;
-        peers.insert(device_id.clone(), peer_info);
-        drop(peers);
-        
+        connection.send(&SignalingMessage::Challenge { nonce: nonce.to_vec() }.into());
+
         // Spawn task to handle outgoing messages
-        let device_id_clone = device_id.clone();
-        tokio::spawn(async move {
+        let writer = tokio::spawn(async move {
             while let Some(msg) = rx.recv().await {
                 if let Err(e) = sender.send(msg).await {
-                    error!("Failed to send message to peer {}: {}", device_id_clone, e);
+                    error!("Failed to send message to {}: {}", remote_addr, e);
                     break;
                 }
             }
         });
-        
-        // Spawn task to handle incoming messages
-        let peers_arc = Arc::clone(&self.peers);
-        let device_id_clone = device_id.clone();
-        tokio::spawn(async move {
-            while let Some(msg_result) = receiver.next().await {
-                match msg_result {
-                    Ok(Message::Text(text)) => {
-                        debug!("Received message from {}: {}", device_id_clone, text);
-                        
-                        match serde_json::from_str::<SignalingMessage>(&text) {
-                            Ok(signaling_msg) => {
-                                handle_signaling_message(signaling_msg, device_id_clone.clone(), Arc::clone(&peers_arc)).await;
-                            }
-                            Err(e) => {
-                                error!("Failed to parse signaling message from {}: {}", device_id_clone, e);
-                            }
-                        }
-                    }
-                    Ok(Message::Close(_)) => {
-                        info!("Peer {} disconnected", device_id_clone);
-                        break;
-                    }
-                    Err(e) => {
-                        error!("WebSocket error for peer {}: {}", device_id_clone, e);
-                        break;
-                    }
-                    _ => {}
+
+        while let Some(msg_result) = receiver.next().await {
+            match msg_result {
+                Ok(Message::Text(text)) => {
+                    debug!("Received message from {}: {}", remote_addr, text);
+                    self.handle_text(&mut connection, &text).await;
+                }
+                Ok(Message::Close(_)) => break,
+                Err(e) => {
+                    warn!("WebSocket error for {}: {}", remote_addr, e);
+                    break;
                 }
+                _ => {}
             }
-            
-            // Remove peer on disconnect
-            let mut peers = peers_arc.write().await;
-            peers.remove(&device_id_clone);
-            info!("Peer {} removed from registry", device_id_clone);
-        });
-        
-        device_id
+        }
+
+        if let Some(device_id) = connection.device_id.take() {
+            self.unregister(&device_id, connection.id).await;
+        }
+        drop(connection);
+        let _ = writer.await;
     }
+
     /// Send message to a specific peer
     pub async fn send_to_peer(&self, target: &DeviceId, message: SignalingMessage) -> Result<(), String> {
         let peers = self.peers.read().await;
-        
+
         if let Some(peer_info) = peers.get(target) {
-            let json = serde_json::to_string(&message)
-                .map_err(|e| format!("Failed to serialize message: {}", e))?;
-            
-            peer_info.sender.send(Message::Text(json))
-                .map_err(|e| format!("Failed to send message: {}", e))?;
-            
+            send_envelope(&peer_info.sender, &SignalingEnvelope::new(message));
             Ok(())
         } else {
             Err(format!("Peer {} not found", target))
         }
     }
-    
+
     /// Get list of all connected peers
     pub async fn get_connected_peers(&self) -> Vec<DeviceId> {
         let peers = self.peers.read().await;
         peers.keys().cloned().collect()
     }
-    
-    /// Unregister a peer
-    pub async fn unregister_peer(&self, device_id: &DeviceId) {
-        let mut peers = self.peers.write().await;
-        peers.remove(device_id);
+
+    /// End the session between `first` and `second`
+    ///
+    /// Both peers are told to close it, and requests still pending between
+    /// them fail. Returns the peers that were online to be told.
+    pub async fn terminate_session(&self, first: &DeviceId, second: &DeviceId, reason: &str) -> Vec<DeviceId> {
+        let peers = self.peers.read().await;
+        let mut notified = Vec::new();
+        for (device_id, peer) in [(first, second), (second, first)] {
+            if let Some(info) = peers.get(device_id) {
+                send_envelope(&info.sender, &SignalingEnvelope::new(SignalingMessage::SessionTerminated {
+                    peer: peer.clone(),
+                    reason: reason.to_string(),
+                }));
+                notified.push(device_id.clone());
+            }
+        }
+
+        let mut relays = self.relays.lock().await;
+        let between: Vec<_> = relays.iter()
+            .filter(|(_, relay)| {
+                (relay.requester == *first && relay.target == *second)
+                    || (relay.requester == *second && relay.target == *first)
+            })
+            .map(|(&id, _)| id)
+            .collect();
+        for id in between {
+            let relay = relays.remove(&id).expect("relay listed above");
+            if let (Some(requester), Some(request_id)) = (peers.get(&relay.requester), relay.request_id) {
+                send_envelope(&requester.sender, &SignalingEnvelope {
+                    id: None,
+                    reply_to: Some(request_id),
+                    message: SignalingMessage::error(SignalingErrorCode::Unauthorized, reason),
+                });
+            }
+        }
+
+        info!("Session between {} and {} terminated: {}", first, second, reason);
+        notified
     }
-    
+
+    /// Only pass TURN relay candidates between `first` and `second` until `until`
+    pub async fn require_relay(&self, first: &DeviceId, second: &DeviceId, until: chrono::DateTime<chrono::Utc>) {
+        let now = chrono::Utc::now();
+        let mut relay_only = self.relay_only.lock().await;
+        relay_only.retain(|_, expires| *expires > now);
+        let expires = relay_only.entry(peer_pair(first, second)).or_insert(until);
+        *expires = (*expires).max(until);
+        info!("🔒 Session between {} and {} is relay-only until {}", first, second, until);
+    }
+
+    /// Whether signaling between `first` and `second` is limited to relay candidates
+    async fn is_relay_only(&self, first: &DeviceId, second: &DeviceId) -> bool {
+        let now = chrono::Utc::now();
+        let mut relay_only = self.relay_only.lock().await;
+        relay_only.retain(|_, expires| *expires > now);
+        relay_only.contains_key(&peer_pair(first, second))
+    }
+
+    /// Apply the pair's relay policy to a message `from` sends to `to`
+    ///
+    /// Returns `None` for a candidate that mustn't be delivered.
+    async fn enforce_relay(&self, from: &DeviceId, to: &DeviceId, mut message: SignalingMessage) -> Option<SignalingMessage> {
+        if !self.is_relay_only(from, to).await {
+            return Some(message);
+        }
+        match &mut message {
+            SignalingMessage::Offer { sdp, .. } | SignalingMessage::Answer { sdp, .. } => {
+                *sdp = strip_non_relay_candidates(sdp);
+            }
+            // An empty candidate marks the end of gathering
+            SignalingMessage::IceCandidate { candidate, .. }
+                if !candidate.is_empty() && !is_relay_candidate(candidate) =>
+            {
+                debug!("Dropped non-relay candidate from {} to {}", from, to);
+                return None;
+            }
+            _ => {}
+        }
+        Some(message)
+    }
+
+    /// Token the admin endpoints require
+    pub fn admin_token(&self) -> Option<&str> {
+        self.config.admin_token.as_deref()
+    }
+
     /// Check if peer is online
     pub async fn is_peer_online(&self, device_id: &DeviceId) -> bool {
         let peers = self.peers.read().await;
         peers.contains_key(device_id)
     }
-}
 
-/// Handle incoming signaling messages and route them appropriately
-async fn handle_signaling_message(
-    message: SignalingMessage,
-    from_device: DeviceId,
-    peers: Arc<RwLock<HashMap<DeviceId, PeerInfo>>>,
-) {
-    match message {
-        SignalingMessage::Offer { to, sdp, .. } => {
-            info!("📤 Forwarding offer from {} to {}", from_device, to);
-            forward_message_to_peer(&to.clone(), SignalingMessage::Offer {
-                from: from_device,
-                to: to.clone(),
-                sdp,
-            }, &peers).await;
-        }
-        SignalingMessage::Answer { to, sdp, .. } => {
-            info!("📤 Forwarding answer from {} to {}", from_device, to);
-            forward_message_to_peer(&to.clone(), SignalingMessage::Answer {
-                from: from_device,
-                to: to.clone(),
-                sdp,
-            }, &peers).await;
-        }
-        SignalingMessage::IceCandidate { to, candidate, sdp_mid, sdp_m_line_index, .. } => {
-            debug!("📤 Forwarding ICE candidate from {} to {}", from_device, to);
-            forward_message_to_peer(&to.clone(), SignalingMessage::IceCandidate {
-                from: from_device,
-                to: to.clone(),
-                candidate,
-                sdp_mid,
-                sdp_m_line_index,
-            }, &peers).await;
-        }
-        SignalingMessage::ConnectionRequest { target, .. } => {
-            info!("🔗 Connection request from {} to {}", from_device, target);
-            forward_message_to_peer(&target.clone(), SignalingMessage::ConnectionRequest {
-                target: target.clone(),
-                from: from_device,
-            }, &peers).await;
-        }
-        SignalingMessage::ListPeers => {
-            // Send list of connected peers back to requester
-            let peer_list = peers.read().await.keys().cloned().collect::<Vec<_>>();
-            // Implementation would send this back to the requesting peer
-            info!("📋 Peer list requested by {}: {} peers online", from_device, peer_list.len());
-        }
-        _ => {
-            debug!("Received unhandled message type from {}", from_device);
-        }
-    }
-}
-
-/// Forward a message to a specific peer
-async fn forward_message_to_peer(
-    target: &DeviceId,
-    message: SignalingMessage,
-    peers: &Arc<RwLock<HashMap<DeviceId, PeerInfo>>>,
-) {
-    let peers_guard = peers.read().await;
-    
-    if let Some(peer_info) = peers_guard.get(target) {
-        let json = match serde_json::to_string(&message) {
-            Ok(j) => j,
+    async fn handle_text(&self, connection: &mut Connection, text: &str) {
+        let envelope = match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(value) => {
+                // Keep the id of malformed requests so the error can be correlated
+                let id = value.get("id").and_then(|id| id.as_u64());
+                match serde_json::from_value::<SignalingEnvelope>(value) {
+                    Ok(envelope) => envelope,
+                    Err(e) => {
+                        warn!("Malformed signaling message from {}: {}", connection.remote_addr, e);
+                        connection.send(&SignalingEnvelope {
+                            id: None,
+                            reply_to: id,
+                            message: SignalingMessage::error(SignalingErrorCode::BadRequest, e.to_string()),
+                        });
+                        return;
+                    }
+                }
+            }
             Err(e) => {
-                error!("Failed to serialize message: {}", e);
+                warn!("Invalid JSON from {}: {}", connection.remote_addr, e);
+                connection.send(&SignalingMessage::error(SignalingErrorCode::BadRequest, e.to_string()).into());
                 return;
             }
         };
-        
-        if let Err(e) = peer_info.sender.send(Message::Text(json)) {
-            error!("Failed to forward message to {}: {}", target, e);
+
+        match &envelope.message {
+            SignalingMessage::Register { device_id, device_name, proof } => {
+                if !connection.claimed.contains(device_id)
+                    && connection.claimed.len() >= self.config.max_device_ids_per_connection
+                {
+                    warn!("🚫 Refused registration of {} from {}: too many device IDs", device_id, connection.remote_addr);
+                    connection.reply(&envelope, SignalingMessage::error(
+                        SignalingErrorCode::RateLimited,
+                        "Too many device IDs registered on this connection",
+                    ));
+                    return;
+                }
+                if let Err((code, e)) = self.authenticate(connection, device_id, proof.as_ref()).await {
+                    warn!("🚫 Refused registration of {} from {}: {}", device_id, connection.remote_addr, e);
+                    connection.reply(&envelope, SignalingMessage::error(code, e));
+                    return;
+                }
+                if !connection.claimed.contains(device_id) {
+                    connection.claimed.push(device_id.clone());
+                }
+                self.register(connection, device_id.clone(), device_name.clone()).await;
+                connection.reply(&envelope, SignalingMessage::Ack);
+                return;
+            }
+            SignalingMessage::Ping => {
+                connection.reply(&envelope, SignalingMessage::Pong);
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(from) = connection.device_id.clone() else {
+            connection.reply(&envelope, SignalingMessage::error(SignalingErrorCode::NotRegistered, "Register before signaling"));
+            return;
+        };
+
+        if let Some(relay_id) = envelope.reply_to {
+            self.complete_relay(connection, &from, relay_id, envelope).await;
+            return;
+        }
+
+        match &envelope.message {
+            SignalingMessage::ListPeers => {
+                let peers = self.peers.read().await
+                    .values()
+                    .filter(|peer| peer.device_id != from)
+                    .map(describe_peer)
+                    .collect();
+                connection.reply(&envelope, SignalingMessage::PeerList { peers });
+            }
+            SignalingMessage::ConnectionRequest { target, .. } => {
+                let limited = self.rate_limiter.lock().await.check(connection.remote_addr.ip(), &target.0);
+                if let Err(limited) = limited {
+                    info!("🚫 Rate limited {} ({}) requesting {}", from, connection.remote_addr.ip(), target);
+                    connection.reply(&envelope, SignalingMessage::Error {
+                        code: SignalingErrorCode::RateLimited,
+                        message: format!("Too many connection requests per {}", limited.scope.as_str()),
+                        retry_after_secs: Some(limited.retry_after.as_secs().max(1)),
+                    });
+                    return;
+                }
+                info!("🔗 Connection request from {} to {}", from, target);
+                self.relay(connection, from, envelope).await;
+            }
+            SignalingMessage::Offer { .. } => {
+                info!("📤 Relaying offer from {}", from);
+                self.relay(connection, from, envelope).await;
+            }
+            SignalingMessage::Answer { .. } | SignalingMessage::IceCandidate { .. } => {
+                let Some(to) = envelope.message.recipient().cloned() else { return };
+                let message = with_sender(envelope.message.clone(), &from);
+                let Some(message) = self.enforce_relay(&from, &to, message).await else {
+                    connection.reply(&envelope, SignalingMessage::Ack);
+                    return;
+                };
+                let forwarded = SignalingEnvelope::new(message);
+                let delivered = match self.peers.read().await.get(&to) {
+                    Some(peer) => {
+                        send_envelope(&peer.sender, &forwarded);
+                        true
+                    }
+                    None => false,
+                };
+                if delivered {
+                    connection.reply(&envelope, SignalingMessage::Ack);
+                } else {
+                    connection.reply(&envelope, peer_not_found(&to));
+                }
+            }
+            _ => {
+                debug!("Unroutable message from {}: {:?}", from, envelope.message);
+                connection.reply(&envelope, SignalingMessage::error(
+                    SignalingErrorCode::BadRequest,
+                    "Message can't be sent to the server",
+                ));
+            }
+        }
+    }
+
+    /// Check that the connection holds the key `device_id` is pinned to,
+    /// pinning it on first registration
+    async fn authenticate(
+        &self,
+        connection: &Connection,
+        device_id: &DeviceId,
+        proof: Option<&RegisterProof>,
+    ) -> Result<(), (SignalingErrorCode, String)> {
+        let unauthorized = |message: String| (SignalingErrorCode::Unauthorized, message);
+        let proof = proof.ok_or_else(|| unauthorized("Register must prove ownership of the device ID".to_string()))?;
+        UnparsedPublicKey::new(&ED25519, &proof.public_key)
+            .verify(&register_proof_message(&connection.nonce, device_id), &proof.signature)
+            .map_err(|_| unauthorized("Invalid registration signature".to_string()))?;
+
+        let mut owners = self.owners.lock().await;
+        match owners.get(device_id) {
+            Some(owner) if *owner != proof.public_key[..] => {
+                Err(unauthorized(format!("{} is registered to another device key", device_id)))
+            }
+            Some(_) => Ok(()),
+            None => owners.pin(device_id, &proof.public_key).map_err(|e| {
+                error!("Failed to record the key of {}: {}", device_id, e);
+                (SignalingErrorCode::Internal, "Failed to record the device key".to_string())
+            }),
+        }
+    }
+
+    /// Bind `device_id` to this connection, replacing any stale socket
+    async fn register(&self, connection: &mut Connection, device_id: DeviceId, device_name: Option<String>) {
+        if let Some(previous) = connection.device_id.take() {
+            if previous != device_id {
+                self.unregister(&previous, connection.id).await;
+            }
+        }
+
+        let peer = PeerInfo {
+            device_id: device_id.clone(),
+            device_name,
+            sender: connection.sender.clone(),
+            connected_at: chrono::Utc::now(),
+            connection: connection.id,
+        };
+        let joined = SignalingEnvelope::new(SignalingMessage::PeerJoined { peer: describe_peer(&peer) });
+
+        let mut peers = self.peers.write().await;
+        if peers.insert(device_id.clone(), peer).is_some() {
+            info!("Peer {} re-registered from {}", device_id, connection.remote_addr);
         } else {
-            debug!("✅ Message forwarded to {}", target);
+            info!("Peer {} registered from {}", device_id, connection.remote_addr);
+        }
+        for other in peers.values().filter(|other| other.device_id != device_id) {
+            send_envelope(&other.sender, &joined);
+        }
+        drop(peers);
+
+        connection.device_id = Some(device_id);
+    }
+
+    /// Remove a peer unless it has re-registered on another socket since
+    async fn unregister(&self, device_id: &DeviceId, connection: u64) {
+        let mut peers = self.peers.write().await;
+        if peers.get(device_id).is_none_or(|peer| peer.connection != connection) {
+            return;
+        }
+        peers.remove(device_id);
+
+        let left = SignalingEnvelope::new(SignalingMessage::PeerLeft { device_id: device_id.clone() });
+        for other in peers.values() {
+            send_envelope(&other.sender, &left);
+        }
+
+        // Requests the peer will never answer fail now instead of timing out
+        let mut relays = self.relays.lock().await;
+        let orphaned: Vec<_> = relays.iter()
+            .filter(|(_, relay)| relay.requester == *device_id || relay.target == *device_id)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in orphaned {
+            let relay = relays.remove(&id).expect("relay listed above");
+            if relay.target == *device_id {
+                if let (Some(requester), Some(request_id)) = (peers.get(&relay.requester), relay.request_id) {
+                    send_envelope(&requester.sender, &SignalingEnvelope {
+                        id: None,
+                        reply_to: Some(request_id),
+                        message: SignalingMessage::error(SignalingErrorCode::PeerNotFound, format!("Peer {} disconnected", device_id)),
+                    });
+                }
+            }
+        }
+
+        info!("Peer {} removed from registry", device_id);
+    }
+
+    /// Forward a request the target has to answer
+    async fn relay(&self, connection: &Connection, from: DeviceId, envelope: SignalingEnvelope) {
+        let Some(target) = envelope.message.recipient().cloned() else { return };
+        let message = with_sender(envelope.message.clone(), &from);
+        let Some(message) = self.enforce_relay(&from, &target, message).await else {
+            connection.reply(&envelope, SignalingMessage::Ack);
+            return;
+        };
+        let relay_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let forwarded = SignalingEnvelope::request(relay_id, message);
+
+        // Hold the peer lock while recording the relay so an unregister can't slip in between
+        let peers = self.peers.read().await;
+        let Some(peer) = peers.get(&target) else {
+            connection.reply(&envelope, peer_not_found(&target));
+            return;
+        };
+        self.relays.lock().await.insert(relay_id, PendingRelay {
+            requester: from,
+            request_id: envelope.id,
+            target: target.clone(),
+        });
+        send_envelope(&peer.sender, &forwarded);
+        drop(peers);
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(manager.config.relay_timeout).await;
+            manager.expire_relay(relay_id).await;
+        });
+    }
+
+    /// Route a peer's reply to a relayed request back to the requester
+    async fn complete_relay(&self, connection: &Connection, from: &DeviceId, relay_id: MessageId, envelope: SignalingEnvelope) {
+        let relay = {
+            let mut relays = self.relays.lock().await;
+            match relays.get(&relay_id) {
+                Some(relay) if relay.target == *from => relays.remove(&relay_id),
+                _ => None,
+            }
+        };
+        let Some(relay) = relay else {
+            debug!("Reply from {} to unknown or expired request {}", from, relay_id);
+            connection.reply(&envelope, SignalingMessage::error(SignalingErrorCode::BadRequest, "No such request"));
+            return;
+        };
+
+        let message = with_sender(envelope.message.clone(), from);
+        let message = self.enforce_relay(from, &relay.requester, message).await;
+        if let (Some(message), Some(requester)) = (message, self.peers.read().await.get(&relay.requester)) {
+            send_envelope(&requester.sender, &SignalingEnvelope {
+                id: None,
+                reply_to: relay.request_id,
+                message,
+            });
+        }
+        connection.reply(&envelope, SignalingMessage::Ack);
+    }
+
+    async fn expire_relay(&self, relay_id: MessageId) {
+        let Some(relay) = self.relays.lock().await.remove(&relay_id) else { return };
+        warn!("⏱️ {} didn't answer request {} from {}", relay.target, relay_id, relay.requester);
+
+        let Some(request_id) = relay.request_id else { return };
+        if let Some(requester) = self.peers.read().await.get(&relay.requester) {
+            send_envelope(&requester.sender, &SignalingEnvelope {
+                id: None,
+                reply_to: Some(request_id),
+                message: SignalingMessage::error(SignalingErrorCode::Timeout, format!("{} didn't answer", relay.target)),
+            });
         }
-    } else {
-        warn!("⚠️ Target peer {} not found", target);
     }
 }
 
@@ -213,3 +616,66 @@ impl Default for PeerManager {
         Self::new()
     }
 }
+
+fn send_envelope(sender: &mpsc::UnboundedSender<Message>, envelope: &SignalingEnvelope) {
+    match serde_json::to_string(envelope) {
+        Ok(json) => {
+            // A closed channel means the peer is going away; its reader cleans up
+            let _ = sender.send(Message::Text(json));
+        }
+        Err(e) => error!("Failed to serialize message: {}", e),
+    }
+}
+
+fn peer_not_found(device_id: &DeviceId) -> SignalingMessage {
+    SignalingMessage::error(SignalingErrorCode::PeerNotFound, format!("Peer {} not found", device_id))
+}
+
+fn describe_peer(peer: &PeerInfo) -> ProtocolPeerInfo {
+    ProtocolPeerInfo {
+        device_id: peer.device_id.clone(),
+        device_name: peer.device_name.clone().unwrap_or_else(|| peer.device_id.to_string()),
+        device_type: DeviceType::Unknown,
+        online: true,
+        last_seen: None,
+    }
+}
+
+/// Stamp the registered sender on a forwarded message so peers can't spoof it
+fn with_sender(mut message: SignalingMessage, sender: &DeviceId) -> SignalingMessage {
+    match &mut message {
+        SignalingMessage::Offer { from, .. }
+        | SignalingMessage::Answer { from, .. }
+        | SignalingMessage::IceCandidate { from, .. }
+        | SignalingMessage::ConnectionRequest { from, .. }
+        | SignalingMessage::ConnectionAccepted { from, .. }
+        | SignalingMessage::ConnectionRejected { from, .. } => *from = sender.clone(),
+        _ => {}
+    }
+    message
+}
+
+/// Key of a peer pair, independent of which side is asking
+fn peer_pair(first: &DeviceId, second: &DeviceId) -> PeerPair {
+    if first.0 <= second.0 {
+        (first.clone(), second.clone())
+    } else {
+        (second.clone(), first.clone())
+    }
+}
+
+/// Whether an ICE candidate line is a TURN relay candidate (`... typ relay ...`)
+fn is_relay_candidate(candidate: &str) -> bool {
+    let mut fields = candidate.split_whitespace();
+    fields.any(|field| field == "typ") && fields.next() == Some("relay")
+}
+
+/// Remove every candidate but relay candidates from an SDP
+fn strip_non_relay_candidates(sdp: &str) -> String {
+    sdp.split_inclusive('\n')
+        .filter(|line| match line.trim_end().strip_prefix("a=") {
+            Some(attribute) if attribute.starts_with("candidate:") => is_relay_candidate(attribute),
+            _ => true,
+        })
+        .collect()
+}
@@ -0,0 +1,215 @@
+// Connection request rate limiting
+//
+// Connection IDs are 9 digits, so without limits a client could walk the ID
+// space or hammer a single host with guesses. Requests are counted in a
+// sliding window both per target connection ID and per source address.
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Rate limit configuration
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Connection requests one source may send per window
+    pub per_source_limit: usize,
+    /// Connection requests one target may receive per window
+    pub per_target_limit: usize,
+    /// Sliding window length
+    pub window: Duration,
+    /// Number of tracked keys before stale entries are pruned
+    pub prune_threshold: usize,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_source_limit: 20,
+            per_target_limit: 10,
+            window: Duration::from_secs(60),
+            prune_threshold: 10_000,
+        }
+    }
+}
+
+/// Which limit rejected a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitScope {
+    Source,
+    Target,
+}
+
+impl LimitScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitScope::Source => "source",
+            LimitScope::Target => "target",
+        }
+    }
+}
+
+/// A rejected request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    pub scope: LimitScope,
+    pub retry_after: Duration,
+}
+
+/// Sliding-window limiter for connection requests
+pub struct ConnectRateLimiter {
+    config: RateLimitConfig,
+    per_source: HashMap<IpAddr, VecDeque<Instant>>,
+    per_target: HashMap<String, VecDeque<Instant>>,
+}
+
+impl ConnectRateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            per_source: HashMap::new(),
+            per_target: HashMap::new(),
+        }
+    }
+
+    /// Check and record a connection request from `source` to `target`
+    pub fn check(&mut self, source: IpAddr, target: &str) -> Result<(), RateLimited> {
+        self.check_at(source, target, Instant::now())
+    }
+
+    /// Check and record a connection request at `now`
+    pub fn check_at(&mut self, source: IpAddr, target: &str, now: Instant) -> Result<(), RateLimited> {
+        if self.per_source.len() + self.per_target.len() > self.config.prune_threshold {
+            self.prune(now);
+        }
+
+        let window = self.config.window;
+        let target = normalize_connection_id(target);
+
+        let source_hits = self.per_source.entry(source).or_default();
+        evict(source_hits, now, window);
+        if source_hits.len() >= self.config.per_source_limit {
+            return Err(RateLimited {
+                scope: LimitScope::Source,
+                retry_after: retry_after(source_hits, now, window),
+            });
+        }
+
+        let target_hits = self.per_target.entry(target).or_default();
+        evict(target_hits, now, window);
+        if target_hits.len() >= self.config.per_target_limit {
+            // Rejected requests still count against the source
+            source_hits.push_back(now);
+            return Err(RateLimited {
+                scope: LimitScope::Target,
+                retry_after: retry_after(target_hits, now, window),
+            });
+        }
+
+        target_hits.push_back(now);
+        source_hits.push_back(now);
+        Ok(())
+    }
+
+    /// Drop keys with no requests in the current window
+    pub fn prune(&mut self, now: Instant) {
+        let window = self.config.window;
+        self.per_source.retain(|_, hits| {
+            evict(hits, now, window);
+            !hits.is_empty()
+        });
+        self.per_target.retain(|_, hits| {
+            evict(hits, now, window);
+            !hits.is_empty()
+        });
+    }
+}
+
+impl Default for ConnectRateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+fn evict(hits: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while let Some(&oldest) = hits.front() {
+        if now.duration_since(oldest) >= window {
+            hits.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+fn retry_after(hits: &VecDeque<Instant>, now: Instant, window: Duration) -> Duration {
+    hits.front()
+        .map(|&oldest| window.saturating_sub(now.duration_since(oldest)))
+        .unwrap_or(window)
+}
+
+/// Strip separators so "123-456-789" and "123456789" share a bucket
+pub fn normalize_connection_id(id: &str) -> String {
+    let digits: String = id.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() == 9 {
+        digits
+    } else {
+        id.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    #[test]
+    fn test_per_target_limit_across_sources() {
+        let mut limiter = ConnectRateLimiter::default();
+        let now = Instant::now();
+
+        for i in 0..10 {
+            assert!(limiter.check_at(ip(i), "123-456-789", now).is_ok());
+        }
+        // Same ID in another format hits the same bucket
+        let rejected = limiter.check_at(ip(99), "123456789", now).unwrap_err();
+        assert_eq!(rejected.scope, LimitScope::Target);
+        assert_eq!(rejected.retry_after, Duration::from_secs(60));
+
+        assert!(limiter.check_at(ip(99), "123456789", now + Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn test_connection_ids_cannot_be_enumerated() {
+        let mut limiter = ConnectRateLimiter::default();
+        let start = Instant::now();
+        let mut admitted = 0u64;
+
+        // One probe per second for a full day, walking the ID space
+        for second in 0..86_400u64 {
+            let target = format!("{:09}", second);
+            if limiter.check_at(ip(1), &target, start + Duration::from_secs(second)).is_ok() {
+                admitted += 1;
+            }
+        }
+
+        // 20 per minute at most: under 0.003% of the 9-digit space per day
+        assert!(admitted <= 20 * 1440);
+        assert!((admitted as f64) / 1e9 < 3e-5);
+    }
+
+    #[test]
+    fn test_prune_bounds_memory() {
+        let mut limiter = ConnectRateLimiter::new(RateLimitConfig {
+            prune_threshold: 100,
+            ..RateLimitConfig::default()
+        });
+        let now = Instant::now();
+        for i in 0..200u32 {
+            let _ = limiter.check_at(ip((i % 250) as u8), &format!("{:09}", i), now);
+        }
+        limiter.prune(now + Duration::from_secs(61));
+        assert!(limiter.per_source.is_empty() && limiter.per_target.is_empty());
+    }
+}
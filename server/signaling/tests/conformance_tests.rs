@@ -0,0 +1,582 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use genxlink_protocol::{
+    register_proof_message, DeviceId, MessageId, RegisterProof, SignalingEnvelope, SignalingErrorCode,
+    SignalingMessage,
+};
+use genxlink_signaling_server::rate_limit::RateLimitConfig;
+use genxlink_signaling_server::{KeyPins, PeerManager, SignalingConfig};
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn start_server(config: SignalingConfig) -> SocketAddr {
+    serve(PeerManager::with_config(config)).await
+}
+
+async fn serve(peers: PeerManager) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(genxlink_signaling_server::serve(listener, peers));
+    address
+}
+
+fn generate_key() -> Arc<Ed25519KeyPair> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    Arc::new(Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap())
+}
+
+/// A raw WebSocket peer speaking the wire protocol
+struct Peer {
+    device_id: DeviceId,
+    key: Arc<Ed25519KeyPair>,
+    /// The server's challenge for this socket
+    nonce: Vec<u8>,
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Peer {
+    async fn connect(address: SocketAddr) -> Self {
+        Self::connect_as(address, DeviceId::new(), generate_key()).await
+    }
+
+    /// Connect with a given identity and take the server's challenge
+    async fn connect_as(address: SocketAddr, device_id: DeviceId, key: Arc<Ed25519KeyPair>) -> Self {
+        let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        let mut peer = Self { device_id, key, nonce: Vec::new(), ws };
+        match peer.recv().await.message {
+            SignalingMessage::Challenge { nonce } => peer.nonce = nonce,
+            other => panic!("expected a challenge, got {:?}", other),
+        }
+        peer
+    }
+
+    /// Connect and register, skipping the registration's ack
+    async fn register(address: SocketAddr) -> Self {
+        let mut peer = Self::connect(address).await;
+        peer.send(peer.register_request(0, &peer.nonce)).await;
+        let ack = peer.reply_to(0).await;
+        assert!(matches!(ack.message, SignalingMessage::Ack), "{:?}", ack);
+        peer
+    }
+
+    /// Register our device ID, signing `nonce` with our key
+    fn register_request(&self, id: MessageId, nonce: &[u8]) -> SignalingEnvelope {
+        let signature = self.key.sign(&register_proof_message(nonce, &self.device_id));
+        SignalingEnvelope::request(id, SignalingMessage::Register {
+            device_id: self.device_id.clone(),
+            device_name: None,
+            proof: Some(RegisterProof {
+                public_key: self.key.public_key().as_ref().to_vec(),
+                signature: signature.as_ref().to_vec(),
+            }),
+        })
+    }
+
+    async fn send(&mut self, envelope: SignalingEnvelope) {
+        self.send_text(serde_json::to_string(&envelope).unwrap()).await;
+    }
+
+    async fn send_text(&mut self, text: String) {
+        self.ws.send(Message::Text(text)).await.unwrap();
+    }
+
+    async fn recv(&mut self) -> SignalingEnvelope {
+        loop {
+            let frame = tokio::time::timeout(TIMEOUT, self.ws.next())
+                .await
+                .expect("timed out waiting for a message")
+                .expect("connection closed")
+                .unwrap();
+            if let Message::Text(text) = frame {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    /// Next message that isn't a peer presence notification
+    async fn recv_routed(&mut self) -> SignalingEnvelope {
+        loop {
+            let envelope = self.recv().await;
+            if !matches!(envelope.message, SignalingMessage::PeerJoined { .. } | SignalingMessage::PeerLeft { .. }) {
+                return envelope;
+            }
+        }
+    }
+
+    async fn reply_to(&mut self, id: MessageId) -> SignalingEnvelope {
+        let envelope = self.recv_routed().await;
+        assert_eq!(envelope.reply_to, Some(id), "{:?}", envelope);
+        envelope
+    }
+
+    fn connection_request(&self, id: MessageId, target: &DeviceId) -> SignalingEnvelope {
+        SignalingEnvelope::request(id, SignalingMessage::ConnectionRequest {
+            target: target.clone(),
+            from: self.device_id.clone(),
+            resume_token: None,
+        })
+    }
+}
+
+fn error_code(envelope: &SignalingEnvelope) -> SignalingErrorCode {
+    match envelope.message {
+        SignalingMessage::Error { code, .. } => code,
+        ref other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_register_and_ping_are_acknowledged() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut peer = Peer::register(address).await;
+
+    peer.send(SignalingEnvelope::request(1, SignalingMessage::Ping)).await;
+    assert!(matches!(peer.reply_to(1).await.message, SignalingMessage::Pong));
+
+    // Notifications get no reply
+    peer.send(SignalingEnvelope::new(SignalingMessage::Ping)).await;
+    peer.send(SignalingEnvelope::request(2, SignalingMessage::Ping)).await;
+    assert!(matches!(peer.reply_to(2).await.message, SignalingMessage::Pong));
+}
+
+#[tokio::test]
+async fn test_requests_before_register_are_rejected() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut peer = Peer::connect(address).await;
+
+    peer.send(SignalingEnvelope::request(1, SignalingMessage::ListPeers)).await;
+    assert_eq!(error_code(&peer.reply_to(1).await), SignalingErrorCode::NotRegistered);
+}
+
+#[tokio::test]
+async fn test_register_requires_a_signed_challenge() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut peer = Peer::connect(address).await;
+
+    peer.send(SignalingEnvelope::request(1, SignalingMessage::Register {
+        device_id: peer.device_id.clone(),
+        device_name: None,
+        proof: None,
+    })).await;
+    assert_eq!(error_code(&peer.reply_to(1).await), SignalingErrorCode::Unauthorized);
+
+    // A registration signed for another socket's challenge can't be replayed here
+    let other = Peer::connect(address).await;
+    peer.send(peer.register_request(2, &other.nonce)).await;
+    assert_eq!(error_code(&peer.reply_to(2).await), SignalingErrorCode::Unauthorized);
+
+    peer.send(SignalingEnvelope::request(3, SignalingMessage::ListPeers)).await;
+    assert_eq!(error_code(&peer.reply_to(3).await), SignalingErrorCode::NotRegistered);
+
+    peer.send(peer.register_request(4, &peer.nonce.clone())).await;
+    assert!(matches!(peer.reply_to(4).await.message, SignalingMessage::Ack));
+}
+
+#[tokio::test]
+async fn test_registered_id_cannot_be_taken_over() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut owner = Peer::register(address).await;
+    let mut controller = Peer::register(address).await;
+
+    // A second socket claims the owner's live ID with its own key
+    let mut impostor = Peer::connect_as(address, owner.device_id.clone(), generate_key()).await;
+    impostor.send(impostor.register_request(1, &impostor.nonce)).await;
+    assert_eq!(error_code(&impostor.reply_to(1).await), SignalingErrorCode::Unauthorized);
+
+    // Traffic for the ID still reaches the owner
+    controller.send(controller.connection_request(1, &owner.device_id)).await;
+    let relayed = owner.recv_routed().await;
+    assert!(matches!(relayed.message, SignalingMessage::ConnectionRequest { .. }), "{:?}", relayed);
+
+    // Nor can it claim the ID while the owner is away
+    let key = owner.key.clone();
+    drop(owner);
+    impostor.send(impostor.register_request(2, &impostor.nonce)).await;
+    assert_eq!(error_code(&impostor.reply_to(2).await), SignalingErrorCode::Unauthorized);
+
+    // The owner's key gets it back from a new socket
+    let mut owner = Peer::connect_as(address, impostor.device_id.clone(), key).await;
+    owner.send(owner.register_request(1, &owner.nonce)).await;
+    assert!(matches!(owner.reply_to(1).await.message, SignalingMessage::Ack));
+}
+
+#[tokio::test]
+async fn test_device_ids_stay_pinned_across_restarts() {
+    let path = std::env::temp_dir().join(format!("genxlink-pins-{}.jsonl", uuid::Uuid::new_v4()));
+    let first = serve(PeerManager::with_key_pins(SignalingConfig::default(), KeyPins::open(&path).unwrap())).await;
+    let owner = Peer::register(first).await;
+
+    // A restarted server still knows whose ID it is
+    let restarted = serve(PeerManager::with_key_pins(SignalingConfig::default(), KeyPins::open(&path).unwrap())).await;
+    let mut impostor = Peer::connect_as(restarted, owner.device_id.clone(), generate_key()).await;
+    impostor.send(impostor.register_request(1, &impostor.nonce)).await;
+    assert_eq!(error_code(&impostor.reply_to(1).await), SignalingErrorCode::Unauthorized);
+
+    let mut owner = Peer::connect_as(restarted, owner.device_id.clone(), owner.key.clone()).await;
+    owner.send(owner.register_request(1, &owner.nonce)).await;
+    assert!(matches!(owner.reply_to(1).await.message, SignalingMessage::Ack));
+    assert_eq!(KeyPins::open(&path).unwrap().len(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_device_ids_per_connection_are_capped() {
+    let address = start_server(SignalingConfig {
+        max_device_ids_per_connection: 2,
+        ..SignalingConfig::default()
+    }).await;
+    let mut peer = Peer::register(address).await;
+    let first = peer.device_id.clone();
+
+    peer.device_id = DeviceId::new();
+    peer.send(peer.register_request(1, &peer.nonce)).await;
+    assert!(matches!(peer.reply_to(1).await.message, SignalingMessage::Ack));
+
+    peer.device_id = DeviceId::new();
+    peer.send(peer.register_request(2, &peer.nonce)).await;
+    assert_eq!(error_code(&peer.reply_to(2).await), SignalingErrorCode::RateLimited);
+
+    // IDs the connection already holds can be registered again
+    peer.device_id = first;
+    peer.send(peer.register_request(3, &peer.nonce)).await;
+    assert!(matches!(peer.reply_to(3).await.message, SignalingMessage::Ack));
+}
+
+#[tokio::test]
+async fn test_malformed_requests_are_correlated() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut peer = Peer::register(address).await;
+
+    peer.send_text(r#"{"type":"NoSuchMessage","id":9}"#.to_string()).await;
+    assert_eq!(error_code(&peer.reply_to(9).await), SignalingErrorCode::BadRequest);
+
+    // Replies can't be sent to the server
+    peer.send(SignalingEnvelope::request(10, SignalingMessage::PeerList { peers: Vec::new() })).await;
+    assert_eq!(error_code(&peer.reply_to(10).await), SignalingErrorCode::BadRequest);
+}
+
+#[tokio::test]
+async fn test_peer_list_and_presence() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut first = Peer::register(address).await;
+    let second = Peer::register(address).await;
+
+    match first.recv().await.message {
+        SignalingMessage::PeerJoined { peer } => assert_eq!(peer.device_id, second.device_id),
+        other => panic!("expected a join notification, got {:?}", other),
+    }
+
+    first.send(SignalingEnvelope::request(1, SignalingMessage::ListPeers)).await;
+    match first.reply_to(1).await.message {
+        SignalingMessage::PeerList { peers } => {
+            assert_eq!(peers.len(), 1);
+            assert_eq!(peers[0].device_id, second.device_id);
+        }
+        other => panic!("expected the peer list, got {:?}", other),
+    }
+
+    drop(second);
+    match first.recv().await.message {
+        SignalingMessage::PeerLeft { device_id } => assert_ne!(device_id, first.device_id),
+        other => panic!("expected a leave notification, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_connection_request_is_correlated_with_its_acceptance() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+
+    // The sender is stamped by the server, whatever the client claims
+    let mut request = controller.connection_request(1, &host.device_id);
+    if let SignalingMessage::ConnectionRequest { from, .. } = &mut request.message {
+        *from = DeviceId::new();
+    }
+    controller.send(request).await;
+
+    let relayed = host.recv_routed().await;
+    let relay_id = relayed.id.expect("relayed requests carry the server's id");
+    match &relayed.message {
+        SignalingMessage::ConnectionRequest { from, .. } => assert_eq!(*from, controller.device_id),
+        other => panic!("expected the connection request, got {:?}", other),
+    }
+
+    let session_id = genxlink_protocol::SessionId::new();
+    host.send(relayed.reply(SignalingMessage::ConnectionAccepted {
+        session_id,
+        from: host.device_id.clone(),
+        resume_token: None,
+    })).await;
+
+    let accepted = controller.reply_to(1).await;
+    match accepted.message {
+        SignalingMessage::ConnectionAccepted { session_id: accepted_id, from, .. } => {
+            assert_eq!(accepted_id, session_id);
+            assert_eq!(from, host.device_id);
+        }
+        other => panic!("expected the acceptance, got {:?}", other),
+    }
+
+    // Each relayed request is answered once
+    host.send(SignalingEnvelope {
+        id: Some(5),
+        reply_to: Some(relay_id),
+        message: SignalingMessage::ConnectionRejected { reason: "late".to_string(), from: host.device_id.clone() },
+    }).await;
+    assert_eq!(error_code(&host.reply_to(5).await), SignalingErrorCode::BadRequest);
+}
+
+#[tokio::test]
+async fn test_concurrent_requests_are_multiplexed() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+
+    for (id, sdp) in [(1, "first"), (2, "second")] {
+        controller.send(SignalingEnvelope::request(id, SignalingMessage::Offer {
+            sdp: sdp.to_string(),
+            from: controller.device_id.clone(),
+            to: host.device_id.clone(),
+        })).await;
+    }
+    controller.send(SignalingEnvelope::request(3, SignalingMessage::ListPeers)).await;
+
+    // The server answers its own request while both offers are outstanding
+    assert!(matches!(controller.reply_to(3).await.message, SignalingMessage::PeerList { .. }));
+
+    // The host answers out of order
+    let first = host.recv_routed().await;
+    let second = host.recv_routed().await;
+    for offer in [second, first] {
+        let SignalingMessage::Offer { sdp, .. } = &offer.message else {
+            panic!("expected an offer, got {:?}", offer);
+        };
+        host.send(offer.reply(SignalingMessage::Answer {
+            sdp: format!("answer to {}", sdp),
+            from: host.device_id.clone(),
+            to: controller.device_id.clone(),
+        })).await;
+    }
+
+    for (expected_id, expected_sdp) in [(2, "answer to second"), (1, "answer to first")] {
+        let answer = controller.recv_routed().await;
+        assert_eq!(answer.reply_to, Some(expected_id));
+        match answer.message {
+            SignalingMessage::Answer { sdp, .. } => assert_eq!(sdp, expected_sdp),
+            other => panic!("expected an answer, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_ice_candidates_are_delivered_and_acknowledged() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+
+    controller.send(SignalingEnvelope::request(1, SignalingMessage::IceCandidate {
+        candidate: "candidate:1 1 udp 2122260223 10.0.0.2 50000 typ host".to_string(),
+        sdp_mid: Some("0".to_string()),
+        sdp_m_line_index: Some(0),
+        from: controller.device_id.clone(),
+        to: host.device_id.clone(),
+    })).await;
+    assert!(matches!(controller.reply_to(1).await.message, SignalingMessage::Ack));
+
+    let delivered = host.recv_routed().await;
+    assert!(delivered.id.is_none() && delivered.reply_to.is_none());
+    assert!(matches!(delivered.message, SignalingMessage::IceCandidate { .. }));
+}
+
+#[tokio::test]
+async fn test_unknown_peer_is_reported() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut controller = Peer::register(address).await;
+
+    controller.send(controller.connection_request(1, &DeviceId::new())).await;
+    assert_eq!(error_code(&controller.reply_to(1).await), SignalingErrorCode::PeerNotFound);
+}
+
+#[tokio::test]
+async fn test_unanswered_request_times_out() {
+    let address = start_server(SignalingConfig {
+        relay_timeout: Duration::from_millis(200),
+        ..SignalingConfig::default()
+    }).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+
+    controller.send(controller.connection_request(1, &host.device_id)).await;
+    let relayed = host.recv_routed().await;
+    assert_eq!(error_code(&controller.reply_to(1).await), SignalingErrorCode::Timeout);
+
+    // A reply after the timeout goes nowhere
+    host.send(SignalingEnvelope {
+        id: Some(2),
+        reply_to: relayed.id,
+        message: SignalingMessage::ConnectionRejected { reason: "too late".to_string(), from: host.device_id.clone() },
+    }).await;
+    assert_eq!(error_code(&host.reply_to(2).await), SignalingErrorCode::BadRequest);
+}
+
+#[tokio::test]
+async fn test_target_leaving_fails_pending_requests() {
+    let address = start_server(SignalingConfig::default()).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+
+    controller.send(controller.connection_request(1, &host.device_id)).await;
+    host.recv_routed().await;
+    drop(host);
+
+    assert_eq!(error_code(&controller.reply_to(1).await), SignalingErrorCode::PeerNotFound);
+}
+
+#[tokio::test]
+async fn test_connection_requests_are_rate_limited() {
+    let address = start_server(SignalingConfig {
+        rate_limit: RateLimitConfig {
+            per_target_limit: 2,
+            ..RateLimitConfig::default()
+        },
+        ..SignalingConfig::default()
+    }).await;
+    let mut controller = Peer::register(address).await;
+    let target = DeviceId::from_string("123-456-789".to_string());
+
+    for id in 1..=2 {
+        controller.send(controller.connection_request(id, &target)).await;
+        assert_eq!(error_code(&controller.reply_to(id).await), SignalingErrorCode::PeerNotFound);
+    }
+
+    controller.send(controller.connection_request(3, &target)).await;
+    match controller.reply_to(3).await.message {
+        SignalingMessage::Error { code, retry_after_secs, .. } => {
+            assert_eq!(code, SignalingErrorCode::RateLimited);
+            assert!(matches!(retry_after_secs, Some(59..=60)), "{:?}", retry_after_secs);
+        }
+        other => panic!("expected an error, got {:?}", other),
+    }
+}
+
+/// POST a JSON body over plain HTTP/1.1, returning the status code and body
+async fn post_json(address: SocketAddr, path: &str, token: Option<&str>, body: &str) -> (u16, String) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let authorization = token.map(|token| format!("Authorization: Bearer {}\r\n", token)).unwrap_or_default();
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, address, authorization, body.len(), body
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(TIMEOUT, stream.read_to_string(&mut response)).await.unwrap().unwrap();
+
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_admin_terminates_sessions() {
+    let address = start_server(SignalingConfig {
+        admin_token: Some("admin-secret".to_string()),
+        ..SignalingConfig::default()
+    }).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+    let body = serde_json::json!({
+        "peers": [controller.device_id, host.device_id],
+        "reason": "Terminated by an administrator",
+    }).to_string();
+
+    // A request still waiting for the host when the session ends
+    controller.send(controller.connection_request(1, &host.device_id)).await;
+    host.recv_routed().await;
+
+    assert_eq!(post_json(address, "/admin/sessions/terminate", None, &body).await.0, 401);
+    assert_eq!(post_json(address, "/admin/sessions/terminate", Some("wrong"), &body).await.0, 401);
+
+    let (status, reply) = post_json(address, "/admin/sessions/terminate", Some("admin-secret"), &body).await;
+    assert_eq!(status, 200);
+    assert_eq!(reply.matches(&controller.device_id.0).count() + reply.matches(&host.device_id.0).count(), 2);
+
+    // Each side is told which session ended
+    let (controller_id, host_id) = (controller.device_id.clone(), host.device_id.clone());
+    for (peer, other) in [(&mut controller, host_id), (&mut host, controller_id)] {
+        match peer.recv_routed().await.message {
+            SignalingMessage::SessionTerminated { peer, reason } => {
+                assert_eq!(peer, other);
+                assert_eq!(reason, "Terminated by an administrator");
+            }
+            other => panic!("expected the session to be terminated, got {:?}", other),
+        }
+    }
+    assert_eq!(error_code(&controller.reply_to(1).await), SignalingErrorCode::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_admin_endpoints_disabled_without_token() {
+    let address = start_server(SignalingConfig::default()).await;
+    let body = serde_json::json!({ "peers": ["a", "b"], "reason": "test", "until": chrono::Utc::now() }).to_string();
+    assert_eq!(post_json(address, "/admin/sessions/terminate", Some(""), &body).await.0, 404);
+    assert_eq!(post_json(address, "/admin/sessions/relay-only", Some(""), &body).await.0, 404);
+}
+
+#[tokio::test]
+async fn test_relay_only_sessions_only_exchange_relay_candidates() {
+    let address = start_server(SignalingConfig {
+        admin_token: Some("admin-secret".to_string()),
+        ..SignalingConfig::default()
+    }).await;
+    let mut controller = Peer::register(address).await;
+    let mut host = Peer::register(address).await;
+    let body = serde_json::json!({
+        "peers": [host.device_id, controller.device_id],
+        "until": chrono::Utc::now() + chrono::Duration::hours(1),
+    }).to_string();
+    assert_eq!(post_json(address, "/admin/sessions/relay-only", Some("wrong"), &body).await.0, 401);
+    assert_eq!(post_json(address, "/admin/sessions/relay-only", Some("admin-secret"), &body).await.0, 204);
+
+    // Host candidates are acknowledged but never reach the peer
+    let relay = "candidate:2 1 udp 41885439 203.0.113.7 3478 typ relay raddr 0.0.0.0 rport 0";
+    for (id, candidate) in [(1, "candidate:1 1 udp 2122260223 10.0.0.2 50000 typ host"), (2, relay)] {
+        controller.send(SignalingEnvelope::request(id, SignalingMessage::IceCandidate {
+            candidate: candidate.to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_m_line_index: Some(0),
+            from: controller.device_id.clone(),
+            to: host.device_id.clone(),
+        })).await;
+        assert!(matches!(controller.reply_to(id).await.message, SignalingMessage::Ack));
+    }
+    match host.recv_routed().await.message {
+        SignalingMessage::IceCandidate { candidate, .. } => assert_eq!(candidate, relay),
+        other => panic!("expected the relay candidate, got {:?}", other),
+    }
+
+    // Offers lose every candidate line but the relay ones
+    let sdp = format!(
+        "v=0\r\na=candidate:1 1 udp 2122260223 10.0.0.2 50000 typ host\r\na={}\r\na=candidate:3 1 udp 1686052607 198.51.100.4 50000 typ srflx raddr 10.0.0.2 rport 50000\r\n",
+        relay
+    );
+    controller.send(SignalingEnvelope::request(3, SignalingMessage::Offer {
+        sdp,
+        from: controller.device_id.clone(),
+        to: host.device_id.clone(),
+    })).await;
+    match host.recv_routed().await.message {
+        SignalingMessage::Offer { sdp, .. } => assert_eq!(sdp, format!("v=0\r\na={}\r\n", relay)),
+        other => panic!("expected an offer, got {:?}", other),
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Point in normalized monitor coordinates (0.0..=1.0 on both axes)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl NormalizedPoint {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self {
+            x: x.clamp(0.0, 1.0),
+            y: y.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Annotation color (RGBA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnnotationColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl AnnotationColor {
+    pub const RED: Self = Self { r: 255, g: 0, b: 0, a: 255 };
+    pub const YELLOW: Self = Self { r: 255, g: 220, b: 0, a: 255 };
+    pub const GREEN: Self = Self { r: 0, g: 200, b: 0, a: 255 };
+    pub const BLUE: Self = Self { r: 0, g: 120, b: 255, a: 255 };
+}
+
+/// Shape of a persistent annotation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AnnotationShape {
+    /// Freehand stroke
+    Stroke { points: Vec<NormalizedPoint> },
+    /// Arrow pointing at `to`
+    Arrow { from: NormalizedPoint, to: NormalizedPoint },
+    /// Rectangle between two corners
+    Rectangle {
+        from: NormalizedPoint,
+        to: NormalizedPoint,
+        filled: bool,
+    },
+    /// Text anchored at its top-left corner
+    Text {
+        position: NormalizedPoint,
+        text: String,
+        /// Glyph height as a fraction of the monitor height
+        size: f32,
+    },
+}
+
+/// Persistent annotation drawn on one monitor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub id: Uuid,
+    /// `MonitorInfo::id` of the monitor the annotation belongs to
+    pub monitor_id: usize,
+    pub color: AnnotationColor,
+    /// Line width in pixels at 1080p, scaled with the frame height
+    pub width: f32,
+    pub shape: AnnotationShape,
+}
+
+/// Annotation action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AnnotationAction {
+    /// Add a persistent annotation
+    Add(Annotation),
+    /// Move the author's laser pointer (`None` hides it)
+    Laser {
+        monitor_id: usize,
+        position: Option<NormalizedPoint>,
+    },
+    /// Remove the author's most recent annotation
+    Undo,
+    /// Remove all annotations (on one monitor, or everywhere)
+    Clear { monitor_id: Option<usize> },
+}
+
+/// Annotation event sent between peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationEvent {
+    /// Peer that produced the event
+    pub author: String,
+    pub action: AnnotationAction,
+}
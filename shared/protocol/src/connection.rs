@@ -43,6 +43,38 @@ pub struct TurnServer {
     pub credential: String,
 }
 
+/// ICE server as handed to the WebRTC stack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+/// Which ICE candidates a peer connection may use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IceTransportPolicy {
+    #[default]
+    All,
+    /// Only TURN relay candidates, so peers never learn each other's addresses
+    Relay,
+}
+
+/// ICE servers issued by the API for one session
+///
+/// TURN credentials stop working at `expires_at`; clients fetch a new
+/// configuration before then.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IceConfiguration {
+    pub ice_servers: Vec<IceServer>,
+    #[serde(default)]
+    pub ice_transport_policy: IceTransportPolicy,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
 impl Default for NatConfig {
     fn default() -> Self {
         Self {
@@ -6,6 +6,8 @@ pub mod device;
 pub mod connection;
 pub mod signaling;
 pub mod input;
+pub mod system_info;
+pub mod annotation;
 
 // Use specific imports to avoid ambiguous re-exports
 pub use messages::{
@@ -13,12 +15,16 @@ pub use messages::{
     DisconnectReason, VideoFrame, VideoConfig, KeyboardEvent, MouseEvent,
     MouseEventType, ClipboardData, QualityReport, FileTransferRequest, 
     FileTransferAccept, FileTransferReject, FileChunk, FileTransferComplete, 
-    FileTransferCancel
+    FileTransferCancel, PrivacyModeRequest, BlockInputRequest, LockDeviceRequest,
+    LockTiming, HostControlStatus, SystemAction, SystemActionRequest,
+    SystemActionStatus, SystemActionResponse, ReconnectTicket
 };
 pub use device::*;
 pub use connection::*;
 pub use signaling::*;
 pub use input::*;
+pub use system_info::*;
+pub use annotation::*;
 
 /// Protocol version for compatibility checking
 pub const PROTOCOL_VERSION: u32 = 1;
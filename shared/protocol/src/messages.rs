@@ -1,5 +1,9 @@
 use serde::{Deserialize, Serialize};
 use crate::{DeviceId, SessionId};
+use crate::annotation::AnnotationEvent;
+use crate::system_info::{
+    ProcessKillRequest, ProcessKillResponse, SystemInfoRequest, SystemInfoResponse,
+};
 
 /// Main message envelope
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +43,23 @@ pub enum MessagePayload {
     FileChunk(FileChunk),
     FileTransferComplete(FileTransferComplete),
     FileTransferCancel(FileTransferCancel),
+    
+    // Host control
+    PrivacyMode(PrivacyModeRequest),
+    BlockInput(BlockInputRequest),
+    LockDevice(LockDeviceRequest),
+    HostControlStatus(HostControlStatus),
+    SystemAction(SystemActionRequest),
+    SystemActionResponse(SystemActionResponse),
+    
+    // System information
+    SystemInfoRequest(SystemInfoRequest),
+    SystemInfoResponse(Box<SystemInfoResponse>),
+    ProcessKillRequest(ProcessKillRequest),
+    ProcessKillResponse(ProcessKillResponse),
+    
+    // Screen annotation
+    Annotation(AnnotationEvent),
 }
 
 /// Connection request
@@ -190,3 +211,85 @@ pub struct FileTransferCancel {
     pub file_id: String,
     pub reason: String,
 }
+
+/// Privacy mode request (blank the host's physical display while streaming continues)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacyModeRequest {
+    pub enabled: bool,
+}
+
+/// Block the host's local keyboard and mouse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockInputRequest {
+    pub enabled: bool,
+}
+
+/// Lock the host workstation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockDeviceRequest {
+    pub timing: LockTiming,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockTiming {
+    Now,
+    OnSessionEnd,
+}
+
+/// Host control state reported back to the controller
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostControlStatus {
+    pub privacy_mode: bool,
+    pub input_blocked: bool,
+    pub lock_on_session_end: bool,
+    pub error: Option<String>,
+}
+
+/// Power and session action on the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SystemAction {
+    Reboot,
+    SafeModeReboot,
+    SignOut,
+    Lock,
+    CtrlAltDel,
+}
+
+/// Request to perform a system action on the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemActionRequest {
+    pub request_id: uuid::Uuid,
+    pub action: SystemAction,
+    /// Resume the session automatically once the host is back
+    pub reconnect: bool,
+}
+
+/// Progress of a system action request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SystemActionStatus {
+    /// Waiting for the user at the host to confirm
+    PendingConfirmation,
+    Declined,
+    Executing,
+    Failed(String),
+}
+
+/// Reply to a system action request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemActionResponse {
+    pub request_id: uuid::Uuid,
+    pub action: SystemAction,
+    pub status: SystemActionStatus,
+    /// Ticket for resuming the session after a restart
+    pub reconnect: Option<ReconnectTicket>,
+}
+
+/// Credentials for resuming a session after the host restarts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconnectTicket {
+    /// Persistent connection ID the host re-registers with
+    pub connection_id: String,
+    /// One-time token presented when resuming
+    pub token: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
@@ -1,10 +1,135 @@
 use serde::{Deserialize, Serialize};
 use crate::{DeviceId, SessionId};
 
+/// Identifies a request on one signaling connection
+///
+/// Each side numbers the requests it sends; ids are only unique per
+/// connection and direction.
+pub type MessageId = u64;
+
+/// A signaling message as sent over the wire
+///
+/// `id` marks the message as a request: the other side answers it with
+/// exactly one message whose `reply_to` carries the same id, either the
+/// response proper, an `Ack` or an `Error`. Messages without an id are
+/// notifications and get no reply. Both fields are omitted when unset, so a
+/// bare `SignalingMessage` is also a valid envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalingEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<MessageId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageId>,
+    #[serde(flatten)]
+    pub message: SignalingMessage,
+}
+
+impl SignalingEnvelope {
+    /// A notification
+    pub fn new(message: SignalingMessage) -> Self {
+        Self { id: None, reply_to: None, message }
+    }
+
+    /// A request expecting a reply to `id`
+    pub fn request(id: MessageId, message: SignalingMessage) -> Self {
+        Self { id: Some(id), reply_to: None, message }
+    }
+
+    /// The reply to this message, uncorrelated if it wasn't a request
+    pub fn reply(&self, message: SignalingMessage) -> Self {
+        Self { id: None, reply_to: self.id, message }
+    }
+}
+
+impl From<SignalingMessage> for SignalingEnvelope {
+    fn from(message: SignalingMessage) -> Self {
+        Self::new(message)
+    }
+}
+
+/// Why a signaling request failed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalingErrorCode {
+    /// The message was malformed or can't be sent in this direction
+    BadRequest,
+    /// The sender has to `Register` first
+    NotRegistered,
+    /// The sender didn't prove it owns the device ID it claims
+    Unauthorized,
+    /// The addressed peer is not connected, or left before replying
+    PeerNotFound,
+    /// Too many connection requests; retry after `retry_after_secs`
+    RateLimited,
+    /// The peer didn't reply in time
+    Timeout,
+    #[default]
+    Internal,
+}
+
+impl SignalingErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignalingErrorCode::BadRequest => "bad_request",
+            SignalingErrorCode::NotRegistered => "not_registered",
+            SignalingErrorCode::Unauthorized => "unauthorized",
+            SignalingErrorCode::PeerNotFound => "peer_not_found",
+            SignalingErrorCode::RateLimited => "rate_limited",
+            SignalingErrorCode::Timeout => "timeout",
+            SignalingErrorCode::Internal => "internal",
+        }
+    }
+}
+
+impl std::fmt::Display for SignalingErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Domain separation for `Register` proofs
+const REGISTER_PROOF_CONTEXT: &[u8] = b"genxlink-signaling-register-v1\0";
+
+/// Proof that the sender of a `Register` holds the device's key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterProof {
+    /// Raw Ed25519 public key of the device
+    pub public_key: Vec<u8>,
+    /// Signature over [`register_proof_message`] for the connection's challenge
+    pub signature: Vec<u8>,
+}
+
+/// The message a device signs to register `device_id` on a connection
+/// that was challenged with `nonce`
+pub fn register_proof_message(nonce: &[u8], device_id: &DeviceId) -> Vec<u8> {
+    [REGISTER_PROOF_CONTEXT, nonce, device_id.0.as_bytes()].concat()
+}
+
 /// Signaling messages for WebRTC connection setup
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum SignalingMessage {
+    /// Random nonce the server sends when a connection opens; `Register`
+    /// signs it so a captured registration can't be replayed
+    Challenge {
+        nonce: Vec<u8>,
+    },
+    
+    /// Claim a device ID on this connection; must come first
+    ///
+    /// The signaling server requires `proof` and pins the device ID to the
+    /// first key that proves it. Direct peer links leave it out.
+    Register {
+        device_id: DeviceId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proof: Option<RegisterProof>,
+    },
+    
+    /// Request handled, with nothing else to report
+    Ack,
+    
     /// WebRTC offer
     Offer {
         sdp: String,
@@ -50,12 +175,19 @@ pub enum SignalingMessage {
     ConnectionRequest {
         target: DeviceId,
         from: DeviceId,
+        /// Token from an earlier `ConnectionAccepted`, asking the host to
+        /// re-admit the same session without prompting
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_token: Option<String>,
     },
     
     /// Connection request accepted
     ConnectionAccepted {
         session_id: SessionId,
         from: DeviceId,
+        /// Single-use token for resuming this session after a network change
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_token: Option<String>,
     },
     
     /// Connection request rejected
@@ -64,6 +196,13 @@ pub enum SignalingMessage {
         from: DeviceId,
     },
     
+    /// The server ended the session with `peer` on an administrator's
+    /// request; close it and don't resume it
+    SessionTerminated {
+        peer: DeviceId,
+        reason: String,
+    },
+    
     /// Heartbeat/ping
     Ping,
     
@@ -72,7 +211,11 @@ pub enum SignalingMessage {
     
     /// Error message
     Error {
+        #[serde(default)]
+        code: SignalingErrorCode,
         message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after_secs: Option<u64>,
     },
 }
 
@@ -97,6 +240,34 @@ pub enum DeviceType {
 }
 
 impl SignalingMessage {
+    /// An error reply
+    pub fn error(code: SignalingErrorCode, message: impl Into<String>) -> Self {
+        SignalingMessage::Error {
+            code,
+            message: message.into(),
+            retry_after_secs: None,
+        }
+    }
+    
+    /// The device a routed message is addressed to
+    ///
+    /// Replies to relayed requests are routed by `reply_to` instead and have
+    /// no recipient of their own.
+    pub fn recipient(&self) -> Option<&DeviceId> {
+        match self {
+            SignalingMessage::Offer { to, .. } => Some(to),
+            SignalingMessage::Answer { to, .. } => Some(to),
+            SignalingMessage::IceCandidate { to, .. } => Some(to),
+            SignalingMessage::ConnectionRequest { target, .. } => Some(target),
+            _ => None,
+        }
+    }
+    
+    /// Whether the addressed peer, rather than the server, answers this request
+    pub fn is_peer_request(&self) -> bool {
+        matches!(self, SignalingMessage::Offer { .. } | SignalingMessage::ConnectionRequest { .. })
+    }
+    
     /// Check if this message is for a specific device
     pub fn is_for_device(&self, device_id: &DeviceId) -> bool {
         match self {
@@ -159,4 +330,67 @@ mod tests {
         assert!(msg.is_for_device(&target));
         assert!(!msg.is_for_device(&from));
     }
+
+    #[test]
+    fn test_resume_token_is_optional() {
+        let json = r#"{"type":"ConnectionRequest","target":"a","from":"b"}"#;
+        match serde_json::from_str::<SignalingMessage>(json).unwrap() {
+            SignalingMessage::ConnectionRequest { resume_token, .. } => assert!(resume_token.is_none()),
+            _ => panic!("Wrong message type"),
+        }
+
+        let msg = SignalingMessage::ConnectionAccepted {
+            session_id: SessionId::new(),
+            from: DeviceId::new(),
+            resume_token: None,
+        };
+        assert!(!serde_json::to_string(&msg).unwrap().contains("resume_token"));
+    }
+
+    #[test]
+    fn test_envelope_correlation_fields() {
+        let request = SignalingEnvelope::request(7, SignalingMessage::ListPeers);
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json, serde_json::json!({"type": "ListPeers", "id": 7}));
+
+        let reply = request.reply(SignalingMessage::PeerList { peers: Vec::new() });
+        let json = serde_json::to_string(&reply).unwrap();
+        let parsed: SignalingEnvelope = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.reply_to, Some(7));
+        assert!(parsed.id.is_none());
+        assert!(matches!(parsed.message, SignalingMessage::PeerList { .. }));
+
+        // A bare message is a notification
+        let parsed: SignalingEnvelope = serde_json::from_str(r#"{"type":"Ping"}"#).unwrap();
+        assert!(parsed.id.is_none() && parsed.reply_to.is_none());
+    }
+
+    #[test]
+    fn test_register_proof_message_binds_nonce_and_device() {
+        let device_id = DeviceId::from_string("device".to_string());
+        let message = register_proof_message(&[1; 32], &device_id);
+
+        assert_ne!(message, register_proof_message(&[2; 32], &device_id));
+        assert_ne!(message, register_proof_message(&[1; 32], &DeviceId::from_string("other".to_string())));
+        assert!(message.starts_with(REGISTER_PROOF_CONTEXT));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let json = r#"{"type":"Error","code":"rate_limited","message":"slow down","retry_after_secs":30,"reply_to":3}"#;
+        let parsed: SignalingEnvelope = serde_json::from_str(json).unwrap();
+        match parsed.message {
+            SignalingMessage::Error { code, retry_after_secs, .. } => {
+                assert_eq!(code, SignalingErrorCode::RateLimited);
+                assert_eq!(retry_after_secs, Some(30));
+            }
+            _ => panic!("Wrong message type"),
+        }
+
+        // Errors from older peers carry no code
+        match serde_json::from_str::<SignalingMessage>(r#"{"type":"Error","message":"oops"}"#).unwrap() {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, SignalingErrorCode::Internal),
+            _ => panic!("Wrong message type"),
+        }
+    }
 }
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// Request for the host's system information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfoRequest {
+    /// Include the process list (can be large)
+    pub include_processes: bool,
+}
+
+/// System information response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfoResponse {
+    pub info: Option<SystemInfo>,
+    pub error: Option<String>,
+}
+
+/// Snapshot of the host's system information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub hostname: String,
+    pub os: OsInfo,
+    pub cpu: CpuInfo,
+    pub memory: MemoryInfo,
+    pub disks: Vec<DiskInfo>,
+    pub network_interfaces: Vec<NetworkInterfaceInfo>,
+    pub logged_in_users: Vec<LoggedInUser>,
+    pub uptime_secs: u64,
+    pub processes: Vec<ProcessInfo>,
+    pub collected_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Operating system details
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OsInfo {
+    pub name: String,
+    pub version: String,
+    pub kernel: String,
+    pub arch: String,
+}
+
+/// CPU details
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CpuInfo {
+    pub model: String,
+    pub logical_cores: u32,
+    pub frequency_mhz: Option<f64>,
+    /// 1, 5 and 15 minute load averages
+    pub load_average: [f64; 3],
+}
+
+/// Memory usage in bytes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    pub swap_total_bytes: u64,
+    pub swap_free_bytes: u64,
+}
+
+/// Mounted filesystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskInfo {
+    pub device: String,
+    pub mount_point: String,
+    pub filesystem: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+}
+
+/// Network interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkInterfaceInfo {
+    pub name: String,
+    pub mac_address: Option<String>,
+    pub addresses: Vec<String>,
+    pub is_up: bool,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Interactive user session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedInUser {
+    pub username: String,
+    pub terminal: String,
+    pub host: Option<String>,
+    /// Login time (seconds since epoch)
+    pub login_time: Option<u64>,
+}
+
+/// Running process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub parent_pid: u32,
+    pub name: String,
+    pub command_line: String,
+    pub user: Option<String>,
+    pub state: String,
+    pub memory_bytes: u64,
+    pub cpu_time_ms: u64,
+    pub threads: u32,
+}
+
+/// Request to terminate a process on the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessKillRequest {
+    pub pid: u32,
+    /// Kill immediately instead of asking the process to exit
+    pub force: bool,
+}
+
+/// Result of a process kill request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessKillResponse {
+    pub pid: u32,
+    pub success: bool,
+    pub error: Option<String>,
+}
@@ -87,3 +87,43 @@ fn test_device_id_from_string() {
     
     assert_eq!(device_id.0, id_string);
 }
+
+#[test]
+fn test_annotation_event_roundtrip() {
+    use genxlink_protocol::{AnnotationAction, AnnotationEvent, NormalizedPoint};
+
+    let payload = MessagePayload::Annotation(AnnotationEvent {
+        author: "supporter".to_string(),
+        action: AnnotationAction::Laser {
+            monitor_id: 0,
+            position: Some(NormalizedPoint::new(0.25, 1.5)),
+        },
+    });
+
+    let json = serde_json::to_string(&payload).expect("Failed to serialize");
+    let deserialized: MessagePayload = serde_json::from_str(&json).expect("Failed to deserialize");
+
+    match deserialized {
+        MessagePayload::Annotation(event) => {
+            assert_eq!(event.author, "supporter");
+            assert_eq!(
+                event.action,
+                AnnotationAction::Laser {
+                    monitor_id: 0,
+                    position: Some(NormalizedPoint { x: 0.25, y: 1.0 }),
+                }
+            );
+        }
+        _ => panic!("Wrong message type"),
+    }
+
+    let undo = MessagePayload::Annotation(AnnotationEvent {
+        author: "supporter".to_string(),
+        action: AnnotationAction::Undo,
+    });
+    let json = serde_json::to_string(&undo).expect("Failed to serialize");
+    assert!(matches!(
+        serde_json::from_str::<MessagePayload>(&json),
+        Ok(MessagePayload::Annotation(AnnotationEvent { action: AnnotationAction::Undo, .. }))
+    ));
+}